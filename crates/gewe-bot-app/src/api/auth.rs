@@ -6,6 +6,7 @@ use axum::{
     middleware::Next,
     response::Response,
 };
+use subtle::ConstantTimeEq;
 
 /// 简单 Token 鉴权中间件
 pub async fn auth_middleware(
@@ -21,7 +22,7 @@ pub async fn auth_middleware(
         return Ok(next.run(request).await);
     }
 
-    // 检查 Authorization 头
+    // 检查 Authorization 头；WebSocket 握手同样只接受请求头，不接受查询参数
     let auth_header = headers.get("Authorization").and_then(|h| h.to_str().ok());
 
     let token = match auth_header {
@@ -36,8 +37,10 @@ pub async fn auth_middleware(
         None => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    // 验证 Token
-    if Some(token.to_string()) == expected_token {
+    // 验证 Token，按常量时间比较
+    if expected_token
+        .is_some_and(|expected| bool::from(expected.as_bytes().ct_eq(token.as_bytes())))
+    {
         Ok(next.run(request).await)
     } else {
         Err(StatusCode::UNAUTHORIZED)
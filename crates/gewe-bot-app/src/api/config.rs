@@ -2,10 +2,21 @@
 
 use super::state::{compute_etag, ApiState};
 use crate::config::AppConfigV2;
+use crate::schedule::{find_overlaps, preview_schedules, ActiveWindow, SchedulePreview};
+use crate::storage::{
+    CanaryState, CanaryStatus, CanaryStore, DEFAULT_CANARY_MIN_MESSAGES,
+    DEFAULT_CANARY_WINDOW_SECS, DEFAULT_MAX_ERROR_RATE_INCREASE,
+};
+use crate::tools::{detect_language, normalize_language_code};
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 
+/// lint 预览每个定时任务接下来的执行次数
+const SCHEDULE_PREVIEW_RUNS: usize = 3;
+/// lint 检查定时任务重叠的时间范围（天）
+const SCHEDULE_OVERLAP_DAYS: i64 = 7;
+
 /// 通用 API 响应
 #[derive(Serialize)]
 struct ApiResponse<T> {
@@ -86,6 +97,10 @@ pub struct LintRequest {
 pub struct LintResponse {
     pub valid: bool,
     pub errors: Vec<String>,
+    /// 不影响保存的提示，如同一机器人的定时任务同时触发
+    pub warnings: Vec<String>,
+    /// 各定时任务接下来的执行时间
+    pub schedules: Vec<SchedulePreview>,
 }
 
 /// POST /api/config/lint - 校验配置
@@ -97,6 +112,8 @@ pub async fn lint_config(Json(req): Json<LintRequest>) -> impl IntoResponse {
             return Json(ApiResponse::success(LintResponse {
                 valid: false,
                 errors: vec![format!("JSON 解析失败: {}", e)],
+                warnings: Vec::new(),
+                schedules: Vec::new(),
             }));
         }
     };
@@ -105,7 +122,24 @@ pub async fn lint_config(Json(req): Json<LintRequest>) -> impl IntoResponse {
     let errors = config.validate();
     let valid = errors.is_empty();
 
-    Json(ApiResponse::success(LintResponse { valid, errors }))
+    // 定时任务预览与重叠检查
+    let now = Utc::now();
+    let entries = config.schedule_entries();
+    let schedules = preview_schedules(&entries, now, SCHEDULE_PREVIEW_RUNS);
+    let mut warnings = find_overlaps(&entries, now, Duration::days(SCHEDULE_OVERLAP_DAYS));
+    warnings.extend(
+        schedules
+            .iter()
+            .filter(|p| p.next_runs.is_empty())
+            .map(|p| format!("{} 在未来几年内不会执行", p.id)),
+    );
+
+    Json(ApiResponse::success(LintResponse {
+        valid,
+        errors,
+        warnings,
+        schedules,
+    }))
 }
 
 /// GET /api/config/meta - 获取配置元信息
@@ -227,6 +261,25 @@ pub async fn save_config(
 pub struct PublishRequest {
     #[serde(default)]
     pub remark: Option<String>,
+    /// 灰度发布：先只对指定机器人生效
+    #[serde(default)]
+    pub canary: Option<CanaryRequest>,
+}
+
+/// 灰度发布参数
+#[derive(Deserialize)]
+pub struct CanaryRequest {
+    /// 灰度机器人的 app_id
+    pub app_id: String,
+    /// 观察时长（秒），默认 1800
+    #[serde(default)]
+    pub window_secs: Option<u64>,
+    /// 允许的错误率上升幅度，默认 0.05（5 个百分点）
+    #[serde(default)]
+    pub max_error_rate_increase: Option<f64>,
+    /// 灰度机器人至少处理的消息数，达到后才判断错误率回退，默认 20
+    #[serde(default)]
+    pub min_messages: Option<u64>,
 }
 
 /// Publish 响应
@@ -235,6 +288,8 @@ pub struct PublishResponse {
     pub version: u64,
     pub published_at: chrono::DateTime<Utc>,
     pub backup_filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canary: Option<CanaryState>,
 }
 
 /// POST /api/config/publish - 发布配置
@@ -252,7 +307,7 @@ pub async fn publish_config(
     }
 
     // 验证配置是否有效
-    match tokio::fs::read_to_string(path).await {
+    let config = match tokio::fs::read_to_string(path).await {
         Ok(content) => match AppConfigV2::parse(&content) {
             Ok(config) => {
                 let errors = config.validate();
@@ -262,6 +317,7 @@ pub async fn publish_config(
                         Json(ApiResponse::<PublishResponse>::validation_errors(errors)),
                     );
                 }
+                config
             }
             Err(e) => {
                 return (
@@ -282,43 +338,213 @@ pub async fn publish_config(
                 ))),
             );
         }
+    };
+
+    // 观察中的灰度发布需先推广或回滚
+    let canary_store = CanaryStore::new(&config.storage.data_dir);
+    match canary_store.load().await {
+        Ok(Some(current)) if current.status == CanaryStatus::Observing => {
+            return (
+                StatusCode::CONFLICT,
+                Json(ApiResponse::<PublishResponse>::error(format!(
+                    "v{} 正在灰度观察中，请先推广或回滚",
+                    current.version
+                ))),
+            );
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<PublishResponse>::error(e)),
+            );
+        }
     }
 
+    // 灰度发布以最近的已发布版本为基线
+    let baseline = match &req.canary {
+        Some(canary) => {
+            if !config.bots.iter().any(|b| b.app_id == canary.app_id) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ApiResponse::<PublishResponse>::error(format!(
+                        "未找到灰度机器人: {}",
+                        canary.app_id
+                    ))),
+                );
+            }
+            let meta = state.get_meta().await;
+            match meta.available_backups.iter().max_by_key(|b| b.version) {
+                Some(baseline) => Some(baseline.clone()),
+                None => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(ApiResponse::<PublishResponse>::error(
+                            "灰度发布需要至少一个已发布版本作为基线",
+                        )),
+                    );
+                }
+            }
+        }
+        None => None,
+    };
+
     // 创建备份
-    match state.create_backup(req.remark).await {
-        Ok(info) => {
-            // 更新元信息
-            state
-                .update_meta(|m| {
-                    m.has_draft = false;
-                    m.last_reload_at = Some(Utc::now());
-                    m.last_reload_result = Some("published".to_string());
-                })
-                .await;
-
-            tracing::info!(
-                version = info.version,
-                filename = %info.filename,
-                "配置已发布"
+    let info = match state.create_backup(req.remark).await {
+        Ok(info) => info,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<PublishResponse>::error(format!(
+                    "创建备份失败: {}",
+                    e
+                ))),
             );
+        }
+    };
 
-            (
-                StatusCode::OK,
-                Json(ApiResponse::success(PublishResponse {
-                    version: info.version,
-                    published_at: info.created_at,
-                    backup_filename: info.filename,
-                })),
+    let canary = match (req.canary, baseline) {
+        (Some(canary), Some(baseline)) => {
+            let canary = CanaryState {
+                version: info.version,
+                baseline_version: baseline.version,
+                app_id: canary.app_id,
+                config_path: path.clone(),
+                candidate_file: state.backup_dir().join(&info.filename),
+                baseline_file: state.backup_dir().join(&baseline.filename),
+                started_at: Utc::now(),
+                window_secs: canary.window_secs.unwrap_or(DEFAULT_CANARY_WINDOW_SECS),
+                max_error_rate_increase: canary
+                    .max_error_rate_increase
+                    .unwrap_or(DEFAULT_MAX_ERROR_RATE_INCREASE),
+                min_messages: canary.min_messages.unwrap_or(DEFAULT_CANARY_MIN_MESSAGES),
+                status: CanaryStatus::Observing,
+                metrics: None,
+                decided_at: None,
+                reason: None,
+            };
+            canary_store.save(&canary).await.map(|_| Some(canary))
+        }
+        _ => canary_store.clear().await.map(|_| None),
+    };
+    let canary = match canary {
+        Ok(canary) => canary,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<PublishResponse>::error(e)),
+            );
+        }
+    };
+
+    // 更新元信息
+    state
+        .update_meta(|m| {
+            m.has_draft = false;
+            m.last_reload_at = Some(Utc::now());
+            m.last_reload_result = Some(
+                if canary.is_some() {
+                    "canary"
+                } else {
+                    "published"
+                }
+                .to_string(),
+            );
+        })
+        .await;
+
+    tracing::info!(
+        version = info.version,
+        filename = %info.filename,
+        canary = ?canary.as_ref().map(|c| &c.app_id),
+        "配置已发布"
+    );
+
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(PublishResponse {
+            version: info.version,
+            published_at: info.created_at,
+            backup_filename: info.filename,
+            canary,
+        })),
+    )
+}
+
+/// GET /api/config/canary - 获取最近一次灰度发布的状态
+pub async fn get_canary(State(state): State<ApiState>) -> impl IntoResponse {
+    let data_dir = match state.data_dir().await {
+        Ok(dir) => dir,
+        Err(e) => return Json(ApiResponse::<Option<CanaryState>>::error(e)),
+    };
+    match CanaryStore::new(data_dir).load().await {
+        Ok(canary) => Json(ApiResponse::success(canary)),
+        Err(e) => Json(ApiResponse::error(e)),
+    }
+}
+
+/// POST /api/config/canary/promote - 提前结束观察，推广到全部机器人
+pub async fn promote_canary(State(state): State<ApiState>) -> impl IntoResponse {
+    decide_canary(&state, CanaryStatus::Promoted).await
+}
+
+/// POST /api/config/canary/abort - 放弃灰度，回滚到基线版本
+pub async fn abort_canary(State(state): State<ApiState>) -> impl IntoResponse {
+    decide_canary(&state, CanaryStatus::RolledBack).await
+}
+
+/// 手动结束观察；dispatcher 在下一次定时检查时切换规则
+async fn decide_canary(
+    state: &ApiState,
+    status: CanaryStatus,
+) -> (StatusCode, Json<ApiResponse<CanaryState>>) {
+    let data_dir = match state.data_dir().await {
+        Ok(dir) => dir,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
             )
         }
-        Err(e) => (
+    };
+    let store = CanaryStore::new(data_dir);
+    let mut canary = match store.load().await {
+        Ok(Some(canary)) if canary.status == CanaryStatus::Observing => canary,
+        Ok(_) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ApiResponse::error("没有观察中的灰度发布")),
+            )
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+
+    let reason = if status == CanaryStatus::RolledBack {
+        if let Err(e) = state.restore_backup(canary.baseline_version).await {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(format!("回滚失败: {}", e))),
+            );
+        }
+        "手动回滚"
+    } else {
+        "手动推广"
+    };
+    canary.decide(status, reason, Utc::now());
+    if let Err(e) = store.save(&canary).await {
+        return (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse::<PublishResponse>::error(format!(
-                "创建备份失败: {}",
-                e
-            ))),
-        ),
+            Json(ApiResponse::error(e)),
+        );
     }
+    tracing::info!(version = canary.version, status = ?canary.status, "灰度发布已手动结束");
+
+    (StatusCode::OK, Json(ApiResponse::success(canary)))
 }
 
 /// Rollback 请求
@@ -342,6 +568,12 @@ pub async fn rollback_config(
 ) -> impl IntoResponse {
     match state.restore_backup(req.version).await {
         Ok(()) => {
+            // 手动回滚后灰度状态不再适用
+            if let Ok(data_dir) = state.data_dir().await {
+                if let Err(e) = CanaryStore::new(data_dir).clear().await {
+                    tracing::warn!(error = %e, "清除灰度状态失败");
+                }
+            }
             let now = Utc::now();
             tracing::info!(version = req.version, "配置已回滚");
 
@@ -368,7 +600,7 @@ pub async fn rollback_config(
 pub struct SimulateRequest {
     /// Bot 的 app_id
     pub app_id: String,
-    /// 消息类型: text, image, voice, video, emoji, link, file_notice
+    /// 消息类型: text, image, voice, video, emoji, link, file_notice, location, red_packet, transfer, name_card
     #[serde(default = "default_msg_kind")]
     pub msg_kind: String,
     /// 聊天类型: private, group
@@ -382,6 +614,9 @@ pub struct SimulateRequest {
     /// 是否被 @ 了机器人
     #[serde(default)]
     pub mentioned: bool,
+    /// 模拟的本地时间 `YYYY-MM-DD HH:MM`，用于检查规则的生效时间，默认当前时间
+    #[serde(default)]
+    pub at: Option<String>,
 }
 
 fn default_msg_kind() -> String {
@@ -444,6 +679,19 @@ pub async fn simulate_config(
         )));
     }
 
+    // 模拟的本地时间，未指定时为当前时间
+    let now = match req.at.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        Some(at) => match chrono::NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M") {
+            Ok(t) => t,
+            Err(_) => {
+                return Json(ApiResponse::<SimulateResponse>::error(
+                    "at 格式应为 YYYY-MM-DD HH:MM".to_string(),
+                ));
+            }
+        },
+        None => chrono::Local::now().naive_local(),
+    };
+
     // 构建模板映射
     let tmpl_map: std::collections::HashMap<String, &crate::config::RuleTemplateV2> = config
         .rule_templates
@@ -456,7 +704,9 @@ pub async fn simulate_config(
     instances.sort_by_key(|i| i.priority.unwrap_or(0));
 
     let mut matched_rules = Vec::new();
-    let mut final_action = None;
+    let mut final_action: Option<String> = None;
+    // 命中未设置 continue_matching 的规则后，之后命中的规则不再执行
+    let mut stopped = false;
 
     for inst in instances {
         // 跳过禁用的实例
@@ -519,6 +769,23 @@ pub async fn simulate_config(
                 }
             }
         }
+        // 生效时间
+        if let Ok(window) = ActiveWindow::parse(
+            &match_cfg.time_ranges,
+            &match_cfg.weekdays,
+            match_cfg.cron.as_deref(),
+        ) {
+            if !window.contains(now) {
+                continue;
+            }
+        }
+        // language
+        if let Some(ref language) = match_cfg.language {
+            let expected = normalize_language_code(language);
+            if detect_language(content) != Some(expected.as_str()) {
+                continue;
+            }
+        }
 
         // 检查 require_mention
         let require_mention = inst
@@ -559,6 +826,15 @@ pub async fn simulate_config(
         {
             actions.push("reply_text".to_string());
         }
+        if tmpl.action.reply_link.is_some() {
+            actions.push("reply_link".to_string());
+        }
+        if !tmpl.action.reply_sequence.is_empty() {
+            actions.push(format!(
+                "reply_sequence({})",
+                tmpl.action.reply_sequence.len()
+            ));
+        }
         if tmpl.action.log.unwrap_or(false)
             || inst.overrides.as_ref().and_then(|o| o.log).unwrap_or(false)
         {
@@ -578,9 +854,18 @@ pub async fn simulate_config(
             action_summary: action_summary.clone(),
         });
 
-        // 第一个匹配的规则作为最终动作
-        if final_action.is_none() {
-            final_action = Some(action_summary);
+        // 依次执行的规则动作作为最终动作，直到命中不继续匹配的规则
+        if !stopped {
+            final_action = Some(match final_action {
+                Some(prev) => format!("{} → {}", prev, action_summary),
+                None => action_summary,
+            });
+            stopped = !inst
+                .overrides
+                .as_ref()
+                .and_then(|o| o.continue_matching)
+                .or(tmpl.continue_matching)
+                .unwrap_or(false);
         }
     }
 
@@ -740,6 +1025,124 @@ api_key = "sk-test"
         assert!(json["data"]["etag"].is_string());
     }
 
+    #[tokio::test]
+    async fn test_publish_canary() {
+        let (state, temp_dir) = create_test_state();
+        let data_dir = temp_dir.path().join("data");
+        let toml_content = create_test_config_toml().replace(
+            "backend = \"file\"",
+            &format!(
+                "backend = \"file\"\ndata_dir = {:?}",
+                data_dir.display().to_string()
+            ),
+        );
+        fs::write(state.config_path(), &toml_content).unwrap();
+        state.initialize().await.unwrap();
+
+        let publish = |canary: serde_json::Value| {
+            let state = state.clone();
+            async move {
+                let req = serde_json::from_value(serde_json::json!({ "canary": canary })).unwrap();
+                let response = publish_config(axum::extract::State(state), Json(req))
+                    .await
+                    .into_response();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                (status, json)
+            }
+        };
+        let canary = serde_json::json!({ "app_id": "test_bot", "window_secs": 60 });
+
+        // 没有基线版本时不能灰度
+        let (status, _) = publish(canary.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = publish(serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, json) = publish(serde_json::json!({ "app_id": "missing" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(json["error"].as_str().unwrap().contains("missing"));
+
+        let (status, json) = publish(canary.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["data"]["version"], 2);
+        assert_eq!(json["data"]["canary"]["baseline_version"], 1);
+        assert_eq!(json["data"]["canary"]["status"], "observing");
+
+        // 观察中不能再次发布
+        let (status, _) = publish(serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, json) = decide_canary(&state, CanaryStatus::Promoted).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json.0.data.unwrap().reason.as_deref(), Some("手动推广"));
+
+        // 普通发布清除已结束的灰度状态
+        let (status, _) = publish(serde_json::Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(CanaryStore::new(&data_dir).load().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_lint_schedules() {
+        let lint = |config: serde_json::Value| async move {
+            let response = lint_config(Json(LintRequest { config })).await;
+            let body = axum::body::to_bytes(response.into_response().into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["data"].clone()
+        };
+        let bot = serde_json::json!({
+            "app_id": "test_bot",
+            "base_url": "http://localhost:2531",
+            "token": "test_token",
+            "digest": {"cron": "0 9 * * *", "timezone": "UTC", "chats": ["ops@chatroom"]}
+        });
+        let countdown = serde_json::json!({
+            "id": "launch",
+            "name": "发布",
+            "at": "2099-01-01",
+            "app_id": "test_bot",
+            "targets": ["team@chatroom"],
+            "post_at": "09:00",
+            "timezone": "UTC"
+        });
+
+        let data = lint(serde_json::json!({
+            "config_version": 2,
+            "bots": [bot],
+            "countdowns": [countdown]
+        }))
+        .await;
+        assert_eq!(data["valid"], true);
+        let schedules = data["schedules"].as_array().unwrap();
+        assert_eq!(schedules.len(), 2);
+        assert_eq!(schedules[0]["id"], "digest:test_bot");
+        assert_eq!(schedules[0]["schedule"], "每天 09:00（UTC）");
+        assert_eq!(schedules[0]["next_runs"].as_array().unwrap().len(), 3);
+        let warnings = data["warnings"].as_array().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0]
+            .as_str()
+            .unwrap()
+            .starts_with("digest:test_bot 与 countdown:launch 将在"));
+
+        let mut bot = bot;
+        bot["digest"]["cron"] = "0 9 * *".into();
+        bot["digest"]["timezone"] = "Asia/Nowhere".into();
+        let data = lint(serde_json::json!({"config_version": 2, "bots": [bot]})).await;
+        assert_eq!(data["valid"], false);
+        assert!(data["errors"][0]
+            .as_str()
+            .unwrap()
+            .contains("digest.timezone 未知的时区: Asia/Nowhere"));
+        assert!(data["schedules"].as_array().unwrap().is_empty());
+    }
+
     #[test]
     fn test_default_msg_kind() {
         assert_eq!(default_msg_kind(), "text");
@@ -0,0 +1,80 @@
+//! 死信队列相关 API 处理函数
+
+use super::state::ApiState;
+use crate::storage::DeadLetterStore;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+/// 默认返回的条数
+const DEFAULT_DEAD_LETTER_LIMIT: usize = 100;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterQuery {
+    pub limit: Option<usize>,
+    pub app_id: Option<String>,
+}
+
+/// GET /api/dead-letters?limit=100&app_id= - 最近处理失败的消息，新的在前
+pub async fn list_dead_letters(
+    State(state): State<ApiState>,
+    Query(query): Query<DeadLetterQuery>,
+) -> impl IntoResponse {
+    let store = match state.data_dir().await {
+        Ok(dir) => DeadLetterStore::new(dir),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    match store.load_all().await {
+        Ok(letters) => {
+            let limit = query.limit.unwrap_or(DEFAULT_DEAD_LETTER_LIMIT);
+            let letters: Vec<_> = letters
+                .into_iter()
+                .rev()
+                .filter(|l| query.app_id.as_ref().is_none_or(|id| &l.app_id == id))
+                .take(limit)
+                .collect();
+            (StatusCode::OK, Json(ApiResponse::success(letters)))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
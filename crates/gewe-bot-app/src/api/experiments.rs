@@ -0,0 +1,120 @@
+//! Prompt A/B 实验相关 API 处理函数
+
+use super::state::ApiState;
+use crate::storage::{
+    build_experiment_reports, ExperimentEvent, ExperimentSignal, ExperimentStore,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+/// 管理员投票请求
+#[derive(Deserialize)]
+pub struct VoteRequest {
+    pub variant: String,
+    /// true 为赞，false 为踩
+    pub up: bool,
+}
+
+/// 实验事件与 dispatcher 共用配置中的 data_dir
+async fn experiment_store(state: &ApiState) -> Result<ExperimentStore, String> {
+    state.data_dir().await.map(ExperimentStore::new)
+}
+
+/// GET /api/experiments - 按实验、变体汇总回复数与反馈
+pub async fn list_experiments(State(state): State<ApiState>) -> impl IntoResponse {
+    let store = match experiment_store(&state).await {
+        Ok(store) => store,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    match store.load_all().await {
+        Ok(events) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(build_experiment_reports(&events))),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
+
+/// POST /api/experiments/{id}/vote - 管理员为某个变体投票
+pub async fn vote_experiment(
+    State(state): State<ApiState>,
+    Path(experiment): Path<String>,
+    Json(req): Json<VoteRequest>,
+) -> impl IntoResponse {
+    if req.variant.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::<()>::error("variant 不能为空")),
+        );
+    }
+    let store = match experiment_store(&state).await {
+        Ok(store) => store,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    let event = ExperimentEvent {
+        at: chrono::Utc::now(),
+        experiment,
+        variant: req.variant,
+        signal: if req.up {
+            ExperimentSignal::AdminUp
+        } else {
+            ExperimentSignal::AdminDown
+        },
+        app_id: None,
+        chat: None,
+        user: None,
+    };
+    match store.append(&event).await {
+        Ok(()) => (StatusCode::OK, Json(ApiResponse::success(()))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
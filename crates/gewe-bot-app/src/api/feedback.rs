@@ -0,0 +1,57 @@
+//! AI 回复评价相关 API 处理函数
+
+use super::state::ApiState;
+use crate::storage::{build_feedback_summary, FeedbackStore};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+/// GET /api/feedback - 按规则、模型汇总用户评价与满意度
+pub async fn feedback_summary(State(state): State<ApiState>) -> impl IntoResponse {
+    let store = match state.data_dir().await {
+        Ok(dir) => FeedbackStore::new(dir),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    match store.load_all().await {
+        Ok(records) => (
+            StatusCode::OK,
+            Json(ApiResponse::success(build_feedback_summary(&records))),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
@@ -0,0 +1,442 @@
+//! 会话消息记录 API 处理函数
+
+use std::collections::HashMap;
+
+use super::media::signed_media_path;
+use super::pages::escape_html;
+use super::state::ApiState;
+use crate::storage::{
+    verify_context_sig, HistoryDirection, HistoryMessage, HistoryStore, MediaLog, MediaRecord,
+    SearchQuery,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+/// 默认返回的条数
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+/// 单次最多返回的条数
+const MAX_HISTORY_LIMIT: usize = 500;
+/// 搜索默认返回的条数
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+/// 搜索单次最多返回的条数
+const MAX_SEARCH_LIMIT: usize = 200;
+/// 上下文页面展示的前后消息条数
+const CONTEXT_BEFORE: usize = 15;
+const CONTEXT_AFTER: usize = 15;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<usize>,
+    pub app_id: Option<String>,
+}
+
+/// 返回给外部应用的一条消息
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub message: HistoryMessage,
+    /// 已由 `save` 动作保存的媒体
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<HistoryMedia>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryMedia {
+    pub sha256: String,
+    pub size: u64,
+    pub file_name: String,
+    /// 签名链接，配置了 external_base_url 时为完整地址
+    pub url: String,
+    pub expires_at: i64,
+}
+
+/// GET /api/history/{chat_id}?limit=50&app_id= - 会话最近的消息，按时间先后排列
+pub async fn get_history(
+    State(state): State<ApiState>,
+    Path(chat_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse {
+    let config = match state.current_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    let history = config.server.history.unwrap_or_default();
+    if !history.enabled {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error("未开启会话消息记录（server.history）")),
+        );
+    }
+    let data_dir = &config.storage.data_dir;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .min(MAX_HISTORY_LIMIT);
+    let messages = match HistoryStore::new(data_dir, history.keep())
+        .recent(query.app_id.as_deref(), &chat_id, limit)
+        .await
+    {
+        Ok(messages) => messages,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+
+    let media_log = MediaLog::new(data_dir);
+    let (records, key) = match (media_log.load_all().await, media_log.signing_key().await) {
+        (Ok(records), Ok(key)) => (records, key),
+        (Err(e), _) | (_, Err(e)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    let saved: HashMap<(&str, i64), &MediaRecord> = records
+        .iter()
+        .filter_map(|r| Some(((r.app_id.as_str(), r.new_msg_id?), r)))
+        .collect();
+    let expires_at = chrono::Utc::now().timestamp() + history.media_link_ttl_secs() as i64;
+    let base_url = config
+        .storage
+        .external_base_url
+        .as_deref()
+        .map(|url| url.trim_end_matches('/'))
+        .unwrap_or_default();
+    let entries: Vec<HistoryEntry> = messages
+        .into_iter()
+        .map(|message| {
+            let media = message
+                .new_msg_id
+                .and_then(|id| saved.get(&(message.app_id.as_str(), id)))
+                .map(|record| HistoryMedia {
+                    sha256: record.sha256.clone(),
+                    size: record.size,
+                    file_name: record.file_name.clone(),
+                    url: format!(
+                        "{}{}",
+                        base_url,
+                        signed_media_path(&key, &record.sha256, expires_at)
+                    ),
+                    expires_at,
+                });
+            HistoryEntry { message, media }
+        })
+        .collect();
+    (StatusCode::OK, Json(ApiResponse::success(entries)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    pub q: String,
+    pub app_id: Option<String>,
+    pub chat: Option<String>,
+    pub sender: Option<String>,
+    pub kind: Option<String>,
+    /// RFC 3339 时间或本地日期 `YYYY-MM-DD`（含当天）
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// GET /api/search?q=&chat=&sender=&kind=&since=&until=&limit=20 - 全文搜索会话消息，按时间倒序返回，
+/// 每条附带命中词以 `<mark>` 高亮的摘要
+pub async fn search_history(
+    State(state): State<ApiState>,
+    Query(params): Query<SearchParams>,
+) -> impl IntoResponse {
+    let config = match state.current_config().await {
+        Ok(config) => config,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    let history = config.server.history.unwrap_or_default();
+    if !history.enabled || history.search.is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(
+                "未开启会话消息全文搜索（server.history.search）",
+            )),
+        );
+    }
+    let (since, until) = match (
+        params
+            .since
+            .as_deref()
+            .map(|v| parse_bound(v, false))
+            .transpose(),
+        params
+            .until
+            .as_deref()
+            .map(|v| parse_bound(v, true))
+            .transpose(),
+    ) {
+        (Ok(since), Ok(until)) => (since, until),
+        (Err(e), _) | (_, Err(e)) => {
+            return (StatusCode::BAD_REQUEST, Json(ApiResponse::error(e)));
+        }
+    };
+    let query = SearchQuery {
+        q: params.q,
+        app_id: params.app_id.filter(|v| !v.is_empty()),
+        chat: params.chat.filter(|v| !v.is_empty()),
+        sender: params.sender.filter(|v| !v.is_empty()),
+        kind: params.kind.filter(|v| !v.is_empty()),
+        since,
+        until,
+        limit: params
+            .limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT),
+    };
+    if query.q.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("搜索词不能为空")),
+        );
+    }
+    let search = match crate::storage::open_history_search(
+        history.search.as_ref().unwrap_or(&Default::default()),
+        &config.storage.data_dir,
+    ) {
+        Ok(search) => search,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    match search.search(&query).await {
+        Ok(hits) => (StatusCode::OK, Json(ApiResponse::success(hits))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContextQuery {
+    pub app_id: String,
+    /// 定位的时间（秒级时间戳）
+    pub at: i64,
+    pub expires: i64,
+    pub sig: String,
+}
+
+/// GET /history/{chat_id}?app_id=&at=&expires=&sig= - 通过签名链接查看某一时刻前后的会话消息
+/// （无需 API 鉴权），供告警消息中的上下文链接使用；`at` 之前的最后一条消息高亮显示
+pub async fn serve_context(
+    State(state): State<ApiState>,
+    Path(chat_id): Path<String>,
+    Query(query): Query<ContextQuery>,
+) -> Response {
+    let config = match state.current_config().await {
+        Ok(config) => config,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    let data_dir = &config.storage.data_dir;
+    let key = match MediaLog::new(data_dir).signing_key().await {
+        Ok(key) => key,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    if !verify_context_sig(
+        &key,
+        &query.app_id,
+        &chat_id,
+        query.at,
+        query.expires,
+        &query.sig,
+    ) {
+        return (StatusCode::FORBIDDEN, "签名无效").into_response();
+    }
+    if query.expires < chrono::Utc::now().timestamp() {
+        return (StatusCode::FORBIDDEN, "链接已过期").into_response();
+    }
+    let Some(at) = chrono::DateTime::from_timestamp(query.at, 0) else {
+        return (StatusCode::BAD_REQUEST, "时间无效").into_response();
+    };
+    let history = config.server.history.unwrap_or_default();
+    // 记录的时间精确到纳秒，取 `at` 所在这一秒的末尾定位
+    let messages = match HistoryStore::new(data_dir, history.keep())
+        .around(
+            &query.app_id,
+            &chat_id,
+            at + chrono::Duration::milliseconds(999),
+            CONTEXT_BEFORE,
+            CONTEXT_AFTER,
+        )
+        .await
+    {
+        Ok(messages) => messages,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    Html(render_context(&chat_id, &messages, at)).into_response()
+}
+
+fn render_context(
+    chat_id: &str,
+    messages: &[HistoryMessage],
+    at: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let pivot = messages
+        .iter()
+        .rposition(|m| m.at.timestamp() <= at.timestamp());
+    let mut rows = String::new();
+    for (i, message) in messages.iter().enumerate() {
+        let who = match message.direction {
+            HistoryDirection::Out => "机器人",
+            HistoryDirection::In => message.sender.as_deref().unwrap_or("?"),
+        };
+        let text = match message.text.as_deref() {
+            Some(text) if message.kind == "text" => text.to_string(),
+            Some(text) => format!("[{}] {}", message.kind, text),
+            None => format!("[{}]", message.kind),
+        };
+        rows.push_str(&format!(
+            r#"<div class="msg{}"><span class="time">{}</span> <b>{}</b>: {}</div>"#,
+            if Some(i) == pivot { " hit" } else { "" },
+            message
+                .at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            escape_html(who),
+            escape_html(&text).replace('\n', "<br>")
+        ));
+    }
+    if rows.is_empty() {
+        rows.push_str("<p>没有找到这段时间的消息记录</p>");
+    }
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>会话上下文 - {chat}</title>
+<style>
+body {{ font-family: sans-serif; margin: 1em; line-height: 1.5; }}
+.msg {{ padding: 4px 8px; border-bottom: 1px solid #eee; word-break: break-all; }}
+.hit {{ background: #fff3c4; }}
+.time {{ color: #888; font-size: 0.85em; }}
+</style>
+</head>
+<body>
+<h3>{chat}</h3>
+{rows}
+</body>
+</html>"#,
+        chat = escape_html(chat_id),
+        rows = rows
+    )
+}
+
+/// 解析搜索的时间范围：RFC 3339 时间，或按本地时区解析的日期（`until` 取当天结束）
+fn parse_bound(value: &str, end_of_day: bool) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    use chrono::TimeZone;
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&chrono::Utc));
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("无法解析的时间: {}（应为 RFC 3339 或 YYYY-MM-DD）", value))?;
+    let day = if end_of_day {
+        date.and_hms_opt(23, 59, 59)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    };
+    day.and_then(|day| chrono::Local.from_local_datetime(&day).earliest())
+        .map(|at| at.with_timezone(&chrono::Utc))
+        .ok_or_else(|| format!("无法解析的时间: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_search_bound() {
+        assert_eq!(
+            parse_bound("2024-01-02T03:04:05Z", false)
+                .unwrap()
+                .timestamp(),
+            1_704_164_645
+        );
+        let since = parse_bound("2024-01-02", false).unwrap();
+        let until = parse_bound("2024-01-02", true).unwrap();
+        assert_eq!((until - since).num_seconds(), 86_399);
+        assert!(parse_bound("yesterday", false).is_err());
+    }
+
+    #[test]
+    fn test_render_context_highlights_pivot() {
+        let message = |seconds: i64, text: &str| HistoryMessage {
+            at: chrono::DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+            app_id: "wx1".to_string(),
+            chat: "1@chatroom".to_string(),
+            sender: Some("wxid_a".to_string()),
+            direction: HistoryDirection::In,
+            kind: "text".to_string(),
+            text: Some(text.to_string()),
+            new_msg_id: None,
+            media_url: None,
+        };
+        let messages = [
+            message(0, "早"),
+            message(10, "<b>退款</b>"),
+            message(20, "好"),
+        ];
+        let at = chrono::DateTime::from_timestamp(1_700_000_010, 0).unwrap();
+        let html = render_context("1@chatroom", &messages, at);
+        assert_eq!(html.matches(r#"class="msg hit""#).count(), 1);
+        assert!(html.contains(r#"<b>wxid_a</b>: &lt;b&gt;退款&lt;/b&gt;</div>"#));
+        let hit = html.find("msg hit").unwrap();
+        assert!(html[hit..].contains("退款"));
+        assert!(!html[hit..].contains("早"));
+    }
+}
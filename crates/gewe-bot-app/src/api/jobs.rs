@@ -0,0 +1,54 @@
+//! 定时任务表相关 API 处理函数
+
+use super::state::ApiState;
+use crate::storage::JobStore;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+/// GET /api/jobs - 列出定时任务及下次执行时间、最近执行结果
+pub async fn list_jobs(State(state): State<ApiState>) -> impl IntoResponse {
+    let store = match state.data_dir().await {
+        Ok(dir) => JobStore::new(dir),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    match store.load().await {
+        Ok(table) => (StatusCode::OK, Json(ApiResponse::success(table.sorted()))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
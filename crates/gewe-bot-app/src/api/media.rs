@@ -0,0 +1,284 @@
+//! 媒体保存记录 API 处理函数，供外部应用访问已保存媒体的签名链接，以及本地文件上传
+
+use super::state::ApiState;
+use crate::storage::MediaLog;
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// 默认返回的条数
+const DEFAULT_MEDIA_LIMIT: usize = 100;
+/// 上传文件的大小上限
+pub(super) const MAX_UPLOAD_BYTES: usize = 100 * 1024 * 1024;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MediaQuery {
+    pub limit: Option<usize>,
+    pub app_id: Option<String>,
+    pub chat: Option<String>,
+    pub sender: Option<String>,
+    pub sha256: Option<String>,
+}
+
+/// GET /api/media?limit=100&app_id=&chat=&sender=&sha256= - 最近的媒体保存记录，新的在前
+pub async fn list_media(
+    State(state): State<ApiState>,
+    Query(query): Query<MediaQuery>,
+) -> impl IntoResponse {
+    let log = match state.data_dir().await {
+        Ok(dir) => MediaLog::new(dir),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    match log.load_all().await {
+        Ok(records) => {
+            let limit = query.limit.unwrap_or(DEFAULT_MEDIA_LIMIT);
+            let records: Vec<_> = records
+                .into_iter()
+                .rev()
+                .filter(|r| query.app_id.as_ref().is_none_or(|id| &r.app_id == id))
+                .filter(|r| query.chat.as_ref().is_none_or(|c| &r.chat == c))
+                .filter(|r| query.sender.as_ref().is_none_or(|s| &r.sender == s))
+                .filter(|r| {
+                    query
+                        .sha256
+                        .as_ref()
+                        .is_none_or(|h| r.sha256.eq_ignore_ascii_case(h))
+                })
+                .take(limit)
+                .collect();
+            (StatusCode::OK, Json(ApiResponse::success(records)))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
+
+/// 媒体签名链接：`/media/{sha256}?expires=&sig=`，签名为 `{sha256}:{expires}` 的 HMAC-SHA256
+pub(super) fn signed_media_path(key: &[u8], sha256: &str, expires: i64) -> String {
+    format!(
+        "/media/{}?expires={}&sig={}",
+        sha256,
+        expires,
+        hex::encode(media_mac(key, sha256, expires).finalize().into_bytes())
+    )
+}
+
+fn media_mac(key: &[u8], sha256: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC 接受任意长度的密钥");
+    mac.update(format!("{}:{}", sha256, expires).as_bytes());
+    mac
+}
+
+fn verify_media_sig(key: &[u8], sha256: &str, expires: i64, sig: &str) -> bool {
+    let Ok(sig) = hex::decode(sig) else {
+        return false;
+    };
+    media_mac(key, sha256, expires).verify_slice(&sig).is_ok()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignedMediaQuery {
+    pub expires: i64,
+    pub sig: String,
+}
+
+/// GET /media/{sha256}?expires=&sig= - 通过签名链接访问已保存的媒体（无需 API 鉴权）；
+/// 本地存储直接返回文件，对象存储与 WebDAV 重定向到对象地址
+pub async fn serve_media(
+    State(state): State<ApiState>,
+    Path(sha256): Path<String>,
+    Query(query): Query<SignedMediaQuery>,
+) -> Response {
+    let log = match state.data_dir().await {
+        Ok(dir) => MediaLog::new(dir),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    let key = match log.signing_key().await {
+        Ok(key) => key,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    if !verify_media_sig(&key, &sha256, query.expires, &query.sig) {
+        return (StatusCode::FORBIDDEN, "签名无效").into_response();
+    }
+    if query.expires < chrono::Utc::now().timestamp() {
+        return (StatusCode::FORBIDDEN, "链接已过期").into_response();
+    }
+    let records = match log.load_all().await {
+        Ok(records) => records,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+    let mut remote = None;
+    for record in records.iter().rev().filter(|r| r.sha256 == sha256) {
+        for location in &record.locations {
+            let location = location.location.as_str();
+            if location.starts_with("http://") || location.starts_with("https://") {
+                remote.get_or_insert(location);
+            } else if let Ok(bytes) = tokio::fs::read(location).await {
+                let content_type = match record.kind {
+                    crate::config::RuleKind::Image => crate::tools::detect_mime(&bytes),
+                    crate::config::RuleKind::Video => "video/mp4",
+                    _ => "application/octet-stream",
+                };
+                return ([(header::CONTENT_TYPE, content_type)], bytes).into_response();
+            }
+        }
+    }
+    match remote {
+        Some(url) => Redirect::temporary(url).into_response(),
+        None => (StatusCode::NOT_FOUND, "媒体不存在").into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    pub file_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadedMedia {
+    /// GeWe 可下载的外部地址
+    pub url: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// POST /api/media/upload?file_name= - 上传本地文件（请求体为文件内容），保存到图片目录后返回
+/// 外部地址，供 `img_url`、`file_url` 等只接受链接的发送接口使用；相同内容只保存一份
+pub async fn upload_media(
+    State(state): State<ApiState>,
+    Query(query): Query<UploadQuery>,
+    body: Bytes,
+) -> impl IntoResponse {
+    let storage = match state.current_config().await {
+        Ok(config) => config.storage,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    let Some(base_url) = storage.external_base_url.as_deref() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error(
+                "未配置 storage.external_base_url，无法生成外部地址",
+            )),
+        );
+    };
+    if body.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::error("文件内容为空")),
+        );
+    }
+    let sha256 = hex::encode(Sha256::digest(&body));
+    let file_name = match upload_ext(&query.file_name) {
+        Some(ext) => format!("upload-{}.{}", sha256, ext),
+        None => format!("upload-{}", sha256),
+    };
+    let path = std::path::Path::new(&storage.image_dir).join(&file_name);
+    if !path.exists() {
+        let written = match tokio::fs::create_dir_all(&storage.image_dir).await {
+            Ok(()) => tokio::fs::write(&path, &body).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(format!("写入文件失败: {}", e))),
+            );
+        }
+        tracing::info!(path = %path.display(), size = body.len(), "已保存上传的文件");
+    }
+    let url = format!(
+        "{}{}/{}",
+        base_url.trim_end_matches('/'),
+        storage.image_url_prefix,
+        file_name
+    );
+    (
+        StatusCode::OK,
+        Json(ApiResponse::success(UploadedMedia {
+            url,
+            sha256,
+            size: body.len() as u64,
+        })),
+    )
+}
+
+/// 上传文件名的扩展名，只保留字母数字
+fn upload_ext(file_name: &str) -> Option<String> {
+    std::path::Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_media_path_round_trip() {
+        let path = signed_media_path(b"key", "abc", 1_700_000_000);
+        let sig = path.rsplit("sig=").next().unwrap();
+        assert!(path.starts_with("/media/abc?expires=1700000000&sig="));
+        assert!(verify_media_sig(b"key", "abc", 1_700_000_000, sig));
+        assert!(!verify_media_sig(b"key", "abc", 1_700_000_001, sig));
+        assert!(!verify_media_sig(b"other", "abc", 1_700_000_000, sig));
+        assert!(!verify_media_sig(b"key", "abc", 1_700_000_000, "zz"));
+    }
+
+    #[test]
+    fn test_upload_ext() {
+        assert_eq!(upload_ext("photo.PNG").as_deref(), Some("png"));
+        assert_eq!(upload_ext("report.tar.gz").as_deref(), Some("gz"));
+        assert_eq!(upload_ext("noext"), None);
+        assert_eq!(upload_ext("x.p/ng"), None);
+        assert_eq!(upload_ext("x.p ng"), None);
+    }
+}
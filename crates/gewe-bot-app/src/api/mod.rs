@@ -4,13 +4,26 @@
 
 pub mod auth;
 mod config;
+mod dead_letters;
+mod experiments;
+mod feedback;
+mod history;
+mod jobs;
+mod media;
+mod models;
+mod moderation;
 mod pages;
 mod prompts;
+mod rule_templates;
+mod safe_mode;
 mod state;
+mod tool_registry;
+mod ws;
 
 pub use state::ApiState;
 
 use axum::{
+    extract::DefaultBodyLimit,
     routing::{get, post, put},
     Router,
 };
@@ -27,13 +40,62 @@ pub fn api_router(state: ApiState) -> Router {
         .route("/config/save", post(config::save_config))
         .route("/config/publish", post(config::publish_config))
         .route("/config/rollback", post(config::rollback_config))
+        .route("/config/canary", get(config::get_canary))
+        .route("/config/canary/promote", post(config::promote_canary))
+        .route("/config/canary/abort", post(config::abort_canary))
         .route("/config/simulate", post(config::simulate_config))
         .route("/config/export", get(config::export_config))
         .route("/config/import", post(config::import_config))
+        // 规则模板分享
+        .route(
+            "/rule-templates/{id}/export",
+            get(rule_templates::export_rule_template),
+        )
+        .route(
+            "/rule-templates/import",
+            post(rule_templates::import_rule_template),
+        )
+        // 工具注册表
+        .route("/tools/install", post(tool_registry::install_registry))
         // Prompts 相关
         .route("/prompts", get(prompts::list_prompts))
         .route("/prompts/{name}", get(prompts::get_prompt))
         .route("/prompts/{name}", put(prompts::put_prompt))
+        // Prompt A/B 实验
+        .route("/experiments", get(experiments::list_experiments))
+        .route("/experiments/{id}/vote", post(experiments::vote_experiment))
+        .route("/feedback", get(feedback::feedback_summary))
+        .route("/dead-letters", get(dead_letters::list_dead_letters))
+        .route("/moderation", get(moderation::list_moderation))
+        .route("/media", get(media::list_media))
+        .route(
+            "/media/upload",
+            post(media::upload_media).layer(DefaultBodyLimit::max(media::MAX_UPLOAD_BYTES)),
+        )
+        .route("/history/{chat_id}", get(history::get_history))
+        .route("/search", get(history::search_history))
+        .route("/ws", get(ws::ws_push))
+        .route("/jobs", get(jobs::list_jobs))
+        .route("/models", get(models::model_report))
+        .route("/safe-mode", get(safe_mode::get_safe_mode))
+        .route(
+            "/safe-mode/{app_id}/resume",
+            post(safe_mode::resume_safe_mode),
+        )
+        .with_state(state)
+}
+
+/// 创建媒体签名链接路由，链接自带签名，不经过 API 鉴权
+pub fn media_router(state: ApiState) -> Router {
+    Router::new()
+        .route("/{sha256}", get(media::serve_media))
+        .with_state(state)
+}
+
+/// 创建会话上下文签名链接路由，链接自带签名，不经过 API 鉴权
+pub fn history_link_router(state: ApiState) -> Router {
+    Router::new()
+        .route("/{chat_id}", get(history::serve_context))
         .with_state(state)
 }
 
@@ -42,6 +104,8 @@ pub fn pages_router(state: ApiState) -> Router {
     Router::new()
         // Dashboard
         .route("/dashboard", get(pages::dashboard))
+        // Stats
+        .route("/stats", get(pages::stats_page))
         // Bots
         .route("/bots", get(pages::bots_list))
         .route("/bots/new", get(pages::bot_new_form))
@@ -60,6 +124,24 @@ pub fn pages_router(state: ApiState) -> Router {
         .route("/tools/edit/{id}", get(pages::tool_edit_form))
         .route("/tools/save", post(pages::tool_save))
         .route("/tools/delete/{id}", post(pages::tool_delete))
+        // Countdowns
+        .route("/countdowns", get(pages::countdowns_list))
+        .route("/countdowns/new", get(pages::countdown_new_form))
+        .route("/countdowns/edit/{id}", get(pages::countdown_edit_form))
+        .route("/countdowns/save", post(pages::countdown_save))
+        .route("/countdowns/delete/{id}", post(pages::countdown_delete))
+        // Chat settings
+        .route("/chat-settings", get(pages::chat_settings_list))
+        .route("/chat-settings/new", get(pages::chat_setting_new_form))
+        .route(
+            "/chat-settings/edit/{app_id}/{chat}",
+            get(pages::chat_setting_edit_form),
+        )
+        .route("/chat-settings/save", post(pages::chat_setting_save))
+        .route(
+            "/chat-settings/delete/{app_id}/{chat}",
+            post(pages::chat_setting_delete),
+        )
         // Rules
         .route("/rules", get(pages::rules_page))
         .route("/rule-templates/new", get(pages::rule_template_new_form))
@@ -95,6 +177,18 @@ pub fn pages_router(state: ApiState) -> Router {
         .with_state(state)
 }
 
+/// 创建需要 API 鉴权的 Pages 路由：日志等可能泄露敏感信息的页面，以及会下载远程注册表的工具安装
+pub fn protected_pages_router(state: ApiState) -> Router {
+    Router::new()
+        // Tools
+        .route("/tools/install", get(pages::tool_install_form))
+        .route("/tools/install", post(pages::tool_install))
+        // Logs
+        .route("/logs", get(pages::logs_page))
+        .route("/logs/tail", get(pages::logs_tail))
+        .with_state(state)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -121,8 +215,9 @@ mod tests {
     #[test]
     fn test_pages_router_creation() {
         let (state, _temp_dir) = create_test_state();
-        let router = pages_router(state);
+        let router = pages_router(state.clone());
 
         assert!(!format!("{:?}", router).is_empty());
+        assert!(!format!("{:?}", protected_pages_router(state)).is_empty());
     }
 }
@@ -0,0 +1,103 @@
+//! 模型对比相关 API 处理函数
+
+use super::state::ApiState;
+use crate::config::{AppConfigV2, ModelPrice};
+use crate::storage::{build_model_report, FeedbackStore, OpsLog};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// 默认统计窗口（天）
+const DEFAULT_REPORT_DAYS: i64 = 7;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelReportQuery {
+    pub days: Option<i64>,
+}
+
+/// GET /api/models?days=7 - 按模型对比调用量、失败率、token 花费、延迟与用户评价
+pub async fn model_report(
+    State(state): State<ApiState>,
+    Query(query): Query<ModelReportQuery>,
+) -> impl IntoResponse {
+    let config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    let days = query.days.unwrap_or(DEFAULT_REPORT_DAYS).clamp(1, 90);
+    let end = chrono::Utc::now();
+    let start = end - chrono::Duration::days(days);
+
+    let data_dir = &config.storage.data_dir;
+    let loaded = async {
+        let events = OpsLog::new(data_dir).load_range(start, end).await?;
+        let feedback = FeedbackStore::new(data_dir).load_all().await?;
+        Ok::<_, String>((events, feedback))
+    }
+    .await;
+    match loaded {
+        Ok((events, feedback)) => {
+            let report = build_model_report(&events, &feedback, start, end, &model_prices(&config));
+            (StatusCode::OK, Json(ApiResponse::success(report)))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
+
+async fn load_config(state: &ApiState) -> Result<AppConfigV2, String> {
+    let content = tokio::fs::read_to_string(state.config_path())
+        .await
+        .map_err(|e| format!("读取配置失败: {}", e))?;
+    AppConfigV2::parse(&content).map_err(|e| format!("解析配置失败: {}", e))
+}
+
+/// 合并各机器人运营摘要中配置的模型单价
+fn model_prices(config: &AppConfigV2) -> BTreeMap<String, ModelPrice> {
+    config
+        .bots
+        .iter()
+        .filter_map(|b| b.digest.as_ref())
+        .flat_map(|d| d.prices.clone())
+        .collect()
+}
@@ -0,0 +1,82 @@
+//! 群聊违规审计日志 API 处理函数
+
+use super::state::ApiState;
+use crate::storage::ModerationLog;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+/// 默认返回的条数
+const DEFAULT_MODERATION_LIMIT: usize = 100;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationQuery {
+    pub limit: Option<usize>,
+    pub app_id: Option<String>,
+    pub chatroom: Option<String>,
+}
+
+/// GET /api/moderation?limit=100&app_id=&chatroom= - 最近的违规处理记录，新的在前
+pub async fn list_moderation(
+    State(state): State<ApiState>,
+    Query(query): Query<ModerationQuery>,
+) -> impl IntoResponse {
+    let log = match state.data_dir().await {
+        Ok(dir) => ModerationLog::new(dir),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::error(e)),
+            )
+        }
+    };
+    match log.load_all().await {
+        Ok(records) => {
+            let limit = query.limit.unwrap_or(DEFAULT_MODERATION_LIMIT);
+            let records: Vec<_> = records
+                .into_iter()
+                .rev()
+                .filter(|r| query.app_id.as_ref().is_none_or(|id| &r.app_id == id))
+                .filter(|r| query.chatroom.as_ref().is_none_or(|c| &r.chatroom == c))
+                .take(limit)
+                .collect();
+            (StatusCode::OK, Json(ApiResponse::success(records)))
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
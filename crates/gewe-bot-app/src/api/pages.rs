@@ -3,19 +3,33 @@
 //! 为 htmx 提供 HTML 片段响应
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::{Html, IntoResponse, Redirect, Response},
     Form,
 };
 use axum_htmx::HxRequest;
+use gewe_http::ChatroomNames;
 use serde::Deserialize;
 
 use super::state::{compute_etag, ApiState};
 use crate::config::{
-    AiProfileV2, AppConfigV2, BotConfigV2, DefaultsAiV2, DefaultsV2, InstanceOverridesV2,
-    MatchConfigV2, RuleInstanceV2, RuleKind, RuleTemplateV2, ServerConfigV2, StorageConfigV2,
-    TemplateActionV2, TemplateDefaultsV2, ToolConfigV2,
+    AiProfileV2, AppConfigV2, BotConfigV2, CountdownConfig, DefaultsAiV2, DefaultsV2,
+    InstanceOverridesV2, MatchConfigV2, RuleInstanceV2, RuleKind, RuleTemplateV2, ServerConfigV2,
+    StorageConfigV2, TemplateActionV2, TemplateDefaultsV2, ToolConfigV2,
 };
+use crate::log_buffer::{LogBuffer, LogQuery};
+use crate::schedule::ScheduleTz;
+use crate::storage::{
+    build_ops_stats, is_valid_locale, CanaryState, CanaryStatus, CanaryStore, ChatSettings,
+    ChatSettingsStore, Formality, OpsLog, RuntimeStateStore,
+};
+
+/// 统计页面展示的规则条数
+const STATS_TOP_RULES: usize = 10;
+/// 统计图表使用的 Chart.js，首次打开统计页时按需加载
+/// 日志页面每次刷新返回的最大条数
+const LOG_TAIL_LIMIT: usize = 200;
+const CHART_JS_URL: &str = "https://cdn.jsdelivr.net/npm/chart.js@4.4.1/dist/chart.umd.min.js";
 
 /// 检查是否为 htmx 请求，如果不是则重定向到主页
 fn require_htmx(is_htmx: bool) -> Option<Response> {
@@ -115,8 +129,21 @@ pub async fn dashboard(
 
     let reload_result = meta.last_reload_result.as_deref().unwrap_or("-");
 
+    let canary = match state.data_dir().await {
+        Ok(dir) => CanaryStore::new(dir).load().await.ok().flatten(),
+        Err(_) => None,
+    };
+
     let content = format!(
         r##"
+<div class="flex justify-end gap-2 mb-2">
+    <button class="btn btn-sm btn-outline" hx-get="/pages/stats" hx-target="#main">
+        查看统计
+    </button>
+    <button class="btn btn-sm btn-outline" hx-get="/pages/logs" hx-target="#main">
+        查看日志
+    </button>
+</div>
 <div class="grid gap-4 md:grid-cols-2 lg:grid-cols-4 mb-6">
     <!-- 统计卡片 -->
     <div class="card bg-base-100 shadow-sm">
@@ -215,6 +242,7 @@ pub async fn dashboard(
         </div>
     </div>
 </div>
+{}
 "##,
         bots_count,
         profiles_count,
@@ -247,12 +275,458 @@ pub async fn dashboard(
             )
         } else {
             String::new()
+        },
+        canary_card(canary.as_ref()),
+    );
+
+    Html(content)
+}
+
+/// 最近一次灰度发布的状态卡片，观察中时可手动推广或回滚
+fn canary_card(canary: Option<&CanaryState>) -> String {
+    let Some(canary) = canary else {
+        return String::new();
+    };
+    let status = match canary.status {
+        CanaryStatus::Observing => r##"<span class="badge badge-warning">观察中</span>"##,
+        CanaryStatus::Promoted => r##"<span class="badge badge-success">已推广</span>"##,
+        CanaryStatus::RolledBack => r##"<span class="badge badge-error">已回滚</span>"##,
+    };
+    let metrics = canary
+        .metrics
+        .as_ref()
+        .map(|m| {
+            format!(
+                "基线 {:.1}%（{} 条） / 灰度 {:.1}%（{} 条）",
+                m.baseline_error_rate() * 100.0,
+                m.baseline_messages,
+                m.canary_error_rate() * 100.0,
+                m.canary_messages
+            )
+        })
+        .unwrap_or_else(|| "-".to_string());
+    let actions = if canary.status == CanaryStatus::Observing {
+        r##"<div class="flex gap-2 mt-4">
+                <button class="btn btn-sm btn-success" hx-post="/api/config/canary/promote"
+                        hx-confirm="确定提前推广到全部机器人？" hx-swap="none">推广到全部</button>
+                <button class="btn btn-sm btn-error btn-outline" hx-post="/api/config/canary/abort"
+                        hx-confirm="确定回滚到基线版本？" hx-swap="none">回滚</button>
+            </div>"##
+    } else {
+        ""
+    };
+
+    format!(
+        r##"
+<div class="card bg-base-100 shadow-sm mt-4">
+    <div class="card-body">
+        <h2 class="card-title">灰度发布 {}</h2>
+        <table class="table table-sm">
+            <tbody>
+                <tr><td class="text-base-content/70">版本</td><td>v{}（基线 v{}）</td></tr>
+                <tr><td class="text-base-content/70">灰度机器人</td><td>{}</td></tr>
+                <tr><td class="text-base-content/70">观察期</td><td>{} ~ {}</td></tr>
+                <tr><td class="text-base-content/70">错误率</td><td>{}</td></tr>
+                <tr><td class="text-base-content/70">结论</td><td>{}</td></tr>
+            </tbody>
+        </table>
+        {}
+    </div>
+</div>
+"##,
+        status,
+        canary.version,
+        canary.baseline_version,
+        escape_html(&canary.app_id),
+        canary
+            .started_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M"),
+        canary
+            .window_end()
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M"),
+        metrics,
+        canary.reason.as_deref().unwrap_or("-"),
+        actions
+    )
+}
+
+/// 统计页面查询参数
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// 统计最近多少天，默认 7
+    #[serde(default)]
+    pub days: Option<i64>,
+}
+
+/// 统计页面 - 消息量趋势、规则命中排行、机器人在线历史、AI 延迟分位数
+pub async fn stats_page(
+    State(state): State<ApiState>,
+    Query(query): Query<StatsQuery>,
+) -> Html<String> {
+    let config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => {
+            return Html(format!(
+                r##"<div class="alert alert-error"><span>{}</span></div>"##,
+                e
+            ))
+        }
+    };
+
+    let days = query.days.unwrap_or(7).clamp(1, 90);
+    // 1 天按小时分桶，更长按天分桶；窗口结束于下一个整点
+    let bucket = if days == 1 {
+        chrono::Duration::hours(1)
+    } else {
+        chrono::Duration::days(1)
+    };
+    let now = chrono::Utc::now();
+    let end = chrono::DurationRound::duration_trunc(now, chrono::Duration::hours(1)).unwrap_or(now)
+        + chrono::Duration::hours(1);
+    let start = end - chrono::Duration::days(days);
+    let events = match OpsLog::new(&config.storage.data_dir)
+        .load_range(start, end)
+        .await
+    {
+        Ok(events) => events,
+        Err(e) => {
+            return Html(format!(
+                r##"<div class="alert alert-error"><span>{}</span></div>"##,
+                e
+            ))
+        }
+    };
+    let stats = build_ops_stats(&events, start, end, bucket, STATS_TOP_RULES);
+
+    let range_buttons: String = [1, 7, 30]
+        .iter()
+        .map(|d| {
+            format!(
+                r##"<button class="btn btn-xs {}" hx-get="/pages/stats?days={}" hx-target="#main">{} 天</button>"##,
+                if *d == days { "btn-primary" } else { "btn-outline" },
+                d,
+                d
+            )
+        })
+        .collect();
+
+    let mut bot_ids: Vec<(&str, bool)> = config
+        .bots
+        .iter()
+        .map(|b| (b.app_id.as_str(), b.failover.is_some()))
+        .collect();
+    for a in &stats.availability {
+        if !bot_ids.iter().any(|(id, _)| *id == a.app_id) {
+            bot_ids.push((&a.app_id, true));
         }
+    }
+    let window_secs = (end - start).num_seconds().max(1) as f64;
+    let availability_rows: String = bot_ids
+        .iter()
+        .map(|(app_id, monitored)| {
+            let record = stats.availability.iter().find(|a| a.app_id == *app_id);
+            let segments: String = record
+                .map(|a| {
+                    a.incidents
+                        .iter()
+                        .map(|i| {
+                            let from = (i.start.unwrap_or(start) - start).num_seconds() as f64;
+                            let to = (i.end.unwrap_or(end) - start).num_seconds() as f64;
+                            format!(
+                                r##"<div class="absolute h-full bg-error" style="left: {:.2}%; width: {:.2}%;"></div>"##,
+                                from / window_secs * 100.0,
+                                ((to - from) / window_secs * 100.0).max(0.5)
+                            )
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let summary = match (record, monitored) {
+                (Some(a), _) => format!(
+                    "可用率 {:.2}%，离线 {} 次",
+                    (1.0 - a.offline_secs as f64 / window_secs) * 100.0,
+                    a.incidents.len()
+                ),
+                (None, true) => "可用率 100%".to_string(),
+                (None, false) => "未开启健康检查".to_string(),
+            };
+            format!(
+                r##"<tr>
+                    <td class="font-mono">{}</td>
+                    <td class="w-1/2"><div class="relative h-4 rounded {}">{}</div></td>
+                    <td>{}</td>
+                </tr>"##,
+                app_id,
+                if *monitored || record.is_some() {
+                    "bg-success/40"
+                } else {
+                    "bg-base-300"
+                },
+                segments,
+                summary
+            )
+        })
+        .collect();
+
+    let label_format = if days == 1 { "%H:%M" } else { "%m-%d" };
+    let chart_data = serde_json::json!({
+        "labels": stats
+            .buckets
+            .iter()
+            .map(|b| b.with_timezone(&chrono::Local).format(label_format).to_string())
+            .collect::<Vec<_>>(),
+        "messages": stats.messages,
+        "rules": stats.top_rules,
+        "latency": stats.latency,
+    });
+    // 避免规则名等数据中的 </script> 提前结束脚本
+    let chart_json = chart_data.to_string().replace("</", "<\\/");
+    let total_messages: u64 = stats.messages.values().flatten().sum();
+
+    let content = format!(
+        r##"
+<div class="flex justify-between items-center mb-4">
+    <h1 class="text-2xl font-bold">统计</h1>
+    <div class="flex gap-1">{range_buttons}</div>
+</div>
+
+<div class="grid gap-4 md:grid-cols-2">
+    <div class="card bg-base-100 shadow-sm md:col-span-2">
+        <div class="card-body">
+            <h2 class="card-title">消息量（共 {total_messages} 条）</h2>
+            <canvas id="stats-messages" height="80"></canvas>
+        </div>
+    </div>
+    <div class="card bg-base-100 shadow-sm">
+        <div class="card-body">
+            <h2 class="card-title">规则命中 Top {STATS_TOP_RULES}</h2>
+            <canvas id="stats-rules"></canvas>
+        </div>
+    </div>
+    <div class="card bg-base-100 shadow-sm">
+        <div class="card-body">
+            <h2 class="card-title">AI 延迟分位数（毫秒）</h2>
+            <canvas id="stats-latency"></canvas>
+        </div>
+    </div>
+    <div class="card bg-base-100 shadow-sm md:col-span-2">
+        <div class="card-body">
+            <h2 class="card-title">在线状态</h2>
+            <div class="overflow-x-auto">
+                <table class="table table-sm">
+                    <thead>
+                        <tr><th>机器人</th><th>时间线（红色为离线）</th><th>概况</th></tr>
+                    </thead>
+                    <tbody>
+                        {availability}
+                    </tbody>
+                </table>
+            </div>
+        </div>
+    </div>
+</div>
+
+<script type="application/json" id="stats-data">{chart_json}</script>
+<script>
+(function () {{
+    function draw() {{
+        var data = JSON.parse(document.getElementById('stats-data').textContent);
+        new Chart(document.getElementById('stats-messages'), {{
+            type: 'line',
+            data: {{
+                labels: data.labels,
+                datasets: Object.keys(data.messages).map(function (bot) {{
+                    return {{ label: bot, data: data.messages[bot], tension: 0.3 }};
+                }})
+            }}
+        }});
+        new Chart(document.getElementById('stats-rules'), {{
+            type: 'bar',
+            data: {{
+                labels: data.rules.map(function (r) {{ return r.rule; }}),
+                datasets: [{{ label: '命中次数', data: data.rules.map(function (r) {{ return r.hits; }}) }}]
+            }},
+            options: {{ indexAxis: 'y' }}
+        }});
+        new Chart(document.getElementById('stats-latency'), {{
+            type: 'bar',
+            data: {{
+                labels: data.latency.map(function (l) {{ return l.model + ' (' + l.samples + ')'; }}),
+                datasets: ['p50', 'p90', 'p99'].map(function (p) {{
+                    return {{ label: p, data: data.latency.map(function (l) {{ return l[p]; }}) }};
+                }})
+            }}
+        }});
+    }}
+    if (window.Chart) {{
+        draw();
+    }} else {{
+        var script = document.createElement('script');
+        script.src = '{CHART_JS_URL}';
+        script.onload = draw;
+        document.head.appendChild(script);
+    }}
+}})();
+</script>
+"##,
+        availability = if availability_rows.is_empty() {
+            r##"<tr><td colspan="3" class="text-center text-base-content/50">暂无机器人</td></tr>"##
+                .to_string()
+        } else {
+            availability_rows
+        },
+    );
+
+    Html(content)
+}
+
+/// 日志页面查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct LogsQuery {
+    /// 最低级别：trace、debug、info、warn、error
+    #[serde(default)]
+    pub level: Option<String>,
+    /// target 前缀
+    #[serde(default)]
+    pub target: Option<String>,
+    /// 消息关键字
+    #[serde(default)]
+    pub q: Option<String>,
+}
+
+/// 日志页面 - 实时查看最近的日志，支持级别与 target 过滤
+pub async fn logs_page(Query(query): Query<LogsQuery>) -> Html<String> {
+    let level = query.level.as_deref().unwrap_or("info");
+    let level_options: String = ["trace", "debug", "info", "warn", "error"]
+        .iter()
+        .map(|l| {
+            format!(
+                r##"<option value="{}"{}>{}</option>"##,
+                l,
+                if *l == level { " selected" } else { "" },
+                l.to_uppercase()
+            )
+        })
+        .collect();
+
+    let content = format!(
+        r##"
+<div class="flex justify-between items-center mb-4">
+    <h1 class="text-2xl font-bold">日志</h1>
+    <label class="label cursor-pointer gap-2">
+        <span class="label-text">暂停刷新</span>
+        <input type="checkbox" id="log-pause" class="toggle toggle-sm" />
+    </label>
+</div>
+
+<form id="log-filters" class="flex flex-wrap gap-2 mb-4"
+      hx-get="/pages/logs/tail" hx-target="#log-lines" hx-trigger="change, keyup changed delay:500ms from:input">
+    <select name="level" class="select select-bordered select-sm">{level_options}</select>
+    <input type="text" name="target" value="{target}" placeholder="target 前缀，如 gewe_bot_app::dispatcher"
+           class="input input-bordered input-sm w-80" />
+    <input type="text" name="q" value="{q}" placeholder="关键字" class="input input-bordered input-sm w-48" />
+</form>
+
+<div class="card bg-base-100 shadow-sm">
+    <div class="card-body p-2">
+        <div class="overflow-x-auto">
+            <table class="table table-xs font-mono">
+                <thead>
+                    <tr><th>时间</th><th>级别</th><th>target</th><th>消息</th></tr>
+                </thead>
+                <tbody id="log-lines"
+                       hx-get="/pages/logs/tail"
+                       hx-include="#log-filters"
+                       hx-trigger="load, every 2s [!document.getElementById('log-pause').checked]">
+                </tbody>
+            </table>
+        </div>
+    </div>
+</div>
+"##,
+        target = escape_html(query.target.as_deref().unwrap_or_default()),
+        q = escape_html(query.q.as_deref().unwrap_or_default()),
     );
 
     Html(content)
 }
 
+/// 日志行片段，最新的在最上方
+pub async fn logs_tail(Query(query): Query<LogsQuery>) -> Html<String> {
+    let level = query
+        .level
+        .as_deref()
+        .and_then(|l| l.parse::<tracing::Level>().ok());
+    let entries = LogBuffer::global().query(&LogQuery {
+        level,
+        target: query.target.as_deref().filter(|s| !s.trim().is_empty()),
+        keyword: query.q.as_deref().filter(|s| !s.trim().is_empty()),
+        after: 0,
+        limit: LOG_TAIL_LIMIT,
+    });
+
+    let rows: String = entries
+        .iter()
+        .rev()
+        .map(|e| {
+            let badge = match e.level {
+                tracing::Level::ERROR => "badge-error",
+                tracing::Level::WARN => "badge-warning",
+                tracing::Level::INFO => "badge-info",
+                _ => "badge-ghost",
+            };
+            format!(
+                r##"<tr>
+                    <td class="whitespace-nowrap">{}</td>
+                    <td><span class="badge badge-xs {}">{}</span></td>
+                    <td class="text-base-content/70">{}</td>
+                    <td class="whitespace-pre-wrap break-all">{}</td>
+                </tr>"##,
+                e.at.with_timezone(&chrono::Local)
+                    .format("%m-%d %H:%M:%S%.3f"),
+                badge,
+                e.level,
+                escape_html(&e.target),
+                escape_html(&e.message)
+            )
+        })
+        .collect();
+
+    if rows.is_empty() {
+        return Html(
+            r##"<tr><td colspan="4" class="text-center text-base-content/50">暂无日志</td></tr>"##
+                .to_string(),
+        );
+    }
+    Html(rows)
+}
+
+/// 群名展示：配置中的别名优先，其次为 dispatcher 运行时快照中记录的群名
+async fn chatroom_names(config: &AppConfigV2) -> ChatroomNames {
+    let names = ChatroomNames::default();
+    if let Ok(Some(snapshot)) = RuntimeStateStore::new(&config.storage.data_dir)
+        .load()
+        .await
+    {
+        for (id, name) in &snapshot.chatroom_names {
+            names.insert(id, name);
+        }
+    }
+    names.set_aliases(config.chatroom_aliases.clone());
+    names
+}
+
+/// 转义日志等不可信内容
+pub(super) fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 /// Bots 列表页面
 pub async fn bots_list(
     State(state): State<ApiState>,
@@ -273,8 +747,8 @@ pub async fn bots_list(
         .iter()
         .map(|bot| {
             let id = bot.id.as_deref().unwrap_or(&bot.app_id);
-            let token_display = if bot.token_env.is_some() {
-                format!("${{{}}}", bot.token_env.as_ref().unwrap())
+            let token_display = if let Some(env) = &bot.token_env {
+                format!("${{{}}}", env)
             } else {
                 "***".to_string()
             };
@@ -374,7 +848,8 @@ pub async fn bot_edit_form(Path(id): Path<String>, State(state): State<ApiState>
         .iter()
         .find(|b| b.id.as_deref().unwrap_or(&b.app_id) == id);
 
-    let (title, bot_id, app_id, base_url, token_env, webhook_secret_env, tags) = match bot {
+    let (title, bot_id, app_id, base_url, token_env, webhook_secret_env, tags, priority) = match bot
+    {
         Some(b) => (
             format!("编辑 Bot: {}", id),
             b.id.clone().unwrap_or_default(),
@@ -387,6 +862,7 @@ pub async fn bot_edit_form(Path(id): Path<String>, State(state): State<ApiState>
             } else {
                 b.tags.join(", ")
             },
+            b.priority.map(|p| p.to_string()).unwrap_or_default(),
         ),
         None => (
             "添加 Bot".to_string(),
@@ -396,6 +872,7 @@ pub async fn bot_edit_form(Path(id): Path<String>, State(state): State<ApiState>
             String::new(),
             String::new(),
             String::new(),
+            String::new(),
         ),
     };
 
@@ -436,13 +913,19 @@ pub async fn bot_edit_form(Path(id): Path<String>, State(state): State<ApiState>
         <input type="text" class="input input-bordered" name="tags" value="{}" placeholder="prod, test" />
     </label>
 
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">协同优先级</span></div>
+        <input type="number" class="input input-bordered" name="priority" value="{}" placeholder="留空则不参与多机器人协同" />
+        <div class="label"><span class="label-text-alt text-base-content/50">同群多个机器人时，仅数值最小且规则命中的机器人响应</span></div>
+    </label>
+
     <div class="modal-action">
         <button type="button" class="btn" onclick="closeModal()">取消</button>
         <button type="submit" class="btn btn-primary" onclick="closeModal()">保存</button>
     </div>
 </form>
 "##,
-        title, id, bot_id, app_id, base_url, token_env, webhook_secret_env, tags
+        title, id, bot_id, app_id, base_url, token_env, webhook_secret_env, tags, priority
     );
 
     Html(content)
@@ -486,6 +969,12 @@ pub async fn bot_new_form() -> Html<String> {
         <input type="text" class="input input-bordered" name="tags" placeholder="prod, test" />
     </label>
 
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">协同优先级</span></div>
+        <input type="number" class="input input-bordered" name="priority" placeholder="留空则不参与多机器人协同" />
+        <div class="label"><span class="label-text-alt text-base-content/50">同群多个机器人时，仅数值最小且规则命中的机器人响应</span></div>
+    </label>
+
     <div class="modal-action">
         <button type="button" class="btn" onclick="closeModal()">取消</button>
         <button type="submit" class="btn btn-primary" onclick="closeModal()">保存</button>
@@ -515,8 +1004,8 @@ pub async fn ai_profiles_list(
         .ai_profiles
         .iter()
         .map(|profile| {
-            let api_key_display = if profile.api_key_env.is_some() {
-                format!("${{{}}}", profile.api_key_env.as_ref().unwrap())
+            let api_key_display = if let Some(env) = &profile.api_key_env {
+                format!("${{{}}}", env)
             } else {
                 "***".to_string()
             };
@@ -682,6 +1171,9 @@ pub async fn ai_profile_edit_form(
             <option value="gemini" {}>Gemini</option>
             <option value="anthropic" {}>Anthropic</option>
             <option value="deepseek" {}>DeepSeek</option>
+            <option value="ollama" {}>Ollama（本地）</option>
+            <option value="azure-openai" {}>Azure OpenAI</option>
+            <option value="openrouter" {}>OpenRouter</option>
         </select>
     </label>
 
@@ -733,6 +1225,17 @@ pub async fn ai_profile_edit_form(
         } else {
             ""
         },
+        if provider == "ollama" { "selected" } else { "" },
+        if provider == "azure-openai" {
+            "selected"
+        } else {
+            ""
+        },
+        if provider == "openrouter" {
+            "selected"
+        } else {
+            ""
+        },
         model,
         base_url,
         api_key_env,
@@ -784,6 +1287,9 @@ pub async fn ai_profile_new_form(State(state): State<ApiState>) -> Html<String>
             <option value="gemini" selected>Gemini</option>
             <option value="anthropic">Anthropic</option>
             <option value="deepseek">DeepSeek</option>
+            <option value="ollama">Ollama（本地）</option>
+            <option value="azure-openai">Azure OpenAI</option>
+            <option value="openrouter">OpenRouter</option>
         </select>
     </label>
 
@@ -849,13 +1355,51 @@ pub async fn tools_list(
         .tools
         .iter()
         .map(|tool| {
+            // 注册表安装的工具：展示文档、所需环境变量（缺失的标红）与来源
+            let env_badges: String = tool
+                .required_env
+                .iter()
+                .map(|key| {
+                    let class = if std::env::var_os(key).is_some() {
+                        "badge-ghost"
+                    } else {
+                        "badge-error"
+                    };
+                    format!(
+                        r##"<span class="badge badge-xs {} font-mono">{}</span>"##,
+                        class,
+                        escape_html(key)
+                    )
+                })
+                .collect();
+            let docs = match tool.docs.as_deref() {
+                Some(d) if d.starts_with("http://") || d.starts_with("https://") => format!(
+                    r##"<a class="link link-primary text-xs" href="{}" target="_blank">文档</a>"##,
+                    escape_html(d)
+                ),
+                Some(d) => format!(
+                    r##"<details class="text-xs"><summary class="cursor-pointer">文档</summary><pre class="whitespace-pre-wrap">{}</pre></details>"##,
+                    escape_html(d)
+                ),
+                None => String::new(),
+            };
+            let source = tool
+                .source
+                .as_deref()
+                .map(|s| {
+                    format!(
+                        r##"<div class="text-xs text-base-content/50 truncate max-w-xs" title="{0}">来源: {0}</div>"##,
+                        escape_html(s)
+                    )
+                })
+                .unwrap_or_default();
             format!(
                 r##"<tr>
                     <td class="font-mono">{}</td>
                     <td>{}</td>
                     <td class="font-mono">{}</td>
                     <td>{}</td>
-                    <td>{}</td>
+                    <td>{}{}{}<div class="flex flex-wrap gap-1">{}{}</div></td>
                     <td>
                         <div class="flex gap-1">
                             <button class="btn btn-ghost btn-xs"
@@ -878,6 +1422,10 @@ pub async fn tools_list(
                 tool.program,
                 tool.timeout_secs.unwrap_or(30),
                 tool.description.as_deref().unwrap_or("-"),
+                docs,
+                source,
+                if env_badges.is_empty() { "" } else { "<span class=\"text-xs\">环境变量:</span>" },
+                env_badges,
                 tool.id,
                 tool.id
             )
@@ -888,12 +1436,20 @@ pub async fn tools_list(
         r##"
 <div class="flex justify-between items-center mb-4">
     <h1 class="text-2xl font-bold">工具管理</h1>
-    <button class="btn btn-primary btn-sm"
-            hx-get="/pages/tools/new"
-            hx-target="#modal-content"
-            onclick="openModal()">
-        添加工具
-    </button>
+    <div class="flex gap-2">
+        <button class="btn btn-ghost btn-sm"
+                hx-get="/pages/tools/install"
+                hx-target="#modal-content"
+                onclick="openModal()">
+            从注册表安装
+        </button>
+        <button class="btn btn-primary btn-sm"
+                hx-get="/pages/tools/new"
+                hx-target="#modal-content"
+                onclick="openModal()">
+            添加工具
+        </button>
+    </div>
 </div>
 
 <div class="card bg-base-100 shadow-sm">
@@ -966,40 +1522,537 @@ pub async fn tool_edit_form(Path(id): Path<String>, State(state): State<ApiState
     let content = format!(
         r##"
 <h3 class="font-bold text-lg mb-4">{}</h3>
-<form hx-post="/pages/tools/save" hx-target="#main" hx-swap="innerHTML" class="space-y-4">
-    <input type="hidden" name="original_id" value="{}" />
+<form hx-post="/pages/tools/save" hx-target="#main" hx-swap="innerHTML" class="space-y-4">
+    <input type="hidden" name="original_id" value="{}" />
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">ID *</span></div>
+        <input type="text" class="input input-bordered" name="id" value="{}" required />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">类型 *</span></div>
+        <select class="select select-bordered" name="kind" required>
+            <option value="command" {}>command (内置命令)</option>
+            <option value="http" {}>http (HTTP 请求)</option>
+        </select>
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">程序/路径 *</span></div>
+        <input type="text" class="input input-bordered" name="program" value="{}" required />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">超时时间 (秒)</span></div>
+        <input type="number" class="input input-bordered" name="timeout_secs" value="{}" min="1" max="600" />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">描述</span></div>
+        <input type="text" class="input input-bordered" name="description" value="{}" />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">预回复 (执行前发送给用户)</span></div>
+        <input type="text" class="input input-bordered" name="pre_reply" value="{}" placeholder="例如：正在处理请求..." />
+    </label>
+
+    <div class="modal-action">
+        <button type="button" class="btn" onclick="closeModal()">取消</button>
+        <button type="submit" class="btn btn-primary" onclick="closeModal()">保存</button>
+    </div>
+</form>
+"##,
+        title,
+        id,
+        tool_id,
+        if kind == "command" { "selected" } else { "" },
+        if kind == "http" { "selected" } else { "" },
+        program,
+        timeout,
+        description,
+        pre_reply
+    );
+
+    Html(content)
+}
+
+/// 新建 Tool 表单
+pub async fn tool_new_form() -> Html<String> {
+    let content = r##"
+<h3 class="font-bold text-lg mb-4">添加工具</h3>
+<form hx-post="/pages/tools/save" hx-target="#main" hx-swap="innerHTML" class="space-y-4">
+    <input type="hidden" name="original_id" value="" />
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">ID *</span></div>
+        <input type="text" class="input input-bordered" name="id" required />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">类型 *</span></div>
+        <select class="select select-bordered" name="kind" required>
+            <option value="command" selected>command (内置命令)</option>
+            <option value="http">http (HTTP 请求)</option>
+        </select>
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">程序/路径 *</span></div>
+        <input type="text" class="input input-bordered" name="program" required />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">超时时间 (秒)</span></div>
+        <input type="number" class="input input-bordered" name="timeout_secs" value="30" min="1" max="600" />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">描述</span></div>
+        <input type="text" class="input input-bordered" name="description" />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">预回复 (执行前发送给用户)</span></div>
+        <input type="text" class="input input-bordered" name="pre_reply" placeholder="例如：正在处理请求..." />
+    </label>
+
+    <div class="modal-action">
+        <button type="button" class="btn" onclick="closeModal()">取消</button>
+        <button type="submit" class="btn btn-primary" onclick="closeModal()">保存</button>
+    </div>
+</form>
+"##;
+
+    Html(content.to_string())
+}
+
+/// 倒计时列表页面
+pub async fn countdowns_list(
+    State(state): State<ApiState>,
+    HxRequest(_is_htmx): HxRequest,
+) -> Html<String> {
+    let config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => {
+            return Html(format!(
+                r##"<div class="alert alert-error"><span>{}</span></div>"##,
+                e
+            ))
+        }
+    };
+
+    let names = chatroom_names(&config).await;
+    let rows: String = config
+        .countdowns
+        .iter()
+        .map(|c| {
+            let targets = c
+                .targets
+                .iter()
+                .map(|t| escape_html(&names.label(t)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let status = if c.enabled == Some(false) {
+                r##"<span class="badge badge-ghost badge-sm">停用</span>"##
+            } else {
+                r##"<span class="badge badge-success badge-sm">启用</span>"##
+            };
+            format!(
+                r##"<tr>
+                    <td class="font-mono">{}</td>
+                    <td>{}</td>
+                    <td class="font-mono">{}</td>
+                    <td class="font-mono">{}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                    <td>
+                        <div class="flex gap-1">
+                            <button class="btn btn-ghost btn-xs"
+                                    hx-get="/pages/countdowns/edit/{}"
+                                    hx-target="#modal-content"
+                                    onclick="openModal()">
+                                编辑
+                            </button>
+                            <button class="btn btn-error btn-xs"
+                                    hx-post="/pages/countdowns/delete/{}"
+                                    hx-target="#main"
+                                    hx-confirm="确定删除吗？">
+                                删除
+                            </button>
+                        </div>
+                    </td>
+                </tr>"##,
+                escape_html(&c.id),
+                escape_html(&c.name),
+                escape_html(&c.at),
+                escape_html(c.post_at.as_deref().unwrap_or("09:00")),
+                targets,
+                status,
+                escape_html(&c.id),
+                escape_html(&c.id)
+            )
+        })
+        .collect();
+
+    let content = format!(
+        r##"
+<div class="flex justify-between items-center mb-4">
+    <h1 class="text-2xl font-bold">倒计时</h1>
+    <button class="btn btn-primary btn-sm"
+            hx-get="/pages/countdowns/new"
+            hx-target="#modal-content"
+            onclick="openModal()">
+        添加倒计时
+    </button>
+</div>
+
+<div class="card bg-base-100 shadow-sm">
+    <div class="card-body">
+        <div class="overflow-x-auto">
+            <table class="table">
+                <thead>
+                    <tr>
+                        <th>ID</th>
+                        <th>名称</th>
+                        <th>事件时间</th>
+                        <th>播报时间</th>
+                        <th>目标会话</th>
+                        <th>状态</th>
+                        <th>操作</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {}
+                </tbody>
+            </table>
+        </div>
+    </div>
+</div>
+"##,
+        if rows.is_empty() {
+            r##"<tr><td colspan="7" class="text-center text-base-content/50">暂无倒计时</td></tr>"##
+                .to_string()
+        } else {
+            rows
+        }
+    );
+
+    Html(content)
+}
+
+/// 倒计时编辑表单
+pub async fn countdown_edit_form(
+    Path(id): Path<String>,
+    State(state): State<ApiState>,
+) -> Html<String> {
+    let config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return error_html(&e),
+    };
+
+    let existing = config.countdowns.iter().find(|c| c.id == id);
+    let title = match existing {
+        Some(_) => format!("编辑倒计时: {}", id),
+        None => "添加倒计时".to_string(),
+    };
+    let countdown = existing.cloned().unwrap_or_default();
+
+    let bot_options: String = config
+        .bots
+        .iter()
+        .map(|b| {
+            format!(
+                r##"<option value="{}" {}>{}</option>"##,
+                b.app_id,
+                if b.app_id == countdown.app_id {
+                    "selected"
+                } else {
+                    ""
+                },
+                b.app_id
+            )
+        })
+        .collect();
+
+    let content = format!(
+        r##"
+<h3 class="font-bold text-lg mb-4">{}</h3>
+<form hx-post="/pages/countdowns/save" hx-target="#main" hx-swap="innerHTML" class="space-y-4">
+    <input type="hidden" name="original_id" value="{}" />
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">ID *</span></div>
+        <input type="text" class="input input-bordered" name="id" value="{}" required />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">事件名称 *</span></div>
+        <input type="text" class="input input-bordered" name="name" value="{}" placeholder="例如：新版本发布" required />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">事件时间 *</span></div>
+        <input type="text" class="input input-bordered font-mono" name="at" value="{}" placeholder="YYYY-MM-DD 或 YYYY-MM-DD HH:MM" required />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">播报 Bot *</span></div>
+        <select class="select select-bordered" name="app_id" required>
+            {}
+        </select>
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">目标会话 * (每行一个群聊 ID 或 wxid)</span></div>
+        <textarea class="textarea textarea-bordered font-mono" name="targets" rows="3" required>{}</textarea>
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">每日播报时间 (HH:MM)</span></div>
+        <input type="text" class="input input-bordered font-mono" name="post_at" value="{}" placeholder="09:00" />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">每日播报模板 (支持 {{name}} {{days}})</span></div>
+        <input type="text" class="input input-bordered" name="message" value="{}" placeholder="距离「{{name}}」还有 {{days}} 天" />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">当天公告模板 (支持 {{name}})</span></div>
+        <input type="text" class="input input-bordered" name="final_message" value="{}" placeholder="今天就是「{{name}}」！" />
+    </label>
+
+    <label class="label cursor-pointer justify-start gap-2">
+        <input type="checkbox" class="checkbox" name="enabled" value="true" {} />
+        <span class="label-text">启用</span>
+    </label>
+
+    <div class="modal-action">
+        <button type="button" class="btn" onclick="closeModal()">取消</button>
+        <button type="submit" class="btn btn-primary" onclick="closeModal()">保存</button>
+    </div>
+</form>
+"##,
+        title,
+        id,
+        countdown.id,
+        countdown.name,
+        countdown.at,
+        bot_options,
+        countdown.targets.join("\n"),
+        countdown.post_at.clone().unwrap_or_default(),
+        countdown.message.clone().unwrap_or_default(),
+        countdown.final_message.clone().unwrap_or_default(),
+        if countdown.enabled == Some(false) {
+            ""
+        } else {
+            "checked"
+        }
+    );
+
+    Html(content)
+}
+
+/// 新建倒计时表单
+pub async fn countdown_new_form(State(state): State<ApiState>) -> Html<String> {
+    countdown_edit_form(Path(String::new()), State(state)).await
+}
+
+/// 会话设置列表页面：各机器人会话的语言、时区与语气
+pub async fn chat_settings_list(
+    State(state): State<ApiState>,
+    HxRequest(_is_htmx): HxRequest,
+) -> Html<String> {
+    let config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return error_html(&e),
+    };
+
+    let store = ChatSettingsStore::new(&config.storage.data_dir);
+    let names = chatroom_names(&config).await;
+    let mut rows = String::new();
+    for bot in &config.bots {
+        let book = match store.load(&bot.app_id).await {
+            Ok(book) => book,
+            Err(e) => return error_html(&e),
+        };
+        for (chat, settings) in &book.chats {
+            rows.push_str(&format!(
+                r##"<tr>
+                    <td class="font-mono">{}</td>
+                    <td>{}</td>
+                    <td class="font-mono">{}</td>
+                    <td class="font-mono">{}</td>
+                    <td>{}</td>
+                    <td>
+                        <div class="flex gap-1">
+                            <button class="btn btn-ghost btn-xs"
+                                    hx-get="/pages/chat-settings/edit/{}/{}"
+                                    hx-target="#modal-content"
+                                    onclick="openModal()">
+                                编辑
+                            </button>
+                            <button class="btn btn-error btn-xs"
+                                    hx-post="/pages/chat-settings/delete/{}/{}"
+                                    hx-target="#main"
+                                    hx-confirm="确定恢复该会话的默认设置吗？">
+                                删除
+                            </button>
+                        </div>
+                    </td>
+                </tr>"##,
+                escape_html(&bot.app_id),
+                escape_html(&names.label(chat)),
+                escape_html(settings.locale.as_deref().unwrap_or("-")),
+                escape_html(settings.timezone.as_deref().unwrap_or("-")),
+                settings.formality.map_or("-", |f| f.label()),
+                escape_html(&bot.app_id),
+                escape_html(chat),
+                escape_html(&bot.app_id),
+                escape_html(chat),
+            ));
+        }
+    }
+
+    let content = format!(
+        r##"
+<div class="flex justify-between items-center mb-4">
+    <h1 class="text-2xl font-bold">会话设置</h1>
+    <button class="btn btn-primary btn-sm"
+            hx-get="/pages/chat-settings/new"
+            hx-target="#modal-content"
+            onclick="openModal()">
+        添加会话设置
+    </button>
+</div>
+
+<div class="card bg-base-100 shadow-sm">
+    <div class="card-body">
+        <p class="text-sm text-base-content/70">语言与语气影响 AI 回复，时区用于提醒、倒计时与提示词中的本地时间；修改后约 30 秒内生效</p>
+        <div class="overflow-x-auto">
+            <table class="table">
+                <thead>
+                    <tr>
+                        <th>Bot</th>
+                        <th>会话</th>
+                        <th>语言</th>
+                        <th>时区</th>
+                        <th>语气</th>
+                        <th>操作</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {}
+                </tbody>
+            </table>
+        </div>
+    </div>
+</div>
+"##,
+        if rows.is_empty() {
+            r##"<tr><td colspan="6" class="text-center text-base-content/50">暂无会话设置</td></tr>"##
+                .to_string()
+        } else {
+            rows
+        }
+    );
+
+    Html(content)
+}
+
+/// 会话设置编辑表单
+pub async fn chat_setting_edit_form(
+    Path((app_id, chat)): Path<(String, String)>,
+    State(state): State<ApiState>,
+) -> Html<String> {
+    let config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return error_html(&e),
+    };
+
+    let settings = if chat.is_empty() {
+        ChatSettings::default()
+    } else {
+        match ChatSettingsStore::new(&config.storage.data_dir)
+            .load(&app_id)
+            .await
+        {
+            Ok(mut book) => book.chats.remove(&chat).unwrap_or_default(),
+            Err(e) => return error_html(&e),
+        }
+    };
+    let title = if chat.is_empty() {
+        "添加会话设置".to_string()
+    } else {
+        format!("编辑会话设置: {}", escape_html(&chat))
+    };
+
+    let bot_options: String = config
+        .bots
+        .iter()
+        .map(|b| {
+            format!(
+                r##"<option value="{}" {}>{}</option>"##,
+                escape_html(&b.app_id),
+                if b.app_id == app_id { "selected" } else { "" },
+                escape_html(&b.app_id)
+            )
+        })
+        .collect();
+    let formality_options: String = [
+        ("", "默认"),
+        ("casual", Formality::Casual.label()),
+        ("neutral", Formality::Neutral.label()),
+        ("formal", Formality::Formal.label()),
+    ]
+    .iter()
+    .map(|(value, label)| {
+        let selected = settings.formality.map_or("", |f| f.as_str()) == *value;
+        format!(
+            r##"<option value="{}" {}>{}</option>"##,
+            value,
+            if selected { "selected" } else { "" },
+            label
+        )
+    })
+    .collect();
+
+    let content = format!(
+        r##"
+<h3 class="font-bold text-lg mb-4">{}</h3>
+<form hx-post="/pages/chat-settings/save" hx-target="#main" hx-swap="innerHTML" class="space-y-4">
+    <input type="hidden" name="original_app_id" value="{}" />
+    <input type="hidden" name="original_chat" value="{}" />
 
     <label class="form-control w-full">
-        <div class="label"><span class="label-text">ID *</span></div>
-        <input type="text" class="input input-bordered" name="id" value="{}" required />
-    </label>
-
-    <label class="form-control w-full">
-        <div class="label"><span class="label-text">类型 *</span></div>
-        <select class="select select-bordered" name="kind" required>
-            <option value="command" {}>command (内置命令)</option>
-            <option value="http" {}>http (HTTP 请求)</option>
+        <div class="label"><span class="label-text">Bot *</span></div>
+        <select class="select select-bordered" name="app_id" required>
+            {}
         </select>
     </label>
 
     <label class="form-control w-full">
-        <div class="label"><span class="label-text">程序/路径 *</span></div>
-        <input type="text" class="input input-bordered" name="program" value="{}" required />
+        <div class="label"><span class="label-text">会话 * (群聊 ID 或 wxid)</span></div>
+        <input type="text" class="input input-bordered font-mono" name="chat" value="{}" required />
     </label>
 
     <label class="form-control w-full">
-        <div class="label"><span class="label-text">超时时间 (秒)</span></div>
-        <input type="number" class="input input-bordered" name="timeout_secs" value="{}" min="1" max="600" />
+        <div class="label"><span class="label-text">语言</span></div>
+        <input type="text" class="input input-bordered font-mono" name="locale" value="{}" placeholder="zh-CN、en-US" />
     </label>
 
     <label class="form-control w-full">
-        <div class="label"><span class="label-text">描述</span></div>
-        <input type="text" class="input input-bordered" name="description" value="{}" />
+        <div class="label"><span class="label-text">时区 (IANA)</span></div>
+        <input type="text" class="input input-bordered font-mono" name="timezone" value="{}" placeholder="Asia/Shanghai" />
     </label>
 
     <label class="form-control w-full">
-        <div class="label"><span class="label-text">预回复 (执行前发送给用户)</span></div>
-        <input type="text" class="input input-bordered" name="pre_reply" value="{}" placeholder="例如：正在处理请求..." />
+        <div class="label"><span class="label-text">语气</span></div>
+        <select class="select select-bordered" name="formality">
+            {}
+        </select>
     </label>
 
     <div class="modal-action">
@@ -1009,67 +2062,21 @@ pub async fn tool_edit_form(Path(id): Path<String>, State(state): State<ApiState
 </form>
 "##,
         title,
-        id,
-        tool_id,
-        if kind == "command" { "selected" } else { "" },
-        if kind == "http" { "selected" } else { "" },
-        program,
-        timeout,
-        description,
-        pre_reply
+        escape_html(&app_id),
+        escape_html(&chat),
+        bot_options,
+        escape_html(&chat),
+        escape_html(settings.locale.as_deref().unwrap_or_default()),
+        escape_html(settings.timezone.as_deref().unwrap_or_default()),
+        formality_options
     );
 
     Html(content)
 }
 
-/// 新建 Tool 表单
-pub async fn tool_new_form() -> Html<String> {
-    let content = r##"
-<h3 class="font-bold text-lg mb-4">添加工具</h3>
-<form hx-post="/pages/tools/save" hx-target="#main" hx-swap="innerHTML" class="space-y-4">
-    <input type="hidden" name="original_id" value="" />
-
-    <label class="form-control w-full">
-        <div class="label"><span class="label-text">ID *</span></div>
-        <input type="text" class="input input-bordered" name="id" required />
-    </label>
-
-    <label class="form-control w-full">
-        <div class="label"><span class="label-text">类型 *</span></div>
-        <select class="select select-bordered" name="kind" required>
-            <option value="command" selected>command (内置命令)</option>
-            <option value="http">http (HTTP 请求)</option>
-        </select>
-    </label>
-
-    <label class="form-control w-full">
-        <div class="label"><span class="label-text">程序/路径 *</span></div>
-        <input type="text" class="input input-bordered" name="program" required />
-    </label>
-
-    <label class="form-control w-full">
-        <div class="label"><span class="label-text">超时时间 (秒)</span></div>
-        <input type="number" class="input input-bordered" name="timeout_secs" value="30" min="1" max="600" />
-    </label>
-
-    <label class="form-control w-full">
-        <div class="label"><span class="label-text">描述</span></div>
-        <input type="text" class="input input-bordered" name="description" />
-    </label>
-
-    <label class="form-control w-full">
-        <div class="label"><span class="label-text">预回复 (执行前发送给用户)</span></div>
-        <input type="text" class="input input-bordered" name="pre_reply" placeholder="例如：正在处理请求..." />
-    </label>
-
-    <div class="modal-action">
-        <button type="button" class="btn" onclick="closeModal()">取消</button>
-        <button type="submit" class="btn btn-primary" onclick="closeModal()">保存</button>
-    </div>
-</form>
-"##;
-
-    Html(content.to_string())
+/// 新建会话设置表单
+pub async fn chat_setting_new_form(State(state): State<ApiState>) -> Html<String> {
+    chat_setting_edit_form(Path((String::new(), String::new())), State(state)).await
 }
 
 /// Rules 页面 (包含模板和实例)
@@ -1126,6 +2133,10 @@ pub async fn rules_page(
                                     onclick="openModal()">
                                 编辑
                             </button>
+                            <a class="btn btn-ghost btn-xs"
+                               href="/api/rule-templates/{}/export">
+                                导出
+                            </a>
                             <button class="btn btn-error btn-xs"
                                     hx-post="/pages/rule-templates/delete/{}"
                                     hx-target="#main"
@@ -1140,6 +2151,7 @@ pub async fn rules_page(
                 kind,
                 action,
                 t.id,
+                t.id,
                 t.id
             )
         })
@@ -1446,6 +2458,10 @@ pub async fn simulator_page(HxRequest(is_htmx): HxRequest) -> Response {
                         <option value="emoji">emoji (表情)</option>
                         <option value="link">link (链接)</option>
                         <option value="file_notice">file_notice (文件)</option>
+                        <option value="location">location (位置)</option>
+                        <option value="red_packet">red_packet (红包)</option>
+                        <option value="transfer">transfer (转账)</option>
+                        <option value="name_card">name_card (名片)</option>
                     </select>
                 </label>
 
@@ -1467,6 +2483,11 @@ pub async fn simulator_page(HxRequest(is_htmx): HxRequest) -> Response {
                     <input type="text" class="input input-bordered" name="from_wxid" placeholder="wxid_xxx" />
                 </label>
 
+                <label class="form-control w-full">
+                    <div class="label"><span class="label-text">模拟时间 (可选，检查规则生效时间)</span></div>
+                    <input type="text" class="input input-bordered font-mono" name="at" placeholder="YYYY-MM-DD HH:MM" />
+                </label>
+
                 <label class="label cursor-pointer justify-start gap-2">
                     <input type="checkbox" class="checkbox" name="mentioned" />
                     <span class="label-text">被 @ 了机器人</span>
@@ -1504,6 +2525,7 @@ pub struct BotFormData {
     pub token_env: Option<String>,
     pub webhook_secret_env: Option<String>,
     pub tags: Option<String>,
+    pub priority: Option<String>,
 }
 
 /// AI Profile 表单数据
@@ -1520,6 +2542,17 @@ pub struct AiProfileFormData {
     pub tool_ids: Vec<String>,
 }
 
+/// 工具注册表安装表单数据
+#[derive(Debug, Deserialize)]
+pub struct ToolInstallFormData {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub overwrite: Option<String>,
+}
+
 /// Tool 表单数据
 #[derive(Debug, Deserialize)]
 pub struct ToolFormData {
@@ -1532,6 +2565,33 @@ pub struct ToolFormData {
     pub pre_reply: Option<String>,
 }
 
+/// 倒计时表单数据
+#[derive(Debug, Deserialize)]
+pub struct CountdownFormData {
+    pub original_id: String,
+    pub id: String,
+    pub name: String,
+    pub at: String,
+    pub app_id: String,
+    pub targets: String,
+    pub post_at: Option<String>,
+    pub message: Option<String>,
+    pub final_message: Option<String>,
+    pub enabled: Option<String>,
+}
+
+/// 会话设置表单数据
+#[derive(Debug, Deserialize)]
+pub struct ChatSettingFormData {
+    pub original_app_id: String,
+    pub original_chat: String,
+    pub app_id: String,
+    pub chat: String,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub formality: Option<String>,
+}
+
 /// 规则模板表单数据
 #[derive(Debug, Deserialize)]
 pub struct RuleTemplateFormData {
@@ -1604,6 +2664,22 @@ pub async fn bot_save(
         webhook_secret: None,
         webhook_secret_env: form.webhook_secret_env.filter(|s| !s.is_empty()),
         tags,
+        priority: form.priority.and_then(|s| s.trim().parse().ok()),
+        failover: config
+            .bots
+            .iter()
+            .find(|b| b.id.as_deref().unwrap_or(&b.app_id) == form.original_id)
+            .and_then(|b| b.failover.clone()),
+        digest: config
+            .bots
+            .iter()
+            .find(|b| b.id.as_deref().unwrap_or(&b.app_id) == form.original_id)
+            .and_then(|b| b.digest.clone()),
+        shadow: config
+            .bots
+            .iter()
+            .find(|b| b.id.as_deref().unwrap_or(&b.app_id) == form.original_id)
+            .and_then(|b| b.shadow),
     };
 
     // 查找并更新或添加
@@ -1639,6 +2715,7 @@ pub async fn ai_profile_save(
         Err(e) => return error_html(&e),
     };
 
+    let existing = config.ai_profiles.iter().find(|p| p.id == form.original_id);
     let new_profile = AiProfileV2 {
         id: form.id.clone(),
         provider: if form.provider.is_empty() {
@@ -1654,6 +2731,25 @@ pub async fn ai_profile_save(
         system_prompt_file: form.system_prompt_file.filter(|s| !s.is_empty()),
         user_prefix: None,
         tool_ids: form.tool_ids,
+        // 表单不编辑语义缓存、Prompt 变体、模型灰度、预算、工具循环保护、provider 额外配置与前置工具，保留原有配置
+        cache: existing.and_then(|p| p.cache.clone()),
+        variants: existing.map(|p| p.variants.clone()).unwrap_or_default(),
+        model_rollout: existing
+            .map(|p| p.model_rollout.clone())
+            .unwrap_or_default(),
+        feedback: existing.and_then(|p| p.feedback.clone()),
+        budget: existing.and_then(|p| p.budget.clone()),
+        structured: existing.and_then(|p| p.structured.clone()),
+        tool_loop: existing.and_then(|p| p.tool_loop.clone()),
+        memory: existing.and_then(|p| p.memory.clone()),
+        stream: existing.and_then(|p| p.stream),
+        stream_edit: existing.and_then(|p| p.stream_edit),
+        reply_voice: existing.and_then(|p| p.reply_voice.clone()),
+        max_chars_per_message: existing.and_then(|p| p.max_chars_per_message),
+        prompt_guard: existing.and_then(|p| p.prompt_guard.clone()),
+        azure: existing.and_then(|p| p.azure.clone()),
+        openrouter: existing.and_then(|p| p.openrouter.clone()),
+        pre_tool: existing.and_then(|p| p.pre_tool.clone()),
     };
 
     // 查找并更新或添加
@@ -1689,6 +2785,11 @@ pub async fn tool_save(
         Err(e) => return error_html(&e),
     };
 
+    let existing = config
+        .tools
+        .iter()
+        .find(|t| !form.original_id.is_empty() && t.id == form.original_id);
+
     let new_tool = ToolConfigV2 {
         id: form.id.clone(),
         kind: if form.kind.is_empty() {
@@ -1704,6 +2805,14 @@ pub async fn tool_save(
         post_reply: None,
         description: form.description.filter(|s| !s.is_empty()),
         parameters: None,
+        // 表单不编辑注册表信息、后台执行开关与内置工具的服务配置，保留原有配置
+        run_async: existing.and_then(|t| t.run_async),
+        docs: existing.and_then(|t| t.docs.clone()),
+        required_env: existing.map(|t| t.required_env.clone()).unwrap_or_default(),
+        source: existing.and_then(|t| t.source.clone()),
+        http: existing.and_then(|t| t.http.clone()),
+        image: existing.and_then(|t| t.image.clone()),
+        ocr: existing.and_then(|t| t.ocr.clone()),
     };
 
     // 查找并更新或添加
@@ -1725,6 +2834,227 @@ pub async fn tool_save(
     success_redirect_html("工具已保存", "/pages/tools")
 }
 
+/// 工具注册表安装表单
+pub async fn tool_install_form() -> Html<String> {
+    Html(
+        r##"
+<h3 class="font-bold text-lg mb-4">从注册表安装工具</h3>
+<form hx-post="/pages/tools/install" hx-target="#main" hx-swap="innerHTML" class="space-y-4">
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">注册表 URL</span></div>
+        <input type="url" class="input input-bordered" name="url" placeholder="https://example.com/tools.toml" />
+    </label>
+
+    <label class="form-control w-full">
+        <div class="label"><span class="label-text">或粘贴注册表内容 (TOML)</span></div>
+        <textarea class="textarea textarea-bordered font-mono h-40" name="content"
+                  placeholder="registry_version = 1&#10;&#10;[[tools]]&#10;name = &quot;weather&quot;&#10;command = &quot;weather-cli&quot;"></textarea>
+    </label>
+
+    <label class="label cursor-pointer justify-start gap-2">
+        <input type="checkbox" class="checkbox checkbox-sm" name="overwrite" value="true" />
+        <span class="label-text">覆盖同名工具</span>
+    </label>
+
+    <div class="modal-action">
+        <button type="button" class="btn" onclick="closeModal()">取消</button>
+        <button type="submit" class="btn btn-primary" onclick="closeModal()">安装</button>
+    </div>
+</form>
+"##
+        .to_string(),
+    )
+}
+
+/// 从注册表安装工具
+pub async fn tool_install(
+    State(state): State<ApiState>,
+    Form(form): Form<ToolInstallFormData>,
+) -> Html<String> {
+    let url = form.url.filter(|s| !s.trim().is_empty());
+    let body = match (&url, form.content.filter(|s| !s.trim().is_empty())) {
+        (Some(url), _) => match fetch_registry(url).await {
+            Ok(body) => body,
+            Err(e) => return error_html(&e),
+        },
+        (None, Some(content)) => content,
+        (None, None) => return error_html("请填写注册表 URL 或内容"),
+    };
+
+    match super::tool_registry::install_and_save(
+        &state,
+        &body,
+        url.as_deref(),
+        form.overwrite.is_some(),
+    )
+    .await
+    {
+        Ok(summary) => {
+            let mut message = format!("已安装: {}", summary.installed.join(", "));
+            if !summary.skipped.is_empty() {
+                message.push_str(&format!("；已存在未覆盖: {}", summary.skipped.join(", ")));
+            }
+            if !summary.missing_env.is_empty() {
+                message.push_str(&format!(
+                    "；缺少环境变量: {}",
+                    summary.missing_env.join(", ")
+                ));
+            }
+            success_redirect_html(&escape_html(&message), "/pages/tools")
+        }
+        Err((_, e, errors)) if !errors.is_empty() => {
+            error_html(&escape_html(&format!("{}: {}", e, errors.join("; "))))
+        }
+        Err((_, e, _)) => error_html(&escape_html(&e)),
+    }
+}
+
+/// 下载注册表；与 HTTP 工具相同，禁止访问内网地址且不跟随重定向
+async fn fetch_registry(url: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(url.trim()).map_err(|e| format!("无效的注册表 URL: {}", e))?;
+    let resp = crate::tools::guarded_get(&url, std::time::Duration::from_secs(15))
+        .await
+        .map_err(|e| format!("下载注册表失败: {}", e))?
+        .send()
+        .await
+        .map_err(|e| format!("下载注册表失败: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("下载注册表失败: HTTP {}", resp.status()));
+    }
+    resp.text()
+        .await
+        .map_err(|e| format!("读取注册表失败: {}", e))
+}
+
+/// 保存倒计时
+pub async fn countdown_save(
+    State(state): State<ApiState>,
+    Form(form): Form<CountdownFormData>,
+) -> Html<String> {
+    let mut config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return error_html(&e),
+    };
+
+    // 表单未提供补跑策略、cron 与时区，保留原配置
+    let original = config
+        .countdowns
+        .iter()
+        .find(|c| !form.original_id.is_empty() && c.id == form.original_id)
+        .cloned()
+        .unwrap_or_default();
+    let new_countdown = CountdownConfig {
+        id: form.id.trim().to_string(),
+        name: form.name.trim().to_string(),
+        at: form.at.trim().to_string(),
+        app_id: form.app_id,
+        targets: form
+            .targets
+            .split(['\n', ','])
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect(),
+        post_at: form.post_at.filter(|s| !s.trim().is_empty()),
+        message: form.message.filter(|s| !s.trim().is_empty()),
+        final_message: form.final_message.filter(|s| !s.trim().is_empty()),
+        enabled: if form.enabled.is_some() {
+            None
+        } else {
+            Some(false)
+        },
+        cron: original.cron,
+        timezone: original.timezone,
+        catch_up: original.catch_up,
+    };
+    if new_countdown.event_time().is_none() {
+        return error_html("事件时间格式应为 YYYY-MM-DD 或 YYYY-MM-DD HH:MM");
+    }
+    if new_countdown.post_time().is_none() {
+        return error_html("播报时间格式应为 HH:MM");
+    }
+
+    // 查找并更新或添加
+    match config
+        .countdowns
+        .iter()
+        .position(|c| !form.original_id.is_empty() && c.id == form.original_id)
+    {
+        Some(pos) => config.countdowns[pos] = new_countdown,
+        None => config.countdowns.push(new_countdown),
+    }
+
+    // 保存
+    if let Err(e) = save_config(&state, &config).await {
+        return error_html(&e);
+    }
+
+    success_redirect_html("倒计时已保存", "/pages/countdowns")
+}
+
+/// 保存会话设置，运行中的 dispatcher 定时读取
+pub async fn chat_setting_save(
+    State(state): State<ApiState>,
+    Form(form): Form<ChatSettingFormData>,
+) -> Html<String> {
+    let data_dir = match state.data_dir().await {
+        Ok(dir) => dir,
+        Err(e) => return error_html(&e),
+    };
+
+    let chat = form.chat.trim();
+    if form.app_id.trim().is_empty() || chat.is_empty() {
+        return error_html("Bot 与会话不能为空");
+    }
+    let non_empty = |value: Option<String>| {
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let locale = non_empty(form.locale);
+    if locale.as_deref().is_some_and(|l| !is_valid_locale(l)) {
+        return error_html("语言格式无效，示例：zh-CN、en-US");
+    }
+    let timezone = non_empty(form.timezone);
+    if let Err(e) = ScheduleTz::parse(timezone.as_deref()) {
+        return error_html(&format!("时区无效: {}", e));
+    }
+    let formality = match non_empty(form.formality) {
+        Some(value) => match Formality::parse(&value) {
+            Some(f) => Some(f),
+            None => return error_html("语气应为 casual、neutral 或 formal"),
+        },
+        None => None,
+    };
+
+    let store = ChatSettingsStore::new(&data_dir);
+    // 修改了 Bot 或会话时移除原记录
+    if !form.original_chat.is_empty()
+        && (form.original_app_id != form.app_id || form.original_chat != chat)
+    {
+        if let Err(e) = store
+            .update(
+                &form.original_app_id,
+                &form.original_chat,
+                ChatSettings::default(),
+            )
+            .await
+        {
+            return error_html(&e);
+        }
+    }
+    let settings = ChatSettings {
+        locale,
+        timezone,
+        formality,
+    };
+    if let Err(e) = store.update(form.app_id.trim(), chat, settings).await {
+        return error_html(&e);
+    }
+
+    success_redirect_html("会话设置已保存", "/pages/chat-settings")
+}
+
 /// 规则模板编辑表单
 pub async fn rule_template_edit_form(
     Path(id): Path<String>,
@@ -1948,11 +3278,19 @@ pub async fn rule_template_save(
         _ => None,
     };
 
+    // 表单未覆盖的媒体过滤条件沿用原模板
+    let existing = config
+        .rule_templates
+        .iter()
+        .find(|t| !form.original_id.is_empty() && t.id == form.original_id);
+    let existing_match = existing.map(|t| t.r#match.clone()).unwrap_or_default();
+
     let match_config = MatchConfigV2 {
         any: form.match_any.as_ref().map(|_| true),
         equals: form.match_equals.filter(|s| !s.is_empty()),
         contains: form.match_contains.filter(|s| !s.is_empty()),
         regex: form.match_regex.filter(|s| !s.is_empty()),
+        ..existing_match
     };
 
     let reply_mode = match form.reply_mode.as_deref() {
@@ -1968,6 +3306,16 @@ pub async fn rule_template_save(
         log: form.log.as_ref().map(|_| true),
         require_mention: form.require_mention.as_ref().map(|_| true),
         reply_text: None,
+        // 表单不编辑组合回复、链接卡片、欢迎语与失败处理，沿用原模板
+        reply_sequence: existing
+            .map(|t| t.action.reply_sequence.clone())
+            .unwrap_or_default(),
+        reply_link: existing.and_then(|t| t.action.reply_link.clone()),
+        reply_voice: existing.and_then(|t| t.action.reply_voice.clone()),
+        welcome: existing.and_then(|t| t.action.welcome.clone()),
+        on_error: existing.and_then(|t| t.action.on_error.clone()),
+        cooldown_secs: existing.and_then(|t| t.action.cooldown_secs),
+        cooldown_scope: existing.and_then(|t| t.action.cooldown_scope),
     };
 
     let defaults = TemplateDefaultsV2 {
@@ -1981,6 +3329,10 @@ pub async fn rule_template_save(
         r#match: match_config,
         action,
         defaults,
+        // 表单不编辑菜单说明，沿用原模板
+        description: existing.and_then(|t| t.description.clone()),
+        usage: existing.and_then(|t| t.usage.clone()),
+        continue_matching: existing.and_then(|t| t.continue_matching),
     };
 
     // 查找并更新或添加
@@ -2180,13 +3532,31 @@ pub async fn rule_instance_save(
         wxid: form.from_wxid.filter(|s| !s.is_empty()),
     };
 
-    // 构建 overrides
+    // 构建 overrides；表单不编辑菜单说明、是否继续匹配与冷却，沿用原实例
+    let (description, usage, continue_matching, cooldown_secs) = config
+        .rule_instances
+        .iter()
+        .find(|i| i.id == form.original_id)
+        .and_then(|i| i.overrides.as_ref())
+        .map(|o| {
+            (
+                o.description.clone(),
+                o.usage.clone(),
+                o.continue_matching,
+                o.cooldown_secs,
+            )
+        })
+        .unwrap_or_default();
     let overrides = if form
         .ai_profile
         .as_ref()
         .map(|s| !s.is_empty())
         .unwrap_or(false)
         || form.require_mention.is_some()
+        || description.is_some()
+        || usage.is_some()
+        || continue_matching.is_some()
+        || cooldown_secs.is_some()
     {
         Some(InstanceOverridesV2 {
             ai_profile: form.ai_profile.filter(|s| !s.is_empty()),
@@ -2194,6 +3564,10 @@ pub async fn rule_instance_save(
             reply_mode: None,
             log: None,
             reply_text: None,
+            description,
+            usage,
+            continue_matching,
+            cooldown_secs,
         })
     } else {
         None
@@ -2502,6 +3876,19 @@ pub async fn settings_save(
     config.server = ServerConfigV2 {
         listen_addr: form.listen_addr,
         queue_size: form.queue_size,
+        shadow: config.server.shadow,
+        admins: config.server.admins.clone(),
+        command_pool: config.server.command_pool.clone(),
+        ai_tasks: config.server.ai_tasks.clone(),
+        memory: config.server.memory.clone(),
+        safe_mode: config.server.safe_mode.clone(),
+        chat_settings: config.server.chat_settings.clone(),
+        own_message_echo: config.server.own_message_echo,
+        moderation: config.server.moderation.clone(),
+        undo: config.server.undo.clone(),
+        media: config.server.media.clone(),
+        history: config.server.history.clone(),
+        watchdog: config.server.watchdog.clone(),
     };
 
     // 更新 storage 配置
@@ -2509,6 +3896,7 @@ pub async fn settings_save(
         image_dir: form.image_dir,
         image_url_prefix: form.image_url_prefix,
         external_base_url: form.external_base_url.filter(|s| !s.is_empty()),
+        data_dir: config.storage.data_dir.clone(),
     };
 
     // 更新 defaults 配置
@@ -2613,6 +4001,54 @@ pub async fn tool_delete(Path(id): Path<String>, State(state): State<ApiState>)
     success_redirect_html(&format!("工具 {} 已删除", id), "/pages/tools")
 }
 
+/// 删除倒计时
+pub async fn countdown_delete(
+    Path(id): Path<String>,
+    State(state): State<ApiState>,
+) -> Html<String> {
+    let mut config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return error_html(&e),
+    };
+
+    // 查找并删除
+    if let Some(pos) = config.countdowns.iter().position(|c| c.id == id) {
+        config.countdowns.remove(pos);
+    } else {
+        return error_html(&format!("未找到倒计时: {}", id));
+    }
+
+    // 保存
+    if let Err(e) = save_config(&state, &config).await {
+        return error_html(&e);
+    }
+
+    success_redirect_html(&format!("倒计时 {} 已删除", id), "/pages/countdowns")
+}
+
+/// 删除会话设置，恢复默认
+pub async fn chat_setting_delete(
+    Path((app_id, chat)): Path<(String, String)>,
+    State(state): State<ApiState>,
+) -> Html<String> {
+    let data_dir = match state.data_dir().await {
+        Ok(dir) => dir,
+        Err(e) => return error_html(&e),
+    };
+
+    if let Err(e) = ChatSettingsStore::new(&data_dir)
+        .update(&app_id, &chat, ChatSettings::default())
+        .await
+    {
+        return error_html(&e);
+    }
+
+    success_redirect_html(
+        &format!("会话 {} 已恢复默认设置", escape_html(&chat)),
+        "/pages/chat-settings",
+    )
+}
+
 /// 删除规则模板
 pub async fn rule_template_delete(
     Path(id): Path<String>,
@@ -0,0 +1,513 @@
+//! 规则模板导入导出 API 处理函数
+//!
+//! 导出的规则包为自包含的 YAML：模板本身、引用的 AI Profile（prompt 文件已内联、API Key 已剥离）
+//! 以及该 Profile 使用的工具，便于在不同部署之间分享。
+
+use super::state::{compute_etag, ApiState};
+use crate::config::{
+    resolve_system_prompt, AiProfileV2, AppConfigV2, RuleTemplateV2, ToolConfigV2,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// 当前规则包格式版本
+pub const RULE_PACK_VERSION: u8 = 1;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<Vec<String>>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+            errors: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+            errors: None,
+        }
+    }
+
+    fn validation_errors(errors: Vec<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some("配置校验失败".to_string()),
+            errors: Some(errors),
+        }
+    }
+}
+
+/// 可分享的规则包
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleTemplatePack {
+    pub pack_version: u8,
+    pub template: RuleTemplateV2,
+    /// 模板引用的 AI Profile，system prompt 已内联
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ai_profile: Option<AiProfileV2>,
+    /// AI Profile 使用的工具
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolConfigV2>,
+}
+
+/// 导入参数
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportQuery {
+    /// 覆盖同名模板、AI Profile 与工具
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// 导入结果
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ImportSummary {
+    pub template_id: String,
+    /// 新增或覆盖的条目，如 `ai_profile:default`
+    pub imported: Vec<String>,
+    /// 已存在而保留本地版本的条目
+    pub skipped: Vec<String>,
+}
+
+/// 从配置中构建规则包
+///
+/// `config_path` 用于解析相对路径的 `system_prompt_file`。
+pub fn build_pack(
+    config: &AppConfigV2,
+    id: &str,
+    config_path: &std::path::Path,
+) -> Result<RuleTemplatePack, String> {
+    let template = config
+        .rule_templates
+        .iter()
+        .find(|t| t.id == id)
+        .cloned()
+        .ok_or_else(|| format!("规则模板不存在: {}", id))?;
+
+    let ai_profile = match template.action.ai_profile.as_deref() {
+        Some(profile_id) => {
+            let profile = config
+                .ai_profiles
+                .iter()
+                .find(|p| p.id == profile_id)
+                .ok_or_else(|| format!("AI Profile 不存在: {}", profile_id))?;
+            Some(inline_profile(profile, config_path)?)
+        }
+        None => None,
+    };
+
+    let tools = ai_profile
+        .iter()
+        .flat_map(|p| p.tool_ids.iter().chain(p.pre_tool.iter()))
+        .filter_map(|tool_id| config.tools.iter().find(|t| &t.id == tool_id))
+        .cloned()
+        .collect();
+
+    Ok(RuleTemplatePack {
+        pack_version: RULE_PACK_VERSION,
+        template,
+        ai_profile,
+        tools,
+    })
+}
+
+/// 内联 prompt 文件并剥离 API Key（保留 api_key_env 以提示需要的环境变量）
+fn inline_profile(
+    profile: &AiProfileV2,
+    config_path: &std::path::Path,
+) -> Result<AiProfileV2, String> {
+    let mut profile = profile.clone();
+    profile.api_key = None;
+    profile.system_prompt = resolve_system_prompt(
+        &profile.system_prompt,
+        &profile.system_prompt_file,
+        config_path,
+    )
+    .map_err(|e| format!("{:#}", e))?;
+    profile.system_prompt_file = None;
+    for variant in &mut profile.variants {
+        variant.system_prompt = resolve_system_prompt(
+            &variant.system_prompt,
+            &variant.system_prompt_file,
+            config_path,
+        )
+        .map_err(|e| format!("{:#}", e))?;
+        variant.system_prompt_file = None;
+    }
+    Ok(profile)
+}
+
+/// 将规则包合并进配置
+///
+/// 模板已存在且未指定 `overwrite` 时报错；AI Profile 与工具已存在时保留本地版本。
+pub fn apply_pack(
+    config: &mut AppConfigV2,
+    pack: RuleTemplatePack,
+    overwrite: bool,
+) -> Result<ImportSummary, String> {
+    if pack.pack_version != RULE_PACK_VERSION {
+        return Err(format!(
+            "不支持的规则包版本: {}（当前支持 {}）",
+            pack.pack_version, RULE_PACK_VERSION
+        ));
+    }
+
+    let mut summary = ImportSummary {
+        template_id: pack.template.id.clone(),
+        ..Default::default()
+    };
+
+    match config
+        .rule_templates
+        .iter()
+        .position(|t| t.id == pack.template.id)
+    {
+        Some(_) if !overwrite => {
+            return Err(format!("规则模板已存在: {}", pack.template.id));
+        }
+        Some(pos) => config.rule_templates[pos] = pack.template,
+        None => config.rule_templates.push(pack.template),
+    }
+    summary
+        .imported
+        .push(format!("template:{}", summary.template_id));
+
+    if let Some(profile) = pack.ai_profile {
+        let label = format!("ai_profile:{}", profile.id);
+        match config.ai_profiles.iter().position(|p| p.id == profile.id) {
+            Some(pos) if overwrite => {
+                config.ai_profiles[pos] = profile;
+                summary.imported.push(label);
+            }
+            Some(_) => summary.skipped.push(label),
+            None => {
+                config.ai_profiles.push(profile);
+                summary.imported.push(label);
+            }
+        }
+    }
+
+    for tool in pack.tools {
+        let label = format!("tool:{}", tool.id);
+        match config.tools.iter().position(|t| t.id == tool.id) {
+            Some(pos) if overwrite => {
+                config.tools[pos] = tool;
+                summary.imported.push(label);
+            }
+            Some(_) => summary.skipped.push(label),
+            None => {
+                config.tools.push(tool);
+                summary.imported.push(label);
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+async fn load_config(state: &ApiState) -> Result<AppConfigV2, String> {
+    let content = tokio::fs::read_to_string(state.config_path())
+        .await
+        .map_err(|e| format!("读取配置失败: {}", e))?;
+    AppConfigV2::parse(&content).map_err(|e| format!("解析配置失败: {}", e))
+}
+
+/// GET /api/rule-templates/{id}/export - 导出规则模板为 YAML 规则包
+pub async fn export_rule_template(
+    Path(id): Path<String>,
+    State(state): State<ApiState>,
+) -> impl IntoResponse {
+    let config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+
+    let pack = match build_pack(&config, &id, state.config_path()) {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::NOT_FOUND, e).into_response(),
+    };
+
+    let yaml = match serde_yaml::to_string(&pack) {
+        Ok(y) => y,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("序列化 YAML 失败: {}", e),
+            )
+                .into_response();
+        }
+    };
+
+    (
+        StatusCode::OK,
+        [
+            ("Content-Type", "application/yaml".to_string()),
+            (
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.rule.yaml\"", id),
+            ),
+        ],
+        yaml,
+    )
+        .into_response()
+}
+
+/// POST /api/rule-templates/import - 导入 YAML 规则包（保存为草稿）
+pub async fn import_rule_template(
+    State(state): State<ApiState>,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> impl IntoResponse {
+    let pack: RuleTemplatePack = match serde_yaml::from_str(&body) {
+        Ok(p) => p,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ApiResponse::<ImportSummary>::error(format!(
+                    "解析 YAML 失败: {}",
+                    e
+                ))),
+            );
+        }
+    };
+
+    let mut config = match load_config(&state).await {
+        Ok(c) => c,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<ImportSummary>::error(e)),
+            );
+        }
+    };
+
+    let summary = match apply_pack(&mut config, pack, query.overwrite) {
+        Ok(s) => s,
+        Err(e) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(ApiResponse::<ImportSummary>::error(e)),
+            );
+        }
+    };
+
+    let errors = config.validate();
+    if !errors.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ApiResponse::<ImportSummary>::validation_errors(errors)),
+        );
+    }
+
+    let toml_content = match config.to_toml() {
+        Ok(c) => c,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ApiResponse::<ImportSummary>::error(format!(
+                    "序列化 TOML 失败: {}",
+                    e
+                ))),
+            );
+        }
+    };
+    if let Err(e) = tokio::fs::write(state.config_path(), &toml_content).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::<ImportSummary>::error(format!(
+                "写入配置失败: {}",
+                e
+            ))),
+        );
+    }
+
+    let etag = compute_etag(&toml_content);
+    state
+        .update_meta(|m| {
+            m.etag = etag;
+            m.has_draft = true;
+            m.last_saved_at = Some(Utc::now());
+        })
+        .await;
+
+    tracing::info!(
+        template = %summary.template_id,
+        imported = ?summary.imported,
+        skipped = ?summary.skipped,
+        "规则包已导入"
+    );
+
+    (StatusCode::OK, Json(ApiResponse::success(summary)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CONFIG: &str = r#"
+config_version = 2
+
+[[bots]]
+app_id = "bot"
+base_url = "http://localhost:2531"
+token = "t"
+
+[[ai_profiles]]
+id = "helper"
+model = "gpt-4o"
+api_key = "sk-secret"
+api_key_env = "OPENAI_API_KEY"
+system_prompt_file = "prompts/helper.md"
+tool_ids = ["weather"]
+
+[[tools]]
+id = "weather"
+program = "weather.sh"
+
+[[tools]]
+id = "unused"
+program = "noop.sh"
+
+[[rule_templates]]
+id = "ask"
+name = "问答"
+
+[rule_templates.match]
+contains = "?"
+
+[rule_templates.action]
+ai_profile = "helper"
+"#;
+
+    fn setup() -> (TempDir, std::path::PathBuf, AppConfigV2) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("prompts")).unwrap();
+        std::fs::write(dir.path().join("prompts/helper.md"), "你是一个助手").unwrap();
+        let config_path = dir.path().join("bot-app.v2.toml");
+        std::fs::write(&config_path, CONFIG).unwrap();
+        let config = AppConfigV2::parse(CONFIG).unwrap();
+        (dir, config_path, config)
+    }
+
+    #[test]
+    fn test_build_pack_inlines_prompt_and_strips_secrets() {
+        let (_dir, config_path, config) = setup();
+        let pack = build_pack(&config, "ask", &config_path).unwrap();
+
+        let profile = pack.ai_profile.unwrap();
+        assert_eq!(profile.system_prompt.as_deref(), Some("你是一个助手"));
+        assert_eq!(profile.system_prompt_file, None);
+        assert_eq!(profile.api_key, None);
+        assert_eq!(profile.api_key_env.as_deref(), Some("OPENAI_API_KEY"));
+        let tools: Vec<_> = pack.tools.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(tools, vec!["weather"]);
+
+        assert!(build_pack(&config, "missing", &config_path).is_err());
+    }
+
+    #[test]
+    fn test_pack_yaml_roundtrip_into_fresh_config() {
+        let (_dir, config_path, config) = setup();
+        let yaml =
+            serde_yaml::to_string(&build_pack(&config, "ask", &config_path).unwrap()).unwrap();
+        let pack: RuleTemplatePack = serde_yaml::from_str(&yaml).unwrap();
+
+        let mut target = AppConfigV2::parse("config_version = 2").unwrap();
+        let summary = apply_pack(&mut target, pack, false).unwrap();
+        assert_eq!(
+            summary.imported,
+            vec!["template:ask", "ai_profile:helper", "tool:weather"]
+        );
+        assert_eq!(
+            target.rule_templates[0].r#match.contains.as_deref(),
+            Some("?")
+        );
+        assert_eq!(
+            target.ai_profiles[0].system_prompt.as_deref(),
+            Some("你是一个助手")
+        );
+    }
+
+    #[test]
+    fn test_apply_pack_conflicts() {
+        let (_dir, config_path, mut config) = setup();
+        let pack = build_pack(&config, "ask", &config_path).unwrap();
+
+        assert!(apply_pack(&mut config, pack.clone(), false)
+            .unwrap_err()
+            .contains("ask"));
+
+        let summary = apply_pack(&mut config, pack.clone(), true).unwrap();
+        assert!(summary.skipped.is_empty());
+        assert_eq!(config.rule_templates.len(), 1);
+        assert_eq!(config.tools.len(), 2);
+
+        // 仅模板被覆盖时，已存在的 Profile 与工具保留本地版本
+        config.rule_templates.clear();
+        let summary = apply_pack(&mut config, pack.clone(), false).unwrap();
+        assert_eq!(summary.skipped, vec!["ai_profile:helper", "tool:weather"]);
+
+        let mut unsupported = pack;
+        unsupported.pack_version = 99;
+        assert!(apply_pack(&mut config, unsupported, true).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_writes_draft() {
+        let (dir, config_path, config) = setup();
+        let yaml =
+            serde_yaml::to_string(&build_pack(&config, "ask", &config_path).unwrap()).unwrap();
+
+        let target_path = dir.path().join("other.toml");
+        std::fs::write(
+            &target_path,
+            "config_version = 2\n\n[[bots]]\napp_id = \"bot\"\nbase_url = \"http://localhost\"\ntoken = \"t\"\n",
+        )
+        .unwrap();
+        let state = ApiState::new(
+            target_path.clone(),
+            dir.path().join("prompts"),
+            dir.path().join("backups"),
+        );
+
+        let response = import_rule_template(
+            State(state.clone()),
+            Query(ImportQuery::default()),
+            yaml.clone(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let saved = AppConfigV2::load_from_file(&target_path).unwrap();
+        assert_eq!(saved.rule_templates[0].id, "ask");
+        assert!(state.get_meta().await.has_draft);
+
+        let response = import_rule_template(State(state), Query(ImportQuery::default()), yaml)
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,86 @@
+//! 安全模式相关 API 处理函数
+
+use super::state::ApiState;
+use crate::storage::{SafeModeState, SafeModeStore};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+        }
+    }
+}
+
+async fn store(state: &ApiState) -> Result<SafeModeStore, String> {
+    state.data_dir().await.map(SafeModeStore::new)
+}
+
+/// GET /api/safe-mode - 处于安全模式的机器人
+pub async fn get_safe_mode(State(state): State<ApiState>) -> impl IntoResponse {
+    let result = match store(&state).await {
+        Ok(store) => store.load().await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(safe_mode) => (StatusCode::OK, Json(ApiResponse::success(safe_mode))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::<SafeModeState>::error(e)),
+        ),
+    }
+}
+
+/// POST /api/safe-mode/{app_id}/resume - 解除安全模式，dispatcher 在下一次定时检查时恢复 AI 与命令动作
+pub async fn resume_safe_mode(
+    State(state): State<ApiState>,
+    Path(app_id): Path<String>,
+) -> impl IntoResponse {
+    let result = match store(&state).await {
+        Ok(store) => store.resume(&app_id).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(true) => {
+            tracing::info!(%app_id, "已通过接口解除安全模式");
+            (StatusCode::OK, Json(ApiResponse::success(app_id)))
+        }
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ApiResponse::error(format!(
+                "机器人 {} 未处于安全模式",
+                app_id
+            ))),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ApiResponse::error(e)),
+        ),
+    }
+}
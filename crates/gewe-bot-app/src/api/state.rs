@@ -76,13 +76,25 @@ impl ApiState {
         &self.inner.config_path
     }
 
+    /// 读取并解析当前配置文件
+    pub async fn current_config(&self) -> Result<crate::config::AppConfigV2, String> {
+        let content = tokio::fs::read_to_string(self.config_path())
+            .await
+            .map_err(|e| format!("读取配置失败: {}", e))?;
+        crate::config::AppConfigV2::parse(&content).map_err(|e| format!("解析配置失败: {}", e))
+    }
+
+    /// 读取当前配置中的 data_dir（与 dispatcher 共用的业务数据目录）
+    pub async fn data_dir(&self) -> Result<PathBuf, String> {
+        Ok(PathBuf::from(self.current_config().await?.storage.data_dir))
+    }
+
     /// 获取 prompts 目录路径
     pub fn prompts_dir(&self) -> &PathBuf {
         &self.inner.prompts_dir
     }
 
     /// 获取备份目录路径
-    #[allow(dead_code)]
     pub fn backup_dir(&self) -> &PathBuf {
         &self.inner.backup_dir
     }
@@ -140,7 +152,7 @@ impl ApiState {
         }
 
         // 按版本号降序排列
-        backups.sort_by(|a, b| b.version.cmp(&a.version));
+        backups.sort_by_key(|b| std::cmp::Reverse(b.version));
 
         self.update_meta(|m| {
             m.available_backups = backups;
@@ -0,0 +1,386 @@
+//! 工具注册表安装 API 处理函数
+//!
+//! 注册表文件（TOML）描述一组外部工具：名称、命令、参数 Schema、文档与所需环境变量，
+//! 安装后写入配置的 `[[tools]]`，无需手工编辑工具配置块。
+
+use super::state::{compute_etag, ApiState};
+use crate::config::{AppConfigV2, ToolConfigV2};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+/// 当前注册表格式版本
+pub const REGISTRY_VERSION: u8 = 1;
+
+/// 通用 API 响应
+#[derive(Serialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<Vec<String>>,
+}
+
+impl<T: Serialize> ApiResponse<T> {
+    fn success(data: T) -> Self {
+        Self {
+            success: true,
+            data: Some(data),
+            error: None,
+            errors: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some(msg.into()),
+            errors: None,
+        }
+    }
+
+    fn validation_errors(errors: Vec<String>) -> Self {
+        Self {
+            success: false,
+            data: None,
+            error: Some("配置校验失败".to_string()),
+            errors: Some(errors),
+        }
+    }
+}
+
+/// 工具注册表文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolRegistry {
+    pub registry_version: u8,
+    #[serde(default)]
+    pub tools: Vec<RegistryTool>,
+}
+
+/// 注册表中的单个工具
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryTool {
+    /// 工具名称，安装后作为工具 ID
+    pub name: String,
+    /// 可执行程序
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// 参数 JSON Schema
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
+    /// 使用说明（Markdown 或链接）
+    #[serde(default)]
+    pub docs: Option<String>,
+    /// 运行所需的环境变量
+    #[serde(default)]
+    pub required_env: Vec<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub max_output: Option<usize>,
+}
+
+impl ToolRegistry {
+    /// 解析并校验注册表文件
+    pub fn parse(body: &str) -> Result<Self, String> {
+        let registry: Self =
+            toml::from_str(body).map_err(|e| format!("解析工具注册表失败: {}", e))?;
+        if registry.registry_version != REGISTRY_VERSION {
+            return Err(format!(
+                "不支持的注册表版本: {}（当前支持 {}）",
+                registry.registry_version, REGISTRY_VERSION
+            ));
+        }
+        if registry.tools.is_empty() {
+            return Err("注册表中没有工具".to_string());
+        }
+        for (i, tool) in registry.tools.iter().enumerate() {
+            if tool.name.trim().is_empty() || tool.command.trim().is_empty() {
+                return Err(format!("tools[{}]: name 与 command 不能为空", i));
+            }
+            if tool.schema.as_ref().is_some_and(|s| !s.is_object()) {
+                return Err(format!("tools[{}]: schema 必须是对象", i));
+            }
+        }
+        Ok(registry)
+    }
+}
+
+impl RegistryTool {
+    fn into_tool_config(self, source: Option<&str>) -> ToolConfigV2 {
+        ToolConfigV2 {
+            id: self.name,
+            kind: Some("command".to_string()),
+            program: self.command,
+            args: self.args,
+            timeout_secs: self.timeout_secs,
+            max_output: self.max_output,
+            description: self.description,
+            parameters: self.schema,
+            docs: self.docs,
+            required_env: self.required_env,
+            source: source.map(str::to_string),
+            ..Default::default()
+        }
+    }
+}
+
+/// 安装参数
+#[derive(Debug, Default, Deserialize)]
+pub struct InstallQuery {
+    /// 注册表来源（URL 或路径），记录到工具配置中
+    #[serde(default)]
+    pub source: Option<String>,
+    /// 覆盖同名工具
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// 安装结果
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct InstallSummary {
+    pub installed: Vec<String>,
+    /// 已存在而未覆盖的工具
+    pub skipped: Vec<String>,
+    /// 当前进程中缺失的环境变量
+    pub missing_env: Vec<String>,
+}
+
+/// 将注册表中的工具合并进配置
+pub fn install_tools(
+    config: &mut AppConfigV2,
+    registry: ToolRegistry,
+    source: Option<&str>,
+    overwrite: bool,
+) -> InstallSummary {
+    let mut summary = InstallSummary::default();
+    for tool in registry.tools {
+        let tool = tool.into_tool_config(source);
+        match config.tools.iter().position(|t| t.id == tool.id) {
+            Some(_) if !overwrite => {
+                summary.skipped.push(tool.id);
+                continue;
+            }
+            Some(pos) => config.tools[pos] = tool.clone(),
+            None => config.tools.push(tool.clone()),
+        }
+        for key in tool.missing_env() {
+            if !summary.missing_env.iter().any(|k| k == key) {
+                summary.missing_env.push(key.to_string());
+            }
+        }
+        summary.installed.push(tool.id);
+    }
+    summary
+}
+
+/// 安装注册表并保存为草稿，供 API 与 Tools 页面共用
+pub async fn install_and_save(
+    state: &ApiState,
+    body: &str,
+    source: Option<&str>,
+    overwrite: bool,
+) -> Result<InstallSummary, (StatusCode, String, Vec<String>)> {
+    let registry =
+        ToolRegistry::parse(body).map_err(|e| (StatusCode::BAD_REQUEST, e, Vec::new()))?;
+
+    let content = tokio::fs::read_to_string(state.config_path())
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("读取配置失败: {}", e),
+                Vec::new(),
+            )
+        })?;
+    let mut config = AppConfigV2::parse(&content).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("解析配置失败: {}", e),
+            Vec::new(),
+        )
+    })?;
+
+    let summary = install_tools(&mut config, registry, source, overwrite);
+
+    let errors = config.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "配置校验失败".to_string(), errors));
+    }
+
+    let toml_content = config.to_toml().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("序列化 TOML 失败: {}", e),
+            Vec::new(),
+        )
+    })?;
+    tokio::fs::write(state.config_path(), &toml_content)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("写入配置失败: {}", e),
+                Vec::new(),
+            )
+        })?;
+
+    let etag = compute_etag(&toml_content);
+    state
+        .update_meta(|m| {
+            m.etag = etag;
+            m.has_draft = true;
+            m.last_saved_at = Some(Utc::now());
+        })
+        .await;
+
+    tracing::info!(
+        source = source.unwrap_or("-"),
+        installed = ?summary.installed,
+        skipped = ?summary.skipped,
+        "工具注册表已安装"
+    );
+    Ok(summary)
+}
+
+/// POST /api/tools/install - 从注册表文件安装工具（保存为草稿）
+pub async fn install_registry(
+    State(state): State<ApiState>,
+    Query(query): Query<InstallQuery>,
+    body: String,
+) -> impl IntoResponse {
+    match install_and_save(&state, &body, query.source.as_deref(), query.overwrite).await {
+        Ok(summary) => (StatusCode::OK, Json(ApiResponse::success(summary))),
+        Err((status, _, errors)) if !errors.is_empty() => (
+            status,
+            Json(ApiResponse::<InstallSummary>::validation_errors(errors)),
+        ),
+        Err((status, e, _)) => (status, Json(ApiResponse::<InstallSummary>::error(e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const REGISTRY: &str = r#"
+registry_version = 1
+
+[[tools]]
+name = "weather"
+command = "weather-cli"
+args = ["--json"]
+description = "查询天气"
+docs = "https://example.com/weather"
+required_env = ["GEWE_TEST_REGISTRY_MISSING_KEY", "PATH"]
+timeout_secs = 10
+
+[tools.schema]
+type = "object"
+required = ["city"]
+
+[tools.schema.properties.city]
+type = "string"
+
+[[tools]]
+name = "echo"
+command = "echo"
+"#;
+
+    #[test]
+    fn test_parse_registry() {
+        let registry = ToolRegistry::parse(REGISTRY).unwrap();
+        assert_eq!(registry.tools.len(), 2);
+        assert_eq!(
+            registry.tools[0].schema.as_ref().unwrap()["required"][0],
+            "city"
+        );
+
+        assert!(ToolRegistry::parse(
+            "registry_version = 2\n[[tools]]\nname = \"a\"\ncommand = \"b\""
+        )
+        .unwrap_err()
+        .contains("版本"));
+        assert!(ToolRegistry::parse("registry_version = 1").is_err());
+        assert!(ToolRegistry::parse(
+            "registry_version = 1\n[[tools]]\nname = \"a\"\ncommand = \"b\"\nschema = \"x\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_install_tools() {
+        let mut config = AppConfigV2::parse("config_version = 2").unwrap();
+        config.tools.push(ToolConfigV2 {
+            id: "echo".to_string(),
+            program: "/bin/echo".to_string(),
+            ..Default::default()
+        });
+
+        let registry = ToolRegistry::parse(REGISTRY).unwrap();
+        let summary = install_tools(&mut config, registry.clone(), Some("./tools.toml"), false);
+        assert_eq!(summary.installed, vec!["weather"]);
+        assert_eq!(summary.skipped, vec!["echo"]);
+        assert_eq!(summary.missing_env, vec!["GEWE_TEST_REGISTRY_MISSING_KEY"]);
+
+        let weather = config.tools.iter().find(|t| t.id == "weather").unwrap();
+        assert_eq!(weather.program, "weather-cli");
+        assert_eq!(weather.args, vec!["--json"]);
+        assert_eq!(weather.source.as_deref(), Some("./tools.toml"));
+        assert_eq!(weather.parameters.as_ref().unwrap()["type"], "object");
+        assert_eq!(config.tools[0].program, "/bin/echo");
+
+        let summary = install_tools(&mut config, registry, None, true);
+        assert_eq!(summary.installed, vec!["weather", "echo"]);
+        assert_eq!(config.tools.len(), 2);
+        assert_eq!(config.tools[0].program, "echo");
+    }
+
+    #[tokio::test]
+    async fn test_install_registry_saves_draft() {
+        let dir = TempDir::new().unwrap();
+        let config_path = dir.path().join("bot-app.v2.toml");
+        std::fs::write(&config_path, "config_version = 2\n").unwrap();
+        let state = ApiState::new(
+            config_path.clone(),
+            dir.path().join("prompts"),
+            dir.path().join("backups"),
+        );
+
+        let response = install_registry(
+            State(state.clone()),
+            Query(InstallQuery::default()),
+            REGISTRY.to_string(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let saved = AppConfigV2::load_from_file(&config_path).unwrap();
+        assert_eq!(saved.tools.len(), 2);
+        assert_eq!(saved.tools[0].required_env.len(), 2);
+        assert!(state.get_meta().await.has_draft);
+
+        let response = install_registry(
+            State(state),
+            Query(InstallQuery::default()),
+            "not toml".to_string(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
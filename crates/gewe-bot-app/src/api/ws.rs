@@ -0,0 +1,159 @@
+//! `/api/ws` WebSocket 推送通道
+//!
+//! 客户端连接后发送订阅消息，之后按订阅接收收到的消息（`event`）与发送结果（`send_status`）：
+//!
+//! ```json
+//! {"type": "subscribe", "app_id": "wx_xxx", "chat": "123@chatroom"}
+//! {"type": "unsubscribe", "app_id": "wx_xxx", "chat": "123@chatroom"}
+//! ```
+//!
+//! `app_id`、`chat` 均可省略，省略表示不限；服务端以 `subscribed` / `unsubscribed` 确认，
+//! 无法解析的消息返回 `error`，客户端落后过多时推送 `lagged` 并附丢弃的条数
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::push::{PushHub, PushMessage};
+use axum::{
+    body::Bytes,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast;
+
+/// 客户端消息的最大长度，超过时断开连接
+const MAX_CLIENT_MESSAGE: usize = 64 * 1024;
+/// 服务端主动 ping 的间隔，避免代理断开空闲连接
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 客户端发来的消息
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        #[serde(default)]
+        app_id: Option<String>,
+        #[serde(default)]
+        chat: Option<String>,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        app_id: Option<String>,
+        #[serde(default)]
+        chat: Option<String>,
+    },
+}
+
+/// 一个客户端的订阅，每项为 (机器人, 会话)，`None` 表示不限
+#[derive(Debug, Default)]
+struct Subscriptions {
+    entries: HashSet<(Option<String>, Option<String>)>,
+}
+
+impl Subscriptions {
+    fn matches(&self, message: &PushMessage) -> bool {
+        self.entries.iter().any(|(app_id, chat)| {
+            app_id.as_deref().is_none_or(|id| id == message.app_id())
+                && chat.as_deref().is_none_or(|c| c == message.chat())
+        })
+    }
+
+    /// 处理一条客户端消息，返回给客户端的确认
+    fn apply(&mut self, text: &str) -> serde_json::Value {
+        match serde_json::from_str::<ClientMessage>(text) {
+            Ok(ClientMessage::Subscribe { app_id, chat }) => {
+                self.entries.insert((app_id.clone(), chat.clone()));
+                json!({ "type": "subscribed", "app_id": app_id, "chat": chat })
+            }
+            Ok(ClientMessage::Unsubscribe { app_id, chat }) => {
+                self.entries.remove(&(app_id.clone(), chat.clone()));
+                json!({ "type": "unsubscribed", "app_id": app_id, "chat": chat })
+            }
+            Err(e) => json!({ "type": "error", "error": format!("无法解析的消息: {}", e) }),
+        }
+    }
+}
+
+/// GET /api/ws - WebSocket 推送通道
+pub async fn ws_push(ws: WebSocketUpgrade) -> Response {
+    // 在握手之前订阅，连接建立后的消息不会遗漏
+    let rx = PushHub::global().subscribe();
+    ws.max_message_size(MAX_CLIENT_MESSAGE)
+        .on_upgrade(move |socket| serve_client(rx, socket))
+}
+
+/// 客户端的 ping 与 close 由 axum 自动应答
+async fn serve_client(mut rx: broadcast::Receiver<PushMessage>, mut socket: WebSocket) {
+    let mut subscriptions = Subscriptions::default();
+    let mut ping =
+        tokio::time::interval_at(tokio::time::Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    loop {
+        let message = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => Message::Text(subscriptions.apply(&text).to_string().into()),
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => break,
+            },
+            message = rx.recv() => match message {
+                Ok(message) if subscriptions.matches(&message) => {
+                    match serde_json::to_string(&message) {
+                        Ok(text) => Message::Text(text.into()),
+                        Err(_) => continue,
+                    }
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "ws 推送客户端处理过慢，已丢弃部分消息");
+                    Message::Text(json!({ "type": "lagged", "skipped": skipped }).to_string().into())
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = ping.tick() => Message::Ping(Bytes::new()),
+        };
+        if socket.send(message).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::push::SendStatus;
+
+    fn send_status(app_id: &str, to: &str) -> PushMessage {
+        PushMessage::SendStatus {
+            at: chrono::Utc::now(),
+            app_id: app_id.to_string(),
+            to: to.to_string(),
+            kind: "text".to_string(),
+            status: SendStatus::Sent,
+            new_msg_id: Some(1),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_subscriptions_filter_by_bot_and_chat() {
+        let mut subscriptions = Subscriptions::default();
+        assert!(!subscriptions.matches(&send_status("wx1", "a")));
+
+        let ack = subscriptions.apply(r#"{"type":"subscribe","app_id":"wx1","chat":"a"}"#);
+        assert_eq!(ack["type"], "subscribed");
+        assert_eq!(ack["chat"], "a");
+        subscriptions.apply(r#"{"type":"subscribe","app_id":"wx2"}"#);
+        assert!(subscriptions.matches(&send_status("wx1", "a")));
+        assert!(!subscriptions.matches(&send_status("wx1", "b")));
+        assert!(subscriptions.matches(&send_status("wx2", "b")));
+
+        let ack = subscriptions.apply(r#"{"type":"unsubscribe","app_id":"wx2"}"#);
+        assert_eq!(ack["type"], "unsubscribed");
+        assert!(!subscriptions.matches(&send_status("wx2", "b")));
+
+        subscriptions.apply(r#"{"type":"subscribe"}"#);
+        assert!(subscriptions.matches(&send_status("wx3", "c")));
+        assert_eq!(subscriptions.apply("ping")["type"], "error");
+    }
+}
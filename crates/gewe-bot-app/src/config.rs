@@ -1,10 +1,21 @@
+use crate::schedule::{
+    ActiveWindow, CronExpr, JobSchedule, Rota, Schedule, ScheduleEntry, ScheduleTz,
+};
+use crate::tools::{normalize_language_code, SUPPORTED_LANGUAGES};
 use anyhow::{Context, Result};
+pub use gewe_webhook::normalize::ChatKind;
+use gewe_webhook::normalize::MessageKind;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::path::PathBuf;
 
+const DEFAULT_CONFIG_PATH: &str = "config/bot-app.toml";
+/// 纯环境变量启动时机器人变量的前缀，如 `GEWE_BOT_0_APPID`
+const ENV_BOT_PREFIX: &str = "GEWE_BOT_";
+const DEFAULT_GEWE_BASE_URL: &str = "http://api.geweapi.com";
+
 fn default_listen_addr() -> String {
     "0.0.0.0:3000".to_string()
 }
@@ -21,6 +32,10 @@ fn default_image_url_prefix() -> String {
     "/images".to_string()
 }
 
+fn default_data_dir() -> String {
+    "data".to_string()
+}
+
 fn default_max_concurrency() -> usize {
     8
 }
@@ -32,10 +47,168 @@ pub struct BotConfig {
     pub base_url: String,
     #[serde(default)]
     pub webhook_secret: Option<String>,
+    /// 多机器人协同优先级，见 [`BotConfigV2::priority`]
+    #[serde(default)]
+    pub priority: Option<i32>,
+    /// 热备切换配置
+    #[serde(default)]
+    pub failover: Option<FailoverConfig>,
+    /// 运营摘要（日报/周报）
+    #[serde(default)]
+    pub digest: Option<DigestConfig>,
+    /// 影子模式：照常匹配规则、调用 AI 与工具，但不实际发送，只记录到运营事件日志
+    #[serde(default)]
+    pub shadow: bool,
     #[serde(default)]
     pub rules: Vec<RuleConfig>,
 }
 
+/// 热备切换：主机器人健康检查离线时，由备用机器人按主机器人的规则接管会话并代发消息
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct FailoverConfig {
+    /// 备用机器人 app_id，需同样配置在 bots 中并已加入相关群
+    pub standby: String,
+    /// 仅切换这些会话（群聊 ID 或 wxid），留空表示全部
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chats: Vec<String>,
+    /// 切换与恢复时接收告警的 wxid，未配置时发给全局管理员
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alert_to: Vec<String>,
+    /// 连续多少次健康检查失败判定离线，默认 2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail_threshold: Option<u32>,
+}
+
+impl FailoverConfig {
+    /// 会话是否在切换范围内
+    pub fn covers(&self, chat: &str) -> bool {
+        self.chats.is_empty() || self.chats.iter().any(|c| c == chat)
+    }
+}
+
+/// 运营摘要：按日或按周汇总消息量、规则命中、AI 花费、错误与离线事件，
+/// 发到运营群（文本）和/或通过 SMTP 发送 HTML 邮件
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct DigestConfig {
+    /// daily（默认）或 weekly
+    #[serde(default)]
+    pub period: DigestPeriod,
+    /// 发送时间 HH:MM（本地时区），默认 09:00
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<String>,
+    /// 周报发送日，如 mon、fri，默认周一
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekday: Option<String>,
+    /// cron 表达式（分 时 日 月 周），设置后代替 at/weekday 决定发送时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// IANA 时区，如 Asia/Shanghai，默认本机时区
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// 接收文本摘要的运营群（群聊 ID 或 wxid）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chats: Vec<String>,
+    /// 邮件发送配置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<DigestEmailConfig>,
+    /// 模型单价（每百万 token），键为模型名，用于估算 AI 花费
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prices: BTreeMap<String, ModelPrice>,
+    /// 错过发送时间后的补跑策略，默认补跑一次
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catch_up: Option<CatchUpPolicy>,
+}
+
+impl DigestConfig {
+    /// 发送时间，未配置时为 09:00
+    pub fn send_time(&self) -> Option<chrono::NaiveTime> {
+        match self.at.as_deref().map(str::trim) {
+            Some(at) if !at.is_empty() => chrono::NaiveTime::parse_from_str(at, "%H:%M").ok(),
+            _ => chrono::NaiveTime::from_hms_opt(9, 0, 0),
+        }
+    }
+
+    /// 周报发送日，未配置时为周一
+    pub fn send_weekday(&self) -> Option<chrono::Weekday> {
+        match self.weekday.as_deref().map(str::trim) {
+            Some(day) if !day.is_empty() => day.parse().ok(),
+            _ => Some(chrono::Weekday::Mon),
+        }
+    }
+
+    /// 执行计划：优先使用 cron，否则按 period 在 at（周报为 weekday 的 at）发送
+    pub fn schedule(&self) -> std::result::Result<JobSchedule, String> {
+        let tz =
+            ScheduleTz::parse(self.timezone.as_deref()).map_err(|e| format!("timezone {}", e))?;
+        if let Some(cron) = self.cron.as_deref().filter(|c| !c.trim().is_empty()) {
+            let cron = CronExpr::parse(cron).map_err(|e| format!("cron 无效: {}", e))?;
+            return Ok(JobSchedule::new(Schedule::Cron(cron), tz));
+        }
+        let at = self.send_time().ok_or("at 格式应为 HH:MM")?;
+        let schedule = match self.period {
+            DigestPeriod::Daily => Schedule::Daily(at),
+            DigestPeriod::Weekly => {
+                let weekday = self.send_weekday().ok_or("weekday 无法识别")?;
+                Schedule::Cron(CronExpr::weekly(weekday, at))
+            }
+        };
+        Ok(JobSchedule::new(schedule, tz))
+    }
+}
+
+/// 运营摘要周期
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestPeriod {
+    #[default]
+    Daily,
+    Weekly,
+}
+
+impl DigestPeriod {
+    /// 统计窗口长度
+    pub fn window(self) -> chrono::Duration {
+        match self {
+            Self::Daily => chrono::Duration::days(1),
+            Self::Weekly => chrono::Duration::days(7),
+        }
+    }
+}
+
+/// 模型单价，单位为每百万 token 的金额（币种自定）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct ModelPrice {
+    #[serde(default)]
+    pub input: f64,
+    #[serde(default)]
+    pub output: f64,
+}
+
+/// 运营摘要的 SMTP 邮件配置
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct DigestEmailConfig {
+    pub smtp_host: String,
+    /// 默认 465（tls）或 587（STARTTLS）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_port: Option<u16>,
+    /// 是否使用隐式 TLS（SMTPS），默认 true；为 false 时要求服务器支持 STARTTLS，不允许明文发送
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// 密码，直接配置（优先级高于 password_env）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// 从环境变量读取密码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// 邮件标题前缀，默认“机器人运营摘要”
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct AppConfig {
@@ -50,7 +223,407 @@ pub struct AppConfig {
     /// 全局最大并发（处理 webhook 事件），默认 8
     #[serde(default = "default_max_concurrency")]
     pub max_concurrency: usize,
+    /// 业务数据目录（待办清单等），默认 data
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    /// 全局管理员 wxid：安全模式、人设、会话设置、`/undo`、AI 预算与告警通知未单独配置管理员时沿用，
+    /// 违规检测也对其免检
+    #[serde(default)]
+    pub admins: Vec<String>,
     pub bots: Vec<BotConfig>,
+    /// 倒计时事件，由定时任务每天播报
+    #[serde(default)]
+    pub countdowns: Vec<CountdownConfig>,
+    /// 外置命令的进程池
+    #[serde(default)]
+    pub command_pool: CommandPoolConfig,
+    /// 后台执行耗时 AI 工具的任务队列
+    #[serde(default)]
+    pub ai_tasks: AiTaskQueueConfig,
+    /// 群名别名（群 ID → 显示名），优先于 getChatroomInfo 查询到的群名
+    #[serde(default)]
+    pub chatroom_aliases: BTreeMap<String, String>,
+    /// 会话内 `/persona` 命令可切换的 AI 人设
+    #[serde(default)]
+    pub persona: PersonaConfig,
+    /// AI 对话记忆的存储后端
+    #[serde(default)]
+    pub memory: MemoryStoreConfig,
+    /// 异常流量时自动进入安全模式
+    #[serde(default)]
+    pub safe_mode: SafeModeConfig,
+    /// 会话设置（语言、时区、语气）的管理员
+    #[serde(default)]
+    pub chat_settings: ChatSettingsConfig,
+    /// 回调中收到机器人自己发送的消息时的处理方式
+    #[serde(default)]
+    pub own_message_echo: OwnEchoPolicy,
+    /// 群聊违规内容检测
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    /// `/undo` 撤回机器人最近一条消息
+    #[serde(default)]
+    pub undo: UndoConfig,
+    /// `save` 动作使用的媒体存储
+    #[serde(default)]
+    pub media: MediaConfig,
+    /// 会话消息记录，供 `/api/history` 查询
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// 机器人在线看护：定时检查在线状态，离线时断线重连并通知管理员
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+}
+
+/// 外置命令进程池：限制同时运行的进程数，系统负载或内存越过水位线时拒绝新命令
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct CommandPoolConfig {
+    /// 同时运行的外置命令进程上限，默认 4
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_processes: Option<usize>,
+    /// 同一规则同时运行的进程上限，默认 1，其余排队
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_rule: Option<usize>,
+    /// 同一规则排队等待的命令上限，默认 8，超出时拒绝
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_queue: Option<usize>,
+    /// 1 分钟平均负载与 CPU 核数之比的上限，如 1.5，超过时拒绝新命令（仅 Linux）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_load: Option<f64>,
+    /// 可用内存下限（MiB），低于时拒绝新命令（仅 Linux）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_free_memory_mb: Option<u64>,
+    /// 拒绝时的回复，默认“当前请求较多，请稍后再试”
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busy_reply: Option<String>,
+}
+
+impl CommandPoolConfig {
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.max_processes == Some(0) {
+            errors.push("max_processes 必须大于 0".to_string());
+        }
+        if self.per_rule == Some(0) {
+            errors.push("per_rule 必须大于 0".to_string());
+        }
+        if let Some(max_load) = self.max_load {
+            if max_load.is_nan() || max_load <= 0.0 {
+                errors.push(format!("max_load 必须大于 0，当前为 {}", max_load));
+            }
+        }
+        errors
+    }
+}
+
+/// AI 任务队列：`async = true` 的 AI 工具（如图像、视频生成）落盘排队，由独立的工作协程执行，
+/// 完成后把结果发回发起的会话；重启后继续执行未完成的任务
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct AiTaskQueueConfig {
+    /// 同时执行的任务上限，默认 2，不占用事件处理的并发额度
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    /// 排队与执行中的任务上限，默认 32，超出时拒绝新任务
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pending: Option<usize>,
+}
+
+impl AiTaskQueueConfig {
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.concurrency == Some(0) {
+            errors.push("concurrency 必须大于 0".to_string());
+        }
+        if self.max_pending == Some(0) {
+            errors.push("max_pending 必须大于 0".to_string());
+        }
+        errors
+    }
+}
+
+/// 安全模式：机器人一分钟内发送的消息或命中的规则过多（如提示词注入导致的循环回复）时，
+/// 暂停该机器人的 AI 与命令类动作并通知管理员，直到管理员发送 `/safe-mode resume` 或调用恢复接口
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SafeModeConfig {
+    /// 每分钟发送消息的上限，未配置时不检查
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_sends_per_minute: Option<u32>,
+    /// 每分钟命中规则的上限，未配置时不检查
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rule_hits_per_minute: Option<u32>,
+    /// 接收通知并可恢复的管理员 wxid，未配置时沿用全局管理员
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<String>,
+}
+
+/// 会话设置：各群聊/私聊的语言、时区与语气，通过 `/chat-settings` 命令或管理页面修改，
+/// 保存在 `{data_dir}/chat_settings/`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ChatSettingsConfig {
+    /// 可修改任意会话设置的管理员 wxid，未配置时沿用全局管理员；群主与群管理员可修改本群，
+    /// 私聊中用户可修改自己的会话
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<String>,
+}
+
+/// `/undo`：管理员在会话中发送后，机器人撤回自己在该会话最近发出的一条消息（需在 2 分钟撤回时限内）
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct UndoConfig {
+    /// 可在任意会话撤回的管理员 wxid，未配置时沿用全局管理员；群主与群管理员可在本群撤回
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<String>,
+}
+
+/// 群聊违规内容检测：按群配置违禁词与正则，同一成员多次违规时逐级警告、撤回消息、移出群聊，
+/// 处理结果写入 `{data_dir}/moderation/audit.jsonl`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ModerationConfig {
+    /// 检测规则，按顺序取第一条命中的
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ModerationRule>,
+    /// 累计违规次数的时间窗口（秒），默认 86400
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_secs: Option<u64>,
+    /// 不做检测的成员 wxid；全局管理员、群主与群管理员也不做检测
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exempt: Vec<String>,
+}
+
+/// 一组违禁词与正则，及违规后的处理
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ModerationRule {
+    /// 生效的群聊 ID，为空时对所有群聊生效
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chatrooms: Vec<String>,
+    /// 违禁词，不区分大小写
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<String>,
+    /// 违禁正则
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    /// 第 n 次违规执行第 n 项，超出时执行最后一项；默认 warn → revoke → remove
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub escalation: Vec<ModerationStep>,
+    /// 警告内容，支持 `{sender}`、`{word}`、`{count}` 占位符
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warn_text: Option<String>,
+}
+
+/// 违规后的处理，逐级加重
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationStep {
+    /// 在群里 @ 发送者警告
+    Warn,
+    /// 撤回违规消息并警告（需机器人为群主或管理员）
+    Revoke,
+    /// 撤回违规消息并移出群聊（需机器人为群主或管理员）
+    Remove,
+}
+
+impl ModerationStep {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Warn => "warn",
+            Self::Revoke => "revoke",
+            Self::Remove => "remove",
+        }
+    }
+}
+
+impl ModerationRule {
+    /// 第 `count` 次违规的处理
+    pub fn step(&self, count: u32) -> ModerationStep {
+        const DEFAULT: [ModerationStep; 3] = [
+            ModerationStep::Warn,
+            ModerationStep::Revoke,
+            ModerationStep::Remove,
+        ];
+        let steps = if self.escalation.is_empty() {
+            &DEFAULT[..]
+        } else {
+            &self.escalation[..]
+        };
+        let idx = (count.max(1) as usize - 1).min(steps.len() - 1);
+        steps[idx]
+    }
+}
+
+impl ModerationConfig {
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.window_secs == Some(0) {
+            errors.push("window_secs 必须大于 0".to_string());
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule.words.iter().all(|w| w.trim().is_empty()) && rule.patterns.is_empty() {
+                errors.push(format!("rules[{}]: words 与 patterns 不能同时为空", i));
+            }
+            for pattern in &rule.patterns {
+                if let Err(err) = regex::Regex::new(pattern) {
+                    errors.push(format!("rules[{}]: 正则无效 {}: {}", i, pattern, err));
+                }
+            }
+            for chatroom in &rule.chatrooms {
+                if !chatroom.ends_with("@chatroom") {
+                    errors.push(format!(
+                        "rules[{}]: 群 ID 应以 @chatroom 结尾: {}",
+                        i, chatroom
+                    ));
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// 自己发送的消息回显：发送后 GeWe 会把这条消息作为回调再推送一次，按 NewMsgId（或接收方与 CreateTime）
+/// 与最近的发送记录比对识别，识别出的回显都不再交给规则处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnEchoPolicy {
+    /// 直接丢弃
+    #[default]
+    Skip,
+    /// 丢弃并输出日志
+    Log,
+    /// 丢弃并写入运营事件日志（`own_message_echo`）
+    Event,
+}
+
+impl SafeModeConfig {
+    /// 是否配置了任一检查
+    pub fn enabled(&self) -> bool {
+        self.max_sends_per_minute.is_some() || self.max_rule_hits_per_minute.is_some()
+    }
+
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.max_sends_per_minute == Some(0) {
+            errors.push("max_sends_per_minute 必须大于 0".to_string());
+        }
+        if self.max_rule_hits_per_minute == Some(0) {
+            errors.push("max_rule_hits_per_minute 必须大于 0".to_string());
+        }
+        errors
+    }
+}
+
+/// AI 对话记忆存储后端
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryBackend {
+    /// 保存在进程内存中，重启后清空
+    #[default]
+    Memory,
+    /// 保存在 SQLite 文件中，重启后保留（需启用 `sqlite` feature）
+    Sqlite,
+}
+
+/// AI 对话记忆的存储位置，各 AI 配置通过 `memory` 开启记忆
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct MemoryStoreConfig {
+    #[serde(default)]
+    pub backend: MemoryBackend,
+    /// SQLite 文件路径，默认 `{data_dir}/memory/conversations.db`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// 人设切换：管理员在会话中发送 `/persona use <名称>`，该会话的 AI 回复改用指定人设，
+/// `/persona reset` 恢复规则自身的 AI 配置；绑定关系保存在 `{data_dir}/persona/` 下，重启后保留
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PersonaConfig {
+    /// 可使用 `/persona` 命令的管理员 wxid，未配置时沿用全局管理员；群主与群管理员可切换本群
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// 可切换的人设，键为名称
+    #[serde(default)]
+    pub profiles: BTreeMap<String, AiAction>,
+}
+
+/// 倒计时事件：每天播报“距离 xx 还有 N 天”，当天发送最终公告
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct CountdownConfig {
+    pub id: String,
+    /// 事件名称，如“新版本发布”
+    pub name: String,
+    /// 事件时间：`YYYY-MM-DD` 或 `YYYY-MM-DD HH:MM`（本地时区）
+    pub at: String,
+    /// 负责播报的机器人
+    pub app_id: String,
+    /// 播报的会话（群聊 ID 或 wxid）
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// 每日播报时间 HH:MM，默认 09:00
+    #[serde(default)]
+    pub post_at: Option<String>,
+    /// cron 表达式（分 时 日 月 周），设置后代替 post_at 决定播报时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    /// IANA 时区，如 Asia/Shanghai，默认本机时区；也用于判断“今天”
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// 每日播报模板，支持 {name} {days} 占位符
+    #[serde(default)]
+    pub message: Option<String>,
+    /// 当天公告模板，支持 {name} 占位符
+    #[serde(default)]
+    pub final_message: Option<String>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// 错过播报时间（如重启）后的补跑策略，默认补跑一次
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catch_up: Option<CatchUpPolicy>,
+}
+
+/// 定时任务错过执行时间后的补跑策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchUpPolicy {
+    /// 恢复后补跑一次
+    #[default]
+    Once,
+    /// 超过 10 分钟宽限期则跳过，等下一周期
+    Skip,
+}
+
+impl CountdownConfig {
+    /// 解析事件时间，仅日期时视为当天 00:00
+    pub fn event_time(&self) -> Option<chrono::NaiveDateTime> {
+        let at = self.at.trim();
+        chrono::NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M")
+            .ok()
+            .or_else(|| {
+                chrono::NaiveDate::parse_from_str(at, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+            })
+    }
+
+    /// 每日播报时间，未配置时为 09:00
+    pub fn post_time(&self) -> Option<chrono::NaiveTime> {
+        match self.post_at.as_deref().map(str::trim) {
+            Some(at) if !at.is_empty() => chrono::NaiveTime::parse_from_str(at, "%H:%M").ok(),
+            _ => chrono::NaiveTime::from_hms_opt(9, 0, 0),
+        }
+    }
+
+    /// 播报计划：优先使用 cron，否则每天 post_at 播报
+    pub fn schedule(&self) -> std::result::Result<JobSchedule, String> {
+        let tz =
+            ScheduleTz::parse(self.timezone.as_deref()).map_err(|e| format!("timezone {}", e))?;
+        if let Some(cron) = self.cron.as_deref().filter(|c| !c.trim().is_empty()) {
+            let cron = CronExpr::parse(cron).map_err(|e| format!("cron 无效: {}", e))?;
+            return Ok(JobSchedule::new(Schedule::Cron(cron), tz));
+        }
+        let at = self.post_time().ok_or("post_at 格式应为 HH:MM")?;
+        Ok(JobSchedule::new(Schedule::Daily(at), tz))
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
@@ -63,11 +636,40 @@ pub enum RuleKind {
     Emoji,
     Link,
     FileNotice,
+    Location,
+    RedPacket,
+    Transfer,
+    NameCard,
     ContactEvent,
+    MemberJoined,
+    MemberLeft,
     #[default]
     Any,
 }
 
+impl From<MessageKind> for RuleKind {
+    /// 规范化结果中的 `Other` 对应规则里的 `any`
+    fn from(kind: MessageKind) -> Self {
+        match kind {
+            MessageKind::Text => RuleKind::Text,
+            MessageKind::Image => RuleKind::Image,
+            MessageKind::Voice => RuleKind::Voice,
+            MessageKind::Video => RuleKind::Video,
+            MessageKind::Emoji => RuleKind::Emoji,
+            MessageKind::Link => RuleKind::Link,
+            MessageKind::FileNotice => RuleKind::FileNotice,
+            MessageKind::Location => RuleKind::Location,
+            MessageKind::RedPacket => RuleKind::RedPacket,
+            MessageKind::Transfer => RuleKind::Transfer,
+            MessageKind::NameCard => RuleKind::NameCard,
+            MessageKind::ContactEvent => RuleKind::ContactEvent,
+            MessageKind::MemberJoined => RuleKind::MemberJoined,
+            MessageKind::MemberLeft => RuleKind::MemberLeft,
+            MessageKind::Other => RuleKind::Any,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct MatchConfig {
     #[serde(default)]
@@ -76,1019 +678,4243 @@ pub struct MatchConfig {
     pub contains: Option<String>,
     #[serde(default)]
     pub regex: Option<String>,
-}
-
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
-pub struct FromConfig {
+    /// 消息类型过滤（MsgType），如 [3, 43] 仅匹配图片和视频。为空不限制。
     #[serde(default)]
-    pub nick: Option<String>,
+    pub msg_types: Vec<i64>,
+    /// appmsg 子类型过滤（XML 中的 <type>），如 [6] 文件、[5] 链接。为空不限制。
     #[serde(default)]
-    pub wxid: Option<String>,
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum ChatKind {
-    Private,
-    Group,
-}
-
-#[derive(Debug, Clone, Default, Deserialize)]
-pub struct SaveAction {
-    /// 保存目录
-    pub dir: String,
-    /// 文件名模板，可选。支持 {new_msg_id}/{app_id}/{from_wxid} 替换。
+    pub appmsg_types: Vec<i32>,
+    /// 文件扩展名过滤（不区分大小写，可带或不带点），如 ["pdf", "zip"]。
     #[serde(default)]
-    pub filename: Option<String>,
-}
-
-#[derive(Debug, Clone, Default, Deserialize)]
-pub struct CommandAction {
-    /// 程序名或可执行路径。内置命令使用预置名称（如 claude_changelog）。
-    pub program: String,
-    /// 传给外置命令的参数，可选。
+    pub file_exts: Vec<String>,
+    /// 媒体/文件最小字节数（含），无法获知大小的消息视为不匹配。
     #[serde(default)]
-    pub args: Vec<String>,
-    /// 超时秒数，可选，默认内部设置。
+    pub min_size: Option<u64>,
+    /// 媒体/文件最大字节数（含），无法获知大小的消息视为不匹配。
     #[serde(default)]
-    pub timeout_secs: Option<u64>,
-    /// 最大输出长度（字节），超出会截断。
+    pub max_size: Option<u64>,
+    /// 表情 md5 过滤（不区分大小写），用于匹配指定的某个表情包。
     #[serde(default)]
-    pub max_output: Option<usize>,
-    /// 在执行命令前先回复的一段话（可选）。
+    pub emoji_md5: Vec<String>,
+    /// 是否为动图表情：true 仅匹配动图，false 仅匹配静态表情，未设置不限制。
     #[serde(default)]
-    pub pre_reply: Option<String>,
-    /// 命令执行完成后（成功）再回复的一段话（可选）。
+    pub emoji_animated: Option<bool>,
+    /// 链接域名过滤：从文本/appmsg 中提取 URL，任一 URL 的域名命中即可。
+    /// "example.com" 仅精确匹配，"*.example.com" 仅匹配子域名（与 HTTP 工具主机名单一致）。
     #[serde(default)]
-    pub post_reply: Option<String>,
-}
-
-#[derive(Debug, Clone, Default, Deserialize)]
-pub struct RuleAction {
+    pub url_domains: Vec<String>,
+    /// 地理围栏：仅匹配分享位置落在圆形区域内的位置消息。
     #[serde(default)]
-    pub reply_text: Option<String>,
+    pub geo_fence: Option<GeoFence>,
+    /// 意图匹配：消息与任一示例语句的 embedding 相似度达到阈值即匹配。
     #[serde(default)]
-    pub save: Option<SaveAction>,
+    pub intent: Option<IntentMatch>,
+    /// 语言过滤：如 "zh"、"en"，仅匹配识别为该语言的文本消息。
     #[serde(default)]
-    pub forward: Option<Vec<String>>,
+    pub language: Option<String>,
+    /// 过滤表达式（gewe-rules），如 `chat == group && sender in [wxid_a, wxid_b]`，
+    /// 与其余条件同时满足才匹配。
     #[serde(default)]
-    pub log: Option<bool>,
+    pub expr: Option<String>,
+    /// 生效时段 `HH:MM-HH:MM`（含开始不含结束），结束早于开始时跨越午夜，如 "22:00-08:00"；
+    /// 任一时段命中即可。按会话设置的时区判断，未设置时为本机时区。
     #[serde(default)]
-    pub ignore: Option<bool>,
+    pub time_ranges: Vec<String>,
+    /// 生效的星期：`mon`、`mon-fri`、`周六` 等，为空不限制。
     #[serde(default)]
-    pub command: Option<CommandAction>,
-    /// AI 回复动作，使用 OpenAI 兼容接口（可指向 Gemini OpenAI 兼容端点）。
+    pub weekdays: Vec<String>,
+    /// cron 表达式（分 时 日 月 周），当前分钟命中才匹配，如 "* 9-17 * * 1-5"。
     #[serde(default)]
-    pub ai: Option<AiAction>,
-    /// 回复模式：none（默认，直接发消息）、quote（引用原消息）、at（在群聊中@发送者）。
+    pub cron: Option<String>,
+}
+
+/// 意图匹配：用 embedding 比较消息与示例语句，能识别同义改写（如“帮我查下天气”与“天气怎么样”）
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct IntentMatch {
+    /// 示例语句
     #[serde(default)]
-    pub reply_mode: Option<ReplyMode>,
-    /// 是否要求在群聊中被 @ 才触发该规则（仅群聊生效）。
+    pub examples: Vec<String>,
+    /// 余弦相似度阈值，默认 0.8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    /// Embedding 模型，默认 text-embedding-3-small（OpenAI 兼容接口）；ollama 默认 nomic-embed-text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    /// Embedding 接口 base_url，默认 https://api.openai.com/v1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Embedding API Key 环境变量名，默认 GEWE_AI_API_KEY
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+}
+
+/// 圆形地理围栏（WGS84 / GCJ-02 坐标与微信位置消息保持一致即可）
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct GeoFence {
+    /// 中心纬度
+    pub lat: f64,
+    /// 中心经度
+    pub lng: f64,
+    /// 半径（米）
+    pub radius_m: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FromConfig {
     #[serde(default)]
-    pub require_mention: Option<bool>,
+    pub nick: Option<String>,
+    #[serde(default)]
+    pub wxid: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct RuleConfig {
+/// 告警动作：命中的消息转发到管理员群聊或私聊，配置了 external_base_url 且开启会话消息记录时附带
+/// 会话上下文的签名链接。warning 级每个关键词每天只即时转发前 `daily_limit` 条，其余与 info 级
+/// 一起计入每日汇总，避免告警刷屏
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AlertAction {
+    /// 接收告警的管理员群聊 ID 或 wxid
     #[serde(default)]
-    pub kind: RuleKind,
+    pub to: Vec<String>,
+    /// 关键词（不区分大小写）及级别，命中多个时取级别最高的；为空时规则命中即按 `severity` 告警
     #[serde(default)]
-    pub r#match: MatchConfig,
+    pub keywords: Vec<AlertKeyword>,
+    /// 未配置关键词时的级别，默认 warning
     #[serde(default)]
-    pub from: FromConfig,
+    pub severity: Option<AlertSeverity>,
+    /// warning 级每个关键词每天即时转发的条数上限，默认 3；critical 总是即时转发
     #[serde(default)]
-    pub chat: Option<ChatKind>,
+    pub daily_limit: Option<u32>,
+    /// 每日汇总的发送时间（本地时区 HH:MM），默认 09:00，汇总此前 24 小时的告警
     #[serde(default)]
-    pub action: RuleAction,
+    pub summary_at: Option<String>,
+    /// 错过 summary_at（如重启）后的补跑策略，默认补跑一次
+    #[serde(default)]
+    pub catch_up: Option<CatchUpPolicy>,
+    /// 上下文链接的有效期（秒），默认 7 天
+    #[serde(default)]
+    pub link_ttl_secs: Option<u64>,
 }
 
+/// 告警关键词
 #[derive(Debug, Clone, Default, Deserialize)]
-pub struct ResponseFormatConfig {
-    /// 目前支持 json_object。
-    #[serde(rename = "type", default)]
-    pub format_type: Option<String>,
-    /// 可选 JSON Schema。
+pub struct AlertKeyword {
+    pub word: String,
     #[serde(default)]
-    pub schema: Option<serde_json::Value>,
+    pub severity: AlertSeverity,
 }
 
-#[derive(Debug, Clone, Default, Deserialize)]
-pub struct AiAction {
-    /// LLM Provider: openai, anthropic, gemini。默认 openai（支持 OpenAI 兼容接口）。
-    #[serde(default)]
-    pub provider: Option<String>,
-    /// 模型名称，如 gpt-4o / claude-3-5-sonnet / gemini-2.0-flash 等。
-    pub model: String,
-    /// API Key，直接配置（优先级高于 api_key_env）。
-    /// 注意：直接写在配置文件中有安全风险，建议使用 api_key_env。
-    #[serde(default)]
-    pub api_key: Option<String>,
-    /// API Key 环境变量名，未配置则读取 GEWE_AI_API_KEY。
-    #[serde(default)]
-    pub api_key_env: Option<String>,
-    /// OpenAI 兼容 base_url。默认为 https://api.openai.com/v1；
-    /// 若调用 Gemini OpenAI 兼容层，设置为 https://generativelanguage.googleapis.com/v1beta/openai/。
+/// 告警级别
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSeverity {
+    /// 只计入每日汇总
+    Info,
+    /// 每个关键词每天即时转发前 `daily_limit` 条
+    #[default]
+    Warning,
+    /// 总是即时转发
+    Critical,
+}
+
+impl AlertSeverity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Info => "提示",
+            Self::Warning => "警告",
+            Self::Critical => "严重",
+        }
+    }
+}
+
+impl AlertAction {
+    pub fn daily_limit(&self) -> u32 {
+        self.daily_limit.unwrap_or(3)
+    }
+
+    pub fn link_ttl_secs(&self) -> u64 {
+        self.link_ttl_secs.unwrap_or(7 * 86400)
+    }
+
+    /// 每日汇总的发送时间
+    pub fn summary_time(&self) -> std::result::Result<chrono::NaiveTime, String> {
+        let at = self.summary_at.as_deref().unwrap_or("09:00");
+        chrono::NaiveTime::parse_from_str(at.trim(), "%H:%M")
+            .map_err(|_| format!("summary_at 格式应为 HH:MM: {}", at))
+    }
+
+    /// 消息命中的关键词与级别；配置了关键词但都未命中时返回 None
+    pub fn classify(&self, text: &str) -> Option<(Option<&str>, AlertSeverity)> {
+        if self.keywords.is_empty() {
+            return Some((None, self.severity.unwrap_or_default()));
+        }
+        let text = text.to_lowercase();
+        self.keywords
+            .iter()
+            .filter(|k| !k.word.trim().is_empty() && text.contains(&k.word.to_lowercase()))
+            .fold(None, |best: Option<&AlertKeyword>, k| match best {
+                Some(b) if b.severity >= k.severity => Some(b),
+                _ => Some(k),
+            })
+            .map(|k| (Some(k.word.as_str()), k.severity))
+    }
+
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.to.iter().all(|t| t.trim().is_empty()) {
+            errors.push("to 至少需要一个接收告警的群聊或 wxid".to_string());
+        }
+        if let Err(err) = self.summary_time() {
+            errors.push(err);
+        }
+        errors
+    }
+}
+
+/// 转人工：创建工单并通知值班人员，值班人员在私聊中发送 `/ack 编号` 接单后，该用户的后续消息
+/// 转发到接单人员的私聊，接单人员的回复转发给用户；`/close` 关闭工单后恢复机器人处理。
+/// 配置 rota 时只通知在班的值班人员，非值班时间的工单排队到下一班开始时再通知
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EscalateAction {
+    /// 值班人员 wxid，需为机器人好友；配置 rota 时不使用
     #[serde(default)]
-    pub base_url: Option<String>,
-    /// system prompt。
+    pub operators: Vec<String>,
+    /// 值班表：按星期与时段排班
     #[serde(default)]
-    pub system_prompt: Option<String>,
-    /// user 内容前置提示词。
+    pub rota: Vec<RotaShift>,
+    /// 值班表所在时区（IANA 时区名），默认本机时区
     #[serde(default)]
-    pub user_prefix: Option<String>,
-    /// 在调用模型前执行的命令（例如查询接口），输出会拼到提示词中。
+    pub timezone: Option<String>,
+    /// 创建工单后回复用户的内容
     #[serde(default)]
-    pub command: Option<CommandAction>,
-    /// command 输出截断长度，未设置时沿用默认 command 最大输出。
+    pub reply_text: Option<String>,
+    /// 接单后通知用户的内容
     #[serde(default)]
-    pub max_command_output: Option<usize>,
-    /// 模型参数
+    pub ack_text: Option<String>,
+    /// 关闭工单后通知用户的内容
     #[serde(default)]
-    pub temperature: Option<f32>,
+    pub close_text: Option<String>,
+    /// 无人接单时每隔多久（秒）再次通知值班人员，默认 300，0 表示不提醒
     #[serde(default)]
-    pub max_tokens: Option<u32>,
-    /// 结构化输出配置
+    pub remind_after_secs: Option<u64>,
+    /// 非值班时间创建工单后回复用户的内容，支持 `{next_shift}`（下一班开始时间）与
+    /// `{wait}`（预计等待时长）
     #[serde(default)]
-    pub response_format: Option<ResponseFormatConfig>,
-    /// OpenAI 工具配置，模型可自主选择调用，内部映射到命令执行。
+    pub off_hours_text: Option<String>,
+    /// 响应时限（秒）：通知值班人员后超过该时长才接单计为超时，默认 600
     #[serde(default)]
-    pub tools: Vec<AiTool>,
-    /// 最大重试次数，默认 2（即最多请求 3 次）。设为 0 禁用重试。
+    pub response_sla_secs: Option<u64>,
+}
+
+/// 值班表中的一个班次：weekdays 与 time_ranges 同规则的生效时间，都满足时该班次在班
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RotaShift {
+    /// 星期，如 `mon-fri`、`sat`、`周日`，为空时每天
     #[serde(default)]
-    pub max_retries: Option<u32>,
-    /// 重试基础延迟（毫秒），默认 1000。采用指数退避：第 N 次重试等待 base_delay * 2^N。
+    pub weekdays: Vec<String>,
+    /// 时段 `HH:MM-HH:MM`，为空时全天
     #[serde(default)]
-    pub retry_delay_ms: Option<u64>,
+    pub time_ranges: Vec<String>,
+    pub operators: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
-#[serde(rename_all = "snake_case")]
-pub enum ReplyMode {
-    #[default]
-    None,
-    Quote,
-    At,
-    QuoteAndAt,
+impl EscalateAction {
+    pub fn reply_text(&self) -> &str {
+        self.reply_text
+            .as_deref()
+            .unwrap_or("已为您转接人工客服，请稍候")
+    }
+
+    pub fn ack_text(&self) -> &str {
+        self.ack_text
+            .as_deref()
+            .unwrap_or("人工客服已接入，请直接描述您的问题")
+    }
+
+    pub fn close_text(&self) -> &str {
+        self.close_text
+            .as_deref()
+            .unwrap_or("人工服务已结束，如有其他问题可继续提问")
+    }
+
+    pub fn remind_after_secs(&self) -> u64 {
+        self.remind_after_secs.unwrap_or(300)
+    }
+
+    pub fn off_hours_text(&self) -> &str {
+        self.off_hours_text.as_deref().unwrap_or(
+            "现在是非值班时间，人工客服将于 {next_shift} 上班后尽快处理（预计等待 {wait}），请稍候",
+        )
+    }
+
+    pub fn response_sla_secs(&self) -> u64 {
+        self.response_sla_secs.unwrap_or(600)
+    }
+
+    /// 去掉空白项后的 operators
+    pub fn operators(&self) -> Vec<String> {
+        trimmed_wxids(&self.operators)
+    }
+
+    /// 解析值班表，未配置 rota 时为 None
+    pub fn rota(&self) -> std::result::Result<Option<Rota>, String> {
+        if self.rota.is_empty() {
+            return Ok(None);
+        }
+        let tz =
+            ScheduleTz::parse(self.timezone.as_deref()).map_err(|e| format!("timezone {}", e))?;
+        let mut rota = Rota::new(tz);
+        for (idx, shift) in self.rota.iter().enumerate() {
+            let window = ActiveWindow::parse(&shift.time_ranges, &shift.weekdays, None)
+                .map_err(|e| format!("rota[{}] {}", idx, e))?;
+            let operators = trimmed_wxids(&shift.operators);
+            if operators.is_empty() {
+                return Err(format!("rota[{}] 至少需要一个值班人员 wxid", idx));
+            }
+            rota.add_shift(window, operators);
+        }
+        Ok(Some(rota))
+    }
+
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        match self.rota() {
+            Err(err) => vec![err],
+            Ok(None) if self.operators().is_empty() => {
+                vec!["operators 或 rota 至少需要一个值班人员 wxid".to_string()]
+            }
+            Ok(_) => Vec::new(),
+        }
+    }
+}
+
+fn trimmed_wxids(wxids: &[String]) -> Vec<String> {
+    wxids
+        .iter()
+        .map(|o| o.trim())
+        .filter(|o| !o.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
-pub struct AiTool {
-    /// 工具名称，需与模型 tool call 的 function.name 一致。
-    pub name: String,
-    /// 工具描述，帮助模型按需选择。
+pub struct SaveAction {
+    /// 保存目录，未配置 sinks 时使用，默认 data
     #[serde(default)]
-    pub description: Option<String>,
-    /// OpenAI 风格的 parameters（JSON Schema），可选。
+    pub dir: String,
+    /// 文件名模板，可选。支持 {new_msg_id}/{app_id}/{from_wxid}/{file_ext}/{sha256} 替换。
     #[serde(default)]
-    pub parameters: Option<serde_json::Value>,
-    /// 绑定的命令（内置或外置）。
+    pub filename: Option<String>,
+    /// 写入的媒体存储，取 `[server.media]` 中 sinks 的 id；为空时写入 dir
     #[serde(default)]
-    pub command: Option<CommandAction>,
+    pub sinks: Vec<String>,
 }
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            listen_addr: default_listen_addr(),
-            queue_size: default_queue_size(),
-            image_dir: default_image_dir(),
-            image_url_prefix: default_image_url_prefix(),
-            external_base_url: None,
-            max_concurrency: default_max_concurrency(),
-            bots: Vec::new(),
+/// 会话消息记录：开启后收到与发出的消息按会话写入 `{data_dir}/history/`，
+/// 供外部应用通过 `GET /api/history/{chat_id}` 渲染对话
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct HistoryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 每个会话保留的最近消息条数，默认 1000
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep: Option<usize>,
+    /// 媒体签名链接的有效期（秒），默认 3600
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_link_ttl_secs: Option<u64>,
+    /// 全文搜索索引：开启后消息同时写入索引，供 `GET /api/search` 查询
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search: Option<HistorySearchConfig>,
+}
+
+/// 全文搜索索引的存储后端
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySearchBackend {
+    /// SQLite FTS5（需启用 `sqlite` feature）
+    #[default]
+    Sqlite,
+    /// PostgreSQL tsvector（需启用 `postgres` feature）
+    Postgres,
+}
+
+/// 会话消息的全文搜索索引，索引不随 `keep` 截断
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct HistorySearchConfig {
+    #[serde(default)]
+    pub backend: HistorySearchBackend,
+    /// SQLite 文件路径，默认 `{data_dir}/history/search.db`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// PostgreSQL 连接串，`postgres` 后端必填
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_url: Option<String>,
+}
+
+impl HistoryConfig {
+    pub fn keep(&self) -> usize {
+        self.keep.unwrap_or(1000)
+    }
+
+    pub fn media_link_ttl_secs(&self) -> u64 {
+        self.media_link_ttl_secs.unwrap_or(3600)
+    }
+
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.keep == Some(0) {
+            errors.push("keep 必须大于 0".to_string());
         }
+        if self.media_link_ttl_secs == Some(0) {
+            errors.push("media_link_ttl_secs 必须大于 0".to_string());
+        }
+        if let Some(search) = &self.search {
+            if search.backend == HistorySearchBackend::Postgres
+                && search.database_url.as_deref().is_none_or(str::is_empty)
+            {
+                errors.push("search.database_url: postgres 后端需要填写连接串".to_string());
+            }
+        }
+        errors
     }
 }
 
-impl AppConfig {
-    pub fn load(path: Option<&str>) -> Result<Self> {
-        let path = path
-            .map(PathBuf::from)
-            .or_else(|| std::env::var("GEWE_BOT_CONFIG").ok().map(PathBuf::from))
-            .unwrap_or_else(|| PathBuf::from("config/bot-app.toml"));
-        let body = std::fs::read_to_string(&path)
-            .with_context(|| format!("读取配置失败: {}", path.display()))?;
+/// 在线看护：定时对每个机器人调用 checkOnline，离线时按指数退避调用 reconnection，
+/// 持续离线超过 `alert_after_secs` 时通知管理员，恢复后再发一条恢复通知
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct WatchdogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 在线时的检查间隔（秒），默认 60
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+    /// 重连失败后的最长退避间隔（秒），默认 1800
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_backoff_secs: Option<u64>,
+    /// 持续离线多久（秒）后通知管理员，默认 300
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert_after_secs: Option<u64>,
+    /// 接收离线与恢复通知的 wxid，由其他在线的机器人代发；未配置时发给全局管理员
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alert_to: Vec<String>,
+    /// 只看护这些机器人（app_id），留空表示全部
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bots: Vec<String>,
+}
 
-        // 检测是否为 V2 配置（config_version = 2）
-        if is_v2_config(&body) {
-            let v2: AppConfigV2 = toml::from_str(&body)
-                .with_context(|| format!("解析 V2 配置失败: {}", path.display()))?;
-            let config = v2
-                .into_v1(&path)
-                .with_context(|| format!("转换 V2 配置失败: {}", path.display()))?;
-            return Ok(config);
-        }
+impl WatchdogConfig {
+    pub fn interval_secs(&self) -> u64 {
+        self.interval_secs.unwrap_or(60)
+    }
 
-        let mut config: AppConfig =
-            toml::from_str(&body).with_context(|| format!("解析配置失败: {}", path.display()))?;
-        if config.listen_addr.is_empty() {
-            config.listen_addr = default_listen_addr();
+    pub fn max_backoff_secs(&self) -> u64 {
+        self.max_backoff_secs.unwrap_or(1800)
+    }
+
+    pub fn alert_after_secs(&self) -> u64 {
+        self.alert_after_secs.unwrap_or(300)
+    }
+
+    /// 是否看护该机器人
+    pub fn watches(&self, app_id: &str) -> bool {
+        self.bots.is_empty() || self.bots.iter().any(|b| b == app_id)
+    }
+
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.interval_secs == Some(0) {
+            errors.push("interval_secs 必须大于 0".to_string());
         }
-        if config.queue_size == 0 {
-            config.queue_size = default_queue_size();
+        if self.max_backoff_secs() < self.interval_secs() {
+            errors.push("max_backoff_secs 不能小于 interval_secs".to_string());
         }
-        if config.max_concurrency == 0 {
-            config.max_concurrency = default_max_concurrency();
+        errors
+    }
+}
+
+/// 媒体存储：`save` 动作可把收到的图片、视频、文件写入本地目录、S3 兼容对象存储或 WebDAV，
+/// 按 SHA-256 去重，保存记录写入 `{data_dir}/media/records.jsonl`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct MediaConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sinks: Vec<MediaSinkConfig>,
+}
+
+impl MediaConfig {
+    pub fn sink(&self, id: &str) -> Option<&MediaSinkConfig> {
+        self.sinks.iter().find(|s| s.id == id)
+    }
+
+    /// 校验存储配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        let is_url = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+        for (i, sink) in self.sinks.iter().enumerate() {
+            if sink.id.trim().is_empty() {
+                errors.push(format!("sinks[{}]: id 不能为空", i));
+            } else if !seen.insert(sink.id.as_str()) {
+                errors.push(format!("sinks[{}]: id 重复: {}", i, sink.id));
+            }
+            match &sink.backend {
+                MediaBackend::Local { dir } if dir.trim().is_empty() => {
+                    errors.push(format!("sinks[{}]: dir 不能为空", i));
+                }
+                MediaBackend::Local { .. } => {}
+                MediaBackend::S3 {
+                    endpoint,
+                    bucket,
+                    access_key_id,
+                    secret_access_key,
+                    secret_access_key_env,
+                    ..
+                } => {
+                    if !is_url(endpoint.trim()) {
+                        errors.push(format!(
+                            "sinks[{}]: endpoint 必须以 http:// 或 https:// 开头: {}",
+                            i, endpoint
+                        ));
+                    }
+                    if bucket.trim().is_empty() {
+                        errors.push(format!("sinks[{}]: bucket 不能为空", i));
+                    }
+                    if access_key_id.trim().is_empty() {
+                        errors.push(format!("sinks[{}]: access_key_id 不能为空", i));
+                    }
+                    if secret_access_key.is_none() && secret_access_key_env.is_none() {
+                        errors.push(format!(
+                            "sinks[{}]: 需要配置 secret_access_key 或 secret_access_key_env",
+                            i
+                        ));
+                    }
+                }
+                MediaBackend::Webdav { url, .. } => {
+                    if !is_url(url.trim()) {
+                        errors.push(format!(
+                            "sinks[{}]: url 必须以 http:// 或 https:// 开头: {}",
+                            i, url
+                        ));
+                    }
+                }
+            }
         }
-        Ok(config)
+        errors
     }
 }
 
-/// 判定配置是否为 V2 结构
-fn is_v2_config(body: &str) -> bool {
-    toml::from_str::<toml::Value>(body)
-        .ok()
-        .and_then(|v| v.get("config_version").and_then(|x| x.as_integer()))
-        == Some(2)
+/// 一个媒体存储
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MediaSinkConfig {
+    pub id: String,
+    #[serde(flatten)]
+    pub backend: MediaBackend,
 }
 
-// -------------------- V2 配置定义与转换 --------------------
+/// 媒体存储后端，按 `kind` 区分
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MediaBackend {
+    /// 本地目录
+    Local { dir: String },
+    /// S3 兼容对象存储（AWS S3、MinIO、Cloudflare R2 等），使用 SigV4 签名上传
+    S3 {
+        /// 服务地址，如 `https://s3.us-east-1.amazonaws.com`
+        endpoint: String,
+        bucket: String,
+        /// 默认 us-east-1
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        region: Option<String>,
+        access_key_id: String,
+        /// 密钥，直接配置（优先级高于 secret_access_key_env）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret_access_key: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret_access_key_env: Option<String>,
+        /// 对象键前缀，如 `wechat/`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+        /// 使用 `{endpoint}/{bucket}/{key}` 路径风格，默认 true；false 时使用 `{bucket}.{host}` 虚拟主机风格
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path_style: Option<bool>,
+    },
+    /// WebDAV 目录，文件名模板中的子目录逐级创建
+    Webdav {
+        /// 目录地址，如 `https://dav.example.com/remote.php/dav/files/bot/wechat`
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        /// 密码，直接配置（优先级高于 password_env）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        password_env: Option<String>,
+    },
+}
 
-/// V2 配置根结构（公开用于 API）
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppConfigV2 {
-    pub config_version: u8,
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UnfurlAction {
+    /// 是否去除 utm_* 等追踪参数，默认 true。
     #[serde(default)]
-    pub server: ServerConfigV2,
+    pub strip_tracking: Option<bool>,
+    /// 网页没有 og:image 时使用的缩略图 URL（可选）。
     #[serde(default)]
-    pub storage: StorageConfigV2,
+    pub default_thumb_url: Option<String>,
+    /// 抓取网页的超时秒数，默认 10。
     #[serde(default)]
-    pub defaults: DefaultsV2,
+    pub timeout_secs: Option<u64>,
+    /// 单条消息最多展开的链接数，默认 1。
     #[serde(default)]
-    pub bots: Vec<BotConfigV2>,
+    pub max_links: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DocumentSummaryAction {
+    /// 摘要使用的模型，字段与 ai 动作相同（system_prompt 覆盖内置的摘要指令）。
+    pub ai: AiAction,
+    /// 在摘要后按章节列出要点，默认 false。
     #[serde(default)]
-    pub ai_profiles: Vec<AiProfileV2>,
+    pub sections: Option<bool>,
+    /// 追加到摘要请求中的要求，如「重点关注金额与日期」。
     #[serde(default)]
-    pub tools: Vec<ToolConfigV2>,
+    pub prompt: Option<String>,
+    /// 每段送入模型的最大字符数，默认 6000。
     #[serde(default)]
-    pub rule_templates: Vec<RuleTemplateV2>,
+    pub chunk_chars: Option<usize>,
+    /// 最多摘要的分段数，超出部分忽略，默认 8。
     #[serde(default)]
-    pub rule_instances: Vec<RuleInstanceV2>,
-}
-
-/// 服务器配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct ServerConfigV2 {
-    #[serde(default = "default_listen_addr")]
-    pub listen_addr: String,
-    #[serde(default = "default_queue_size")]
-    pub queue_size: usize,
-}
-
-/// 存储配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct StorageConfigV2 {
-    #[serde(default = "default_image_dir")]
-    pub image_dir: String,
-    #[serde(default = "default_image_url_prefix")]
-    pub image_url_prefix: String,
+    pub max_chunks: Option<usize>,
+    /// 允许处理的最大文件字节数，默认 20 MiB。
     #[serde(default)]
-    pub external_base_url: Option<String>,
+    pub max_file_size: Option<u64>,
 }
 
-/// 默认配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct DefaultsV2 {
+/// 长语音/音频文件转写后生成会议纪要
+#[derive(Debug, Clone, Deserialize)]
+pub struct MeetingNotesAction {
+    /// 生成纪要使用的模型，字段与 ai 动作相同（system_prompt 覆盖内置的纪要指令）。
+    pub ai: AiAction,
+    /// 语音转写配置。
     #[serde(default)]
-    pub reply_mode: Option<ReplyMode>,
+    pub stt: SttConfig,
+    /// 语音消息达到该时长（秒）才生成纪要，默认 30。
     #[serde(default)]
-    pub log: Option<bool>,
+    pub min_duration_secs: Option<u64>,
+    /// 音频文件达到该字节数才生成纪要，默认 1 MiB。
     #[serde(default)]
-    pub ai: Option<DefaultsAiV2>,
-}
-
-/// AI 默认配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct DefaultsAiV2 {
+    pub min_file_size: Option<u64>,
+    /// 允许转写的最大字节数，默认 25 MiB（OpenAI 转写接口上限）。
     #[serde(default)]
-    pub profile: Option<String>,
+    pub max_file_size: Option<u64>,
+    /// 是否把转写原文作为 .txt 文件一并发送，默认 true（需配置 external_base_url）。
     #[serde(default)]
-    pub require_mention: Option<bool>,
+    pub attach_transcript: Option<bool>,
+    /// 追加到纪要请求中的要求，如「列出每位发言人的观点」。
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// 每段送入模型的最大字符数，默认 6000。
+    #[serde(default)]
+    pub chunk_chars: Option<usize>,
+    /// 最多整理的分段数，超出部分忽略，默认 8。
+    #[serde(default)]
+    pub max_chunks: Option<usize>,
 }
 
-/// Bot 配置（V2）
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct BotConfigV2 {
+/// 语音转写（STT）配置，使用 OpenAI 兼容的 /audio/transcriptions 接口
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SttConfig {
+    /// 转写模型，默认 whisper-1
     #[serde(default)]
-    pub id: Option<String>,
-    pub app_id: String,
+    pub model: Option<String>,
+    /// API 基础地址（含 /v1），默认 https://api.openai.com/v1
     #[serde(default)]
-    pub token: Option<String>,
+    pub base_url: Option<String>,
+    /// API Key 环境变量，默认 OPENAI_API_KEY
     #[serde(default)]
-    pub token_env: Option<String>,
-    pub base_url: String,
+    pub api_key_env: Option<String>,
+    /// 音频语言（ISO-639-1，如 zh），默认自动识别
     #[serde(default)]
-    pub webhook_secret: Option<String>,
+    pub language: Option<String>,
+    /// 转写提示词，可填写人名、术语以提高准确率
     #[serde(default)]
-    pub webhook_secret_env: Option<String>,
+    pub prompt: Option<String>,
+    /// 微信语音为 SILK 格式，需先转码：命令及参数，`{input}`/`{output}` 替换为文件路径，
+    /// 如 ["silk2mp3", "{input}", "{output}"]；执行需设置 GEWE_ALLOW_COMMAND=1
     #[serde(default)]
-    pub tags: Vec<String>,
+    pub convert_command: Vec<String>,
+    /// 转码输出的扩展名，默认 mp3
+    #[serde(default)]
+    pub convert_ext: Option<String>,
+    /// 转写超时秒数，默认 300
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
 }
 
-/// AI Profile 配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct AiProfileV2 {
-    pub id: String,
+/// 语音合成（TTS）配置，使用 OpenAI 兼容的 /audio/speech 接口
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct TtsConfig {
+    /// 合成模型，默认 tts-1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 音色，默认 alloy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+    /// API 基础地址（含 /v1），默认 https://api.openai.com/v1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// API Key 环境变量，默认 OPENAI_API_KEY
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// 请求的音频格式（mp3、wav 等），默认 mp3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+    /// 微信只能播放 SILK 语音，服务商返回其他格式时的转码命令及参数，`{input}`/`{output}`
+    /// 替换为文件路径，如 ["wav2silk", "{input}", "{output}"]；执行需设置 GEWE_ALLOW_COMMAND=1
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub convert_command: Vec<String>,
+    /// 超过该字数时改为文字回复，默认 300
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chars: Option<usize>,
+    /// 合成超时秒数，默认 60
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+/// 以语音消息回复：合成文字后保存到图片目录，通过 external_base_url 供 GeWe 下载
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct VoiceReplyAction {
+    /// 合成的文字，支持与 `reply_text` 相同的占位符
+    pub text: String,
     #[serde(default)]
-    pub provider: Option<String>,
-    pub model: String,
+    pub tts: TtsConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TodoAction {
+    /// 命令前缀，默认 /todo。
     #[serde(default)]
-    pub base_url: Option<String>,
+    pub prefix: Option<String>,
+    /// 每日在该时间（本地时区 HH:MM）把未完成待办发到对应会话，可选。
     #[serde(default)]
-    pub api_key: Option<String>,
+    pub daily_summary_at: Option<String>,
+    /// 错过 daily_summary_at（如重启）后的补跑策略，默认补跑一次。
     #[serde(default)]
-    pub api_key_env: Option<String>,
+    pub catch_up: Option<CatchUpPolicy>,
+    /// 单个会话最多保存的条目数，默认 50。
     #[serde(default)]
-    pub system_prompt: Option<String>,
+    pub max_items: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RemindAction {
+    /// 命令前缀，默认 /remind。
     #[serde(default)]
-    pub system_prompt_file: Option<String>,
+    pub prefix: Option<String>,
+    /// 群聊中创建的提醒改为私聊发送（默认在群里 @ 提醒人）。
     #[serde(default)]
-    pub user_prefix: Option<String>,
+    pub dm: Option<bool>,
+    /// 每人最多同时存在的提醒数，默认 20。
     #[serde(default)]
-    pub tool_ids: Vec<String>,
+    pub max_per_user: Option<usize>,
 }
 
-/// 工具配置（V2）
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct ToolConfigV2 {
-    pub id: String,
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NameCardAction {
+    /// 回发机器人自己的名片给对方。
     #[serde(default)]
-    pub kind: Option<String>,
+    pub send_own_card: Option<bool>,
+    /// 向名片中的联系人发起好友申请（仅非好友名片可用）。
+    #[serde(default)]
+    pub add_friend: Option<bool>,
+    /// 好友申请的验证语。
+    #[serde(default)]
+    pub greeting: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CommandAction {
+    /// 程序名或可执行路径。内置命令使用预置名称（如 claude_changelog）。
     pub program: String,
+    /// 传给外置命令的参数，可选。
     #[serde(default)]
     pub args: Vec<String>,
+    /// 超时秒数，可选，默认内部设置。
     #[serde(default)]
     pub timeout_secs: Option<u64>,
+    /// 最大输出长度（字节），超出会截断。
     #[serde(default)]
     pub max_output: Option<usize>,
+    /// 在执行命令前先回复的一段话（可选）。
     #[serde(default)]
     pub pre_reply: Option<String>,
+    /// 命令执行完成后（成功）再回复的一段话（可选）。
     #[serde(default)]
     pub post_reply: Option<String>,
+    /// 异步执行：立即回复任务编号，命令在后台运行，完成或失败后把结果发回原会话。
+    /// 规则的 command 动作期间可发送 `/jobs status <编号>` 查询进度；AI 工具进入可持久化的
+    /// AI 任务队列，发送 `/tasks status <编号>` 查询。适合耗时数分钟的命令与图像、视频生成。
+    #[serde(default, rename = "async")]
+    pub run_async: bool,
+    /// 内置 http_request 的访问策略，未配置时使用默认策略（禁止内网地址）。
     #[serde(default)]
-    pub description: Option<String>,
-    /// 可选的 parameters（JSON Schema），未配置时会补全 {"type":"object"}
+    pub http: Option<HttpPolicy>,
+    /// 内置图像生成工具的服务商与默认参数，未配置时使用 Gemini。
     #[serde(default)]
-    pub parameters: Option<serde_json::Value>,
+    pub image: Option<ImageToolConfig>,
+    /// 内置 ocr 工具的识别服务，未配置时使用 OpenAI 兼容的视觉模型。
+    #[serde(default)]
+    pub ocr: Option<OcrConfig>,
 }
 
-/// 规则模板（V2）
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct RuleTemplateV2 {
+/// http_request 工具的访问策略
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HttpPolicy {
+    /// 允许访问的主机，支持 `*.example.com`；为空时允许除 deny_hosts 外的所有公网主机
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_hosts: Vec<String>,
+    /// 禁止访问的主机，支持 `*.example.com`，优先于 allow_hosts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_hosts: Vec<String>,
+    /// 允许访问内网、回环、链路本地与云元数据地址，默认 false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_private: Option<bool>,
+    /// 响应体最大字节数，超出部分截断，默认 1 MiB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_response_bytes: Option<usize>,
+    /// 允许的响应 Content-Type 前缀（如 `application/json`、`text/`），为空不限制
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_content_types: Vec<String>,
+    /// 认证配置，模型通过 `auth` 参数按 id 选用
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth_profiles: Vec<HttpAuthProfile>,
+}
+
+/// http_request 的认证配置：header 值只从环境变量读取，不写入配置、不暴露给模型
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HttpAuthProfile {
     pub id: String,
+    /// 仅对这些主机附加认证 header（支持 `*.example.com`），为空时不限制
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    /// header 名称 -> 环境变量名
     #[serde(default)]
-    pub name: Option<String>,
-    #[serde(default)]
-    pub kind: Option<RuleKind>,
-    #[serde(default)]
-    pub r#match: MatchConfigV2,
-    #[serde(default)]
-    pub action: TemplateActionV2,
+    pub headers_env: BTreeMap<String, String>,
+}
+
+/// 图像生成服务商
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageProviderKind {
+    /// Gemini generateContent（默认）
+    #[default]
+    Gemini,
+    /// OpenAI Images API（gpt-image-1、dall-e-3 及兼容端点）
+    Openai,
+    /// Stability AI / SDXL text-to-image 及兼容端点
+    Stability,
+}
+
+impl ImageProviderKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gemini => "gemini",
+            Self::Openai => "openai",
+            Self::Stability => "stability",
+        }
+    }
+
+    /// 未配置 api_key_env 时读取的环境变量
+    pub fn default_api_key_env(&self) -> &'static str {
+        match self {
+            Self::Gemini => "GEWE_AI_API_KEY",
+            Self::Openai => "OPENAI_API_KEY",
+            Self::Stability => "STABILITY_API_KEY",
+        }
+    }
+}
+
+/// 图像生成工具配置：选择服务商及其默认参数，模型调用时传入的同名参数优先
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImageToolConfig {
     #[serde(default)]
-    pub defaults: TemplateDefaultsV2,
+    pub provider: ImageProviderKind,
+    /// 模型名称，未配置时使用服务商默认模型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// API 基础地址（代理或兼容端点）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// API Key 环境变量；Gemini 未配置时沿用 AI Profile 的 Key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// Gemini: 宽高比，如 16:9
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<String>,
+    /// Gemini: 图像尺寸 1K/2K/4K
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<String>,
+    /// OpenAI / Stability: 像素尺寸，如 1024x1024
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// OpenAI: 质量，如 standard/hd（dall-e-3）或 low/medium/high（gpt-image-1）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    /// OpenAI: dall-e-3 风格 vivid/natural
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// Stability: 采样步数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steps: Option<u32>,
+    /// Stability: 提示词相关度
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cfg_scale: Option<f32>,
+    /// Stability: 风格预设，如 photographic、anime
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style_preset: Option<String>,
+    /// Stability: 反向提示词
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negative_prompt: Option<String>,
+    /// Stability 图生图: 原图保留程度 0~1，越大越接近原图，默认 0.35
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_strength: Option<f32>,
 }
 
-/// 模板默认配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct TemplateDefaultsV2 {
+/// OCR 识别服务
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OcrProviderKind {
+    /// OpenAI 兼容的视觉模型（chat/completions），默认
+    #[default]
+    Openai,
+    /// 本地 tesseract 命令，需以 `tesseract` feature 编译
+    Tesseract,
+}
+
+/// ocr 工具配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrConfig {
     #[serde(default)]
-    pub require_mention: Option<bool>,
+    pub provider: OcrProviderKind,
+    /// 视觉模型名称，默认 gpt-4o-mini
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// API 基础地址（含 /v1），默认 https://api.openai.com/v1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// API Key 环境变量，默认 OPENAI_API_KEY
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// 识别指令，覆盖默认的「只输出图片中的文字」
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// tesseract 语言，默认 chi_sim+eng
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<String>,
+    /// tesseract 可执行文件路径，默认从 PATH 查找
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tesseract_path: Option<String>,
 }
 
-/// 匹配配置（V2）
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct MatchConfigV2 {
+/// 解析 `1024x1024` 形式的像素尺寸
+pub fn parse_image_size(size: &str) -> Option<(u32, u32)> {
+    let (w, h) = size.trim().split_once(['x', 'X'])?;
+    let w = w.trim().parse().ok().filter(|v| *v > 0)?;
+    let h = h.trim().parse().ok().filter(|v| *v > 0)?;
+    Some((w, h))
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RuleAction {
     #[serde(default)]
-    pub equals: Option<String>,
+    pub reply_text: Option<String>,
+    /// 按顺序回复的多条消息（文字、图片、文件、链接），发送期间不会穿插其他回复。
     #[serde(default)]
-    pub contains: Option<String>,
+    pub reply_sequence: Vec<ReplyPart>,
+    /// 以链接卡片回复，标题、描述、链接、缩略图支持占位符。
     #[serde(default)]
-    pub regex: Option<String>,
+    pub reply_link: Option<LinkReplyAction>,
+    /// 以语音消息回复，合成失败或超过字数上限时改发文字。
     #[serde(default)]
-    pub any: Option<bool>,
-}
-
-/// 模板动作配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct TemplateActionV2 {
+    pub reply_voice: Option<VoiceReplyAction>,
+    /// 新成员入群时发送欢迎语，可附带图片，仅用于 `member_joined` 规则。
     #[serde(default)]
-    pub ai_profile: Option<String>,
+    pub welcome: Option<WelcomeAction>,
+    /// 动作失败后的处理：重试、回退回复、通知管理员、写入死信队列。
     #[serde(default)]
-    pub reply_mode: Option<ReplyMode>,
+    pub on_error: Option<ErrorPolicy>,
+    #[serde(default)]
+    pub save: Option<SaveAction>,
+    #[serde(default)]
+    pub forward: Option<Vec<String>>,
+    /// 不在会话中回复，把命中的消息连同上下文链接转发给管理员，按关键词分级并每日汇总。
+    #[serde(default)]
+    pub alert: Option<AlertAction>,
+    /// 转人工：创建工单并通知值班人员，接单后在用户与值班人员之间转发消息，直到 `/close`。
+    #[serde(default)]
+    pub escalate: Option<EscalateAction>,
+    /// 抓取消息中链接的标题/描述，并以整理后的链接卡片回复。
+    #[serde(default)]
+    pub unfurl: Option<UnfurlAction>,
+    /// 下载收到的 PDF/DOCX 文件，提取文字后用模型生成摘要并回复。
+    #[serde(default)]
+    pub summarize_document: Option<DocumentSummaryAction>,
+    /// 长语音/音频文件转写后回复会议纪要（决定事项、待办），并附带转写原文。
+    #[serde(default)]
+    pub meeting_notes: Option<MeetingNotesAction>,
+    /// 收到名片后回发自己的名片或添加名片联系人。
+    #[serde(default)]
+    pub name_card: Option<NameCardAction>,
+    /// 会话共享待办清单（/todo add、/todo done 2、/todo list）。
+    #[serde(default)]
+    pub todo: Option<TodoAction>,
+    /// 提醒命令（/remind me in 2h to ...、/remind 明天9点 开会、/remind list、/remind cancel 3）。
+    #[serde(default)]
+    pub remind: Option<RemindAction>,
     #[serde(default)]
     pub log: Option<bool>,
     #[serde(default)]
-    pub require_mention: Option<bool>,
+    pub ignore: Option<bool>,
     #[serde(default)]
-    pub reply_text: Option<String>,
-}
-
-/// 实例覆盖配置
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct InstanceOverridesV2 {
+    pub command: Option<CommandAction>,
+    /// AI 回复动作，使用 OpenAI 兼容接口（可指向 Gemini OpenAI 兼容端点）。
     #[serde(default)]
-    pub require_mention: Option<bool>,
+    pub ai: Option<AiAction>,
+    /// 回复模式：none（默认，直接发消息）、quote（引用原消息）、at（在群聊中@发送者）。
     #[serde(default)]
     pub reply_mode: Option<ReplyMode>,
+    /// 是否要求在群聊中被 @ 才触发该规则（仅群聊生效）。
     #[serde(default)]
-    pub ai_profile: Option<String>,
+    pub require_mention: Option<bool>,
+    /// 冷却时间（秒）：规则触发后，同一范围内在冷却期间再次命中时跳过动作。
     #[serde(default)]
-    pub log: Option<bool>,
+    pub cooldown_secs: Option<u64>,
+    /// 冷却的范围：sender（默认，按发送者）或 chat（按会话）。
     #[serde(default)]
-    pub reply_text: Option<String>,
+    pub cooldown_scope: Option<CooldownScope>,
 }
 
-/// 规则实例（V2）
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct RuleInstanceV2 {
-    pub id: String,
-    pub template: String,
+/// 规则冷却的计算范围
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CooldownScope {
+    /// 按发送者，群聊中为发言的群成员
+    #[default]
+    Sender,
+    /// 按会话，群聊中整个群共享冷却
+    Chat,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig {
+    /// 规则 ID，用于评价等统计；V2 配置取规则实例 id
     #[serde(default)]
-    pub channel: Option<String>, // private/group/both
+    pub id: Option<String>,
+    #[serde(default)]
+    pub kind: RuleKind,
+    #[serde(default)]
+    pub r#match: MatchConfig,
     #[serde(default)]
     pub from: FromConfig,
     #[serde(default)]
+    pub chat: Option<ChatKind>,
+    #[serde(default)]
+    pub action: RuleAction,
+    /// 在 `/help` 菜单中展示的功能说明，未配置时该规则不出现在菜单中
+    #[serde(default)]
+    pub description: Option<String>,
+    /// 菜单中的用法，如 `/weather 城市`；未配置时取 `match.equals`
+    #[serde(default)]
+    pub usage: Option<String>,
+    /// 匹配优先级，数值越小越先匹配，默认 0；相同优先级按配置顺序
+    #[serde(default)]
     pub priority: Option<i32>,
+    /// 命中并执行动作后继续匹配后续规则，默认只执行第一条命中的规则；`ignore` 动作总是停止匹配
     #[serde(default)]
-    pub overrides: Option<InstanceOverridesV2>,
+    pub continue_matching: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResponseFormatConfig {
+    /// 目前支持 json_object。
+    #[serde(rename = "type", default)]
+    pub format_type: Option<String>,
+    /// 可选 JSON Schema。
     #[serde(default)]
-    pub enabled: Option<bool>,
+    pub schema: Option<serde_json::Value>,
 }
 
-impl AppConfigV2 {
-    /// 从文件加载 V2 配置
-    #[allow(dead_code)]
-    pub fn load_from_file(path: &Path) -> Result<Self> {
-        let body = std::fs::read_to_string(path)
-            .with_context(|| format!("读取配置失败: {}", path.display()))?;
-        Self::parse(&body)
-    }
+/// 语义缓存：相似问题直接复用近期回答，减少重复的模型调用
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SemanticCacheConfig {
+    /// Embedding 模型，默认 text-embedding-3-small（OpenAI 兼容接口）；ollama 默认 nomic-embed-text
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    /// Embedding 接口 base_url，未配置时沿用 AI 动作的 OpenAI 兼容 base_url
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Embedding API Key 环境变量名，未配置时沿用 AI 动作的 Key
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// 余弦相似度阈值，默认 0.92
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    /// 缓存有效期（秒），默认 3600
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// 每个会话保留的问答条数，默认 50
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    /// 消息包含该关键词时跳过缓存并重新提问，默认 #nocache
+    #[serde(default)]
+    pub bypass_keyword: Option<String>,
+    /// 命中缓存时回复的前缀，默认“[缓存] ”
+    #[serde(default)]
+    pub cached_prefix: Option<String>,
+}
 
-    /// 从 TOML 字符串解析
-    pub fn parse(body: &str) -> Result<Self> {
-        toml::from_str(body).with_context(|| "解析 V2 配置失败")
-    }
+/// 结构化输出：模型按 JSON 返回回复与要执行的动作，由 dispatcher 校验后执行
+///
+/// 内置字段：`reply`（回复文本）、`forward_to`（转发目标 wxid 列表）、`label`（给发送者打的标签）
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct StructuredOutputConfig {
+    /// 自定义 JSON Schema，未配置时使用内置 schema；需包含上述内置字段才会执行对应动作
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<serde_json::Value>,
+    /// 允许模型转发的目标 wxid，不在列表中的目标会被忽略
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_forward: Vec<String>,
+    /// 允许模型使用的标签名，不在列表中的标签会被忽略
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_labels: Vec<String>,
+}
 
-    /// 从 JSON 字符串解析
-    #[allow(dead_code)]
-    pub fn from_json(json: &str) -> Result<Self> {
-        serde_json::from_str(json).with_context(|| "解析 JSON 配置失败")
-    }
+/// Azure OpenAI 额外配置（provider = "azure-openai"）
+///
+/// `base_url` 填资源终结点，如 `https://my-resource.openai.azure.com`，
+/// 请求地址为 `{base_url}/openai/deployments/{deployment}/chat/completions?api-version={api_version}`。
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct AzureOpenAiConfig {
+    /// 部署名称，未配置时与 model 相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<String>,
+    /// API 版本，格式为 `YYYY-MM-DD` 或 `YYYY-MM-DD-preview`，默认 2024-10-21
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+}
 
-    /// 序列化为 TOML 字符串
-    pub fn to_toml(&self) -> Result<String> {
-        toml::to_string_pretty(self).with_context(|| "序列化 TOML 失败")
-    }
+/// OpenRouter 额外配置（provider = "openrouter"），用于 OpenRouter 排行榜的应用归属
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct OpenRouterConfig {
+    /// 站点地址，作为 `HTTP-Referer` 请求头发送
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_url: Option<String>,
+    /// 应用名称，作为 `X-Title` 请求头发送，默认 gewe-bot-app
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+}
 
-    /// 序列化为 JSON 字符串
-    #[allow(dead_code)]
-    pub fn to_json(&self) -> Result<String> {
-        serde_json::to_string_pretty(self).with_context(|| "序列化 JSON 失败")
-    }
+/// AI 回复评价：回复后同一用户在时间窗口内发送 👍/👎 或关键词即记为评价
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct FeedbackConfig {
+    /// 回复后接受评价的时间窗口（秒），默认 600
+    #[serde(default)]
+    pub window_secs: Option<u64>,
+    /// 好评关键词，未配置时使用内置词表；👍 始终视为好评
+    #[serde(default)]
+    pub positive_keywords: Vec<String>,
+    /// 差评关键词，未配置时使用内置词表；👎 始终视为差评
+    #[serde(default)]
+    pub negative_keywords: Vec<String>,
+}
 
-    /// 校验配置，返回错误列表
-    pub fn validate(&self) -> Vec<String> {
-        let mut errors = Vec::new();
+/// AI 预算：调用模型前估算 token 与花费，超出单条或当日预算时回复提示并跳过调用
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct BudgetConfig {
+    /// 单条消息的 token 上限（估算的输入 + 预留的输出）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_per_message: Option<u64>,
+    /// 单条消息的花费上限，需在 prices 中配置该模型单价
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost_per_message: Option<f64>,
+    /// 每个机器人每个模型当日（本地时区）的 token 上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_tokens: Option<u64>,
+    /// 每个机器人每个模型当日的花费上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_cost: Option<f64>,
+    /// 每百万 token 单价，键为模型名
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prices: BTreeMap<String, ModelPrice>,
+    /// 超出预算时的回复，未配置时按原因使用内置提示
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+    /// 不受预算限制的管理员 wxid，未配置时沿用全局管理员
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<String>,
+}
 
-        // 检查 config_version
-        if self.config_version != 2 {
-            errors.push(format!(
-                "config_version 应为 2，当前为 {}",
-                self.config_version
-            ));
-        }
+/// 工具调用循环保护：限制单条消息内的工具调用次数，发现重复调用时停止调用工具并强制模型作答
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ToolLoopConfig {
+    /// 单条消息最多调用工具的次数，默认 1（调用一次后直接作答）；大于 1 时模型可多轮调用工具
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_calls: Option<u32>,
+    /// 同一工具以相同参数最多执行的次数，默认 1，再次以相同参数调用即视为陷入循环
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_identical_calls: Option<u32>,
+    /// 同一工具连续调用（参数不同）的次数上限，默认 3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_consecutive_calls: Option<u32>,
+    /// 被强制结束时附在回答后的说明，未配置时按原因使用内置提示，设为空字符串则不附加
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
 
-        // 检查 bots
-        let mut bot_ids = std::collections::HashSet::new();
-        for (i, bot) in self.bots.iter().enumerate() {
-            if bot.app_id.trim().is_empty() {
-                errors.push(format!("bots[{}]: app_id 不能为空", i));
-            }
-            if bot.base_url.trim().is_empty() {
-                errors.push(format!("bots[{}]: base_url 不能为空", i));
-            }
-            if bot.token.is_none() && bot.token_env.is_none() {
-                errors.push(format!("bots[{}]: token 或 token_env 必须设置其一", i));
-            }
-            let id = bot.id.clone().unwrap_or_else(|| bot.app_id.clone());
-            if !bot_ids.insert(id.clone()) {
-                errors.push(format!("bots[{}]: 重复的 id/app_id: {}", i, id));
-            }
-        }
+/// 单条消息工具调用次数上限
+pub const MAX_TOOL_CALLS: u32 = 10;
+
+/// 对话记忆：按会话保存最近几轮问答，调用模型时作为历史消息传入；群聊中所有成员共用一份记忆
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ConversationMemoryConfig {
+    /// 保留的最近轮数，默认 10
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+    /// 超过该时长（秒）未续上的对话不再作为历史，默认 1800
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+}
 
-        // 检查 ai_profiles
-        let mut profile_ids = std::collections::HashSet::new();
-        for (i, profile) in self.ai_profiles.iter().enumerate() {
-            if profile.id.trim().is_empty() {
-                errors.push(format!("ai_profiles[{}]: id 不能为空", i));
-            }
-            if profile.model.trim().is_empty() {
-                errors.push(format!("ai_profiles[{}]: model 不能为空", i));
-            }
-            if !profile_ids.insert(profile.id.clone()) {
-                errors.push(format!("ai_profiles[{}]: 重复的 id: {}", i, profile.id));
-            }
-            // 检查引用的 tool_ids 是否存在
-            for tool_id in &profile.tool_ids {
-                if !self.tools.iter().any(|t| &t.id == tool_id) {
-                    errors.push(format!("ai_profiles[{}]: 引用的工具不存在: {}", i, tool_id));
-                }
-            }
-        }
+/// 检测到疑似提示词注入时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptGuardLevel {
+    /// 只记录日志，内容原样传给模型
+    Log,
+    /// 记录日志并把命中的片段替换为占位符
+    #[default]
+    Neutralize,
+    /// 记录日志并拒绝调用模型，回复 `notice`
+    Reject,
+}
 
-        // 检查 tools
-        let mut tool_ids = std::collections::HashSet::new();
-        for (i, tool) in self.tools.iter().enumerate() {
-            if tool.id.trim().is_empty() {
-                errors.push(format!("tools[{}]: id 不能为空", i));
-            }
-            if tool.program.trim().is_empty() {
-                errors.push(format!("tools[{}]: program 不能为空", i));
-            }
-            if !tool_ids.insert(tool.id.clone()) {
-                errors.push(format!("tools[{}]: 重复的 id: {}", i, tool.id));
+/// 提示词注入防护：用户消息与查询结果放入标签内并在 system prompt 中声明其不是指令，
+/// 按 `level` 处理“忽略之前的指令”一类的注入特征
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PromptGuardConfig {
+    #[serde(default)]
+    pub level: PromptGuardLevel,
+    /// level = "reject" 时的回复，默认“消息包含疑似越权指令，已忽略”
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+}
+
+/// 对话记忆保留轮数上限
+pub const MAX_MEMORY_TURNS: usize = 50;
+
+/// Prompt 变体：按权重分流，用于 A/B 测试
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PromptVariant {
+    pub id: String,
+    /// 流量权重，默认 1；为 0 时不分配流量
+    #[serde(default)]
+    pub weight: Option<u32>,
+    /// 覆盖 system prompt
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// 覆盖 system prompt（从文件读取，仅 V2 配置）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt_file: Option<String>,
+    /// 覆盖 user 前置提示词
+    #[serde(default)]
+    pub user_prefix: Option<String>,
+}
+
+impl PromptVariant {
+    pub fn weight(&self) -> u32 {
+        self.weight.unwrap_or(1)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AiAction {
+    /// LLM Provider: openai, anthropic, gemini, ollama, azure-openai, openrouter。
+    /// 默认 openai（支持 OpenAI 兼容接口）。
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// 模型名称，如 gpt-4o / claude-3-5-sonnet / gemini-2.0-flash 等。
+    pub model: String,
+    /// API Key，直接配置（优先级高于 api_key_env）。
+    /// 注意：直接写在配置文件中有安全风险，建议使用 api_key_env。
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// API Key 环境变量名，未配置则读取 GEWE_AI_API_KEY。
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// OpenAI 兼容 base_url。默认为 https://api.openai.com/v1；
+    /// 若调用 Gemini OpenAI 兼容层，设置为 https://generativelanguage.googleapis.com/v1beta/openai/。
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// system prompt。
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// user 内容前置提示词。
+    #[serde(default)]
+    pub user_prefix: Option<String>,
+    /// 在调用模型前执行的命令（例如查询接口），输出会拼到提示词中。
+    #[serde(default)]
+    pub command: Option<CommandAction>,
+    /// command 输出截断长度，未设置时沿用默认 command 最大输出。
+    #[serde(default)]
+    pub max_command_output: Option<usize>,
+    /// 模型参数
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// 结构化输出配置
+    #[serde(default)]
+    pub response_format: Option<ResponseFormatConfig>,
+    /// OpenAI 工具配置，模型可自主选择调用，内部映射到命令执行。
+    #[serde(default)]
+    pub tools: Vec<AiTool>,
+    /// 最大重试次数，默认 2（即最多请求 3 次）。设为 0 禁用重试。
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    /// 重试基础延迟（毫秒），默认 1000。采用指数退避：第 N 次重试等待 base_delay * 2^N。
+    #[serde(default)]
+    pub retry_delay_ms: Option<u64>,
+    /// 语义缓存，未配置时每次都调用模型。
+    #[serde(default)]
+    pub cache: Option<SemanticCacheConfig>,
+    /// A/B 实验名称，用于汇总报告；未配置时使用模型名。
+    #[serde(default)]
+    pub experiment: Option<String>,
+    /// Prompt 变体，配置后每次回复按权重随机选择一个。
+    #[serde(default)]
+    pub variants: Vec<PromptVariant>,
+    /// 模型灰度：模型名 -> 流量百分比，未分配的比例使用 `model`。
+    /// 各模型沿用同一 provider、base_url 与 API Key。
+    #[serde(default)]
+    pub model_rollout: BTreeMap<String, u32>,
+    /// 用户评价收集，配置后记录对回复的 👍/👎。
+    #[serde(default)]
+    pub feedback: Option<FeedbackConfig>,
+    /// 预算控制，配置后调用模型前先估算 token 与花费。
+    #[serde(default)]
+    pub budget: Option<BudgetConfig>,
+    /// 结构化输出，配置后模型返回 JSON 动作而非纯文本。
+    #[serde(default)]
+    pub structured: Option<StructuredOutputConfig>,
+    /// Azure OpenAI 部署名与 API 版本，仅 provider = "azure-openai" 时生效。
+    #[serde(default)]
+    pub azure: Option<AzureOpenAiConfig>,
+    /// 工具调用循环保护，未配置时每条消息最多调用一次工具。
+    #[serde(default)]
+    pub tool_loop: Option<ToolLoopConfig>,
+    /// 对话记忆，未配置时每条消息独立调用模型。
+    #[serde(default)]
+    pub memory: Option<ConversationMemoryConfig>,
+    /// 流式调用模型，每生成完整段落即发送，不必等待完整回复；调用工具或结构化输出时不生效。
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// 流式回复先发出第一段，生成完成后撤回该段并改发完整回复；完整回复超过单条字数上限
+    /// 或已超过撤回时限时，其余段落照常发送。
+    #[serde(default)]
+    pub stream_edit: Option<bool>,
+    /// 以语音消息发送回复，配置后不再流式发送；合成失败或超过字数上限时改发文字。
+    #[serde(default)]
+    pub reply_voice: Option<TtsConfig>,
+    /// 单条消息的字数上限，超出时在段落或换行处拆成多条发送；流式回复未配置时为 2000。
+    #[serde(default)]
+    pub max_chars_per_message: Option<usize>,
+    /// 提示词注入防护，未配置时用户消息原样传给模型。
+    #[serde(default)]
+    pub prompt_guard: Option<PromptGuardConfig>,
+    /// OpenRouter 归属请求头，仅 provider = "openrouter" 时生效。
+    #[serde(default)]
+    #[cfg_attr(not(feature = "ai"), allow(dead_code))]
+    pub openrouter: Option<OpenRouterConfig>,
+}
+
+/// 规则动作失败后的处理，对该规则的每个动作分别生效
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ErrorPolicy {
+    /// 失败后的重试次数，默认 0，最多 10
+    #[serde(default)]
+    pub retries: u32,
+    /// 首次重试前的等待毫秒数，默认 1000，之后每次翻倍
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay_ms: Option<u64>,
+    /// 重试后仍失败时改为回复的消息，如图片发送失败时回复一段文字
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback: Vec<ReplyPart>,
+    /// 接收失败通知的管理员 wxid
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify: Vec<String>,
+    /// 是否写入死信队列 `{data_dir}/dlq/dead_letters.jsonl`
+    #[serde(default)]
+    pub dlq: bool,
+}
+
+/// 重试次数上限，避免配置错误时长时间阻塞消息处理
+pub const MAX_ACTION_RETRIES: u32 = 10;
+
+impl ErrorPolicy {
+    /// 校验配置，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.retries > MAX_ACTION_RETRIES {
+            errors.push(format!(
+                "retries 不能超过 {}，当前为 {}",
+                MAX_ACTION_RETRIES, self.retries
+            ));
+        }
+        for (i, part) in self.fallback.iter().enumerate() {
+            if let Some(err) = part.validate() {
+                errors.push(format!("fallback[{}] {}", i, err));
             }
         }
+        if self.notify.iter().any(|wxid| wxid.trim().is_empty()) {
+            errors.push("notify 中不能有空的 wxid".to_string());
+        }
+        errors
+    }
+}
 
-        // 检查 rule_templates
-        let mut template_ids = std::collections::HashSet::new();
-        for (i, template) in self.rule_templates.iter().enumerate() {
-            if template.id.trim().is_empty() {
-                errors.push(format!("rule_templates[{}]: id 不能为空", i));
-            }
-            if !template_ids.insert(template.id.clone()) {
-                errors.push(format!("rule_templates[{}]: 重复的 id: {}", i, template.id));
-            }
-            // 检查引用的 ai_profile 是否存在
-            if let Some(ref profile_id) = template.action.ai_profile {
-                if !profile_ids.contains(profile_id) {
-                    errors.push(format!(
-                        "rule_templates[{}]: 引用的 ai_profile 不存在: {}",
-                        i, profile_id
-                    ));
-                }
-            }
+/// 链接卡片回复
+///
+/// 各字段支持占位符：{app_id} {chat} {from_wxid} {sender_wxid} {to_wxid} {new_msg_id}
+/// {content} {nickname}，以及 regex 的捕获组 {0} {1} … 与命名捕获组 {name}。
+/// 填入 url 的值会进行 URL 编码；url 须以 http:// 或 https:// 开头。
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct LinkReplyAction {
+    pub title: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumb_url: Option<String>,
+}
+
+impl LinkReplyAction {
+    /// 校验模板，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.title.trim().is_empty() {
+            errors.push("title 不能为空".to_string());
         }
+        let url = self.url.trim();
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            errors.push(format!("url 必须以 http:// 或 https:// 开头: {}", self.url));
+        }
+        errors
+    }
+}
 
-        // 检查 rule_instances
-        let mut instance_ids = std::collections::HashSet::new();
-        for (i, instance) in self.rule_instances.iter().enumerate() {
-            if instance.id.trim().is_empty() {
-                errors.push(format!("rule_instances[{}]: id 不能为空", i));
-            }
-            if instance.template.trim().is_empty() {
-                errors.push(format!("rule_instances[{}]: template 不能为空", i));
-            }
-            if !template_ids.contains(&instance.template) {
+/// 入群欢迎语，`text` 支持 `{nickname}`（新成员昵称，多人以「、」连接）、`{chatroom_name}`（群名）、
+/// `{member_count}` 与 `{operator}`（邀请人）占位符；配置 `image_url` 时在欢迎语后发送该图片。
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct WelcomeAction {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    /// 在欢迎语前 @ 新成员，只有带 wxid 的入群通知可以 @
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mention: Option<bool>,
+}
+
+impl WelcomeAction {
+    /// 校验欢迎语，返回错误描述
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.text.trim().is_empty() {
+            errors.push("text 不能为空".to_string());
+        }
+        if let Some(url) = self.image_url.as_deref().map(str::trim) {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
                 errors.push(format!(
-                    "rule_instances[{}]: 引用的模板不存在: {}",
-                    i, instance.template
+                    "image_url 必须以 http:// 或 https:// 开头: {}",
+                    url
                 ));
             }
-            if !instance_ids.insert(instance.id.clone()) {
-                errors.push(format!("rule_instances[{}]: 重复的 id: {}", i, instance.id));
+        }
+        errors
+    }
+}
+
+/// 组合回复中的一条消息
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplyPart {
+    /// 文字；回复模式（引用、@）作用于序列中的第一条文字
+    Text { text: String },
+    /// 图片链接
+    Image { url: String },
+    /// 文件链接，未配置 name 时取链接中的文件名
+    File {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    /// 链接卡片
+    Link {
+        title: String,
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        desc: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thumb_url: Option<String>,
+    },
+}
+
+impl ReplyPart {
+    /// 校验单条消息，返回错误描述
+    pub fn validate(&self) -> Option<String> {
+        let is_url = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+        match self {
+            Self::Text { text } if text.trim().is_empty() => Some("text 不能为空".to_string()),
+            Self::Image { url } | Self::File { url, .. } | Self::Link { url, .. }
+                if !is_url(url.trim()) =>
+            {
+                Some(format!("url 必须以 http:// 或 https:// 开头: {}", url))
             }
-            // 检查 channel 值
-            if let Some(ref channel) = instance.channel {
-                if !["private", "group", "both"].contains(&channel.as_str()) {
-                    errors.push(format!(
-                        "rule_instances[{}]: channel 必须是 private/group/both，当前为: {}",
-                        i, channel
-                    ));
-                }
+            Self::Link { title, .. } if title.trim().is_empty() => {
+                Some("link 的 title 不能为空".to_string())
             }
-            // 检查 overrides 中引用的 ai_profile
-            if let Some(ref overrides) = instance.overrides {
-                if let Some(ref profile_id) = overrides.ai_profile {
-                    if !profile_ids.contains(profile_id) {
-                        errors.push(format!(
-                            "rule_instances[{}].overrides: 引用的 ai_profile 不存在: {}",
-                            i, profile_id
-                        ));
-                    }
-                }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyMode {
+    #[default]
+    None,
+    Quote,
+    At,
+    QuoteAndAt,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AiTool {
+    /// 工具名称，需与模型 tool call 的 function.name 一致。
+    pub name: String,
+    /// 工具描述，帮助模型按需选择。
+    #[serde(default)]
+    pub description: Option<String>,
+    /// OpenAI 风格的 parameters（JSON Schema），可选。
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+    /// 绑定的命令（内置或外置）。
+    #[serde(default)]
+    pub command: Option<CommandAction>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: default_listen_addr(),
+            queue_size: default_queue_size(),
+            image_dir: default_image_dir(),
+            image_url_prefix: default_image_url_prefix(),
+            external_base_url: None,
+            max_concurrency: default_max_concurrency(),
+            data_dir: default_data_dir(),
+            admins: Vec::new(),
+            bots: Vec::new(),
+            countdowns: Vec::new(),
+            command_pool: CommandPoolConfig::default(),
+            ai_tasks: AiTaskQueueConfig::default(),
+            chatroom_aliases: BTreeMap::new(),
+            persona: PersonaConfig::default(),
+            memory: MemoryStoreConfig::default(),
+            safe_mode: SafeModeConfig::default(),
+            chat_settings: ChatSettingsConfig::default(),
+            own_message_echo: OwnEchoPolicy::default(),
+            moderation: ModerationConfig::default(),
+            undo: UndoConfig::default(),
+            media: MediaConfig::default(),
+            history: HistoryConfig::default(),
+            watchdog: WatchdogConfig::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let explicit = path
+            .map(PathBuf::from)
+            .or_else(|| std::env::var("GEWE_BOT_CONFIG").ok().map(PathBuf::from));
+        // 未指定配置文件且默认文件不存在时，尝试只用环境变量启动（容器部署）
+        if explicit.is_none() && !Path::new(DEFAULT_CONFIG_PATH).exists() {
+            let vars: HashMap<String, String> = std::env::vars().collect();
+            if let Some(config) = Self::from_env_vars(&vars)? {
+                return Ok(config);
             }
         }
+        let path = explicit.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+        let body = std::fs::read_to_string(&path)
+            .with_context(|| format!("读取配置失败: {}", path.display()))?;
+
+        // 检测是否为 V2 配置（config_version = 2）
+        if is_v2_config(&body) {
+            let v2: AppConfigV2 = toml::from_str(&body)
+                .with_context(|| format!("解析 V2 配置失败: {}", path.display()))?;
+            let config = v2
+                .into_v1(&path)
+                .with_context(|| format!("转换 V2 配置失败: {}", path.display()))?;
+            return Ok(config);
+        }
+
+        let mut config: AppConfig =
+            toml::from_str(&body).with_context(|| format!("解析配置失败: {}", path.display()))?;
+        if config.listen_addr.is_empty() {
+            config.listen_addr = default_listen_addr();
+        }
+        if config.queue_size == 0 {
+            config.queue_size = default_queue_size();
+        }
+        if config.max_concurrency == 0 {
+            config.max_concurrency = default_max_concurrency();
+        }
+        Ok(config)
+    }
+
+    /// 仅由环境变量构建配置，未设置 `GEWE_BOT_0_APPID` 时返回 None
+    ///
+    /// - `GEWE_BOT_{n}_APPID` / `_TOKEN` / `_BASE_URL` / `_WEBHOOK_SECRET`：n 从 0 开始连续编号
+    /// - `GEWE_RULES_JSON`：V1 规则数组（JSON），应用到所有机器人
+    /// - `GEWE_LISTEN_ADDR`、`GEWE_DATA_DIR`、`GEWE_IMAGE_DIR`、`GEWE_EXTERNAL_BASE_URL`：可选
+    pub fn from_env_vars(vars: &HashMap<String, String>) -> Result<Option<Self>> {
+        let get = |key: &str| {
+            vars.get(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let rules: Vec<RuleConfig> = match get("GEWE_RULES_JSON") {
+            Some(json) => serde_json::from_str(&json).context("解析 GEWE_RULES_JSON 失败")?,
+            None => Vec::new(),
+        };
+
+        let mut bots = Vec::new();
+        while let Some(app_id) = get(&format!("{}{}_APPID", ENV_BOT_PREFIX, bots.len())) {
+            let i = bots.len();
+            let token = get(&format!("{}{}_TOKEN", ENV_BOT_PREFIX, i))
+                .with_context(|| format!("缺少环境变量 {}{}_TOKEN", ENV_BOT_PREFIX, i))?;
+            bots.push(BotConfig {
+                app_id,
+                token,
+                base_url: get(&format!("{}{}_BASE_URL", ENV_BOT_PREFIX, i))
+                    .unwrap_or_else(|| DEFAULT_GEWE_BASE_URL.to_string()),
+                webhook_secret: get(&format!("{}{}_WEBHOOK_SECRET", ENV_BOT_PREFIX, i)),
+                priority: None,
+                failover: None,
+                digest: None,
+                shadow: false,
+                rules: rules.clone(),
+            });
+        }
+        if bots.is_empty() {
+            return Ok(None);
+        }
+
+        let mut config = AppConfig {
+            bots,
+            external_base_url: get("GEWE_EXTERNAL_BASE_URL"),
+            ..Default::default()
+        };
+        if let Some(addr) = get("GEWE_LISTEN_ADDR") {
+            config.listen_addr = addr;
+        }
+        if let Some(dir) = get("GEWE_DATA_DIR") {
+            config.data_dir = dir;
+        }
+        if let Some(dir) = get("GEWE_IMAGE_DIR") {
+            config.image_dir = dir;
+        }
+        Ok(Some(config))
+    }
+
+    /// 读取 V2 配置文件（如已发布版本的备份），Prompt 等相对路径按 base_path 所在目录解析
+    pub fn load_v2_with_base(path: &Path, base_path: &Path) -> Result<Self> {
+        AppConfigV2::load_from_file(path)?
+            .into_v1(base_path)
+            .with_context(|| format!("转换 V2 配置失败: {}", path.display()))
+    }
+}
+
+/// 判定配置是否为 V2 结构
+fn is_v2_config(body: &str) -> bool {
+    toml::from_str::<toml::Value>(body)
+        .ok()
+        .and_then(|v| v.get("config_version").and_then(|x| x.as_integer()))
+        == Some(2)
+}
+
+// -------------------- V2 配置定义与转换 --------------------
+
+/// V2 配置根结构（公开用于 API）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppConfigV2 {
+    pub config_version: u8,
+    #[serde(default)]
+    pub server: ServerConfigV2,
+    #[serde(default)]
+    pub storage: StorageConfigV2,
+    #[serde(default)]
+    pub defaults: DefaultsV2,
+    #[serde(default)]
+    pub bots: Vec<BotConfigV2>,
+    #[serde(default)]
+    pub ai_profiles: Vec<AiProfileV2>,
+    #[serde(default)]
+    pub tools: Vec<ToolConfigV2>,
+    #[serde(default)]
+    pub rule_templates: Vec<RuleTemplateV2>,
+    #[serde(default)]
+    pub rule_instances: Vec<RuleInstanceV2>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub countdowns: Vec<CountdownConfig>,
+    /// 群名别名（群 ID → 显示名），用于日志与管理页面
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub chatroom_aliases: BTreeMap<String, String>,
+    /// `/persona` 人设切换命令
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<PersonaConfigV2>,
+}
+
+/// 人设切换配置，人设即 ai_profiles 中的 Profile
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PersonaConfigV2 {
+    /// 可使用 `/persona` 命令的管理员 wxid，未配置时沿用 `server.admins`
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// 可切换的 Profile id，留空时可切换全部 ai_profiles
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<String>,
+}
+
+/// 服务器配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerConfigV2 {
+    #[serde(default = "default_listen_addr")]
+    pub listen_addr: String,
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// 全局影子模式，机器人未单独配置 `shadow` 时沿用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<bool>,
+    /// 全局管理员 wxid，各功能未单独配置管理员时沿用
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<String>,
+    /// 外置命令的进程池
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_pool: Option<CommandPoolConfig>,
+    /// AI 任务队列
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ai_tasks: Option<AiTaskQueueConfig>,
+    /// AI 对话记忆的存储后端
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryStoreConfig>,
+    /// 异常流量时自动进入安全模式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safe_mode: Option<SafeModeConfig>,
+    /// 会话设置命令的管理员
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_settings: Option<ChatSettingsConfig>,
+    /// 自己发送的消息回显时的处理方式：skip（默认）、log、event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub own_message_echo: Option<OwnEchoPolicy>,
+    /// 群聊违规内容检测
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<ModerationConfig>,
+    /// `/undo` 的管理员
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undo: Option<UndoConfig>,
+    /// 媒体存储：本地目录、S3 兼容对象存储、WebDAV
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media: Option<MediaConfig>,
+    /// 会话消息记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<HistoryConfig>,
+    /// 机器人在线看护
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchdog: Option<WatchdogConfig>,
+}
+
+/// 存储配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageConfigV2 {
+    #[serde(default = "default_image_dir")]
+    pub image_dir: String,
+    #[serde(default = "default_image_url_prefix")]
+    pub image_url_prefix: String,
+    #[serde(default)]
+    pub external_base_url: Option<String>,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+}
+
+/// 默认配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DefaultsV2 {
+    #[serde(default)]
+    pub reply_mode: Option<ReplyMode>,
+    #[serde(default)]
+    pub log: Option<bool>,
+    #[serde(default)]
+    pub ai: Option<DefaultsAiV2>,
+}
+
+/// AI 默认配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DefaultsAiV2 {
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub require_mention: Option<bool>,
+}
+
+/// Bot 配置（V2）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BotConfigV2 {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub app_id: String,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub token_env: Option<String>,
+    pub base_url: String,
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+    #[serde(default)]
+    pub webhook_secret_env: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// 多机器人协同优先级（数值越小越优先）：同一群消息仅由规则命中且优先级最高的机器人响应；
+    /// 未配置则不参与协同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failover: Option<FailoverConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<DigestConfig>,
+    /// 影子模式：只记录本应发送的回复而不实际发送，用于在线上流量中验证配置改动；
+    /// 未配置时沿用 `server.shadow`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<bool>,
+}
+
+/// AI Profile 配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AiProfileV2 {
+    pub id: String,
+    #[serde(default)]
+    pub provider: Option<String>,
+    pub model: String,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub system_prompt_file: Option<String>,
+    #[serde(default)]
+    pub user_prefix: Option<String>,
+    #[serde(default)]
+    pub tool_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<SemanticCacheConfig>,
+    /// Prompt A/B 变体，实验名称即 profile id
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<PromptVariant>,
+    /// 模型灰度，如 `{ "gpt-4o-mini" = 90, "claude-sonnet" = 10 }`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub model_rollout: BTreeMap<String, u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<FeedbackConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured: Option<StructuredOutputConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_loop: Option<ToolLoopConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<ConversationMemoryConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_edit: Option<bool>,
+    /// 以语音消息发送回复
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_voice: Option<TtsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chars_per_message: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_guard: Option<PromptGuardConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure: Option<AzureOpenAiConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub openrouter: Option<OpenRouterConfig>,
+    /// 调用模型前先执行的工具 id，输出作为上下文附加到用户消息（如 ocr 识别收到的图片）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_tool: Option<String>,
+}
+
+/// 工具配置（V2）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolConfigV2 {
+    pub id: String,
+    #[serde(default)]
+    pub kind: Option<String>,
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub max_output: Option<usize>,
+    #[serde(default)]
+    pub pre_reply: Option<String>,
+    #[serde(default)]
+    pub post_reply: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// 可选的 parameters（JSON Schema），未配置时会补全 {"type":"object"}
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+    /// 后台执行：规则中作为异步命令，AI 调用时进入 AI 任务队列
+    #[serde(default, rename = "async", skip_serializing_if = "Option::is_none")]
+    pub run_async: Option<bool>,
+    /// program 为 http_request 时的访问策略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpPolicy>,
+    /// program 为 gemini_image / image_generate 时的服务商与默认参数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageToolConfig>,
+    /// program 为 ocr 时的识别服务配置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr: Option<OcrConfig>,
+    /// 使用说明（Markdown 或链接），从工具注册表安装时填充
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docs: Option<String>,
+    /// 运行所需的环境变量，缺失时在 Tools 页面提示
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_env: Vec<String>,
+    /// 安装来源（注册表 URL 或路径）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl ToolConfigV2 {
+    /// 当前进程中缺失的 required_env
+    pub fn missing_env(&self) -> Vec<&str> {
+        self.required_env
+            .iter()
+            .filter(|key| std::env::var_os(key.as_str()).is_none())
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// 规则模板（V2）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleTemplateV2 {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub kind: Option<RuleKind>,
+    #[serde(default)]
+    pub r#match: MatchConfigV2,
+    #[serde(default)]
+    pub action: TemplateActionV2,
+    #[serde(default)]
+    pub defaults: TemplateDefaultsV2,
+    /// `/help` 菜单中的功能说明
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// `/help` 菜单中的用法
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+    /// 命中后继续匹配优先级更低的规则，如记录全部消息的规则
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continue_matching: Option<bool>,
+}
+
+/// 模板默认配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateDefaultsV2 {
+    #[serde(default)]
+    pub require_mention: Option<bool>,
+}
+
+/// 匹配配置（V2）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MatchConfigV2 {
+    #[serde(default)]
+    pub equals: Option<String>,
+    #[serde(default)]
+    pub contains: Option<String>,
+    #[serde(default)]
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub any: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub msg_types: Vec<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub appmsg_types: Vec<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_exts: Vec<String>,
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emoji_md5: Vec<String>,
+    #[serde(default)]
+    pub emoji_animated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub url_domains: Vec<String>,
+    #[serde(default)]
+    pub geo_fence: Option<GeoFence>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intent: Option<IntentMatch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub time_ranges: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weekdays: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+}
+
+/// 模板动作配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateActionV2 {
+    #[serde(default)]
+    pub ai_profile: Option<String>,
+    #[serde(default)]
+    pub reply_mode: Option<ReplyMode>,
+    #[serde(default)]
+    pub log: Option<bool>,
+    #[serde(default)]
+    pub require_mention: Option<bool>,
+    #[serde(default)]
+    pub reply_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reply_sequence: Vec<ReplyPart>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_link: Option<LinkReplyAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_voice: Option<VoiceReplyAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub welcome: Option<WelcomeAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<ErrorPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_scope: Option<CooldownScope>,
+}
+
+/// 实例覆盖配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstanceOverridesV2 {
+    #[serde(default)]
+    pub require_mention: Option<bool>,
+    #[serde(default)]
+    pub reply_mode: Option<ReplyMode>,
+    #[serde(default)]
+    pub ai_profile: Option<String>,
+    #[serde(default)]
+    pub log: Option<bool>,
+    #[serde(default)]
+    pub reply_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continue_matching: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_secs: Option<u64>,
+}
+
+/// 规则实例（V2）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleInstanceV2 {
+    pub id: String,
+    pub template: String,
+    #[serde(default)]
+    pub channel: Option<String>, // private/group/both
+    #[serde(default)]
+    pub from: FromConfig,
+    #[serde(default)]
+    pub priority: Option<i32>,
+    #[serde(default)]
+    pub overrides: Option<InstanceOverridesV2>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+impl AppConfigV2 {
+    /// 从文件加载 V2 配置
+    #[allow(dead_code)]
+    pub fn load_from_file(path: &Path) -> Result<Self> {
+        let body = std::fs::read_to_string(path)
+            .with_context(|| format!("读取配置失败: {}", path.display()))?;
+        Self::parse(&body)
+    }
+
+    /// 从 TOML 字符串解析
+    pub fn parse(body: &str) -> Result<Self> {
+        toml::from_str(body).with_context(|| "解析 V2 配置失败")
+    }
+
+    /// 从 JSON 字符串解析
+    #[allow(dead_code)]
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).with_context(|| "解析 JSON 配置失败")
+    }
+
+    /// 序列化为 TOML 字符串
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).with_context(|| "序列化 TOML 失败")
+    }
+
+    /// 序列化为 JSON 字符串
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).with_context(|| "序列化 JSON 失败")
+    }
+
+    /// 校验配置，返回错误列表
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        // 检查 config_version
+        if self.config_version != 2 {
+            errors.push(format!(
+                "config_version 应为 2，当前为 {}",
+                self.config_version
+            ));
+        }
+
+        if let Some(ref pool) = self.server.command_pool {
+            for err in pool.validate() {
+                errors.push(format!("server.command_pool: {}", err));
+            }
+        }
+        if let Some(ref queue) = self.server.ai_tasks {
+            for err in queue.validate() {
+                errors.push(format!("server.ai_tasks: {}", err));
+            }
+        }
+        if let Some(ref safe_mode) = self.server.safe_mode {
+            for err in safe_mode.validate() {
+                errors.push(format!("server.safe_mode: {}", err));
+            }
+        }
+        if let Some(ref moderation) = self.server.moderation {
+            for err in moderation.validate() {
+                errors.push(format!("server.moderation: {}", err));
+            }
+        }
+        if let Some(ref media) = self.server.media {
+            for err in media.validate() {
+                errors.push(format!("server.media: {}", err));
+            }
+        }
+        if let Some(ref history) = self.server.history {
+            for err in history.validate() {
+                errors.push(format!("server.history: {}", err));
+            }
+        }
+        if let Some(ref watchdog) = self.server.watchdog {
+            for err in watchdog.validate() {
+                errors.push(format!("server.watchdog: {}", err));
+            }
+            for app_id in &watchdog.bots {
+                if !self.bots.iter().any(|b| &b.app_id == app_id) {
+                    errors.push(format!(
+                        "server.watchdog: bots 引用的机器人不存在: {}",
+                        app_id
+                    ));
+                }
+            }
+        }
+        for (id, name) in &self.chatroom_aliases {
+            if !id.ends_with("@chatroom") {
+                errors.push(format!(
+                    "chatroom_aliases: 群 ID 应以 @chatroom 结尾: {}",
+                    id
+                ));
+            } else if name.trim().is_empty() {
+                errors.push(format!("chatroom_aliases: 别名不能为空: {}", id));
+            }
+        }
+        if let Some(ref persona) = self.persona {
+            if persona
+                .admins
+                .iter()
+                .chain(&self.server.admins)
+                .all(|wxid| wxid.trim().is_empty())
+            {
+                errors.push("persona.admins 或 server.admins 至少需要一个管理员 wxid".to_string());
+            }
+            for id in &persona.profiles {
+                if !self.ai_profiles.iter().any(|p| &p.id == id) {
+                    errors.push(format!(
+                        "persona.profiles: 引用的 ai_profile 不存在: {}",
+                        id
+                    ));
+                }
+            }
+        }
+
+        // 检查 bots
+        let mut bot_ids = std::collections::HashSet::new();
+        for (i, bot) in self.bots.iter().enumerate() {
+            if bot.app_id.trim().is_empty() {
+                errors.push(format!("bots[{}]: app_id 不能为空", i));
+            }
+            if bot.base_url.trim().is_empty() {
+                errors.push(format!("bots[{}]: base_url 不能为空", i));
+            }
+            if bot.token.is_none() && bot.token_env.is_none() {
+                errors.push(format!("bots[{}]: token 或 token_env 必须设置其一", i));
+            }
+            let id = bot.id.clone().unwrap_or_else(|| bot.app_id.clone());
+            if !bot_ids.insert(id.clone()) {
+                errors.push(format!("bots[{}]: 重复的 id/app_id: {}", i, id));
+            }
+            if let Some(failover) = bot.failover.as_ref() {
+                if failover.standby == bot.app_id {
+                    errors.push(format!("bots[{}]: failover.standby 不能是自身", i));
+                } else if !self.bots.iter().any(|b| b.app_id == failover.standby) {
+                    errors.push(format!(
+                        "bots[{}]: failover.standby 引用的机器人不存在: {}",
+                        i, failover.standby
+                    ));
+                }
+            }
+            if let Some(digest) = bot.digest.as_ref() {
+                if let Err(e) = digest.schedule() {
+                    errors.push(format!("bots[{}]: digest.{}", i, e));
+                }
+                match digest.email.as_ref() {
+                    Some(email) => {
+                        if email.smtp_host.trim().is_empty() || email.from.trim().is_empty() {
+                            errors
+                                .push(format!("bots[{}]: digest.email 需要 smtp_host 与 from", i));
+                        }
+                        if email.to.is_empty() {
+                            errors.push(format!("bots[{}]: digest.email.to 不能为空", i));
+                        }
+                    }
+                    None if digest.chats.is_empty() => {
+                        errors.push(format!("bots[{}]: digest 需配置 chats 或 email", i));
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        // 检查 ai_profiles
+        let mut profile_ids = std::collections::HashSet::new();
+        for (i, profile) in self.ai_profiles.iter().enumerate() {
+            if profile.id.trim().is_empty() {
+                errors.push(format!("ai_profiles[{}]: id 不能为空", i));
+            }
+            if profile.model.trim().is_empty() {
+                errors.push(format!("ai_profiles[{}]: model 不能为空", i));
+            }
+            if !profile_ids.insert(profile.id.clone()) {
+                errors.push(format!("ai_profiles[{}]: 重复的 id: {}", i, profile.id));
+            }
+            // 检查引用的 tool_ids 是否存在
+            for tool_id in profile.tool_ids.iter().chain(profile.pre_tool.iter()) {
+                if !self.tools.iter().any(|t| &t.id == tool_id) {
+                    errors.push(format!("ai_profiles[{}]: 引用的工具不存在: {}", i, tool_id));
+                }
+            }
+            let mut variant_ids = std::collections::HashSet::new();
+            for variant in &profile.variants {
+                if variant.id.trim().is_empty() {
+                    errors.push(format!("ai_profiles[{}]: variants 的 id 不能为空", i));
+                }
+                if !variant_ids.insert(variant.id.as_str()) {
+                    errors.push(format!(
+                        "ai_profiles[{}]: 重复的 variant id: {}",
+                        i, variant.id
+                    ));
+                }
+            }
+            if !profile.variants.is_empty() && profile.variants.iter().all(|v| v.weight() == 0) {
+                errors.push(format!("ai_profiles[{}]: variants 的权重之和必须大于 0", i));
+            }
+            if let Some(budget) = profile.budget.as_ref() {
+                let costs = [budget.max_cost_per_message, budget.daily_cost];
+                if costs.iter().flatten().any(|c| c.is_nan() || *c <= 0.0) {
+                    errors.push(format!("ai_profiles[{}]: budget 的花费上限必须大于 0", i));
+                }
+                if costs.iter().any(Option::is_some) && budget.prices.is_empty() {
+                    errors.push(format!(
+                        "ai_profiles[{}]: budget 配置了花费上限，需同时配置 prices",
+                        i
+                    ));
+                }
+            }
+            if profile.model_rollout.keys().any(|m| m.trim().is_empty()) {
+                errors.push(format!(
+                    "ai_profiles[{}]: model_rollout 的模型名不能为空",
+                    i
+                ));
+            }
+            let rollout_total: u64 = profile.model_rollout.values().map(|&p| u64::from(p)).sum();
+            if rollout_total > 100 {
+                errors.push(format!(
+                    "ai_profiles[{}]: model_rollout 的百分比之和不能超过 100，当前为 {}",
+                    i, rollout_total
+                ));
+            }
+            if let Some(threshold) = profile.cache.as_ref().and_then(|c| c.threshold) {
+                if !(threshold > 0.0 && threshold <= 1.0) {
+                    errors.push(format!(
+                        "ai_profiles[{}]: cache.threshold 应在 (0, 1] 之间，当前为 {}",
+                        i, threshold
+                    ));
+                }
+            }
+            if let Some(structured) = profile.structured.as_ref() {
+                if profile.cache.is_some() {
+                    errors.push(format!(
+                        "ai_profiles[{}]: structured 与 cache 不能同时配置",
+                        i
+                    ));
+                }
+                if structured.schema.as_ref().is_some_and(|s| !s.is_object()) {
+                    errors.push(format!("ai_profiles[{}]: structured.schema 必须是对象", i));
+                }
+            }
+            if let Some(tool_loop) = profile.tool_loop.as_ref() {
+                if tool_loop
+                    .max_calls
+                    .is_some_and(|n| n == 0 || n > MAX_TOOL_CALLS)
+                {
+                    errors.push(format!(
+                        "ai_profiles[{}]: tool_loop.max_calls 应在 1 到 {} 之间",
+                        i, MAX_TOOL_CALLS
+                    ));
+                }
+                if tool_loop.max_identical_calls == Some(0) {
+                    errors.push(format!(
+                        "ai_profiles[{}]: tool_loop.max_identical_calls 不能为 0",
+                        i
+                    ));
+                }
+                if tool_loop.max_consecutive_calls == Some(0) {
+                    errors.push(format!(
+                        "ai_profiles[{}]: tool_loop.max_consecutive_calls 不能为 0",
+                        i
+                    ));
+                }
+            }
+            if let Some(memory) = profile.memory.as_ref() {
+                if memory
+                    .max_turns
+                    .is_some_and(|n| n == 0 || n > MAX_MEMORY_TURNS)
+                {
+                    errors.push(format!(
+                        "ai_profiles[{}]: memory.max_turns 应在 1 到 {} 之间",
+                        i, MAX_MEMORY_TURNS
+                    ));
+                }
+                if memory.ttl_secs == Some(0) {
+                    errors.push(format!("ai_profiles[{}]: memory.ttl_secs 不能为 0", i));
+                }
+            }
+            if profile.stream == Some(true) && profile.structured.is_some() {
+                errors.push(format!(
+                    "ai_profiles[{}]: stream 与 structured 不能同时配置",
+                    i
+                ));
+            }
+            if profile.stream_edit == Some(true) && profile.stream != Some(true) {
+                errors.push(format!(
+                    "ai_profiles[{}]: stream_edit 需要同时开启 stream",
+                    i
+                ));
+            }
+            if profile.max_chars_per_message == Some(0) {
+                errors.push(format!(
+                    "ai_profiles[{}]: max_chars_per_message 不能为 0",
+                    i
+                ));
+            }
+            errors.extend(
+                provider_preset_errors(profile)
+                    .into_iter()
+                    .map(|e| format!("ai_profiles[{}]: {}", i, e)),
+            );
+        }
+
+        // 检查 tools
+        let mut tool_ids = std::collections::HashSet::new();
+        for (i, tool) in self.tools.iter().enumerate() {
+            if tool.id.trim().is_empty() {
+                errors.push(format!("tools[{}]: id 不能为空", i));
+            }
+            if tool.program.trim().is_empty() {
+                errors.push(format!("tools[{}]: program 不能为空", i));
+            }
+            if !tool_ids.insert(tool.id.clone()) {
+                errors.push(format!("tools[{}]: 重复的 id: {}", i, tool.id));
+            }
+            if let Some(http) = tool.http.as_ref() {
+                let mut auth_ids = std::collections::HashSet::new();
+                for profile in &http.auth_profiles {
+                    if profile.id.trim().is_empty() {
+                        errors.push(format!("tools[{}]: http.auth_profiles 的 id 不能为空", i));
+                    } else if !auth_ids.insert(profile.id.as_str()) {
+                        errors.push(format!(
+                            "tools[{}]: 重复的 http.auth_profiles id: {}",
+                            i, profile.id
+                        ));
+                    }
+                    if profile.headers_env.is_empty() {
+                        errors.push(format!(
+                            "tools[{}]: http.auth_profiles[{}] 需配置 headers_env",
+                            i, profile.id
+                        ));
+                    }
+                }
+                if http.max_response_bytes == Some(0) {
+                    errors.push(format!("tools[{}]: http.max_response_bytes 必须大于 0", i));
+                }
+            }
+            if let Some(image) = tool.image.as_ref() {
+                if let Some(size) = image.size.as_deref() {
+                    if parse_image_size(size).is_none() {
+                        errors.push(format!(
+                            "tools[{}]: image.size 格式应为 宽x高，如 1024x1024",
+                            i
+                        ));
+                    }
+                }
+                if image.steps == Some(0) {
+                    errors.push(format!("tools[{}]: image.steps 必须大于 0", i));
+                }
+                if image
+                    .image_strength
+                    .is_some_and(|v| !(0.0..=1.0).contains(&v))
+                {
+                    errors.push(format!(
+                        "tools[{}]: image.image_strength 应在 0 到 1 之间",
+                        i
+                    ));
+                }
+            }
+        }
+
+        // 检查 rule_templates
+        let mut template_ids = std::collections::HashSet::new();
+        for (i, template) in self.rule_templates.iter().enumerate() {
+            if template.id.trim().is_empty() {
+                errors.push(format!("rule_templates[{}]: id 不能为空", i));
+            }
+            if !template_ids.insert(template.id.clone()) {
+                errors.push(format!("rule_templates[{}]: 重复的 id: {}", i, template.id));
+            }
+            if let Some(ref fence) = template.r#match.geo_fence {
+                if !(-90.0..=90.0).contains(&fence.lat) || !(-180.0..=180.0).contains(&fence.lng) {
+                    errors.push(format!("rule_templates[{}]: geo_fence 坐标超出范围", i));
+                }
+                if fence.radius_m <= 0.0 {
+                    errors.push(format!(
+                        "rule_templates[{}]: geo_fence.radius_m 必须大于 0",
+                        i
+                    ));
+                }
+            }
+            for (j, part) in template.action.reply_sequence.iter().enumerate() {
+                if let Some(err) = part.validate() {
+                    errors.push(format!(
+                        "rule_templates[{}]: reply_sequence[{}] {}",
+                        i, j, err
+                    ));
+                }
+            }
+            if let Some(ref link) = template.action.reply_link {
+                for err in link.validate() {
+                    errors.push(format!("rule_templates[{}]: reply_link {}", i, err));
+                }
+            }
+            if let Some(ref welcome) = template.action.welcome {
+                if template.kind != Some(RuleKind::MemberJoined) {
+                    errors.push(format!(
+                        "rule_templates[{}]: welcome 仅适用于 kind = \"member_joined\"",
+                        i
+                    ));
+                }
+                for err in welcome.validate() {
+                    errors.push(format!("rule_templates[{}]: welcome {}", i, err));
+                }
+            }
+            if let Some(ref policy) = template.action.on_error {
+                for err in policy.validate() {
+                    errors.push(format!("rule_templates[{}]: on_error {}", i, err));
+                }
+            }
+            if template.action.cooldown_scope.is_some() && template.action.cooldown_secs.is_none() {
+                errors.push(format!(
+                    "rule_templates[{}]: cooldown_scope 需要同时配置 cooldown_secs",
+                    i
+                ));
+            }
+            if let Some(ref intent) = template.r#match.intent {
+                if intent.examples.iter().all(|e| e.trim().is_empty()) {
+                    errors.push(format!("rule_templates[{}]: intent.examples 不能为空", i));
+                }
+                if let Some(threshold) = intent.threshold {
+                    if !(threshold > 0.0 && threshold <= 1.0) {
+                        errors.push(format!(
+                            "rule_templates[{}]: intent.threshold 应在 (0, 1] 之间，当前为 {}",
+                            i, threshold
+                        ));
+                    }
+                }
+            }
+            if let Some(ref language) = template.r#match.language {
+                let code = normalize_language_code(language);
+                if !SUPPORTED_LANGUAGES.contains(&code.as_str()) {
+                    errors.push(format!(
+                        "rule_templates[{}]: 不支持的 language: {}（可选 {}）",
+                        i,
+                        language,
+                        SUPPORTED_LANGUAGES.join("、")
+                    ));
+                }
+            }
+            if let Some(ref expr) = template.r#match.expr {
+                if let Err(err) = gewe_rules::Filter::parse(expr) {
+                    errors.push(format!("rule_templates[{}]: expr 无效: {}", i, err));
+                }
+            }
+            let m = &template.r#match;
+            if let Err(err) = ActiveWindow::parse(&m.time_ranges, &m.weekdays, m.cron.as_deref()) {
+                errors.push(format!("rule_templates[{}]: 生效时间无效: {}", i, err));
+            }
+            // 检查引用的 ai_profile 是否存在
+            if let Some(ref profile_id) = template.action.ai_profile {
+                if !profile_ids.contains(profile_id) {
+                    errors.push(format!(
+                        "rule_templates[{}]: 引用的 ai_profile 不存在: {}",
+                        i, profile_id
+                    ));
+                }
+            }
+        }
+
+        // 检查 rule_instances
+        let mut instance_ids = std::collections::HashSet::new();
+        for (i, instance) in self.rule_instances.iter().enumerate() {
+            if instance.id.trim().is_empty() {
+                errors.push(format!("rule_instances[{}]: id 不能为空", i));
+            }
+            if instance.template.trim().is_empty() {
+                errors.push(format!("rule_instances[{}]: template 不能为空", i));
+            }
+            if !template_ids.contains(&instance.template) {
+                errors.push(format!(
+                    "rule_instances[{}]: 引用的模板不存在: {}",
+                    i, instance.template
+                ));
+            }
+            if !instance_ids.insert(instance.id.clone()) {
+                errors.push(format!("rule_instances[{}]: 重复的 id: {}", i, instance.id));
+            }
+            // 检查 channel 值
+            if let Some(ref channel) = instance.channel {
+                if !["private", "group", "both"].contains(&channel.as_str()) {
+                    errors.push(format!(
+                        "rule_instances[{}]: channel 必须是 private/group/both，当前为: {}",
+                        i, channel
+                    ));
+                }
+            }
+            // 检查 overrides 中引用的 ai_profile
+            if let Some(ref overrides) = instance.overrides {
+                if let Some(ref profile_id) = overrides.ai_profile {
+                    if !profile_ids.contains(profile_id) {
+                        errors.push(format!(
+                            "rule_instances[{}].overrides: 引用的 ai_profile 不存在: {}",
+                            i, profile_id
+                        ));
+                    }
+                }
+            }
+        }
+
+        // 检查 countdowns
+        let mut countdown_ids = std::collections::HashSet::new();
+        for (i, countdown) in self.countdowns.iter().enumerate() {
+            if countdown.id.trim().is_empty() {
+                errors.push(format!("countdowns[{}]: id 不能为空", i));
+            }
+            if !countdown_ids.insert(countdown.id.clone()) {
+                errors.push(format!("countdowns[{}]: 重复的 id: {}", i, countdown.id));
+            }
+            if countdown.name.trim().is_empty() {
+                errors.push(format!("countdowns[{}]: name 不能为空", i));
+            }
+            if countdown.event_time().is_none() {
+                errors.push(format!(
+                    "countdowns[{}]: at 格式应为 YYYY-MM-DD 或 YYYY-MM-DD HH:MM，当前为: {}",
+                    i, countdown.at
+                ));
+            }
+            if let Err(e) = countdown.schedule() {
+                errors.push(format!("countdowns[{}]: {}", i, e));
+            }
+            if !self.bots.iter().any(|b| b.app_id == countdown.app_id) {
+                errors.push(format!(
+                    "countdowns[{}]: 引用的 bot 不存在: {}",
+                    i, countdown.app_id
+                ));
+            }
+            if countdown.targets.iter().all(|t| t.trim().is_empty()) {
+                errors.push(format!("countdowns[{}]: targets 不能为空", i));
+            }
+        }
+
+        errors
+    }
+
+    /// 配置中有效的定时计划（运营摘要与启用的倒计时），id 与任务表一致
+    pub fn schedule_entries(&self) -> Vec<ScheduleEntry> {
+        let digests = self.bots.iter().filter_map(|bot| {
+            let schedule = bot.digest.as_ref()?.schedule().ok()?;
+            Some(ScheduleEntry {
+                id: format!("digest:{}", bot.app_id),
+                app_id: bot.app_id.clone(),
+                schedule,
+            })
+        });
+        let countdowns = self
+            .countdowns
+            .iter()
+            .filter(|c| c.enabled != Some(false))
+            .filter_map(|c| {
+                Some(ScheduleEntry {
+                    id: format!("countdown:{}", c.id),
+                    app_id: c.app_id.clone(),
+                    schedule: c.schedule().ok()?,
+                })
+            });
+        digests.chain(countdowns).collect()
+    }
+
+    /// 将 V2 配置转换为 V1 运行时配置
+    fn into_v1(self, base_path: &std::path::Path) -> Result<AppConfig> {
+        // 构建映射
+        let ai_map: HashMap<String, AiProfileV2> = self
+            .ai_profiles
+            .into_iter()
+            .map(|p| (p.id.clone(), p))
+            .collect();
+        let tool_map: HashMap<String, ToolConfigV2> =
+            self.tools.into_iter().map(|t| (t.id.clone(), t)).collect();
+        let tmpl_map: HashMap<String, RuleTemplateV2> = self
+            .rule_templates
+            .into_iter()
+            .map(|t| (t.id.clone(), t))
+            .collect();
+
+        let mut bots = Vec::new();
+        for bot in self.bots {
+            let token = pick_credential("token", &bot.token, &bot.token_env)?;
+            let webhook_secret = pick_optional_credential(
+                "webhook_secret",
+                &bot.webhook_secret,
+                &bot.webhook_secret_env,
+            )?;
+
+            // 依据实例生成规则
+            let mut instances = self.rule_instances.clone();
+            // 优先级排序，小值在前
+            instances.sort_by_key(|i| i.priority.unwrap_or(0));
+
+            let mut rules = Vec::new();
+            for inst in instances {
+                if matches!(inst.enabled, Some(false)) {
+                    continue;
+                }
+                let tmpl = tmpl_map
+                    .get(&inst.template)
+                    .ok_or_else(|| anyhow::anyhow!("未找到规则模板: {}", inst.template))?;
+
+                let chat = match inst.channel.as_deref() {
+                    Some("group") => Some(ChatKind::Group),
+                    Some("private") => Some(ChatKind::Private),
+                    Some("both") | None => None,
+                    Some(other) => {
+                        return Err(anyhow::anyhow!("不支持的 channel: {}", other));
+                    }
+                };
+
+                let mut action = RuleAction::default();
+
+                // reply_mode 组合：实例覆盖 > 模板 > 全局 defaults
+                action.reply_mode = inst
+                    .overrides
+                    .as_ref()
+                    .and_then(|o| o.reply_mode.clone())
+                    .or_else(|| tmpl.action.reply_mode.clone())
+                    .or_else(|| self.defaults.reply_mode.clone());
+
+                // log 组合
+                action.log = inst
+                    .overrides
+                    .as_ref()
+                    .and_then(|o| o.log)
+                    .or(tmpl.action.log)
+                    .or(self.defaults.log);
+
+                // require_mention 组合：实例覆盖 > 模板 action > 模板 defaults > 全局 defaults.ai
+                action.require_mention = inst
+                    .overrides
+                    .as_ref()
+                    .and_then(|o| o.require_mention)
+                    .or(tmpl.action.require_mention)
+                    .or(tmpl.defaults.require_mention)
+                    .or_else(|| self.defaults.ai.as_ref().and_then(|a| a.require_mention));
+
+                // reply_text 组合
+                action.reply_text = inst
+                    .overrides
+                    .as_ref()
+                    .and_then(|o| o.reply_text.clone())
+                    .or_else(|| tmpl.action.reply_text.clone());
+                action.reply_sequence = tmpl.action.reply_sequence.clone();
+                action.reply_link = tmpl.action.reply_link.clone();
+                action.reply_voice = tmpl.action.reply_voice.clone();
+                action.welcome = tmpl.action.welcome.clone();
+                action.on_error = tmpl.action.on_error.clone();
+                // 冷却：实例覆盖 > 模板
+                action.cooldown_secs = inst
+                    .overrides
+                    .as_ref()
+                    .and_then(|o| o.cooldown_secs)
+                    .or(tmpl.action.cooldown_secs);
+                action.cooldown_scope = tmpl.action.cooldown_scope;
+
+                // AI 配置：实例覆盖 > 模板 action > 全局 defaults.ai.profile
+                if let Some(profile_id) = inst
+                    .overrides
+                    .as_ref()
+                    .and_then(|o| o.ai_profile.clone())
+                    .or_else(|| tmpl.action.ai_profile.clone())
+                {
+                    let ai_profile = ai_map
+                        .get(&profile_id)
+                        .ok_or_else(|| anyhow::anyhow!("未找到 AI Profile: {}", profile_id))?;
+                    action.ai = Some(build_ai_action(ai_profile, &tool_map, base_path)?);
+                }
+
+                let rule = RuleConfig {
+                    id: Some(inst.id.clone()),
+                    kind: tmpl.kind.clone().unwrap_or_default(),
+                    r#match: tmpl.r#match.to_v1(),
+                    from: inst.from.clone(),
+                    chat,
+                    action,
+                    // 菜单说明：实例覆盖 > 模板
+                    description: inst
+                        .overrides
+                        .as_ref()
+                        .and_then(|o| o.description.clone())
+                        .or_else(|| tmpl.description.clone()),
+                    usage: inst
+                        .overrides
+                        .as_ref()
+                        .and_then(|o| o.usage.clone())
+                        .or_else(|| tmpl.usage.clone()),
+                    priority: inst.priority,
+                    continue_matching: inst
+                        .overrides
+                        .as_ref()
+                        .and_then(|o| o.continue_matching)
+                        .or(tmpl.continue_matching),
+                };
+                rules.push(rule);
+            }
+
+            let bot_cfg = BotConfig {
+                app_id: bot.app_id,
+                token,
+                base_url: bot.base_url,
+                webhook_secret,
+                priority: bot.priority,
+                failover: bot.failover,
+                digest: bot.digest,
+                shadow: bot.shadow.or(self.server.shadow).unwrap_or(false),
+                rules,
+            };
+            bots.push(bot_cfg);
+        }
+
+        let persona = match self.persona {
+            Some(persona) => {
+                let mut profiles = BTreeMap::new();
+                for (id, profile) in &ai_map {
+                    if persona.profiles.is_empty() || persona.profiles.contains(id) {
+                        profiles
+                            .insert(id.clone(), build_ai_action(profile, &tool_map, base_path)?);
+                    }
+                }
+                PersonaConfig {
+                    admins: persona.admins,
+                    profiles,
+                }
+            }
+            None => PersonaConfig::default(),
+        };
+
+        Ok(AppConfig {
+            listen_addr: self.server.listen_addr,
+            queue_size: self.server.queue_size,
+            image_dir: self.storage.image_dir,
+            image_url_prefix: self.storage.image_url_prefix,
+            external_base_url: self.storage.external_base_url,
+            max_concurrency: default_max_concurrency(),
+            data_dir: self.storage.data_dir,
+            admins: self.server.admins,
+            bots,
+            countdowns: self.countdowns,
+            command_pool: self.server.command_pool.unwrap_or_default(),
+            ai_tasks: self.server.ai_tasks.unwrap_or_default(),
+            chatroom_aliases: self.chatroom_aliases,
+            persona,
+            memory: self.server.memory.unwrap_or_default(),
+            safe_mode: self.server.safe_mode.unwrap_or_default(),
+            chat_settings: self.server.chat_settings.unwrap_or_default(),
+            own_message_echo: self.server.own_message_echo.unwrap_or_default(),
+            moderation: self.server.moderation.unwrap_or_default(),
+            undo: self.server.undo.unwrap_or_default(),
+            media: self.server.media.unwrap_or_default(),
+            history: self.server.history.unwrap_or_default(),
+            watchdog: self.server.watchdog.unwrap_or_default(),
+        })
+    }
+}
+
+impl MatchConfigV2 {
+    fn to_v1(&self) -> MatchConfig {
+        MatchConfig {
+            equals: self.equals.clone(),
+            contains: self.contains.clone(),
+            regex: self.regex.clone(),
+            msg_types: self.msg_types.clone(),
+            appmsg_types: self.appmsg_types.clone(),
+            file_exts: self.file_exts.clone(),
+            min_size: self.min_size,
+            max_size: self.max_size,
+            emoji_md5: self.emoji_md5.clone(),
+            emoji_animated: self.emoji_animated,
+            url_domains: self.url_domains.clone(),
+            geo_fence: self.geo_fence.clone(),
+            intent: self.intent.clone(),
+            language: self.language.clone(),
+            expr: self.expr.clone(),
+            time_ranges: self.time_ranges.clone(),
+            weekdays: self.weekdays.clone(),
+            cron: self.cron.clone(),
+        }
+    }
+}
+
+fn pick_credential(name: &str, value: &Option<String>, env_key: &Option<String>) -> Result<String> {
+    if let Some(v) = value.clone() {
+        if !v.trim().is_empty() {
+            return Ok(v);
+        }
+    }
+    if let Some(env) = env_key.as_deref() {
+        if let Ok(v) = std::env::var(env) {
+            if !v.trim().is_empty() {
+                return Ok(v);
+            }
+        }
+        return Err(anyhow::anyhow!("未找到 {}，请设置环境变量 {}", name, env));
+    }
+    Err(anyhow::anyhow!("未找到 {}，请在配置或环境变量中设置", name))
+}
+
+fn pick_optional_credential(
+    _name: &str,
+    value: &Option<String>,
+    env_key: &Option<String>,
+) -> Result<Option<String>> {
+    if let Some(v) = value.clone() {
+        if !v.trim().is_empty() {
+            return Ok(Some(v));
+        }
+    }
+    if let Some(env) = env_key.as_deref() {
+        if let Ok(v) = std::env::var(env) {
+            if !v.trim().is_empty() {
+                return Ok(Some(v));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn build_ai_action(
+    profile: &AiProfileV2,
+    tool_map: &HashMap<String, ToolConfigV2>,
+    base_path: &std::path::Path,
+) -> Result<AiAction> {
+    let system_prompt = resolve_system_prompt(
+        &profile.system_prompt,
+        &profile.system_prompt_file,
+        base_path,
+    )?;
+
+    let mut variants = Vec::new();
+    for variant in &profile.variants {
+        variants.push(PromptVariant {
+            system_prompt: resolve_system_prompt(
+                &variant.system_prompt,
+                &variant.system_prompt_file,
+                base_path,
+            )?,
+            system_prompt_file: None,
+            ..variant.clone()
+        });
+    }
+
+    let lookup_tool = |tool_id: &String| {
+        tool_map
+            .get(tool_id)
+            .ok_or_else(|| anyhow::anyhow!("AI Profile 引用的工具不存在: {}", tool_id))
+    };
+
+    let mut tools = Vec::new();
+    for tool_id in &profile.tool_ids {
+        let tool = lookup_tool(tool_id)?;
+        let cmd = tool_command(tool);
+        tools.push(AiTool {
+            name: tool.id.clone(),
+            description: tool.description.clone(),
+            parameters: Some(
+                tool.parameters
+                    .clone()
+                    .unwrap_or_else(|| json!({"type": "object"})),
+            ),
+            command: Some(cmd),
+        });
+    }
+
+    Ok(AiAction {
+        provider: profile.provider.clone(),
+        model: profile.model.clone(),
+        api_key: profile.api_key.clone(),
+        api_key_env: profile.api_key_env.clone(),
+        base_url: profile.base_url.clone(),
+        system_prompt,
+        user_prefix: profile.user_prefix.clone(),
+        command: profile
+            .pre_tool
+            .as_ref()
+            .map(lookup_tool)
+            .transpose()?
+            .map(tool_command),
+        max_command_output: None,
+        temperature: None,
+        max_tokens: None,
+        response_format: None,
+        tools,
+        max_retries: None,
+        retry_delay_ms: None,
+        cache: profile.cache.clone(),
+        experiment: (!variants.is_empty()).then(|| profile.id.clone()),
+        variants,
+        model_rollout: profile.model_rollout.clone(),
+        feedback: profile.feedback.clone(),
+        budget: profile.budget.clone(),
+        structured: profile.structured.clone(),
+        tool_loop: profile.tool_loop.clone(),
+        memory: profile.memory.clone(),
+        stream: profile.stream,
+        stream_edit: profile.stream_edit,
+        reply_voice: profile.reply_voice.clone(),
+        max_chars_per_message: profile.max_chars_per_message,
+        prompt_guard: profile.prompt_guard.clone(),
+        azure: profile.azure.clone(),
+        openrouter: profile.openrouter.clone(),
+    })
+}
+
+/// 校验 azure-openai / openrouter 预设所需的额外字段
+fn provider_preset_errors(profile: &AiProfileV2) -> Vec<String> {
+    let mut errors = Vec::new();
+    let provider = profile
+        .provider
+        .as_deref()
+        .map(|p| p.trim().to_ascii_lowercase());
+    let is_azure = matches!(provider.as_deref(), Some("azure-openai" | "azure"));
+    let is_openrouter = provider.as_deref() == Some("openrouter");
+
+    if profile.azure.is_some() && !is_azure {
+        errors.push("azure 仅适用于 provider = \"azure-openai\"".to_string());
+    }
+    if profile.openrouter.is_some() && !is_openrouter {
+        errors.push("openrouter 仅适用于 provider = \"openrouter\"".to_string());
+    }
+
+    if is_azure {
+        match profile.base_url.as_deref().map(str::trim) {
+            None | Some("") => errors.push(
+                "azure-openai 需要配置 base_url 为资源终结点，如 https://my-resource.openai.azure.com"
+                    .to_string(),
+            ),
+            Some(url) if !url.starts_with("https://") => {
+                errors.push(format!("azure-openai 的 base_url 必须以 https:// 开头: {}", url))
+            }
+            _ => {}
+        }
+        let azure = profile.azure.clone().unwrap_or_default();
+        if azure
+            .deployment
+            .as_deref()
+            .is_some_and(|d| d.trim().is_empty() || d.contains('/'))
+        {
+            errors.push("azure.deployment 不能为空且不能包含 /".to_string());
+        }
+        if let Some(version) = azure.api_version.as_deref() {
+            if !is_azure_api_version(version) {
+                errors.push(format!(
+                    "azure.api_version 格式应为 YYYY-MM-DD 或 YYYY-MM-DD-preview，当前为 {}",
+                    version
+                ));
+            }
+        }
+    }
+
+    if is_openrouter {
+        if !profile.model.trim().is_empty() && !profile.model.contains('/') {
+            errors.push(format!(
+                "openrouter 的 model 应为 厂商/模型 格式，如 openai/gpt-4o，当前为 {}",
+                profile.model
+            ));
+        }
+        let openrouter = profile.openrouter.clone().unwrap_or_default();
+        if let Some(site_url) = openrouter.site_url.as_deref() {
+            if !site_url.starts_with("http://") && !site_url.starts_with("https://") {
+                errors.push(format!(
+                    "openrouter.site_url 必须以 http:// 或 https:// 开头: {}",
+                    site_url
+                ));
+            }
+        }
+        // app_name 作为请求头发送，只能包含可见 ASCII 字符
+        if let Some(app_name) = openrouter.app_name.as_deref() {
+            if app_name.is_empty() || !app_name.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+                errors.push(format!(
+                    "openrouter.app_name 只能包含英文字母、数字与常见符号: {}",
+                    app_name
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// 判断是否为 Azure OpenAI 的 API 版本号，如 2024-10-21、2025-01-01-preview
+fn is_azure_api_version(version: &str) -> bool {
+    let date = version.strip_suffix("-preview").unwrap_or(version);
+    let parts: Vec<&str> = date.split('-').collect();
+    parts.len() == 3
+        && [4, 2, 2]
+            .iter()
+            .zip(&parts)
+            .all(|(len, part)| part.len() == *len && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// V2 工具配置转换为命令动作
+fn tool_command(tool: &ToolConfigV2) -> CommandAction {
+    CommandAction {
+        program: tool.program.clone(),
+        args: tool.args.clone(),
+        timeout_secs: tool.timeout_secs,
+        max_output: tool.max_output,
+        pre_reply: tool.pre_reply.clone(),
+        post_reply: tool.post_reply.clone(),
+        run_async: tool.run_async.unwrap_or(false),
+        http: tool.http.clone(),
+        image: tool.image.clone(),
+        ocr: tool.ocr.clone(),
+    }
+}
+
+/// system_prompt 优先，否则读取 system_prompt_file（相对路径基于配置文件所在目录）
+pub(crate) fn resolve_system_prompt(
+    prompt: &Option<String>,
+    file: &Option<String>,
+    base_path: &std::path::Path,
+) -> Result<Option<String>> {
+    if prompt.is_some() {
+        return Ok(prompt.clone());
+    }
+    let Some(file) = file else {
+        return Ok(None);
+    };
+    let abs = if PathBuf::from(file).is_absolute() {
+        PathBuf::from(file)
+    } else {
+        base_path.parent().unwrap_or(Path::new(".")).join(file)
+    };
+    let content = std::fs::read_to_string(&abs)
+        .with_context(|| format!("读取 system_prompt_file 失败: {}", abs.display()))?;
+    Ok(Some(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_kind_serde() {
+        // 测试 RuleKind 序列化和反序列化
+        let kind = RuleKind::Text;
+        let json = serde_json::to_string(&kind).unwrap();
+        assert_eq!(json, r#""text""#);
+
+        let deserialized: RuleKind = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, RuleKind::Text);
+
+        // 测试默认值
+        let default_kind = RuleKind::default();
+        assert_eq!(default_kind, RuleKind::Any);
+    }
+
+    #[test]
+    fn test_reply_mode_serde() {
+        // 测试 ReplyMode 序列化和反序列化
+        let mode = ReplyMode::Quote;
+        let json = serde_json::to_string(&mode).unwrap();
+        assert_eq!(json, r#""quote""#);
+
+        let deserialized: ReplyMode = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, ReplyMode::Quote);
+
+        // 测试默认值
+        let default_mode = ReplyMode::default();
+        assert_eq!(default_mode, ReplyMode::None);
+    }
+
+    #[test]
+    fn test_chat_kind_serde() {
+        // 测试 ChatKind 序列化和反序列化
+        let kind = ChatKind::Group;
+        let json = serde_json::to_string(&kind).unwrap();
+        assert_eq!(json, r#""group""#);
+
+        let deserialized: ChatKind = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, ChatKind::Group);
+    }
+
+    #[test]
+    fn test_from_config_serde() {
+        // 测试 FromConfig 序列化和反序列化
+        let config = FromConfig {
+            nick: Some("test_nick".to_string()),
+            wxid: Some("test_wxid".to_string()),
+        };
+
+        let json = serde_json::to_string(&config).unwrap();
+        let deserialized: FromConfig = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(deserialized.nick, Some("test_nick".to_string()));
+        assert_eq!(deserialized.wxid, Some("test_wxid".to_string()));
+    }
+
+    #[test]
+    fn test_match_config_deserialize() {
+        // 测试 MatchConfig 反序列化
+        let toml_str = r#"
+            equals = "hello"
+            contains = "world"
+            regex = "^test.*"
+        "#;
+
+        let config: MatchConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.equals, Some("hello".to_string()));
+        assert_eq!(config.contains, Some("world".to_string()));
+        assert_eq!(config.regex, Some("^test.*".to_string()));
+    }
+
+    #[test]
+    fn test_command_action_deserialize() {
+        // 测试 CommandAction 反序列化
+        let toml_str = r#"
+            program = "echo"
+            args = ["hello", "world"]
+            timeout_secs = 30
+            max_output = 1024
+            pre_reply = "Processing..."
+            post_reply = "Done!"
+        "#;
+
+        let action: CommandAction = toml::from_str(toml_str).unwrap();
+        assert_eq!(action.program, "echo");
+        assert_eq!(action.args, vec!["hello", "world"]);
+        assert_eq!(action.timeout_secs, Some(30));
+        assert_eq!(action.max_output, Some(1024));
+        assert_eq!(action.pre_reply, Some("Processing...".to_string()));
+        assert_eq!(action.post_reply, Some("Done!".to_string()));
+    }
+
+    #[test]
+    fn test_app_config_default() {
+        // 测试 AppConfig 默认值
+        let config = AppConfig::default();
+        assert_eq!(config.listen_addr, "0.0.0.0:3000");
+        assert_eq!(config.queue_size, 2048);
+        assert_eq!(config.image_dir, "data/images");
+        assert_eq!(config.image_url_prefix, "/images");
+        assert_eq!(config.max_concurrency, 8);
+        assert!(config.bots.is_empty());
+    }
+
+    #[test]
+    fn test_is_v2_config() {
+        // 测试 V2 配置检测
+        let v2_toml = r#"
+            config_version = 2
+
+            [server]
+            listen_addr = "0.0.0.0:3000"
+        "#;
+
+        assert!(is_v2_config(v2_toml));
+
+        let v1_toml = r#"
+            listen_addr = "0.0.0.0:3000"
+
+            [[bots]]
+            app_id = "test"
+        "#;
+
+        assert!(!is_v2_config(v1_toml));
+    }
+
+    #[test]
+    fn test_app_config_v2_parse() {
+        // 测试 V2 配置解析
+        let toml_str = r#"
+            config_version = 2
+
+            [server]
+            listen_addr = "0.0.0.0:3000"
+            queue_size = 1024
+
+            [storage]
+            image_dir = "data/images"
+            image_url_prefix = "/images"
+        "#;
+
+        let config = AppConfigV2::parse(toml_str).unwrap();
+        assert_eq!(config.config_version, 2);
+        assert_eq!(config.server.listen_addr, "0.0.0.0:3000");
+        assert_eq!(config.server.queue_size, 1024);
+        assert_eq!(config.storage.image_dir, "data/images");
+    }
+
+    #[test]
+    fn test_app_config_v2_to_toml() {
+        // 测试 V2 配置序列化为 TOML
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2 {
+                listen_addr: "0.0.0.0:3000".to_string(),
+                queue_size: 2048,
+                shadow: None,
+                admins: Vec::new(),
+                command_pool: None,
+                ai_tasks: None,
+                memory: None,
+                safe_mode: None,
+                chat_settings: None,
+                own_message_echo: None,
+                moderation: None,
+                undo: None,
+                media: None,
+                history: None,
+                watchdog: None,
+            },
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let toml = config.to_toml().unwrap();
+        assert!(toml.contains("config_version = 2"));
+        assert!(toml.contains("listen_addr"));
+    }
+
+    #[test]
+    fn test_app_config_v2_validate_empty() {
+        // 测试空配置验证
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let errors = config.validate();
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_app_config_v2_validate_wrong_version() {
+        // 测试错误的版本号
+        let config = AppConfigV2 {
+            config_version: 1,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors[0].contains("config_version"));
+    }
+
+    #[test]
+    fn test_app_config_v2_validate_duplicate_bot_ids() {
+        // 测试重复的 bot id
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![
+                BotConfigV2 {
+                    id: Some("bot1".to_string()),
+                    app_id: "app1".to_string(),
+                    token: Some("token1".to_string()),
+                    base_url: "http://localhost".to_string(),
+                    ..Default::default()
+                },
+                BotConfigV2 {
+                    id: Some("bot1".to_string()),
+                    app_id: "app2".to_string(),
+                    token: Some("token2".to_string()),
+                    base_url: "http://localhost".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("重复的 id")));
+    }
+
+    #[test]
+    fn test_app_config_v2_validate_missing_token() {
+        // 测试缺少 token
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![BotConfigV2 {
+                app_id: "app1".to_string(),
+                token: None,
+                token_env: None,
+                base_url: "http://localhost".to_string(),
+                ..Default::default()
+            }],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("token")));
+    }
+
+    #[test]
+    fn test_pick_credential_direct() {
+        // 测试直接配置凭证
+        let result = pick_credential(
+            "test",
+            &Some("direct_value".to_string()),
+            &Some("ENV_VAR".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(result, "direct_value");
+    }
+
+    #[test]
+    fn test_pick_credential_missing() {
+        // 测试缺少凭证
+        let result = pick_credential("test", &None, &None);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("未找到"));
+    }
+
+    #[test]
+    fn test_pick_optional_credential() {
+        // 测试可选凭证
+        let result = pick_optional_credential("test", &Some("value".to_string()), &None).unwrap();
+
+        assert_eq!(result, Some("value".to_string()));
+
+        let result = pick_optional_credential("test", &None, &None).unwrap();
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_match_config_v2_to_v1() {
+        // 测试 MatchConfigV2 转换为 V1
+        let v2 = MatchConfigV2 {
+            equals: Some("test".to_string()),
+            contains: Some("hello".to_string()),
+            regex: Some("^world".to_string()),
+            any: Some(true),
+            ..Default::default()
+        };
+
+        let v1 = v2.to_v1();
+        assert_eq!(v1.equals, Some("test".to_string()));
+        assert_eq!(v1.contains, Some("hello".to_string()));
+        assert_eq!(v1.regex, Some("^world".to_string()));
+    }
+
+    #[test]
+    fn test_match_config_media_filters() {
+        // 测试媒体类过滤条件的反序列化与 V2 转换
+        let toml_str = r#"
+            msg_types = [49]
+            appmsg_types = [6]
+            file_exts = ["pdf", ".ZIP"]
+            min_size = 10485760
+        "#;
+
+        let v2: MatchConfigV2 = toml::from_str(toml_str).unwrap();
+        let v1 = v2.to_v1();
+        assert_eq!(v1.msg_types, vec![49]);
+        assert_eq!(v1.appmsg_types, vec![6]);
+        assert_eq!(v1.file_exts, vec!["pdf", ".ZIP"]);
+        assert_eq!(v1.min_size, Some(10 * 1024 * 1024));
+        assert_eq!(v1.max_size, None);
+
+        // 未配置的过滤条件不应出现在序列化结果中
+        let toml = toml::to_string(&MatchConfigV2::default()).unwrap();
+        assert!(!toml.contains("msg_types"));
+        assert!(!toml.contains("file_exts"));
+    }
+
+    #[test]
+    fn test_default_functions() {
+        // 测试默认值函数
+        assert_eq!(default_listen_addr(), "0.0.0.0:3000");
+        assert_eq!(default_queue_size(), 2048);
+        assert_eq!(default_image_dir(), "data/images");
+        assert_eq!(default_image_url_prefix(), "/images");
+        assert_eq!(default_max_concurrency(), 8);
+    }
+
+    #[test]
+    fn test_app_config_load_v1() {
+        // 测试加载 V1 配置
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+listen_addr = "127.0.0.1:8080"
+queue_size = 1024
+image_dir = "test/images"
+image_url_prefix = "/test-images"
+external_base_url = "https://example.com"
+max_concurrency = 4
+
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+webhook_secret = "secret123"
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
+
+        let config = AppConfig::load(Some(tmpfile.path().to_str().unwrap())).unwrap();
+        assert_eq!(config.listen_addr, "127.0.0.1:8080");
+        assert_eq!(config.queue_size, 1024);
+        assert_eq!(config.image_dir, "test/images");
+        assert_eq!(config.image_url_prefix, "/test-images");
+        assert_eq!(
+            config.external_base_url,
+            Some("https://example.com".to_string())
+        );
+        assert_eq!(config.max_concurrency, 4);
+        assert_eq!(config.bots.len(), 1);
+        assert_eq!(config.bots[0].app_id, "test_app");
+        assert_eq!(config.bots[0].token, "test_token");
+    }
+
+    #[test]
+    fn test_app_config_load_with_defaults() {
+        // 测试加载配置时应用默认值
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+listen_addr = ""
+queue_size = 0
+max_concurrency = 0
 
-        errors
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
+
+        let config = AppConfig::load(Some(tmpfile.path().to_str().unwrap())).unwrap();
+        assert_eq!(config.listen_addr, "0.0.0.0:3000");
+        assert_eq!(config.queue_size, 2048);
+        assert_eq!(config.max_concurrency, 8);
     }
 
-    /// 将 V2 配置转换为 V1 运行时配置
-    fn into_v1(self, base_path: &std::path::Path) -> Result<AppConfig> {
-        // 构建映射
-        let ai_map: HashMap<String, AiProfileV2> = self
-            .ai_profiles
-            .into_iter()
-            .map(|p| (p.id.clone(), p))
-            .collect();
-        let tool_map: HashMap<String, ToolConfigV2> =
-            self.tools.into_iter().map(|t| (t.id.clone(), t)).collect();
-        let tmpl_map: HashMap<String, RuleTemplateV2> = self
-            .rule_templates
-            .into_iter()
-            .map(|t| (t.id.clone(), t))
-            .collect();
+    #[test]
+    fn test_app_config_load_v2() {
+        // 测试加载 V2 配置
+        use std::io::Write;
+        use tempfile::NamedTempFile;
 
-        let mut bots = Vec::new();
-        for bot in self.bots {
-            let token = pick_credential("token", &bot.token, &bot.token_env)?;
-            let webhook_secret = pick_optional_credential(
-                "webhook_secret",
-                &bot.webhook_secret,
-                &bot.webhook_secret_env,
-            )?;
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+config_version = 2
 
-            // 依据实例生成规则
-            let mut instances = self.rule_instances.clone();
-            // 优先级排序，小值在前
-            instances.sort_by_key(|i| i.priority.unwrap_or(0));
+[server]
+listen_addr = "0.0.0.0:8080"
+queue_size = 512
 
-            let mut rules = Vec::new();
-            for inst in instances {
-                if matches!(inst.enabled, Some(false)) {
-                    continue;
-                }
-                let tmpl = tmpl_map
-                    .get(&inst.template)
-                    .ok_or_else(|| anyhow::anyhow!("未找到规则模板: {}", inst.template))?;
+[storage]
+image_dir = "v2/images"
+image_url_prefix = "/v2-images"
 
-                let chat = match inst.channel.as_deref() {
-                    Some("group") => Some(ChatKind::Group),
-                    Some("private") => Some(ChatKind::Private),
-                    Some("both") | None => None,
-                    Some(other) => {
-                        return Err(anyhow::anyhow!("不支持的 channel: {}", other));
-                    }
-                };
+[[bots]]
+app_id = "v2_app"
+token = "v2_token"
+base_url = "https://v2.example.com"
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
 
-                let mut action = RuleAction::default();
+        let config = AppConfig::load(Some(tmpfile.path().to_str().unwrap())).unwrap();
+        assert_eq!(config.listen_addr, "0.0.0.0:8080");
+        assert_eq!(config.queue_size, 512);
+        assert_eq!(config.image_dir, "v2/images");
+        assert_eq!(config.image_url_prefix, "/v2-images");
+    }
 
-                // reply_mode 组合：实例覆盖 > 模板 > 全局 defaults
-                action.reply_mode = inst
-                    .overrides
-                    .as_ref()
-                    .and_then(|o| o.reply_mode.clone())
-                    .or_else(|| tmpl.action.reply_mode.clone())
-                    .or_else(|| self.defaults.reply_mode.clone());
+    #[test]
+    fn test_app_config_load_nonexistent() {
+        // 测试加载不存在的配置文件
+        let result = AppConfig::load(Some("/nonexistent/path/config.toml"));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("读取配置失败"));
+    }
 
-                // log 组合
-                action.log = inst
-                    .overrides
-                    .as_ref()
-                    .and_then(|o| o.log)
-                    .or(tmpl.action.log)
-                    .or(self.defaults.log);
+    #[test]
+    fn test_app_config_load_invalid_toml() {
+        // 测试加载无效的 TOML 配置
+        use std::io::Write;
+        use tempfile::NamedTempFile;
 
-                // require_mention 组合：实例覆盖 > 模板 action > 模板 defaults > 全局 defaults.ai
-                action.require_mention = inst
-                    .overrides
-                    .as_ref()
-                    .and_then(|o| o.require_mention)
-                    .or(tmpl.action.require_mention)
-                    .or(tmpl.defaults.require_mention)
-                    .or_else(|| self.defaults.ai.as_ref().and_then(|a| a.require_mention));
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        tmpfile.write_all(b"invalid toml content {{{").unwrap();
+        tmpfile.flush().unwrap();
 
-                // reply_text 组合
-                action.reply_text = inst
-                    .overrides
-                    .as_ref()
-                    .and_then(|o| o.reply_text.clone())
-                    .or_else(|| tmpl.action.reply_text.clone());
+        let result = AppConfig::load(Some(tmpfile.path().to_str().unwrap()));
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("解析配置失败"));
+    }
 
-                // AI 配置：实例覆盖 > 模板 action > 全局 defaults.ai.profile
-                if let Some(profile_id) = inst
-                    .overrides
-                    .as_ref()
-                    .and_then(|o| o.ai_profile.clone())
-                    .or_else(|| tmpl.action.ai_profile.clone())
-                {
-                    let ai_profile = ai_map
-                        .get(&profile_id)
-                        .ok_or_else(|| anyhow::anyhow!("未找到 AI Profile: {}", profile_id))?;
-                    action.ai = Some(build_ai_action(ai_profile, &tool_map, base_path)?);
-                }
+    #[test]
+    fn test_app_config_v2_from_json() {
+        // 测试从 JSON 解析 V2 配置
+        let json = r#"{
+            "config_version": 2,
+            "server": {
+                "listen_addr": "0.0.0.0:3000",
+                "queue_size": 2048
+            },
+            "storage": {
+                "image_dir": "data/images",
+                "image_url_prefix": "/images",
+                "external_base_url": null
+            },
+            "defaults": {},
+            "bots": [],
+            "ai_profiles": [],
+            "tools": [],
+            "rule_templates": [],
+            "rule_instances": []
+        }"#;
 
-                let rule = RuleConfig {
-                    kind: tmpl.kind.clone().unwrap_or_default(),
-                    r#match: tmpl.r#match.to_v1(),
-                    from: inst.from.clone(),
-                    chat,
-                    action,
-                };
-                rules.push(rule);
-            }
+        let config = AppConfigV2::from_json(json).unwrap();
+        assert_eq!(config.config_version, 2);
+        assert_eq!(config.server.listen_addr, "0.0.0.0:3000");
+    }
 
-            let bot_cfg = BotConfig {
-                app_id: bot.app_id,
-                token,
-                base_url: bot.base_url,
-                webhook_secret,
-                rules,
-            };
-            bots.push(bot_cfg);
-        }
+    #[test]
+    fn test_app_config_v2_to_json() {
+        // 测试 V2 配置序列化为 JSON
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
 
-        Ok(AppConfig {
-            listen_addr: self.server.listen_addr,
-            queue_size: self.server.queue_size,
-            image_dir: self.storage.image_dir,
-            image_url_prefix: self.storage.image_url_prefix,
-            external_base_url: self.storage.external_base_url,
-            max_concurrency: default_max_concurrency(),
-            bots,
-        })
+        let json = config.to_json().unwrap();
+        assert!(json.contains("\"config_version\": 2"));
+        assert!(json.contains("\"listen_addr\""));
     }
-}
 
-impl MatchConfigV2 {
-    fn to_v1(&self) -> MatchConfig {
-        MatchConfig {
-            equals: self.equals.clone(),
-            contains: self.contains.clone(),
-            regex: self.regex.clone(),
-        }
+    #[test]
+    fn test_app_config_v2_validate_empty_bot_fields() {
+        // 测试验证空的 bot 字段
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![BotConfigV2 {
+                app_id: "".to_string(),
+                base_url: "".to_string(),
+                token: Some("token".to_string()),
+                ..Default::default()
+            }],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("app_id 不能为空")));
+        assert!(errors.iter().any(|e| e.contains("base_url 不能为空")));
+    }
+
+    #[test]
+    fn test_app_config_v2_validate_empty_ai_profile_fields() {
+        // 测试验证空的 AI profile 字段
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![AiProfileV2 {
+                id: "".to_string(),
+                model: "".to_string(),
+                ..Default::default()
+            }],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
+        assert!(errors.iter().any(|e| e.contains("model 不能为空")));
     }
-}
 
-fn pick_credential(name: &str, value: &Option<String>, env_key: &Option<String>) -> Result<String> {
-    if let Some(v) = value.clone() {
-        if !v.trim().is_empty() {
-            return Ok(v);
-        }
-    }
-    if let Some(env) = env_key.as_deref() {
-        if let Ok(v) = std::env::var(env) {
-            if !v.trim().is_empty() {
-                return Ok(v);
-            }
+    fn config_with_profile(profile: AiProfileV2) -> AppConfigV2 {
+        AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![profile],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
         }
-        return Err(anyhow::anyhow!("未找到 {}，请设置环境变量 {}", name, env));
     }
-    Err(anyhow::anyhow!("未找到 {}，请在配置或环境变量中设置", name))
-}
 
-fn pick_optional_credential(
-    _name: &str,
-    value: &Option<String>,
-    env_key: &Option<String>,
-) -> Result<Option<String>> {
-    if let Some(v) = value.clone() {
-        if !v.trim().is_empty() {
-            return Ok(Some(v));
-        }
-    }
-    if let Some(env) = env_key.as_deref() {
-        if let Ok(v) = std::env::var(env) {
-            if !v.trim().is_empty() {
-                return Ok(Some(v));
-            }
-        }
+    #[test]
+    fn test_app_config_v2_validate_azure_openai_preset() {
+        let valid = config_with_profile(AiProfileV2 {
+            id: "azure".to_string(),
+            provider: Some("azure-openai".to_string()),
+            model: "gpt-4o".to_string(),
+            base_url: Some("https://my-resource.openai.azure.com".to_string()),
+            azure: Some(AzureOpenAiConfig {
+                deployment: Some("gpt4o-prod".to_string()),
+                api_version: Some("2025-01-01-preview".to_string()),
+            }),
+            ..Default::default()
+        });
+        assert!(valid.validate().is_empty());
+
+        let invalid = config_with_profile(AiProfileV2 {
+            id: "azure".to_string(),
+            provider: Some("azure-openai".to_string()),
+            model: "gpt-4o".to_string(),
+            azure: Some(AzureOpenAiConfig {
+                deployment: Some("a/b".to_string()),
+                api_version: Some("2024-10".to_string()),
+            }),
+            ..Default::default()
+        });
+        let errors = invalid.validate();
+        assert!(errors.iter().any(|e| e.contains("需要配置 base_url")));
+        assert!(errors.iter().any(|e| e.contains("azure.deployment")));
+        assert!(errors.iter().any(|e| e.contains("azure.api_version")));
     }
-    Ok(None)
-}
 
-fn build_ai_action(
-    profile: &AiProfileV2,
-    tool_map: &HashMap<String, ToolConfigV2>,
-    base_path: &std::path::Path,
-) -> Result<AiAction> {
-    let mut system_prompt = profile.system_prompt.clone();
-    if system_prompt.is_none() {
-        if let Some(ref file) = profile.system_prompt_file {
-            let abs = if PathBuf::from(file).is_absolute() {
-                PathBuf::from(file)
-            } else {
-                base_path.parent().unwrap_or(Path::new(".")).join(file)
-            };
-            system_prompt = Some(
-                std::fs::read_to_string(&abs)
-                    .with_context(|| format!("读取 system_prompt_file 失败: {}", abs.display()))?,
-            );
-        }
+    #[test]
+    fn test_app_config_v2_validate_openrouter_preset() {
+        let valid = config_with_profile(AiProfileV2 {
+            id: "router".to_string(),
+            provider: Some("openrouter".to_string()),
+            model: "anthropic/claude-sonnet-4".to_string(),
+            openrouter: Some(OpenRouterConfig {
+                site_url: Some("https://example.com".to_string()),
+                app_name: Some("Support Bot".to_string()),
+            }),
+            ..Default::default()
+        });
+        assert!(valid.validate().is_empty());
+
+        let invalid = config_with_profile(AiProfileV2 {
+            id: "router".to_string(),
+            provider: Some("openrouter".to_string()),
+            model: "gpt-4o".to_string(),
+            openrouter: Some(OpenRouterConfig {
+                site_url: Some("example.com".to_string()),
+                app_name: None,
+            }),
+            ..Default::default()
+        });
+        let errors = invalid.validate();
+        assert!(errors.iter().any(|e| e.contains("厂商/模型")));
+        assert!(errors.iter().any(|e| e.contains("openrouter.site_url")));
+
+        let non_ascii = config_with_profile(AiProfileV2 {
+            id: "router".to_string(),
+            provider: Some("openrouter".to_string()),
+            model: "openai/gpt-4o".to_string(),
+            openrouter: Some(OpenRouterConfig {
+                site_url: None,
+                app_name: Some("客服机器人".to_string()),
+            }),
+            ..Default::default()
+        });
+        assert!(non_ascii
+            .validate()
+            .iter()
+            .any(|e| e.contains("openrouter.app_name")));
     }
 
-    let mut tools = Vec::new();
-    for tool_id in &profile.tool_ids {
-        let tool = tool_map
-            .get(tool_id)
-            .ok_or_else(|| anyhow::anyhow!("AI Profile 引用的工具不存在: {}", tool_id))?;
-        let cmd = CommandAction {
-            program: tool.program.clone(),
-            args: tool.args.clone(),
-            timeout_secs: tool.timeout_secs,
-            max_output: tool.max_output,
-            pre_reply: tool.pre_reply.clone(),
-            post_reply: tool.post_reply.clone(),
-        };
-        tools.push(AiTool {
-            name: tool.id.clone(),
-            description: tool.description.clone(),
-            parameters: Some(
-                tool.parameters
-                    .clone()
-                    .unwrap_or_else(|| json!({"type": "object"})),
-            ),
-            command: Some(cmd),
+    #[test]
+    fn test_app_config_v2_validate_preset_fields_require_provider() {
+        let config = config_with_profile(AiProfileV2 {
+            id: "main".to_string(),
+            model: "gpt-4o".to_string(),
+            azure: Some(AzureOpenAiConfig::default()),
+            openrouter: Some(OpenRouterConfig::default()),
+            ..Default::default()
         });
+        let errors = config.validate();
+        assert!(errors.iter().any(|e| e.contains("azure 仅适用于")));
+        assert!(errors.iter().any(|e| e.contains("openrouter 仅适用于")));
     }
 
-    Ok(AiAction {
-        provider: profile.provider.clone(),
-        model: profile.model.clone(),
-        api_key: profile.api_key.clone(),
-        api_key_env: profile.api_key_env.clone(),
-        base_url: profile.base_url.clone(),
-        system_prompt,
-        user_prefix: profile.user_prefix.clone(),
-        command: None,
-        max_command_output: None,
-        temperature: None,
-        max_tokens: None,
-        response_format: None,
-        tools,
-        max_retries: None,
-        retry_delay_ms: None,
-    })
-}
+    #[test]
+    fn test_app_config_v2_validate_tool_loop() {
+        let config = config_with_profile(AiProfileV2 {
+            id: "agent".to_string(),
+            model: "gpt-4o".to_string(),
+            tool_loop: Some(ToolLoopConfig {
+                max_calls: Some(MAX_TOOL_CALLS + 1),
+                max_identical_calls: Some(0),
+                max_consecutive_calls: Some(0),
+                note: None,
+            }),
+            ..Default::default()
+        });
+        let errors = config.validate();
+        assert!(errors.iter().any(|e| e.contains("tool_loop.max_calls")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("tool_loop.max_identical_calls")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("tool_loop.max_consecutive_calls")));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let config = config_with_profile(AiProfileV2 {
+            id: "agent".to_string(),
+            model: "gpt-4o".to_string(),
+            tool_loop: Some(ToolLoopConfig {
+                max_calls: Some(5),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert!(config.validate().is_empty());
+    }
 
     #[test]
-    fn test_rule_kind_serde() {
-        // 测试 RuleKind 序列化和反序列化
-        let kind = RuleKind::Text;
-        let json = serde_json::to_string(&kind).unwrap();
-        assert_eq!(json, r#""text""#);
-
-        let deserialized: RuleKind = serde_json::from_str(&json).unwrap();
-        assert_eq!(deserialized, RuleKind::Text);
+    fn test_app_config_v2_validate_stream() {
+        let config = config_with_profile(AiProfileV2 {
+            id: "writer".to_string(),
+            model: "gpt-4o".to_string(),
+            stream: Some(true),
+            max_chars_per_message: Some(0),
+            structured: Some(StructuredOutputConfig::default()),
+            ..Default::default()
+        });
+        let errors = config.validate();
+        assert!(!errors.iter().any(|e| e.contains("stream_edit")));
+        assert!(errors
+            .iter()
+            .any(|e| e == "ai_profiles[0]: stream 与 structured 不能同时配置"));
+        assert!(errors
+            .iter()
+            .any(|e| e == "ai_profiles[0]: max_chars_per_message 不能为 0"));
+
+        let config = config_with_profile(AiProfileV2 {
+            id: "writer".to_string(),
+            model: "gpt-4o".to_string(),
+            stream: Some(true),
+            stream_edit: Some(true),
+            max_chars_per_message: Some(500),
+            ..Default::default()
+        });
+        assert!(config.validate().is_empty());
 
-        // 测试默认值
-        let default_kind = RuleKind::default();
-        assert_eq!(default_kind, RuleKind::Any);
+        let config = config_with_profile(AiProfileV2 {
+            id: "writer".to_string(),
+            model: "gpt-4o".to_string(),
+            stream_edit: Some(true),
+            ..Default::default()
+        });
+        assert_eq!(
+            config.validate(),
+            ["ai_profiles[0]: stream_edit 需要同时开启 stream"]
+        );
     }
 
     #[test]
-    fn test_reply_mode_serde() {
-        // 测试 ReplyMode 序列化和反序列化
-        let mode = ReplyMode::Quote;
-        let json = serde_json::to_string(&mode).unwrap();
-        assert_eq!(json, r#""quote""#);
+    fn test_is_azure_api_version() {
+        assert!(is_azure_api_version("2024-10-21"));
+        assert!(is_azure_api_version("2025-04-01-preview"));
+        assert!(!is_azure_api_version("2024-10"));
+        assert!(!is_azure_api_version("v1"));
+        assert!(!is_azure_api_version("2024-1x-21"));
+    }
 
-        let deserialized: ReplyMode = serde_json::from_str(&json).unwrap();
-        assert_eq!(deserialized, ReplyMode::Quote);
+    #[test]
+    fn test_app_config_v2_validate_duplicate_profiles() {
+        // 测试验证重复的 AI profile ID
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![
+                AiProfileV2 {
+                    id: "profile1".to_string(),
+                    model: "gpt-4".to_string(),
+                    ..Default::default()
+                },
+                AiProfileV2 {
+                    id: "profile1".to_string(),
+                    model: "gpt-3.5".to_string(),
+                    ..Default::default()
+                },
+            ],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
 
-        // 测试默认值
-        let default_mode = ReplyMode::default();
-        assert_eq!(default_mode, ReplyMode::None);
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("重复的 id")));
     }
 
     #[test]
-    fn test_chat_kind_serde() {
-        // 测试 ChatKind 序列化和反序列化
-        let kind = ChatKind::Group;
-        let json = serde_json::to_string(&kind).unwrap();
-        assert_eq!(json, r#""group""#);
+    fn test_app_config_v2_validate_missing_tool_reference() {
+        // 测试验证 AI profile 引用不存在的工具
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![AiProfileV2 {
+                id: "profile1".to_string(),
+                model: "gpt-4".to_string(),
+                tool_ids: vec!["nonexistent_tool".to_string()],
+                ..Default::default()
+            }],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
 
-        let deserialized: ChatKind = serde_json::from_str(&json).unwrap();
-        assert_eq!(deserialized, ChatKind::Group);
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("引用的工具不存在")));
     }
 
     #[test]
-    fn test_from_config_serde() {
-        // 测试 FromConfig 序列化和反序列化
-        let config = FromConfig {
-            nick: Some("test_nick".to_string()),
-            wxid: Some("test_wxid".to_string()),
+    fn test_app_config_v2_validate_empty_tool_fields() {
+        // 测试验证空的工具字段
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![ToolConfigV2 {
+                id: "".to_string(),
+                program: "".to_string(),
+                ..Default::default()
+            }],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
         };
 
-        let json = serde_json::to_string(&config).unwrap();
-        let deserialized: FromConfig = serde_json::from_str(&json).unwrap();
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
+        assert!(errors.iter().any(|e| e.contains("program 不能为空")));
+    }
+
+    #[test]
+    fn test_app_config_v2_validate_http_policy() {
+        let mut config = AppConfigV2::parse(
+            r#"
+config_version = 2
+
+[[tools]]
+id = "api"
+program = "http_request"
+
+[tools.http]
+allow_hosts = ["api.example.com"]
+max_response_bytes = 0
+
+[[tools.http.auth_profiles]]
+id = "key"
+headers_env = { Authorization = "API_AUTH" }
+
+[[tools.http.auth_profiles]]
+id = "key"
+"#,
+        )
+        .unwrap();
+        let errors = config.validate();
+        assert!(errors.iter().any(|e| e.contains("max_response_bytes")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("重复的 http.auth_profiles id")));
+        assert!(errors.iter().any(|e| e.contains("headers_env")));
 
-        assert_eq!(deserialized.nick, Some("test_nick".to_string()));
-        assert_eq!(deserialized.wxid, Some("test_wxid".to_string()));
+        let http = config.tools[0].http.as_mut().unwrap();
+        http.max_response_bytes = Some(1024);
+        http.auth_profiles.pop();
+        assert!(config.validate().is_empty());
+        assert_eq!(
+            config.tools[0].http.as_ref().unwrap().auth_profiles[0].headers_env["Authorization"],
+            "API_AUTH"
+        );
     }
 
     #[test]
-    fn test_match_config_deserialize() {
-        // 测试 MatchConfig 反序列化
-        let toml_str = r#"
-            equals = "hello"
-            contains = "world"
-            regex = "^test.*"
-        "#;
+    fn test_app_config_v2_validate_duplicate_tools() {
+        // 测试验证重复的工具 ID
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![
+                ToolConfigV2 {
+                    id: "tool1".to_string(),
+                    program: "echo".to_string(),
+                    ..Default::default()
+                },
+                ToolConfigV2 {
+                    id: "tool1".to_string(),
+                    program: "cat".to_string(),
+                    ..Default::default()
+                },
+            ],
+            rule_templates: vec![],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
 
-        let config: MatchConfig = toml::from_str(toml_str).unwrap();
-        assert_eq!(config.equals, Some("hello".to_string()));
-        assert_eq!(config.contains, Some("world".to_string()));
-        assert_eq!(config.regex, Some("^test.*".to_string()));
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("重复的 id")));
     }
 
     #[test]
-    fn test_command_action_deserialize() {
-        // 测试 CommandAction 反序列化
-        let toml_str = r#"
-            program = "echo"
-            args = ["hello", "world"]
-            timeout_secs = 30
-            max_output = 1024
-            pre_reply = "Processing..."
-            post_reply = "Done!"
-        "#;
+    fn test_app_config_v2_validate_empty_template_fields() {
+        // 测试验证空的规则模板字段
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![RuleTemplateV2 {
+                id: "".to_string(),
+                ..Default::default()
+            }],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
 
-        let action: CommandAction = toml::from_str(toml_str).unwrap();
-        assert_eq!(action.program, "echo");
-        assert_eq!(action.args, vec!["hello", "world"]);
-        assert_eq!(action.timeout_secs, Some(30));
-        assert_eq!(action.max_output, Some(1024));
-        assert_eq!(action.pre_reply, Some("Processing...".to_string()));
-        assert_eq!(action.post_reply, Some("Done!".to_string()));
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
     }
 
     #[test]
-    fn test_app_config_default() {
-        // 测试 AppConfig 默认值
-        let config = AppConfig::default();
-        assert_eq!(config.listen_addr, "0.0.0.0:3000");
-        assert_eq!(config.queue_size, 2048);
-        assert_eq!(config.image_dir, "data/images");
-        assert_eq!(config.image_url_prefix, "/images");
-        assert_eq!(config.max_concurrency, 8);
-        assert!(config.bots.is_empty());
+    fn test_app_config_v2_validate_duplicate_templates() {
+        // 测试验证重复的模板 ID
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![
+                RuleTemplateV2 {
+                    id: "template1".to_string(),
+                    ..Default::default()
+                },
+                RuleTemplateV2 {
+                    id: "template1".to_string(),
+                    ..Default::default()
+                },
+            ],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
+
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("重复的 id")));
     }
 
     #[test]
-    fn test_is_v2_config() {
-        // 测试 V2 配置检测
-        let v2_toml = r#"
-            config_version = 2
-
-            [server]
-            listen_addr = "0.0.0.0:3000"
-        "#;
-
-        assert!(is_v2_config(v2_toml));
-
-        let v1_toml = r#"
-            listen_addr = "0.0.0.0:3000"
-
-            [[bots]]
-            app_id = "test"
-        "#;
+    fn test_app_config_v2_validate_template_missing_profile() {
+        // 测试验证模板引用不存在的 AI profile
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![RuleTemplateV2 {
+                id: "template1".to_string(),
+                action: TemplateActionV2 {
+                    ai_profile: Some("nonexistent_profile".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            rule_instances: vec![],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
 
-        assert!(!is_v2_config(v1_toml));
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("引用的 ai_profile 不存在")));
     }
 
     #[test]
-    fn test_app_config_v2_parse() {
-        // 测试 V2 配置解析
-        let toml_str = r#"
-            config_version = 2
-
-            [server]
-            listen_addr = "0.0.0.0:3000"
-            queue_size = 1024
-
-            [storage]
-            image_dir = "data/images"
-            image_url_prefix = "/images"
-        "#;
+    fn test_app_config_v2_validate_empty_instance_fields() {
+        // 测试验证空的实例字段
+        let config = AppConfigV2 {
+            config_version: 2,
+            server: ServerConfigV2::default(),
+            storage: StorageConfigV2::default(),
+            defaults: DefaultsV2::default(),
+            bots: vec![],
+            ai_profiles: vec![],
+            tools: vec![],
+            rule_templates: vec![],
+            rule_instances: vec![RuleInstanceV2 {
+                id: "".to_string(),
+                template: "".to_string(),
+                ..Default::default()
+            }],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
+        };
 
-        let config = AppConfigV2::parse(toml_str).unwrap();
-        assert_eq!(config.config_version, 2);
-        assert_eq!(config.server.listen_addr, "0.0.0.0:3000");
-        assert_eq!(config.server.queue_size, 1024);
-        assert_eq!(config.storage.image_dir, "data/images");
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
+        assert!(errors.iter().any(|e| e.contains("template 不能为空")));
     }
 
     #[test]
-    fn test_app_config_v2_to_toml() {
-        // 测试 V2 配置序列化为 TOML
+    fn test_app_config_v2_validate_instance_missing_template() {
+        // 测试验证实例引用不存在的模板
         let config = AppConfigV2 {
             config_version: 2,
-            server: ServerConfigV2 {
-                listen_addr: "0.0.0.0:3000".to_string(),
-                queue_size: 2048,
-            },
+            server: ServerConfigV2::default(),
             storage: StorageConfigV2::default(),
             defaults: DefaultsV2::default(),
             bots: vec![],
             ai_profiles: vec![],
             tools: vec![],
             rule_templates: vec![],
-            rule_instances: vec![],
+            rule_instances: vec![RuleInstanceV2 {
+                id: "instance1".to_string(),
+                template: "nonexistent_template".to_string(),
+                ..Default::default()
+            }],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
         };
 
-        let toml = config.to_toml().unwrap();
-        assert!(toml.contains("config_version = 2"));
-        assert!(toml.contains("listen_addr"));
+        let errors = config.validate();
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("引用的模板不存在")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_empty() {
-        // 测试空配置验证
+    fn test_app_config_v2_validate_duplicate_instances() {
+        // 测试验证重复的实例 ID
+        let template = RuleTemplateV2 {
+            id: "template1".to_string(),
+            ..Default::default()
+        };
         let config = AppConfigV2 {
             config_version: 2,
             server: ServerConfigV2::default(),
@@ -1097,223 +4923,516 @@ mod tests {
             bots: vec![],
             ai_profiles: vec![],
             tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
+            rule_templates: vec![template],
+            rule_instances: vec![
+                RuleInstanceV2 {
+                    id: "instance1".to_string(),
+                    template: "template1".to_string(),
+                    ..Default::default()
+                },
+                RuleInstanceV2 {
+                    id: "instance1".to_string(),
+                    template: "template1".to_string(),
+                    ..Default::default()
+                },
+            ],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
         };
 
         let errors = config.validate();
-        assert!(errors.is_empty());
+        assert!(!errors.is_empty());
+        assert!(errors.iter().any(|e| e.contains("重复的 id")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_wrong_version() {
-        // 测试错误的版本号
+    fn test_app_config_v2_validate_invalid_channel() {
+        // 测试验证无效的 channel 值
+        let template = RuleTemplateV2 {
+            id: "template1".to_string(),
+            ..Default::default()
+        };
         let config = AppConfigV2 {
-            config_version: 1,
+            config_version: 2,
             server: ServerConfigV2::default(),
             storage: StorageConfigV2::default(),
             defaults: DefaultsV2::default(),
             bots: vec![],
             ai_profiles: vec![],
             tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
+            rule_templates: vec![template],
+            rule_instances: vec![RuleInstanceV2 {
+                id: "instance1".to_string(),
+                template: "template1".to_string(),
+                channel: Some("invalid_channel".to_string()),
+                ..Default::default()
+            }],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
         };
 
         let errors = config.validate();
         assert!(!errors.is_empty());
-        assert!(errors[0].contains("config_version"));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("channel 必须是 private/group/both")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_duplicate_bot_ids() {
-        // 测试重复的 bot id
+    fn test_app_config_v2_validate_instance_override_missing_profile() {
+        // 测试验证实例覆盖引用不存在的 AI profile
+        let template = RuleTemplateV2 {
+            id: "template1".to_string(),
+            ..Default::default()
+        };
         let config = AppConfigV2 {
             config_version: 2,
             server: ServerConfigV2::default(),
             storage: StorageConfigV2::default(),
             defaults: DefaultsV2::default(),
-            bots: vec![
-                BotConfigV2 {
-                    id: Some("bot1".to_string()),
-                    app_id: "app1".to_string(),
-                    token: Some("token1".to_string()),
-                    base_url: "http://localhost".to_string(),
-                    ..Default::default()
-                },
-                BotConfigV2 {
-                    id: Some("bot1".to_string()),
-                    app_id: "app2".to_string(),
-                    token: Some("token2".to_string()),
-                    base_url: "http://localhost".to_string(),
-                    ..Default::default()
-                },
-            ],
+            bots: vec![],
             ai_profiles: vec![],
             tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
+            rule_templates: vec![template],
+            rule_instances: vec![RuleInstanceV2 {
+                id: "instance1".to_string(),
+                template: "template1".to_string(),
+                overrides: Some(InstanceOverridesV2 {
+                    ai_profile: Some("nonexistent_profile".to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            countdowns: vec![],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
         };
 
         let errors = config.validate();
         assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("重复的 id")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("引用的 ai_profile 不存在")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_missing_token() {
-        // 测试缺少 token
+    fn test_app_config_v2_validate_countdowns() {
+        // 测试验证倒计时的时间格式与 bot 引用
         let config = AppConfigV2 {
             config_version: 2,
             server: ServerConfigV2::default(),
             storage: StorageConfigV2::default(),
             defaults: DefaultsV2::default(),
-            bots: vec![BotConfigV2 {
-                app_id: "app1".to_string(),
-                token: None,
-                token_env: None,
-                base_url: "http://localhost".to_string(),
-                ..Default::default()
-            }],
+            bots: vec![],
             ai_profiles: vec![],
             tools: vec![],
             rule_templates: vec![],
             rule_instances: vec![],
+            countdowns: vec![CountdownConfig {
+                id: "launch".to_string(),
+                name: "发布".to_string(),
+                at: "10月20日".to_string(),
+                app_id: "missing_bot".to_string(),
+                post_at: Some("9点".to_string()),
+                ..Default::default()
+            }],
+            chatroom_aliases: BTreeMap::new(),
+            persona: None,
         };
 
         let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("token")));
+        assert!(errors.iter().any(|e| e.contains("at 格式")));
+        assert!(errors.iter().any(|e| e.contains("post_at 格式")));
+        assert!(errors.iter().any(|e| e.contains("引用的 bot 不存在")));
+        assert!(errors.iter().any(|e| e.contains("targets 不能为空")));
+    }
+
+    #[test]
+    fn test_countdown_event_time() {
+        let mut countdown = CountdownConfig {
+            at: "2026-10-20".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            countdown.event_time().map(|t| t.to_string()),
+            Some("2026-10-20 00:00:00".to_string())
+        );
+        countdown.at = "2026-10-20 14:30".to_string();
+        assert_eq!(
+            countdown.event_time().map(|t| t.to_string()),
+            Some("2026-10-20 14:30:00".to_string())
+        );
+        assert_eq!(
+            countdown.post_time(),
+            chrono::NaiveTime::from_hms_opt(9, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_pick_credential_from_env() {
+        // 测试从环境变量获取凭证
+        std::env::set_var("TEST_TOKEN_ENV", "env_token_value");
+
+        let result = pick_credential("test", &None, &Some("TEST_TOKEN_ENV".to_string())).unwrap();
+
+        assert_eq!(result, "env_token_value");
+        std::env::remove_var("TEST_TOKEN_ENV");
+    }
+
+    #[test]
+    fn test_pick_credential_env_not_found() {
+        // 测试环境变量不存在
+        let result = pick_credential("test", &None, &Some("NONEXISTENT_ENV_VAR".to_string()));
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("未找到"));
+    }
+
+    #[test]
+    fn test_pick_credential_empty_value() {
+        // 测试空值应被忽略
+        let result = pick_credential("test", &Some("   ".to_string()), &None);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_pick_optional_credential_from_env() {
+        // 测试从环境变量获取可选凭证
+        std::env::set_var("TEST_OPTIONAL_ENV", "optional_value");
+
+        let result =
+            pick_optional_credential("test", &None, &Some("TEST_OPTIONAL_ENV".to_string()))
+                .unwrap();
+
+        assert_eq!(result, Some("optional_value".to_string()));
+        std::env::remove_var("TEST_OPTIONAL_ENV");
+    }
+
+    #[test]
+    fn test_pick_optional_credential_empty_value() {
+        // 测试空值返回 None
+        let result = pick_optional_credential("test", &Some("   ".to_string()), &None).unwrap();
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_app_config_v2_into_v1_basic() {
+        // 测试 V2 配置转换为 V1（基础配置）
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+config_version = 2
+
+[server]
+listen_addr = "0.0.0.0:8080"
+queue_size = 1024
+
+[storage]
+image_dir = "test/images"
+image_url_prefix = "/test-images"
+
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
+
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+
+        assert_eq!(v1.listen_addr, "0.0.0.0:8080");
+        assert_eq!(v1.queue_size, 1024);
+        assert_eq!(v1.image_dir, "test/images");
+        assert_eq!(v1.bots.len(), 1);
+        assert_eq!(v1.bots[0].app_id, "test_app");
+    }
+
+    #[test]
+    fn test_app_config_v2_into_v1_with_rules() {
+        // 测试 V2 配置转换为 V1（包含规则）
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+config_version = 2
+
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[rule_templates]]
+id = "template1"
+kind = "text"
+
+[rule_templates.match]
+contains = "hello"
+
+[rule_templates.action]
+reply_text = "Hi there!"
+
+[[rule_instances]]
+id = "instance1"
+template = "template1"
+channel = "group"
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
+
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+
+        assert_eq!(v1.bots.len(), 1);
+        assert_eq!(v1.bots[0].rules.len(), 1);
+        assert_eq!(v1.bots[0].rules[0].kind, RuleKind::Text);
+        assert_eq!(
+            v1.bots[0].rules[0].r#match.contains,
+            Some("hello".to_string())
+        );
+        assert_eq!(
+            v1.bots[0].rules[0].action.reply_text,
+            Some("Hi there!".to_string())
+        );
+        assert_eq!(v1.bots[0].rules[0].chat, Some(ChatKind::Group));
+    }
+
+    #[test]
+    fn test_app_config_v2_into_v1_disabled_instance() {
+        // 测试禁用的规则实例不会被转换
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+config_version = 2
+
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[rule_templates]]
+id = "template1"
+
+[[rule_instances]]
+id = "instance1"
+template = "template1"
+enabled = false
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
+
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+
+        assert_eq!(v1.bots.len(), 1);
+        assert_eq!(v1.bots[0].rules.len(), 0);
     }
 
-    #[test]
-    fn test_pick_credential_direct() {
-        // 测试直接配置凭证
-        let result = pick_credential(
-            "test",
-            &Some("direct_value".to_string()),
-            &Some("ENV_VAR".to_string()),
-        )
-        .unwrap();
+    #[test]
+    fn test_app_config_v2_into_v1_channel_private() {
+        // 测试 channel = private 转换
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+config_version = 2
+
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[rule_templates]]
+id = "template1"
 
-        assert_eq!(result, "direct_value");
-    }
+[[rule_instances]]
+id = "instance1"
+template = "template1"
+channel = "private"
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
 
-    #[test]
-    fn test_pick_credential_missing() {
-        // 测试缺少凭证
-        let result = pick_credential("test", &None, &None);
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
 
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("未找到"));
+        assert_eq!(v1.bots[0].rules[0].chat, Some(ChatKind::Private));
     }
 
     #[test]
-    fn test_pick_optional_credential() {
-        // 测试可选凭证
-        let result = pick_optional_credential("test", &Some("value".to_string()), &None).unwrap();
+    fn test_app_config_v2_into_v1_channel_both() {
+        // 测试 channel = both 转换（应为 None）
+        use std::io::Write;
+        use tempfile::NamedTempFile;
 
-        assert_eq!(result, Some("value".to_string()));
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        let config_content = r#"
+config_version = 2
 
-        let result = pick_optional_credential("test", &None, &None).unwrap();
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
 
-        assert_eq!(result, None);
-    }
+[[rule_templates]]
+id = "template1"
 
-    #[test]
-    fn test_match_config_v2_to_v1() {
-        // 测试 MatchConfigV2 转换为 V1
-        let v2 = MatchConfigV2 {
-            equals: Some("test".to_string()),
-            contains: Some("hello".to_string()),
-            regex: Some("^world".to_string()),
-            any: Some(true),
-        };
+[[rule_instances]]
+id = "instance1"
+template = "template1"
+channel = "both"
+"#;
+        tmpfile.write_all(config_content.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
 
-        let v1 = v2.to_v1();
-        assert_eq!(v1.equals, Some("test".to_string()));
-        assert_eq!(v1.contains, Some("hello".to_string()));
-        assert_eq!(v1.regex, Some("^world".to_string()));
-    }
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
 
-    #[test]
-    fn test_default_functions() {
-        // 测试默认值函数
-        assert_eq!(default_listen_addr(), "0.0.0.0:3000");
-        assert_eq!(default_queue_size(), 2048);
-        assert_eq!(default_image_dir(), "data/images");
-        assert_eq!(default_image_url_prefix(), "/images");
-        assert_eq!(default_max_concurrency(), 8);
+        assert_eq!(v1.bots[0].rules[0].chat, None);
     }
 
     #[test]
-    fn test_app_config_load_v1() {
-        // 测试加载 V1 配置
+    fn test_app_config_v2_into_v1_priority_sorting() {
+        // 测试规则实例按优先级排序
         use std::io::Write;
         use tempfile::NamedTempFile;
 
         let mut tmpfile = NamedTempFile::new().unwrap();
         let config_content = r#"
-listen_addr = "127.0.0.1:8080"
-queue_size = 1024
-image_dir = "test/images"
-image_url_prefix = "/test-images"
-external_base_url = "https://example.com"
-max_concurrency = 4
+config_version = 2
 
 [[bots]]
 app_id = "test_app"
 token = "test_token"
 base_url = "https://api.example.com"
-webhook_secret = "secret123"
+
+[[rule_templates]]
+id = "template1"
+[rule_templates.action]
+reply_text = "First"
+
+[[rule_templates]]
+id = "template2"
+[rule_templates.action]
+reply_text = "Second"
+
+[[rule_instances]]
+id = "instance1"
+template = "template1"
+priority = 10
+
+[[rule_instances]]
+id = "instance2"
+template = "template2"
+priority = 5
 "#;
         tmpfile.write_all(config_content.as_bytes()).unwrap();
         tmpfile.flush().unwrap();
 
-        let config = AppConfig::load(Some(tmpfile.path().to_str().unwrap())).unwrap();
-        assert_eq!(config.listen_addr, "127.0.0.1:8080");
-        assert_eq!(config.queue_size, 1024);
-        assert_eq!(config.image_dir, "test/images");
-        assert_eq!(config.image_url_prefix, "/test-images");
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+
+        assert_eq!(v1.bots[0].rules.len(), 2);
+        // 优先级 5 应该在前面
         assert_eq!(
-            config.external_base_url,
-            Some("https://example.com".to_string())
+            v1.bots[0].rules[0].action.reply_text,
+            Some("Second".to_string())
+        );
+        assert_eq!(
+            v1.bots[0].rules[1].action.reply_text,
+            Some("First".to_string())
         );
-        assert_eq!(config.max_concurrency, 4);
-        assert_eq!(config.bots.len(), 1);
-        assert_eq!(config.bots[0].app_id, "test_app");
-        assert_eq!(config.bots[0].token, "test_token");
     }
 
     #[test]
-    fn test_app_config_load_with_defaults() {
-        // 测试加载配置时应用默认值
+    fn test_app_config_v2_into_v1_shadow() {
+        let config_content = r#"
+config_version = 2
+
+[server]
+shadow = true
+
+[[bots]]
+app_id = "wx_inherit"
+token = "t1"
+base_url = "https://api.example.com"
+
+[[bots]]
+app_id = "wx_live"
+token = "t2"
+base_url = "https://api.example.com"
+shadow = false
+"#;
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(Path::new("config.toml")).unwrap();
+        assert!(v1.bots[0].shadow);
+        assert!(!v1.bots[1].shadow);
+
+        let v2 = AppConfigV2::parse(&config_content.replace("shadow = true", "")).unwrap();
+        let v1 = v2.into_v1(Path::new("config.toml")).unwrap();
+        assert!(!v1.bots[0].shadow);
+    }
+
+    #[test]
+    fn test_app_config_v2_into_v1_overrides() {
+        // 测试实例覆盖配置
         use std::io::Write;
         use tempfile::NamedTempFile;
 
         let mut tmpfile = NamedTempFile::new().unwrap();
         let config_content = r#"
-listen_addr = ""
-queue_size = 0
-max_concurrency = 0
+config_version = 2
+
+[defaults]
+reply_mode = "quote"
+log = true
 
 [[bots]]
 app_id = "test_app"
 token = "test_token"
 base_url = "https://api.example.com"
+
+[[rule_templates]]
+id = "template1"
+[rule_templates.action]
+reply_text = "Template reply"
+reply_mode = "at"
+
+[[rule_instances]]
+id = "instance1"
+template = "template1"
+
+[rule_instances.overrides]
+reply_text = "Overridden reply"
+reply_mode = "quote_and_at"
+log = false
 "#;
         tmpfile.write_all(config_content.as_bytes()).unwrap();
         tmpfile.flush().unwrap();
 
-        let config = AppConfig::load(Some(tmpfile.path().to_str().unwrap())).unwrap();
-        assert_eq!(config.listen_addr, "0.0.0.0:3000");
-        assert_eq!(config.queue_size, 2048);
-        assert_eq!(config.max_concurrency, 8);
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+
+        let rule = &v1.bots[0].rules[0];
+        assert_eq!(rule.action.reply_text, Some("Overridden reply".to_string()));
+        assert_eq!(rule.action.reply_mode, Some(ReplyMode::QuoteAndAt));
+        assert_eq!(rule.action.log, Some(false));
     }
 
     #[test]
-    fn test_app_config_load_v2() {
-        // 测试加载 V2 配置
+    fn test_app_config_v2_into_v1_with_ai_profile() {
+        // 测试包含 AI profile 的转换
         use std::io::Write;
         use tempfile::NamedTempFile;
 
@@ -1321,731 +5440,1258 @@ base_url = "https://api.example.com"
         let config_content = r#"
 config_version = 2
 
-[server]
-listen_addr = "0.0.0.0:8080"
-queue_size = 512
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
 
-[storage]
-image_dir = "v2/images"
-image_url_prefix = "/v2-images"
+[[ai_profiles]]
+id = "gpt4"
+model = "gpt-4"
+provider = "openai"
+api_key = "test_key"
+system_prompt = "You are a helpful assistant"
+user_prefix = "User: "
 
-[[bots]]
-app_id = "v2_app"
-token = "v2_token"
-base_url = "https://v2.example.com"
+[[rule_templates]]
+id = "ai_template"
+[rule_templates.action]
+ai_profile = "gpt4"
+
+[[rule_instances]]
+id = "ai_instance"
+template = "ai_template"
 "#;
         tmpfile.write_all(config_content.as_bytes()).unwrap();
         tmpfile.flush().unwrap();
 
-        let config = AppConfig::load(Some(tmpfile.path().to_str().unwrap())).unwrap();
-        assert_eq!(config.listen_addr, "0.0.0.0:8080");
-        assert_eq!(config.queue_size, 512);
-        assert_eq!(config.image_dir, "v2/images");
-        assert_eq!(config.image_url_prefix, "/v2-images");
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+
+        let rule = &v1.bots[0].rules[0];
+        assert!(rule.action.ai.is_some());
+        let ai = rule.action.ai.as_ref().unwrap();
+        assert_eq!(ai.model, "gpt-4");
+        assert_eq!(ai.provider, Some("openai".to_string()));
+        assert_eq!(ai.api_key, Some("test_key".to_string()));
+        assert_eq!(
+            ai.system_prompt,
+            Some("You are a helpful assistant".to_string())
+        );
+        assert_eq!(ai.user_prefix, Some("User: ".to_string()));
+    }
+
+    #[test]
+    fn test_app_config_v2_ai_profile_semantic_cache() {
+        // 测试语义缓存配置透传到 V1 AiAction，并校验阈值范围
+        let config_content = r##"
+config_version = 2
+
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[ai_profiles]]
+id = "gpt4"
+model = "gpt-4"
+api_key = "test_key"
+
+[ai_profiles.cache]
+threshold = 0.9
+ttl_secs = 600
+bypass_keyword = "#重新回答"
+
+[[rule_templates]]
+id = "ai_template"
+[rule_templates.action]
+ai_profile = "gpt4"
+
+[[rule_instances]]
+id = "ai_instance"
+template = "ai_template"
+"##;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        v2.ai_profiles[0].cache.as_mut().unwrap().threshold = Some(1.5);
+        assert!(v2.validate().iter().any(|e| e.contains("cache.threshold")));
+        v2.ai_profiles[0].cache.as_mut().unwrap().threshold = Some(0.9);
+
+        let v1 = v2.into_v1(Path::new("config.toml")).unwrap();
+        let cache = v1.bots[0].rules[0]
+            .action
+            .ai
+            .as_ref()
+            .and_then(|ai| ai.cache.as_ref())
+            .unwrap();
+        assert_eq!(cache.ttl_secs, Some(600));
+        assert_eq!(cache.bypass_keyword.as_deref(), Some("#重新回答"));
     }
 
     #[test]
-    fn test_app_config_load_nonexistent() {
-        // 测试加载不存在的配置文件
-        let result = AppConfig::load(Some("/nonexistent/path/config.toml"));
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("读取配置失败"));
-    }
+    fn test_app_config_v2_bot_failover() {
+        // 测试热备配置透传，并校验备用机器人必须存在
+        let config_content = r#"
+config_version = 2
 
-    #[test]
-    fn test_app_config_load_invalid_toml() {
-        // 测试加载无效的 TOML 配置
-        use std::io::Write;
-        use tempfile::NamedTempFile;
+[[bots]]
+app_id = "primary"
+token = "t1"
+base_url = "https://api.example.com"
 
-        let mut tmpfile = NamedTempFile::new().unwrap();
-        tmpfile.write_all(b"invalid toml content {{{").unwrap();
-        tmpfile.flush().unwrap();
+[bots.failover]
+standby = "standby"
+chats = ["123@chatroom"]
+alert_to = ["wxid_ops"]
 
-        let result = AppConfig::load(Some(tmpfile.path().to_str().unwrap()));
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("解析配置失败"));
+[[bots]]
+app_id = "standby"
+token = "t2"
+base_url = "https://api.example.com"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        v2.bots[0].failover.as_mut().unwrap().standby = "missing".to_string();
+        assert!(v2.validate().iter().any(|e| e.contains("failover.standby")));
+        v2.bots[0].failover.as_mut().unwrap().standby = "primary".to_string();
+        assert!(v2.validate().iter().any(|e| e.contains("不能是自身")));
+        v2.bots[0].failover.as_mut().unwrap().standby = "standby".to_string();
+
+        let v1 = v2.into_v1(Path::new("config.toml")).unwrap();
+        let failover = v1.bots[0].failover.as_ref().unwrap();
+        assert!(failover.covers("123@chatroom"));
+        assert!(!failover.covers("456@chatroom"));
+        assert_eq!(failover.alert_to, vec!["wxid_ops".to_string()]);
     }
 
     #[test]
-    fn test_app_config_v2_from_json() {
-        // 测试从 JSON 解析 V2 配置
-        let json = r#"{
-            "config_version": 2,
-            "server": {
-                "listen_addr": "0.0.0.0:3000",
-                "queue_size": 2048
-            },
-            "storage": {
-                "image_dir": "data/images",
-                "image_url_prefix": "/images",
-                "external_base_url": null
-            },
-            "defaults": {},
-            "bots": [],
-            "ai_profiles": [],
-            "tools": [],
-            "rule_templates": [],
-            "rule_instances": []
-        }"#;
+    fn test_app_config_v2_bot_digest() {
+        // 测试运营摘要配置透传与校验
+        let config_content = r#"
+config_version = 2
 
-        let config = AppConfigV2::from_json(json).unwrap();
-        assert_eq!(config.config_version, 2);
-        assert_eq!(config.server.listen_addr, "0.0.0.0:3000");
-    }
+[[bots]]
+app_id = "primary"
+token = "t1"
+base_url = "https://api.example.com"
 
-    #[test]
-    fn test_app_config_v2_to_json() {
-        // 测试 V2 配置序列化为 JSON
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
-        };
+[bots.digest]
+period = "weekly"
+weekday = "fri"
+at = "18:30"
+chats = ["ops@chatroom"]
 
-        let json = config.to_json().unwrap();
-        assert!(json.contains("\"config_version\": 2"));
-        assert!(json.contains("\"listen_addr\""));
+[bots.digest.prices.gpt-4o]
+input = 2.5
+output = 10.0
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let digest = v2.bots[0].digest.as_mut().unwrap();
+        assert_eq!(digest.period, DigestPeriod::Weekly);
+        assert_eq!(digest.send_weekday(), Some(chrono::Weekday::Fri));
+        assert_eq!(
+            digest.send_time(),
+            chrono::NaiveTime::from_hms_opt(18, 30, 0)
+        );
+        digest.chats.clear();
+        assert!(v2.validate().iter().any(|e| e.contains("chats 或 email")));
+        let digest = v2.bots[0].digest.as_mut().unwrap();
+        digest.email = Some(DigestEmailConfig {
+            smtp_host: "smtp.example.com".to_string(),
+            from: "bot@example.com".to_string(),
+            ..Default::default()
+        });
+        digest.at = Some("25:00".to_string());
+        let errors = v2.validate();
+        assert!(errors.iter().any(|e| e.contains("digest.email.to")));
+        assert!(errors.iter().any(|e| e.contains("digest.at")));
+        let digest = v2.bots[0].digest.as_mut().unwrap();
+        digest.at = None;
+        digest.email.as_mut().unwrap().to = vec!["ops@example.com".to_string()];
+        assert!(v2.validate().is_empty());
+
+        let v1 = v2.into_v1(Path::new("config.toml")).unwrap();
+        let digest = v1.bots[0].digest.as_ref().unwrap();
+        assert_eq!(digest.prices["gpt-4o"].output, 10.0);
+        assert_eq!(digest.period.window(), chrono::Duration::days(7));
     }
 
     #[test]
-    fn test_app_config_v2_validate_empty_bot_fields() {
-        // 测试验证空的 bot 字段
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![BotConfigV2 {
-                app_id: "".to_string(),
-                base_url: "".to_string(),
-                token: Some("token".to_string()),
-                ..Default::default()
-            }],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
+    fn test_schedule_cron_and_timezone() {
+        // 周报按 weekday/at 转为 cron，cron 与 timezone 校验失败时报告字段
+        let mut digest = DigestConfig {
+            period: DigestPeriod::Weekly,
+            weekday: Some("fri".to_string()),
+            at: Some("18:30".to_string()),
+            ..Default::default()
         };
+        assert_eq!(digest.schedule().unwrap().key(), "30 18 * * 5");
+        digest.cron = Some("0 9 * * 1-5".to_string());
+        digest.timezone = Some("UTC".to_string());
+        assert_eq!(digest.schedule().unwrap().key(), "0 9 * * 1-5 @UTC");
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("app_id 不能为空")));
-        assert!(errors.iter().any(|e| e.contains("base_url 不能为空")));
-    }
+        let mut v2 = AppConfigV2::parse(
+            r#"
+config_version = 2
 
-    #[test]
-    fn test_app_config_v2_validate_empty_ai_profile_fields() {
-        // 测试验证空的 AI profile 字段
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![AiProfileV2 {
-                id: "".to_string(),
-                model: "".to_string(),
-                ..Default::default()
-            }],
-            tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
-        };
+[[bots]]
+app_id = "primary"
+token = "t1"
+base_url = "https://api.example.com"
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
-        assert!(errors.iter().any(|e| e.contains("model 不能为空")));
+[bots.digest]
+cron = "0 25 * * *"
+chats = ["ops@chatroom"]
+
+[[countdowns]]
+id = "launch"
+name = "发布"
+at = "2026-10-20"
+app_id = "primary"
+targets = ["team@chatroom"]
+timezone = "Mars/Olympus_Mons"
+"#,
+        )
+        .unwrap();
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("bots[0]: digest.cron 无效: cron 表达式无效")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("countdowns[0]: timezone 未知的时区: Mars/Olympus_Mons")));
+        assert!(v2.schedule_entries().is_empty());
+
+        v2.bots[0].digest.as_mut().unwrap().cron = Some("0 9 * * 1".to_string());
+        v2.countdowns[0].timezone = Some("UTC".to_string());
+        assert!(v2.validate().is_empty());
+        let ids: Vec<String> = v2.schedule_entries().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec!["digest:primary", "countdown:launch"]);
     }
 
     #[test]
-    fn test_app_config_v2_validate_duplicate_profiles() {
-        // 测试验证重复的 AI profile ID
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![
-                AiProfileV2 {
-                    id: "profile1".to_string(),
-                    model: "gpt-4".to_string(),
-                    ..Default::default()
-                },
-                AiProfileV2 {
-                    id: "profile1".to_string(),
-                    model: "gpt-3.5".to_string(),
-                    ..Default::default()
-                },
-            ],
-            tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
-        };
+    fn test_app_config_v2_ai_profile_structured() {
+        // 测试结构化输出配置透传，并校验与缓存互斥
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("重复的 id")));
-    }
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
 
-    #[test]
-    fn test_app_config_v2_validate_missing_tool_reference() {
-        // 测试验证 AI profile 引用不存在的工具
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![AiProfileV2 {
-                id: "profile1".to_string(),
-                model: "gpt-4".to_string(),
-                tool_ids: vec!["nonexistent_tool".to_string()],
-                ..Default::default()
-            }],
-            tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![],
-        };
+[[ai_profiles]]
+id = "router"
+model = "gpt-4o"
+api_key = "test_key"
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("引用的工具不存在")));
-    }
+[ai_profiles.structured]
+allowed_forward = ["wxid_admin"]
+allowed_labels = ["VIP"]
 
-    #[test]
-    fn test_app_config_v2_validate_empty_tool_fields() {
-        // 测试验证空的工具字段
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![ToolConfigV2 {
-                id: "".to_string(),
-                program: "".to_string(),
-                ..Default::default()
-            }],
-            rule_templates: vec![],
-            rule_instances: vec![],
-        };
+[[rule_templates]]
+id = "ai_template"
+[rule_templates.action]
+ai_profile = "router"
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
-        assert!(errors.iter().any(|e| e.contains("program 不能为空")));
+[[rule_instances]]
+id = "ai_instance"
+template = "ai_template"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        v2.ai_profiles[0].cache = Some(SemanticCacheConfig::default());
+        assert!(v2.validate().iter().any(|e| e.contains("structured")));
+        v2.ai_profiles[0].cache = None;
+        v2.ai_profiles[0].structured.as_mut().unwrap().schema = Some(json!("bad"));
+        assert!(v2
+            .validate()
+            .iter()
+            .any(|e| e.contains("structured.schema")));
+        v2.ai_profiles[0].structured.as_mut().unwrap().schema = None;
+
+        let v1 = v2.into_v1(Path::new("config.toml")).unwrap();
+        let rule = &v1.bots[0].rules[0];
+        assert_eq!(rule.id.as_deref(), Some("ai_instance"));
+        let structured = rule
+            .action
+            .ai
+            .as_ref()
+            .and_then(|ai| ai.structured.as_ref())
+            .unwrap();
+        assert_eq!(structured.allowed_forward, vec!["wxid_admin".to_string()]);
+        assert_eq!(structured.allowed_labels, vec!["VIP".to_string()]);
     }
 
     #[test]
-    fn test_app_config_v2_validate_duplicate_tools() {
-        // 测试验证重复的工具 ID
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![
-                ToolConfigV2 {
-                    id: "tool1".to_string(),
-                    program: "echo".to_string(),
-                    ..Default::default()
-                },
-                ToolConfigV2 {
-                    id: "tool1".to_string(),
-                    program: "cat".to_string(),
-                    ..Default::default()
-                },
-            ],
-            rule_templates: vec![],
-            rule_instances: vec![],
-        };
+    fn test_app_config_v2_ai_profile_variants() {
+        // 测试 Prompt 变体透传，实验名称取 profile id
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("重复的 id")));
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[ai_profiles]]
+id = "faq"
+model = "gpt-4"
+api_key = "test_key"
+system_prompt = "base"
+
+[[ai_profiles.variants]]
+id = "concise"
+weight = 3
+system_prompt = "回答尽量简短"
+
+[[ai_profiles.variants]]
+id = "friendly"
+
+[[rule_templates]]
+id = "ai_template"
+[rule_templates.action]
+ai_profile = "faq"
+
+[[rule_instances]]
+id = "ai_instance"
+template = "ai_template"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+
+        let ai = v2.clone().into_v1(Path::new("config.toml")).unwrap().bots[0].rules[0]
+            .action
+            .ai
+            .clone()
+            .unwrap();
+        assert_eq!(ai.experiment.as_deref(), Some("faq"));
+        assert_eq!(ai.variants.len(), 2);
+        assert_eq!(ai.variants[0].weight(), 3);
+        assert_eq!(ai.variants[1].weight(), 1);
+
+        v2.ai_profiles[0].variants[1].id = "concise".to_string();
+        assert!(v2
+            .validate()
+            .iter()
+            .any(|e| e.contains("重复的 variant id")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_empty_template_fields() {
-        // 测试验证空的规则模板字段
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![RuleTemplateV2 {
-                id: "".to_string(),
-                ..Default::default()
-            }],
-            rule_instances: vec![],
-        };
+    fn test_app_config_v2_ai_profile_model_rollout() {
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[ai_profiles]]
+id = "faq"
+model = "gpt-4o-mini"
+api_key = "test_key"
+model_rollout = { "gpt-4o-mini" = 90, "claude-sonnet" = 10 }
+
+[[rule_templates]]
+id = "ai_template"
+[rule_templates.action]
+ai_profile = "faq"
+
+[[rule_instances]]
+id = "ai_instance"
+template = "ai_template"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+
+        let ai = v2.clone().into_v1(Path::new("config.toml")).unwrap().bots[0].rules[0]
+            .action
+            .ai
+            .clone()
+            .unwrap();
+        assert_eq!(ai.model_rollout.get("claude-sonnet"), Some(&10));
+        assert_eq!(ai.model_rollout.get("gpt-4o-mini"), Some(&90));
+
+        v2.ai_profiles[0]
+            .model_rollout
+            .insert("gpt-4o".to_string(), 5);
+        assert!(v2
+            .validate()
+            .iter()
+            .any(|e| e.contains("model_rollout 的百分比之和不能超过 100")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_duplicate_templates() {
-        // 测试验证重复的模板 ID
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![
-                RuleTemplateV2 {
-                    id: "template1".to_string(),
-                    ..Default::default()
-                },
-                RuleTemplateV2 {
-                    id: "template1".to_string(),
-                    ..Default::default()
-                },
-            ],
-            rule_instances: vec![],
-        };
+    fn test_app_config_v2_ai_profile_budget() {
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("重复的 id")));
+[[ai_profiles]]
+id = "faq"
+model = "gpt-4o"
+api_key = "test_key"
+
+[ai_profiles.budget]
+max_tokens_per_message = 4000
+daily_cost = 2.0
+admins = ["wxid_admin"]
+
+[ai_profiles.budget.prices.gpt-4o]
+input = 2.5
+output = 10.0
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let budget = v2.ai_profiles[0].budget.clone().unwrap();
+        assert_eq!(budget.max_tokens_per_message, Some(4000));
+        assert_eq!(budget.admins, vec!["wxid_admin"]);
+
+        let budget = v2.ai_profiles[0].budget.as_mut().unwrap();
+        budget.prices.clear();
+        budget.max_cost_per_message = Some(0.0);
+        let errors = v2.validate();
+        assert!(errors.iter().any(|e| e.contains("需同时配置 prices")));
+        assert!(errors.iter().any(|e| e.contains("花费上限必须大于 0")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_template_missing_profile() {
-        // 测试验证模板引用不存在的 AI profile
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![RuleTemplateV2 {
-                id: "template1".to_string(),
-                action: TemplateActionV2 {
-                    ai_profile: Some("nonexistent_profile".to_string()),
-                    ..Default::default()
-                },
-                ..Default::default()
-            }],
-            rule_instances: vec![],
-        };
+    fn test_app_config_v2_rule_template_intent() {
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
+[[rule_templates]]
+id = "refund"
+[rule_templates.match.intent]
+examples = ["我要退款", "怎么申请退货"]
+threshold = 0.85
+[rule_templates.action]
+reply_text = "请提供订单号"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let intent = v2.rule_templates[0].r#match.to_v1().intent.unwrap();
+        assert_eq!(intent.examples.len(), 2);
+        assert_eq!(intent.threshold, Some(0.85));
+
+        let intent = v2.rule_templates[0].r#match.intent.as_mut().unwrap();
+        intent.examples = vec![" ".to_string()];
+        intent.threshold = Some(1.5);
+        let errors = v2.validate();
         assert!(errors
             .iter()
-            .any(|e| e.contains("引用的 ai_profile 不存在")));
+            .any(|e| e.contains("intent.examples 不能为空")));
+        assert!(errors.iter().any(|e| e.contains("intent.threshold")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_empty_instance_fields() {
-        // 测试验证空的实例字段
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![RuleInstanceV2 {
-                id: "".to_string(),
-                template: "".to_string(),
-                ..Default::default()
-            }],
-        };
+    fn test_app_config_v2_rule_template_language() {
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("id 不能为空")));
-        assert!(errors.iter().any(|e| e.contains("template 不能为空")));
+[[rule_templates]]
+id = "english"
+[rule_templates.match]
+language = "en-US"
+[rule_templates.action]
+reply_text = "Hello"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        assert_eq!(
+            v2.rule_templates[0].r#match.to_v1().language.as_deref(),
+            Some("en-US")
+        );
+
+        v2.rule_templates[0].r#match.language = Some("klingon".to_string());
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("不支持的 language: klingon")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_instance_missing_template() {
-        // 测试验证实例引用不存在的模板
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![],
-            rule_instances: vec![RuleInstanceV2 {
-                id: "instance1".to_string(),
-                template: "nonexistent_template".to_string(),
-                ..Default::default()
-            }],
-        };
+    fn test_app_config_v2_rule_template_expr() {
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("引用的模板不存在")));
+[[rule_templates]]
+id = "cmd"
+[rule_templates.match]
+expr = 'chat == group && content ~= /^\/cmd/'
+[rule_templates.action]
+reply_text = "ok"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        assert_eq!(
+            v2.rule_templates[0].r#match.to_v1().expr.as_deref(),
+            Some(r"chat == group && content ~= /^\/cmd/")
+        );
+
+        v2.rule_templates[0].r#match.expr = Some("chat == groups".to_string());
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("expr 无效: unknown value `groups`")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_duplicate_instances() {
-        // 测试验证重复的实例 ID
-        let template = RuleTemplateV2 {
-            id: "template1".to_string(),
-            ..Default::default()
-        };
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![template],
-            rule_instances: vec![
-                RuleInstanceV2 {
-                    id: "instance1".to_string(),
-                    template: "template1".to_string(),
-                    ..Default::default()
-                },
-                RuleInstanceV2 {
-                    id: "instance1".to_string(),
-                    template: "template1".to_string(),
-                    ..Default::default()
-                },
-            ],
-        };
+    fn test_app_config_v2_reply_sequence() {
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
-        assert!(errors.iter().any(|e| e.contains("重复的 id")));
-    }
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
 
-    #[test]
-    fn test_app_config_v2_validate_invalid_channel() {
-        // 测试验证无效的 channel 值
-        let template = RuleTemplateV2 {
-            id: "template1".to_string(),
-            ..Default::default()
-        };
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![template],
-            rule_instances: vec![RuleInstanceV2 {
-                id: "instance1".to_string(),
-                template: "template1".to_string(),
-                channel: Some("invalid_channel".to_string()),
-                ..Default::default()
-            }],
-        };
+[[rule_templates]]
+id = "guide"
+[rule_templates.match]
+contains = "教程"
+
+[[rule_templates.action.reply_sequence]]
+type = "text"
+text = "请按下图操作"
+
+[[rule_templates.action.reply_sequence]]
+type = "image"
+url = "https://example.com/guide.png"
+
+[[rule_templates.action.reply_sequence]]
+type = "link"
+title = "完整文档"
+url = "https://example.com/docs"
+
+[[rule_instances]]
+id = "guide"
+template = "guide"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2.clone().into_v1(Path::new("config.toml")).unwrap();
+        let sequence = &v1.bots[0].rules[0].action.reply_sequence;
+        assert_eq!(sequence.len(), 3);
+        assert_eq!(
+            sequence[1],
+            ReplyPart::Image {
+                url: "https://example.com/guide.png".to_string()
+            }
+        );
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
+        // 序列化后保持原样
+        let toml = v2.to_toml().unwrap();
+        assert!(toml.contains("type = \"link\""));
+
+        v2.rule_templates[0]
+            .action
+            .reply_sequence
+            .push(ReplyPart::File {
+                url: "ftp://example.com/a.zip".to_string(),
+                name: None,
+            });
+        let errors = v2.validate();
         assert!(errors
             .iter()
-            .any(|e| e.contains("channel 必须是 private/group/both")));
+            .any(|e| e.contains("reply_sequence[3] url 必须以 http:// 或 https:// 开头")));
     }
 
     #[test]
-    fn test_app_config_v2_validate_instance_override_missing_profile() {
-        // 测试验证实例覆盖引用不存在的 AI profile
-        let template = RuleTemplateV2 {
-            id: "template1".to_string(),
-            ..Default::default()
-        };
-        let config = AppConfigV2 {
-            config_version: 2,
-            server: ServerConfigV2::default(),
-            storage: StorageConfigV2::default(),
-            defaults: DefaultsV2::default(),
-            bots: vec![],
-            ai_profiles: vec![],
-            tools: vec![],
-            rule_templates: vec![template],
-            rule_instances: vec![RuleInstanceV2 {
-                id: "instance1".to_string(),
-                template: "template1".to_string(),
-                overrides: Some(InstanceOverridesV2 {
-                    ai_profile: Some("nonexistent_profile".to_string()),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            }],
-        };
+    fn test_app_config_v2_welcome() {
+        let config_content = r#"
+config_version = 2
 
-        let errors = config.validate();
-        assert!(!errors.is_empty());
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[rule_templates]]
+id = "welcome"
+kind = "member_joined"
+[rule_templates.action.welcome]
+text = "欢迎 {nickname} 加入{chatroom_name}"
+image_url = "https://example.com/rules.png"
+mention = true
+
+[[rule_instances]]
+id = "welcome-all"
+template = "welcome"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        let rule = &v1.bots[0].rules[0];
+        assert_eq!(rule.kind, RuleKind::MemberJoined);
+        assert_eq!(rule.action.welcome.as_ref().unwrap().mention, Some(true));
+
+        v2.rule_templates[0].kind = Some(RuleKind::Text);
+        v2.rule_templates[0].action.welcome = Some(WelcomeAction {
+            text: " ".to_string(),
+            image_url: Some("rules.png".to_string()),
+            mention: None,
+        });
+        let errors = v2.validate();
         assert!(errors
             .iter()
-            .any(|e| e.contains("引用的 ai_profile 不存在")));
+            .any(|e| e.contains("welcome 仅适用于 kind = \"member_joined\"")));
+        assert!(errors.iter().any(|e| e.contains("welcome text 不能为空")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("welcome image_url 必须以 http:// 或 https:// 开头")));
     }
 
     #[test]
-    fn test_pick_credential_from_env() {
-        // 测试从环境变量获取凭证
-        std::env::set_var("TEST_TOKEN_ENV", "env_token_value");
-
-        let result = pick_credential("test", &None, &Some("TEST_TOKEN_ENV".to_string())).unwrap();
+    fn test_app_config_v2_reply_link() {
+        let config_content = r#"
+config_version = 2
 
-        assert_eq!(result, "env_token_value");
-        std::env::remove_var("TEST_TOKEN_ENV");
+[[rule_templates]]
+id = "order"
+[rule_templates.match]
+regex = "^查订单\\s*(\\d+)$"
+[rule_templates.action.reply_link]
+title = "订单 {1}"
+desc = "点击查看订单详情"
+url = "https://erp.example.com/orders/{1}"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let link = v2.rule_templates[0].action.reply_link.clone().unwrap();
+        assert_eq!(link.url, "https://erp.example.com/orders/{1}");
+        assert_eq!(link.thumb_url, None);
+
+        v2.rule_templates[0].action.reply_link = Some(LinkReplyAction {
+            title: " ".to_string(),
+            url: "{1}".to_string(),
+            ..link
+        });
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("reply_link title 不能为空")));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("reply_link url 必须以 http:// 或 https:// 开头")));
     }
 
     #[test]
-    fn test_pick_credential_env_not_found() {
-        // 测试环境变量不存在
-        let result = pick_credential("test", &None, &Some("NONEXISTENT_ENV_VAR".to_string()));
+    fn test_app_config_from_env_vars() {
+        let mut vars: HashMap<String, String> = HashMap::new();
+        assert!(AppConfig::from_env_vars(&vars).unwrap().is_none());
+
+        vars.insert("GEWE_BOT_0_APPID".into(), "wx_a".into());
+        vars.insert("GEWE_BOT_0_TOKEN".into(), "token_a".into());
+        vars.insert("GEWE_BOT_1_APPID".into(), "wx_b".into());
+        vars.insert("GEWE_BOT_1_TOKEN".into(), "token_b".into());
+        vars.insert("GEWE_BOT_1_BASE_URL".into(), "http://gewe.local".into());
+        // 编号不连续的不会读取
+        vars.insert("GEWE_BOT_3_APPID".into(), "wx_d".into());
+        vars.insert(
+            "GEWE_RULES_JSON".into(),
+            r#"[{"match": {"equals": "ping"}, "action": {"reply_text": "pong"}}]"#.into(),
+        );
+        vars.insert("GEWE_LISTEN_ADDR".into(), "0.0.0.0:8080".into());
+        let config = AppConfig::from_env_vars(&vars).unwrap().unwrap();
+        assert_eq!(config.listen_addr, "0.0.0.0:8080");
+        assert_eq!(config.data_dir, "data");
+        assert_eq!(config.bots.len(), 2);
+        assert_eq!(config.bots[0].base_url, DEFAULT_GEWE_BASE_URL);
+        assert_eq!(config.bots[1].base_url, "http://gewe.local");
+        assert_eq!(config.bots[1].token, "token_b");
+        for bot in &config.bots {
+            assert_eq!(bot.rules.len(), 1);
+            assert_eq!(bot.rules[0].r#match.equals.as_deref(), Some("ping"));
+            assert_eq!(bot.rules[0].action.reply_text.as_deref(), Some("pong"));
+        }
 
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("未找到"));
+        vars.remove("GEWE_BOT_1_TOKEN");
+        let err = AppConfig::from_env_vars(&vars).unwrap_err();
+        assert!(err.to_string().contains("GEWE_BOT_1_TOKEN"));
+
+        vars.insert("GEWE_BOT_1_TOKEN".into(), "token_b".into());
+        vars.insert("GEWE_RULES_JSON".into(), "{not json".into());
+        assert!(AppConfig::from_env_vars(&vars).is_err());
     }
 
     #[test]
-    fn test_pick_credential_empty_value() {
-        // 测试空值应被忽略
-        let result = pick_credential("test", &Some("   ".to_string()), &None);
+    fn test_app_config_v2_on_error() {
+        let config_content = r#"
+config_version = 2
 
-        assert!(result.is_err());
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[rule_templates]]
+id = "poster"
+[rule_templates.match]
+equals = "海报"
+[rule_templates.action]
+reply_sequence = [{ type = "image", url = "https://example.com/poster.png" }]
+[rule_templates.action.on_error]
+retries = 2
+retry_delay_ms = 500
+notify = ["wxid_admin"]
+dlq = true
+fallback = [{ type = "text", text = "海报暂时无法发送，请稍后再试" }]
+
+[[rule_instances]]
+id = "poster_instance"
+template = "poster"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        let policy = v1.bots[0].rules[0].action.on_error.clone().unwrap();
+        assert_eq!(policy.retries, 2);
+        assert_eq!(policy.retry_delay_ms, Some(500));
+        assert_eq!(policy.notify, vec!["wxid_admin".to_string()]);
+        assert!(policy.dlq);
+        assert_eq!(policy.fallback.len(), 1);
+
+        v2.rule_templates[0].action.on_error = Some(ErrorPolicy {
+            retries: MAX_ACTION_RETRIES + 1,
+            fallback: vec![ReplyPart::Image {
+                url: "poster.png".to_string(),
+            }],
+            ..policy
+        });
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("on_error retries 不能超过 10")));
+        assert!(errors.iter().any(|e| e.contains("on_error fallback[0]")));
     }
 
     #[test]
-    fn test_pick_optional_credential_from_env() {
-        // 测试从环境变量获取可选凭证
-        std::env::set_var("TEST_OPTIONAL_ENV", "optional_value");
-
-        let result =
-            pick_optional_credential("test", &None, &Some("TEST_OPTIONAL_ENV".to_string()))
-                .unwrap();
+    fn test_app_config_v2_command_pool() {
+        let config_content = r#"
+config_version = 2
 
-        assert_eq!(result, Some("optional_value".to_string()));
-        std::env::remove_var("TEST_OPTIONAL_ENV");
+[server.command_pool]
+max_processes = 2
+per_rule = 1
+max_queue = 4
+max_load = 1.5
+min_free_memory_mb = 512
+busy_reply = "稍后再试"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.command_pool.max_processes, Some(2));
+        assert_eq!(v1.command_pool.max_load, Some(1.5));
+        assert_eq!(v1.command_pool.busy_reply.as_deref(), Some("稍后再试"));
+
+        // 未配置时使用默认值
+        let v1 = AppConfigV2::parse("config_version = 2")
+            .unwrap()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.command_pool, CommandPoolConfig::default());
+
+        v2.server.command_pool = Some(CommandPoolConfig {
+            max_processes: Some(0),
+            max_load: Some(-1.0),
+            ..Default::default()
+        });
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.command_pool: max_processes 必须大于 0"));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("server.command_pool: max_load 必须大于 0")));
     }
 
     #[test]
-    fn test_pick_optional_credential_empty_value() {
-        // 测试空值返回 None
-        let result = pick_optional_credential("test", &Some("   ".to_string()), &None).unwrap();
+    fn test_app_config_v2_ai_tasks() {
+        let config_content = r#"
+config_version = 2
 
-        assert_eq!(result, None);
+[server.ai_tasks]
+concurrency = 1
+max_pending = 8
+
+[[tools]]
+id = "video"
+program = "/opt/bin/gen-video"
+async = true
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        assert_eq!(v2.tools[0].run_async, Some(true));
+        assert!(tool_command(&v2.tools[0]).run_async);
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.ai_tasks.concurrency, Some(1));
+        assert_eq!(v1.ai_tasks.max_pending, Some(8));
+        let toml = toml::to_string(&v2).unwrap();
+        assert!(toml.contains("async = true"));
+
+        v2.server.ai_tasks = Some(AiTaskQueueConfig {
+            concurrency: Some(0),
+            max_pending: Some(0),
+        });
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.ai_tasks: concurrency 必须大于 0"));
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.ai_tasks: max_pending 必须大于 0"));
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_basic() {
-        // 测试 V2 配置转换为 V1（基础配置）
-        use std::io::Write;
-        use tempfile::NamedTempFile;
-
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    fn test_app_config_v2_memory() {
         let config_content = r#"
 config_version = 2
 
-[server]
-listen_addr = "0.0.0.0:8080"
-queue_size = 1024
+[server.memory]
+backend = "sqlite"
+path = "/var/lib/gewe/memory.db"
 
-[storage]
-image_dir = "test/images"
-image_url_prefix = "/test-images"
+[[ai_profiles]]
+id = "chat"
+model = "gpt-4o"
 
-[[bots]]
-app_id = "test_app"
-token = "test_token"
-base_url = "https://api.example.com"
+[ai_profiles.memory]
+max_turns = 6
+ttl_secs = 600
 "#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
-
         let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
-
-        assert_eq!(v1.listen_addr, "0.0.0.0:8080");
-        assert_eq!(v1.queue_size, 1024);
-        assert_eq!(v1.image_dir, "test/images");
-        assert_eq!(v1.bots.len(), 1);
-        assert_eq!(v1.bots[0].app_id, "test_app");
+        assert!(v2.validate().is_empty());
+        assert_eq!(
+            v2.ai_profiles[0].memory,
+            Some(ConversationMemoryConfig {
+                max_turns: Some(6),
+                ttl_secs: Some(600),
+            })
+        );
+        let v1 = v2
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.memory.backend, MemoryBackend::Sqlite);
+        assert_eq!(v1.memory.path.as_deref(), Some("/var/lib/gewe/memory.db"));
+        assert_eq!(AppConfig::default().memory.backend, MemoryBackend::Memory);
+
+        let config = config_with_profile(AiProfileV2 {
+            id: "chat".to_string(),
+            model: "gpt-4o".to_string(),
+            memory: Some(ConversationMemoryConfig {
+                max_turns: Some(MAX_MEMORY_TURNS + 1),
+                ttl_secs: Some(0),
+            }),
+            ..Default::default()
+        });
+        let errors = config.validate();
+        assert!(errors.iter().any(|e| e.contains("memory.max_turns")));
+        assert!(errors.iter().any(|e| e.contains("memory.ttl_secs")));
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_with_rules() {
-        // 测试 V2 配置转换为 V1（包含规则）
-        use std::io::Write;
-        use tempfile::NamedTempFile;
-
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    fn test_app_config_v2_rule_help() {
         let config_content = r#"
 config_version = 2
 
 [[bots]]
-app_id = "test_app"
-token = "test_token"
+app_id = "wx_help"
+token = "token"
 base_url = "https://api.example.com"
 
 [[rule_templates]]
-id = "template1"
-kind = "text"
+id = "weather"
+description = "查询天气"
+usage = "/weather 城市"
 
 [rule_templates.match]
-contains = "hello"
+regex = "^/weather"
 
-[rule_templates.action]
-reply_text = "Hi there!"
+[[rule_instances]]
+id = "weather_default"
+template = "weather"
 
 [[rule_instances]]
-id = "instance1"
-template = "template1"
+id = "weather_group"
+template = "weather"
 channel = "group"
+
+[rule_instances.overrides]
+description = "查询本群所在城市的天气"
 "#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
+        let v1 = AppConfigV2::parse(config_content)
+            .unwrap()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        let rules = &v1.bots[0].rules;
+        assert_eq!(rules[0].description.as_deref(), Some("查询天气"));
+        assert_eq!(rules[0].usage.as_deref(), Some("/weather 城市"));
+        assert_eq!(
+            rules[1].description.as_deref(),
+            Some("查询本群所在城市的天气")
+        );
+        assert_eq!(rules[1].usage.as_deref(), Some("/weather 城市"));
+    }
 
-        let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+    #[test]
+    fn test_app_config_v2_prompt_guard() {
+        let config_content = r#"
+config_version = 2
 
-        assert_eq!(v1.bots.len(), 1);
-        assert_eq!(v1.bots[0].rules.len(), 1);
-        assert_eq!(v1.bots[0].rules[0].kind, RuleKind::Text);
+[[ai_profiles]]
+id = "strict"
+model = "gpt-4o"
+
+[ai_profiles.prompt_guard]
+level = "reject"
+notice = "请勿尝试修改机器人设定"
+
+[[ai_profiles]]
+id = "default"
+model = "gpt-4o"
+prompt_guard = {}
+"#;
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
         assert_eq!(
-            v1.bots[0].rules[0].r#match.contains,
-            Some("hello".to_string())
+            v2.ai_profiles[0].prompt_guard,
+            Some(PromptGuardConfig {
+                level: PromptGuardLevel::Reject,
+                notice: Some("请勿尝试修改机器人设定".to_string()),
+            })
         );
         assert_eq!(
-            v1.bots[0].rules[0].action.reply_text,
-            Some("Hi there!".to_string())
+            v2.ai_profiles[1].prompt_guard.as_ref().map(|g| g.level),
+            Some(PromptGuardLevel::Neutralize)
         );
-        assert_eq!(v1.bots[0].rules[0].chat, Some(ChatKind::Group));
+        let action = build_ai_action(
+            &v2.ai_profiles[0],
+            &HashMap::new(),
+            std::path::Path::new("/tmp"),
+        )
+        .unwrap();
+        assert_eq!(action.prompt_guard, v2.ai_profiles[0].prompt_guard);
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_disabled_instance() {
-        // 测试禁用的规则实例不会被转换
-        use std::io::Write;
-        use tempfile::NamedTempFile;
-
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    fn test_app_config_v2_safe_mode() {
         let config_content = r#"
 config_version = 2
 
-[[bots]]
-app_id = "test_app"
-token = "test_token"
-base_url = "https://api.example.com"
+[server.safe_mode]
+max_sends_per_minute = 60
+max_rule_hits_per_minute = 120
+admins = ["wxid_admin"]
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert!(v1.safe_mode.enabled());
+        assert_eq!(v1.safe_mode.max_sends_per_minute, Some(60));
+        assert_eq!(v1.safe_mode.admins, ["wxid_admin"]);
+        assert!(!AppConfig::default().safe_mode.enabled());
+
+        v2.server.safe_mode = Some(SafeModeConfig {
+            max_sends_per_minute: Some(0),
+            max_rule_hits_per_minute: Some(0),
+            admins: Vec::new(),
+        });
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.safe_mode: max_sends_per_minute 必须大于 0"));
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.safe_mode: max_rule_hits_per_minute 必须大于 0"));
+    }
+
+    #[test]
+    fn test_app_config_v2_active_window() {
+        let config_content = r#"
+config_version = 2
 
 [[rule_templates]]
-id = "template1"
+id = "off_duty"
 
-[[rule_instances]]
-id = "instance1"
-template = "template1"
-enabled = false
+[rule_templates.match]
+any = true
+time_ranges = ["18:00-09:00"]
+weekdays = ["mon-fri"]
+
+[rule_templates.action]
+reply_text = "已下班，明天回复"
 "#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let m = v2.rule_templates[0].r#match.to_v1();
+        assert_eq!(m.time_ranges, ["18:00-09:00"]);
+        assert_eq!(m.weekdays, ["mon-fri"]);
+
+        v2.rule_templates[0].r#match.cron = Some("0 9 * *".to_string());
+        assert!(v2
+            .validate()
+            .iter()
+            .any(|e| e.starts_with("rule_templates[0]: 生效时间无效")));
+    }
+
+    #[test]
+    fn test_app_config_v2_chat_settings() {
+        let config_content = r#"
+config_version = 2
+
+[server.chat_settings]
+admins = ["wxid_admin"]
+"#;
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.chat_settings.admins, ["wxid_admin"]);
+        assert!(AppConfig::default().chat_settings.admins.is_empty());
+    }
+
+    #[test]
+    fn test_app_config_v2_media() {
+        let config_content = r#"
+config_version = 2
+
+[[server.media.sinks]]
+id = "nas"
+kind = "local"
+dir = "/mnt/nas/wechat"
+
+[[server.media.sinks]]
+id = "s3"
+kind = "s3"
+endpoint = "https://s3.us-east-1.amazonaws.com"
+bucket = "bot-media"
+access_key_id = "AKIA"
+secret_access_key_env = "S3_SECRET"
+prefix = "wechat/"
+
+[[server.media.sinks]]
+id = "dav"
+kind = "webdav"
+url = "https://dav.example.com/wechat"
+username = "bot"
+password_env = "DAV_PASSWORD"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.media.sinks.len(), 3);
+        assert!(matches!(
+            v1.media.sink("s3").map(|s| &s.backend),
+            Some(MediaBackend::S3 { prefix: Some(p), .. }) if p == "wechat/"
+        ));
+
+        let media = v2.server.media.as_mut().unwrap();
+        media.sinks[1].id = "nas".to_string();
+        media.sinks[2].backend = MediaBackend::Webdav {
+            url: "dav.example.com".to_string(),
+            username: None,
+            password: None,
+            password_env: None,
+        };
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.media: sinks[1]: id 重复: nas"));
+        assert!(errors
+            .iter()
+            .any(|e| e
+                == "server.media: sinks[2]: url 必须以 http:// 或 https:// 开头: dav.example.com"));
+    }
+
+    #[test]
+    fn test_app_config_v2_undo() {
+        let config_content = r#"
+config_version = 2
 
+[server.undo]
+admins = ["wxid_admin"]
+"#;
         let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
-
-        assert_eq!(v1.bots.len(), 1);
-        assert_eq!(v1.bots[0].rules.len(), 0);
+        let v1 = v2
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.undo.admins, ["wxid_admin"]);
+        assert!(AppConfig::default().undo.admins.is_empty());
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_channel_private() {
-        // 测试 channel = private 转换
-        use std::io::Write;
-        use tempfile::NamedTempFile;
-
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    fn test_app_config_v2_history() {
         let config_content = r#"
 config_version = 2
 
-[[bots]]
-app_id = "test_app"
-token = "test_token"
-base_url = "https://api.example.com"
-
-[[rule_templates]]
-id = "template1"
+[server.history]
+enabled = true
+keep = 200
 
-[[rule_instances]]
-id = "instance1"
-template = "template1"
-channel = "private"
+[server.history.search]
+backend = "postgres"
 "#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert!(v1.history.enabled);
+        assert_eq!(v1.history.keep(), 200);
+        assert_eq!(v1.history.media_link_ttl_secs(), 3600);
+        assert!(!AppConfig::default().history.enabled);
+        assert_eq!(
+            v1.history.search.as_ref().unwrap().backend,
+            HistorySearchBackend::Postgres
+        );
 
-        let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+        v2.server.history.as_mut().unwrap().keep = Some(0);
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.history: keep 必须大于 0"));
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("server.history: search.database_url")));
+    }
 
-        assert_eq!(v1.bots[0].rules[0].chat, Some(ChatKind::Private));
+    #[test]
+    fn test_alert_action_classify() {
+        let alert: AlertAction = toml::from_str(
+            r#"
+to = ["wxid_admin"]
+keywords = [
+  { word = "Refund" },
+  { word = "投诉", severity = "critical" },
+  { word = "优惠", severity = "info" },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            alert.classify("要 REFUND"),
+            Some((Some("Refund"), AlertSeverity::Warning))
+        );
+        // 命中多个关键词时取级别最高的
+        assert_eq!(
+            alert.classify("优惠没兑现，我要投诉"),
+            Some((Some("投诉"), AlertSeverity::Critical))
+        );
+        assert_eq!(alert.classify("你好"), None);
+        assert!(alert.validate().is_empty());
+
+        let bare = AlertAction {
+            severity: Some(AlertSeverity::Info),
+            summary_at: Some("9点".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(bare.classify("任何内容"), Some((None, AlertSeverity::Info)));
+        assert_eq!(bare.validate().len(), 2);
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_channel_both() {
-        // 测试 channel = both 转换（应为 None）
-        use std::io::Write;
-        use tempfile::NamedTempFile;
+    fn test_escalate_action_rota() {
+        let escalate: EscalateAction = toml::from_str(
+            r#"
+timezone = "UTC"
+rota = [
+  { weekdays = ["mon-fri"], time_ranges = ["09:00-18:00"], operators = ["wxid_day", " "] },
+  { weekdays = ["sat", "sun"], operators = ["wxid_weekend"] },
+]
+"#,
+        )
+        .unwrap();
+        assert!(escalate.validate().is_empty());
+        let rota = escalate.rota().unwrap().unwrap();
+        let at = |s: &str| chrono::DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        // 2030-07-05 为周五
+        assert_eq!(rota.on_duty(at("2030-07-05T10:00:00Z")), ["wxid_day"]);
+        assert_eq!(rota.on_duty(at("2030-07-06T03:00:00Z")), ["wxid_weekend"]);
+
+        for (toml, err) in [
+            ("", "operators 或 rota"),
+            ("rota = [{ operators = [] }]", "rota[0] 至少需要"),
+            (
+                r#"rota = [{ time_ranges = ["9-18"], operators = ["a"] }]"#,
+                "rota[0] 时段格式",
+            ),
+            (
+                r#"timezone = "Mars/Base"
+rota = [{ operators = ["a"] }]"#,
+                "timezone",
+            ),
+        ] {
+            let escalate: EscalateAction = toml::from_str(toml).unwrap();
+            let errors = escalate.validate();
+            assert!(errors[0].starts_with(err), "{toml}: {errors:?}");
+        }
+    }
 
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    #[test]
+    fn test_app_config_v2_watchdog() {
         let config_content = r#"
 config_version = 2
 
+[server.watchdog]
+enabled = true
+interval_secs = 30
+alert_to = ["wxid_admin"]
+bots = ["wx_bot"]
+
 [[bots]]
-app_id = "test_app"
-token = "test_token"
+app_id = "wx_bot"
+token = "t1"
 base_url = "https://api.example.com"
-
-[[rule_templates]]
-id = "template1"
-
-[[rule_instances]]
-id = "instance1"
-template = "template1"
-channel = "both"
 "#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
-
-        let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
-
-        assert_eq!(v1.bots[0].rules[0].chat, None);
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert!(v1.watchdog.enabled);
+        assert_eq!(v1.watchdog.interval_secs(), 30);
+        assert_eq!(v1.watchdog.max_backoff_secs(), 1800);
+        assert_eq!(v1.watchdog.alert_after_secs(), 300);
+        assert!(v1.watchdog.watches("wx_bot"));
+        assert!(!v1.watchdog.watches("wx_other"));
+        assert!(!AppConfig::default().watchdog.enabled);
+
+        let watchdog = v2.server.watchdog.as_mut().unwrap();
+        watchdog.max_backoff_secs = Some(10);
+        watchdog.bots.push("missing".to_string());
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.watchdog: max_backoff_secs 不能小于 interval_secs"));
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.watchdog: bots 引用的机器人不存在: missing"));
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_priority_sorting() {
-        // 测试规则实例按优先级排序
-        use std::io::Write;
-        use tempfile::NamedTempFile;
-
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    fn test_app_config_v2_rule_cooldown() {
         let config_content = r#"
 config_version = 2
 
@@ -2055,138 +6701,195 @@ token = "test_token"
 base_url = "https://api.example.com"
 
 [[rule_templates]]
-id = "template1"
-[rule_templates.action]
-reply_text = "First"
+id = "ai"
 
-[[rule_templates]]
-id = "template2"
 [rule_templates.action]
-reply_text = "Second"
+cooldown_secs = 60
+cooldown_scope = "chat"
 
 [[rule_instances]]
-id = "instance1"
-template = "template1"
-priority = 10
+id = "default"
+template = "ai"
 
 [[rule_instances]]
-id = "instance2"
-template = "template2"
-priority = 5
-"#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
+id = "vip"
+template = "ai"
 
-        let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
-
-        assert_eq!(v1.bots[0].rules.len(), 2);
-        // 优先级 5 应该在前面
-        assert_eq!(
-            v1.bots[0].rules[0].action.reply_text,
-            Some("Second".to_string())
-        );
+[rule_instances.overrides]
+cooldown_secs = 10
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        let actions: Vec<_> = v1.bots[0]
+            .rules
+            .iter()
+            .map(|r| (r.action.cooldown_secs, r.action.cooldown_scope))
+            .collect();
         assert_eq!(
-            v1.bots[0].rules[1].action.reply_text,
-            Some("First".to_string())
+            actions,
+            [
+                (Some(60), Some(CooldownScope::Chat)),
+                (Some(10), Some(CooldownScope::Chat)),
+            ]
         );
+
+        v2.rule_templates[0].action.cooldown_secs = None;
+        assert!(v2
+            .validate()
+            .iter()
+            .any(|e| e.contains("cooldown_scope 需要同时配置 cooldown_secs")));
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_overrides() {
-        // 测试实例覆盖配置
-        use std::io::Write;
-        use tempfile::NamedTempFile;
-
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    fn test_app_config_v2_moderation() {
         let config_content = r#"
 config_version = 2
 
-[defaults]
-reply_mode = "quote"
-log = true
-
-[[bots]]
-app_id = "test_app"
-token = "test_token"
-base_url = "https://api.example.com"
+[server.moderation]
+window_secs = 3600
 
-[[rule_templates]]
-id = "template1"
-[rule_templates.action]
-reply_text = "Template reply"
-reply_mode = "at"
+[[server.moderation.rules]]
+chatrooms = ["123@chatroom"]
+words = ["代开发票"]
+escalation = ["warn", "remove"]
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        let rule = &v1.moderation.rules[0];
+        assert_eq!(rule.step(1), ModerationStep::Warn);
+        assert_eq!(rule.step(5), ModerationStep::Remove);
+        assert_eq!(ModerationRule::default().step(2), ModerationStep::Revoke);
+
+        let moderation = v2.server.moderation.as_mut().unwrap();
+        moderation.rules[0].patterns = vec!["(".to_string()];
+        moderation.rules.push(ModerationRule {
+            chatrooms: vec!["123".to_string()],
+            ..Default::default()
+        });
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("server.moderation: rules[0]: 正则无效 (")));
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.moderation: rules[1]: words 与 patterns 不能同时为空"));
+        assert!(errors
+            .iter()
+            .any(|e| e == "server.moderation: rules[1]: 群 ID 应以 @chatroom 结尾: 123"));
+    }
 
-[[rule_instances]]
-id = "instance1"
-template = "template1"
+    #[test]
+    fn test_app_config_v2_own_message_echo() {
+        let config_content = r#"
+config_version = 2
 
-[rule_instances.overrides]
-reply_text = "Overridden reply"
-reply_mode = "quote_and_at"
-log = false
+[server]
+own_message_echo = "event"
 "#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
+        let v1 = AppConfigV2::parse(config_content)
+            .unwrap()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.own_message_echo, OwnEchoPolicy::Event);
+        assert_eq!(AppConfig::default().own_message_echo, OwnEchoPolicy::Skip);
+    }
 
-        let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
+    #[test]
+    fn test_app_config_v2_chatroom_aliases() {
+        let config_content = r#"
+config_version = 2
 
-        let rule = &v1.bots[0].rules[0];
-        assert_eq!(rule.action.reply_text, Some("Overridden reply".to_string()));
-        assert_eq!(rule.action.reply_mode, Some(ReplyMode::QuoteAndAt));
-        assert_eq!(rule.action.log, Some(false));
+[chatroom_aliases]
+"123@chatroom" = "技术群"
+"#;
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.chatroom_aliases["123@chatroom"], "技术群");
+        assert!(toml::to_string(&v2).unwrap().contains("[chatroom_aliases]"));
+
+        v2.chatroom_aliases
+            .insert("wxid_alice".to_string(), "Alice".to_string());
+        v2.chatroom_aliases
+            .insert("456@chatroom".to_string(), " ".to_string());
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "chatroom_aliases: 群 ID 应以 @chatroom 结尾: wxid_alice"));
+        assert!(errors
+            .iter()
+            .any(|e| e == "chatroom_aliases: 别名不能为空: 456@chatroom"));
     }
 
     #[test]
-    fn test_app_config_v2_into_v1_with_ai_profile() {
-        // 测试包含 AI profile 的转换
-        use std::io::Write;
-        use tempfile::NamedTempFile;
-
-        let mut tmpfile = NamedTempFile::new().unwrap();
+    fn test_app_config_v2_persona() {
         let config_content = r#"
 config_version = 2
 
-[[bots]]
-app_id = "test_app"
-token = "test_token"
-base_url = "https://api.example.com"
+[[ai_profiles]]
+id = "friendly"
+model = "gpt-4o-mini"
+system_prompt = "你是一个热情的助手"
 
 [[ai_profiles]]
-id = "gpt4"
-model = "gpt-4"
-provider = "openai"
-api_key = "test_key"
-system_prompt = "You are a helpful assistant"
-user_prefix = "User: "
+id = "expert"
+model = "gpt-4o"
 
-[[rule_templates]]
-id = "ai_template"
-[rule_templates.action]
-ai_profile = "gpt4"
+[[ai_profiles]]
+id = "internal"
+model = "gpt-4o"
 
-[[rule_instances]]
-id = "ai_instance"
-template = "ai_template"
+[persona]
+admins = ["wxid_admin"]
+profiles = ["friendly", "expert"]
 "#;
-        tmpfile.write_all(config_content.as_bytes()).unwrap();
-        tmpfile.flush().unwrap();
-
-        let v2 = AppConfigV2::parse(config_content).unwrap();
-        let v1 = v2.into_v1(tmpfile.path()).unwrap();
-
-        let rule = &v1.bots[0].rules[0];
-        assert!(rule.action.ai.is_some());
-        let ai = rule.action.ai.as_ref().unwrap();
-        assert_eq!(ai.model, "gpt-4");
-        assert_eq!(ai.provider, Some("openai".to_string()));
-        assert_eq!(ai.api_key, Some("test_key".to_string()));
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2
+            .clone()
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.persona.admins, vec!["wxid_admin"]);
         assert_eq!(
-            ai.system_prompt,
-            Some("You are a helpful assistant".to_string())
+            v1.persona.profiles.keys().collect::<Vec<_>>(),
+            ["expert", "friendly"]
         );
-        assert_eq!(ai.user_prefix, Some("User: ".to_string()));
+        assert_eq!(
+            v1.persona.profiles["friendly"].system_prompt.as_deref(),
+            Some("你是一个热情的助手")
+        );
+
+        v2.persona = Some(PersonaConfigV2 {
+            admins: vec![],
+            profiles: vec!["missing".to_string()],
+        });
+        let errors = v2.validate();
+        assert!(errors
+            .iter()
+            .any(|e| e == "persona.admins 或 server.admins 至少需要一个管理员 wxid"));
+        assert!(errors
+            .iter()
+            .any(|e| e == "persona.profiles: 引用的 ai_profile 不存在: missing"));
+
+        // 未单独配置时沿用全局管理员
+        v2.server.admins = vec!["wxid_owner".to_string()];
+        assert!(!v2.validate().iter().any(|e| e.contains("admins")));
+        let v1 = v2
+            .into_v1(std::path::Path::new("/tmp/config.toml"))
+            .unwrap();
+        assert_eq!(v1.admins, ["wxid_owner"]);
+        assert!(v1.persona.admins.is_empty());
     }
 
     #[test]
@@ -2244,6 +6947,59 @@ template = "ai_template"
         assert_eq!(cmd.timeout_secs, Some(30));
     }
 
+    #[test]
+    fn test_app_config_v2_into_v1_pre_tool() {
+        // 前置工具（如 OCR）转换为 AiAction.command，输出作为提示词上下文
+        let config_content = r#"
+config_version = 2
+
+[[bots]]
+app_id = "test_app"
+token = "test_token"
+base_url = "https://api.example.com"
+
+[[tools]]
+id = "screenshot_ocr"
+program = "ocr"
+
+[tools.ocr]
+provider = "tesseract"
+languages = "chi_sim"
+
+[[ai_profiles]]
+id = "doc_qa"
+model = "gpt-4o"
+pre_tool = "screenshot_ocr"
+
+[[rule_templates]]
+id = "doc_template"
+[rule_templates.action]
+ai_profile = "doc_qa"
+
+[[rule_instances]]
+id = "doc_instance"
+template = "doc_template"
+"#;
+        let v2 = AppConfigV2::parse(config_content).unwrap();
+        assert!(v2.validate().is_empty());
+        let v1 = v2.into_v1(Path::new("bot-app.v2.toml")).unwrap();
+
+        let ai = v1.bots[0].rules[0].action.ai.as_ref().unwrap();
+        assert!(ai.tools.is_empty());
+        let cmd = ai.command.as_ref().unwrap();
+        assert_eq!(cmd.program, "ocr");
+        let ocr = cmd.ocr.as_ref().unwrap();
+        assert_eq!(ocr.provider, OcrProviderKind::Tesseract);
+        assert_eq!(ocr.languages.as_deref(), Some("chi_sim"));
+
+        let mut v2 = AppConfigV2::parse(config_content).unwrap();
+        v2.ai_profiles[0].pre_tool = Some("missing".to_string());
+        assert!(v2
+            .validate()
+            .iter()
+            .any(|e| e.contains("引用的工具不存在: missing")));
+    }
+
     #[test]
     fn test_app_config_v2_into_v1_tool_default_parameters() {
         // 测试工具默认 parameters
@@ -2298,8 +7054,7 @@ template = "ai_template"
         std::fs::write(&prompt_file, "System prompt from file").unwrap();
 
         let mut config_file = NamedTempFile::new_in(tempdir.path()).unwrap();
-        let config_content = format!(
-            r#"
+        let config_content = r#"
 config_version = 2
 
 [[bots]]
@@ -2320,12 +7075,11 @@ ai_profile = "gpt4"
 [[rule_instances]]
 id = "ai_instance"
 template = "ai_template"
-"#
-        );
+"#;
         config_file.write_all(config_content.as_bytes()).unwrap();
         config_file.flush().unwrap();
 
-        let v2 = AppConfigV2::parse(&config_content).unwrap();
+        let v2 = AppConfigV2::parse(config_content).unwrap();
         let v1 = v2.into_v1(config_file.path()).unwrap();
 
         let ai = v1.bots[0].rules[0].action.ai.as_ref().unwrap();
@@ -2516,7 +7270,13 @@ template = "template2"
             (RuleKind::Emoji, "emoji"),
             (RuleKind::Link, "link"),
             (RuleKind::FileNotice, "file_notice"),
+            (RuleKind::Location, "location"),
+            (RuleKind::RedPacket, "red_packet"),
+            (RuleKind::Transfer, "transfer"),
+            (RuleKind::NameCard, "name_card"),
             (RuleKind::ContactEvent, "contact_event"),
+            (RuleKind::MemberJoined, "member_joined"),
+            (RuleKind::MemberLeft, "member_left"),
             (RuleKind::Any, "any"),
         ];
 
@@ -0,0 +1,310 @@
+//! 导入已有的聊天导出数据
+//!
+//! 把其他工具导出的微信聊天记录（CSV，或 JSON 数组 / JSONL）转换为会话消息记录，
+//! 让搜索、统计与 AI 上下文也能用上机器人部署之前的历史。常见导出工具的字段名均可识别：
+//!
+//! | 字段 | 可用的列名 |
+//! | --- | --- |
+//! | 时间 | `at`、`time`、`timestamp`、`CreateTime`、`create_time`、`StrTime` |
+//! | 会话 | `chat`、`talker`、`room`、`chatroom`、`StrTalker` |
+//! | 发送者 | `sender`、`Sender`、`from`、`wxid` |
+//! | 内容 | `text`、`content`、`msg`、`StrContent` |
+//! | 类型 | `kind`、`Type`、`type`、`type_name` |
+//! | 方向 | `is_send`、`IsSender`、`is_sender`、`direction` |
+//! | 消息 ID | `new_msg_id`、`MsgSvrID` |
+//!
+//! 时间支持秒或毫秒时间戳、RFC 3339 与按本地时区解析的 `YYYY-MM-DD HH:MM:SS`；
+//! 类型支持微信的数字类型与中文类型名。运行 `gewe-bot-app import-history <文件> --app-id <机器人>`
+//! 导入。
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use crate::storage::{HistoryDirection, HistoryMessage};
+
+const TIME_FIELDS: &[&str] = &[
+    "at",
+    "time",
+    "timestamp",
+    "CreateTime",
+    "create_time",
+    "StrTime",
+];
+const CHAT_FIELDS: &[&str] = &["chat", "talker", "room", "chatroom", "StrTalker"];
+const SENDER_FIELDS: &[&str] = &["sender", "Sender", "from", "wxid"];
+const TEXT_FIELDS: &[&str] = &["text", "content", "msg", "StrContent"];
+const KIND_FIELDS: &[&str] = &["kind", "Type", "type", "type_name"];
+const DIRECTION_FIELDS: &[&str] = &["is_send", "IsSender", "is_sender", "direction"];
+const MSG_ID_FIELDS: &[&str] = &["new_msg_id", "MsgSvrID"];
+
+/// 解析导出文件：`.csv` 按 CSV 解析，其余按 JSON 数组或 JSONL 解析。
+/// `chat` 为未带会话列时使用的会话
+pub fn parse_export(path: &Path, app_id: &str, chat: Option<&str>) -> Result<Vec<HistoryMessage>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("读取导出文件失败: {}", path.display()))?;
+    let is_csv = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    let records = if is_csv {
+        parse_csv(&content)?
+    } else {
+        parse_json(&content)?
+    };
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            convert_record(record, app_id, chat).with_context(|| format!("第 {} 条记录", i + 1))
+        })
+        .collect()
+}
+
+fn parse_json(content: &str) -> Result<Vec<Map<String, Value>>> {
+    let trimmed = content.trim_start_matches('\u{feff}').trim();
+    let values: Vec<Value> = if trimmed.starts_with('[') {
+        serde_json::from_str(trimmed).context("解析 JSON 数组失败")?
+    } else {
+        trimmed
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line).with_context(|| format!("解析第 {} 行 JSON 失败", i + 1))
+            })
+            .collect::<Result<_>>()?
+    };
+    values
+        .into_iter()
+        .map(|value| match value {
+            Value::Object(map) => Ok(map),
+            other => Err(anyhow!("记录不是 JSON 对象: {}", other)),
+        })
+        .collect()
+}
+
+/// 解析带表头的 CSV，每行按表头转换为字段表；列数少于表头的行只取已有的列
+fn parse_csv(content: &str) -> Result<Vec<Map<String, Value>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+    let header: Vec<String> = reader
+        .headers()
+        .context("解析 CSV 表头失败")?
+        .iter()
+        .map(|name| name.trim().to_string())
+        .collect();
+    if header.iter().all(|name| name.is_empty()) {
+        bail!("CSV 文件为空");
+    }
+    let mut records = Vec::new();
+    for (i, row) in reader.records().enumerate() {
+        let row = row.with_context(|| format!("解析 CSV 第 {} 行失败", i + 2))?;
+        if row.iter().all(|field| field.is_empty()) {
+            continue;
+        }
+        records.push(
+            header
+                .iter()
+                .zip(row.iter())
+                .map(|(name, field)| (name.clone(), Value::String(field.to_string())))
+                .collect(),
+        );
+    }
+    Ok(records)
+}
+
+fn field<'a>(record: &'a Map<String, Value>, names: &[&str]) -> Option<&'a Value> {
+    names
+        .iter()
+        .filter_map(|name| record.get(*name))
+        .find(|value| match value {
+            Value::Null => false,
+            Value::String(s) => !s.trim().is_empty(),
+            _ => true,
+        })
+}
+
+fn field_str(record: &Map<String, Value>, names: &[&str]) -> Option<String> {
+    field(record, names).map(|value| match value {
+        Value::String(s) => s.trim().to_string(),
+        other => other.to_string(),
+    })
+}
+
+fn convert_record(
+    record: &Map<String, Value>,
+    app_id: &str,
+    default_chat: Option<&str>,
+) -> Result<HistoryMessage> {
+    let at = field(record, TIME_FIELDS)
+        .ok_or_else(|| anyhow!("缺少时间字段"))
+        .and_then(parse_time)?;
+    let chat = field_str(record, CHAT_FIELDS)
+        .or_else(|| default_chat.map(str::to_string))
+        .ok_or_else(|| anyhow!("缺少会话字段，可用 --chat 指定"))?;
+    let direction = match field(record, DIRECTION_FIELDS) {
+        Some(value) => parse_direction(value)?,
+        None => HistoryDirection::In,
+    };
+    let mut sender = field_str(record, SENDER_FIELDS);
+    let mut text = field_str(record, TEXT_FIELDS);
+    // 群聊导出的内容常以 "wxid:\n" 开头标明发送者
+    if sender.is_none() && chat.ends_with("@chatroom") {
+        if let Some((prefix, rest)) = text.as_deref().and_then(|t| t.split_once(":\n")) {
+            if !prefix.is_empty() && !prefix.contains(char::is_whitespace) {
+                sender = Some(prefix.to_string());
+                text = Some(rest.to_string());
+            }
+        }
+    }
+    if direction == HistoryDirection::Out {
+        sender = None;
+    } else if sender.is_none() && !chat.ends_with("@chatroom") {
+        sender = Some(chat.clone());
+    }
+    let kind = field_str(record, KIND_FIELDS)
+        .map(|kind| normalize_kind(&kind))
+        .unwrap_or_else(|| "text".to_string());
+    let new_msg_id = field_str(record, MSG_ID_FIELDS)
+        .map(|id| {
+            id.parse::<i64>()
+                .with_context(|| format!("消息 ID 不是整数: {}", id))
+        })
+        .transpose()?;
+    Ok(HistoryMessage {
+        at,
+        app_id: app_id.to_string(),
+        chat,
+        sender,
+        direction,
+        kind,
+        text,
+        new_msg_id,
+        media_url: None,
+    })
+}
+
+fn parse_time(value: &Value) -> Result<DateTime<Utc>> {
+    let raw = match value {
+        Value::String(s) => s.trim().to_string(),
+        other => other.to_string(),
+    };
+    if let Ok(number) = raw.parse::<i64>() {
+        // 超过 10^11 视为毫秒时间戳
+        let parsed = if number.abs() >= 100_000_000_000 {
+            DateTime::from_timestamp_millis(number)
+        } else {
+            DateTime::from_timestamp(number, 0)
+        };
+        return parsed.ok_or_else(|| anyhow!("时间戳超出范围: {}", raw));
+    }
+    if let Ok(at) = DateTime::parse_from_rfc3339(&raw) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(&raw, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(&raw, "%Y/%m/%d %H:%M:%S"))
+        .with_context(|| format!("无法解析的时间: {}", raw))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|at| at.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("本地时区不存在该时间: {}", raw))
+}
+
+fn parse_direction(value: &Value) -> Result<HistoryDirection> {
+    let raw = match value {
+        Value::String(s) => s.trim().to_ascii_lowercase(),
+        other => other.to_string(),
+    };
+    match raw.as_str() {
+        "1" | "true" | "out" | "send" | "sent" => Ok(HistoryDirection::Out),
+        "0" | "false" | "in" | "recv" | "received" => Ok(HistoryDirection::In),
+        _ => bail!("无法识别的消息方向: {}", raw),
+    }
+}
+
+/// 把微信数字类型、中文类型名规范化为消息记录使用的类型
+fn normalize_kind(kind: &str) -> String {
+    match kind.trim() {
+        "1" | "文本" => "text",
+        "3" | "图片" => "image",
+        "34" | "语音" => "voice",
+        "43" | "视频" => "video",
+        "47" | "动画表情" | "表情" => "emoji",
+        "49" | "链接" => "link",
+        "文件" => "file",
+        other => return other.to_ascii_lowercase(),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_csv_export() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("export.csv");
+        std::fs::write(
+            &path,
+            "\u{feff}MsgSvrID,Type,IsSender,CreateTime,StrTalker,StrContent\r\n\
+             11,1,0,1700000000,123@chatroom,\"wxid_a:\n早上好, 各位\"\r\n\
+             12,3,1,1700000060,123@chatroom,\"[图片]\"\r\n\
+             13,文本,0,1700000120000,wxid_b,\"说 \"\"你好\"\"\"\r\n",
+        )
+        .unwrap();
+        let messages = parse_export(&path, "wx_app", None).unwrap();
+        assert_eq!(messages.len(), 3);
+
+        assert_eq!(messages[0].chat, "123@chatroom");
+        assert_eq!(messages[0].sender.as_deref(), Some("wxid_a"));
+        assert_eq!(messages[0].text.as_deref(), Some("早上好, 各位"));
+        assert_eq!(messages[0].new_msg_id, Some(11));
+        assert_eq!(messages[0].at.timestamp(), 1_700_000_000);
+
+        assert_eq!(messages[1].direction, HistoryDirection::Out);
+        assert_eq!(messages[1].sender, None);
+        assert_eq!(messages[1].kind, "image");
+
+        assert_eq!(messages[2].at.timestamp(), 1_700_000_120);
+        assert_eq!(messages[2].sender.as_deref(), Some("wxid_b"));
+        assert_eq!(messages[2].text.as_deref(), Some("说 \"你好\""));
+    }
+
+    #[test]
+    fn test_parse_json_and_jsonl_export() {
+        let temp = TempDir::new().unwrap();
+        let array = temp.path().join("export.json");
+        std::fs::write(
+            &array,
+            r#"[{"time": "2024-01-02T03:04:05Z", "sender": "wxid_a", "content": "hi", "type": "图片"}]"#,
+        )
+        .unwrap();
+        let messages = parse_export(&array, "wx_app", Some("123@chatroom")).unwrap();
+        assert_eq!(messages[0].chat, "123@chatroom");
+        assert_eq!(messages[0].kind, "image");
+        assert_eq!(messages[0].at.to_rfc3339(), "2024-01-02T03:04:05+00:00");
+
+        let lines = temp.path().join("export.jsonl");
+        std::fs::write(
+            &lines,
+            "{\"timestamp\": 1700000000, \"talker\": \"wxid_b\", \"msg\": \"a\", \"is_send\": true}\n\n\
+             {\"timestamp\": 1700000001, \"talker\": \"wxid_b\", \"msg\": \"b\"}\n",
+        )
+        .unwrap();
+        let messages = parse_export(&lines, "wx_app", None).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].direction, HistoryDirection::Out);
+        assert_eq!(messages[1].sender.as_deref(), Some("wxid_b"));
+
+        let missing_chat = temp.path().join("missing.json");
+        std::fs::write(&missing_chat, r#"[{"time": 1700000000, "text": "x"}]"#).unwrap();
+        let err = parse_export(&missing_chat, "wx_app", None).unwrap_err();
+        assert!(format!("{:#}", err).contains("--chat"));
+    }
+}
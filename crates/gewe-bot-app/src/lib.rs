@@ -5,5 +5,12 @@
 pub mod api;
 pub mod config;
 pub mod dispatcher;
+pub mod history_import;
+pub mod llm;
+pub mod log_buffer;
+pub mod push;
+pub mod schedule;
 pub mod storage;
+pub mod telemetry;
 pub mod tools;
+pub mod watchdog;
@@ -0,0 +1,429 @@
+//! Azure OpenAI provider
+//!
+//! Azure 按部署名路由请求，地址形如
+//! `{endpoint}/openai/deployments/{deployment}/chat/completions?api-version={version}`，
+//! 并通过 `api-key` 请求头鉴权，与 OpenAI 兼容接口不同，因此直接调用 HTTP 接口，
+//! 未启用 `ai` 特性时也可使用。
+
+use super::{
+    resolve_ai_api_key, CompletionRequest, LlmProvider, LlmResponse, LlmStream, LlmToolCall,
+};
+use crate::config::AiAction;
+use crate::tools::TokenUsage;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 未配置 api_version 时使用的 GA 版本
+const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Azure OpenAI 部署
+pub struct AzureOpenAiProvider {
+    endpoint: String,
+    deployment: String,
+    api_version: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl AzureOpenAiProvider {
+    pub fn new(endpoint: &str, deployment: &str, api_version: &str, api_key: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| anyhow!("创建 Azure OpenAI 客户端失败: {e}"))?;
+        Ok(Self {
+            endpoint: endpoint.trim().trim_end_matches('/').to_string(),
+            deployment: deployment.to_string(),
+            api_version: api_version.to_string(),
+            api_key: api_key.to_string(),
+            client,
+        })
+    }
+
+    /// base_url 为资源终结点，部署名默认与 model 相同
+    pub fn from_config(action: &AiAction) -> Result<Self> {
+        let endpoint = action
+            .base_url
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+            .ok_or_else(|| anyhow!("Azure OpenAI 需要配置 base_url 为资源终结点"))?;
+        let api_key = resolve_ai_api_key(action)?;
+        let azure = action.azure.clone().unwrap_or_default();
+        Self::new(
+            endpoint,
+            azure.deployment.as_deref().unwrap_or(&action.model),
+            azure
+                .api_version
+                .as_deref()
+                .unwrap_or(DEFAULT_AZURE_API_VERSION),
+            &api_key,
+        )
+    }
+
+    fn url(&self, deployment: &str, operation: &str) -> String {
+        format!(
+            "{}/openai/deployments/{}/{}?api-version={}",
+            self.endpoint, deployment, operation, self.api_version
+        )
+    }
+
+    async fn post(&self, url: String, body: &Value) -> Result<reqwest::Response> {
+        let resp = self
+            .client
+            .post(url)
+            .header("api-key", &self.api_key)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Azure OpenAI 请求失败: {e}"))?;
+        check_status(resp).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AzureOpenAiProvider {
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse> {
+        let body = chat_body(request, false);
+        let value: Value = self
+            .post(self.url(&self.deployment, "chat/completions"), &body)
+            .await?
+            .json()
+            .await
+            .map_err(|e| anyhow!("解析 Azure OpenAI 响应失败: {e}"))?;
+        Ok(parse_chat_response(&value))
+    }
+
+    async fn complete_stream(&self, request: CompletionRequest) -> Result<LlmStream> {
+        let body = chat_body(request, true);
+        let mut resp = self
+            .post(self.url(&self.deployment, "chat/completions"), &body)
+            .await?;
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            // 响应为 SSE，每个 `data:` 行携带一段增量文本，以 `data: [DONE]` 结束
+            let mut buf = Vec::new();
+            loop {
+                let chunk = match resp.chunk().await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => break,
+                    Err(e) => {
+                        let _ = tx
+                            .send(Err(anyhow!("Azure OpenAI 流式响应失败: {e}")))
+                            .await;
+                        return;
+                    }
+                };
+                buf.extend_from_slice(&chunk);
+                while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = buf.drain(..=pos).collect();
+                    let Some(piece) = parse_sse_line(&line) else {
+                        continue;
+                    };
+                    let failed = piece.is_err();
+                    if tx.send(piece).await.is_err() || failed {
+                        return;
+                    }
+                }
+            }
+            if let Some(piece) = parse_sse_line(&buf) {
+                let _ = tx.send(piece).await;
+            }
+        });
+        Ok(rx)
+    }
+
+    /// model 为 embedding 模型的部署名
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f64>> {
+        let value: Value = self
+            .post(self.url(model, "embeddings"), &json!({ "input": text }))
+            .await?
+            .json()
+            .await
+            .map_err(|e| anyhow!("解析 Azure OpenAI 响应失败: {e}"))?;
+        value["data"][0]["embedding"]
+            .as_array()
+            .map(|v| v.iter().filter_map(Value::as_f64).collect::<Vec<_>>())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("Azure OpenAI 未返回 embedding"))
+    }
+}
+
+/// 非 2xx 响应转换为错误，错误信息包含状态码以便判断是否重试
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    Err(anyhow!(
+        "Azure OpenAI 请求失败（{}）: {}",
+        status.as_u16(),
+        message
+    ))
+}
+
+/// 部署已绑定模型，请求体不需要 model 字段
+fn chat_body(request: CompletionRequest, stream: bool) -> Value {
+    let mut messages = Vec::new();
+    if let Some(preamble) = request.preamble.filter(|p| !p.trim().is_empty()) {
+        messages.push(json!({ "role": "system", "content": preamble }));
+    }
+    for message in &request.history {
+        messages.push(json!({ "role": message.role(), "content": message.content() }));
+    }
+    messages.push(json!({ "role": "user", "content": request.user_content }));
+
+    let mut body = json!({ "messages": messages });
+    if stream {
+        body["stream"] = json!(true);
+    }
+    if let Some(t) = request.temperature {
+        body["temperature"] = json!(t);
+    }
+    if let Some(n) = request.max_tokens {
+        body["max_tokens"] = json!(n);
+    }
+    if !request.tools.is_empty() {
+        body["tools"] = request
+            .tools
+            .iter()
+            .map(|t| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": t.name,
+                        "description": t.description,
+                        "parameters": t.parameters,
+                    }
+                })
+            })
+            .collect();
+    }
+    // 与 OpenAI 相同的附加参数（如 response_format）原样透传
+    if let Some(Value::Object(params)) = request.additional_params {
+        for (key, value) in params {
+            body[key] = value;
+        }
+    }
+    body
+}
+
+fn parse_chat_response(value: &Value) -> LlmResponse {
+    let message = &value["choices"][0]["message"];
+    let text = message["content"]
+        .as_str()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    let tool_call = message["tool_calls"]
+        .as_array()
+        .and_then(|calls| calls.first())
+        .and_then(|call| {
+            let function = &call["function"];
+            Some(LlmToolCall {
+                name: function["name"].as_str()?.to_string(),
+                arguments: function["arguments"].as_str().map(str::to_string),
+            })
+        });
+    LlmResponse {
+        text,
+        tool_call,
+        usage: TokenUsage {
+            input_tokens: value["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            output_tokens: value["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        },
+    }
+}
+
+/// 解析 SSE 的一行；非 data 行、结束标记与不含文本的行返回 None
+fn parse_sse_line(line: &[u8]) -> Option<Result<String>> {
+    let line = std::str::from_utf8(line).ok()?.trim();
+    let data = line.strip_prefix("data:")?.trim();
+    if data.is_empty() || data == "[DONE]" {
+        return None;
+    }
+    let value: Value = match serde_json::from_str(data) {
+        Ok(v) => v,
+        Err(e) => return Some(Err(anyhow!("解析 Azure OpenAI 流式响应失败: {e}"))),
+    };
+    if let Some(err) = value["error"]["message"].as_str() {
+        return Some(Err(anyhow!("Azure OpenAI 流式响应失败: {err}")));
+    }
+    value["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .map(|s| Ok(s.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AzureOpenAiConfig;
+    use axum::response::IntoResponse;
+    use axum::{extract::OriginalUri, http::HeaderMap, routing::post, Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    type Seen = Arc<Mutex<Vec<(String, Option<String>, Value)>>>;
+
+    /// 启动模拟的 Azure OpenAI 服务，记录请求地址、api-key 与请求体
+    async fn mock_azure() -> (String, Seen) {
+        let seen: Seen = Arc::default();
+        let recorder = seen.clone();
+        let app = Router::new().fallback(post(
+            move |OriginalUri(uri): OriginalUri, headers: HeaderMap, Json(body): Json<Value>| {
+                let recorder = recorder.clone();
+                async move {
+                    let api_key = headers
+                        .get("api-key")
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string);
+                    recorder
+                        .lock()
+                        .unwrap()
+                        .push((uri.to_string(), api_key, body.clone()));
+                    if uri.path().ends_with("/embeddings") {
+                        return Json(json!({ "data": [{ "embedding": [0.5, 0.25] }] }))
+                            .into_response();
+                    }
+                    if body["stream"] == json!(true) {
+                        let sse = ["po", "ng"]
+                            .iter()
+                            .map(|c| {
+                                format!(
+                                    "data: {}\n\n",
+                                    json!({ "choices": [{ "delta": { "content": c } }] })
+                                )
+                            })
+                            .chain(std::iter::once("data: [DONE]\n\n".to_string()))
+                            .collect::<String>();
+                        return sse.into_response();
+                    }
+                    Json(json!({
+                        "choices": [{ "message": { "role": "assistant", "content": "pong" } }],
+                        "usage": { "prompt_tokens": 3, "completion_tokens": 1 }
+                    }))
+                    .into_response()
+                }
+            },
+        ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{addr}"), seen)
+    }
+
+    fn request() -> CompletionRequest {
+        CompletionRequest {
+            preamble: Some("你是助手".to_string()),
+            history: Vec::new(),
+            user_content: "ping".to_string(),
+            tools: vec![],
+            temperature: Some(0.2),
+            max_tokens: Some(64),
+            additional_params: Some(json!({ "response_format": { "type": "json_object" } })),
+        }
+    }
+
+    fn action(base_url: Option<String>) -> AiAction {
+        AiAction {
+            provider: Some("azure-openai".to_string()),
+            model: "gpt-4o".to_string(),
+            api_key: Some("azure-key".to_string()),
+            base_url,
+            azure: Some(AzureOpenAiConfig {
+                deployment: Some("gpt4o-prod".to_string()),
+                api_version: Some("2025-01-01-preview".to_string()),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_from_config_requires_endpoint() {
+        let err = AzureOpenAiProvider::from_config(&action(None))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("base_url"));
+    }
+
+    #[test]
+    fn test_deployment_defaults_to_model() {
+        let mut action = action(Some("https://res.openai.azure.com/".to_string()));
+        action.azure = None;
+        let provider = AzureOpenAiProvider::from_config(&action).unwrap();
+        assert_eq!(
+            provider.url(&provider.deployment, "chat/completions"),
+            "https://res.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete_uses_deployment_url() {
+        let (base_url, seen) = mock_azure().await;
+        let provider = AzureOpenAiProvider::from_config(&action(Some(base_url))).unwrap();
+        let resp = provider.complete(request()).await.unwrap();
+        assert_eq!(resp.text.as_deref(), Some("pong"));
+        assert_eq!(resp.usage.input_tokens, 3);
+        assert_eq!(resp.usage.output_tokens, 1);
+
+        let seen = seen.lock().unwrap();
+        let (uri, api_key, body) = &seen[0];
+        assert_eq!(
+            uri,
+            "/openai/deployments/gpt4o-prod/chat/completions?api-version=2025-01-01-preview"
+        );
+        assert_eq!(api_key.as_deref(), Some("azure-key"));
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["max_tokens"], 64);
+        assert_eq!(body["response_format"]["type"], "json_object");
+        assert!(body.get("model").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_complete_stream_and_embed() {
+        let (base_url, seen) = mock_azure().await;
+        let provider = AzureOpenAiProvider::from_config(&action(Some(base_url))).unwrap();
+
+        let mut rx = provider.complete_stream(request()).await.unwrap();
+        let mut text = String::new();
+        while let Some(piece) = rx.recv().await {
+            text.push_str(&piece.unwrap());
+        }
+        assert_eq!(text, "pong");
+
+        let embedding = provider.embed("embedding-prod", "hi").await.unwrap();
+        assert_eq!(embedding, vec![0.5, 0.25]);
+        assert_eq!(
+            seen.lock().unwrap()[1].0,
+            "/openai/deployments/embedding-prod/embeddings?api-version=2025-01-01-preview"
+        );
+    }
+
+    #[test]
+    fn test_parse_chat_response_tool_call() {
+        let value = json!({
+            "choices": [{ "message": {
+                "content": null,
+                "tool_calls": [{ "function": { "name": "lookup", "arguments": "{\"q\":1}" } }]
+            } }]
+        });
+        let resp = parse_chat_response(&value);
+        assert!(resp.text.is_none());
+        let call = resp.tool_call.unwrap();
+        assert_eq!(call.name, "lookup");
+        assert_eq!(call.arguments.as_deref(), Some("{\"q\":1}"));
+    }
+}
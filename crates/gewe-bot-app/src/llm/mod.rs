@@ -0,0 +1,847 @@
+//! LLM provider 抽象
+//!
+//! `LlmProvider` 定义 completion、流式 completion 与 embedding 三个能力，内置的
+//! OpenAI/Anthropic/Gemini 实现基于 rig，仅在启用 `ai` 特性时编译。`LlmRegistry`
+//! 按规则中的 `provider` 名称创建实例，库使用者可注册自己的实现（如本地 llama.cpp），
+//! 无需修改本 crate。Ollama 与 Azure OpenAI 直接走 HTTP 接口，未启用 `ai` 特性时也可使用。
+
+mod azure;
+mod ollama;
+
+pub use azure::AzureOpenAiProvider;
+pub use ollama::{OllamaProvider, DEFAULT_OLLAMA_EMBEDDING_MODEL};
+
+use crate::config::AiAction;
+use crate::tools::TokenUsage;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time;
+
+#[cfg(feature = "ai")]
+use futures::StreamExt;
+#[cfg(feature = "ai")]
+use rig::completion::{self, CompletionModel};
+#[cfg(feature = "ai")]
+use rig::embeddings::EmbeddingModel;
+#[cfg(feature = "ai")]
+use rig::prelude::*;
+#[cfg(feature = "ai")]
+use rig::providers::{anthropic, gemini, openai};
+#[cfg(feature = "ai")]
+use rig::streaming::StreamedAssistantContent;
+
+/// 未指定 provider 时使用的名称，也是未注册名称的兜底（OpenAI 兼容接口）
+pub const DEFAULT_PROVIDER: &str = "openai";
+
+/// 提供给模型的工具定义
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// 对话历史中的一条消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatMessage {
+    User(String),
+    Assistant(String),
+}
+
+impl ChatMessage {
+    /// OpenAI 兼容接口中的 role
+    pub fn role(&self) -> &'static str {
+        match self {
+            ChatMessage::User(_) => "user",
+            ChatMessage::Assistant(_) => "assistant",
+        }
+    }
+
+    pub fn content(&self) -> &str {
+        match self {
+            ChatMessage::User(text) | ChatMessage::Assistant(text) => text,
+        }
+    }
+}
+
+/// completion 请求：可选的对话历史加本轮用户消息
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "ai"), allow(dead_code))]
+pub struct CompletionRequest {
+    pub preamble: Option<String>,
+    /// 此前的对话，按时间先后排列，不含本轮用户消息
+    pub history: Vec<ChatMessage>,
+    pub user_content: String,
+    pub tools: Vec<ToolDefinition>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u64>,
+    /// 透传给 provider 的额外参数
+    pub additional_params: Option<serde_json::Value>,
+}
+
+#[cfg(feature = "ai")]
+impl From<CompletionRequest> for completion::CompletionRequest {
+    fn from(req: CompletionRequest) -> Self {
+        let mut chat_history: Vec<completion::Message> = req
+            .history
+            .into_iter()
+            .map(|m| match m {
+                ChatMessage::User(text) => completion::Message::user(text),
+                ChatMessage::Assistant(text) => completion::Message::assistant(text),
+            })
+            .collect();
+        chat_history.push(completion::Message::user(req.user_content));
+        completion::CompletionRequest {
+            preamble: req.preamble,
+            chat_history: rig::OneOrMany::many(chat_history).expect("至少包含本轮用户消息"),
+            tools: req
+                .tools
+                .into_iter()
+                .map(|t| completion::ToolDefinition {
+                    name: t.name,
+                    description: t.description,
+                    parameters: t.parameters,
+                })
+                .collect(),
+            tool_choice: None,
+            temperature: req.temperature,
+            max_tokens: req.max_tokens,
+            additional_params: req.additional_params,
+            documents: vec![],
+        }
+    }
+}
+
+/// LLM 响应结果
+#[derive(Debug, Clone, Default)]
+pub struct LlmResponse {
+    /// 文本回复（如果有）
+    pub text: Option<String>,
+    /// 工具调用（如果有）
+    pub tool_call: Option<LlmToolCall>,
+    pub usage: TokenUsage,
+}
+
+/// LLM 工具调用
+#[derive(Debug, Clone)]
+pub struct LlmToolCall {
+    pub name: String,
+    #[allow(dead_code)]
+    pub arguments: Option<String>,
+}
+
+/// 流式回复，按到达顺序接收文本片段；发送端关闭即结束
+pub type LlmStream = mpsc::Receiver<Result<String>>;
+
+/// LLM provider
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// 执行一次 completion
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse>;
+
+    /// 流式 completion；默认实现等待完整回复后一次性返回
+    async fn complete_stream(&self, request: CompletionRequest) -> Result<LlmStream> {
+        let response = self.complete(request).await?;
+        let (tx, rx) = mpsc::channel(1);
+        if let Some(text) = response.text {
+            let _ = tx.send(Ok(text)).await;
+        }
+        Ok(rx)
+    }
+
+    /// 计算文本 embedding；默认不支持
+    #[cfg_attr(not(feature = "ai"), allow(dead_code))]
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f64>> {
+        let _ = (model, text);
+        Err(anyhow!("该 LLM provider 不支持 embedding"))
+    }
+}
+
+/// 根据规则配置创建 provider
+pub type ProviderFactory = Arc<dyn Fn(&AiAction) -> Result<Arc<dyn LlmProvider>> + Send + Sync>;
+
+/// provider 注册表，键为规则中 `provider` 的取值
+#[derive(Clone)]
+pub struct LlmRegistry {
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl LlmRegistry {
+    /// 不含内置 provider 的空注册表
+    pub fn empty() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// 注册 provider，同名时覆盖（可用于替换内置实现）
+    #[cfg_attr(not(feature = "ai"), allow(dead_code))]
+    pub fn register<F>(&mut self, name: impl Into<String>, factory: F) -> &mut Self
+    where
+        F: Fn(&AiAction) -> Result<Arc<dyn LlmProvider>> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.into(), Arc::new(factory));
+        self
+    }
+
+    /// 按规则中的 provider 创建客户端；未注册的名称按 OpenAI 兼容接口处理
+    pub fn client(&self, action: &AiAction) -> Result<LlmClient> {
+        let name = action.provider.as_deref().unwrap_or(DEFAULT_PROVIDER);
+        let factory = self
+            .factories
+            .get(name)
+            .or_else(|| self.factories.get(DEFAULT_PROVIDER))
+            .ok_or_else(|| {
+                anyhow!(
+                    "未注册的 LLM provider: {}（内置 provider 需要启用 ai 特性）",
+                    name
+                )
+            })?;
+        Ok(LlmClient::new(factory(action)?))
+    }
+}
+
+impl Default for LlmRegistry {
+    /// 包含内置 provider：ollama、azure-openai（别名 azure），以及启用 `ai` 特性时的
+    /// openai、anthropic（别名 claude）、gemini（别名 google）与 openrouter
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("ollama", |action| {
+            Ok(Arc::new(OllamaProvider::from_config(action)?))
+        });
+        for name in ["azure-openai", "azure"] {
+            registry.register(name, |action| {
+                Ok(Arc::new(AzureOpenAiProvider::from_config(action)?))
+            });
+        }
+        #[cfg(feature = "ai")]
+        {
+            registry.register(DEFAULT_PROVIDER, |action| {
+                Ok(Arc::new(OpenAiProvider::from_config(action)?))
+            });
+            for name in ["anthropic", "claude"] {
+                registry.register(name, |action| {
+                    Ok(Arc::new(AnthropicProvider::from_config(action)?))
+                });
+            }
+            for name in ["gemini", "google"] {
+                registry.register(name, |action| {
+                    Ok(Arc::new(GeminiProvider::from_config(action)?))
+                });
+            }
+            registry.register("openrouter", |action| {
+                Ok(Arc::new(OpenRouterProvider::from_config(action)?))
+            });
+        }
+        registry
+    }
+}
+
+/// 优先使用直接配置的 api_key，否则从环境变量读取
+pub fn resolve_ai_api_key(action: &AiAction) -> Result<String> {
+    if let Some(ref key) = action.api_key {
+        return Ok(key.clone());
+    }
+    let env_name = action.api_key_env.as_deref().unwrap_or("GEWE_AI_API_KEY");
+    std::env::var(env_name)
+        .or_else(|_| std::env::var("GEWE_AI_API_KEY"))
+        .map_err(|_| {
+            anyhow!(
+                "未找到 AI API Key，请配置 api_key 或设置环境变量 {}",
+                env_name
+            )
+        })
+}
+
+/// 调用 OpenAI 兼容的 embedding 接口
+#[cfg(feature = "ai")]
+pub async fn embed_text(
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+    text: &str,
+) -> Result<Vec<f64>> {
+    OpenAiProvider::embedding(base_url, api_key)?
+        .embed(model, text)
+        .await
+}
+
+/// 未启用 `ai` 特性时 embedding 不可用
+#[cfg(not(feature = "ai"))]
+pub async fn embed_text(
+    _base_url: &str,
+    _api_key: &str,
+    _model: &str,
+    _text: &str,
+) -> Result<Vec<f64>> {
+    Err(anyhow!("AI 功能未启用，请使用 --features ai 重新编译"))
+}
+
+/// 基于 rig completion 模型的内置 provider
+#[cfg(feature = "ai")]
+pub struct RigProvider<M> {
+    /// 用于错误信息的 provider 名称
+    label: &'static str,
+    model: Option<M>,
+    /// OpenAI 兼容接口同时提供 embedding
+    embedding_client: Option<openai::Client>,
+}
+
+#[cfg(feature = "ai")]
+pub type OpenAiProvider = RigProvider<openai::responses_api::ResponsesCompletionModel>;
+#[cfg(feature = "ai")]
+pub type AnthropicProvider = RigProvider<anthropic::completion::CompletionModel>;
+#[cfg(feature = "ai")]
+pub type GeminiProvider = RigProvider<gemini::completion::CompletionModel>;
+/// OpenRouter 使用 OpenAI 兼容的 Chat Completions 接口
+#[cfg(feature = "ai")]
+pub type OpenRouterProvider = RigProvider<openai::completion::CompletionModel>;
+
+/// OpenRouter 未配置 base_url 时使用的地址
+#[cfg(feature = "ai")]
+pub const DEFAULT_OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+/// OpenRouter 未配置 app_name 时发送的 X-Title
+#[cfg(feature = "ai")]
+pub const DEFAULT_OPENROUTER_APP_NAME: &str = "gewe-bot-app";
+
+#[cfg(feature = "ai")]
+fn openai_client(base_url: &str, api_key: &str) -> Result<openai::Client> {
+    openai::Client::builder()
+        .api_key(api_key)
+        .base_url(base_url.trim_end_matches('/'))
+        .build()
+        .map_err(|e| anyhow!("创建 OpenAI 客户端失败: {}", e))
+}
+
+#[cfg(feature = "ai")]
+impl OpenAiProvider {
+    /// 默认使用 OpenAI 兼容模式，支持自定义 base_url
+    pub fn from_config(action: &AiAction) -> Result<Self> {
+        let api_key = resolve_ai_api_key(action)?;
+        let base_url = action
+            .base_url
+            .as_deref()
+            .unwrap_or("https://api.openai.com/v1");
+        let client = openai_client(base_url, &api_key)?;
+        Ok(Self {
+            label: "OpenAI",
+            model: Some(client.completion_model(&action.model)),
+            embedding_client: Some(client),
+        })
+    }
+
+    /// 只用于 embedding 的实例
+    pub fn embedding(base_url: &str, api_key: &str) -> Result<Self> {
+        Ok(Self {
+            label: "OpenAI",
+            model: None,
+            embedding_client: Some(openai_client(base_url, api_key)?),
+        })
+    }
+}
+
+#[cfg(feature = "ai")]
+impl AnthropicProvider {
+    pub fn from_config(action: &AiAction) -> Result<Self> {
+        let api_key = resolve_ai_api_key(action)?;
+        let mut builder = anthropic::Client::builder().api_key(&api_key);
+        if let Some(ref url) = action.base_url {
+            builder = builder.base_url(url.trim_end_matches('/'));
+        }
+        let client = builder
+            .build()
+            .map_err(|e| anyhow!("创建 Anthropic 客户端失败: {}", e))?;
+        Ok(Self {
+            label: "Anthropic",
+            model: Some(client.completion_model(&action.model)),
+            embedding_client: None,
+        })
+    }
+}
+
+#[cfg(feature = "ai")]
+impl GeminiProvider {
+    pub fn from_config(action: &AiAction) -> Result<Self> {
+        let api_key = resolve_ai_api_key(action)?;
+        let mut builder = gemini::Client::builder().api_key(&api_key);
+        if let Some(ref url) = action.base_url {
+            builder = builder.base_url(url.trim_end_matches('/'));
+        }
+        let client = builder
+            .build()
+            .map_err(|e| anyhow!("创建 Gemini 客户端失败: {}", e))?;
+        Ok(Self {
+            label: "Gemini",
+            model: Some(client.completion_model(&action.model)),
+            embedding_client: None,
+        })
+    }
+}
+
+#[cfg(feature = "ai")]
+impl OpenRouterProvider {
+    /// 按 OpenRouter 要求附带 `HTTP-Referer` 与 `X-Title` 请求头
+    pub fn from_config(action: &AiAction) -> Result<Self> {
+        let api_key = resolve_ai_api_key(action)?;
+        let base_url = action
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_OPENROUTER_BASE_URL);
+        let client = openai::Client::<reqwest::Client>::builder()
+            .api_key(&api_key)
+            .base_url(base_url.trim_end_matches('/'))
+            .http_client(openrouter_http_client(action)?)
+            .build()
+            .map_err(|e| anyhow!("创建 OpenRouter 客户端失败: {}", e))?
+            .completions_api();
+        Ok(Self {
+            label: "OpenRouter",
+            model: Some(client.completion_model(&action.model)),
+            embedding_client: None,
+        })
+    }
+}
+
+/// 带 OpenRouter 归属请求头的 HTTP 客户端
+#[cfg(feature = "ai")]
+fn openrouter_http_client(action: &AiAction) -> Result<reqwest::Client> {
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    let cfg = action.openrouter.clone().unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    if let Some(ref site_url) = cfg.site_url {
+        let value = HeaderValue::from_str(site_url)
+            .map_err(|e| anyhow!("openrouter.site_url 不是合法的请求头: {}", e))?;
+        headers.insert("HTTP-Referer", value);
+    }
+    let app_name = cfg
+        .app_name
+        .as_deref()
+        .unwrap_or(DEFAULT_OPENROUTER_APP_NAME);
+    let value = HeaderValue::from_str(app_name)
+        .map_err(|e| anyhow!("openrouter.app_name 不是合法的请求头: {}", e))?;
+    headers.insert("X-Title", value);
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .map_err(|e| anyhow!("创建 OpenRouter HTTP 客户端失败: {}", e))
+}
+
+#[cfg(feature = "ai")]
+impl<M: CompletionModel> RigProvider<M> {
+    fn model(&self) -> Result<&M> {
+        self.model
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} 实例未配置 completion 模型", self.label))
+    }
+}
+
+#[cfg(feature = "ai")]
+#[async_trait]
+impl<M> LlmProvider for RigProvider<M>
+where
+    M: CompletionModel + Send + Sync + 'static,
+    M::StreamingResponse: Send + 'static,
+{
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse> {
+        let response = self
+            .model()?
+            .completion(request.into())
+            .await
+            .map_err(|e| anyhow!("{} 请求失败: {}", self.label, e))?;
+        Ok(parse_response(response))
+    }
+
+    async fn complete_stream(&self, request: CompletionRequest) -> Result<LlmStream> {
+        let label = self.label;
+        let mut stream = self
+            .model()?
+            .stream(request.into())
+            .await
+            .map_err(|e| anyhow!("{} 请求失败: {}", label, e))?;
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            while let Some(item) = stream.next().await {
+                let chunk = match item {
+                    Ok(StreamedAssistantContent::Text(t)) => Ok(t.text),
+                    Ok(_) => continue,
+                    Err(e) => Err(anyhow!("{} 流式响应失败: {}", label, e)),
+                };
+                let failed = chunk.is_err();
+                if tx.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f64>> {
+        let client = self
+            .embedding_client
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} 不支持 embedding", self.label))?;
+        let embedding = client
+            .embedding_model(model)
+            .embed_text(text)
+            .await
+            .map_err(|e| anyhow!("Embedding 请求失败: {}", e))?;
+        Ok(embedding.vec)
+    }
+}
+
+/// 解析 LLM 响应，提取文本和工具调用
+#[cfg(feature = "ai")]
+fn parse_response<T>(response: completion::CompletionResponse<T>) -> LlmResponse {
+    let mut text = None;
+    let mut tool_call = None;
+
+    for content in response.choice.iter() {
+        match content {
+            completion::AssistantContent::Text(t) => {
+                let content_text = t.text.trim();
+                if !content_text.is_empty() {
+                    text = Some(content_text.to_string());
+                }
+            }
+            completion::AssistantContent::ToolCall(tc) => {
+                tool_call = Some(LlmToolCall {
+                    name: tc.function.name.clone(),
+                    arguments: Some(tc.function.arguments.to_string()),
+                });
+            }
+            _ => {} // 忽略其他内容类型（如 Reasoning）
+        }
+    }
+
+    LlmResponse {
+        text,
+        tool_call,
+        usage: TokenUsage {
+            input_tokens: response.usage.input_tokens,
+            output_tokens: response.usage.output_tokens,
+        },
+    }
+}
+
+/// 调度器使用的 LLM 客户端，在 provider 之上提供重试
+#[derive(Clone)]
+pub struct LlmClient {
+    provider: Arc<dyn LlmProvider>,
+}
+
+impl LlmClient {
+    pub fn new(provider: Arc<dyn LlmProvider>) -> Self {
+        Self { provider }
+    }
+
+    pub fn provider(&self) -> &Arc<dyn LlmProvider> {
+        &self.provider
+    }
+
+    /// 执行 completion 请求
+    pub async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse> {
+        self.provider.complete(request).await
+    }
+
+    /// 带重试的 completion 请求
+    pub async fn complete_with_retry(
+        &self,
+        request_builder: impl Fn() -> CompletionRequest,
+        max_retries: u32,
+        base_delay_ms: u64,
+    ) -> Result<LlmResponse> {
+        let mut last_error = None;
+
+        for attempt in 0..=max_retries {
+            let request = request_builder();
+            match self.complete(request).await {
+                Ok(resp) => return Ok(resp),
+                Err(e) => {
+                    let is_last = attempt == max_retries;
+                    let retryable = Self::is_retryable_error(&e);
+
+                    if is_last || !retryable {
+                        tracing::warn!(
+                            attempt = attempt + 1,
+                            max_retries = max_retries + 1,
+                            retryable,
+                            err = ?e,
+                            "AI 请求失败，不再重试"
+                        );
+                        return Err(e);
+                    }
+
+                    let delay_ms = base_delay_ms * 2u64.pow(attempt);
+                    tracing::info!(
+                        attempt = attempt + 1,
+                        max_retries = max_retries + 1,
+                        delay_ms,
+                        err = ?e,
+                        "AI 请求失败，准备重试"
+                    );
+                    last_error = Some(e);
+                    time::sleep(Duration::from_millis(delay_ms)).await;
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("AI 请求失败")))
+    }
+
+    /// 判断错误是否可重试
+    pub fn is_retryable_error(err: &anyhow::Error) -> bool {
+        let msg = err.to_string().to_lowercase();
+        // 可重试的情况：网络问题、超时、服务端错误、限流
+        msg.contains("timeout")
+            || msg.contains("timed out")
+            || msg.contains("connection")
+            || msg.contains("network")
+            || msg.contains("503")
+            || msg.contains("502")
+            || msg.contains("500")
+            || msg.contains("429")
+            || msg.contains("rate limit")
+            || msg.contains("rate_limit")
+            || msg.contains("overloaded")
+            || msg.contains("temporarily unavailable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 原样返回用户消息的本地 provider
+    struct EchoProvider;
+
+    #[async_trait]
+    impl LlmProvider for EchoProvider {
+        async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse> {
+            Ok(LlmResponse {
+                text: Some(request.user_content),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn request(content: &str) -> CompletionRequest {
+        CompletionRequest {
+            preamble: None,
+            history: Vec::new(),
+            user_content: content.to_string(),
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            additional_params: None,
+        }
+    }
+
+    fn action(provider: Option<&str>) -> AiAction {
+        AiAction {
+            provider: provider.map(str::to_string),
+            api_key: Some("sk-test".to_string()),
+            model: "local-model".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_custom_provider() {
+        let mut registry = LlmRegistry::empty();
+        registry.register("ollama", |_| Ok(Arc::new(EchoProvider)));
+
+        let client = registry.client(&action(Some("ollama"))).unwrap();
+        let response = client.complete(request("你好")).await.unwrap();
+        assert_eq!(response.text.as_deref(), Some("你好"));
+
+        // 空注册表中未注册的 provider 没有兜底
+        let err = registry.client(&action(Some("vllm"))).err().unwrap();
+        assert!(err.to_string().contains("vllm"));
+        assert!(registry.client(&action(None)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_registry_falls_back_to_default_provider() {
+        let mut registry = LlmRegistry::empty();
+        registry.register(DEFAULT_PROVIDER, |_| Ok(Arc::new(EchoProvider)));
+        assert!(registry.client(&action(Some("deepseek"))).is_ok());
+        assert!(registry.client(&action(None)).is_ok());
+    }
+
+    #[test]
+    fn test_default_registry_ollama() {
+        // Ollama 不需要 API Key，也不依赖 ai 特性
+        let mut action = action(Some("ollama"));
+        action.api_key = None;
+        assert!(LlmRegistry::default().client(&action).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_provider_default_stream_and_embed() {
+        let provider = EchoProvider;
+        let mut stream = provider.complete_stream(request("hello")).await.unwrap();
+        assert_eq!(stream.recv().await.unwrap().unwrap(), "hello");
+        assert!(stream.recv().await.is_none());
+        assert!(provider
+            .embed("text-embedding-3-small", "hi")
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "ai")]
+    #[test]
+    fn test_default_registry_builtin_providers() {
+        let registry = LlmRegistry::default();
+        for name in [
+            "openai",
+            "anthropic",
+            "claude",
+            "gemini",
+            "google",
+            "ollama",
+            "openrouter",
+        ] {
+            assert!(registry.client(&action(Some(name))).is_ok(), "{}", name);
+        }
+
+        // Azure OpenAI 必须配置资源终结点
+        assert!(registry.client(&action(Some("azure-openai"))).is_err());
+        let mut azure_action = action(Some("azure-openai"));
+        azure_action.base_url = Some("https://my-resource.openai.azure.com".to_string());
+        assert!(registry.client(&azure_action).is_ok());
+    }
+
+    /// 启动模拟的 OpenAI 兼容服务，记录收到的请求路径与请求头
+    #[cfg(feature = "ai")]
+    async fn mock_chat_server() -> (
+        String,
+        Arc<std::sync::Mutex<Vec<(String, axum::http::HeaderMap)>>>,
+    ) {
+        use axum::{extract::OriginalUri, routing::post, Json, Router};
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let app = Router::new().fallback(post(
+            move |OriginalUri(uri): OriginalUri, headers: axum::http::HeaderMap| {
+                let recorder = recorder.clone();
+                async move {
+                    recorder.lock().unwrap().push((uri.to_string(), headers));
+                    Json(serde_json::json!({
+                        "id": "chatcmpl-1",
+                        "object": "chat.completion",
+                        "created": 0,
+                        "model": "mock",
+                        "choices": [{
+                            "index": 0,
+                            "message": { "role": "assistant", "content": "pong" },
+                            "finish_reason": "stop"
+                        }],
+                        "usage": { "prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4 }
+                    }))
+                }
+            },
+        ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}", addr), seen)
+    }
+
+    #[cfg(feature = "ai")]
+    fn ping() -> CompletionRequest {
+        CompletionRequest {
+            preamble: None,
+            history: Vec::new(),
+            user_content: "ping".to_string(),
+            tools: vec![],
+            temperature: None,
+            max_tokens: None,
+            additional_params: None,
+        }
+    }
+
+    #[cfg(feature = "ai")]
+    #[tokio::test]
+    async fn test_openrouter_attribution_headers() {
+        let (base_url, seen) = mock_chat_server().await;
+        let mut router_action = action(Some("openrouter"));
+        router_action.model = "openai/gpt-4o".to_string();
+        router_action.base_url = Some(base_url);
+        router_action.openrouter = Some(crate::config::OpenRouterConfig {
+            site_url: Some("https://example.com".to_string()),
+            app_name: None,
+        });
+        let provider = OpenRouterProvider::from_config(&router_action).unwrap();
+        let resp = provider.complete(ping()).await.unwrap();
+        assert_eq!(resp.text.as_deref(), Some("pong"));
+
+        let seen = seen.lock().unwrap();
+        let (uri, headers) = &seen[0];
+        assert_eq!(uri, "/chat/completions");
+        assert_eq!(headers.get("http-referer").unwrap(), "https://example.com");
+        assert_eq!(headers.get("x-title").unwrap(), DEFAULT_OPENROUTER_APP_NAME);
+        assert_eq!(headers.get("authorization").unwrap(), "Bearer sk-test");
+    }
+
+    #[test]
+    fn test_is_retryable_error() {
+        // 可重试的错误
+        assert!(LlmClient::is_retryable_error(&anyhow!(
+            "Connection timeout"
+        )));
+        assert!(LlmClient::is_retryable_error(&anyhow!("Request timed out")));
+        assert!(LlmClient::is_retryable_error(&anyhow!(
+            "503 Service Unavailable"
+        )));
+        assert!(LlmClient::is_retryable_error(&anyhow!("502 Bad Gateway")));
+        assert!(LlmClient::is_retryable_error(&anyhow!(
+            "500 Internal Server Error"
+        )));
+        assert!(LlmClient::is_retryable_error(&anyhow!(
+            "429 Rate limit exceeded"
+        )));
+        assert!(LlmClient::is_retryable_error(&anyhow!(
+            "Network connection failed"
+        )));
+        assert!(LlmClient::is_retryable_error(&anyhow!(
+            "Service overloaded"
+        )));
+
+        // 不可重试的错误
+        assert!(!LlmClient::is_retryable_error(&anyhow!("Invalid API key")));
+        assert!(!LlmClient::is_retryable_error(&anyhow!("401 Unauthorized")));
+        assert!(!LlmClient::is_retryable_error(&anyhow!("400 Bad Request")));
+    }
+
+    #[cfg(feature = "ai")]
+    #[test]
+    fn test_completion_request_into_rig() {
+        let req = CompletionRequest {
+            preamble: Some("system".to_string()),
+            history: Vec::new(),
+            user_content: "hi".to_string(),
+            tools: vec![ToolDefinition {
+                name: "lookup".to_string(),
+                description: "查询".to_string(),
+                parameters: serde_json::json!({"type": "object"}),
+            }],
+            temperature: Some(0.5),
+            max_tokens: Some(128),
+            additional_params: None,
+        };
+        let rig_req = completion::CompletionRequest::from(req);
+        assert_eq!(rig_req.preamble.as_deref(), Some("system"));
+        assert_eq!(rig_req.chat_history.len(), 1);
+        assert_eq!(rig_req.tools.len(), 1);
+        assert_eq!(rig_req.tools[0].name, "lookup");
+        assert_eq!(rig_req.max_tokens, Some(128));
+        assert!(rig_req.tool_choice.is_none());
+    }
+}
@@ -0,0 +1,467 @@
+//! Ollama provider
+//!
+//! 直接调用本地 Ollama 的 `/api/chat` 与 `/api/embed`，不依赖 rig，未启用 `ai` 特性时也可使用。
+//! 首次使用某个模型前通过 `/api/show` 确认已下载，未下载时提示执行 `ollama pull`。
+
+use super::{CompletionRequest, LlmProvider, LlmResponse, LlmStream, LlmToolCall};
+use crate::config::AiAction;
+use crate::tools::TokenUsage;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 未配置 base_url 且未设置 OLLAMA_HOST 时使用的地址
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434";
+/// 语义缓存未指定 embedding 模型时使用
+pub const DEFAULT_OLLAMA_EMBEDDING_MODEL: &str = "nomic-embed-text";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 本地模型首次加载较慢，请求超时放宽
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+const SHOW_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 已确认下载的模型，键为 "base_url|model"
+static READY_MODELS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+/// 本地 Ollama 服务
+pub struct OllamaProvider {
+    base_url: String,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl OllamaProvider {
+    pub fn new(base_url: &str, model: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| anyhow!("创建 Ollama 客户端失败: {e}"))?;
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            client,
+        })
+    }
+
+    /// base_url 依次取规则配置、OLLAMA_HOST 环境变量与默认地址；不需要 API Key
+    pub fn from_config(action: &AiAction) -> Result<Self> {
+        let env_host = std::env::var("OLLAMA_HOST").ok();
+        let base_url = action
+            .base_url
+            .as_deref()
+            .or(env_host.as_deref())
+            .filter(|s| !s.trim().is_empty())
+            .map(normalize_host)
+            .unwrap_or_else(|| DEFAULT_OLLAMA_BASE_URL.to_string());
+        Self::new(&base_url, &action.model)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// 确认模型已下载，结果在进程内缓存
+    async fn ensure_model(&self, model: &str) -> Result<()> {
+        let key = format!("{}|{}", self.base_url, model);
+        let ready = READY_MODELS.get_or_init(Default::default);
+        if ready.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+
+        let resp = self
+            .client
+            .post(self.url("/api/show"))
+            .timeout(SHOW_TIMEOUT)
+            .json(&json!({ "model": model }))
+            .send()
+            .await
+            .map_err(|e| anyhow!("无法连接 Ollama（{}）: {e}", self.base_url))?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow!(
+                "Ollama 未找到模型 {model}，请先执行 `ollama pull {model}`"
+            ));
+        }
+        check_status(resp).await?;
+
+        ready.lock().unwrap().insert(key);
+        Ok(())
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<reqwest::Response> {
+        let resp = self
+            .client
+            .post(self.url(path))
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Ollama 请求失败: {e}"))?;
+        check_status(resp).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    async fn complete(&self, request: CompletionRequest) -> Result<LlmResponse> {
+        self.ensure_model(&self.model).await?;
+        let body = chat_body(&self.model, request, false);
+        let value: Value = self
+            .post("/api/chat", &body)
+            .await?
+            .json()
+            .await
+            .map_err(|e| anyhow!("解析 Ollama 响应失败: {e}"))?;
+        Ok(parse_chat_response(&value))
+    }
+
+    async fn complete_stream(&self, request: CompletionRequest) -> Result<LlmStream> {
+        self.ensure_model(&self.model).await?;
+        let body = chat_body(&self.model, request, true);
+        let mut resp = self.post("/api/chat", &body).await?;
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            // 响应为逐行 JSON，每行携带一段增量文本
+            let mut buf = Vec::new();
+            loop {
+                let chunk = match resp.chunk().await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => break,
+                    Err(e) => {
+                        let _ = tx.send(Err(anyhow!("Ollama 流式响应失败: {e}"))).await;
+                        return;
+                    }
+                };
+                buf.extend_from_slice(&chunk);
+                while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = buf.drain(..=pos).collect();
+                    let Some(piece) = parse_stream_line(&line) else {
+                        continue;
+                    };
+                    let failed = piece.is_err();
+                    if tx.send(piece).await.is_err() || failed {
+                        return;
+                    }
+                }
+            }
+            if let Some(piece) = parse_stream_line(&buf) {
+                let _ = tx.send(piece).await;
+            }
+        });
+        Ok(rx)
+    }
+
+    async fn embed(&self, model: &str, text: &str) -> Result<Vec<f64>> {
+        self.ensure_model(model).await?;
+        let value: Value = self
+            .post("/api/embed", &json!({ "model": model, "input": text }))
+            .await?
+            .json()
+            .await
+            .map_err(|e| anyhow!("解析 Ollama 响应失败: {e}"))?;
+        value["embeddings"][0]
+            .as_array()
+            .map(|v| v.iter().filter_map(Value::as_f64).collect::<Vec<_>>())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("Ollama 未返回 embedding"))
+    }
+}
+
+/// OLLAMA_HOST 允许省略协议（如 0.0.0.0:11434）
+fn normalize_host(host: &str) -> String {
+    let host = host.trim().trim_end_matches('/');
+    if host.contains("://") {
+        host.to_string()
+    } else {
+        format!("http://{host}")
+    }
+}
+
+/// 非 2xx 响应转换为错误，错误信息包含状态码以便判断是否重试
+async fn check_status(resp: reqwest::Response) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|v| v["error"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    Err(anyhow!(
+        "Ollama 请求失败（{}）: {}",
+        status.as_u16(),
+        message
+    ))
+}
+
+fn chat_body(model: &str, request: CompletionRequest, stream: bool) -> Value {
+    let mut messages = Vec::new();
+    if let Some(preamble) = request.preamble.filter(|p| !p.trim().is_empty()) {
+        messages.push(json!({ "role": "system", "content": preamble }));
+    }
+    for message in &request.history {
+        messages.push(json!({ "role": message.role(), "content": message.content() }));
+    }
+    messages.push(json!({ "role": "user", "content": request.user_content }));
+
+    let mut body = json!({
+        "model": model,
+        "messages": messages,
+        "stream": stream,
+    });
+
+    let mut options = serde_json::Map::new();
+    if let Some(t) = request.temperature {
+        options.insert("temperature".to_string(), json!(t));
+    }
+    if let Some(n) = request.max_tokens {
+        options.insert("num_predict".to_string(), json!(n));
+    }
+    if !options.is_empty() {
+        body["options"] = Value::Object(options);
+    }
+
+    if !request.tools.is_empty() {
+        body["tools"] = request
+            .tools
+            .iter()
+            .map(|t| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": t.name,
+                        "description": t.description,
+                        "parameters": t.parameters,
+                    }
+                })
+            })
+            .collect();
+    }
+
+    // 结构化输出：有 schema 时按 schema 约束，否则开启 JSON 模式
+    if let Some(rf) = request
+        .additional_params
+        .as_ref()
+        .and_then(|p| p.get("response_format"))
+    {
+        if let Some(schema) = rf.get("schema") {
+            body["format"] = schema.clone();
+        } else if rf["type"].as_str().is_some_and(|t| t.starts_with("json")) {
+            body["format"] = json!("json");
+        }
+    }
+    body
+}
+
+fn parse_chat_response(value: &Value) -> LlmResponse {
+    let message = &value["message"];
+    let text = message["content"]
+        .as_str()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    let tool_call = message["tool_calls"]
+        .as_array()
+        .and_then(|calls| calls.first())
+        .and_then(|call| {
+            let function = &call["function"];
+            Some(LlmToolCall {
+                name: function["name"].as_str()?.to_string(),
+                arguments: function.get("arguments").map(Value::to_string),
+            })
+        });
+    LlmResponse {
+        text,
+        tool_call,
+        usage: TokenUsage {
+            input_tokens: value["prompt_eval_count"].as_u64().unwrap_or(0),
+            output_tokens: value["eval_count"].as_u64().unwrap_or(0),
+        },
+    }
+}
+
+/// 解析流式响应的一行；空行与不含文本的行返回 None
+fn parse_stream_line(line: &[u8]) -> Option<Result<String>> {
+    let line = std::str::from_utf8(line).ok()?.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let value: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(e) => return Some(Err(anyhow!("解析 Ollama 流式响应失败: {e}"))),
+    };
+    if let Some(err) = value["error"].as_str() {
+        return Some(Err(anyhow!("Ollama 流式响应失败: {err}")));
+    }
+    value["message"]["content"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .map(|s| Ok(s.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{ChatMessage, ToolDefinition};
+    use axum::{routing::post, Json, Router};
+
+    fn request(content: &str) -> CompletionRequest {
+        CompletionRequest {
+            preamble: Some("你是助手".to_string()),
+            history: Vec::new(),
+            user_content: content.to_string(),
+            tools: vec![],
+            temperature: Some(0.2),
+            max_tokens: Some(64),
+            additional_params: None,
+        }
+    }
+
+    /// 启动模拟的 Ollama 服务，只提供 llama3 与 nomic-embed-text 两个模型
+    async fn mock_ollama() -> String {
+        async fn show(Json(body): Json<Value>) -> axum::response::Response {
+            use axum::response::IntoResponse;
+            match body["model"].as_str() {
+                Some("llama3" | "nomic-embed-text") => Json(json!({})).into_response(),
+                other => (
+                    reqwest::StatusCode::NOT_FOUND,
+                    Json(json!({ "error": format!("model '{}' not found", other.unwrap_or("")) })),
+                )
+                    .into_response(),
+            }
+        }
+        async fn chat(Json(body): Json<Value>) -> axum::response::Response {
+            use axum::response::IntoResponse;
+            let content = body["messages"][1]["content"]
+                .as_str()
+                .unwrap_or("")
+                .to_string();
+            if body["stream"] == json!(true) {
+                let lines = content
+                    .chars()
+                    .map(|c| {
+                        json!({ "message": { "content": c.to_string() }, "done": false })
+                            .to_string()
+                    })
+                    .chain([json!({ "message": { "content": "" }, "done": true }).to_string()])
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return lines.into_response();
+            }
+            Json(json!({
+                "message": { "role": "assistant", "content": format!("echo: {content}") },
+                "prompt_eval_count": 12,
+                "eval_count": 3,
+                "done": true,
+            }))
+            .into_response()
+        }
+        async fn embed(Json(_): Json<Value>) -> Json<Value> {
+            Json(json!({ "embeddings": [[0.1, 0.2, 0.3]] }))
+        }
+
+        let app = Router::new()
+            .route("/api/show", post(show))
+            .route("/api/chat", post(chat))
+            .route("/api/embed", post(embed));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_ollama_complete_stream_and_embed() {
+        let base_url = mock_ollama().await;
+        let provider = OllamaProvider::new(&base_url, "llama3").unwrap();
+
+        let response = provider.complete(request("你好")).await.unwrap();
+        assert_eq!(response.text.as_deref(), Some("echo: 你好"));
+        assert_eq!(response.usage.input_tokens, 12);
+        assert_eq!(response.usage.output_tokens, 3);
+
+        let mut stream = provider.complete_stream(request("hi")).await.unwrap();
+        let mut text = String::new();
+        while let Some(piece) = stream.recv().await {
+            text.push_str(&piece.unwrap());
+        }
+        assert_eq!(text, "hi");
+
+        let embedding = provider.embed("nomic-embed-text", "你好").await.unwrap();
+        assert_eq!(embedding, vec![0.1, 0.2, 0.3]);
+    }
+
+    #[tokio::test]
+    async fn test_ollama_missing_model() {
+        let base_url = mock_ollama().await;
+        let provider = OllamaProvider::new(&base_url, "qwen2").unwrap();
+        let err = provider.complete(request("你好")).await.unwrap_err();
+        assert!(err.to_string().contains("ollama pull qwen2"), "{err}");
+    }
+
+    #[test]
+    fn test_chat_body() {
+        let mut req = request("查天气");
+        req.tools = vec![ToolDefinition {
+            name: "weather".to_string(),
+            description: "查询天气".to_string(),
+            parameters: json!({ "type": "object" }),
+        }];
+        req.additional_params = Some(json!({ "response_format": { "type": "json_object" } }));
+        let body = chat_body("llama3", req, false);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "查天气");
+        assert_eq!(body["options"]["num_predict"], 64);
+        assert_eq!(body["tools"][0]["function"]["name"], "weather");
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["stream"], false);
+    }
+
+    #[test]
+    fn test_chat_body_history() {
+        let mut req = request("那明天呢");
+        req.history = vec![
+            ChatMessage::User("今天北京天气".to_string()),
+            ChatMessage::Assistant("晴，25 度".to_string()),
+        ];
+        let body = chat_body("llama3", req, false);
+        let roles: Vec<&str> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert_eq!(body["messages"][3]["content"], "那明天呢");
+    }
+
+    #[test]
+    fn test_parse_chat_response_tool_call() {
+        let resp = parse_chat_response(&json!({
+            "message": {
+                "content": "",
+                "tool_calls": [{ "function": { "name": "weather", "arguments": { "city": "北京" } } }]
+            }
+        }));
+        assert!(resp.text.is_none());
+        let call = resp.tool_call.unwrap();
+        assert_eq!(call.name, "weather");
+        assert!(call.arguments.unwrap().contains("北京"));
+        assert_eq!(resp.usage.total(), 0);
+    }
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("0.0.0.0:11434"), "http://0.0.0.0:11434");
+        assert_eq!(
+            normalize_host("https://ollama.local/"),
+            "https://ollama.local"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! 最近日志环形缓冲
+//!
+//! 作为 tracing Layer 挂在全局订阅器上，保留最近的日志事件，供 `/pages/logs` 在浏览器中查看
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Utc};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// 默认保留的日志条数
+const DEFAULT_CAPACITY: usize = 2000;
+
+/// 一条日志
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    /// 自增序号，用于增量拉取
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub level: Level,
+    pub target: String,
+    /// 消息及附带字段（key=value），已去除 ANSI 颜色码
+    pub message: String,
+}
+
+/// 日志查询条件
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery<'a> {
+    /// 最低级别，如 WARN 表示只看 WARN 与 ERROR
+    pub level: Option<Level>,
+    /// target 前缀，如 gewe_bot_app::dispatcher
+    pub target: Option<&'a str>,
+    /// 消息包含的关键字
+    pub keyword: Option<&'a str>,
+    /// 只返回序号大于该值的日志
+    pub after: u64,
+    pub limit: usize,
+}
+
+#[derive(Debug)]
+struct Inner {
+    entries: VecDeque<LogEntry>,
+    next_seq: u64,
+}
+
+/// 固定容量的日志缓冲，满后丢弃最旧的日志
+#[derive(Debug)]
+pub struct LogBuffer {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: Mutex::new(Inner {
+                entries: VecDeque::new(),
+                next_seq: 1,
+            }),
+        }
+    }
+
+    /// 全局缓冲，由 init_tracing 挂载的 Layer 写入
+    pub fn global() -> &'static LogBuffer {
+        static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+        BUFFER.get_or_init(|| LogBuffer::new(DEFAULT_CAPACITY))
+    }
+
+    pub fn push(&self, level: Level, target: &str, message: String) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        if inner.entries.len() >= self.capacity {
+            inner.entries.pop_front();
+        }
+        inner.entries.push_back(LogEntry {
+            seq,
+            at: Utc::now(),
+            level,
+            target: target.to_string(),
+            message,
+        });
+    }
+
+    /// 按条件返回最近的日志，按时间正序
+    pub fn query(&self, query: &LogQuery<'_>) -> Vec<LogEntry> {
+        let Ok(inner) = self.inner.lock() else {
+            return Vec::new();
+        };
+        let mut entries: Vec<_> = inner
+            .entries
+            .iter()
+            .rev()
+            .filter(|e| e.seq > query.after)
+            .filter(|e| query.level.is_none_or(|level| e.level <= level))
+            .filter(|e| query.target.is_none_or(|t| e.target.starts_with(t)))
+            .filter(|e| query.keyword.is_none_or(|k| e.message.contains(k)))
+            .take(query.limit)
+            .cloned()
+            .collect();
+        entries.reverse();
+        entries
+    }
+}
+
+/// 把事件写入 [`LogBuffer`] 的 Layer
+pub struct LogBufferLayer {
+    buffer: &'static LogBuffer,
+}
+
+impl LogBufferLayer {
+    pub fn new(buffer: &'static LogBuffer) -> Self {
+        Self { buffer }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        self.buffer.push(
+            *metadata.level(),
+            metadata.target(),
+            strip_ansi(&visitor.finish()),
+        );
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl MessageVisitor {
+    fn finish(self) -> String {
+        match (self.message.is_empty(), self.fields.is_empty()) {
+            (_, true) => self.message,
+            (true, false) => self.fields,
+            (false, false) => format!("{} {}", self.message, self.fields),
+        }
+    }
+}
+
+impl MessageVisitor {
+    fn push_field(&mut self, name: &str, value: std::fmt::Arguments<'_>) {
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let _ = write!(self.fields, "{}={}", name, value);
+    }
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.push_field(field.name(), format_args!("{}", value));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.push_field(field.name(), format_args!("{:?}", value));
+        }
+    }
+}
+
+/// 去除 `\x1b[...m` 形式的 ANSI 颜色码
+fn strip_ansi(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' && chars.peek() == Some(&'[') {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_log_buffer_capacity_and_query() {
+        let buffer = LogBuffer::new(3);
+        buffer.push(
+            Level::INFO,
+            "gewe_bot_app::dispatcher",
+            "规则命中".to_string(),
+        );
+        buffer.push(Level::DEBUG, "gewe_bot_app::dispatcher", "跳过".to_string());
+        buffer.push(Level::WARN, "gewe_http", "请求失败".to_string());
+        buffer.push(Level::ERROR, "gewe_bot_app::api", "保存失败".to_string());
+
+        let all = buffer.query(&LogQuery {
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].seq, 2);
+
+        let warn = buffer.query(&LogQuery {
+            level: Some(Level::WARN),
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(warn.len(), 2);
+
+        let target = buffer.query(&LogQuery {
+            target: Some("gewe_bot_app"),
+            keyword: Some("保存"),
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(target.len(), 1);
+        assert_eq!(target[0].level, Level::ERROR);
+
+        let after = buffer.query(&LogQuery {
+            after: 3,
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(after.len(), 1);
+    }
+
+    #[test]
+    fn test_log_buffer_layer() {
+        static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+        let buffer = BUFFER.get_or_init(|| LogBuffer::new(10));
+        let subscriber = tracing_subscriber::registry().with(LogBufferLayer::new(buffer));
+        tracing::subscriber::with_default(subscriber, || {
+            let app = "\u{1b}[34mwx_app\u{1b}[0m";
+            tracing::info!(app_id = %app, count = 2, "规则命中");
+        });
+        let entries = buffer.query(&LogQuery {
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message, "规则命中 app_id=wx_app count=2");
+        assert_eq!(entries[0].level, Level::INFO);
+    }
+}
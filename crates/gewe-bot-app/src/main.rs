@@ -1,28 +1,52 @@
 mod api;
 mod config;
 mod dispatcher;
+mod history_import;
+mod llm;
+mod log_buffer;
+mod push;
+mod schedule;
 mod storage;
+mod telemetry;
 mod tools;
+mod watchdog;
 
-use crate::api::{api_router, auth, pages_router, ApiState};
+use crate::api::{
+    api_router, auth, history_link_router, media_router, pages_router, protected_pages_router,
+    ApiState,
+};
 use crate::config::AppConfig;
 use crate::dispatcher::Dispatcher;
+use crate::log_buffer::{LogBuffer, LogBufferLayer};
+use crate::storage::HistoryStore;
 use axum::{middleware, response::Html, routing::get, Router};
 use gewe_core::{AppId, BotContext};
 use gewe_session::{InMemorySessionStore, SessionStore};
-use gewe_webhook::{router_with_channel_and_state, WebhookBuilderOptions};
+use gewe_webhook::{fixtures, router_with_channel_and_store, WebhookBuilderOptions, WebhookEvent};
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::mpsc;
 use tower::make::Shared;
 use tower_http::services::ServeDir;
+use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    if std::env::args().nth(1).as_deref() == Some("import-fixtures") {
+        return import_fixtures();
+    }
+    if std::env::args().nth(1).as_deref() == Some("import-history") {
+        return import_history().await;
+    }
+    if std::env::args().nth(1).as_deref() == Some("reindex-history") {
+        return reindex_history().await;
+    }
     let config_path = std::env::args().nth(1);
     let app_config = AppConfig::load(config_path.as_deref())?;
     init_tracing();
+    let metrics_handle = telemetry::install()?;
 
     // 确保图片目录存在
     tokio::fs::create_dir_all(&app_config.image_dir).await?;
@@ -33,6 +57,12 @@ async fn main() -> anyhow::Result<()> {
         .map(PathBuf::from)
         .or_else(|| std::env::var("GEWE_BOT_CONFIG").ok().map(PathBuf::from))
         .unwrap_or_else(|| PathBuf::from("config/bot-app.v2.toml"));
+    if !config_file_path.exists() {
+        tracing::info!(
+            bots = app_config.bots.len(),
+            "未找到配置文件，已从环境变量加载配置，管理端的配置编辑不可用"
+        );
+    }
 
     let prompts_dir = config_file_path
         .parent()
@@ -49,59 +79,84 @@ async fn main() -> anyhow::Result<()> {
         tracing::warn!(error = ?e, "API 状态初始化失败，部分功能可能不可用");
     }
 
-    let (webhook_router, rx, store) =
-        router_with_channel_and_state::<InMemorySessionStore>(WebhookBuilderOptions {
-            queue_size: app_config.queue_size,
-        });
-
-    for bot in &app_config.bots {
-        store
-            .put_session(BotContext {
-                app_id: AppId(bot.app_id.clone()),
-                token: bot.token.clone(),
-                webhook_secret: bot.webhook_secret.clone(),
-                description: Some("gewe-bot-app bot".to_string()),
-            })
-            .await;
-    }
+    let (webhook_router, rx) = build_webhook_router(&app_config).await?;
 
     // 合并 webhook 路由、API 路由、Pages 路由和静态文件路由
     let image_url_prefix = app_config.image_url_prefix.trim_start_matches('/');
 
     // 可选的鉴权中间件
-    let api_router = if std::env::var("GEWE_API_TOKEN").is_ok() {
+    if std::env::var("GEWE_API_TOKEN").is_ok() {
         tracing::info!("API Token 鉴权已启用");
-        api_router(api_state.clone()).route_layer(middleware::from_fn(auth::auth_middleware))
     } else if std::env::var("GEWE_API_USERNAME").is_ok() {
         tracing::info!("Basic Auth 鉴权已启用");
-        api_router(api_state.clone()).route_layer(middleware::from_fn(auth::basic_auth_middleware))
     } else {
         tracing::warn!("API 鉴权未启用，生产环境建议设置 GEWE_API_TOKEN 或 GEWE_API_USERNAME/GEWE_API_PASSWORD");
-        api_router(api_state.clone())
-    };
+    }
+    let api_router = with_api_auth(api_router(api_state.clone()));
+    // Prometheus 指标，与 API 使用相同的鉴权
+    let metrics_router = with_api_auth(telemetry::router(metrics_handle));
+    // 日志等敏感页面与 API 使用相同的鉴权
+    let protected_pages = with_api_auth(protected_pages_router(api_state.clone()));
 
     let router: Router = webhook_router
+        .merge(metrics_router)
         .route("/", get(index_page))
         .nest("/api", api_router)
-        .nest("/pages", pages_router(api_state))
+        .nest("/media", media_router(api_state.clone()))
+        .nest("/history", history_link_router(api_state.clone()))
+        .nest("/pages", pages_router(api_state).merge(protected_pages))
         .nest_service(
             &format!("/{}", image_url_prefix),
             ServeDir::new(&app_config.image_dir),
         );
 
     let dispatcher = Dispatcher::new(&app_config)?;
+    // 多个进程连接同一 Redis 时，多机器人协同的登记也放在 Redis 中跨进程共享
+    #[cfg(feature = "redis")]
+    let dispatcher = match std::env::var("GEWE_REDIS_URL") {
+        Ok(url) => dispatcher.with_claim_store(std::sync::Arc::new(
+            gewe_session::redis_store::RedisClaimStore::new(&url, "gewe:claim")?,
+        )),
+        Err(_) => dispatcher,
+    };
     let shared = std::sync::Arc::new(dispatcher);
+    shared.restore_state().await;
+    // 定时任务：热备健康检查、灰度发布评估、安全模式与会话设置同步、提醒、转人工工单催办、任务表中的待办日报与倒计时播报，并保存运行时状态
+    let scheduler = shared.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(30));
+        loop {
+            ticker.tick().await;
+            scheduler.check_failovers().await;
+            scheduler.check_canary().await;
+            scheduler.sync_safe_mode().await;
+            scheduler.sync_chat_settings().await;
+            scheduler.post_due_reminders(chrono::Utc::now()).await;
+            scheduler.remind_escalations(chrono::Utc::now()).await;
+            scheduler.run_scheduled_jobs(chrono::Local::now()).await;
+            scheduler.persist_state().await;
+        }
+    });
+    // 异步命令与 AI 任务在后台执行，不占用事件处理的并发额度
+    tokio::spawn(shared.clone().run_command_jobs());
+    tokio::spawn(shared.clone().run_ai_tasks());
+    // 在线看护：离线时断线重连并通知管理员
+    tokio::spawn(shared.clone().run_online_watchdog());
     let mut event_rx = rx;
     let concurrency = std::sync::Arc::new(tokio::sync::Semaphore::new(
         app_config.max_concurrency.max(1),
     ));
     tokio::spawn(async move {
         while let Some(event) = event_rx.recv().await {
+            telemetry::queue_depth(event_rx.len());
             let permit = concurrency.clone().acquire_owned().await;
             let shared = shared.clone();
             tokio::spawn(async move {
                 let _permit = permit;
-                if let Err(err) = shared.handle(event).await {
+                telemetry::event_started();
+                let result = shared.handle(event).await;
+                telemetry::event_finished(result.is_ok());
+                if let Err(err) = result {
                     tracing::warn!(?err, "事件处理失败");
                 }
             });
@@ -121,7 +176,134 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// 按环境变量为路由加上 API 鉴权：设置 GEWE_API_TOKEN 时校验 Token，其次设置 GEWE_API_USERNAME 时使用 Basic Auth
+fn with_api_auth(router: Router) -> Router {
+    if std::env::var("GEWE_API_TOKEN").is_ok() {
+        router.route_layer(middleware::from_fn(auth::auth_middleware))
+    } else if std::env::var("GEWE_API_USERNAME").is_ok() {
+        router.route_layer(middleware::from_fn(auth::basic_auth_middleware))
+    } else {
+        router
+    }
+}
+
 /// 返回前端主页面（占位）
+/// 构建 webhook 路由并登记机器人上下文；启用 redis 特性且设置了 GEWE_REDIS_URL 时会话存入 Redis
+async fn build_webhook_router(
+    app_config: &AppConfig,
+) -> anyhow::Result<(Router, mpsc::Receiver<WebhookEvent>)> {
+    let opts = WebhookBuilderOptions {
+        queue_size: app_config.queue_size,
+    };
+
+    #[cfg(feature = "redis")]
+    if let Ok(url) = std::env::var("GEWE_REDIS_URL") {
+        let store = Arc::new(gewe_session::redis_store::RedisSessionStore::new(
+            &url,
+            "gewe:session",
+        )?);
+        register_bots(store.as_ref(), app_config).await;
+        tracing::info!("会话存储使用 Redis");
+        return Ok(router_with_channel_and_store(opts, store));
+    }
+
+    let store = Arc::new(InMemorySessionStore::default());
+    register_bots(store.as_ref(), app_config).await;
+    Ok(router_with_channel_and_store(opts, store))
+}
+
+async fn register_bots<S: SessionStore>(store: &S, app_config: &AppConfig) {
+    for bot in &app_config.bots {
+        store
+            .put_session(BotContext {
+                app_id: AppId(bot.app_id.clone()),
+                token: bot.token.clone(),
+                webhook_secret: bot.webhook_secret.clone(),
+                description: Some("gewe-bot-app bot".to_string()),
+            })
+            .await;
+    }
+}
+
+/// `gewe-bot-app import-fixtures <dump 目录> [夹具目录]`：把 GEWE_WEBHOOK_DUMP_DIR 落盘的回调脱敏后导入为测试夹具
+fn import_fixtures() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(2);
+    let dump_dir = args
+        .next()
+        .or_else(|| std::env::var("GEWE_WEBHOOK_DUMP_DIR").ok())
+        .ok_or_else(|| {
+            anyhow::anyhow!("用法: gewe-bot-app import-fixtures <dump 目录> [夹具目录]")
+        })?;
+    let fixtures_dir = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(fixtures::normalize_fixtures_dir);
+    for path in fixtures::import_dump_dir(Path::new(&dump_dir), &fixtures_dir)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// `gewe-bot-app import-history <导出文件> --app-id <机器人> [--chat <会话>] [--config <配置文件>]`：
+/// 把其他工具导出的聊天记录（CSV / JSON / JSONL）导入会话消息记录，与已有记录合并去重
+async fn import_history() -> anyhow::Result<()> {
+    const USAGE: &str = "用法: gewe-bot-app import-history <导出文件> --app-id <机器人> [--chat <会话>] [--config <配置文件>]";
+    let mut file = None;
+    let mut app_id = None;
+    let mut chat = None;
+    let mut config_path = None;
+    let mut args = std::env::args().skip(2);
+    while let Some(arg) = args.next() {
+        let slot = match arg.as_str() {
+            "--app-id" => &mut app_id,
+            "--chat" => &mut chat,
+            "--config" => &mut config_path,
+            _ if file.is_none() && !arg.starts_with("--") => {
+                file = Some(arg);
+                continue;
+            }
+            _ => anyhow::bail!("未知参数 {}\n{}", arg, USAGE),
+        };
+        *slot = Some(
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{} 缺少取值\n{}", arg, USAGE))?,
+        );
+    }
+    let (file, app_id) = file.zip(app_id).ok_or_else(|| anyhow::anyhow!(USAGE))?;
+    let app_config = AppConfig::load(config_path.as_deref())?;
+    let messages = history_import::parse_export(Path::new(&file), &app_id, chat.as_deref())?;
+    let summary = HistoryStore::from_config(&app_config.data_dir, &app_config.history)
+        .map_err(|e| anyhow::anyhow!(e))?
+        .import(messages)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    println!(
+        "已导入 {} 个会话：新增 {} 条，重复跳过 {} 条，超出保留上限丢弃 {} 条",
+        summary.chats, summary.added, summary.duplicates, summary.dropped
+    );
+    Ok(())
+}
+
+/// `gewe-bot-app reindex-history [--config <配置文件>]`：清空全文搜索索引并按现有的会话消息记录重建
+async fn reindex_history() -> anyhow::Result<()> {
+    let config_path = match std::env::args().skip(2).collect::<Vec<_>>().as_slice() {
+        [] => None,
+        [flag, path] if flag == "--config" => Some(path.clone()),
+        _ => anyhow::bail!("用法: gewe-bot-app reindex-history [--config <配置文件>]"),
+    };
+    let app_config = AppConfig::load(config_path.as_deref())?;
+    if app_config.history.search.is_none() {
+        anyhow::bail!("未配置全文搜索索引（server.history.search）");
+    }
+    let indexed = HistoryStore::from_config(&app_config.data_dir, &app_config.history)
+        .map_err(|e| anyhow::anyhow!(e))?
+        .reindex()
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    println!("已重建搜索索引，读取 {} 条消息", indexed);
+    Ok(())
+}
+
 async fn index_page() -> Html<&'static str> {
     Html(
         r#"<!DOCTYPE html>
@@ -156,27 +338,31 @@ fn init_tracing() {
     let log_file = std::env::var("GEWE_LOG_FILE").ok();
     let rolling = std::env::var("GEWE_LOG_ROLLING").unwrap_or_else(|_| "daily".to_string());
 
-    if let Some(path) = log_file {
+    let fmt_layer: Box<dyn Layer<Registry> + Send + Sync> = if let Some(path) = log_file {
         let writer = make_file_writer(&path, &rolling);
-        let builder = tracing_subscriber::fmt()
-            .with_env_filter(env_filter)
+        let layer = tracing_subscriber::fmt::layer()
             .with_ansi(false)
             .with_writer(writer);
         if use_json {
-            builder.json().flatten_event(true).finish().init();
+            layer.json().flatten_event(true).boxed()
         } else {
-            builder.finish().init();
+            layer.boxed()
         }
     } else {
-        let builder = tracing_subscriber::fmt()
-            .with_env_filter(env_filter)
-            .with_ansi(!use_json);
+        let layer = tracing_subscriber::fmt::layer().with_ansi(!use_json);
         if use_json {
-            builder.json().flatten_event(true).finish().init();
+            layer.json().flatten_event(true).boxed()
         } else {
-            builder.finish().init();
+            layer.boxed()
         }
-    }
+    };
+
+    // 同时写入最近日志缓冲，供 /pages/logs 查看
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(LogBufferLayer::new(LogBuffer::global()))
+        .with(env_filter)
+        .init();
 }
 
 fn make_file_writer(path: &str, rolling: &str) -> tracing_appender::non_blocking::NonBlocking {
@@ -0,0 +1,135 @@
+//! UI 客户端推送通道
+//!
+//! 收到的消息与每次发送的结果经全局广播发布，`/api/ws` 的 WebSocket 客户端按订阅的机器人、
+//! 会话过滤后接收；没有客户端连接时不产生任何开销
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::storage::HistoryMessage;
+
+/// 广播缓冲的容量，客户端落后超过该数量时丢弃其最早的消息
+const PUSH_CAPACITY: usize = 512;
+
+/// 发送结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SendStatus {
+    Sent,
+    Failed,
+    /// 影子模式下未实际发送
+    Shadowed,
+}
+
+/// 推送给 UI 客户端的消息，以 `type` 字段区分
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PushMessage {
+    /// 收到的消息，字段与会话消息记录相同
+    Event {
+        #[serde(flatten)]
+        message: HistoryMessage,
+    },
+    /// 一次发送的结果
+    SendStatus {
+        at: DateTime<Utc>,
+        app_id: String,
+        /// 接收方：群聊 ID 或私聊 wxid
+        to: String,
+        kind: String,
+        status: SendStatus,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        new_msg_id: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+impl PushMessage {
+    pub fn app_id(&self) -> &str {
+        match self {
+            PushMessage::Event { message } => &message.app_id,
+            PushMessage::SendStatus { app_id, .. } => app_id,
+        }
+    }
+
+    /// 所属会话
+    pub fn chat(&self) -> &str {
+        match self {
+            PushMessage::Event { message } => &message.chat,
+            PushMessage::SendStatus { to, .. } => to,
+        }
+    }
+}
+
+/// 推送消息的广播端
+#[derive(Debug)]
+pub struct PushHub {
+    tx: broadcast::Sender<PushMessage>,
+}
+
+impl PushHub {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self { tx }
+    }
+
+    /// 全局推送通道，由调度器发布、`/api/ws` 订阅
+    pub fn global() -> &'static PushHub {
+        static HUB: OnceLock<PushHub> = OnceLock::new();
+        HUB.get_or_init(|| PushHub::new(PUSH_CAPACITY))
+    }
+
+    /// 是否有客户端订阅，没有时调用方可跳过构造消息
+    pub fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    /// 发布消息；没有订阅者时直接丢弃
+    pub fn publish(&self, message: PushMessage) {
+        if self.has_subscribers() {
+            let _ = self.tx.send(message);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PushMessage> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_status(app_id: &str) -> PushMessage {
+        PushMessage::SendStatus {
+            at: Utc::now(),
+            app_id: app_id.to_string(),
+            to: "123@chatroom".to_string(),
+            kind: "text".to_string(),
+            status: SendStatus::Failed,
+            new_msg_id: None,
+            error: Some("timeout".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_push_message_serializes_with_type_tag() {
+        let hub = PushHub::new(4);
+        hub.publish(send_status("wx1"));
+        let mut rx = hub.subscribe();
+        hub.publish(send_status("wx2"));
+        // 订阅之前发布的消息被丢弃
+        let message = rx.try_recv().unwrap();
+        assert_eq!(message.app_id(), "wx2");
+        assert_eq!(message.chat(), "123@chatroom");
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(value["type"], "send_status");
+        assert_eq!(value["status"], "failed");
+        assert_eq!(value["error"], "timeout");
+        assert!(value.get("new_msg_id").is_none());
+    }
+}
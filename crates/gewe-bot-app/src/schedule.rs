@@ -0,0 +1,1040 @@
+//! 定时计划
+//!
+//! 定时任务按每日 `HH:MM` 或 5 段 cron 表达式（分 时 日 月 周）执行，可用 IANA 时区名
+//! （如 `Asia/Shanghai`）指定时区，未指定时使用本机时区。cron 表达式由 `cron` crate 解析，
+//! 时区规则使用 chrono-tz 内置的时区数据库。
+
+use crate::storage::next_daily_run;
+use chrono::{
+    DateTime, Datelike, Duration, DurationRound, FixedOffset, Local, NaiveDate, NaiveDateTime,
+    NaiveTime, Offset, TimeZone, Timelike, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use cron::TimeUnitSpec;
+use serde::Serialize;
+
+/// cron 最多向后搜索的年数：2 月 29 日最长 8 年出现一次
+const CRON_SEARCH_YEARS: i32 = 8;
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const WEEKDAY_ZH: [&str; 7] = ["日", "一", "二", "三", "四", "五", "六"];
+
+/// 5 段 cron 表达式（分 时 日 月 周），由 `cron` crate 解析，支持 `*`、列表、范围、步长、
+/// 英文月份/星期缩写与 `@daily` 等宏。周字段 0 与 7 都表示周日，日与周同时限定时满足其一即可
+/// （与 Vixie cron 一致）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    source: String,
+    /// `cron` crate 对日与周取交集，两者同时限定时拆成只限日与只限周两个计划
+    schedules: Vec<cron::Schedule>,
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let source = expr.split_whitespace().collect::<Vec<_>>().join(" ");
+        let lower = source.to_ascii_lowercase();
+        let expanded = match lower.as_str() {
+            "" => return Err("cron 表达式不能为空".to_string()),
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            macro_name if macro_name.starts_with('@') => {
+                return Err(format!("不支持的 cron 宏: {}", source))
+            }
+            fields => fields,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "cron 表达式应为 5 段（分 时 日 月 周），当前为 {} 段: {}",
+                fields.len(),
+                source
+            ));
+        };
+
+        let weekdays = weekday_field(weekday)?;
+        let variants = if day.starts_with('*') || weekday.starts_with('*') {
+            vec![(day, weekdays.as_str())]
+        } else {
+            vec![(day, "*"), ("*", weekdays.as_str())]
+        };
+        let schedules = variants
+            .into_iter()
+            .map(|(day, weekday)| {
+                // `cron` crate 的表达式以秒开头
+                format!("0 {} {} {} {} {}", minute, hour, day, month, weekday)
+                    .parse::<cron::Schedule>()
+                    .map_err(|e| format!("cron 表达式无效: {}（{}）", source, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let cron = Self { source, schedules };
+        // 以闰年起点搜索，覆盖 2 月 29 日等稀有日期
+        let reference = NaiveDate::from_ymd_opt(2000, 1, 1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .unwrap_or_default();
+        if cron.next_naive(reference).is_none() {
+            return Err(format!("cron 表达式永远不会触发: {}", cron.source));
+        }
+        Ok(cron)
+    }
+
+    /// 每周固定星期与时刻
+    pub fn weekly(weekday: Weekday, at: NaiveTime) -> Self {
+        Self::parse(&format!(
+            "{} {} * * {}",
+            at.minute(),
+            at.hour(),
+            weekday.num_days_from_sunday()
+        ))
+        .expect("每周计划总是有效的 cron 表达式")
+    }
+
+    /// 规范化后的表达式
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 挂钟时间所在的分钟是否命中
+    pub fn matches(&self, t: NaiveDateTime) -> bool {
+        t.date()
+            .and_hms_opt(t.hour(), t.minute(), 0)
+            .is_some_and(|t| self.schedules.iter().any(|s| s.includes(t.and_utc())))
+    }
+
+    /// `after` 之后（不含）的下一个触发时刻（挂钟时间）
+    fn next_naive(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.schedules
+            .iter()
+            .filter_map(|s| s.after(&after.and_utc()).next())
+            .min()
+            .map(|t| t.naive_utc())
+    }
+
+    /// 常见形式（固定时刻，按天、按周或按月）的中文描述
+    fn describe(&self) -> Option<String> {
+        let [schedule] = self.schedules.as_slice() else {
+            return None;
+        };
+        if schedule.minutes().count() != 1
+            || schedule.hours().count() != 1
+            || !schedule.months().is_all()
+        {
+            return None;
+        }
+        let time = format!(
+            "{:02}:{:02}",
+            schedule.hours().iter().next()?,
+            schedule.minutes().iter().next()?
+        );
+        match (
+            schedule.days_of_month().is_all(),
+            schedule.days_of_week().is_all(),
+        ) {
+            (true, true) => Some(format!("每天 {}", time)),
+            (true, false) => {
+                // `cron` crate 的星期从周日起记为 1
+                let days: Vec<&str> = schedule
+                    .days_of_week()
+                    .iter()
+                    .filter_map(|d| WEEKDAY_ZH.get(d as usize - 1).copied())
+                    .collect();
+                Some(format!("每周{} {}", days.join("、"), time))
+            }
+            (false, true) => {
+                let days: Vec<String> = schedule
+                    .days_of_month()
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect();
+                Some(format!("每月 {} 日 {}", days.join("、"), time))
+            }
+            (false, false) => None,
+        }
+    }
+}
+
+/// 将周字段改写为英文缩写列表：`cron` crate 的数字星期从周日起记为 1，
+/// 而 cron 惯例为 0-7（0 与 7 都表示周日）
+fn weekday_field(field: &str) -> Result<String, String> {
+    if field == "*" {
+        return Ok(field.to_string());
+    }
+    let value = |s: &str| -> Result<usize, String> {
+        let v = match s.parse::<usize>() {
+            Ok(v) => v,
+            Err(_) => WEEKDAY_NAMES
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(s))
+                .ok_or_else(|| format!("周字段无法识别: {}", s))?,
+        };
+        if v > 7 {
+            return Err(format!("周字段超出范围 0-7: {}", v));
+        }
+        Ok(v)
+    };
+
+    let mut days = [false; 7];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("周字段步长无效: {}", part))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (lo, hi) = if range == "*" {
+            (0, 7)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (value(a)?, value(b)?)
+        } else {
+            let v = value(range)?;
+            (v, if step.is_some() { 7 } else { v })
+        };
+        if lo > hi {
+            return Err(format!("周字段范围无效: {}", part));
+        }
+        for d in (lo..=hi).step_by(step.unwrap_or(1)) {
+            days[d % 7] = true;
+        }
+    }
+    Ok(WEEKDAY_NAMES
+        .iter()
+        .zip(days)
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+/// 计划所在时区
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleTz {
+    /// 本机时区
+    Local,
+    Utc,
+    /// IANA 时区
+    Zone(Tz),
+}
+
+impl ScheduleTz {
+    /// 解析时区名，未配置时为本机时区
+    pub fn parse(name: Option<&str>) -> Result<Self, String> {
+        match name.map(str::trim).filter(|n| !n.is_empty()) {
+            None => Ok(Self::Local),
+            Some("UTC" | "Etc/UTC" | "Etc/UCT" | "Zulu" | "Etc/Zulu") => Ok(Self::Utc),
+            Some(name) => name
+                .parse::<Tz>()
+                .map(Self::Zone)
+                .map_err(|_| format!("未知的时区: {}", name)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Local => "本机时区",
+            Self::Utc => "UTC",
+            Self::Zone(zone) => zone.name(),
+        }
+    }
+
+    /// UTC 时刻对应的挂钟时间
+    pub fn to_local(&self, utc: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Local => utc.with_timezone(&Local).naive_local(),
+            Self::Utc => utc.naive_utc(),
+            Self::Zone(zone) => utc.with_timezone(zone).naive_local(),
+        }
+    }
+
+    /// UTC 时刻在该时区的偏移
+    pub fn offset_at(&self, utc: DateTime<Utc>) -> FixedOffset {
+        let secs = (self.to_local(utc) - utc.naive_utc()).num_seconds();
+        FixedOffset::east_opt(secs as i32).unwrap_or_else(|| Utc.fix())
+    }
+
+    /// 该时区的当前时间
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        let utc = Utc::now();
+        utc.with_timezone(&self.offset_at(utc))
+    }
+
+    /// 挂钟时间对应的 UTC 时刻；重复的时刻取较早者，不存在（夏令时跳变）时为 None
+    pub fn to_utc(&self, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Local => Local
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|t| t.with_timezone(&Utc)),
+            Self::Utc => Some(local.and_utc()),
+            Self::Zone(zone) => zone
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|t| t.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// 执行规则：每日固定时刻或 cron 表达式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Daily(NaiveTime),
+    Cron(CronExpr),
+}
+
+/// 定时任务的执行计划
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobSchedule {
+    pub schedule: Schedule,
+    pub tz: ScheduleTz,
+}
+
+impl JobSchedule {
+    pub fn new(schedule: Schedule, tz: ScheduleTz) -> Self {
+        Self { schedule, tz }
+    }
+
+    /// 本机时区的每日计划
+    pub fn daily(at: NaiveTime) -> Self {
+        Self::new(Schedule::Daily(at), ScheduleTz::Local)
+    }
+
+    /// `after` 之后（不含）的下一次执行时刻
+    pub fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match (&self.schedule, &self.tz) {
+            (Schedule::Daily(at), ScheduleTz::Local) => {
+                Some(next_daily_run(&after.with_timezone(&Local), *at))
+            }
+            (Schedule::Daily(at), ScheduleTz::Utc) => Some(next_daily_run(after, *at)),
+            (Schedule::Daily(at), tz) => {
+                let mut date = tz.to_local(*after).date();
+                for _ in 0..=366 {
+                    if let Some(candidate) = tz.to_utc(date.and_time(*at)) {
+                        if candidate > *after {
+                            return Some(candidate);
+                        }
+                    }
+                    date = date.succ_opt()?;
+                }
+                None
+            }
+            (Schedule::Cron(cron), tz) => {
+                let mut local = tz.to_local(*after);
+                let limit = local.year() + CRON_SEARCH_YEARS;
+                while local.year() <= limit {
+                    local = cron.next_naive(local)?;
+                    // 落在夏令时跳变中不存在的时刻跳过
+                    if let Some(candidate) = tz.to_utc(local) {
+                        if candidate > *after {
+                            return Some(candidate);
+                        }
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// 下一次执行时刻；找不到时（如 cron 只落在夏令时跳变中）推迟一年
+    pub fn next_run(&self, after: &DateTime<Utc>) -> DateTime<Utc> {
+        self.next_after(after)
+            .unwrap_or(*after + Duration::days(366))
+    }
+
+    /// 之后的 `count` 次执行时刻
+    pub fn upcoming(&self, after: &DateTime<Utc>, count: usize) -> Vec<DateTime<Utc>> {
+        let mut runs = Vec::with_capacity(count);
+        let mut t = *after;
+        while runs.len() < count {
+            match self.next_after(&t) {
+                Some(next) => {
+                    runs.push(next);
+                    t = next;
+                }
+                None => break,
+            }
+        }
+        runs
+    }
+
+    /// 用于判断计划是否变更的键：本机时区的每日计划为 `HH:MM`，其余附带 `@时区`
+    pub fn key(&self) -> String {
+        let base = match &self.schedule {
+            Schedule::Daily(at) => at.format("%H:%M").to_string(),
+            Schedule::Cron(cron) => cron.source().to_string(),
+        };
+        match &self.tz {
+            ScheduleTz::Local => base,
+            tz => format!("{} @{}", base, tz.name()),
+        }
+    }
+
+    /// 中文描述，如「每周一、五 09:00（Asia/Shanghai）」
+    pub fn describe(&self) -> String {
+        let base = match &self.schedule {
+            Schedule::Daily(at) => format!("每天 {}", at.format("%H:%M")),
+            Schedule::Cron(cron) => cron
+                .describe()
+                .unwrap_or_else(|| format!("cron {}", cron.source())),
+        };
+        format!("{}（{}）", base, self.tz.name())
+    }
+
+    /// 以计划时区显示执行时刻，如「2026-10-16 09:00 周五（21 小时后）」
+    pub fn format_run(&self, run: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        let local = self.tz.to_local(run);
+        format!(
+            "{} 周{}（{}）",
+            local.format("%Y-%m-%d %H:%M"),
+            WEEKDAY_ZH[local.weekday().num_days_from_sunday() as usize],
+            humanize_until(run - now)
+        )
+    }
+}
+
+fn humanize_until(delta: Duration) -> String {
+    let minutes = delta.num_minutes().max(0);
+    if minutes < 1 {
+        "即将执行".to_string()
+    } else if minutes < 60 {
+        format!("{} 分钟后", minutes)
+    } else if minutes < 48 * 60 {
+        format!("{} 小时后", minutes / 60)
+    } else {
+        format!("{} 天后", minutes / (24 * 60))
+    }
+}
+
+/// 配置中的一条定时计划
+#[derive(Debug, Clone)]
+pub struct ScheduleEntry {
+    /// 任务 id，与任务表一致，如 `digest:wx_main`
+    pub id: String,
+    pub app_id: String,
+    pub schedule: JobSchedule,
+}
+
+/// 定时计划预览
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchedulePreview {
+    pub id: String,
+    pub app_id: String,
+    pub schedule: String,
+    pub timezone: String,
+    /// 接下来几次执行时间，按计划时区显示
+    pub next_runs: Vec<String>,
+}
+
+/// 各计划接下来 `count` 次的执行时间
+pub fn preview_schedules(
+    entries: &[ScheduleEntry],
+    now: DateTime<Utc>,
+    count: usize,
+) -> Vec<SchedulePreview> {
+    entries
+        .iter()
+        .map(|entry| SchedulePreview {
+            id: entry.id.clone(),
+            app_id: entry.app_id.clone(),
+            schedule: entry.schedule.describe(),
+            timezone: entry.schedule.tz.name().to_string(),
+            next_runs: entry
+                .schedule
+                .upcoming(&now, count)
+                .into_iter()
+                .map(|run| entry.schedule.format_run(run, now))
+                .collect(),
+        })
+        .collect()
+}
+
+/// 同一机器人的计划在 `window` 内于同一分钟触发时给出提示，每对计划只报告首次重叠
+pub fn find_overlaps(
+    entries: &[ScheduleEntry],
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Vec<String> {
+    /// 每个计划最多展开的执行次数，避免每分钟执行的 cron 展开过多
+    const MAX_RUNS: usize = 2000;
+    let end = now + window;
+    let runs: Vec<Vec<DateTime<Utc>>> = entries
+        .iter()
+        .map(|entry| {
+            let mut runs = Vec::new();
+            let mut t = now;
+            while runs.len() < MAX_RUNS {
+                match entry.schedule.next_after(&t).filter(|next| *next <= end) {
+                    Some(next) => {
+                        runs.push(next);
+                        t = next;
+                    }
+                    None => break,
+                }
+            }
+            runs
+        })
+        .collect();
+
+    let mut warnings = Vec::new();
+    for (i, a) in entries.iter().enumerate() {
+        for (j, b) in entries.iter().enumerate().skip(i + 1) {
+            if a.app_id != b.app_id {
+                continue;
+            }
+            let first = runs[i]
+                .iter()
+                .find(|run| runs[j].binary_search(run).is_ok());
+            if let Some(run) = first {
+                warnings.push(format!(
+                    "{} 与 {} 将在 {} 同时触发（同一机器人 {}）",
+                    a.id,
+                    b.id,
+                    a.schedule.format_run(*run, now),
+                    a.app_id
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// 规则的生效时间：时段、星期与 cron 条件各自按当前挂钟时间判断，全部满足才生效，未配置的条件不限制
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActiveWindow {
+    /// (开始, 结束)，含开始不含结束；结束早于开始时跨越午夜
+    ranges: Vec<(NaiveTime, NaiveTime)>,
+    weekdays: Vec<Weekday>,
+    cron: Option<CronExpr>,
+}
+
+impl ActiveWindow {
+    /// 解析时段（`HH:MM-HH:MM`）、星期（`mon`、`mon-fri`、`周一`）与 cron 表达式
+    pub fn parse(
+        time_ranges: &[String],
+        weekdays: &[String],
+        cron: Option<&str>,
+    ) -> Result<Self, String> {
+        let ranges = time_ranges
+            .iter()
+            .map(|r| parse_time_range(r))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut days = Vec::new();
+        for spec in weekdays {
+            for day in parse_weekdays(spec)? {
+                if !days.contains(&day) {
+                    days.push(day);
+                }
+            }
+        }
+        let cron = match cron.map(str::trim).filter(|c| !c.is_empty()) {
+            Some(expr) => Some(CronExpr::parse(expr)?),
+            None => None,
+        };
+        Ok(Self {
+            ranges,
+            weekdays: days,
+            cron,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty() && self.weekdays.is_empty() && self.cron.is_none()
+    }
+
+    /// 挂钟时间是否在生效时间内
+    pub fn contains(&self, local: NaiveDateTime) -> bool {
+        let time = local.time();
+        let in_range = |(start, end): &(NaiveTime, NaiveTime)| {
+            if start < end {
+                time >= *start && time < *end
+            } else {
+                time >= *start || time < *end
+            }
+        };
+        if !self.ranges.is_empty() && !self.ranges.iter().any(in_range) {
+            return false;
+        }
+        if !self.weekdays.is_empty() && !self.weekdays.contains(&local.weekday()) {
+            return false;
+        }
+        self.cron.as_ref().is_none_or(|cron| cron.matches(local))
+    }
+}
+
+/// 值班表最多向后搜索的天数：每个班次每周至少出现一次
+const ROTA_SEARCH_DAYS: i64 = 8;
+
+/// 转人工的值班表：各班次的生效时间与值班人员，按值班表所在时区的挂钟时间判断
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rota {
+    shifts: Vec<(ActiveWindow, Vec<String>)>,
+    tz: ScheduleTz,
+}
+
+impl Rota {
+    pub fn new(tz: ScheduleTz) -> Self {
+        Self {
+            shifts: Vec::new(),
+            tz,
+        }
+    }
+
+    pub fn add_shift(&mut self, window: ActiveWindow, operators: Vec<String>) {
+        self.shifts.push((window, operators));
+    }
+
+    /// 该时刻在班的值班人员，班次重叠时合并去重
+    pub fn on_duty(&self, at: DateTime<Utc>) -> Vec<String> {
+        let local = self.tz.to_local(at);
+        let mut operators: Vec<String> = Vec::new();
+        for (window, shift) in &self.shifts {
+            if window.contains(local) {
+                for op in shift {
+                    if !operators.contains(op) {
+                        operators.push(op.clone());
+                    }
+                }
+            }
+        }
+        operators
+    }
+
+    /// `after` 之后（不含）下一个有人在班的整分钟
+    pub fn next_shift(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        (0..ROTA_SEARCH_DAYS * 24 * 60)
+            .map(|m| start + Duration::minutes(m))
+            .find(|at| !self.on_duty(*at).is_empty())
+    }
+
+    /// 值班表时区中的时间，如 `10-19 09:00 周一`
+    pub fn format_time(&self, at: DateTime<Utc>) -> String {
+        let local = self.tz.to_local(at);
+        format!(
+            "{} 周{}",
+            local.format("%m-%d %H:%M"),
+            WEEKDAY_ZH[local.weekday().num_days_from_sunday() as usize]
+        )
+    }
+}
+
+/// 解析 `HH:MM-HH:MM`，结束时间可写作 `24:00`
+fn parse_time_range(range: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let invalid = || format!("时段格式应为 HH:MM-HH:MM: {}", range);
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+    let parse = |s: &str| match s.trim() {
+        "24:00" => NaiveTime::from_hms_opt(0, 0, 0),
+        s => NaiveTime::parse_from_str(s, "%H:%M").ok(),
+    };
+    let (start, end) = (
+        parse(start).ok_or_else(invalid)?,
+        parse(end).ok_or_else(invalid)?,
+    );
+    if start == end {
+        return Err(format!("时段的开始与结束时间相同: {}", range));
+    }
+    Ok((start, end))
+}
+
+/// 英文缩写或全称（不区分大小写），或 `周一`、`星期日` 等中文写法
+fn parse_weekday(name: &str) -> Option<Weekday> {
+    let name = name.trim();
+    if let Ok(day) = name.parse::<Weekday>() {
+        return Some(day);
+    }
+    let zh = name
+        .strip_prefix("星期")
+        .or_else(|| name.strip_prefix("周"))?;
+    let from_sunday = match zh {
+        "天" => 0,
+        zh => WEEKDAY_ZH.iter().position(|d| *d == zh)?,
+    };
+    Weekday::try_from(((from_sunday + 6) % 7) as u8).ok()
+}
+
+/// 单个星期或 `mon-fri` 形式的范围，范围可跨过周日（如 `fri-mon`）
+fn parse_weekdays(spec: &str) -> Result<Vec<Weekday>, String> {
+    if let Some(day) = parse_weekday(spec) {
+        return Ok(vec![day]);
+    }
+    let invalid = || format!("星期无法识别: {}", spec);
+    let (from, to) = spec.split_once('-').ok_or_else(invalid)?;
+    let (mut day, to) = (
+        parse_weekday(from).ok_or_else(invalid)?,
+        parse_weekday(to).ok_or_else(invalid)?,
+    );
+    let mut days = vec![day];
+    while day != to {
+        day = day.succ();
+        days.push(day);
+    }
+    Ok(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn naive(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn cron(expr: &str, tz: ScheduleTz) -> JobSchedule {
+        JobSchedule::new(Schedule::Cron(CronExpr::parse(expr).unwrap()), tz)
+    }
+
+    #[test]
+    fn test_cron_parse_errors() {
+        assert!(CronExpr::parse("0 9 * *").unwrap_err().contains("5 段"));
+        for expr in ["60 9 * * *", "0 9-5 * * *", "*/0 * * * *", "0 9 * foo *"] {
+            assert!(CronExpr::parse(expr)
+                .unwrap_err()
+                .contains("cron 表达式无效"));
+        }
+        assert!(CronExpr::parse("0 9 * * funday")
+            .unwrap_err()
+            .contains("无法识别"));
+        assert!(CronExpr::parse("0 9 * * 8")
+            .unwrap_err()
+            .contains("超出范围"));
+        assert!(CronExpr::parse("0 9 * * 5-1")
+            .unwrap_err()
+            .contains("范围无效"));
+        assert!(CronExpr::parse("0 9 * * */0").unwrap_err().contains("步长"));
+        assert!(CronExpr::parse("@reboot").unwrap_err().contains("不支持"));
+        assert!(CronExpr::parse("0 0 30 2 *")
+            .unwrap_err()
+            .contains("永远不会触发"));
+        assert!(CronExpr::parse("0 0 29 2 *").is_ok());
+        assert_eq!(
+            CronExpr::parse("  0  9 * *   MON-FRI ").unwrap().source(),
+            "0 9 * * MON-FRI"
+        );
+    }
+
+    #[test]
+    fn test_cron_next() {
+        let weekdays = CronExpr::parse("30 9 * * 1-5").unwrap();
+        // 2026-10-16 为周五
+        assert_eq!(
+            weekdays.next_naive(naive("2026-10-16 09:30")),
+            Some(naive("2026-10-19 09:30"))
+        );
+        assert_eq!(
+            weekdays.next_naive(naive("2026-10-16 09:29")),
+            Some(naive("2026-10-16 09:30"))
+        );
+
+        let every = CronExpr::parse("*/15 8-9 * * *").unwrap();
+        assert_eq!(
+            every.next_naive(naive("2026-10-16 09:50")),
+            Some(naive("2026-10-17 08:00"))
+        );
+
+        // 日与周同时限定时满足其一即可
+        let either = CronExpr::parse("0 0 13 * fri").unwrap();
+        assert_eq!(
+            either.next_naive(naive("2026-10-12 00:00")),
+            Some(naive("2026-10-13 00:00"))
+        );
+        assert_eq!(
+            either.next_naive(naive("2026-10-13 00:00")),
+            Some(naive("2026-10-16 00:00"))
+        );
+
+        let sunday = CronExpr::parse("0 12 * * 7").unwrap();
+        assert_eq!(
+            sunday.next_naive(naive("2026-10-16 00:00")),
+            Some(naive("2026-10-18 12:00"))
+        );
+        let weekend = CronExpr::parse("0 12 1 * 6-7").unwrap();
+        assert_eq!(
+            weekend.next_naive(naive("2026-10-17 12:00")),
+            Some(naive("2026-10-18 12:00"))
+        );
+        assert_eq!(
+            weekend.next_naive(naive("2026-10-18 12:00")),
+            Some(naive("2026-10-24 12:00"))
+        );
+        assert_eq!(
+            CronExpr::parse("@monthly")
+                .unwrap()
+                .next_naive(naive("2026-12-05 00:00")),
+            Some(naive("2027-01-01 00:00"))
+        );
+    }
+
+    #[test]
+    fn test_cron_describe() {
+        let describe = |expr: &str| CronExpr::parse(expr).unwrap().describe();
+        assert_eq!(describe("0 9 * * *").as_deref(), Some("每天 09:00"));
+        assert_eq!(
+            describe("30 18 * * 1,5").as_deref(),
+            Some("每周一、五 18:30")
+        );
+        assert_eq!(
+            describe("0 8 1,15 * *").as_deref(),
+            Some("每月 1、15 日 08:00")
+        );
+        assert_eq!(describe("*/5 * * * *"), None);
+        assert_eq!(
+            JobSchedule::new(
+                Schedule::Cron(CronExpr::parse("*/5 * * * *").unwrap()),
+                ScheduleTz::Utc
+            )
+            .describe(),
+            "cron */5 * * * *（UTC）"
+        );
+    }
+
+    #[test]
+    fn test_active_window_time_ranges() {
+        let window = ActiveWindow::parse(&["09:00-18:00".to_string()], &[], None).unwrap();
+        // 含开始，不含结束
+        assert!(!window.contains(naive("2030-07-01 08:59")));
+        assert!(window.contains(naive("2030-07-01 09:00")));
+        assert!(window.contains(naive("2030-07-01 17:59")));
+        assert!(!window.contains(naive("2030-07-01 18:00")));
+
+        // 跨越午夜
+        let night = ActiveWindow::parse(
+            &["22:00-08:00".to_string(), "12:00-13:00".to_string()],
+            &[],
+            None,
+        )
+        .unwrap();
+        assert!(night.contains(naive("2030-07-01 22:00")));
+        assert!(night.contains(naive("2030-07-01 23:59")));
+        assert!(night.contains(naive("2030-07-02 00:00")));
+        assert!(night.contains(naive("2030-07-02 07:59")));
+        assert!(!night.contains(naive("2030-07-02 08:00")));
+        assert!(night.contains(naive("2030-07-02 12:30")));
+        assert!(!night.contains(naive("2030-07-02 21:59")));
+
+        let evening = ActiveWindow::parse(&["18:00-24:00".to_string()], &[], None).unwrap();
+        assert!(evening.contains(naive("2030-07-01 23:59")));
+        assert!(!evening.contains(naive("2030-07-02 00:00")));
+
+        assert!(ActiveWindow::parse(&["9:00".to_string()], &[], None).is_err());
+        assert!(ActiveWindow::parse(&["25:00-26:00".to_string()], &[], None).is_err());
+        assert!(ActiveWindow::parse(&["08:00-08:00".to_string()], &[], None).is_err());
+        assert!(ActiveWindow::default().is_empty());
+        assert!(ActiveWindow::default().contains(naive("2030-07-01 03:00")));
+    }
+
+    #[test]
+    fn test_active_window_weekdays_and_cron() {
+        // 2030-07-05 为周五
+        let workdays =
+            ActiveWindow::parse(&["09:00-18:00".to_string()], &["mon-fri".to_string()], None)
+                .unwrap();
+        assert!(workdays.contains(naive("2030-07-05 17:59")));
+        assert!(!workdays.contains(naive("2030-07-06 10:00")));
+        assert!(workdays.contains(naive("2030-07-08 09:00")));
+
+        let weekend =
+            ActiveWindow::parse(&[], &["周六".to_string(), "Sunday".to_string()], None).unwrap();
+        assert!(!weekend.contains(naive("2030-07-05 23:59")));
+        assert!(weekend.contains(naive("2030-07-06 00:00")));
+        assert!(weekend.contains(naive("2030-07-07 23:59")));
+        assert!(!weekend.contains(naive("2030-07-08 00:00")));
+
+        let wrapped = ActiveWindow::parse(&[], &["fri-mon".to_string()], None).unwrap();
+        assert!(wrapped.contains(naive("2030-07-08 12:00")));
+        assert!(!wrapped.contains(naive("2030-07-09 12:00")));
+        assert!(ActiveWindow::parse(&[], &["funday".to_string()], None).is_err());
+
+        let cron = ActiveWindow::parse(&[], &[], Some("*/30 9-17 * * 1-5")).unwrap();
+        assert!(cron.contains(naive("2030-07-05 09:00")));
+        assert!(cron.contains(naive("2030-07-05 17:30")));
+        assert!(!cron.contains(naive("2030-07-05 17:31")));
+        assert!(!cron.contains(naive("2030-07-05 18:00")));
+        assert!(!cron.contains(naive("2030-07-06 09:00")));
+        assert!(ActiveWindow::parse(&[], &[], Some("0 9 * *")).is_err());
+    }
+
+    #[test]
+    fn test_rota_on_duty_and_next_shift() {
+        let shift = |ranges: &[&str], days: &str, ops: &[&str]| {
+            (
+                ActiveWindow::parse(
+                    &ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+                    &[days.to_string()],
+                    None,
+                )
+                .unwrap(),
+                ops.iter().map(|o| o.to_string()).collect::<Vec<_>>(),
+            )
+        };
+        let mut rota = Rota::new(ScheduleTz::Utc);
+        for (window, ops) in [
+            shift(&["09:00-18:00"], "mon-fri", &["wxid_a"]),
+            shift(&["12:00-20:00"], "mon-fri", &["wxid_b", "wxid_a"]),
+            shift(&["10:00-16:00"], "sat", &["wxid_c"]),
+        ] {
+            rota.add_shift(window, ops);
+        }
+        // 2030-07-05 为周五
+        assert_eq!(rota.on_duty(utc("2030-07-05T09:30:00Z")), ["wxid_a"]);
+        assert_eq!(
+            rota.on_duty(utc("2030-07-05T12:00:00Z")),
+            ["wxid_a", "wxid_b"]
+        );
+        assert_eq!(
+            rota.on_duty(utc("2030-07-05T19:00:00Z")),
+            ["wxid_b", "wxid_a"]
+        );
+        assert!(rota.on_duty(utc("2030-07-05T20:00:00Z")).is_empty());
+
+        assert_eq!(
+            rota.next_shift(utc("2030-07-05T21:15:30Z")),
+            Some(utc("2030-07-06T10:00:00Z"))
+        );
+        assert_eq!(
+            rota.next_shift(utc("2030-07-06T16:00:00Z")),
+            Some(utc("2030-07-08T09:00:00Z"))
+        );
+        assert_eq!(
+            rota.format_time(utc("2030-07-08T09:00:00Z")),
+            "07-08 09:00 周一"
+        );
+        assert_eq!(
+            Rota::new(ScheduleTz::Utc).next_shift(utc("2030-07-05T00:00:00Z")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_zone_dst_gap_and_overlap() {
+        let tz = ScheduleTz::parse(Some("America/New_York")).unwrap();
+        assert_eq!(
+            tz.to_utc(naive("2026-07-01 09:00")),
+            Some(utc("2026-07-01T13:00:00Z"))
+        );
+        // 跳变中不存在的时刻
+        assert_eq!(tz.to_utc(naive("2026-03-08 02:30")), None);
+        // 重复的时刻取较早者
+        assert_eq!(
+            tz.to_utc(naive("2026-11-01 01:30")),
+            Some(utc("2026-11-01T05:30:00Z"))
+        );
+        assert_eq!(
+            tz.to_local(utc("2026-01-15T14:00:00Z")),
+            naive("2026-01-15 09:00")
+        );
+
+        // 每日计划遇到跳变顺延一天
+        let daily = JobSchedule::new(
+            Schedule::Daily(NaiveTime::from_hms_opt(2, 30, 0).unwrap()),
+            tz.clone(),
+        );
+        assert_eq!(
+            daily.next_after(&utc("2026-03-07T08:00:00Z")),
+            Some(utc("2026-03-09T06:30:00Z"))
+        );
+        let weekdays = cron("0 9 * * 1-5", tz);
+        assert_eq!(
+            weekdays.next_after(&utc("2026-03-06T15:00:00Z")),
+            Some(utc("2026-03-09T13:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_zone_offsets() {
+        let tz = ScheduleTz::parse(Some("Asia/Shanghai")).unwrap();
+        assert_eq!(tz.name(), "Asia/Shanghai");
+        assert_eq!(
+            tz.to_utc(naive("2026-10-16 09:00")),
+            Some(utc("2026-10-16T01:00:00Z"))
+        );
+        let tz = ScheduleTz::parse(Some("America/New_York")).unwrap();
+        assert_eq!(
+            tz.to_local(utc("2030-07-01T12:00:00Z")),
+            naive("2030-07-01 08:00")
+        );
+        assert_eq!(
+            tz.to_local(utc("2030-12-01T12:00:00Z")),
+            naive("2030-12-01 07:00")
+        );
+        assert_eq!(
+            tz.offset_at(utc("2030-07-01T12:00:00Z")),
+            FixedOffset::west_opt(4 * 3600).unwrap()
+        );
+        assert_eq!(
+            ScheduleTz::Utc.offset_at(utc("2030-07-01T12:00:00Z")),
+            Utc.fix()
+        );
+    }
+
+    #[test]
+    fn test_schedule_tz_parse() {
+        assert_eq!(ScheduleTz::parse(None).unwrap(), ScheduleTz::Local);
+        assert_eq!(ScheduleTz::parse(Some(" ")).unwrap(), ScheduleTz::Local);
+        assert_eq!(ScheduleTz::parse(Some("Etc/UTC")).unwrap(), ScheduleTz::Utc);
+        for name in [
+            "Mars/Olympus_Mons",
+            "../etc/passwd",
+            "/etc/localtime",
+            "Asia//X",
+        ] {
+            assert!(ScheduleTz::parse(Some(name))
+                .unwrap_err()
+                .contains("未知的时区"));
+        }
+    }
+
+    #[test]
+    fn test_job_schedule_key_and_preview() {
+        let at = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(JobSchedule::daily(at).key(), "09:00");
+        let schedule = JobSchedule::new(Schedule::Daily(at), ScheduleTz::Utc);
+        assert_eq!(schedule.key(), "09:00 @UTC");
+        assert_eq!(schedule.describe(), "每天 09:00（UTC）");
+
+        let now = utc("2026-10-15T12:00:00Z");
+        let entries = vec![ScheduleEntry {
+            id: "digest:wx_a".to_string(),
+            app_id: "wx_a".to_string(),
+            schedule: cron("0 9 * * mon", ScheduleTz::Utc),
+        }];
+        let previews = preview_schedules(&entries, now, 2);
+        assert_eq!(previews[0].schedule, "每周一 09:00（UTC）");
+        assert_eq!(
+            previews[0].next_runs,
+            vec![
+                "2026-10-19 09:00 周一（3 天后）",
+                "2026-10-26 09:00 周一（10 天后）"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_overlaps() {
+        let now = utc("2026-10-15T00:00:00Z");
+        let entry = |id: &str, app_id: &str, expr: &str| ScheduleEntry {
+            id: id.to_string(),
+            app_id: app_id.to_string(),
+            schedule: cron(expr, ScheduleTz::Utc),
+        };
+        let entries = vec![
+            entry("digest:wx_a", "wx_a", "0 9 * * 1"),
+            entry("countdown:launch", "wx_a", "0 9 * * *"),
+            entry("countdown:other", "wx_b", "0 9 * * *"),
+            entry("countdown:noon", "wx_a", "0 12 * * *"),
+        ];
+        let warnings = find_overlaps(&entries, now, Duration::days(7));
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0].starts_with("digest:wx_a 与 countdown:launch 将在 2026-10-19 09:00 周一")
+        );
+    }
+}
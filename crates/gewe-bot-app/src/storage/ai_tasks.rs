@@ -0,0 +1,313 @@
+//! AI 任务队列
+//!
+//! 单个 JSON 文件：`{data_dir}/ai_tasks/tasks.json`，记录排队、执行中与近期结束的 AI 工具任务；
+//! 任务保存原始回调事件，执行时重新解析，重启后据此继续执行未完成的任务
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::config::ReplyMode;
+
+/// 单个任务最多开始执行的次数；执行中被重启打断的任务重新排队，超过后标记失败
+pub const MAX_AI_TASK_ATTEMPTS: u32 = 3;
+
+/// 已结束任务的保留时长（小时），期间仍可查询状态
+pub const AI_TASK_RETENTION_HOURS: i64 = 24;
+
+/// 任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AiTaskStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl AiTaskStatus {
+    /// 排队或执行中
+    pub fn is_pending(self) -> bool {
+        matches!(self, AiTaskStatus::Queued | AiTaskStatus::Running)
+    }
+}
+
+/// 任务表中的一条记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AiTaskRecord {
+    pub id: String,
+    pub app_id: String,
+    /// 发起任务的会话，结果发回此处，也仅该会话可查询
+    pub chat: String,
+    /// 规则 ID（V2 为规则实例 id），执行时据此查找工具配置
+    pub rule: String,
+    /// AI 调用的工具名
+    pub tool: String,
+    /// 模型给出的工具参数（JSON）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+    #[serde(default)]
+    pub reply_mode: ReplyMode,
+    /// 原始回调的 TypeName
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    /// 原始回调的 Data
+    pub data: serde_json::Value,
+    pub status: AiTaskStatus,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 已开始执行的次数
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl AiTaskRecord {
+    /// 标记开始执行
+    pub fn start(&mut self, now: DateTime<Utc>) {
+        self.status = AiTaskStatus::Running;
+        self.started_at = Some(now);
+        self.attempts += 1;
+    }
+
+    /// 记录执行结果
+    pub fn finish(&mut self, now: DateTime<Utc>, result: Result<(), String>) {
+        self.finished_at = Some(now);
+        match result {
+            Ok(()) => {
+                self.status = AiTaskStatus::Succeeded;
+                self.error = None;
+            }
+            Err(err) => {
+                self.status = AiTaskStatus::Failed;
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+/// 全部任务，键为任务编号
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AiTaskTable {
+    #[serde(default)]
+    pub tasks: BTreeMap<String, AiTaskRecord>,
+}
+
+impl AiTaskTable {
+    /// 排队与执行中的任务数
+    pub fn pending_count(&self) -> usize {
+        self.tasks
+            .values()
+            .filter(|t| t.status.is_pending())
+            .count()
+    }
+
+    /// 排在该任务之前、仍在排队的任务数
+    pub fn queued_before(&self, id: &str) -> usize {
+        let Some(task) = self.tasks.get(id) else {
+            return 0;
+        };
+        self.tasks
+            .values()
+            .filter(|t| {
+                t.status == AiTaskStatus::Queued
+                    && (t.created_at, t.id.as_str()) < (task.created_at, id)
+            })
+            .count()
+    }
+
+    /// 删除结束超过保留期的任务，返回是否有变化
+    pub fn prune(&mut self, now: DateTime<Utc>) -> bool {
+        let before = self.tasks.len();
+        let cutoff = now - Duration::hours(AI_TASK_RETENTION_HOURS);
+        self.tasks
+            .retain(|_, t| t.finished_at.is_none_or(|at| at > cutoff));
+        self.tasks.len() != before
+    }
+
+    /// 重启后恢复：执行中的任务视为被打断并重新排队，开始次数耗尽的标记失败。
+    /// 返回按创建时间排序的待执行任务编号
+    pub fn recover(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        for task in self.tasks.values_mut() {
+            if task.status != AiTaskStatus::Running {
+                continue;
+            }
+            if task.attempts >= MAX_AI_TASK_ATTEMPTS {
+                task.finish(now, Err("多次执行中断，已放弃".to_string()));
+            } else {
+                task.status = AiTaskStatus::Queued;
+            }
+        }
+        let mut queued: Vec<_> = self
+            .tasks
+            .values()
+            .filter(|t| t.status == AiTaskStatus::Queued)
+            .collect();
+        queued.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        queued.into_iter().map(|t| t.id.clone()).collect()
+    }
+
+    /// 某会话的任务，最新的在前
+    pub fn for_chat(&self, app_id: &str, chat: &str) -> Vec<&AiTaskRecord> {
+        let mut tasks: Vec<_> = self
+            .tasks
+            .values()
+            .filter(|t| t.app_id == app_id && t.chat == chat)
+            .collect();
+        tasks.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        tasks
+    }
+}
+
+/// 基于文件的任务表存储
+#[derive(Debug, Clone)]
+pub struct AiTaskStore {
+    dir: PathBuf,
+}
+
+impl AiTaskStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("ai_tasks"),
+        }
+    }
+
+    fn table_path(&self) -> PathBuf {
+        self.dir.join("tasks.json")
+    }
+
+    /// 读取任务表，不存在时返回空
+    pub async fn load(&self) -> Result<AiTaskTable, String> {
+        let path = self.table_path();
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析 AI 任务表失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AiTaskTable::default()),
+            Err(e) => Err(format!("读取 AI 任务表失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    pub async fn save(&self, table: &AiTaskTable) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建 AI 任务表目录失败: {}", e))?;
+        let path = self.table_path();
+        let content = serde_json::to_string_pretty(table)
+            .map_err(|e| format!("序列化 AI 任务表失败: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入 AI 任务表失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入 AI 任务表失败 {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn task(id: &str, chat: &str, created_at: &str) -> AiTaskRecord {
+        AiTaskRecord {
+            id: id.to_string(),
+            app_id: "wx_app".to_string(),
+            chat: chat.to_string(),
+            rule: "draw".to_string(),
+            tool: "video".to_string(),
+            arguments: Some(r#"{"prompt":"猫"}"#.to_string()),
+            reply_mode: ReplyMode::Quote,
+            type_name: Some("AddMsg".to_string()),
+            data: serde_json::json!({ "MsgType": 1 }),
+            status: AiTaskStatus::Queued,
+            created_at: utc(created_at),
+            started_at: None,
+            finished_at: None,
+            error: None,
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn test_recover_requeues_interrupted_tasks() {
+        let now = utc("2026-10-15T09:00:00Z");
+        let mut table = AiTaskTable::default();
+        for t in [
+            task("b", "wxid_a", "2026-10-15T08:02:00Z"),
+            task("a", "wxid_a", "2026-10-15T08:01:00Z"),
+            task("c", "wxid_b", "2026-10-15T08:03:00Z"),
+            task("d", "wxid_b", "2026-10-15T08:00:00Z"),
+        ] {
+            table.tasks.insert(t.id.clone(), t);
+        }
+        table.tasks.get_mut("b").unwrap().start(now);
+        let exhausted = table.tasks.get_mut("c").unwrap();
+        for _ in 0..MAX_AI_TASK_ATTEMPTS {
+            exhausted.start(now);
+        }
+        table.tasks.get_mut("d").unwrap().finish(now, Ok(()));
+
+        assert_eq!(table.pending_count(), 3);
+        assert_eq!(table.recover(now), vec!["a", "b"]);
+        assert_eq!(table.tasks["b"].status, AiTaskStatus::Queued);
+        assert_eq!(table.tasks["c"].status, AiTaskStatus::Failed);
+        assert_eq!(table.tasks["d"].status, AiTaskStatus::Succeeded);
+        assert_eq!(table.queued_before("b"), 1);
+        assert_eq!(table.queued_before("a"), 0);
+    }
+
+    #[test]
+    fn test_prune_and_for_chat() {
+        let mut table = AiTaskTable::default();
+        for t in [
+            task("a", "wxid_a", "2026-10-14T08:00:00Z"),
+            task("b", "wxid_a", "2026-10-15T08:00:00Z"),
+            task("c", "wxid_b", "2026-10-15T08:00:00Z"),
+        ] {
+            table.tasks.insert(t.id.clone(), t);
+        }
+        table
+            .tasks
+            .get_mut("a")
+            .unwrap()
+            .finish(utc("2026-10-14T08:05:00Z"), Err("超时".to_string()));
+
+        let ids = |table: &AiTaskTable| {
+            table
+                .for_chat("wx_app", "wxid_a")
+                .iter()
+                .map(|t| t.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&table), vec!["b", "a"]);
+        assert!(!table.prune(utc("2026-10-15T08:00:00Z")));
+        assert!(table.prune(utc("2026-10-15T09:00:00Z")));
+        assert_eq!(ids(&table), vec!["b"]);
+        assert!(table.for_chat("wx_other", "wxid_a").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ai_task_store_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let store = AiTaskStore::new(temp.path());
+        assert!(store.load().await.unwrap().tasks.is_empty());
+        let mut table = AiTaskTable::default();
+        table
+            .tasks
+            .insert("a".to_string(), task("a", "wxid_a", "2026-10-15T08:00:00Z"));
+        store.save(&table).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), table);
+    }
+}
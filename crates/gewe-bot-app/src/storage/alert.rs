@@ -0,0 +1,191 @@
+//! 告警记录
+//!
+//! 按日追加写入 `{data_dir}/alerts/YYYY-MM-DD.jsonl`（UTC 日期），用于统计关键词当天已即时转发的
+//! 条数，以及生成每日汇总
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::jsonl;
+use crate::config::AlertSeverity;
+
+/// 一次命中的告警
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRecord {
+    pub at: DateTime<Utc>,
+    /// 规则所属机器人
+    pub app_id: String,
+    pub rule: String,
+    /// 会话：群聊 ID 或私聊 wxid
+    pub chat: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    /// 命中的关键词，规则未配置关键词时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<String>,
+    pub severity: AlertSeverity,
+    pub text: String,
+    /// 是否已即时转发给管理员，否则只计入每日汇总
+    pub forwarded: bool,
+}
+
+/// 基于 JSONL 文件的告警记录
+#[derive(Debug, Clone)]
+pub struct AlertLog {
+    dir: PathBuf,
+}
+
+impl AlertLog {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("alerts"),
+        }
+    }
+
+    fn day_path(&self, date: chrono::NaiveDate) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", date.format("%Y-%m-%d")))
+    }
+
+    pub async fn append(&self, record: &AlertRecord) -> Result<(), String> {
+        jsonl::append_line(&self.day_path(record.at.date_naive()), record)
+            .await
+            .map_err(|e| format!("写入告警记录失败: {}", e))
+    }
+
+    /// 读取 [start, end) 内的告警
+    pub async fn load_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<AlertRecord>, String> {
+        let mut records = Vec::new();
+        let mut date = start.date_naive();
+        while date <= end.date_naive() {
+            let day: Vec<AlertRecord> = jsonl::read_lines(&self.day_path(date))
+                .await
+                .map_err(|e| format!("读取告警记录失败: {}", e))?;
+            records.extend(day.into_iter().filter(|r| r.at >= start && r.at < end));
+            let Some(next) = date.succ_opt() else {
+                break;
+            };
+            date = next;
+        }
+        Ok(records)
+    }
+}
+
+/// 汇总中的一行：同一关键词与级别的告警
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlertSummaryLine {
+    pub severity: AlertSeverity,
+    pub keyword: Option<String>,
+    pub total: usize,
+    pub forwarded: usize,
+    /// 涉及的会话，按告警条数从多到少
+    pub chats: Vec<(String, usize)>,
+}
+
+/// 按关键词与级别汇总告警，级别高的在前，同级按条数从多到少
+pub fn summarize_alerts(records: &[AlertRecord]) -> Vec<AlertSummaryLine> {
+    let mut groups: BTreeMap<(AlertSeverity, Option<&str>), Vec<&AlertRecord>> = BTreeMap::new();
+    for record in records {
+        groups
+            .entry((record.severity, record.keyword.as_deref()))
+            .or_default()
+            .push(record);
+    }
+    let mut lines: Vec<AlertSummaryLine> = groups
+        .into_iter()
+        .map(|((severity, keyword), records)| {
+            let mut chats: BTreeMap<&str, usize> = BTreeMap::new();
+            for record in &records {
+                *chats.entry(record.chat.as_str()).or_default() += 1;
+            }
+            let mut chats: Vec<(String, usize)> = chats
+                .into_iter()
+                .map(|(chat, n)| (chat.to_string(), n))
+                .collect();
+            chats.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+            AlertSummaryLine {
+                severity,
+                keyword: keyword.map(str::to_string),
+                total: records.len(),
+                forwarded: records.iter().filter(|r| r.forwarded).count(),
+                chats,
+            }
+        })
+        .collect();
+    lines.sort_by(|a, b| b.severity.cmp(&a.severity).then(b.total.cmp(&a.total)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    fn record(minutes: i64, chat: &str, keyword: &str, severity: AlertSeverity) -> AlertRecord {
+        AlertRecord {
+            at: DateTime::parse_from_rfc3339("2026-10-14T23:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc)
+                + Duration::minutes(minutes),
+            app_id: "wx1".to_string(),
+            rule: "alert".to_string(),
+            chat: chat.to_string(),
+            sender: Some("wxid_a".to_string()),
+            keyword: Some(keyword.to_string()),
+            severity,
+            text: format!("消息含{}", keyword),
+            forwarded: severity == AlertSeverity::Critical,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_alert_log_range_across_days() {
+        let dir = TempDir::new().unwrap();
+        let log = AlertLog::new(dir.path());
+        for minutes in [0, 90, 180] {
+            log.append(&record(
+                minutes,
+                "1@chatroom",
+                "退款",
+                AlertSeverity::Warning,
+            ))
+            .await
+            .unwrap();
+        }
+        let start = record(30, "", "", AlertSeverity::Info).at;
+        let records = log
+            .load_range(start, start + Duration::hours(3))
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(dir.path().join("alerts/2026-10-15.jsonl").exists());
+    }
+
+    #[test]
+    fn test_summarize_alerts() {
+        let records = [
+            record(0, "1@chatroom", "退款", AlertSeverity::Warning),
+            record(1, "2@chatroom", "退款", AlertSeverity::Warning),
+            record(2, "2@chatroom", "退款", AlertSeverity::Warning),
+            record(3, "1@chatroom", "投诉", AlertSeverity::Critical),
+            record(4, "1@chatroom", "优惠", AlertSeverity::Info),
+        ];
+        let lines = summarize_alerts(&records);
+        let keys: Vec<_> = lines
+            .iter()
+            .map(|l| (l.keyword.as_deref().unwrap(), l.total, l.forwarded))
+            .collect();
+        assert_eq!(keys, [("投诉", 1, 1), ("退款", 3, 0), ("优惠", 1, 0)]);
+        assert_eq!(
+            lines[1].chats,
+            [("2@chatroom".to_string(), 2), ("1@chatroom".to_string(), 1)]
+        );
+    }
+}
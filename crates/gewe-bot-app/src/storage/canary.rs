@@ -0,0 +1,342 @@
+//! 灰度发布状态
+//!
+//! 单个 JSON 文件：`{data_dir}/canary/canary.json`，由发布接口写入、dispatcher 定时读取。
+//! 观察期内仅灰度机器人使用新版本规则，其余机器人保持基线版本；期满且错误率未回退时推广到全部机器人，
+//! 观察期内错误率明显上升则自动回滚
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::{OpsEvent, OpsEventKind};
+
+/// 默认观察时长（秒）
+pub const DEFAULT_CANARY_WINDOW_SECS: u64 = 1800;
+/// 默认允许的错误率上升幅度（绝对值）
+pub const DEFAULT_MAX_ERROR_RATE_INCREASE: f64 = 0.05;
+/// 灰度机器人至少处理这么多消息后才判断错误率回退
+pub const DEFAULT_CANARY_MIN_MESSAGES: u64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CanaryStatus {
+    /// 观察中：仅灰度机器人使用新版本
+    Observing,
+    /// 已推广到全部机器人
+    Promoted,
+    /// 已回滚到基线版本
+    RolledBack,
+}
+
+/// 观察期内的消息与错误计数
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CanaryMetrics {
+    /// 发布前同样时长内灰度机器人的消息数
+    pub baseline_messages: u64,
+    pub baseline_errors: u64,
+    /// 发布后灰度机器人的消息数
+    pub canary_messages: u64,
+    pub canary_errors: u64,
+}
+
+fn rate(errors: u64, messages: u64) -> f64 {
+    if messages == 0 {
+        0.0
+    } else {
+        errors as f64 / messages as f64
+    }
+}
+
+impl CanaryMetrics {
+    pub fn baseline_error_rate(&self) -> f64 {
+        rate(self.baseline_errors, self.baseline_messages)
+    }
+
+    pub fn canary_error_rate(&self) -> f64 {
+        rate(self.canary_errors, self.canary_messages)
+    }
+}
+
+/// 一次灰度发布
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanaryState {
+    /// 新发布的版本
+    pub version: u64,
+    /// 发布前最近的已发布版本
+    pub baseline_version: u64,
+    /// 灰度机器人
+    pub app_id: String,
+    /// 主配置文件，回滚时用基线版本覆盖
+    pub config_path: PathBuf,
+    pub candidate_file: PathBuf,
+    pub baseline_file: PathBuf,
+    pub started_at: DateTime<Utc>,
+    pub window_secs: u64,
+    pub max_error_rate_increase: f64,
+    pub min_messages: u64,
+    pub status: CanaryStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<CanaryMetrics>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_at: Option<DateTime<Utc>>,
+    /// 推广或回滚的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// 定时评估的结论
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanaryVerdict {
+    Continue,
+    Promote,
+    Rollback(String),
+}
+
+impl CanaryState {
+    pub fn window(&self) -> Duration {
+        Duration::seconds(self.window_secs as i64)
+    }
+
+    pub fn window_end(&self) -> DateTime<Utc> {
+        self.started_at + self.window()
+    }
+
+    /// 规则按哪个版本文件生效
+    pub fn rules_file(&self, app_id: &str) -> &Path {
+        match self.status {
+            CanaryStatus::Observing if app_id == self.app_id => &self.candidate_file,
+            CanaryStatus::Observing | CanaryStatus::RolledBack => &self.baseline_file,
+            CanaryStatus::Promoted => &self.candidate_file,
+        }
+    }
+
+    pub fn decide(&mut self, status: CanaryStatus, reason: impl Into<String>, now: DateTime<Utc>) {
+        self.status = status;
+        self.reason = Some(reason.into());
+        self.decided_at = Some(now);
+    }
+
+    /// 统计灰度机器人发布前后同样时长内的消息与错误
+    pub fn measure(&self, events: &[OpsEvent], now: DateTime<Utc>) -> CanaryMetrics {
+        let baseline_start = self.started_at - self.window();
+        let canary_end = now.min(self.window_end());
+        let mut metrics = CanaryMetrics::default();
+        for event in events.iter().filter(|e| e.app_id == self.app_id) {
+            let (messages, errors) = if event.at >= baseline_start && event.at < self.started_at {
+                (&mut metrics.baseline_messages, &mut metrics.baseline_errors)
+            } else if event.at >= self.started_at && event.at < canary_end {
+                (&mut metrics.canary_messages, &mut metrics.canary_errors)
+            } else {
+                continue;
+            };
+            match event.kind {
+                OpsEventKind::Message => *messages += 1,
+                OpsEventKind::Error { .. } => *errors += 1,
+                _ => {}
+            }
+        }
+        metrics
+    }
+
+    /// 错误率回退立即回滚；观察期结束且未回退则推广
+    pub fn evaluate(&self, metrics: &CanaryMetrics, now: DateTime<Utc>) -> CanaryVerdict {
+        let baseline = metrics.baseline_error_rate();
+        let canary = metrics.canary_error_rate();
+        if metrics.canary_messages >= self.min_messages
+            && canary > baseline + self.max_error_rate_increase
+        {
+            return CanaryVerdict::Rollback(format!(
+                "错误率回退：{:.1}% → {:.1}%（{} 条消息）",
+                baseline * 100.0,
+                canary * 100.0,
+                metrics.canary_messages
+            ));
+        }
+        if now >= self.window_end() {
+            CanaryVerdict::Promote
+        } else {
+            CanaryVerdict::Continue
+        }
+    }
+}
+
+/// 灰度状态存储
+#[derive(Debug, Clone)]
+pub struct CanaryStore {
+    dir: PathBuf,
+}
+
+impl CanaryStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("canary"),
+        }
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("canary.json")
+    }
+
+    /// 读取最近一次灰度发布，从未灰度时返回 None
+    pub async fn load(&self) -> Result<Option<CanaryState>, String> {
+        let path = self.state_path();
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| format!("解析灰度状态失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("读取灰度状态失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    /// 清除灰度状态，之后的普通发布或回滚不再受其影响
+    pub async fn clear(&self) -> Result<(), String> {
+        match fs::remove_file(self.state_path()).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("清除灰度状态失败: {}", e)),
+        }
+    }
+
+    pub async fn save(&self, state: &CanaryState) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建灰度状态目录失败: {}", e))?;
+        let path = self.state_path();
+        let content = serde_json::to_string_pretty(state)
+            .map_err(|e| format!("序列化灰度状态失败: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入灰度状态失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入灰度状态失败 {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn state(started_at: DateTime<Utc>) -> CanaryState {
+        CanaryState {
+            version: 3,
+            baseline_version: 2,
+            app_id: "wx_canary".to_string(),
+            config_path: PathBuf::from("config/bot-app.v2.toml"),
+            candidate_file: PathBuf::from("config/backups/v3"),
+            baseline_file: PathBuf::from("config/backups/v2"),
+            started_at,
+            window_secs: 600,
+            max_error_rate_increase: DEFAULT_MAX_ERROR_RATE_INCREASE,
+            min_messages: 10,
+            status: CanaryStatus::Observing,
+            metrics: None,
+            decided_at: None,
+            reason: None,
+        }
+    }
+
+    fn event(app_id: &str, at: DateTime<Utc>, kind: OpsEventKind) -> OpsEvent {
+        OpsEvent {
+            at,
+            app_id: app_id.to_string(),
+            kind,
+        }
+    }
+
+    fn error() -> OpsEventKind {
+        OpsEventKind::Error {
+            source: "ai".to_string(),
+            message: "timeout".to_string(),
+            model: None,
+        }
+    }
+
+    #[test]
+    fn test_canary_rules_file() {
+        let mut state = state(Utc::now());
+        assert_eq!(
+            state.rules_file("wx_canary"),
+            Path::new("config/backups/v3")
+        );
+        assert_eq!(state.rules_file("wx_other"), Path::new("config/backups/v2"));
+        state.decide(CanaryStatus::Promoted, "ok", Utc::now());
+        assert_eq!(state.rules_file("wx_other"), Path::new("config/backups/v3"));
+        state.decide(CanaryStatus::RolledBack, "bad", Utc::now());
+        assert_eq!(
+            state.rules_file("wx_canary"),
+            Path::new("config/backups/v2")
+        );
+    }
+
+    #[test]
+    fn test_canary_measure_and_evaluate() {
+        let started = Utc::now() - Duration::seconds(300);
+        let state = state(started);
+        let mut events = Vec::new();
+        for i in 0..20 {
+            let before = started - Duration::seconds(10 + i);
+            let after = started + Duration::seconds(10 + i);
+            events.push(event("wx_canary", before, OpsEventKind::Message));
+            events.push(event("wx_canary", after, OpsEventKind::Message));
+            events.push(event("wx_other", after, error()));
+        }
+        events.push(event("wx_canary", started - Duration::seconds(5), error()));
+        // 基线窗口之前的事件不计入
+        events.push(event(
+            "wx_canary",
+            started - Duration::seconds(900),
+            error(),
+        ));
+
+        let now = Utc::now();
+        let metrics = state.measure(&events, now);
+        assert_eq!(metrics.baseline_messages, 20);
+        assert_eq!(metrics.baseline_errors, 1);
+        assert_eq!(metrics.canary_messages, 20);
+        assert_eq!(metrics.canary_errors, 0);
+        assert_eq!(state.evaluate(&metrics, now), CanaryVerdict::Continue);
+        assert_eq!(
+            state.evaluate(&metrics, state.window_end()),
+            CanaryVerdict::Promote
+        );
+
+        for i in 0..3 {
+            events.push(event("wx_canary", started + Duration::seconds(i), error()));
+        }
+        let metrics = state.measure(&events, now);
+        assert!(matches!(
+            state.evaluate(&metrics, now),
+            CanaryVerdict::Rollback(reason) if reason.contains("15.0%")
+        ));
+
+        // 消息太少时不判断回退
+        let quiet = CanaryMetrics {
+            canary_messages: 2,
+            canary_errors: 2,
+            ..Default::default()
+        };
+        assert_eq!(state.evaluate(&quiet, now), CanaryVerdict::Continue);
+    }
+
+    #[tokio::test]
+    async fn test_canary_store_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let store = CanaryStore::new(dir.path());
+        assert_eq!(store.load().await.unwrap(), None);
+
+        let mut state = state(Utc::now());
+        state.metrics = Some(CanaryMetrics::default());
+        store.save(&state).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), Some(state));
+
+        store.clear().await.unwrap();
+        assert_eq!(store.load().await.unwrap(), None);
+        store.clear().await.unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! 会话设置存储
+//!
+//! 每个机器人一份 JSON 文件：`{data_dir}/chat_settings/{app_id}.json`，记录各群聊/私聊的语言、时区与语气。
+//! `/chat-settings` 命令与管理页面写入，dispatcher 定时读取
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::todo::sanitize_segment;
+
+/// AI 回复的语气
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formality {
+    Casual,
+    Neutral,
+    Formal,
+}
+
+impl Formality {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "casual" | "随意" | "轻松" => Some(Self::Casual),
+            "neutral" | "中性" | "默认" => Some(Self::Neutral),
+            "formal" | "正式" => Some(Self::Formal),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Casual => "casual",
+            Self::Neutral => "neutral",
+            Self::Formal => "formal",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Casual => "轻松",
+            Self::Neutral => "中性",
+            Self::Formal => "正式",
+        }
+    }
+}
+
+/// 语言区域只允许字母、数字、`-` 与 `_`，如 `zh-CN`
+pub fn is_valid_locale(locale: &str) -> bool {
+    !locale.is_empty()
+        && locale.len() <= 35
+        && locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 一个会话的设置，未设置的项沿用默认行为
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    /// 语言区域，如 `zh-CN`、`en-US`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// IANA 时区，如 `Asia/Tokyo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formality: Option<Formality>,
+}
+
+impl ChatSettings {
+    pub fn is_empty(&self) -> bool {
+        self.locale.is_none() && self.timezone.is_none() && self.formality.is_none()
+    }
+}
+
+/// 某个机器人各会话的设置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettingsBook {
+    /// 会话（群聊 ID 或私聊 wxid）→ 设置
+    #[serde(default)]
+    pub chats: BTreeMap<String, ChatSettings>,
+}
+
+/// 基于文件的会话设置存储
+#[derive(Debug, Clone)]
+pub struct ChatSettingsStore {
+    dir: PathBuf,
+}
+
+impl ChatSettingsStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("chat_settings"),
+        }
+    }
+
+    fn book_path(&self, app_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", sanitize_segment(app_id)))
+    }
+
+    /// 读取设置，不存在时返回空
+    pub async fn load(&self, app_id: &str) -> Result<ChatSettingsBook, String> {
+        let path = self.book_path(app_id);
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析会话设置失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ChatSettingsBook::default()),
+            Err(e) => Err(format!("读取会话设置失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    pub async fn save(&self, app_id: &str, book: &ChatSettingsBook) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建会话设置目录失败: {}", e))?;
+        let path = self.book_path(app_id);
+        let content =
+            serde_json::to_string_pretty(book).map_err(|e| format!("序列化会话设置失败: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入会话设置失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入会话设置失败 {}: {}", path.display(), e))
+    }
+
+    /// 写入一个会话的设置，设置为空时删除该会话
+    pub async fn update(
+        &self,
+        app_id: &str,
+        chat: &str,
+        settings: ChatSettings,
+    ) -> Result<(), String> {
+        let mut book = self.load(app_id).await?;
+        if settings.is_empty() {
+            book.chats.remove(chat);
+        } else {
+            book.chats.insert(chat.to_string(), settings);
+        }
+        self.save(app_id, &book).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_chat_settings_store_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let store = ChatSettingsStore::new(temp.path());
+        assert!(store.load("app").await.unwrap().chats.is_empty());
+
+        let settings = ChatSettings {
+            locale: Some("en-US".to_string()),
+            timezone: Some("Asia/Tokyo".to_string()),
+            formality: Some(Formality::Formal),
+        };
+        store
+            .update("app", "123@chatroom", settings.clone())
+            .await
+            .unwrap();
+        let book = store.load("app").await.unwrap();
+        assert_eq!(book.chats.get("123@chatroom"), Some(&settings));
+        assert!(store.load("other").await.unwrap().chats.is_empty());
+
+        store
+            .update("app", "123@chatroom", ChatSettings::default())
+            .await
+            .unwrap();
+        assert!(store.load("app").await.unwrap().chats.is_empty());
+    }
+
+    #[test]
+    fn test_formality_parse() {
+        assert_eq!(Formality::parse(" Formal "), Some(Formality::Formal));
+        assert_eq!(Formality::parse("轻松"), Some(Formality::Casual));
+        assert_eq!(Formality::parse("strict"), None);
+        assert!(is_valid_locale("zh-CN"));
+        assert!(!is_valid_locale("zh CN"));
+        assert!(!is_valid_locale(""));
+    }
+}
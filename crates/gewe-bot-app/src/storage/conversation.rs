@@ -0,0 +1,291 @@
+//! AI 对话记忆存储
+//!
+//! 按 (机器人 app_id, 会话) 保存最近几轮问答，调用模型时作为历史消息传入。内置内存与 SQLite
+//! 两种后端，库使用者可实现 [`ConversationStore`] 接入其他存储。
+
+use std::collections::{HashMap, VecDeque};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+/// 一轮问答
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversationTurn {
+    /// 用户消息，群聊带发送者昵称
+    pub user: String,
+    pub assistant: String,
+    pub at: DateTime<Utc>,
+}
+
+/// 对话记忆存储后端
+#[async_trait]
+pub trait ConversationStore: Send + Sync {
+    /// 读取 `since` 之后最近的 `max_turns` 轮，按时间先后排列
+    async fn recent(
+        &self,
+        app_id: &str,
+        chat: &str,
+        since: DateTime<Utc>,
+        max_turns: usize,
+    ) -> Result<Vec<ConversationTurn>, String>;
+
+    /// 追加一轮，该会话只保留最近的 `keep` 轮
+    async fn append(
+        &self,
+        app_id: &str,
+        chat: &str,
+        turn: ConversationTurn,
+        keep: usize,
+    ) -> Result<(), String>;
+}
+
+/// 内存存储，重启后清空
+#[derive(Debug, Default)]
+pub struct InMemoryConversationStore {
+    chats: Mutex<HashMap<(String, String), VecDeque<ConversationTurn>>>,
+}
+
+#[async_trait]
+impl ConversationStore for InMemoryConversationStore {
+    async fn recent(
+        &self,
+        app_id: &str,
+        chat: &str,
+        since: DateTime<Utc>,
+        max_turns: usize,
+    ) -> Result<Vec<ConversationTurn>, String> {
+        let chats = self.chats.lock().await;
+        let Some(turns) = chats.get(&(app_id.to_string(), chat.to_string())) else {
+            return Ok(Vec::new());
+        };
+        let recent: Vec<ConversationTurn> = turns
+            .iter()
+            .rev()
+            .take_while(|t| t.at >= since)
+            .take(max_turns)
+            .cloned()
+            .collect();
+        Ok(recent.into_iter().rev().collect())
+    }
+
+    async fn append(
+        &self,
+        app_id: &str,
+        chat: &str,
+        turn: ConversationTurn,
+        keep: usize,
+    ) -> Result<(), String> {
+        let mut chats = self.chats.lock().await;
+        let turns = chats
+            .entry((app_id.to_string(), chat.to_string()))
+            .or_default();
+        turns.push_back(turn);
+        while turns.len() > keep {
+            turns.pop_front();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS conversation_turns (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    app_id TEXT NOT NULL,
+    chat TEXT NOT NULL,
+    user_text TEXT NOT NULL,
+    assistant_text TEXT NOT NULL,
+    at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_conversation_turns_chat ON conversation_turns (app_id, chat, id);
+"#;
+
+/// SQLite 存储，记忆在重启后保留；首次读写时建表
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct SqliteConversationStore {
+    pool: sqlx::SqlitePool,
+    schema: tokio::sync::OnceCell<()>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteConversationStore {
+    /// 打开数据库文件，不存在时创建（连接延迟到首次使用）
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        if let Some(parent) = path.as_ref().parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("创建对话记忆目录失败: {}", e))?;
+            }
+        }
+        let options = sqlx::sqlite::SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        Ok(Self {
+            pool: sqlx::SqlitePool::connect_lazy_with(options),
+            schema: tokio::sync::OnceCell::new(),
+        })
+    }
+
+    async fn pool(&self) -> Result<&sqlx::SqlitePool, String> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::raw_sql(SCHEMA)
+                    .execute(&self.pool)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| format!("初始化对话记忆表失败: {}", e))
+            })
+            .await?;
+        Ok(&self.pool)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ConversationStore for SqliteConversationStore {
+    async fn recent(
+        &self,
+        app_id: &str,
+        chat: &str,
+        since: DateTime<Utc>,
+        max_turns: usize,
+    ) -> Result<Vec<ConversationTurn>, String> {
+        use sqlx::Row;
+        let rows = sqlx::query(
+            "SELECT user_text, assistant_text, at FROM conversation_turns \
+             WHERE app_id = ? AND chat = ? AND at >= ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(app_id)
+        .bind(chat)
+        .bind(since.timestamp())
+        .bind(max_turns as i64)
+        .fetch_all(self.pool().await?)
+        .await
+        .map_err(|e| format!("读取对话记忆失败: {}", e))?;
+        let mut turns = rows
+            .iter()
+            .map(|row| ConversationTurn {
+                user: row.get("user_text"),
+                assistant: row.get("assistant_text"),
+                at: DateTime::from_timestamp(row.get("at"), 0).unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        turns.reverse();
+        Ok(turns)
+    }
+
+    async fn append(
+        &self,
+        app_id: &str,
+        chat: &str,
+        turn: ConversationTurn,
+        keep: usize,
+    ) -> Result<(), String> {
+        let pool = self.pool().await?;
+        sqlx::query(
+            "INSERT INTO conversation_turns (app_id, chat, user_text, assistant_text, at) \
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(app_id)
+        .bind(chat)
+        .bind(&turn.user)
+        .bind(&turn.assistant)
+        .bind(turn.at.timestamp())
+        .execute(pool)
+        .await
+        .map_err(|e| format!("写入对话记忆失败: {}", e))?;
+        sqlx::query(
+            "DELETE FROM conversation_turns WHERE app_id = ? AND chat = ? AND id NOT IN \
+             (SELECT id FROM conversation_turns WHERE app_id = ? AND chat = ? ORDER BY id DESC LIMIT ?)",
+        )
+        .bind(app_id)
+        .bind(chat)
+        .bind(app_id)
+        .bind(chat)
+        .bind(keep as i64)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("清理对话记忆失败: {}", e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(user: &str, at: DateTime<Utc>) -> ConversationTurn {
+        ConversationTurn {
+            user: user.to_string(),
+            assistant: format!("re: {}", user),
+            at,
+        }
+    }
+
+    async fn exercise(store: &dyn ConversationStore) {
+        let now = Utc::now();
+        let old = now - chrono::Duration::hours(2);
+        store
+            .append("app", "chat", turn("q0", old), 3)
+            .await
+            .unwrap();
+        for q in ["q1", "q2", "q3"] {
+            store.append("app", "chat", turn(q, now), 3).await.unwrap();
+        }
+        store
+            .append("app", "other", turn("x", now), 3)
+            .await
+            .unwrap();
+
+        // 只保留最近 3 轮，q0 已被淘汰
+        let since = now - chrono::Duration::days(1);
+        let users: Vec<String> = store
+            .recent("app", "chat", since, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| t.user)
+            .collect();
+        assert_eq!(users, ["q1", "q2", "q3"]);
+
+        let recent = store.recent("app", "chat", since, 2).await.unwrap();
+        assert_eq!(recent[0].user, "q2");
+        assert_eq!(recent[1].assistant, "re: q3");
+        assert!(store
+            .recent("app", "chat", now + chrono::Duration::minutes(1), 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .recent("other_app", "chat", since, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        exercise(&InMemoryConversationStore::default()).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory/conversations.db");
+        exercise(&SqliteConversationStore::open(&path).unwrap()).await;
+        // 重新打开后记忆仍在
+        let reopened = SqliteConversationStore::open(&path).unwrap();
+        let since = Utc::now() - chrono::Duration::days(1);
+        assert_eq!(
+            reopened
+                .recent("app", "chat", since, 10)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! 死信队列
+//!
+//! 规则动作重试后仍失败且配置了 `on_error.dlq` 时，追加写入 `{data_dir}/dlq/dead_letters.jsonl`，
+//! 保留原消息的关键信息，便于人工排查与补发
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::jsonl;
+
+/// 一条处理失败的消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub at: DateTime<Utc>,
+    pub app_id: String,
+    /// 规则 ID（V2 为规则实例 id）
+    pub rule: String,
+    /// 失败的动作，如 reply_text、ai
+    pub action: String,
+    pub error: String,
+    /// 含重试在内的尝试次数
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_wxid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender_wxid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_msg_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+/// 基于 JSONL 文件的死信存储
+#[derive(Debug, Clone)]
+pub struct DeadLetterStore {
+    dir: PathBuf,
+}
+
+impl DeadLetterStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("dlq"),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("dead_letters.jsonl")
+    }
+
+    pub async fn append(&self, letter: &DeadLetter) -> Result<(), String> {
+        jsonl::append_line(&self.path(), letter)
+            .await
+            .map_err(|e| format!("写入死信失败: {}", e))
+    }
+
+    /// 按写入顺序读取全部死信
+    pub async fn load_all(&self) -> Result<Vec<DeadLetter>, String> {
+        jsonl::read_lines(&self.path())
+            .await
+            .map_err(|e| format!("读取死信失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_dead_letter_store() {
+        let temp = TempDir::new().unwrap();
+        let store = DeadLetterStore::new(temp.path());
+        assert!(store.load_all().await.unwrap().is_empty());
+
+        let letter = DeadLetter {
+            at: Utc::now(),
+            app_id: "wx_a".to_string(),
+            rule: "faq".to_string(),
+            action: "reply_link".to_string(),
+            error: "timeout".to_string(),
+            attempts: 3,
+            from_wxid: Some("wxid_u".to_string()),
+            sender_wxid: None,
+            new_msg_id: Some(42),
+            content: Some("查订单 1".to_string()),
+        };
+        store.append(&letter).await.unwrap();
+        assert_eq!(store.load_all().await.unwrap(), vec![letter]);
+        assert!(temp.path().join("dlq/dead_letters.jsonl").exists());
+    }
+}
@@ -0,0 +1,67 @@
+//! Embedding 缓存（内存）
+//!
+//! 按键（接口 + 模型 + 文本）保存 embedding，容量满时淘汰最早写入的条目
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    entries: HashMap<String, Arc<Vec<f64>>>,
+    /// 写入顺序，用于淘汰
+    order: VecDeque<String>,
+}
+
+/// 容量有限的 embedding 缓存
+#[derive(Debug)]
+pub struct EmbeddingCache {
+    inner: Mutex<CacheInner>,
+    capacity: usize,
+}
+
+impl EmbeddingCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(CacheInner::default()),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Arc<Vec<f64>>> {
+        self.inner.lock().await.entries.get(key).cloned()
+    }
+
+    pub async fn insert(&self, key: String, embedding: Arc<Vec<f64>>) {
+        let mut inner = self.inner.lock().await;
+        if inner.entries.insert(key.clone(), embedding).is_none() {
+            inner.order.push_back(key);
+        }
+        while inner.order.len() > self.capacity {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_embedding_cache_eviction() {
+        let cache = EmbeddingCache::new(2);
+        cache.insert("a".into(), Arc::new(vec![1.0])).await;
+        cache.insert("b".into(), Arc::new(vec![2.0])).await;
+        // 覆盖已有的键不改变淘汰顺序
+        cache.insert("a".into(), Arc::new(vec![1.5])).await;
+        assert_eq!(cache.get("a").await.as_deref(), Some(&vec![1.5]));
+
+        cache.insert("c".into(), Arc::new(vec![3.0])).await;
+        assert!(cache.get("a").await.is_none());
+        assert_eq!(cache.get("b").await.as_deref(), Some(&vec![2.0]));
+        assert_eq!(cache.get("c").await.as_deref(), Some(&vec![3.0]));
+    }
+}
@@ -0,0 +1,304 @@
+//! 转人工工单
+//!
+//! 未关闭的工单保存在单个 JSON 文件 `{data_dir}/escalation/tickets.json`，每次变更后整体写回；
+//! 关闭的工单从中移除并追加到 `{data_dir}/escalation/closed.jsonl` 备查
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::jsonl;
+
+/// 一张转人工工单
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ticket {
+    pub id: u64,
+    /// 规则所属机器人
+    pub app_id: String,
+    /// 会话：群聊 ID 或私聊 wxid
+    pub chat: String,
+    /// 发起转人工的用户 wxid
+    pub user: String,
+    /// 用户昵称，群聊中转发回复时用于 @
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    pub rule: String,
+    /// 触发转人工的消息
+    pub text: String,
+    /// 收到通知、可以接单的值班人员；排队中的工单为空
+    pub operators: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// 非值班时间创建的工单排队到该时间（下一班开始）再通知值班人员
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued_until: Option<DateTime<Utc>>,
+    /// 排队的工单转交值班人员的时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routed_at: Option<DateTime<Utc>>,
+    /// 排队期间用户的后续消息，转交时一并发给值班人员
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backlog: Vec<String>,
+    /// 接单人员
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<String>,
+    /// 最近一次接单（或切换到该工单）的时间，接单人员有多张工单时回复发往最近的一张
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acked_at: Option<DateTime<Utc>>,
+    /// 最近一次提醒值班人员的时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reminded_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_by: Option<String>,
+}
+
+impl Ticket {
+    /// 转发给值班人员时显示的用户名
+    pub fn user_label(&self) -> &str {
+        self.user_name.as_deref().unwrap_or(&self.user)
+    }
+
+    /// 响应时长的起点：通知值班人员的时间
+    pub fn routed_since(&self) -> DateTime<Utc> {
+        self.routed_at.unwrap_or(self.created_at)
+    }
+}
+
+/// 未关闭的工单
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TicketBook {
+    /// 下一张工单的编号，从 1 开始
+    #[serde(default)]
+    pub next_id: u64,
+    #[serde(default)]
+    pub open: Vec<Ticket>,
+}
+
+impl TicketBook {
+    /// 新建工单并返回编号
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        &mut self,
+        app_id: &str,
+        chat: &str,
+        user: &str,
+        user_name: Option<String>,
+        rule: &str,
+        text: &str,
+        operators: Vec<String>,
+        now: DateTime<Utc>,
+    ) -> u64 {
+        let id = self.next_id.max(1);
+        self.next_id = id + 1;
+        self.open.push(Ticket {
+            id,
+            app_id: app_id.to_string(),
+            chat: chat.to_string(),
+            user: user.to_string(),
+            user_name,
+            rule: rule.to_string(),
+            text: text.to_string(),
+            operators,
+            created_at: now,
+            queued_until: None,
+            routed_at: None,
+            backlog: Vec::new(),
+            assignee: None,
+            acked_at: None,
+            reminded_at: None,
+            closed_at: None,
+            closed_by: None,
+        });
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Ticket> {
+        self.open.iter().find(|t| t.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Ticket> {
+        self.open.iter_mut().find(|t| t.id == id)
+    }
+
+    /// 用户在该会话中未关闭的工单
+    pub fn find_by_user(&self, app_id: &str, chat: &str, user: &str) -> Option<&Ticket> {
+        self.open
+            .iter()
+            .find(|t| t.app_id == app_id && t.chat == chat && t.user == user)
+    }
+
+    /// 接单人员当前处理的工单：最近接单或切换到的一张
+    pub fn current_for(&self, app_id: &str, operator: &str) -> Option<&Ticket> {
+        self.open
+            .iter()
+            .filter(|t| t.app_id == app_id && t.assignee.as_deref() == Some(operator))
+            .max_by_key(|t| t.acked_at)
+    }
+
+    /// 值班人员可见的工单：通知过该人员的全部未关闭工单，按编号排列
+    pub fn visible_to<'a>(
+        &'a self,
+        app_id: &'a str,
+        operator: &'a str,
+    ) -> impl Iterator<Item = &'a Ticket> + 'a {
+        self.open
+            .iter()
+            .filter(move |t| t.app_id == app_id && t.operators.iter().any(|o| o == operator))
+    }
+
+    /// 关闭工单并从未关闭列表中移除
+    pub fn close(&mut self, id: u64, by: &str, now: DateTime<Utc>) -> Option<Ticket> {
+        let idx = self.open.iter().position(|t| t.id == id)?;
+        let mut ticket = self.open.remove(idx);
+        ticket.closed_at = Some(now);
+        ticket.closed_by = Some(by.to_string());
+        Some(ticket)
+    }
+}
+
+/// 基于文件的工单存储
+#[derive(Debug, Clone)]
+pub struct TicketStore {
+    dir: PathBuf,
+}
+
+impl TicketStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("escalation"),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("tickets.json")
+    }
+
+    /// 读取未关闭的工单，不存在时返回空
+    pub async fn load(&self) -> Result<TicketBook, String> {
+        let path = self.path();
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析工单失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(TicketBook::default()),
+            Err(e) => Err(format!("读取工单失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    pub async fn save(&self, book: &TicketBook) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建工单目录失败: {}", e))?;
+        let content =
+            serde_json::to_string_pretty(book).map_err(|e| format!("序列化工单失败: {}", e))?;
+        let path = self.path();
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入工单失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入工单失败 {}: {}", path.display(), e))
+    }
+
+    /// 记录已关闭的工单
+    pub async fn archive(&self, ticket: &Ticket) -> Result<(), String> {
+        jsonl::append_line(&self.dir.join("closed.jsonl"), ticket)
+            .await
+            .map_err(|e| format!("写入已关闭工单失败: {}", e))
+    }
+
+    /// 读取已关闭的工单，按关闭先后排列
+    pub async fn closed(&self) -> Result<Vec<Ticket>, String> {
+        jsonl::read_lines(&self.dir.join("closed.jsonl"))
+            .await
+            .map_err(|e| format!("读取已关闭工单失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    fn operators() -> Vec<String> {
+        vec!["wxid_op1".to_string(), "wxid_op2".to_string()]
+    }
+
+    #[tokio::test]
+    async fn test_ticket_store_round_trip_and_archive() {
+        let temp = TempDir::new().unwrap();
+        let store = TicketStore::new(temp.path());
+        let mut book = store.load().await.unwrap();
+        assert!(book.open.is_empty());
+
+        let now = Utc::now();
+        let id = book.create(
+            "wx1",
+            "wxid_user",
+            "wxid_user",
+            Some("张三".to_string()),
+            "human",
+            "转人工",
+            operators(),
+            now,
+        );
+        assert_eq!(id, 1);
+        store.save(&book).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), book);
+
+        let closed = book.close(id, "wxid_op1", now).unwrap();
+        store.archive(&closed).await.unwrap();
+        store.save(&book).await.unwrap();
+        let reloaded = store.load().await.unwrap();
+        assert!(reloaded.open.is_empty());
+        assert_eq!(reloaded.next_id, 2);
+        let archived = store.closed().await.unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].closed_by.as_deref(), Some("wxid_op1"));
+    }
+
+    #[test]
+    fn test_ticket_book_lookup() {
+        let mut book = TicketBook::default();
+        let now = Utc::now();
+        let first = book.create(
+            "wx1",
+            "1@chatroom",
+            "wxid_a",
+            None,
+            "human",
+            "转人工",
+            operators(),
+            now,
+        );
+        let second = book.create(
+            "wx1",
+            "wxid_b",
+            "wxid_b",
+            None,
+            "human",
+            "人工客服",
+            operators(),
+            now,
+        );
+        assert_eq!(
+            book.find_by_user("wx1", "1@chatroom", "wxid_a").unwrap().id,
+            first
+        );
+        assert!(book.find_by_user("wx1", "2@chatroom", "wxid_a").is_none());
+        assert!(book.find_by_user("wx2", "1@chatroom", "wxid_a").is_none());
+
+        for (id, secs) in [(first, 0), (second, 10)] {
+            let ticket = book.get_mut(id).unwrap();
+            ticket.assignee = Some("wxid_op1".to_string());
+            ticket.acked_at = Some(now + Duration::seconds(secs));
+        }
+        assert_eq!(book.current_for("wx1", "wxid_op1").unwrap().id, second);
+        assert!(book.current_for("wx1", "wxid_op2").is_none());
+        assert_eq!(book.visible_to("wx1", "wxid_op2").count(), 2);
+        assert_eq!(book.visible_to("wx1", "wxid_other").count(), 0);
+    }
+}
@@ -0,0 +1,176 @@
+//! Prompt A/B 实验事件存储
+//!
+//! 追加写入 `{data_dir}/experiments/events.jsonl`，每行一个事件；报告按需聚合
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::jsonl;
+
+/// 实验信号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExperimentSignal {
+    /// 某个变体生成并发送了一次回复
+    Served,
+    /// 用户正向反馈（如“谢谢”）
+    Positive,
+    /// 用户负向反馈（如“不对”）
+    Negative,
+    /// 管理员投票
+    AdminUp,
+    AdminDown,
+}
+
+/// 单条实验事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExperimentEvent {
+    pub at: DateTime<Utc>,
+    pub experiment: String,
+    pub variant: String,
+    pub signal: ExperimentSignal,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+/// 单个变体的统计
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct VariantReport {
+    pub variant: String,
+    pub served: u64,
+    pub positive: u64,
+    pub negative: u64,
+    pub admin_up: u64,
+    pub admin_down: u64,
+    /// 正向信号占全部反馈的比例，无反馈时为空
+    pub satisfaction: Option<f64>,
+}
+
+/// 单个实验的报告
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExperimentReport {
+    pub experiment: String,
+    pub variants: Vec<VariantReport>,
+}
+
+/// 按实验、变体聚合事件
+pub fn build_experiment_reports(events: &[ExperimentEvent]) -> Vec<ExperimentReport> {
+    let mut grouped: BTreeMap<&str, BTreeMap<&str, VariantReport>> = BTreeMap::new();
+    for event in events {
+        let report = grouped
+            .entry(&event.experiment)
+            .or_default()
+            .entry(&event.variant)
+            .or_insert_with(|| VariantReport {
+                variant: event.variant.clone(),
+                ..Default::default()
+            });
+        match event.signal {
+            ExperimentSignal::Served => report.served += 1,
+            ExperimentSignal::Positive => report.positive += 1,
+            ExperimentSignal::Negative => report.negative += 1,
+            ExperimentSignal::AdminUp => report.admin_up += 1,
+            ExperimentSignal::AdminDown => report.admin_down += 1,
+        }
+    }
+    grouped
+        .into_iter()
+        .map(|(experiment, variants)| ExperimentReport {
+            experiment: experiment.to_string(),
+            variants: variants
+                .into_values()
+                .map(|mut v| {
+                    let good = v.positive + v.admin_up;
+                    let total = good + v.negative + v.admin_down;
+                    v.satisfaction = (total > 0).then(|| good as f64 / total as f64);
+                    v
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// 基于 JSONL 文件的实验事件存储
+#[derive(Debug, Clone)]
+pub struct ExperimentStore {
+    dir: PathBuf,
+}
+
+impl ExperimentStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("experiments"),
+        }
+    }
+
+    fn events_path(&self) -> PathBuf {
+        self.dir.join("events.jsonl")
+    }
+
+    /// 追加一条事件
+    pub async fn append(&self, event: &ExperimentEvent) -> Result<(), String> {
+        jsonl::append_line(&self.events_path(), event)
+            .await
+            .map_err(|e| format!("写入实验事件失败: {}", e))
+    }
+
+    /// 读取全部事件，跳过无法解析的行
+    pub async fn load_all(&self) -> Result<Vec<ExperimentEvent>, String> {
+        jsonl::read_lines(&self.events_path())
+            .await
+            .map_err(|e| format!("读取实验事件失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn event(variant: &str, signal: ExperimentSignal) -> ExperimentEvent {
+        ExperimentEvent {
+            at: Utc::now(),
+            experiment: "faq".to_string(),
+            variant: variant.to_string(),
+            signal,
+            app_id: None,
+            chat: None,
+            user: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_experiment_store_and_report() {
+        let temp = TempDir::new().unwrap();
+        let store = ExperimentStore::new(temp.path());
+        assert!(store.load_all().await.unwrap().is_empty());
+
+        for e in [
+            event("a", ExperimentSignal::Served),
+            event("a", ExperimentSignal::Served),
+            event("a", ExperimentSignal::Positive),
+            event("a", ExperimentSignal::AdminDown),
+            event("b", ExperimentSignal::Served),
+        ] {
+            store.append(&e).await.unwrap();
+        }
+
+        let events = store.load_all().await.unwrap();
+        assert_eq!(events.len(), 5);
+        let reports = build_experiment_reports(&events);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].experiment, "faq");
+        let a = &reports[0].variants[0];
+        assert_eq!((a.variant.as_str(), a.served, a.positive), ("a", 2, 1));
+        assert_eq!(a.satisfaction, Some(0.5));
+        let b = &reports[0].variants[1];
+        assert_eq!((b.served, b.satisfaction), (1, None));
+    }
+}
@@ -3,7 +3,9 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use super::{ConfigStorage, FileStorage, PostgresStorage, PromptStorage};
+#[cfg(feature = "postgres")]
+use super::PostgresStorage;
+use super::{ConfigStorage, FileStorage, PromptStorage};
 
 /// 存储后端类型
 #[derive(Debug, Clone)]
@@ -12,6 +14,10 @@ pub enum StorageBackend {
     Postgres,
 }
 
+/// 未启用 `postgres` 特性时选择 Postgres 后端的错误信息
+#[cfg(not(feature = "postgres"))]
+const POSTGRES_DISABLED: &str = "Postgres 存储未启用，请使用 --features postgres 重新编译";
+
 /// 存储工厂
 pub struct StorageFactory;
 
@@ -41,6 +47,7 @@ impl StorageFactory {
                 let storage = FileStorage::new(config_path, prompts_dir, backup_dir);
                 Ok(Arc::new(storage) as Arc<dyn ConfigStorage>)
             }
+            #[cfg(feature = "postgres")]
             StorageBackend::Postgres => {
                 let database_url = database_url.ok_or("Postgres 存储需要 database_url")?;
                 let storage = PostgresStorage::new(&database_url).await?;
@@ -50,6 +57,11 @@ impl StorageFactory {
 
                 Ok(Arc::new(storage) as Arc<dyn ConfigStorage>)
             }
+            #[cfg(not(feature = "postgres"))]
+            StorageBackend::Postgres => {
+                let _ = database_url;
+                Err(POSTGRES_DISABLED.to_string())
+            }
         }
     }
 
@@ -68,11 +80,17 @@ impl StorageFactory {
                 let storage = FileStorage::new(config_path, prompts_dir, backup_dir);
                 Ok(Arc::new(storage) as Arc<dyn PromptStorage>)
             }
+            #[cfg(feature = "postgres")]
             StorageBackend::Postgres => {
                 let database_url = database_url.ok_or("Postgres 存储需要 database_url")?;
                 let storage = PostgresStorage::new(&database_url).await?;
                 Ok(Arc::new(storage) as Arc<dyn PromptStorage>)
             }
+            #[cfg(not(feature = "postgres"))]
+            StorageBackend::Postgres => {
+                let _ = database_url;
+                Err(POSTGRES_DISABLED.to_string())
+            }
         }
     }
 }
@@ -174,6 +192,7 @@ version = "1.0.0"
         assert!(result.is_ok());
     }
 
+    #[cfg(feature = "postgres")]
     #[tokio::test]
     async fn test_create_config_storage_postgres_without_url() {
         let result =
@@ -212,6 +231,7 @@ version = "1.0.0"
         assert!(result.is_ok());
     }
 
+    #[cfg(feature = "postgres")]
     #[tokio::test]
     async fn test_create_prompt_storage_postgres_without_url() {
         let result =
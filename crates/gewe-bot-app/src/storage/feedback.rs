@@ -0,0 +1,141 @@
+//! AI 回复评价存储
+//!
+//! 追加写入 `{data_dir}/feedback/ratings.jsonl`，按规则与模型汇总满意度
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::jsonl;
+
+/// 一条评价，关联到具体的一轮问答
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedbackRecord {
+    pub at: DateTime<Utc>,
+    pub app_id: String,
+    pub chat: String,
+    pub user: String,
+    /// 规则 ID（V2 为规则实例 id）
+    pub rule: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    pub reply: String,
+    /// true 为好评，false 为差评
+    pub positive: bool,
+}
+
+/// 某个规则 + 模型的满意度汇总
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FeedbackSummary {
+    pub rule: String,
+    pub model: String,
+    pub positive: u64,
+    pub negative: u64,
+    pub satisfaction: Option<f64>,
+}
+
+/// 按 (规则, 模型) 汇总评价
+pub fn build_feedback_summary(records: &[FeedbackRecord]) -> Vec<FeedbackSummary> {
+    let mut grouped: BTreeMap<(&str, &str), FeedbackSummary> = BTreeMap::new();
+    for record in records {
+        let summary = grouped
+            .entry((&record.rule, &record.model))
+            .or_insert_with(|| FeedbackSummary {
+                rule: record.rule.clone(),
+                model: record.model.clone(),
+                ..Default::default()
+            });
+        if record.positive {
+            summary.positive += 1;
+        } else {
+            summary.negative += 1;
+        }
+    }
+    grouped
+        .into_values()
+        .map(|mut s| {
+            let total = s.positive + s.negative;
+            s.satisfaction = (total > 0).then(|| s.positive as f64 / total as f64);
+            s
+        })
+        .collect()
+}
+
+/// 基于 JSONL 文件的评价存储
+#[derive(Debug, Clone)]
+pub struct FeedbackStore {
+    dir: PathBuf,
+}
+
+impl FeedbackStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("feedback"),
+        }
+    }
+
+    fn ratings_path(&self) -> PathBuf {
+        self.dir.join("ratings.jsonl")
+    }
+
+    pub async fn append(&self, record: &FeedbackRecord) -> Result<(), String> {
+        jsonl::append_line(&self.ratings_path(), record)
+            .await
+            .map_err(|e| format!("写入评价失败: {}", e))
+    }
+
+    pub async fn load_all(&self) -> Result<Vec<FeedbackRecord>, String> {
+        jsonl::read_lines(&self.ratings_path())
+            .await
+            .map_err(|e| format!("读取评价失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(rule: &str, model: &str, positive: bool) -> FeedbackRecord {
+        FeedbackRecord {
+            at: Utc::now(),
+            app_id: "app".to_string(),
+            chat: "123@chatroom".to_string(),
+            user: "wxid_a".to_string(),
+            rule: rule.to_string(),
+            model: model.to_string(),
+            variant: None,
+            question: Some("几点开会".to_string()),
+            reply: "三点".to_string(),
+            positive,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_feedback_store_and_summary() {
+        let temp = TempDir::new().unwrap();
+        let store = FeedbackStore::new(temp.path());
+        for r in [
+            record("faq", "gpt-4o", true),
+            record("faq", "gpt-4o", true),
+            record("faq", "gpt-4o", false),
+            record("chat", "gpt-4o-mini", false),
+        ] {
+            store.append(&r).await.unwrap();
+        }
+
+        let records = store.load_all().await.unwrap();
+        assert_eq!(records.len(), 4);
+        let summary = build_feedback_summary(&records);
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].rule, "chat");
+        assert_eq!(summary[0].satisfaction, Some(0.0));
+        assert_eq!((summary[1].positive, summary[1].negative), (2, 1));
+        assert!((summary[1].satisfaction.unwrap() - 2.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -176,7 +176,7 @@ impl ConfigStorage for FileStorage {
         }
 
         // 按版本号降序排列
-        backups.sort_by(|a, b| b.version.cmp(&a.version));
+        backups.sort_by_key(|b| std::cmp::Reverse(b.version));
         Ok(backups)
     }
 }
@@ -0,0 +1,588 @@
+//! 会话消息记录
+//!
+//! 每个会话一份 JSONL 文件：`{data_dir}/history/{app_id}/{chat}.jsonl`，
+//! 条数超过保留上限的两倍时截断为最近的保留条数，避免每次追加都重写文件。
+//! 行数记在同目录的 `{chat}.lines` 中，重启后首次追加无需读取整个文件；
+//! 配置了全文搜索索引时同时写入索引，截断掉的消息从索引中删除（见 [`super::history_search`]）
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use super::history_search::{open_history_search, HistorySearch};
+use super::jsonl;
+use super::todo::sanitize_segment;
+
+/// 消息方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryDirection {
+    /// 收到的消息
+    In,
+    /// 机器人发出的消息
+    Out,
+}
+
+/// 一条会话消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryMessage {
+    pub at: DateTime<Utc>,
+    pub app_id: String,
+    /// 会话：群聊 ID 或私聊 wxid
+    pub chat: String,
+    /// 发送者 wxid，机器人发出的消息为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    pub direction: HistoryDirection,
+    /// 消息类型，如 text、image、file
+    pub kind: String,
+    /// 规范化后的文本内容
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_msg_id: Option<i64>,
+    /// 发出的图片、文件的原始地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_url: Option<String>,
+}
+
+/// 单个会话的锁，锁内为该会话文件当前的行数，尚未读取时为 `None`
+type ChatLines = Arc<Mutex<Option<usize>>>;
+
+/// 基于 JSONL 文件的会话消息记录
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    dir: PathBuf,
+    keep: usize,
+    /// 每个会话一把锁，保证同一会话的追加与截断不交错，不同会话互不阻塞
+    chats: Arc<std::sync::Mutex<HashMap<PathBuf, ChatLines>>>,
+    search: Option<Arc<dyn HistorySearch>>,
+}
+
+impl HistoryStore {
+    pub fn new(data_dir: impl AsRef<Path>, keep: usize) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("history"),
+            keep: keep.max(1),
+            chats: Arc::default(),
+            search: None,
+        }
+    }
+
+    /// 按配置创建，配置了 `search` 时打开全文搜索索引
+    pub fn from_config(
+        data_dir: &str,
+        config: &crate::config::HistoryConfig,
+    ) -> Result<Self, String> {
+        let store = Self::new(data_dir, config.keep());
+        match &config.search {
+            Some(search) => Ok(store.with_search(open_history_search(search, data_dir)?)),
+            None => Ok(store),
+        }
+    }
+
+    /// 写入消息时同时写入全文搜索索引
+    pub fn with_search(mut self, search: Arc<dyn HistorySearch>) -> Self {
+        self.search = Some(search);
+        self
+    }
+
+    fn chat_path(&self, app_id: &str, chat: &str) -> PathBuf {
+        self.dir
+            .join(sanitize_segment(app_id))
+            .join(format!("{}.jsonl", sanitize_segment(chat)))
+    }
+
+    /// 会话文件的锁，首次使用时创建
+    fn chat_lock(&self, path: &Path) -> ChatLines {
+        let mut chats = self.chats.lock().unwrap_or_else(|e| e.into_inner());
+        chats.entry(path.to_path_buf()).or_default().clone()
+    }
+
+    /// 会话文件当前的行数：优先读取 `.lines` 记录，缺失或损坏时（如升级前的数据）逐行统计一次
+    async fn load_count(path: &Path) -> Result<usize, String> {
+        if let Ok(saved) = fs::read_to_string(path.with_extension("lines")).await {
+            if let Ok(count) = saved.trim().parse() {
+                return Ok(count);
+            }
+        }
+        Ok(jsonl::read_lines::<HistoryMessage>(path).await?.len())
+    }
+
+    async fn save_count(path: &Path, count: usize) -> Result<(), String> {
+        let lines = path.with_extension("lines");
+        fs::write(&lines, count.to_string())
+            .await
+            .map_err(|e| format!("写入失败 {}: {}", lines.display(), e))
+    }
+
+    pub async fn append(&self, message: &HistoryMessage) -> Result<(), String> {
+        let path = self.chat_path(&message.app_id, &message.chat);
+        let lock = self.chat_lock(&path);
+        let mut lines = lock.lock().await;
+        let count = match *lines {
+            Some(count) => count,
+            None => Self::load_count(&path).await?,
+        };
+        jsonl::append_line(&path, message)
+            .await
+            .map_err(|e| format!("写入会话消息记录失败: {}", e))?;
+        let mut count = count + 1;
+        let mut removed = Vec::new();
+        if count > self.keep * 2 {
+            let mut messages: Vec<HistoryMessage> = jsonl::read_lines(&path).await?;
+            let start = messages.len().saturating_sub(self.keep);
+            jsonl::write_lines(&path, &messages[start..])
+                .await
+                .map_err(|e| format!("截断会话消息记录失败: {}", e))?;
+            count = messages.len() - start;
+            messages.truncate(start);
+            removed = messages;
+        }
+        *lines = Some(count);
+        Self::save_count(&path, count).await?;
+        drop(lines);
+        if let Some(search) = &self.search {
+            search.index(std::slice::from_ref(message)).await?;
+            search.remove(&removed).await?;
+        }
+        Ok(())
+    }
+
+    /// 批量导入历史消息：与已有记录合并去重（有 `new_msg_id` 时按它，否则按时间、发送者与内容），
+    /// 按时间排序后保留最近的 `keep` 条
+    pub async fn import(&self, messages: Vec<HistoryMessage>) -> Result<HistoryImport, String> {
+        let mut by_chat: HashMap<PathBuf, Vec<HistoryMessage>> = HashMap::new();
+        for message in messages {
+            let path = self.chat_path(&message.app_id, &message.chat);
+            by_chat.entry(path).or_default().push(message);
+        }
+        let mut summary = HistoryImport::default();
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        for (path, imported) in by_chat {
+            let lock = self.chat_lock(&path);
+            let mut lines = lock.lock().await;
+            let mut merged: Vec<HistoryMessage> = jsonl::read_lines(&path).await?;
+            let mut seen: HashSet<String> = merged.iter().map(dedup_key).collect();
+            let mut new_keys = HashSet::new();
+            for message in imported {
+                let key = dedup_key(&message);
+                if seen.insert(key.clone()) {
+                    new_keys.insert(key);
+                    merged.push(message);
+                    summary.added += 1;
+                } else {
+                    summary.duplicates += 1;
+                }
+            }
+            merged.sort_by_key(|m| m.at);
+            let start = merged.len().saturating_sub(self.keep);
+            summary.dropped += start;
+            jsonl::write_lines(&path, &merged[start..])
+                .await
+                .map_err(|e| format!("写入会话消息记录失败: {}", e))?;
+            *lines = Some(merged.len() - start);
+            Self::save_count(&path, merged.len() - start).await?;
+            summary.chats += 1;
+            if self.search.is_some() {
+                // 只为保留下来的新消息写索引，截断掉的原有消息从索引中删除
+                let kept = merged.split_off(start);
+                added.extend(
+                    kept.into_iter()
+                        .filter(|m| new_keys.contains(&dedup_key(m))),
+                );
+                removed.extend(
+                    merged
+                        .into_iter()
+                        .filter(|m| !new_keys.contains(&dedup_key(m))),
+                );
+            }
+        }
+        if let Some(search) = &self.search {
+            search.index(&added).await?;
+            search.remove(&removed).await?;
+        }
+        Ok(summary)
+    }
+
+    /// 清空全文搜索索引并按现有的会话消息记录重建，返回读取的消息条数；未配置索引时不做任何事
+    pub async fn reindex(&self) -> Result<usize, String> {
+        let Some(search) = &self.search else {
+            return Ok(0);
+        };
+        search.clear().await?;
+        let mut indexed = 0;
+        for app_id in self.app_ids().await? {
+            let dir = self.dir.join(&app_id);
+            let mut entries = fs::read_dir(&dir)
+                .await
+                .map_err(|e| format!("读取目录失败 {}: {}", dir.display(), e))?;
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "jsonl") {
+                    let messages: Vec<HistoryMessage> = jsonl::read_lines(&path).await?;
+                    search.index(&messages).await?;
+                    indexed += messages.len();
+                }
+            }
+        }
+        Ok(indexed)
+    }
+
+    /// 会话最近的 `limit` 条消息，按时间先后排列；未指定机器人时合并各机器人的记录
+    pub async fn recent(
+        &self,
+        app_id: Option<&str>,
+        chat: &str,
+        limit: usize,
+    ) -> Result<Vec<HistoryMessage>, String> {
+        let app_ids = match app_id {
+            Some(app_id) => vec![app_id.to_string()],
+            None => self.app_ids().await?,
+        };
+        let mut messages = Vec::new();
+        for app_id in app_ids {
+            let path = self.chat_path(&app_id, chat);
+            let loaded: Vec<HistoryMessage> = jsonl::read_lines(&path)
+                .await
+                .map_err(|e| format!("读取会话消息记录失败: {}", e))?;
+            messages.extend(loaded);
+        }
+        messages.sort_by_key(|m| m.at);
+        let start = messages.len().saturating_sub(limit);
+        Ok(messages.split_off(start))
+    }
+
+    /// `at` 前后的消息：此前（含同一时刻）最多 `before` 条、之后最多 `after` 条，按时间先后排列
+    pub async fn around(
+        &self,
+        app_id: &str,
+        chat: &str,
+        at: DateTime<Utc>,
+        before: usize,
+        after: usize,
+    ) -> Result<Vec<HistoryMessage>, String> {
+        let mut messages: Vec<HistoryMessage> = jsonl::read_lines(&self.chat_path(app_id, chat))
+            .await
+            .map_err(|e| format!("读取会话消息记录失败: {}", e))?;
+        messages.sort_by_key(|m| m.at);
+        let pivot = messages.partition_point(|m| m.at <= at);
+        let end = (pivot + after).min(messages.len());
+        messages.truncate(end);
+        Ok(messages.split_off(pivot.saturating_sub(before)))
+    }
+
+    async fn app_ids(&self) -> Result<Vec<String>, String> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("读取目录失败 {}: {}", self.dir.display(), e)),
+        };
+        let mut app_ids = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.path().is_dir() {
+                app_ids.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(app_ids)
+    }
+}
+
+/// 会话上下文签名链接：`/history/{chat}?app_id=&at=&expires=&sig=`，`at` 为秒级时间戳，
+/// 签名为 `{app_id}:{chat}:{at}:{expires}` 的 HMAC-SHA256，密钥与媒体签名链接相同
+pub fn signed_context_path(key: &[u8], app_id: &str, chat: &str, at: i64, expires: i64) -> String {
+    format!(
+        "/history/{}?app_id={}&at={}&expires={}&sig={}",
+        chat,
+        app_id,
+        at,
+        expires,
+        hex::encode(
+            context_mac(key, app_id, chat, at, expires)
+                .finalize()
+                .into_bytes()
+        )
+    )
+}
+
+/// 校验会话上下文链接的签名（不检查是否过期）
+pub fn verify_context_sig(
+    key: &[u8],
+    app_id: &str,
+    chat: &str,
+    at: i64,
+    expires: i64,
+    sig: &str,
+) -> bool {
+    let Ok(sig) = hex::decode(sig) else {
+        return false;
+    };
+    context_mac(key, app_id, chat, at, expires)
+        .verify_slice(&sig)
+        .is_ok()
+}
+
+fn context_mac(key: &[u8], app_id: &str, chat: &str, at: i64, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC 接受任意长度的密钥");
+    mac.update(format!("{}:{}:{}:{}", app_id, chat, at, expires).as_bytes());
+    mac
+}
+
+/// 批量导入的结果
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HistoryImport {
+    /// 写入的会话数
+    pub chats: usize,
+    pub added: usize,
+    /// 与已有记录重复而跳过的条数
+    pub duplicates: usize,
+    /// 超出保留上限而丢弃的最早的条数
+    pub dropped: usize,
+}
+
+fn dedup_key(message: &HistoryMessage) -> String {
+    match message.new_msg_id {
+        Some(id) => id.to_string(),
+        None => format!(
+            "{}|{}|{}",
+            message.at.timestamp(),
+            message.sender.as_deref().unwrap_or_default(),
+            message.text.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn message(app_id: &str, seconds: i64, text: &str) -> HistoryMessage {
+        HistoryMessage {
+            at: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+            app_id: app_id.to_string(),
+            chat: "123@chatroom".to_string(),
+            sender: Some("wxid_a".to_string()),
+            direction: HistoryDirection::In,
+            kind: "text".to_string(),
+            text: Some(text.to_string()),
+            new_msg_id: Some(seconds),
+            media_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_store_around_and_context_link() {
+        let temp = TempDir::new().unwrap();
+        let store = HistoryStore::new(temp.path(), 100);
+        for i in 0..10 {
+            store
+                .append(&message("app_a", i * 10, &format!("m{}", i)))
+                .await
+                .unwrap();
+        }
+        let at = DateTime::from_timestamp(1_700_000_045, 0).unwrap();
+        let around = store
+            .around("app_a", "123@chatroom", at, 2, 3)
+            .await
+            .unwrap();
+        let texts: Vec<_> = around.iter().filter_map(|m| m.text.as_deref()).collect();
+        assert_eq!(texts, ["m3", "m4", "m5", "m6", "m7"]);
+        assert!(store
+            .around("app_b", "123@chatroom", at, 2, 3)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let path = signed_context_path(b"key", "app_a", "123@chatroom", 45, 100);
+        let sig = path.rsplit_once("sig=").unwrap().1;
+        assert!(path.starts_with("/history/123@chatroom?app_id=app_a&at=45&expires=100&sig="));
+        assert!(verify_context_sig(
+            b"key",
+            "app_a",
+            "123@chatroom",
+            45,
+            100,
+            sig
+        ));
+        assert!(!verify_context_sig(
+            b"key",
+            "app_a",
+            "456@chatroom",
+            45,
+            100,
+            sig
+        ));
+        assert!(!verify_context_sig(
+            b"other",
+            "app_a",
+            "123@chatroom",
+            45,
+            100,
+            sig
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_history_store_truncates_and_merges_bots() {
+        let temp = TempDir::new().unwrap();
+        let store = HistoryStore::new(temp.path(), 2);
+        for i in 0..5 {
+            store
+                .append(&message("app_a", i * 2, &format!("a{}", i)))
+                .await
+                .unwrap();
+        }
+        // 第 5 条超过 2 × 2 条，截断为最近 2 条
+        let texts = |messages: Vec<HistoryMessage>| {
+            messages
+                .into_iter()
+                .map(|m| m.text.unwrap())
+                .collect::<Vec<_>>()
+        };
+        let kept = store.recent(Some("app_a"), "123@chatroom", 10).await;
+        assert_eq!(texts(kept.unwrap()), ["a3", "a4"]);
+
+        // 行数记录在 `.lines` 中，新实例接着计数而不是重新读取文件
+        let lines = temp.path().join("history/app_a/123@chatroom.lines");
+        assert_eq!(std::fs::read_to_string(&lines).unwrap(), "2");
+        let restarted = HistoryStore::new(temp.path(), 2);
+        restarted.append(&message("app_a", 5, "a5")).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&lines).unwrap(), "3");
+
+        store.append(&message("app_b", 7, "b")).await.unwrap();
+        let merged = store.recent(None, "123@chatroom", 2).await.unwrap();
+        assert_eq!(texts(merged), ["b", "a4"]);
+        assert!(store.recent(None, "other", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_store_concurrent_appends() {
+        let temp = TempDir::new().unwrap();
+        let store = HistoryStore::new(temp.path(), 100);
+        let appends: Vec<_> = (0..20)
+            .map(|i| {
+                let store = store.clone();
+                let chat = if i % 2 == 0 {
+                    "123@chatroom"
+                } else {
+                    "456@chatroom"
+                };
+                tokio::spawn(async move {
+                    let mut m = message("app_a", i, &format!("m{}", i));
+                    m.chat = chat.to_string();
+                    store.append(&m).await
+                })
+            })
+            .collect();
+        for append in appends {
+            append.await.unwrap().unwrap();
+        }
+        for chat in ["123@chatroom", "456@chatroom"] {
+            let lines = temp.path().join(format!("history/app_a/{}.lines", chat));
+            assert_eq!(std::fs::read_to_string(lines).unwrap(), "10");
+            let recent = store.recent(Some("app_a"), chat, 100).await.unwrap();
+            assert_eq!(recent.len(), 10);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_import_merges_and_dedups() {
+        let temp = TempDir::new().unwrap();
+        let store = HistoryStore::new(temp.path(), 3);
+        store.append(&message("app_a", 10, "live")).await.unwrap();
+
+        let mut no_id = message("app_a", 1, "old");
+        no_id.new_msg_id = None;
+        let imported = vec![
+            message("app_a", 10, "live"),
+            no_id.clone(),
+            no_id,
+            message("app_a", 2, "a"),
+            message("app_a", 3, "b"),
+        ];
+        let summary = store.import(imported).await.unwrap();
+        assert_eq!(
+            summary,
+            HistoryImport {
+                chats: 1,
+                added: 3,
+                duplicates: 2,
+                dropped: 1,
+            }
+        );
+        let texts: Vec<_> = store
+            .recent(Some("app_a"), "123@chatroom", 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.text.unwrap())
+            .collect();
+        assert_eq!(texts, ["a", "b", "live"]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_history_store_indexes_for_search() {
+        use crate::storage::{SearchQuery, SqliteHistorySearch};
+
+        let temp = TempDir::new().unwrap();
+        let search = Arc::new(SqliteHistorySearch::open(temp.path().join("search.db")).unwrap());
+        let store = HistoryStore::new(temp.path(), 10).with_search(search.clone());
+        store
+            .append(&message("app_a", 1, "上线通知"))
+            .await
+            .unwrap();
+        store
+            .import(vec![
+                message("app_a", 1, "上线通知"),
+                message("app_a", 2, "上线回滚"),
+            ])
+            .await
+            .unwrap();
+        let query = SearchQuery {
+            q: "上线".to_string(),
+            limit: 10,
+            ..Default::default()
+        };
+        // 导入时重复的消息不重复写入索引
+        assert_eq!(search.search(&query).await.unwrap().len(), 2);
+
+        search.clear().await.unwrap();
+        assert_eq!(store.reindex().await.unwrap(), 2);
+        let hits = search.search(&query).await.unwrap();
+        assert_eq!(hits[0].message.text.as_deref(), Some("上线回滚"));
+
+        // 截断掉的消息同时从索引中删除，导入时被截断的新消息不写入索引
+        search.clear().await.unwrap();
+        let store = HistoryStore::new(temp.path().join("truncated"), 1).with_search(search.clone());
+        for i in 0..3 {
+            store
+                .append(&message("app_a", 10 + i, &format!("上线{}", i)))
+                .await
+                .unwrap();
+        }
+        let texts = |hits: Vec<crate::storage::SearchHit>| {
+            hits.into_iter()
+                .map(|h| h.message.text.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(search.search(&query).await.unwrap()), ["上线2"]);
+        store
+            .import(vec![
+                message("app_a", 5, "上线旧"),
+                message("app_a", 20, "上线新"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(texts(search.search(&query).await.unwrap()), ["上线新"]);
+    }
+}
@@ -0,0 +1,641 @@
+//! 会话消息全文搜索
+//!
+//! 开启 `[server.history.search]` 后，写入会话消息记录的每条文本消息同时写入索引：SQLite 使用 FTS5，
+//! PostgreSQL 使用 `tsvector`。两种分词器都不切分中文，写入与查询前在 CJK 字符之间插入分隔符，
+//! 按字索引、按短语匹配，因此「你好」只匹配相邻的两个字。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::HistoryMessage;
+use crate::config::{HistorySearchBackend, HistorySearchConfig};
+
+/// 摘要中命中词的高亮标记
+pub const HIGHLIGHT_START: &str = "<mark>";
+pub const HIGHLIGHT_END: &str = "</mark>";
+
+/// 写入索引时插入在 CJK 字符之间的分隔符（U+2063，不可见），生成摘要后去掉
+const SEGMENT_SEPARATOR: char = '\u{2063}';
+
+/// 索引生成摘要时使用的命中标记（私用区字符），转义消息文本后再替换为 `<mark>`
+const MATCH_START: char = '\u{e000}';
+const MATCH_END: char = '\u{e001}';
+
+/// 搜索条件，`q` 按空白拆分为多个词，全部命中才返回
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub q: String,
+    pub app_id: Option<String>,
+    pub chat: Option<String>,
+    pub sender: Option<String>,
+    pub kind: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: usize,
+}
+
+/// 一条命中的消息，按时间倒序返回
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub message: HistoryMessage,
+    /// 命中位置附近的摘要，消息文本已做 HTML 转义，命中词以 `<mark>` 包裹
+    pub snippet: String,
+}
+
+/// 全文搜索索引后端
+#[async_trait]
+pub trait HistorySearch: Send + Sync + std::fmt::Debug {
+    /// 写入索引，没有文本的消息跳过
+    async fn index(&self, messages: &[HistoryMessage]) -> Result<(), String>;
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, String>;
+
+    /// 删除这些消息的索引，会话消息记录截断时调用
+    async fn remove(&self, messages: &[HistoryMessage]) -> Result<(), String>;
+
+    /// 清空索引，重建前调用
+    async fn clear(&self) -> Result<(), String>;
+}
+
+/// 打开配置的搜索索引；同一位置的索引在进程内共享，调度器与 API 使用同一个连接池
+pub fn open_history_search(
+    config: &HistorySearchConfig,
+    data_dir: &str,
+) -> Result<Arc<dyn HistorySearch>, String> {
+    static OPENED: OnceLock<Mutex<HashMap<String, Arc<dyn HistorySearch>>>> = OnceLock::new();
+    let location = match config.backend {
+        HistorySearchBackend::Sqlite => config.path.clone().unwrap_or_else(|| {
+            std::path::Path::new(data_dir)
+                .join("history/search.db")
+                .to_string_lossy()
+                .into_owned()
+        }),
+        HistorySearchBackend::Postgres => config
+            .database_url
+            .clone()
+            .filter(|url| !url.is_empty())
+            .ok_or("postgres 搜索索引需要填写 database_url")?,
+    };
+    let key = format!("{:?}:{}", config.backend, location);
+    let mut opened = OPENED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(search) = opened.get(&key) {
+        return Ok(search.clone());
+    }
+    let search = open_backend(config.backend, &location)?;
+    opened.insert(key, search.clone());
+    Ok(search)
+}
+
+#[cfg_attr(
+    not(all(feature = "sqlite", feature = "postgres")),
+    allow(unused_variables)
+)]
+fn open_backend(
+    backend: HistorySearchBackend,
+    location: &str,
+) -> Result<Arc<dyn HistorySearch>, String> {
+    match backend {
+        #[cfg(feature = "sqlite")]
+        HistorySearchBackend::Sqlite => Ok(Arc::new(SqliteHistorySearch::open(location)?)),
+        #[cfg(not(feature = "sqlite"))]
+        HistorySearchBackend::Sqlite => {
+            Err("SQLite 搜索索引未启用，请使用 --features sqlite 重新编译".to_string())
+        }
+        #[cfg(feature = "postgres")]
+        HistorySearchBackend::Postgres => Ok(Arc::new(PostgresHistorySearch::connect(location)?)),
+        #[cfg(not(feature = "postgres"))]
+        HistorySearchBackend::Postgres => {
+            Err("Postgres 搜索索引未启用，请使用 --features postgres 重新编译".to_string())
+        }
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{ac00}'..='\u{d7af}'
+        | '\u{20000}'..='\u{2fa1f}')
+}
+
+/// 在 CJK 字符与相邻字符之间插入分隔符，使分词器按字切分
+fn segment(text: &str, separator: char) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut prev: Option<char> = None;
+    for c in text.chars() {
+        if let Some(p) = prev {
+            if (is_cjk(c) || is_cjk(p)) && !p.is_whitespace() && !c.is_whitespace() {
+                out.push(separator);
+            }
+        }
+        out.push(c);
+        prev = Some(c);
+    }
+    out
+}
+
+/// 写入索引的文本
+fn index_body(text: &str) -> String {
+    segment(text, SEGMENT_SEPARATOR)
+}
+
+/// 去掉摘要中的分隔符，转义消息文本后把命中标记换成 `<mark>`
+fn clean_snippet(snippet: &str) -> String {
+    snippet
+        .replace(SEGMENT_SEPARATOR, "")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace(MATCH_START, HIGHLIGHT_START)
+        .replace(MATCH_END, HIGHLIGHT_END)
+}
+
+/// 待删除的索引行：有文本（写入过索引）的消息按会话分组，值为序列化后的消息
+fn removed_rows(messages: &[HistoryMessage]) -> Result<HashMap<(&str, &str), Vec<String>>, String> {
+    let mut rows: HashMap<(&str, &str), Vec<String>> = HashMap::new();
+    for message in messages {
+        if message
+            .text
+            .as_deref()
+            .is_some_and(|t| !t.trim().is_empty())
+        {
+            let json = serde_json::to_string(message).map_err(|e| e.to_string())?;
+            rows.entry((message.app_id.as_str(), message.chat.as_str()))
+                .or_default()
+                .push(json);
+        }
+    }
+    Ok(rows)
+}
+
+/// 查询词：按空白拆分，每个词按字切分为短语；不含文字或数字的词忽略
+fn query_terms(q: &str) -> Result<Vec<String>, String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .filter(|term| term.chars().any(char::is_alphanumeric))
+        .map(|term| segment(term, ' '))
+        .collect();
+    if terms.is_empty() {
+        return Err("搜索词不能为空".to_string());
+    }
+    Ok(terms)
+}
+
+fn parse_message(json: &str) -> Result<HistoryMessage, String> {
+    serde_json::from_str(json).map_err(|e| format!("解析索引中的消息失败: {}", e))
+}
+
+#[cfg(feature = "sqlite")]
+const SQLITE_SCHEMA: &str = r#"
+CREATE VIRTUAL TABLE IF NOT EXISTS history_fts USING fts5(
+    body,
+    app_id UNINDEXED,
+    chat UNINDEXED,
+    sender UNINDEXED,
+    kind UNINDEXED,
+    at UNINDEXED,
+    message UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+"#;
+
+/// SQLite FTS5 索引；首次读写时建表
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct SqliteHistorySearch {
+    pool: sqlx::SqlitePool,
+    schema: tokio::sync::OnceCell<()>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteHistorySearch {
+    /// 打开数据库文件，不存在时创建（连接延迟到首次使用）
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, String> {
+        if let Some(parent) = path.as_ref().parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("创建搜索索引目录失败: {}", e))?;
+            }
+        }
+        let options = sqlx::sqlite::SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        Ok(Self {
+            pool: sqlx::SqlitePool::connect_lazy_with(options),
+            schema: tokio::sync::OnceCell::new(),
+        })
+    }
+
+    async fn pool(&self) -> Result<&sqlx::SqlitePool, String> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::raw_sql(SQLITE_SCHEMA)
+                    .execute(&self.pool)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| format!("初始化搜索索引失败: {}", e))
+            })
+            .await?;
+        Ok(&self.pool)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl HistorySearch for SqliteHistorySearch {
+    async fn index(&self, messages: &[HistoryMessage]) -> Result<(), String> {
+        let mut tx = self
+            .pool()
+            .await?
+            .begin()
+            .await
+            .map_err(|e| format!("写入搜索索引失败: {}", e))?;
+        for message in messages {
+            let Some(text) = message.text.as_deref().filter(|t| !t.trim().is_empty()) else {
+                continue;
+            };
+            let json = serde_json::to_string(message).map_err(|e| e.to_string())?;
+            sqlx::query(
+                "INSERT INTO history_fts (body, app_id, chat, sender, kind, at, message) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(index_body(text))
+            .bind(&message.app_id)
+            .bind(&message.chat)
+            .bind(message.sender.as_deref())
+            .bind(&message.kind)
+            .bind(message.at.timestamp())
+            .bind(json)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("写入搜索索引失败: {}", e))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("写入搜索索引失败: {}", e))
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, String> {
+        use sqlx::Row;
+        let expr = query_terms(&query.q)?
+            .iter()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let rows = sqlx::query(
+            "SELECT message, snippet(history_fts, 0, ?, ?, '…', 24) AS snippet FROM history_fts \
+             WHERE history_fts MATCH ? \
+             AND (? IS NULL OR app_id = ?) AND (? IS NULL OR chat = ?) \
+             AND (? IS NULL OR sender = ?) AND (? IS NULL OR kind = ?) \
+             AND (? IS NULL OR at >= ?) AND (? IS NULL OR at <= ?) \
+             ORDER BY at DESC LIMIT ?",
+        )
+        .bind(MATCH_START.to_string())
+        .bind(MATCH_END.to_string())
+        .bind(expr)
+        .bind(query.app_id.as_deref())
+        .bind(query.app_id.as_deref())
+        .bind(query.chat.as_deref())
+        .bind(query.chat.as_deref())
+        .bind(query.sender.as_deref())
+        .bind(query.sender.as_deref())
+        .bind(query.kind.as_deref())
+        .bind(query.kind.as_deref())
+        .bind(query.since.map(|at| at.timestamp()))
+        .bind(query.since.map(|at| at.timestamp()))
+        .bind(query.until.map(|at| at.timestamp()))
+        .bind(query.until.map(|at| at.timestamp()))
+        .bind(query.limit as i64)
+        .fetch_all(self.pool().await?)
+        .await
+        .map_err(|e| format!("搜索失败: {}", e))?;
+        rows.iter()
+            .map(|row| {
+                Ok(SearchHit {
+                    message: parse_message(row.get("message"))?,
+                    snippet: clean_snippet(row.get("snippet")),
+                })
+            })
+            .collect()
+    }
+
+    async fn remove(&self, messages: &[HistoryMessage]) -> Result<(), String> {
+        let rows = removed_rows(messages)?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut tx = self
+            .pool()
+            .await?
+            .begin()
+            .await
+            .map_err(|e| format!("删除搜索索引失败: {}", e))?;
+        // 每个会话一条语句，按序列化后的消息整体匹配
+        for ((app_id, chat), json) in rows {
+            sqlx::query(
+                "DELETE FROM history_fts WHERE app_id = ? AND chat = ? \
+                 AND message IN (SELECT value FROM json_each(?))",
+            )
+            .bind(app_id)
+            .bind(chat)
+            .bind(serde_json::to_string(&json).map_err(|e| e.to_string())?)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("删除搜索索引失败: {}", e))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("删除搜索索引失败: {}", e))
+    }
+
+    async fn clear(&self) -> Result<(), String> {
+        sqlx::query("DELETE FROM history_fts")
+            .execute(self.pool().await?)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("清空搜索索引失败: {}", e))
+    }
+}
+
+#[cfg(feature = "postgres")]
+const POSTGRES_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS history_search (
+    id BIGSERIAL PRIMARY KEY,
+    app_id TEXT NOT NULL,
+    chat TEXT NOT NULL,
+    sender TEXT,
+    kind TEXT NOT NULL,
+    at TIMESTAMPTZ NOT NULL,
+    body TEXT NOT NULL,
+    message TEXT NOT NULL,
+    tsv TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', body)) STORED
+);
+CREATE INDEX IF NOT EXISTS idx_history_search_tsv ON history_search USING GIN (tsv);
+CREATE INDEX IF NOT EXISTS idx_history_search_chat ON history_search (app_id, chat, at);
+"#;
+
+/// PostgreSQL `tsvector` 索引，使用 `simple` 配置；数据库需为 UTF-8 编码与区域设置，
+/// 否则中文字符不会被分词器识别。首次读写时建表
+#[cfg(feature = "postgres")]
+#[derive(Debug)]
+pub struct PostgresHistorySearch {
+    pool: sqlx::PgPool,
+    schema: tokio::sync::OnceCell<()>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresHistorySearch {
+    /// 创建连接池（连接延迟到首次使用）
+    pub fn connect(database_url: &str) -> Result<Self, String> {
+        Ok(Self {
+            pool: sqlx::PgPool::connect_lazy(database_url)
+                .map_err(|e| format!("连接搜索索引数据库失败: {}", e))?,
+            schema: tokio::sync::OnceCell::new(),
+        })
+    }
+
+    async fn pool(&self) -> Result<&sqlx::PgPool, String> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::raw_sql(POSTGRES_SCHEMA)
+                    .execute(&self.pool)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| format!("初始化搜索索引失败: {}", e))
+            })
+            .await?;
+        Ok(&self.pool)
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl HistorySearch for PostgresHistorySearch {
+    async fn index(&self, messages: &[HistoryMessage]) -> Result<(), String> {
+        let mut tx = self
+            .pool()
+            .await?
+            .begin()
+            .await
+            .map_err(|e| format!("写入搜索索引失败: {}", e))?;
+        for message in messages {
+            let Some(text) = message.text.as_deref().filter(|t| !t.trim().is_empty()) else {
+                continue;
+            };
+            let json = serde_json::to_string(message).map_err(|e| e.to_string())?;
+            sqlx::query(
+                "INSERT INTO history_search (app_id, chat, sender, kind, at, body, message) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(&message.app_id)
+            .bind(&message.chat)
+            .bind(message.sender.as_deref())
+            .bind(&message.kind)
+            .bind(message.at)
+            .bind(index_body(text))
+            .bind(json)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("写入搜索索引失败: {}", e))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("写入搜索索引失败: {}", e))
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<SearchHit>, String> {
+        use sqlx::Row;
+        let terms = query_terms(&query.q)?;
+        // $1..$6 为过滤条件，$7 为条数，之后每个词一个参数
+        let tsquery = (0..terms.len())
+            .map(|i| format!("phraseto_tsquery('simple', ${})", i + 8))
+            .collect::<Vec<_>>()
+            .join(" && ");
+        let sql = format!(
+            "SELECT message, ts_headline('simple', body, q.query, \
+             'StartSel={start}, StopSel={end}, MaxWords=24, MinWords=8') AS snippet \
+             FROM history_search, (SELECT {tsquery} AS query) AS q \
+             WHERE tsv @@ q.query \
+             AND ($1::text IS NULL OR app_id = $1) AND ($2::text IS NULL OR chat = $2) \
+             AND ($3::text IS NULL OR sender = $3) AND ($4::text IS NULL OR kind = $4) \
+             AND ($5::timestamptz IS NULL OR at >= $5) AND ($6::timestamptz IS NULL OR at <= $6) \
+             ORDER BY at DESC LIMIT $7",
+            start = MATCH_START,
+            end = MATCH_END,
+        );
+        let mut statement = sqlx::query(&sql)
+            .bind(query.app_id.as_deref())
+            .bind(query.chat.as_deref())
+            .bind(query.sender.as_deref())
+            .bind(query.kind.as_deref())
+            .bind(query.since)
+            .bind(query.until)
+            .bind(query.limit as i64);
+        for term in &terms {
+            statement = statement.bind(term);
+        }
+        let rows = statement
+            .fetch_all(self.pool().await?)
+            .await
+            .map_err(|e| format!("搜索失败: {}", e))?;
+        rows.iter()
+            .map(|row| {
+                Ok(SearchHit {
+                    message: parse_message(row.get("message"))?,
+                    snippet: clean_snippet(row.get("snippet")),
+                })
+            })
+            .collect()
+    }
+
+    async fn remove(&self, messages: &[HistoryMessage]) -> Result<(), String> {
+        let rows = removed_rows(messages)?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut tx = self
+            .pool()
+            .await?
+            .begin()
+            .await
+            .map_err(|e| format!("删除搜索索引失败: {}", e))?;
+        for ((app_id, chat), json) in rows {
+            sqlx::query(
+                "DELETE FROM history_search WHERE app_id = $1 AND chat = $2 AND message = ANY($3)",
+            )
+            .bind(app_id)
+            .bind(chat)
+            .bind(json)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("删除搜索索引失败: {}", e))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| format!("删除搜索索引失败: {}", e))
+    }
+
+    async fn clear(&self) -> Result<(), String> {
+        sqlx::query("TRUNCATE history_search")
+            .execute(self.pool().await?)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("清空搜索索引失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_and_query_terms() {
+        assert_eq!(segment("明天 meeting在3楼", '|'), "明|天 meeting|在|3|楼");
+        assert_eq!(clean_snippet(&index_body("你好 world")), "你好 world");
+        assert_eq!(
+            clean_snippet("<b>\u{e000}a&b\u{e001}</b>"),
+            "&lt;b&gt;<mark>a&amp;b</mark>&lt;/b&gt;"
+        );
+        assert_eq!(query_terms("  发布 v2.0 ！ ").unwrap(), ["发 布", "v2.0"]);
+        assert!(query_terms(" ？ ").is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_history_search() {
+        use super::super::HistoryDirection;
+
+        let dir = tempfile::tempdir().unwrap();
+        let search = SqliteHistorySearch::open(dir.path().join("search.db")).unwrap();
+        let message = |seconds: i64, chat: &str, sender: &str, text: Option<&str>| HistoryMessage {
+            at: DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap(),
+            app_id: "wx_app".to_string(),
+            chat: chat.to_string(),
+            sender: Some(sender.to_string()),
+            direction: HistoryDirection::In,
+            kind: if text.is_some() { "text" } else { "image" }.to_string(),
+            text: text.map(str::to_string),
+            new_msg_id: Some(seconds),
+            media_url: None,
+        };
+        search
+            .index(&[
+                message(1, "1@chatroom", "wxid_a", Some("明天下午发布新版本")),
+                message(2, "1@chatroom", "wxid_b", Some("发布会推迟到周五")),
+                message(3, "2@chatroom", "wxid_a", Some("版本发布 checklist 已更新")),
+                message(4, "1@chatroom", "wxid_a", None),
+                message(5, "1@chatroom", "wxid_c", Some("布发")),
+                message(6, "2@chatroom", "wxid_c", Some("<script>发布</script>")),
+            ])
+            .await
+            .unwrap();
+
+        let query = |q: &str| SearchQuery {
+            q: q.to_string(),
+            limit: 10,
+            ..Default::default()
+        };
+        let hits = search.search(&query("发布")).await.unwrap();
+        let ids: Vec<_> = hits.iter().map(|h| h.message.new_msg_id.unwrap()).collect();
+        assert_eq!(ids, [6, 3, 2, 1]);
+        assert_eq!(
+            hits[0].snippet,
+            "&lt;script&gt;<mark>发布</mark>&lt;/script&gt;"
+        );
+        assert_eq!(hits[2].snippet, "<mark>发布</mark>会推迟到周五");
+
+        // 删除指定消息的索引，同一会话的其他消息保留
+        search
+            .remove(&[
+                message(6, "2@chatroom", "wxid_c", Some("<script>发布</script>")),
+                message(4, "1@chatroom", "wxid_a", None),
+            ])
+            .await
+            .unwrap();
+        let hits = search.search(&query("发布")).await.unwrap();
+        let ids: Vec<_> = hits.iter().map(|h| h.message.new_msg_id.unwrap()).collect();
+        assert_eq!(ids, [3, 2, 1]);
+
+        let hits = search
+            .search(&SearchQuery {
+                chat: Some("1@chatroom".to_string()),
+                sender: Some("wxid_a".to_string()),
+                ..query("发布 版本")
+            })
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message.text.as_deref(), Some("明天下午发布新版本"));
+
+        let since = DateTime::from_timestamp(1_700_000_002, 0);
+        let hits = search
+            .search(&SearchQuery {
+                since,
+                until: since,
+                ..query("发布")
+            })
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(search
+            .search(&query("CHECKLIST"))
+            .await
+            .unwrap()
+            .iter()
+            .all(|h| h.snippet.contains("<mark>checklist</mark>")));
+
+        search.clear().await.unwrap();
+        assert!(search.search(&query("发布")).await.unwrap().is_empty());
+    }
+}
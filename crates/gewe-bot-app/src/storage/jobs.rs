@@ -0,0 +1,287 @@
+//! 定时任务表
+//!
+//! 单个 JSON 文件：`{data_dir}/jobs/jobs.json`，记录每个定时任务的下次执行时间与最近结果；
+//! 执行成功后才推进下次执行时间（至少执行一次），重启后据此补跑错过的任务
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::config::CatchUpPolicy;
+use crate::schedule::JobSchedule;
+
+/// 单次执行失败后最多重试的次数，超过后跳到下一周期
+pub const MAX_JOB_ATTEMPTS: u32 = 3;
+
+/// 最近一次执行结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Ok,
+    Failed,
+    /// 按 catch_up = skip 跳过了错过的执行
+    Skipped,
+}
+
+/// 由配置生成的任务定义
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobSpec {
+    pub id: String,
+    /// 任务类型：todo_summary、digest、countdown
+    pub kind: String,
+    /// 执行计划：每日时刻或 cron 表达式，可指定时区
+    pub schedule: JobSchedule,
+    pub catch_up: CatchUpPolicy,
+}
+
+/// 任务表中的一条记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub kind: String,
+    /// 执行计划，见 [`JobSchedule::key`]：本机时区的每日任务为 HH:MM
+    #[serde(alias = "daily_at")]
+    pub schedule: String,
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+    pub next_run_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_status: Option<JobStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// 当前周期已失败的次数
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl JobRecord {
+    /// 记录一次执行结果：成功或重试耗尽后推进到 next，否则保持到期以便下次重试
+    pub fn finish(&mut self, now: DateTime<Utc>, next: DateTime<Utc>, result: Result<(), String>) {
+        self.last_run_at = Some(now);
+        match result {
+            Ok(()) => {
+                self.last_status = Some(JobStatus::Ok);
+                self.last_error = None;
+                self.attempts = 0;
+                self.next_run_at = next;
+            }
+            Err(err) => {
+                self.last_status = Some(JobStatus::Failed);
+                self.last_error = Some(err);
+                self.attempts += 1;
+                if self.attempts >= MAX_JOB_ATTEMPTS {
+                    self.attempts = 0;
+                    self.next_run_at = next;
+                }
+            }
+        }
+    }
+
+    /// 跳过错过的执行
+    pub fn skip(&mut self, next: DateTime<Utc>) {
+        self.last_status = Some(JobStatus::Skipped);
+        self.attempts = 0;
+        self.next_run_at = next;
+    }
+}
+
+/// 全部任务，键为任务 id
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobTable {
+    #[serde(default)]
+    pub jobs: BTreeMap<String, JobRecord>,
+}
+
+impl JobTable {
+    /// 与配置对齐：新增任务、执行时间变更的任务从宽限期起重新排期，删除配置中已移除的任务。
+    /// 返回任务表是否有变化
+    pub fn reconcile<Tz: TimeZone>(
+        &mut self,
+        specs: &[JobSpec],
+        now: &DateTime<Tz>,
+        grace: Duration,
+    ) -> bool {
+        let before = self.jobs.len();
+        self.jobs
+            .retain(|id, _| specs.iter().any(|spec| &spec.id == id));
+        let mut changed = self.jobs.len() != before;
+        for spec in specs {
+            let schedule = spec.schedule.key();
+            match self.jobs.get_mut(&spec.id) {
+                Some(record) if record.schedule == schedule => {
+                    if record.catch_up != spec.catch_up {
+                        record.catch_up = spec.catch_up;
+                        changed = true;
+                    }
+                }
+                existing => {
+                    let after = (now.clone() - grace).with_timezone(&Utc);
+                    let next_run_at = spec.schedule.next_run(&after);
+                    let record = JobRecord {
+                        id: spec.id.clone(),
+                        kind: spec.kind.clone(),
+                        schedule,
+                        catch_up: spec.catch_up,
+                        next_run_at,
+                        last_run_at: existing.as_ref().and_then(|r| r.last_run_at),
+                        last_status: existing.as_ref().and_then(|r| r.last_status),
+                        last_error: None,
+                        attempts: 0,
+                    };
+                    self.jobs.insert(spec.id.clone(), record);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// 按下次执行时间排序的任务列表
+    pub fn sorted(&self) -> Vec<JobRecord> {
+        let mut jobs: Vec<_> = self.jobs.values().cloned().collect();
+        jobs.sort_by(|a, b| a.next_run_at.cmp(&b.next_run_at).then(a.id.cmp(&b.id)));
+        jobs
+    }
+}
+
+/// `after` 之后（不含）的下一个每日 `at` 时刻；本地时间不存在（夏令时跳变）时顺延一天
+pub fn next_daily_run<Tz: TimeZone>(after: &DateTime<Tz>, at: NaiveTime) -> DateTime<Utc> {
+    let tz = after.timezone();
+    let mut date = after.date_naive();
+    loop {
+        if let Some(candidate) = tz.from_local_datetime(&date.and_time(at)).earliest() {
+            if candidate > *after {
+                return candidate.with_timezone(&Utc);
+            }
+        }
+        date = date.succ_opt().unwrap_or(date);
+    }
+}
+
+/// 基于文件的任务表存储
+#[derive(Debug, Clone)]
+pub struct JobStore {
+    dir: PathBuf,
+}
+
+impl JobStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("jobs"),
+        }
+    }
+
+    fn table_path(&self) -> PathBuf {
+        self.dir.join("jobs.json")
+    }
+
+    /// 读取任务表，不存在时返回空
+    pub async fn load(&self) -> Result<JobTable, String> {
+        let path = self.table_path();
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析任务表失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(JobTable::default()),
+            Err(e) => Err(format!("读取任务表失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    pub async fn save(&self, table: &JobTable) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建任务表目录失败: {}", e))?;
+        let path = self.table_path();
+        let content =
+            serde_json::to_string_pretty(table).map_err(|e| format!("序列化任务表失败: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入任务表失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入任务表失败 {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::{Schedule, ScheduleTz};
+    use tempfile::TempDir;
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn spec(id: &str, daily_at: NaiveTime) -> JobSpec {
+        JobSpec {
+            id: id.to_string(),
+            kind: "countdown".to_string(),
+            schedule: JobSchedule::new(Schedule::Daily(daily_at), ScheduleTz::Utc),
+            catch_up: CatchUpPolicy::Once,
+        }
+    }
+
+    #[test]
+    fn test_next_daily_run() {
+        let now = utc("2026-10-15T08:00:00Z");
+        assert_eq!(next_daily_run(&now, at(9, 0)), utc("2026-10-15T09:00:00Z"));
+        assert_eq!(next_daily_run(&now, at(8, 0)), utc("2026-10-16T08:00:00Z"));
+    }
+
+    #[test]
+    fn test_job_table_reconcile_and_finish() {
+        let grace = Duration::minutes(10);
+        let now = utc("2026-10-15T09:05:00Z");
+        let mut table = JobTable::default();
+        assert!(table.reconcile(&[spec("a", at(9, 0)), spec("b", at(8, 0))], &now, grace));
+        // 宽限期内的今日时刻仍会执行，已过宽限期的排到明天
+        assert_eq!(table.jobs["a"].next_run_at, utc("2026-10-15T09:00:00Z"));
+        assert_eq!(table.jobs["b"].next_run_at, utc("2026-10-16T08:00:00Z"));
+        assert!(!table.reconcile(&[spec("a", at(9, 0)), spec("b", at(8, 0))], &now, grace));
+
+        // 失败保持到期，重试耗尽后推进
+        let next = utc("2026-10-16T09:00:00Z");
+        let job = table.jobs.get_mut("a").unwrap();
+        job.finish(now, next, Err("发送失败".to_string()));
+        assert_eq!(job.next_run_at, utc("2026-10-15T09:00:00Z"));
+        assert_eq!(job.last_status, Some(JobStatus::Failed));
+        job.finish(now, next, Err("发送失败".to_string()));
+        job.finish(now, next, Err("发送失败".to_string()));
+        assert_eq!(job.next_run_at, next);
+        assert_eq!(job.attempts, 0);
+        job.finish(now, next, Ok(()));
+        assert_eq!(job.last_status, Some(JobStatus::Ok));
+        assert_eq!(job.last_error, None);
+
+        // 删除与改期
+        assert!(table.reconcile(&[spec("a", at(10, 0))], &now, grace));
+        assert_eq!(table.jobs.len(), 1);
+        assert_eq!(table.jobs["a"].next_run_at, utc("2026-10-15T10:00:00Z"));
+        assert_eq!(table.jobs["a"].last_status, Some(JobStatus::Ok));
+    }
+
+    #[tokio::test]
+    async fn test_job_store_roundtrip() {
+        let temp = TempDir::new().unwrap();
+        let store = JobStore::new(temp.path());
+        assert!(store.load().await.unwrap().jobs.is_empty());
+        let mut table = JobTable::default();
+        table.reconcile(
+            &[spec("a", at(9, 0))],
+            &utc("2026-10-15T08:00:00Z"),
+            Duration::minutes(10),
+        );
+        store.save(&table).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), table);
+    }
+}
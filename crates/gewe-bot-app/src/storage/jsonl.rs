@@ -0,0 +1,61 @@
+//! 追加写入的 JSONL 事件文件
+
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// 追加一行；单行追加写入，无需读改写
+pub(super) async fn append_line<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("创建目录失败 {}: {}", dir.display(), e))?;
+    }
+    let mut line = serde_json::to_string(value).map_err(|e| format!("序列化失败: {}", e))?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|e| format!("打开文件失败 {}: {}", path.display(), e))?;
+    file.write_all(line.as_bytes())
+        .await
+        .map_err(|e| format!("写入失败 {}: {}", path.display(), e))?;
+    // tokio 的文件写入在后台线程完成，flush 后才能保证随后读取可见
+    file.flush()
+        .await
+        .map_err(|e| format!("写入失败 {}: {}", path.display(), e))
+}
+
+/// 读取全部行，文件不存在时返回空，跳过无法解析的行
+pub(super) async fn read_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, String> {
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("读取失败 {}: {}", path.display(), e)),
+    };
+    Ok(content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect())
+}
+
+/// 整体重写文件：先写临时文件再改名，避免读到写了一半的内容
+pub(super) async fn write_lines<T: Serialize>(path: &Path, values: &[T]) -> Result<(), String> {
+    let mut content = String::new();
+    for value in values {
+        content.push_str(&serde_json::to_string(value).map_err(|e| format!("序列化失败: {}", e))?);
+        content.push('\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, content)
+        .await
+        .map_err(|e| format!("写入失败 {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path)
+        .await
+        .map_err(|e| format!("替换文件失败 {}: {}", path.display(), e))
+}
@@ -0,0 +1,150 @@
+//! 媒体保存记录
+//!
+//! 追加写入 `{data_dir}/media/records.jsonl`，记录每次保存的来源、SHA-256 与各存储中的位置，
+//! 同时用于按 SHA-256 去重；`signing.key` 为媒体签名链接的密钥，首次使用时随机生成
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::jsonl;
+use crate::config::RuleKind;
+
+/// 一次媒体保存
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaRecord {
+    pub at: DateTime<Utc>,
+    pub app_id: String,
+    /// 会话：群聊 ID 或私聊 wxid
+    pub chat: String,
+    pub sender: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_msg_id: Option<i64>,
+    pub kind: RuleKind,
+    pub sha256: String,
+    pub size: u64,
+    /// 渲染后的文件名
+    pub file_name: String,
+    pub locations: Vec<MediaLocation>,
+}
+
+/// 媒体在某个存储中的位置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaLocation {
+    pub sink: String,
+    /// 本地文件路径或对象地址
+    pub location: String,
+    /// 该存储中已有相同内容，未重复写入
+    #[serde(default)]
+    pub deduplicated: bool,
+}
+
+/// 基于 JSONL 文件的保存记录
+#[derive(Debug, Clone)]
+pub struct MediaLog {
+    dir: PathBuf,
+}
+
+impl MediaLog {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("media"),
+        }
+    }
+
+    fn records_path(&self) -> PathBuf {
+        self.dir.join("records.jsonl")
+    }
+
+    pub async fn append(&self, record: &MediaRecord) -> Result<(), String> {
+        jsonl::append_line(&self.records_path(), record)
+            .await
+            .map_err(|e| format!("写入媒体保存记录失败: {}", e))
+    }
+
+    pub async fn load_all(&self) -> Result<Vec<MediaRecord>, String> {
+        jsonl::read_lines(&self.records_path())
+            .await
+            .map_err(|e| format!("读取媒体保存记录失败: {}", e))
+    }
+
+    /// 相同内容在某个存储中已有的位置
+    pub async fn find_location(&self, sink: &str, sha256: &str) -> Result<Option<String>, String> {
+        Ok(self
+            .load_all()
+            .await?
+            .into_iter()
+            .filter(|r| r.sha256 == sha256)
+            .flat_map(|r| r.locations)
+            .find(|l| l.sink == sink)
+            .map(|l| l.location))
+    }
+
+    /// 媒体签名链接的密钥，不存在时生成并保存
+    pub async fn signing_key(&self) -> Result<Vec<u8>, String> {
+        let path = self.dir.join("signing.key");
+        match fs::read_to_string(&path).await {
+            Ok(content) => {
+                return hex::decode(content.trim())
+                    .map_err(|e| format!("解析媒体签名密钥失败 {}: {}", path.display(), e))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("读取媒体签名密钥失败 {}: {}", path.display(), e)),
+        }
+        let key = rand::random::<[u8; 32]>().to_vec();
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建目录失败 {}: {}", self.dir.display(), e))?;
+        fs::write(&path, hex::encode(&key))
+            .await
+            .map_err(|e| format!("保存媒体签名密钥失败 {}: {}", path.display(), e))?;
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_media_log_find_location() {
+        let temp = TempDir::new().unwrap();
+        let log = MediaLog::new(temp.path());
+        assert_eq!(log.find_location("s3", "abc").await.unwrap(), None);
+
+        log.append(&MediaRecord {
+            at: Utc::now(),
+            app_id: "app".to_string(),
+            chat: "123@chatroom".to_string(),
+            sender: "wxid_a".to_string(),
+            msg_id: Some(1),
+            new_msg_id: Some(2),
+            kind: RuleKind::Image,
+            sha256: "abc".to_string(),
+            size: 3,
+            file_name: "2.png".to_string(),
+            locations: vec![MediaLocation {
+                sink: "s3".to_string(),
+                location: "https://s3.example.com/media/2.png".to_string(),
+                deduplicated: false,
+            }],
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            log.find_location("s3", "abc").await.unwrap().as_deref(),
+            Some("https://s3.example.com/media/2.png")
+        );
+        assert_eq!(log.find_location("dav", "abc").await.unwrap(), None);
+        assert_eq!(log.find_location("s3", "def").await.unwrap(), None);
+
+        let key = log.signing_key().await.unwrap();
+        assert_eq!(key.len(), 32);
+        assert_eq!(log.signing_key().await.unwrap(), key);
+    }
+}
@@ -1,17 +1,85 @@
 //! 配置存储抽象层
 //!
-//! 定义统一的存储接口，支持文件存储和 Postgres 存储
+//! 定义统一的存储接口，支持文件存储和 Postgres 存储；另含待办清单、AI 语义缓存等业务数据存储
 //!
 //! 注意：存储抽象层当前为预留功能，待后续完整集成
 
 #![allow(dead_code)]
 
+mod ai_tasks;
+mod alert;
+mod canary;
+mod chat_settings;
+mod conversation;
+mod dead_letter;
+mod embedding_cache;
+mod escalation;
+mod experiment;
 mod factory;
+mod feedback;
 mod file;
+mod history;
+mod history_search;
+mod jobs;
+mod jsonl;
+mod media;
+mod moderation;
+mod ops;
+mod persona;
+#[cfg(feature = "postgres")]
 mod postgres;
-
+mod reminder;
+mod runtime;
+mod safe_mode;
+mod semantic_cache;
+mod todo;
+
+pub use ai_tasks::{AiTaskRecord, AiTaskStatus, AiTaskStore, AiTaskTable};
+pub use alert::{summarize_alerts, AlertLog, AlertRecord};
+pub use canary::{
+    CanaryState, CanaryStatus, CanaryStore, CanaryVerdict, DEFAULT_CANARY_MIN_MESSAGES,
+    DEFAULT_CANARY_WINDOW_SECS, DEFAULT_MAX_ERROR_RATE_INCREASE,
+};
+pub use chat_settings::{is_valid_locale, ChatSettings, ChatSettingsStore, Formality};
+#[cfg(feature = "sqlite")]
+pub use conversation::SqliteConversationStore;
+pub use conversation::{ConversationStore, ConversationTurn, InMemoryConversationStore};
+pub use dead_letter::{DeadLetter, DeadLetterStore};
+pub use embedding_cache::EmbeddingCache;
+pub use escalation::{Ticket, TicketBook, TicketStore};
+pub use experiment::{
+    build_experiment_reports, ExperimentEvent, ExperimentSignal, ExperimentStore,
+};
+pub use feedback::{build_feedback_summary, FeedbackRecord, FeedbackStore};
 pub use file::FileStorage;
+pub use history::{
+    signed_context_path, verify_context_sig, HistoryDirection, HistoryMessage, HistoryStore,
+};
+pub use history_search::{open_history_search, SearchQuery};
+// 搜索索引后端与结果，二进制内只经 HistoryStore 使用，供库使用者接入其他索引
+#[cfg(feature = "postgres")]
+#[allow(unused_imports)]
+pub use history_search::PostgresHistorySearch;
+#[cfg(feature = "sqlite")]
+#[allow(unused_imports)]
+pub use history_search::SqliteHistorySearch;
+#[allow(unused_imports)]
+pub use history_search::{HistorySearch, SearchHit, HIGHLIGHT_END, HIGHLIGHT_START};
+pub use jobs::{next_daily_run, JobSpec, JobStore};
+pub use media::{MediaLocation, MediaLog, MediaRecord};
+pub use moderation::{ModerationLog, ModerationRecord};
+pub use ops::{
+    build_model_report, build_ops_digest, build_ops_stats, OpsDigest, OpsEvent, OpsEventKind,
+    OpsLog,
+};
+pub use persona::{PersonaBindings, PersonaStore};
+#[cfg(feature = "postgres")]
 pub use postgres::PostgresStorage;
+pub use reminder::{Reminder, ReminderStore};
+pub use runtime::{RuntimeSnapshot, RuntimeStateStore, TurnSnapshot};
+pub use safe_mode::{SafeModeEntry, SafeModeState, SafeModeStore};
+pub use semantic_cache::{cosine_similarity, SemanticCache};
+pub use todo::{TodoItem, TodoList, TodoStore};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
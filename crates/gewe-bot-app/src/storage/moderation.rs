@@ -0,0 +1,124 @@
+//! 群聊违规处理审计日志
+//!
+//! 追加写入 `{data_dir}/moderation/audit.jsonl`，同时用于统计成员在窗口内的违规次数
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::jsonl;
+use crate::config::ModerationStep;
+
+/// 一次违规及其处理结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModerationRecord {
+    pub at: DateTime<Utc>,
+    pub app_id: String,
+    pub chatroom: String,
+    pub sender: String,
+    /// 命中的违禁词或正则
+    pub matched: String,
+    pub content: String,
+    /// 窗口内第几次违规
+    pub count: u32,
+    pub step: ModerationStep,
+    /// 处理失败的原因，如机器人没有撤回或移出成员的权限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 基于 JSONL 文件的审计日志
+#[derive(Debug, Clone)]
+pub struct ModerationLog {
+    dir: PathBuf,
+}
+
+impl ModerationLog {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("moderation"),
+        }
+    }
+
+    fn audit_path(&self) -> PathBuf {
+        self.dir.join("audit.jsonl")
+    }
+
+    pub async fn append(&self, record: &ModerationRecord) -> Result<(), String> {
+        jsonl::append_line(&self.audit_path(), record)
+            .await
+            .map_err(|e| format!("写入违规审计日志失败: {}", e))
+    }
+
+    pub async fn load_all(&self) -> Result<Vec<ModerationRecord>, String> {
+        jsonl::read_lines(&self.audit_path())
+            .await
+            .map_err(|e| format!("读取违规审计日志失败: {}", e))
+    }
+
+    /// 成员在某个群自 `since` 起的违规次数
+    pub async fn violations(
+        &self,
+        app_id: &str,
+        chatroom: &str,
+        sender: &str,
+        since: DateTime<Utc>,
+    ) -> Result<u32, String> {
+        Ok(self
+            .load_all()
+            .await?
+            .iter()
+            .filter(|r| {
+                r.app_id == app_id && r.chatroom == chatroom && r.sender == sender && r.at >= since
+            })
+            .count() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(sender: &str, at: DateTime<Utc>) -> ModerationRecord {
+        ModerationRecord {
+            at,
+            app_id: "app".to_string(),
+            chatroom: "123@chatroom".to_string(),
+            sender: sender.to_string(),
+            matched: "代开发票".to_string(),
+            content: "代开发票，联系我".to_string(),
+            count: 1,
+            step: ModerationStep::Warn,
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_moderation_log_violations() {
+        let temp = TempDir::new().unwrap();
+        let log = ModerationLog::new(temp.path());
+        let now = Utc::now();
+        log.append(&record("wxid_a", now - chrono::Duration::days(2)))
+            .await
+            .unwrap();
+        log.append(&record("wxid_a", now)).await.unwrap();
+        log.append(&record("wxid_b", now)).await.unwrap();
+
+        let since = now - chrono::Duration::days(1);
+        assert_eq!(
+            log.violations("app", "123@chatroom", "wxid_a", since)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            log.violations("app", "456@chatroom", "wxid_a", since)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(log.load_all().await.unwrap().len(), 3);
+    }
+}
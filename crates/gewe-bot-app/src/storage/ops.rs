@@ -0,0 +1,729 @@
+//! 运营事件日志
+//!
+//! 按日追加写入 `{data_dir}/ops/YYYY-MM-DD.jsonl`（UTC 日期），供运营摘要按时间窗口汇总
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{jsonl, FeedbackRecord};
+use crate::config::ModelPrice;
+
+/// 摘要中保留的最近错误条数
+const RECENT_ERROR_LIMIT: usize = 10;
+
+/// 运营事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpsEvent {
+    pub at: DateTime<Utc>,
+    pub app_id: String,
+    #[serde(flatten)]
+    pub kind: OpsEventKind,
+}
+
+impl OpsEvent {
+    pub fn new(app_id: &str, kind: OpsEventKind) -> Self {
+        Self {
+            at: Utc::now(),
+            app_id: app_id.to_string(),
+            kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OpsEventKind {
+    /// 处理了一条消息
+    Message,
+    /// 规则命中
+    RuleHit { rule: String },
+    /// 一次 AI 请求及其 token 用量、耗时（含重试）
+    AiCall {
+        model: String,
+        input_tokens: u64,
+        output_tokens: u64,
+        #[serde(default)]
+        latency_ms: u64,
+    },
+    /// 处理出错；AI 请求失败时附带模型名
+    Error {
+        source: String,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+    },
+    /// 健康检查判定离线
+    Offline,
+    /// 离线后恢复在线
+    Online,
+    /// 影子模式下本应发送的消息或操作
+    Shadow {
+        to: String,
+        action: String,
+        content: String,
+    },
+    /// 回调中收到的自己发送的消息
+    OwnMessageEcho {
+        to: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new_msg_id: Option<i64>,
+    },
+}
+
+/// 某条规则的命中次数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleHitCount {
+    pub rule: String,
+    pub hits: u64,
+}
+
+/// 某个模型的 AI 用量与花费
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AiSpend {
+    pub model: String,
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 未配置单价时为 None
+    pub cost: Option<f64>,
+}
+
+/// 一条错误记录
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorSample {
+    pub at: DateTime<Utc>,
+    pub source: String,
+    pub message: String,
+}
+
+/// 一次离线事件；start 为 None 表示窗口开始前已离线，end 为 None 表示尚未恢复
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OfflineIncident {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// 某个机器人在时间窗口内的运营摘要
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpsDigest {
+    pub app_id: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub messages: u64,
+    /// 按命中次数降序
+    pub rule_hits: Vec<RuleHitCount>,
+    pub ai: Vec<AiSpend>,
+    /// 已配置单价的模型花费合计
+    pub ai_cost: f64,
+    pub errors: u64,
+    /// 最近的错误，按时间倒序
+    pub recent_errors: Vec<ErrorSample>,
+    pub incidents: Vec<OfflineIncident>,
+}
+
+/// 汇总某个机器人在 [start, end) 内的事件
+pub fn build_ops_digest(
+    events: &[OpsEvent],
+    app_id: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    prices: &BTreeMap<String, ModelPrice>,
+) -> OpsDigest {
+    let mut messages = 0;
+    let mut rule_hits: BTreeMap<&str, u64> = BTreeMap::new();
+    let mut ai: BTreeMap<&str, AiSpend> = BTreeMap::new();
+    let mut errors = Vec::new();
+    let mut incidents: Vec<OfflineIncident> = Vec::new();
+
+    let mut events: Vec<_> = events
+        .iter()
+        .filter(|e| e.app_id == app_id && e.at >= start && e.at < end)
+        .collect();
+    events.sort_by_key(|e| e.at);
+    for event in events {
+        match &event.kind {
+            OpsEventKind::Message => messages += 1,
+            OpsEventKind::RuleHit { rule } => *rule_hits.entry(rule).or_default() += 1,
+            OpsEventKind::AiCall {
+                model,
+                input_tokens,
+                output_tokens,
+                ..
+            } => {
+                let spend = ai.entry(model).or_insert_with(|| AiSpend {
+                    model: model.clone(),
+                    ..Default::default()
+                });
+                spend.calls += 1;
+                spend.input_tokens += input_tokens;
+                spend.output_tokens += output_tokens;
+            }
+            OpsEventKind::Error {
+                source, message, ..
+            } => errors.push(ErrorSample {
+                at: event.at,
+                source: source.clone(),
+                message: message.clone(),
+            }),
+            OpsEventKind::Offline | OpsEventKind::Online => track_incident(&mut incidents, event),
+            OpsEventKind::Shadow { .. } | OpsEventKind::OwnMessageEcho { .. } => {}
+        }
+    }
+
+    let mut rule_hits: Vec<_> = rule_hits
+        .into_iter()
+        .map(|(rule, hits)| RuleHitCount {
+            rule: rule.to_string(),
+            hits,
+        })
+        .collect();
+    rule_hits.sort_by(|a, b| b.hits.cmp(&a.hits).then(a.rule.cmp(&b.rule)));
+
+    let ai: Vec<_> = ai
+        .into_values()
+        .map(|mut spend| {
+            spend.cost = prices.get(&spend.model).map(|p| {
+                (spend.input_tokens as f64 * p.input + spend.output_tokens as f64 * p.output)
+                    / 1_000_000.0
+            });
+            spend
+        })
+        .collect();
+    let ai_cost = ai.iter().filter_map(|s| s.cost).sum();
+
+    let error_count = errors.len() as u64;
+    errors.reverse();
+    errors.truncate(RECENT_ERROR_LIMIT);
+
+    OpsDigest {
+        app_id: app_id.to_string(),
+        start,
+        end,
+        messages,
+        rule_hits,
+        ai,
+        ai_cost,
+        errors: error_count,
+        recent_errors: errors,
+        incidents,
+    }
+}
+
+/// 按时间顺序把离线/恢复事件合并为离线区间
+fn track_incident(incidents: &mut Vec<OfflineIncident>, event: &OpsEvent) {
+    match event.kind {
+        OpsEventKind::Offline if incidents.last().is_none_or(|i| i.end.is_some()) => {
+            incidents.push(OfflineIncident {
+                start: Some(event.at),
+                end: None,
+            });
+        }
+        OpsEventKind::Online => match incidents.last_mut() {
+            Some(incident) if incident.end.is_none() => incident.end = Some(event.at),
+            _ => incidents.push(OfflineIncident {
+                start: None,
+                end: Some(event.at),
+            }),
+        },
+        _ => {}
+    }
+}
+
+/// 某个机器人的在线状态历史
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BotAvailability {
+    pub app_id: String,
+    pub incidents: Vec<OfflineIncident>,
+    /// 窗口内的离线总时长（秒）
+    pub offline_secs: i64,
+}
+
+/// 某个模型的 AI 请求耗时分位数（毫秒）
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LatencyPercentiles {
+    pub model: String,
+    pub samples: usize,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+/// 统计页面使用的时间序列与分布
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpsStats {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// 各时间桶的起点
+    pub buckets: Vec<DateTime<Utc>>,
+    /// 各机器人每个时间桶的消息数
+    pub messages: BTreeMap<String, Vec<u64>>,
+    /// 命中最多的规则（跨机器人合计）
+    pub top_rules: Vec<RuleHitCount>,
+    /// 出现过离线/恢复事件的机器人
+    pub availability: Vec<BotAvailability>,
+    pub latency: Vec<LatencyPercentiles>,
+}
+
+/// 汇总 [start, end) 内全部机器人的事件，消息量按 bucket 分桶
+pub fn build_ops_stats(
+    events: &[OpsEvent],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: chrono::Duration,
+    top_rules: usize,
+) -> OpsStats {
+    let bucket_secs = bucket.num_seconds().max(1);
+    let count = ((end - start).num_seconds().max(0) + bucket_secs - 1) / bucket_secs;
+    let buckets: Vec<_> = (0..count)
+        .map(|i| start + chrono::Duration::seconds(i * bucket_secs))
+        .collect();
+
+    let mut messages: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    let mut rules: BTreeMap<&str, u64> = BTreeMap::new();
+    let mut incidents: BTreeMap<&str, Vec<OfflineIncident>> = BTreeMap::new();
+    let mut latencies: BTreeMap<&str, Vec<u64>> = BTreeMap::new();
+
+    let mut events: Vec<_> = events
+        .iter()
+        .filter(|e| e.at >= start && e.at < end)
+        .collect();
+    events.sort_by_key(|e| e.at);
+    for event in events {
+        match &event.kind {
+            OpsEventKind::Message => {
+                let idx = ((event.at - start).num_seconds() / bucket_secs) as usize;
+                let series = messages
+                    .entry(event.app_id.clone())
+                    .or_insert_with(|| vec![0; buckets.len()]);
+                if let Some(slot) = series.get_mut(idx) {
+                    *slot += 1;
+                }
+            }
+            OpsEventKind::RuleHit { rule } => *rules.entry(rule).or_default() += 1,
+            OpsEventKind::AiCall {
+                model, latency_ms, ..
+            } if *latency_ms > 0 => latencies.entry(model).or_default().push(*latency_ms),
+            OpsEventKind::Offline | OpsEventKind::Online => {
+                track_incident(incidents.entry(&event.app_id).or_default(), event)
+            }
+            _ => {}
+        }
+    }
+
+    let mut rules: Vec<_> = rules
+        .into_iter()
+        .map(|(rule, hits)| RuleHitCount {
+            rule: rule.to_string(),
+            hits,
+        })
+        .collect();
+    rules.sort_by(|a, b| b.hits.cmp(&a.hits).then(a.rule.cmp(&b.rule)));
+    rules.truncate(top_rules);
+
+    let availability = incidents
+        .into_iter()
+        .map(|(app_id, incidents)| BotAvailability {
+            app_id: app_id.to_string(),
+            offline_secs: incidents
+                .iter()
+                .map(|i| (i.end.unwrap_or(end) - i.start.unwrap_or(start)).num_seconds())
+                .sum(),
+            incidents,
+        })
+        .collect();
+
+    let latency = latencies
+        .into_iter()
+        .map(|(model, mut samples)| {
+            samples.sort_unstable();
+            LatencyPercentiles {
+                model: model.to_string(),
+                samples: samples.len(),
+                p50: percentile(&samples, 50),
+                p90: percentile(&samples, 90),
+                p99: percentile(&samples, 99),
+            }
+        })
+        .collect();
+
+    OpsStats {
+        start,
+        end,
+        buckets,
+        messages,
+        top_rules: rules,
+        availability,
+        latency,
+    }
+}
+
+/// 最近秩法求分位数，samples 需已排序
+/// 某个模型在时间窗口内的调用结果，用于模型灰度时对比质量与花费
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ModelReport {
+    pub model: String,
+    pub calls: u64,
+    pub errors: u64,
+    /// 失败占全部请求（成功 + 失败）的比例
+    pub error_rate: Option<f64>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 未配置单价时为 None
+    pub cost: Option<f64>,
+    pub cost_per_call: Option<f64>,
+    pub p50_latency_ms: Option<u64>,
+    pub p90_latency_ms: Option<u64>,
+    pub positive: u64,
+    pub negative: u64,
+    pub satisfaction: Option<f64>,
+}
+
+/// 按模型汇总 [start, end) 内全部机器人的 AI 调用、失败与用户评价
+pub fn build_model_report(
+    events: &[OpsEvent],
+    feedback: &[FeedbackRecord],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    prices: &BTreeMap<String, ModelPrice>,
+) -> Vec<ModelReport> {
+    let mut reports: BTreeMap<&str, ModelReport> = BTreeMap::new();
+    let mut latencies: BTreeMap<&str, Vec<u64>> = BTreeMap::new();
+
+    for event in events.iter().filter(|e| e.at >= start && e.at < end) {
+        match &event.kind {
+            OpsEventKind::AiCall {
+                model,
+                input_tokens,
+                output_tokens,
+                latency_ms,
+            } => {
+                let report = model_report(&mut reports, model);
+                report.calls += 1;
+                report.input_tokens += input_tokens;
+                report.output_tokens += output_tokens;
+                if *latency_ms > 0 {
+                    latencies.entry(model).or_default().push(*latency_ms);
+                }
+            }
+            OpsEventKind::Error {
+                model: Some(model), ..
+            } => model_report(&mut reports, model).errors += 1,
+            _ => {}
+        }
+    }
+    for record in feedback.iter().filter(|r| r.at >= start && r.at < end) {
+        let report = model_report(&mut reports, &record.model);
+        if record.positive {
+            report.positive += 1;
+        } else {
+            report.negative += 1;
+        }
+    }
+
+    reports
+        .into_values()
+        .map(|mut report| {
+            let attempts = report.calls + report.errors;
+            report.error_rate = (attempts > 0).then(|| report.errors as f64 / attempts as f64);
+            report.cost = prices.get(&report.model).map(|p| {
+                (report.input_tokens as f64 * p.input + report.output_tokens as f64 * p.output)
+                    / 1_000_000.0
+            });
+            report.cost_per_call = report
+                .cost
+                .filter(|_| report.calls > 0)
+                .map(|cost| cost / report.calls as f64);
+            if let Some(samples) = latencies.get_mut(report.model.as_str()) {
+                samples.sort_unstable();
+                report.p50_latency_ms = Some(percentile(samples, 50));
+                report.p90_latency_ms = Some(percentile(samples, 90));
+            }
+            let rated = report.positive + report.negative;
+            report.satisfaction = (rated > 0).then(|| report.positive as f64 / rated as f64);
+            report
+        })
+        .collect()
+}
+
+fn model_report<'a, 'm>(
+    reports: &'a mut BTreeMap<&'m str, ModelReport>,
+    model: &'m str,
+) -> &'a mut ModelReport {
+    reports.entry(model).or_insert_with(|| ModelReport {
+        model: model.to_string(),
+        ..Default::default()
+    })
+}
+
+fn percentile(samples: &[u64], p: usize) -> u64 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let rank = (p * samples.len()).div_ceil(100).max(1);
+    samples[rank.min(samples.len()) - 1]
+}
+
+/// 基于按日 JSONL 文件的运营事件存储
+#[derive(Debug, Clone)]
+pub struct OpsLog {
+    dir: PathBuf,
+}
+
+impl OpsLog {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("ops"),
+        }
+    }
+
+    fn day_path(&self, date: chrono::NaiveDate) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", date.format("%Y-%m-%d")))
+    }
+
+    pub async fn append(&self, event: &OpsEvent) -> Result<(), String> {
+        jsonl::append_line(&self.day_path(event.at.date_naive()), event)
+            .await
+            .map_err(|e| format!("写入运营事件失败: {}", e))
+    }
+
+    /// 读取 [start, end) 内的事件
+    pub async fn load_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<OpsEvent>, String> {
+        let mut events = Vec::new();
+        let mut date = start.date_naive();
+        while date <= end.date_naive() {
+            let day: Vec<OpsEvent> = jsonl::read_lines(&self.day_path(date))
+                .await
+                .map_err(|e| format!("读取运营事件失败: {}", e))?;
+            events.extend(day.into_iter().filter(|e| e.at >= start && e.at < end));
+            let Some(next) = date.succ_opt() else {
+                break;
+            };
+            date = next;
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    fn event(app_id: &str, minutes: i64, kind: OpsEventKind) -> OpsEvent {
+        OpsEvent {
+            at: DateTime::parse_from_rfc3339("2026-10-14T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc)
+                + Duration::minutes(minutes),
+            app_id: app_id.to_string(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_build_ops_digest() {
+        let hit = |rule: &str| OpsEventKind::RuleHit {
+            rule: rule.to_string(),
+        };
+        let call = |input, output| OpsEventKind::AiCall {
+            model: "gpt-4o".to_string(),
+            input_tokens: input,
+            output_tokens: output,
+            latency_ms: 0,
+        };
+        let events = vec![
+            event("app", 1, OpsEventKind::Online),
+            event("app", 2, OpsEventKind::Message),
+            event("app", 3, OpsEventKind::Message),
+            event("other", 3, OpsEventKind::Message),
+            event("app", 4, hit("faq")),
+            event("app", 5, hit("faq")),
+            event("app", 6, hit("chat")),
+            event("app", 7, call(1_000_000, 0)),
+            event("app", 8, call(0, 500_000)),
+            event(
+                "app",
+                9,
+                OpsEventKind::Error {
+                    source: "ai".to_string(),
+                    message: "超时".to_string(),
+                    model: None,
+                },
+            ),
+            event("app", 10, OpsEventKind::Offline),
+            event("app", 11, OpsEventKind::Offline),
+            event("app", 2000, OpsEventKind::Message),
+        ];
+        let start = events[0].at - Duration::minutes(1);
+        let prices = BTreeMap::from([(
+            "gpt-4o".to_string(),
+            ModelPrice {
+                input: 2.5,
+                output: 10.0,
+            },
+        )]);
+        let digest = build_ops_digest(&events, "app", start, start + Duration::days(1), &prices);
+
+        assert_eq!(digest.messages, 2);
+        assert_eq!(digest.rule_hits[0].rule, "faq");
+        assert_eq!(digest.rule_hits[0].hits, 2);
+        assert_eq!(digest.ai[0].calls, 2);
+        assert!((digest.ai_cost - 7.5).abs() < 1e-9);
+        assert_eq!(digest.errors, 1);
+        assert_eq!(digest.recent_errors[0].message, "超时");
+        assert_eq!(digest.incidents.len(), 2);
+        assert_eq!(digest.incidents[0].start, None);
+        assert_eq!(digest.incidents[1].start, Some(events[10].at));
+        assert_eq!(digest.incidents[1].end, None);
+    }
+
+    #[test]
+    fn test_build_model_report() {
+        let call = |model: &str, latency_ms| OpsEventKind::AiCall {
+            model: model.to_string(),
+            input_tokens: 1_000,
+            output_tokens: 500,
+            latency_ms,
+        };
+        let error = |model: Option<&str>| OpsEventKind::Error {
+            source: "ai".to_string(),
+            message: "超时".to_string(),
+            model: model.map(str::to_string),
+        };
+        let events = vec![
+            event("app", 1, call("gpt-4o-mini", 800)),
+            event("app", 2, call("gpt-4o-mini", 1_200)),
+            event("other", 3, call("claude-sonnet", 2_000)),
+            event("app", 4, error(Some("claude-sonnet"))),
+            event("app", 5, error(None)),
+            event("app", 60 * 25, call("gpt-4o-mini", 900)),
+        ];
+        let rating = |model: &str, positive| FeedbackRecord {
+            at: events[0].at,
+            app_id: "app".to_string(),
+            chat: "room".to_string(),
+            user: "wxid_a".to_string(),
+            rule: "ask".to_string(),
+            model: model.to_string(),
+            variant: None,
+            question: None,
+            reply: "回复".to_string(),
+            positive,
+        };
+        let feedback = vec![
+            rating("gpt-4o-mini", true),
+            rating("gpt-4o-mini", false),
+            rating("claude-sonnet", true),
+        ];
+        let prices = BTreeMap::from([(
+            "gpt-4o-mini".to_string(),
+            ModelPrice {
+                input: 0.15,
+                output: 0.6,
+            },
+        )]);
+        let start = events[0].at - Duration::minutes(1);
+        let report = build_model_report(
+            &events,
+            &feedback,
+            start,
+            start + Duration::days(1),
+            &prices,
+        );
+
+        assert_eq!(report.len(), 2);
+        let claude = &report[0];
+        assert_eq!(claude.model, "claude-sonnet");
+        assert_eq!((claude.calls, claude.errors), (1, 1));
+        assert_eq!(claude.error_rate, Some(0.5));
+        assert_eq!(claude.cost, None);
+        assert_eq!(claude.p50_latency_ms, Some(2_000));
+        assert_eq!(claude.satisfaction, Some(1.0));
+
+        let mini = &report[1];
+        assert_eq!(mini.model, "gpt-4o-mini");
+        assert_eq!((mini.calls, mini.errors), (2, 0));
+        assert_eq!(mini.error_rate, Some(0.0));
+        assert_eq!((mini.input_tokens, mini.output_tokens), (2_000, 1_000));
+        let cost = mini.cost.unwrap();
+        assert!((cost - 0.0009).abs() < 1e-9);
+        assert!((mini.cost_per_call.unwrap() - 0.00045).abs() < 1e-9);
+        assert_eq!(mini.p50_latency_ms, Some(800));
+        assert_eq!(mini.p90_latency_ms, Some(1_200));
+        assert_eq!((mini.positive, mini.negative), (1, 1));
+        assert_eq!(mini.satisfaction, Some(0.5));
+    }
+
+    #[test]
+    fn test_build_ops_stats() {
+        let call = |latency_ms| OpsEventKind::AiCall {
+            model: "gpt-4o".to_string(),
+            input_tokens: 10,
+            output_tokens: 10,
+            latency_ms,
+        };
+        let mut events = vec![
+            event("app", 0, OpsEventKind::Message),
+            event("app", 30, OpsEventKind::Message),
+            event("app", 90, OpsEventKind::Message),
+            event("other", 61, OpsEventKind::Message),
+            event("app", 5, OpsEventKind::Offline),
+            event("app", 35, OpsEventKind::Online),
+            event(
+                "app",
+                6,
+                OpsEventKind::RuleHit {
+                    rule: "faq".to_string(),
+                },
+            ),
+        ];
+        for (i, latency) in (1..=100).enumerate() {
+            events.push(event("app", i as i64, call(latency * 10)));
+        }
+        events.push(event("app", 1, call(0)));
+        let start = events[0].at;
+        let stats = build_ops_stats(
+            &events,
+            start,
+            start + Duration::hours(2),
+            Duration::hours(1),
+            10,
+        );
+
+        assert_eq!(stats.buckets.len(), 2);
+        assert_eq!(stats.messages["app"], vec![2, 1]);
+        assert_eq!(stats.messages["other"], vec![0, 1]);
+        assert_eq!(stats.top_rules[0].hits, 1);
+        assert_eq!(stats.availability[0].offline_secs, 30 * 60);
+        let latency = &stats.latency[0];
+        assert_eq!(latency.samples, 100);
+        assert_eq!((latency.p50, latency.p90, latency.p99), (500, 900, 990));
+    }
+
+    #[tokio::test]
+    async fn test_ops_log_load_range() {
+        let temp = TempDir::new().unwrap();
+        let log = OpsLog::new(temp.path());
+        let events = [
+            event("app", 0, OpsEventKind::Message),
+            event("app", 60 * 24, OpsEventKind::Message),
+            event("app", 60 * 48, OpsEventKind::Message),
+        ];
+        for e in &events {
+            log.append(e).await.unwrap();
+        }
+        let loaded = log
+            .load_range(events[0].at + Duration::minutes(1), events[2].at)
+            .await
+            .unwrap();
+        assert_eq!(loaded, vec![events[1].clone()]);
+    }
+}
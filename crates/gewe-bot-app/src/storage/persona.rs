@@ -0,0 +1,85 @@
+//! 会话人设绑定存储
+//!
+//! 每个机器人一份 JSON 文件：`{data_dir}/persona/{app_id}.json`，记录 `/persona use` 切换后的人设
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::todo::sanitize_segment;
+
+/// 某个机器人各会话绑定的人设
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PersonaBindings {
+    /// 会话（群聊 ID 或私聊 wxid）→ 人设名称
+    #[serde(default)]
+    pub chats: BTreeMap<String, String>,
+}
+
+/// 基于文件的人设绑定存储
+#[derive(Debug, Clone)]
+pub struct PersonaStore {
+    dir: PathBuf,
+}
+
+impl PersonaStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("persona"),
+        }
+    }
+
+    fn bindings_path(&self, app_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", sanitize_segment(app_id)))
+    }
+
+    /// 读取绑定，不存在时返回空
+    pub async fn load(&self, app_id: &str) -> Result<PersonaBindings, String> {
+        let path = self.bindings_path(app_id);
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析人设绑定失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(PersonaBindings::default()),
+            Err(e) => Err(format!("读取人设绑定失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    pub async fn save(&self, app_id: &str, bindings: &PersonaBindings) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建人设目录失败: {}", e))?;
+        let path = self.bindings_path(app_id);
+        let content = serde_json::to_string_pretty(bindings)
+            .map_err(|e| format!("序列化人设绑定失败: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入人设绑定失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入人设绑定失败 {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_persona_store_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let store = PersonaStore::new(temp.path());
+        let mut bindings = store.load("app").await.unwrap();
+        assert!(bindings.chats.is_empty());
+
+        bindings
+            .chats
+            .insert("123@chatroom".to_string(), "expert".to_string());
+        store.save("app", &bindings).await.unwrap();
+        assert_eq!(store.load("app").await.unwrap(), bindings);
+        assert!(store.load("other").await.unwrap().chats.is_empty());
+    }
+}
@@ -529,8 +529,7 @@ token = "test_token"
     fn test_option_handling() {
         // 测试 Option 处理
         let remark: Option<String> = Some("test remark".to_string());
-        assert!(remark.is_some());
-        assert_eq!(remark.unwrap(), "test remark");
+        assert_eq!(remark.as_deref(), Some("test remark"));
 
         let remark: Option<String> = None;
         assert!(remark.is_none());
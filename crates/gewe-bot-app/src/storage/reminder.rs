@@ -0,0 +1,144 @@
+//! 提醒事项存储
+//!
+//! 每个机器人一份 JSON 文件：`{data_dir}/reminders/{app_id}.json`
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::todo::sanitize_segment;
+
+/// 单条提醒
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reminder {
+    /// 机器人内自增编号，用于 cancel
+    pub id: u64,
+    /// 创建提醒的会话（群聊 ID 或私聊 wxid）
+    pub chat: String,
+    /// 提醒对象 wxid
+    pub requester: String,
+    /// 提醒对象昵称，群聊 @ 时使用
+    #[serde(default)]
+    pub nickname: Option<String>,
+    pub text: String,
+    pub due_at: DateTime<Utc>,
+    /// 是否私聊提醒（群聊中创建时默认在群里 @）
+    #[serde(default)]
+    pub dm: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 某个机器人的全部提醒
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReminderBook {
+    #[serde(default)]
+    pub next_id: u64,
+    #[serde(default)]
+    pub reminders: Vec<Reminder>,
+}
+
+impl ReminderBook {
+    /// 分配新的提醒编号（从 1 开始）
+    pub fn allocate_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// 取出所有已到期的提醒
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<Reminder> {
+        let (due, pending) = self.reminders.drain(..).partition(|r| r.due_at <= now);
+        self.reminders = pending;
+        due
+    }
+}
+
+/// 基于文件的提醒存储
+#[derive(Debug, Clone)]
+pub struct ReminderStore {
+    dir: PathBuf,
+}
+
+impl ReminderStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("reminders"),
+        }
+    }
+
+    fn book_path(&self, app_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", sanitize_segment(app_id)))
+    }
+
+    /// 读取提醒，不存在时返回空
+    pub async fn load(&self, app_id: &str) -> Result<ReminderBook, String> {
+        let path = self.book_path(app_id);
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析提醒失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ReminderBook::default()),
+            Err(e) => Err(format!("读取提醒失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    pub async fn save(&self, app_id: &str, book: &ReminderBook) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建提醒目录失败: {}", e))?;
+        let path = self.book_path(app_id);
+        let content =
+            serde_json::to_string_pretty(book).map_err(|e| format!("序列化提醒失败: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入提醒失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入提醒失败 {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    fn reminder(id: u64, due_at: DateTime<Utc>) -> Reminder {
+        Reminder {
+            id,
+            chat: "123@chatroom".to_string(),
+            requester: "wxid_a".to_string(),
+            nickname: Some("小明".to_string()),
+            text: "开会".to_string(),
+            due_at,
+            dm: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reminder_store_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let store = ReminderStore::new(temp.path());
+        let mut book = store.load("app").await.unwrap();
+        assert!(book.reminders.is_empty());
+
+        let now = Utc::now();
+        let id = book.allocate_id();
+        book.reminders
+            .push(reminder(id, now - Duration::minutes(1)));
+        let id = book.allocate_id();
+        book.reminders.push(reminder(id, now + Duration::hours(1)));
+        store.save("app", &book).await.unwrap();
+
+        let mut loaded = store.load("app").await.unwrap();
+        assert_eq!(loaded, book);
+        let due = loaded.take_due(now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, 1);
+        assert_eq!(loaded.reminders.len(), 1);
+        assert_eq!(loaded.allocate_id(), 3);
+    }
+}
@@ -0,0 +1,154 @@
+//! Dispatcher 运行时状态快照
+//!
+//! 单个 JSON 文件：`{data_dir}/runtime/state.json`，带版本号，重启时恢复
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::config::FeedbackConfig;
+
+/// 当前快照格式版本，结构不兼容时递增
+pub const RUNTIME_SNAPSHOT_VERSION: u32 = 1;
+
+/// 等待反馈的 AI 回复
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TurnSnapshot {
+    pub app_id: String,
+    pub chat: String,
+    pub user: String,
+    pub rule: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    pub reply: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<FeedbackConfig>,
+    pub served_at: DateTime<Utc>,
+}
+
+/// 运行时状态快照
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeSnapshot {
+    pub version: u32,
+    pub saved_at: DateTime<Utc>,
+    /// 会话中等待反馈的 AI 回复
+    #[serde(default)]
+    pub ai_turns: Vec<TurnSnapshot>,
+    /// 已切换到备用机器人的主机器人
+    #[serde(default)]
+    pub failover_active: Vec<String>,
+    /// 查询到的群名（群 ID → 群名），供重启后与管理页面使用
+    #[serde(default)]
+    pub chatroom_names: BTreeMap<String, String>,
+}
+
+impl Default for RuntimeSnapshot {
+    fn default() -> Self {
+        Self {
+            version: RUNTIME_SNAPSHOT_VERSION,
+            saved_at: Utc::now(),
+            ai_turns: Vec::new(),
+            failover_active: Vec::new(),
+            chatroom_names: BTreeMap::new(),
+        }
+    }
+}
+
+/// 基于文件的运行时状态存储
+#[derive(Debug, Clone)]
+pub struct RuntimeStateStore {
+    dir: PathBuf,
+}
+
+impl RuntimeStateStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("runtime"),
+        }
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("state.json")
+    }
+
+    /// 读取快照，不存在时返回 None；版本不一致时返回错误，由调用方决定丢弃
+    pub async fn load(&self) -> Result<Option<RuntimeSnapshot>, String> {
+        let path = self.state_path();
+        let content = match fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("读取运行时状态失败 {}: {}", path.display(), e)),
+        };
+        let snapshot: RuntimeSnapshot = serde_json::from_str(&content)
+            .map_err(|e| format!("解析运行时状态失败 {}: {}", path.display(), e))?;
+        if snapshot.version != RUNTIME_SNAPSHOT_VERSION {
+            return Err(format!(
+                "运行时状态版本不兼容: {}（当前 {}）",
+                snapshot.version, RUNTIME_SNAPSHOT_VERSION
+            ));
+        }
+        Ok(Some(snapshot))
+    }
+
+    pub async fn save(&self, snapshot: &RuntimeSnapshot) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("创建运行时状态目录失败: {}", e))?;
+        let path = self.state_path();
+        let content = serde_json::to_string_pretty(snapshot)
+            .map_err(|e| format!("序列化运行时状态失败: {}", e))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入运行时状态失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入运行时状态失败 {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_runtime_state_roundtrip_and_version() {
+        let temp = TempDir::new().unwrap();
+        let store = RuntimeStateStore::new(temp.path());
+        assert_eq!(store.load().await.unwrap(), None);
+
+        let mut snapshot = RuntimeSnapshot::default();
+        snapshot.ai_turns.push(TurnSnapshot {
+            app_id: "app".to_string(),
+            chat: "123@chatroom".to_string(),
+            user: "wxid_a".to_string(),
+            rule: "faq".to_string(),
+            model: "gpt-4o".to_string(),
+            experiment: Some("faq".to_string()),
+            variant: Some("a".to_string()),
+            question: Some("几点开会".to_string()),
+            reply: "三点".to_string(),
+            feedback: Some(FeedbackConfig::default()),
+            served_at: Utc::now(),
+        });
+        snapshot.failover_active.push("primary".to_string());
+        snapshot
+            .chatroom_names
+            .insert("123@chatroom".to_string(), "技术群".to_string());
+        store.save(&snapshot).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), Some(snapshot.clone()));
+
+        snapshot.version = RUNTIME_SNAPSHOT_VERSION + 1;
+        store.save(&snapshot).await.unwrap();
+        assert!(store.load().await.unwrap_err().contains("版本不兼容"));
+    }
+}
@@ -0,0 +1,109 @@
+//! 安全模式状态
+//!
+//! 单个 JSON 文件：`{data_dir}/safe_mode/state.json`，记录处于安全模式的机器人。dispatcher 进入安全模式时写入，
+//! 管理员命令或恢复接口删除对应条目，dispatcher 定时读取以同步接口的恢复操作
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// 一个机器人进入安全模式的时间与原因
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SafeModeEntry {
+    pub since: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// 处于安全模式的机器人
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SafeModeState {
+    /// app_id → 进入安全模式的记录
+    #[serde(default)]
+    pub bots: BTreeMap<String, SafeModeEntry>,
+}
+
+/// 基于文件的安全模式状态存储
+#[derive(Debug, Clone)]
+pub struct SafeModeStore {
+    path: PathBuf,
+}
+
+impl SafeModeStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            path: data_dir.as_ref().join("safe_mode").join("state.json"),
+        }
+    }
+
+    /// 读取状态，不存在时返回空
+    pub async fn load(&self) -> Result<SafeModeState, String> {
+        match fs::read_to_string(&self.path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析安全模式状态失败 {}: {}", self.path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SafeModeState::default()),
+            Err(e) => Err(format!(
+                "读取安全模式状态失败 {}: {}",
+                self.path.display(),
+                e
+            )),
+        }
+    }
+
+    pub async fn save(&self, state: &SafeModeState) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("创建安全模式目录失败: {}", e))?;
+        }
+        let content = serde_json::to_string_pretty(state)
+            .map_err(|e| format!("序列化安全模式状态失败: {}", e))?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入安全模式状态失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| format!("写入安全模式状态失败 {}: {}", self.path.display(), e))
+    }
+
+    /// 解除某个机器人的安全模式，返回此前是否处于安全模式
+    pub async fn resume(&self, app_id: &str) -> Result<bool, String> {
+        let mut state = self.load().await?;
+        if state.bots.remove(app_id).is_none() {
+            return Ok(false);
+        }
+        self.save(&state).await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_safe_mode_store_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let store = SafeModeStore::new(temp.path());
+        assert!(store.load().await.unwrap().bots.is_empty());
+
+        let mut state = SafeModeState::default();
+        state.bots.insert(
+            "app".to_string(),
+            SafeModeEntry {
+                since: Utc::now(),
+                reason: "最近一分钟发送 80 条消息".to_string(),
+            },
+        );
+        store.save(&state).await.unwrap();
+        assert_eq!(store.load().await.unwrap(), state);
+
+        assert!(!store.resume("other").await.unwrap());
+        assert!(store.resume("app").await.unwrap());
+        assert!(store.load().await.unwrap().bots.is_empty());
+    }
+}
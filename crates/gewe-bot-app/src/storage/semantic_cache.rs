@@ -0,0 +1,168 @@
+//! AI 问答语义缓存（内存）
+//!
+//! 按会话保存近期问题的 embedding 与回答，相似问题直接复用回答
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    question: String,
+    embedding: Vec<f64>,
+    answer: String,
+    created_at: Instant,
+}
+
+/// 命中的缓存条目
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheHit {
+    pub question: String,
+    pub answer: String,
+    pub similarity: f64,
+}
+
+/// 以 scope（机器人 + 会话 + 模型）分桶的语义缓存
+#[derive(Debug, Default)]
+pub struct SemanticCache {
+    buckets: Mutex<HashMap<String, VecDeque<CacheEntry>>>,
+}
+
+impl SemanticCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 查找相似度不低于 threshold 且未过期的最相似条目，同时清理过期条目
+    pub async fn lookup(
+        &self,
+        scope: &str,
+        embedding: &[f64],
+        threshold: f64,
+        ttl: Duration,
+        now: Instant,
+    ) -> Option<CacheHit> {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets.get_mut(scope)?;
+        bucket.retain(|e| now.saturating_duration_since(e.created_at) < ttl);
+        bucket
+            .iter()
+            .map(|e| (e, cosine_similarity(&e.embedding, embedding)))
+            .filter(|(_, sim)| *sim >= threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(e, similarity)| CacheHit {
+                question: e.question.clone(),
+                answer: e.answer.clone(),
+                similarity,
+            })
+    }
+
+    /// 写入问答，超过 max_entries 时淘汰最旧的条目
+    pub async fn insert(
+        &self,
+        scope: &str,
+        question: String,
+        embedding: Vec<f64>,
+        answer: String,
+        max_entries: usize,
+        now: Instant,
+    ) {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets.entry(scope.to_string()).or_default();
+        bucket.push_back(CacheEntry {
+            question,
+            embedding,
+            answer,
+            created_at: now,
+        });
+        while bucket.len() > max_entries.max(1) {
+            bucket.pop_front();
+        }
+    }
+}
+
+/// 余弦相似度；维度不一致或零向量时返回 0
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-9);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_cache_lookup_ttl_and_eviction() {
+        let cache = SemanticCache::new();
+        let ttl = Duration::from_secs(60);
+        let t0 = Instant::now();
+        cache
+            .insert(
+                "app:chat",
+                "几点开会".into(),
+                vec![1.0, 0.1],
+                "三点".into(),
+                2,
+                t0,
+            )
+            .await;
+
+        let hit = cache
+            .lookup("app:chat", &[1.0, 0.12], 0.9, ttl, t0)
+            .await
+            .unwrap();
+        assert_eq!(hit.answer, "三点");
+        assert!(hit.similarity > 0.99);
+
+        // 不相似、其他会话、过期都不命中
+        assert!(cache
+            .lookup("app:chat", &[0.0, 1.0], 0.9, ttl, t0)
+            .await
+            .is_none());
+        assert!(cache
+            .lookup("app:other", &[1.0, 0.1], 0.9, ttl, t0)
+            .await
+            .is_none());
+        assert!(cache
+            .lookup("app:chat", &[1.0, 0.1], 0.9, ttl, t0 + ttl)
+            .await
+            .is_none());
+
+        // 超过 max_entries 淘汰最旧条目
+        for (q, v) in [
+            ("a", vec![0.0, 1.0]),
+            ("b", vec![1.0, 1.0]),
+            ("c", vec![1.0, -1.0]),
+        ] {
+            cache.insert("app:chat", q.into(), v, q.into(), 2, t0).await;
+        }
+        assert!(cache
+            .lookup("app:chat", &[0.0, 1.0], 0.99, ttl, t0)
+            .await
+            .is_none());
+        assert_eq!(
+            cache
+                .lookup("app:chat", &[1.0, -1.0], 0.99, ttl, t0)
+                .await
+                .map(|h| h.answer),
+            Some("c".to_string())
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! 待办清单存储
+//!
+//! 每个会话（群聊或私聊）一份 JSON 文件：`{data_dir}/todos/{app_id}/{chat}.json`
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// 单条待办
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TodoItem {
+    pub text: String,
+    #[serde(default)]
+    pub done: bool,
+    /// 添加者 wxid
+    #[serde(default)]
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 会话内共享的待办清单
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TodoList {
+    #[serde(default)]
+    pub items: Vec<TodoItem>,
+}
+
+impl TodoList {
+    /// 未完成条目数
+    pub fn open_count(&self) -> usize {
+        self.items.iter().filter(|i| !i.done).count()
+    }
+}
+
+/// 基于文件的待办清单存储
+#[derive(Debug, Clone)]
+pub struct TodoStore {
+    dir: PathBuf,
+}
+
+impl TodoStore {
+    pub fn new(data_dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: data_dir.as_ref().join("todos"),
+        }
+    }
+
+    fn bot_dir(&self, app_id: &str) -> PathBuf {
+        self.dir.join(sanitize_segment(app_id))
+    }
+
+    fn list_path(&self, app_id: &str, chat: &str) -> PathBuf {
+        self.bot_dir(app_id)
+            .join(format!("{}.json", sanitize_segment(chat)))
+    }
+
+    /// 读取会话清单，不存在时返回空清单
+    pub async fn load(&self, app_id: &str, chat: &str) -> Result<TodoList, String> {
+        let path = self.list_path(app_id, chat);
+        match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("解析待办清单失败 {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(TodoList::default()),
+            Err(e) => Err(format!("读取待办清单失败 {}: {}", path.display(), e)),
+        }
+    }
+
+    /// 保存会话清单；清单为空时删除文件
+    pub async fn save(&self, app_id: &str, chat: &str, list: &TodoList) -> Result<(), String> {
+        let path = self.list_path(app_id, chat);
+        if list.items.is_empty() {
+            return match fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(format!("删除待办清单失败 {}: {}", path.display(), e))
+                }
+                _ => Ok(()),
+            };
+        }
+        fs::create_dir_all(self.bot_dir(app_id))
+            .await
+            .map_err(|e| format!("创建待办目录失败: {}", e))?;
+        let content =
+            serde_json::to_string_pretty(list).map_err(|e| format!("序列化待办清单失败: {}", e))?;
+        // 先写临时文件再重命名，避免并发写入时读到半截内容
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入待办清单失败 {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, &path)
+            .await
+            .map_err(|e| format!("写入待办清单失败 {}: {}", path.display(), e))
+    }
+
+    /// 列出某个机器人下所有存在清单的会话
+    pub async fn list_chats(&self, app_id: &str) -> Result<Vec<String>, String> {
+        let dir = self.bot_dir(app_id);
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("读取待办目录失败 {}: {}", dir.display(), e)),
+        };
+        let mut chats = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(chat) = name.strip_suffix(".json") {
+                chats.push(chat.to_string());
+            }
+        }
+        chats.sort();
+        Ok(chats)
+    }
+}
+
+/// wxid / chatroom id 只包含字母数字与 _-@.，其余字符替换掉以免路径穿越
+pub(super) fn sanitize_segment(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '@' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn item(text: &str, done: bool) -> TodoItem {
+        TodoItem {
+            text: text.to_string(),
+            done,
+            created_by: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_todo_store_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let store = TodoStore::new(temp.path());
+
+        let empty = store.load("app", "123@chatroom").await.unwrap();
+        assert!(empty.items.is_empty());
+
+        let list = TodoList {
+            items: vec![item("买咖啡", false), item("写周报", true)],
+        };
+        store.save("app", "123@chatroom", &list).await.unwrap();
+        assert_eq!(store.load("app", "123@chatroom").await.unwrap(), list);
+        assert_eq!(list.open_count(), 1);
+        assert_eq!(
+            store.list_chats("app").await.unwrap(),
+            vec!["123@chatroom".to_string()]
+        );
+
+        // 清空后删除文件
+        store
+            .save("app", "123@chatroom", &TodoList::default())
+            .await
+            .unwrap();
+        assert!(store.list_chats("app").await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_segment() {
+        assert_eq!(sanitize_segment("123@chatroom"), "123@chatroom");
+        assert_eq!(sanitize_segment("../etc/passwd"), "_etc_passwd");
+    }
+}
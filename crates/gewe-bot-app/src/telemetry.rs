@@ -0,0 +1,198 @@
+//! Prometheus 指标
+//!
+//! webhook 与调度器通过 `metrics` 门面记录指标；服务启动时安装 Prometheus 记录器，由 `/metrics`
+//! 路由输出文本格式。未安装记录器时（测试或作为库使用）记录操作为空操作
+
+use std::time::Duration;
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use metrics::Unit;
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::push::SendStatus;
+
+/// 规则命中次数，标签 `app_id`、`rule`
+pub const RULE_HITS: &str = "gewe_rule_hits_total";
+/// 发送结果，标签 `app_id`、`kind`、`status`（`sent`/`failed`/`shadowed`）
+pub const MESSAGES_SENT: &str = "gewe_messages_sent_total";
+/// AI 请求次数，标签 `model`、`outcome`（`ok`/`error`）
+pub const AI_REQUESTS: &str = "gewe_ai_requests_total";
+/// 成功的 AI 请求耗时（含重试），标签 `model`
+pub const AI_LATENCY: &str = "gewe_ai_request_duration_seconds";
+/// 调度器处理完的事件，标签 `outcome`（`ok`/`error`）
+pub const EVENTS_HANDLED: &str = "gewe_dispatcher_events_total";
+/// 调度器正在处理的事件数
+pub const EVENTS_IN_FLIGHT: &str = "gewe_dispatcher_in_flight";
+/// 新建的转人工工单，标签 `app_id`、`routing`（`on_duty`/`queued`）
+pub const ESCALATIONS: &str = "gewe_escalations_total";
+/// 通知值班人员到接单的时长，标签 `app_id`
+pub const ESCALATION_RESPONSE: &str = "gewe_escalation_response_seconds";
+/// 创建工单到接单的时长（含排队），标签 `app_id`
+pub const ESCALATION_WAIT: &str = "gewe_escalation_wait_seconds";
+/// 接单是否在响应时限内，标签 `app_id`、`outcome`（`met`/`breached`）
+pub const ESCALATION_SLA: &str = "gewe_escalation_sla_total";
+
+const AI_LATENCY_BUCKETS: &[f64] = &[0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0];
+const ESCALATION_BUCKETS: &[f64] = &[
+    30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0, 7200.0, 14400.0, 43200.0, 86400.0,
+];
+
+fn builder() -> Result<PrometheusBuilder, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(AI_LATENCY.to_string()), AI_LATENCY_BUCKETS)?
+        .set_buckets_for_metric(
+            Matcher::Full(ESCALATION_RESPONSE.to_string()),
+            ESCALATION_BUCKETS,
+        )?
+        .set_buckets_for_metric(
+            Matcher::Full(ESCALATION_WAIT.to_string()),
+            ESCALATION_BUCKETS,
+        )
+}
+
+/// 安装全局 Prometheus 记录器，返回用于输出指标的句柄；只能调用一次
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    let handle = builder()?.install_recorder()?;
+    describe();
+    Ok(handle)
+}
+
+fn describe() {
+    gewe_webhook::describe_metrics();
+    metrics::describe_counter!(RULE_HITS, "规则命中次数");
+    metrics::describe_counter!(MESSAGES_SENT, "消息发送结果");
+    metrics::describe_counter!(AI_REQUESTS, "AI 请求次数");
+    metrics::describe_histogram!(AI_LATENCY, Unit::Seconds, "成功的 AI 请求耗时");
+    metrics::describe_counter!(EVENTS_HANDLED, "调度器处理完的事件数");
+    metrics::describe_gauge!(EVENTS_IN_FLIGHT, "调度器正在处理的事件数");
+    metrics::describe_counter!(ESCALATIONS, "新建的转人工工单数");
+    metrics::describe_histogram!(
+        ESCALATION_RESPONSE,
+        Unit::Seconds,
+        "通知值班人员到接单的时长"
+    );
+    metrics::describe_histogram!(ESCALATION_WAIT, Unit::Seconds, "创建工单到接单的时长");
+    metrics::describe_counter!(ESCALATION_SLA, "接单是否在响应时限内");
+}
+
+/// `/metrics` 路由
+pub fn router(handle: PrometheusHandle) -> Router {
+    Router::new()
+        .route("/metrics", get(render))
+        .with_state(handle)
+}
+
+async fn render(State(handle): State<PrometheusHandle>) -> impl IntoResponse {
+    // 没有独立的导出任务，输出前清理过期的直方图样本
+    handle.run_upkeep();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        handle.render(),
+    )
+}
+
+pub fn rule_hit(app_id: &str, rule: &str) {
+    metrics::counter!(RULE_HITS, "app_id" => app_id.to_string(), "rule" => rule.to_string())
+        .increment(1);
+}
+
+pub fn message_sent(app_id: &str, kind: &str, status: SendStatus) {
+    let status = match status {
+        SendStatus::Sent => "sent",
+        SendStatus::Failed => "failed",
+        SendStatus::Shadowed => "shadowed",
+    };
+    metrics::counter!(
+        MESSAGES_SENT,
+        "app_id" => app_id.to_string(),
+        "kind" => kind.to_string(),
+        "status" => status
+    )
+    .increment(1);
+}
+
+/// 记录一次 AI 请求；`latency` 为 None 表示请求失败
+pub fn ai_request(model: &str, latency: Option<Duration>) {
+    let outcome = if latency.is_some() { "ok" } else { "error" };
+    metrics::counter!(AI_REQUESTS, "model" => model.to_string(), "outcome" => outcome).increment(1);
+    if let Some(latency) = latency {
+        metrics::histogram!(AI_LATENCY, "model" => model.to_string()).record(latency);
+    }
+}
+
+/// 事件队列中等待处理的事件数
+pub fn queue_depth(depth: usize) {
+    metrics::gauge!(gewe_webhook::METRIC_QUEUE_DEPTH).set(depth as f64);
+}
+
+pub fn event_started() {
+    metrics::gauge!(EVENTS_IN_FLIGHT).increment(1.0);
+}
+
+pub fn event_finished(ok: bool) {
+    metrics::gauge!(EVENTS_IN_FLIGHT).decrement(1.0);
+    let outcome = if ok { "ok" } else { "error" };
+    metrics::counter!(EVENTS_HANDLED, "outcome" => outcome).increment(1);
+}
+
+/// 新建转人工工单；`queued` 表示非值班时间，排队到下一班
+pub fn escalation_created(app_id: &str, queued: bool) {
+    let routing = if queued { "queued" } else { "on_duty" };
+    metrics::counter!(ESCALATIONS, "app_id" => app_id.to_string(), "routing" => routing)
+        .increment(1);
+}
+
+/// 工单首次接单：`response` 自通知值班人员起算，`wait` 自创建工单起算
+pub fn escalation_acked(app_id: &str, response: Duration, wait: Duration, sla: Duration) {
+    metrics::histogram!(ESCALATION_RESPONSE, "app_id" => app_id.to_string()).record(response);
+    metrics::histogram!(ESCALATION_WAIT, "app_id" => app_id.to_string()).record(wait);
+    let outcome = if response <= sla { "met" } else { "breached" };
+    metrics::counter!(ESCALATION_SLA, "app_id" => app_id.to_string(), "outcome" => outcome)
+        .increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let recorder = builder().unwrap().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            rule_hit("wx1", "faq");
+            rule_hit("wx1", "faq");
+            message_sent("wx1", "text", SendStatus::Sent);
+            message_sent("wx1", "image", SendStatus::Failed);
+            ai_request("gpt-4o-mini", Some(Duration::from_millis(1500)));
+            ai_request("gpt-4o-mini", None);
+            event_started();
+            event_finished(true);
+            queue_depth(3);
+            escalation_created("wx1", true);
+            escalation_acked(
+                "wx1",
+                Duration::from_secs(90),
+                Duration::from_secs(4 * 3600),
+                Duration::from_secs(60),
+            );
+        });
+        let rendered = handle.render();
+        for line in [
+            r#"gewe_rule_hits_total{app_id="wx1",rule="faq"} 2"#,
+            r#"gewe_messages_sent_total{app_id="wx1",kind="image",status="failed"} 1"#,
+            r#"gewe_ai_requests_total{model="gpt-4o-mini",outcome="error"} 1"#,
+            r#"gewe_ai_request_duration_seconds_bucket{model="gpt-4o-mini",le="1"} 0"#,
+            r#"gewe_ai_request_duration_seconds_bucket{model="gpt-4o-mini",le="2"} 1"#,
+            r#"gewe_dispatcher_events_total{outcome="ok"} 1"#,
+            "gewe_dispatcher_in_flight 0",
+            "gewe_webhook_queue_depth 3",
+            r#"gewe_escalations_total{app_id="wx1",routing="queued"} 1"#,
+            r#"gewe_escalation_response_seconds_bucket{app_id="wx1",le="120"} 1"#,
+            r#"gewe_escalation_wait_seconds_bucket{app_id="wx1",le="14400"} 1"#,
+            r#"gewe_escalation_sla_total{app_id="wx1",outcome="breached"} 1"#,
+        ] {
+            assert!(rendered.contains(line), "{line}\n{rendered}");
+        }
+    }
+}
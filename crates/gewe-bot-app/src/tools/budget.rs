@@ -0,0 +1,280 @@
+//! AI 预算估算
+//!
+//! 调用模型前按字符粗略估算 token：中日韩等宽字符约 1 token/字，其余约 4 字符/token。
+//! 估算只用于预算拦截，不追求与服务商的计费完全一致。
+
+use crate::config::{BudgetConfig, ModelPrice};
+use crate::storage::{OpsEvent, OpsEventKind};
+use std::fmt;
+
+/// 未配置 max_tokens 时为输出预留的 token 数
+pub const DEFAULT_OUTPUT_TOKEN_RESERVE: u64 = 1024;
+
+/// 估算文本的 token 数
+pub fn estimate_tokens(text: &str) -> u64 {
+    let (wide, narrow) = text.chars().fold((0u64, 0u64), |(wide, narrow), c| {
+        if is_wide(c) {
+            (wide + 1, narrow)
+        } else {
+            (wide, narrow + 1)
+        }
+    });
+    wide + narrow.div_ceil(4)
+}
+
+/// 中日韩文字、全角符号等通常单独计为 token 的字符
+fn is_wide(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF | 0x20000..=0x2FFFF
+    )
+}
+
+/// 输入、输出 token 用量（预估或实际）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+
+    /// 按每百万 token 单价计算花费
+    pub fn cost(&self, price: &ModelPrice) -> f64 {
+        (self.input_tokens as f64 * price.input + self.output_tokens as f64 * price.output)
+            / 1_000_000.0
+    }
+}
+
+/// 汇总某个机器人某个模型的实际用量
+pub fn usage_from_events(events: &[OpsEvent], app_id: &str, model: &str) -> TokenUsage {
+    events
+        .iter()
+        .filter(|e| e.app_id == app_id)
+        .fold(TokenUsage::default(), |mut usage, event| {
+            if let OpsEventKind::AiCall {
+                model: m,
+                input_tokens,
+                output_tokens,
+                ..
+            } = &event.kind
+            {
+                if m == model {
+                    usage.input_tokens += input_tokens;
+                    usage.output_tokens += output_tokens;
+                }
+            }
+            usage
+        })
+}
+
+/// 超出预算的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetExceeded {
+    MessageTokens {
+        estimate: u64,
+        limit: u64,
+    },
+    MessageCost {
+        estimate: f64,
+        limit: f64,
+    },
+    DailyTokens {
+        used: u64,
+        estimate: u64,
+        limit: u64,
+    },
+    DailyCost {
+        used: f64,
+        estimate: f64,
+        limit: f64,
+    },
+}
+
+impl BudgetExceeded {
+    /// 未配置 notice 时回复给用户的提示
+    pub fn notice(&self) -> &'static str {
+        match self {
+            Self::MessageTokens { .. } | Self::MessageCost { .. } => {
+                "这条消息内容太长，超出了单次 AI 调用的预算，请精简后再试"
+            }
+            Self::DailyTokens { .. } | Self::DailyCost { .. } => "今天的 AI 额度已用完，请明天再试",
+        }
+    }
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageTokens { estimate, limit } => {
+                write!(f, "单条预估 {} tokens，上限 {}", estimate, limit)
+            }
+            Self::MessageCost { estimate, limit } => {
+                write!(f, "单条预估花费 {:.4}，上限 {:.4}", estimate, limit)
+            }
+            Self::DailyTokens {
+                used,
+                estimate,
+                limit,
+            } => write!(
+                f,
+                "当日已用 {} tokens，本次预估 {}，上限 {}",
+                used, estimate, limit
+            ),
+            Self::DailyCost {
+                used,
+                estimate,
+                limit,
+            } => write!(
+                f,
+                "当日已花费 {:.4}，本次预估 {:.4}，上限 {:.4}",
+                used, estimate, limit
+            ),
+        }
+    }
+}
+
+/// 检查本次请求是否超出预算；`used` 为当日已用量，未配置该模型单价时不检查花费
+pub fn check_budget(
+    budget: &BudgetConfig,
+    model: &str,
+    estimate: &TokenUsage,
+    used: &TokenUsage,
+) -> Option<BudgetExceeded> {
+    let price = budget.prices.get(model);
+    if let Some(limit) = budget.max_tokens_per_message {
+        if estimate.total() > limit {
+            return Some(BudgetExceeded::MessageTokens {
+                estimate: estimate.total(),
+                limit,
+            });
+        }
+    }
+    if let (Some(limit), Some(price)) = (budget.max_cost_per_message, price) {
+        let estimate = estimate.cost(price);
+        if estimate > limit {
+            return Some(BudgetExceeded::MessageCost { estimate, limit });
+        }
+    }
+    if let Some(limit) = budget.daily_tokens {
+        if used.total() + estimate.total() > limit {
+            return Some(BudgetExceeded::DailyTokens {
+                used: used.total(),
+                estimate: estimate.total(),
+                limit,
+            });
+        }
+    }
+    if let (Some(limit), Some(price)) = (budget.daily_cost, price) {
+        let (used, estimate) = (used.cost(price), estimate.cost(price));
+        if used + estimate > limit {
+            return Some(BudgetExceeded::DailyCost {
+                used,
+                estimate,
+                limit,
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello world!"), 3);
+        assert_eq!(estimate_tokens("你好，世界"), 5);
+        assert_eq!(estimate_tokens("用 Rust 写"), 4);
+    }
+
+    #[test]
+    fn test_usage_from_events() {
+        let call = |app: &str, model: &str, input, output| {
+            OpsEvent::new(
+                app,
+                OpsEventKind::AiCall {
+                    model: model.to_string(),
+                    input_tokens: input,
+                    output_tokens: output,
+                    latency_ms: 0,
+                },
+            )
+        };
+        let events = vec![
+            call("wx_a", "gpt-4o", 100, 20),
+            call("wx_a", "gpt-4o-mini", 1_000, 200),
+            call("wx_b", "gpt-4o", 1_000, 200),
+            OpsEvent::new("wx_a", OpsEventKind::Message),
+            call("wx_a", "gpt-4o", 50, 10),
+        ];
+        assert_eq!(
+            usage_from_events(&events, "wx_a", "gpt-4o"),
+            TokenUsage {
+                input_tokens: 150,
+                output_tokens: 30,
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_budget() {
+        let mut budget = BudgetConfig {
+            max_tokens_per_message: Some(2_000),
+            daily_tokens: Some(10_000),
+            ..Default::default()
+        };
+        let estimate = TokenUsage {
+            input_tokens: 800,
+            output_tokens: 1_024,
+        };
+        let used = TokenUsage {
+            input_tokens: 6_000,
+            output_tokens: 1_000,
+        };
+        assert_eq!(check_budget(&budget, "gpt-4o", &estimate, &used), None);
+
+        let long = TokenUsage {
+            input_tokens: 1_500,
+            ..estimate
+        };
+        let exceeded = check_budget(&budget, "gpt-4o", &long, &used).unwrap();
+        assert_eq!(
+            exceeded,
+            BudgetExceeded::MessageTokens {
+                estimate: 2_524,
+                limit: 2_000,
+            }
+        );
+        assert!(exceeded.notice().contains("太长"));
+
+        let busy = TokenUsage {
+            input_tokens: 8_000,
+            output_tokens: 1_000,
+        };
+        let exceeded = check_budget(&budget, "gpt-4o", &estimate, &busy).unwrap();
+        assert!(matches!(exceeded, BudgetExceeded::DailyTokens { .. }));
+        assert!(exceeded.notice().contains("今天"));
+
+        // 花费上限只对配置了单价的模型生效
+        budget.daily_tokens = None;
+        budget.daily_cost = Some(0.04);
+        budget.prices = BTreeMap::from([(
+            "gpt-4o".to_string(),
+            ModelPrice {
+                input: 2.5,
+                output: 10.0,
+            },
+        )]);
+        assert_eq!(check_budget(&budget, "gpt-4o-mini", &estimate, &busy), None);
+        let exceeded = check_budget(&budget, "gpt-4o", &estimate, &busy).unwrap();
+        assert!(matches!(exceeded, BudgetExceeded::DailyCost { .. }));
+        assert!(exceeded.to_string().contains("上限 0.0400"));
+    }
+}
@@ -7,9 +7,12 @@
 //! - `version`: 获取指定版本
 //! - `range`: 获取版本范围
 //! - `list`: 列出所有版本号
+//!
+//! 输出结构见 [`ChangelogOutput`]，`format: "json"` 时返回结构化 JSON。
 
+use super::output::{OutputFormat, ToolOutput};
 use anyhow::{anyhow, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::sync::OnceLock;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
@@ -19,6 +22,8 @@ const CLAUDE_CHANGELOG_URL: &str =
     "https://raw.githubusercontent.com/anthropics/claude-code/main/CHANGELOG.md";
 const CACHE_TTL: Duration = Duration::from_secs(300);
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+/// [`ChangelogOutput`] 的结构版本，字段不兼容变更时递增
+pub const CHANGELOG_SCHEMA_VERSION: u32 = 1;
 
 /// Changelog 查询参数
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -38,6 +43,9 @@ pub struct ChangelogQuery {
     /// 结束版本（用于 range 模式）
     #[serde(default)]
     pub to: Option<String>,
+    /// 输出格式: text（默认）, json
+    #[serde(default)]
+    pub format: Option<String>,
 }
 
 impl ChangelogQuery {
@@ -55,6 +63,11 @@ impl ChangelogQuery {
     pub fn count(&self) -> usize {
         self.count.unwrap_or(3)
     }
+
+    /// 获取输出格式，默认为文本
+    pub fn format(&self) -> OutputFormat {
+        OutputFormat::parse(self.format.as_deref())
+    }
 }
 
 /// 执行结果
@@ -67,10 +80,36 @@ pub struct ChangelogResult {
 }
 
 /// 单个版本的 Changelog
-#[derive(Debug, Clone)]
-struct ChangelogEntry {
-    version: String,
-    content: String,
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangelogEntry {
+    pub version: String,
+    /// 该版本的 Markdown 原文（含 `## 版本号` 标题）
+    pub content: String,
+}
+
+/// claude_changelog 的输出结构
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangelogOutput {
+    pub schema_version: u32,
+    /// 实际使用的查询模式
+    pub mode: String,
+    /// 命中的版本号，从新到旧
+    pub versions: Vec<String>,
+    /// 命中的版本内容；list 模式下为空
+    pub entries: Vec<ChangelogEntry>,
+}
+
+impl ToolOutput for ChangelogOutput {
+    fn to_text(&self) -> String {
+        if self.mode == "list" {
+            return format!(
+                "共 {} 个版本：\n{}",
+                self.versions.len(),
+                self.versions.join(", ")
+            );
+        }
+        format_entries(&self.entries.iter().collect::<Vec<_>>())
+    }
 }
 
 /// Changelog 缓存
@@ -125,67 +164,65 @@ pub async fn run_claude_changelog(
 /// 执行查询
 async fn execute_query(query: &ChangelogQuery) -> Result<String> {
     let entries = fetch_and_parse().await?;
+    let output = select_entries(&entries, query)?;
+    Ok(output.render(query.format()))
+}
 
-    match query.mode() {
-        "latest" => entries
-            .first()
-            .map(|e| e.content.clone())
-            .ok_or_else(|| anyhow!("没有找到任何版本")),
-        "recent" => {
-            let count = query.count().min(entries.len());
-            let selected: Vec<_> = entries.iter().take(count).collect();
-            if selected.is_empty() {
-                Err(anyhow!("没有找到任何版本"))
-            } else {
-                Ok(format_entries(&selected))
-            }
-        }
+/// 按查询模式挑选版本
+fn select_entries(entries: &[ChangelogEntry], query: &ChangelogQuery) -> Result<ChangelogOutput> {
+    let selected: Vec<&ChangelogEntry> = match query.mode() {
+        "latest" => entries.first().into_iter().collect(),
+        "recent" => entries.iter().take(query.count()).collect(),
         "first" => {
             let count = query.count().min(entries.len());
-            let selected: Vec<_> = entries.iter().rev().take(count).rev().collect();
-            if selected.is_empty() {
-                Err(anyhow!("没有找到任何版本"))
-            } else {
-                Ok(format_entries(&selected))
-            }
+            entries[entries.len() - count..].iter().collect()
         }
         "version" => {
             let ver = query
                 .version
                 .as_deref()
                 .ok_or_else(|| anyhow!("缺少 version 参数"))?;
-            entries
+            let entry = entries
                 .iter()
                 .find(|e| e.version == ver || e.version.ends_with(ver))
-                .map(|e| e.content.clone())
-                .ok_or_else(|| anyhow!("未找到版本: {}", ver))
+                .ok_or_else(|| anyhow!("未找到版本: {}", ver))?;
+            vec![entry]
         }
         "range" => {
-            let from = query.from.as_deref();
-            let to = query.to.as_deref();
-
-            let (start_idx, end_idx) = find_range_indices(&entries, from, to)?;
+            let (start_idx, end_idx) =
+                find_range_indices(entries, query.from.as_deref(), query.to.as_deref())?;
             let selected: Vec<_> = entries[start_idx..=end_idx].iter().collect();
-
             if selected.is_empty() {
-                Err(anyhow!("指定范围内没有版本"))
-            } else {
-                Ok(format_entries(&selected))
+                return Err(anyhow!("指定范围内没有版本"));
             }
+            selected
         }
         "list" => {
-            let versions: Vec<_> = entries.iter().map(|e| e.version.as_str()).collect();
-            Ok(format!(
-                "共 {} 个版本：\n{}",
-                versions.len(),
-                versions.join(", ")
+            return Ok(ChangelogOutput {
+                schema_version: CHANGELOG_SCHEMA_VERSION,
+                mode: "list".to_string(),
+                versions: entries.iter().map(|e| e.version.clone()).collect(),
+                entries: Vec::new(),
+            });
+        }
+        other => {
+            return Err(anyhow!(
+                "未知模式: {}，支持: latest, recent, first, version, range, list",
+                other
             ))
         }
-        other => Err(anyhow!(
-            "未知模式: {}，支持: latest, recent, first, version, range, list",
-            other
-        )),
+    };
+
+    if selected.is_empty() {
+        return Err(anyhow!("没有找到任何版本"));
     }
+
+    Ok(ChangelogOutput {
+        schema_version: CHANGELOG_SCHEMA_VERSION,
+        mode: query.mode().to_string(),
+        versions: selected.iter().map(|e| e.version.clone()).collect(),
+        entries: selected.into_iter().cloned().collect(),
+    })
 }
 
 /// 获取并解析 Changelog
@@ -334,6 +371,7 @@ fn clamp_output(text: String, max: usize) -> (String, bool) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::output::assert_golden;
 
     #[test]
     fn test_parse_query() {
@@ -524,7 +562,7 @@ mod tests {
 
     #[test]
     fn test_format_entries() {
-        let entries = vec![
+        let entries = [
             ChangelogEntry {
                 version: "2.0.55".to_string(),
                 content: "## 2.0.55\n- Feature A".to_string(),
@@ -628,4 +666,56 @@ mod tests {
         // 版本号应包含完整的版本行
         assert!(entries[0].version.contains("2.0.55"));
     }
+
+    const GOLDEN_CHANGELOG: &str = r#"# Changelog
+
+## 2.0.55
+- Feature A
+- Fix B
+
+## 2.0.54
+- Feature C
+
+## 2.0.53
+- Fix D
+"#;
+
+    #[test]
+    fn test_select_entries_modes() {
+        let entries = parse_changelog(GOLDEN_CHANGELOG).unwrap();
+        let select = |json: &str| select_entries(&entries, &ChangelogQuery::from_json(json));
+
+        assert_eq!(select(r#"{}"#).unwrap().versions, vec!["2.0.55"]);
+        assert_eq!(
+            select(r#"{"mode":"first","count":2}"#).unwrap().versions,
+            vec!["2.0.54", "2.0.53"]
+        );
+        assert_eq!(
+            select(r#"{"mode":"version","version":"0.54"}"#)
+                .unwrap()
+                .versions,
+            vec!["2.0.54"]
+        );
+        assert!(select(r#"{"mode":"version"}"#).is_err());
+        assert!(select(r#"{"mode":"unknown"}"#).is_err());
+    }
+
+    #[test]
+    fn test_golden_output() {
+        let entries = parse_changelog(GOLDEN_CHANGELOG).unwrap();
+        let recent = ChangelogQuery::from_json(r#"{"mode":"recent","count":2}"#);
+        let output = select_entries(&entries, &recent).unwrap();
+        assert_golden(
+            "claude_changelog_recent.txt",
+            &output.render(OutputFormat::Text),
+        );
+        assert_golden(
+            "claude_changelog_recent.json",
+            &output.render(OutputFormat::Json),
+        );
+
+        let list = ChangelogQuery::from_json(r#"{"mode":"list","format":"text"}"#);
+        let output = select_entries(&entries, &list).unwrap();
+        assert_golden("claude_changelog_list.txt", &output.render(list.format()));
+    }
 }
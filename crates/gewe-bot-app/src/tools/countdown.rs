@@ -0,0 +1,82 @@
+//! 倒计时播报
+//!
+//! 根据事件日期生成每日“距离 xx 还有 N 天”以及当天的最终公告
+
+use chrono::NaiveDate;
+
+use crate::config::CountdownConfig;
+
+const DEFAULT_DAILY_TEMPLATE: &str = "距离「{name}」还有 {days} 天";
+const DEFAULT_FINAL_TEMPLATE: &str = "今天就是「{name}」！";
+
+/// 生成某天的播报文本；事件已过或时间无法解析时返回 None
+pub fn render_countdown(countdown: &CountdownConfig, today: NaiveDate) -> Option<String> {
+    let days = (countdown.event_time()?.date() - today).num_days();
+    let template = match days {
+        d if d > 0 => non_empty(&countdown.message).unwrap_or(DEFAULT_DAILY_TEMPLATE),
+        0 => non_empty(&countdown.final_message).unwrap_or(DEFAULT_FINAL_TEMPLATE),
+        _ => return None,
+    };
+    Some(
+        template
+            .replace("{name}", &countdown.name)
+            .replace("{days}", &days.to_string()),
+    )
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|s| !s.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn countdown(at: &str) -> CountdownConfig {
+        CountdownConfig {
+            id: "launch".to_string(),
+            name: "新版本发布".to_string(),
+            at: at.to_string(),
+            app_id: "wx_app".to_string(),
+            targets: vec!["123@chatroom".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_render_countdown() {
+        let c = countdown("2026-10-20 14:00");
+        assert_eq!(
+            render_countdown(&c, date("2026-10-15")).as_deref(),
+            Some("距离「新版本发布」还有 5 天")
+        );
+        assert_eq!(
+            render_countdown(&c, date("2026-10-20")).as_deref(),
+            Some("今天就是「新版本发布」！")
+        );
+        assert_eq!(render_countdown(&c, date("2026-10-21")), None);
+        assert_eq!(
+            render_countdown(&countdown("下周"), date("2026-10-15")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render_countdown_custom_templates() {
+        let mut c = countdown("2026-10-16");
+        c.message = Some("还剩 {days} 天：{name}".to_string());
+        c.final_message = Some("{name} 正式上线".to_string());
+        assert_eq!(
+            render_countdown(&c, date("2026-10-15")).as_deref(),
+            Some("还剩 1 天：新版本发布")
+        );
+        assert_eq!(
+            render_countdown(&c, date("2026-10-16")).as_deref(),
+            Some("新版本发布 正式上线")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! 文档摘要工具
+//!
+//! 从收到的 PDF/DOCX 文件中提取文字，按段落切分为适合模型上下文的分段，
+//! 并构建分段摘要与汇总摘要的提示词；模型调用由调度器完成。
+
+#[cfg(feature = "tools")]
+use super::{docx_text, pdf_text};
+use anyhow::{anyhow, Result};
+
+/// 支持摘要的文件扩展名
+pub const SUPPORTED_DOCUMENT_EXTS: &[&str] = &["pdf", "docx"];
+
+/// 摘要请求默认的 system prompt
+pub const DEFAULT_SUMMARY_SYSTEM_PROMPT: &str =
+    "你是文档摘要助手。请使用与文档相同的语言，忠实概括文档内容，不要编造文档中没有的信息。";
+
+/// 是否为支持摘要的文件扩展名（不区分大小写）
+pub fn is_supported_document(ext: &str) -> bool {
+    SUPPORTED_DOCUMENT_EXTS
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(ext.trim().trim_start_matches('.')))
+}
+
+/// 按扩展名提取文件中的文字
+pub fn extract_document_text(ext: &str, data: &[u8]) -> Result<String> {
+    match ext
+        .trim()
+        .trim_start_matches('.')
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        #[cfg(feature = "tools")]
+        "pdf" => pdf_text::extract_pdf_text(data),
+        #[cfg(feature = "tools")]
+        "docx" => docx_text::extract_docx_text(data),
+        #[cfg(not(feature = "tools"))]
+        "pdf" | "docx" => {
+            let _ = data;
+            Err(anyhow!("文档解析未启用，请使用 --features tools 重新编译"))
+        }
+        other => Err(anyhow!("不支持的文件类型: {}", other)),
+    }
+}
+
+/// 按段落把文本切分为不超过 `max_chars` 个字符的分段，超长段落按字符硬切
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let len = line.chars().count();
+        if current_len > 0 && current_len + 1 + len > max_chars {
+            chunks.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        if len > max_chars {
+            let chars: Vec<char> = line.chars().collect();
+            for piece in chars.chunks(max_chars) {
+                if current_len > 0 {
+                    chunks.push(std::mem::take(&mut current));
+                }
+                current = piece.iter().collect();
+                current_len = piece.len();
+            }
+            continue;
+        }
+        if current_len > 0 {
+            current.push('\n');
+            current_len += 1;
+        }
+        current.push_str(line);
+        current_len += len;
+    }
+    if current_len > 0 {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// 长文档的分段摘要提示词
+pub fn chunk_summary_prompt(name: &str, index: usize, total: usize, chunk: &str) -> String {
+    format!(
+        "以下是文件《{}》的第 {}/{} 部分。请用不超过 300 字提炼这一部分的主要内容，保留章节标题、关键数字与结论：\n\n{}",
+        name, index, total, chunk
+    )
+}
+
+/// 最终摘要提示词：`content` 为全文（单段）或各部分摘要（`partial` 为 true）
+pub fn final_summary_prompt(
+    name: &str,
+    content: &str,
+    partial: bool,
+    sections: bool,
+    extra: Option<&str>,
+) -> String {
+    let mut prompt = if partial {
+        format!("以下是文件《{}》各部分的摘要，请整合为一份全文摘要。", name)
+    } else {
+        format!("请为文件《{}》写一份摘要。", name)
+    };
+    prompt.push_str("先用一段话（不超过 200 字）概括全文");
+    if sections {
+        prompt.push_str(
+            "，然后按文档的章节逐节列出要点：每节一行章节名，其后 1-3 条以「- 」开头的要点",
+        );
+    }
+    prompt.push_str("。不要使用 Markdown 标题。");
+    if let Some(extra) = extra.map(str::trim).filter(|s| !s.is_empty()) {
+        prompt.push_str("\n\n额外要求：");
+        prompt.push_str(extra);
+    }
+    prompt.push_str("\n\n");
+    prompt.push_str(content);
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_supported_document() {
+        assert!(is_supported_document("pdf"));
+        assert!(is_supported_document(".DOCX"));
+        assert!(!is_supported_document("doc"));
+        assert!(!is_supported_document("zip"));
+    }
+
+    #[test]
+    fn test_extract_document_text_rejects_unknown() {
+        assert!(extract_document_text("txt", b"hello").is_err());
+        assert!(extract_document_text("pdf", b"not a pdf").is_err());
+        assert!(extract_document_text("docx", b"not a zip").is_err());
+    }
+
+    #[test]
+    fn test_chunk_text() {
+        let text = "第一章\n\n甲乙丙丁\n戊己庚辛\n第二章";
+        assert_eq!(
+            chunk_text(text, 9),
+            vec!["第一章\n甲乙丙丁", "戊己庚辛\n第二章"]
+        );
+        assert_eq!(
+            chunk_text(text, 100),
+            vec!["第一章\n甲乙丙丁\n戊己庚辛\n第二章"]
+        );
+
+        // 超长段落按字符切分，不截断多字节字符
+        assert_eq!(
+            chunk_text("前言\n一二三四五", 2),
+            vec!["前言", "一二", "三四", "五"]
+        );
+        assert!(chunk_text("\n\n", 10).is_empty());
+    }
+
+    #[test]
+    fn test_summary_prompts() {
+        let prompt = chunk_summary_prompt("周报.pdf", 2, 3, "正文");
+        assert!(prompt.contains("《周报.pdf》的第 2/3 部分"));
+        assert!(prompt.ends_with("正文"));
+
+        let prompt = final_summary_prompt("周报.pdf", "全文", false, false, None);
+        assert!(prompt.starts_with("请为文件《周报.pdf》写一份摘要"));
+        assert!(!prompt.contains("章节"));
+        assert!(prompt.ends_with("全文"));
+
+        let prompt = final_summary_prompt("周报.pdf", "分段摘要", true, true, Some("关注金额"));
+        assert!(prompt.contains("各部分的摘要"));
+        assert!(prompt.contains("按文档的章节逐节列出要点"));
+        assert!(prompt.contains("额外要求：关注金额"));
+    }
+}
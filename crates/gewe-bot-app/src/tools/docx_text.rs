@@ -0,0 +1,203 @@
+//! DOCX 文字提取
+//!
+//! 从 ZIP 容器中读取 `word/document.xml`，按段落还原纯文本，标题段落以 `#` 标记，
+//! 便于模型按章节归纳要点。
+
+use anyhow::{anyhow, Result};
+use std::io::{Cursor, Read};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// document.xml 解压后的最大字节数，防止压缩炸弹
+const MAX_XML_BYTES: u64 = 64 * 1024 * 1024;
+const DOCUMENT_XML: &str = "word/document.xml";
+
+/// 提取 DOCX 正文文字，段落之间以换行分隔
+pub(super) fn extract_docx_text(data: &[u8]) -> Result<String> {
+    let xml = read_zip_entry(data, DOCUMENT_XML)?;
+    Ok(document_text(&String::from_utf8_lossy(&xml)))
+}
+
+/// 解压 ZIP 中的单个文件
+fn read_zip_entry(data: &[u8], name: &str) -> Result<Vec<u8>> {
+    let mut archive =
+        ZipArchive::new(Cursor::new(data)).map_err(|_| anyhow!("不是有效的 DOCX 文件"))?;
+    let entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Err(anyhow!("不是有效的 DOCX 文件：缺少 {}", name)),
+        Err(e) => return Err(anyhow!("读取 {} 失败: {}", name, e)),
+    };
+    let mut out = Vec::new();
+    entry
+        .take(MAX_XML_BYTES)
+        .read_to_end(&mut out)
+        .map_err(|e| anyhow!("解压 {} 失败: {}", name, e))?;
+    Ok(out)
+}
+
+/// 将 document.xml 转为纯文本
+fn document_text(xml: &str) -> String {
+    let mut out = String::new();
+    let mut para = String::new();
+    let mut heading: Option<usize> = None;
+    let mut in_text = false;
+    let mut rest = xml;
+
+    while let Some(lt) = rest.find('<') {
+        if in_text {
+            para.push_str(&decode_entities(&rest[..lt]));
+        }
+        let Some(gt) = rest[lt..].find('>') else {
+            break;
+        };
+        let tag = &rest[lt + 1..lt + gt];
+        rest = &rest[lt + gt + 1..];
+
+        if let Some(closing) = tag.strip_prefix('/') {
+            match closing.trim() {
+                "w:t" => in_text = false,
+                "w:p" => {
+                    let text = para.trim();
+                    if !text.is_empty() {
+                        if let Some(level) = heading {
+                            out.push_str(&"#".repeat(level));
+                            out.push(' ');
+                        }
+                        out.push_str(text);
+                        out.push('\n');
+                    }
+                    para.clear();
+                    heading = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        match name {
+            "w:t" => in_text = !tag.ends_with('/'),
+            "w:tab" => para.push('\t'),
+            "w:br" | "w:cr" => para.push('\n'),
+            "w:pStyle" => heading = attr_value(tag, "w:val").and_then(heading_level),
+            _ => {}
+        }
+    }
+    out.trim_end().to_string()
+}
+
+fn attr_value<'a>(tag: &'a str, attr: &str) -> Option<&'a str> {
+    let needle = format!("{}=\"", attr);
+    let start = tag.find(&needle)? + needle.len();
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+/// 内置标题样式（Heading1…Heading9、Title）对应的标题级别
+fn heading_level(style: &str) -> Option<usize> {
+    let style = style.to_ascii_lowercase();
+    if style == "title" {
+        return Some(1);
+    }
+    let level = style.strip_prefix("heading")?;
+    Some(level.trim().parse().unwrap_or(1).clamp(1, 6))
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';').filter(|i| *i <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_extract_docx_text() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+<w:p><w:pPr><w:pStyle w:val="Title"/></w:pPr><w:r><w:t>季度报告</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>一、收入</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">收入 </w:t></w:r><w:r><w:t>&lt;100&gt; 万 &amp; 增长</w:t></w:r><w:r><w:tab/><w:t>10%</w:t></w:r></w:p>
+<w:p/>
+<w:p><w:r><w:t>R&amp;D</w:t><w:br/><w:t>&#x4E0B;一行</w:t></w:r></w:p>
+</w:body></w:document>"#;
+        let data = zip(&[
+            ("[Content_Types].xml", b"<Types/>"),
+            ("word/document.xml", xml.as_bytes()),
+        ]);
+        assert_eq!(
+            extract_docx_text(&data).unwrap(),
+            "# 季度报告\n## 一、收入\n收入 <100> 万 & 增长\t10%\nR&D\n下一行"
+        );
+    }
+
+    #[test]
+    fn test_extract_docx_missing_document() {
+        let data = zip(&[("word/styles.xml", b"<w:styles/>")]);
+        let err = extract_docx_text(&data).unwrap_err();
+        assert!(err.to_string().contains("word/document.xml"));
+        assert!(extract_docx_text(b"%PDF-1.7").is_err());
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &#20320;&#x597D; &unknown; &"),
+            "a & b 你好 &unknown; &"
+        );
+    }
+}
@@ -1,15 +1,32 @@
 //! 通用 HTTP 请求工具
 //!
-//! 支持自定义 method/url/headers/query/body，自动格式化 JSON 响应
+//! 支持自定义 method/url/headers/query/body，自动格式化 JSON 响应。
+//! 输出结构见 [`HttpResponseOutput`]，`format: "json"` 时返回结构化 JSON。
+//!
+//! 请求受 [`HttpPolicy`] 约束：主机白名单/黑名单、默认禁止内网与云元数据地址
+//! （解析后固定 IP，防止 DNS 重绑定；不跟随重定向）、响应大小与 Content-Type 限制，
+//! 认证 header 只能通过 `auth` 引用策略中的认证配置，值从环境变量读取。
 
+use super::output::{OutputFormat, ToolOutput};
+use crate::config::HttpPolicy;
 use anyhow::{anyhow, Result};
-use reqwest::{header, Method};
-use serde::Deserialize;
+use reqwest::{header, Method, Url};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::time::{Duration, Instant};
 use tokio::time;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+/// [`HttpResponseOutput`] 的结构版本，字段不兼容变更时递增
+pub const HTTP_REQUEST_SCHEMA_VERSION: u32 = 1;
+/// 不允许由模型直接设置的认证类 header
+const SENSITIVE_HEADERS: &[header::HeaderName] = &[
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+];
 
 /// HTTP 请求参数
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -23,7 +40,7 @@ pub struct HttpRequestQuery {
     /// 查询参数，将附加到 URL 上
     #[serde(default)]
     pub query: Option<HashMap<String, String>>,
-    /// 请求头
+    /// 请求头；认证类 header（Authorization、Cookie 等）会被拒绝，需通过 `auth` 引用认证配置
     #[serde(default)]
     pub headers: Option<HashMap<String, String>>,
     /// JSON 请求体（优先级低于 body_text）
@@ -35,6 +52,12 @@ pub struct HttpRequestQuery {
     /// 是否将响应按 JSON 格式化输出（未指定时按 content-type 自动判断）
     #[serde(default)]
     pub expect_json: Option<bool>,
+    /// 工具输出格式: text（默认）, json
+    #[serde(default)]
+    pub format: Option<String>,
+    /// 使用的认证配置 id（见 HttpPolicy.auth_profiles）
+    #[serde(default)]
+    pub auth: Option<String>,
 }
 
 impl HttpRequestQuery {
@@ -51,6 +74,10 @@ impl HttpRequestQuery {
         Method::from_bytes(m.as_bytes()).map_err(|_| anyhow!("不支持的 HTTP 方法: {}", m))
     }
 
+    pub fn format(&self) -> OutputFormat {
+        OutputFormat::parse(self.format.as_deref())
+    }
+
     fn expect_json(&self, content_type: Option<&str>) -> bool {
         self.expect_json.unwrap_or_else(|| {
             content_type
@@ -63,6 +90,81 @@ impl HttpRequestQuery {
     }
 }
 
+/// http_request 的输出结构
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HttpResponseOutput {
+    pub schema_version: u32,
+    pub url: String,
+    pub method: String,
+    pub status: u16,
+    pub status_text: String,
+    pub content_type: Option<String>,
+    /// 响应体原文
+    pub body: String,
+    /// 按 JSON 解析成功时的响应体
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json: Option<serde_json::Value>,
+    /// 响应体超过 max_response_bytes 被截断
+    pub body_truncated: bool,
+}
+
+impl HttpResponseOutput {
+    fn new(
+        url: &str,
+        method: &Method,
+        status: reqwest::StatusCode,
+        content_type: Option<String>,
+        body: String,
+        expect_json: bool,
+    ) -> Self {
+        let json = if expect_json {
+            serde_json::from_str(&body).ok()
+        } else {
+            None
+        };
+        Self {
+            schema_version: HTTP_REQUEST_SCHEMA_VERSION,
+            url: url.to_string(),
+            method: method.to_string(),
+            status: status.as_u16(),
+            status_text: status.canonical_reason().unwrap_or("Unknown").to_string(),
+            content_type,
+            body,
+            json,
+            body_truncated: false,
+        }
+    }
+}
+
+impl ToolOutput for HttpResponseOutput {
+    fn to_text(&self) -> String {
+        let formatted_body = self
+            .json
+            .as_ref()
+            .and_then(|v| serde_json::to_string_pretty(v).ok())
+            .unwrap_or_else(|| self.body.clone());
+
+        let mut lines = Vec::new();
+        lines.push("HTTP 请求结果：".to_string());
+        lines.push(String::new());
+        lines.push(format!("URL: {}", self.url));
+        lines.push(format!("Method: {}", self.method));
+        lines.push(format!("状态: {} {}", self.status, self.status_text));
+        if let Some(ref ct) = self.content_type {
+            lines.push(format!("Content-Type: {}", ct));
+        }
+        lines.push(String::new());
+        lines.push("响应体:".to_string());
+        lines.push(formatted_body);
+        if self.body_truncated {
+            lines.push(String::new());
+            lines.push("[响应体超过大小上限，已截断]".to_string());
+        }
+
+        lines.join("\n")
+    }
+}
+
 /// 执行结果
 pub struct HttpRequestResult {
     pub content: String,
@@ -75,6 +177,7 @@ pub struct HttpRequestResult {
 /// 执行 HTTP 请求
 pub async fn run_http_request(
     query: HttpRequestQuery,
+    policy: &HttpPolicy,
     timeout_secs: Option<u64>,
     max_output: usize,
 ) -> HttpRequestResult {
@@ -83,7 +186,7 @@ pub async fn run_http_request(
         .unwrap_or(DEFAULT_TIMEOUT);
     let start = Instant::now();
 
-    match time::timeout(timeout, execute_request(&query)).await {
+    match time::timeout(timeout, execute_request(&query, policy)).await {
         Ok(Ok(content)) => {
             let (text, truncated) = clamp_output(content, max_output);
             HttpRequestResult {
@@ -112,29 +215,35 @@ pub async fn run_http_request(
 }
 
 /// 执行请求并格式化输出
-async fn execute_request(query: &HttpRequestQuery) -> Result<String> {
+async fn execute_request(query: &HttpRequestQuery, policy: &HttpPolicy) -> Result<String> {
     let url = query.url()?;
     let method = query.method()?;
 
-    let client = reqwest::Client::builder()
+    let parsed = Url::parse(url).map_err(|e| anyhow!("无效 URL: {e}"))?;
+    let host = target_host(&parsed)?;
+    check_host_lists(&host, policy)?;
+    let addrs = resolve_addrs(&parsed, &host, policy.allow_private.unwrap_or(false)).await?;
+
+    let client = pinned_client(&host, &addrs)
         .build()
         .map_err(|e| anyhow!("创建 HTTP 客户端失败: {e}"))?;
 
-    let mut builder = client.request(method.clone(), url);
+    let mut builder = client.request(method.clone(), parsed);
 
     if let Some(ref params) = query.query {
         builder = builder.query(params);
     }
 
-    if let Some(ref headers) = query.headers {
-        let mut header_map = header::HeaderMap::new();
-        for (k, v) in headers {
-            let name = header::HeaderName::from_bytes(k.as_bytes())
-                .map_err(|_| anyhow!("无效 header 名称: {}", k))?;
-            let value =
-                header::HeaderValue::from_str(v).map_err(|_| anyhow!("无效 header 值: {}", k))?;
+    let mut header_map = match query.headers {
+        Some(ref headers) => request_headers(headers)?,
+        None => header::HeaderMap::new(),
+    };
+    if let Some(ref auth) = query.auth {
+        for (name, value) in auth_headers(policy, auth, &host)? {
             header_map.insert(name, value);
         }
+    }
+    if !header_map.is_empty() {
         builder = builder.headers(header_map);
     }
 
@@ -144,7 +253,7 @@ async fn execute_request(query: &HttpRequestQuery) -> Result<String> {
         builder = builder.json(body);
     }
 
-    let resp = builder.send().await.map_err(|e| anyhow!("请求失败: {e}"))?;
+    let mut resp = builder.send().await.map_err(|e| anyhow!("请求失败: {e}"))?;
 
     let status = resp.status();
     let content_type = resp
@@ -152,39 +261,221 @@ async fn execute_request(query: &HttpRequestQuery) -> Result<String> {
         .get(header::CONTENT_TYPE)
         .and_then(|v| v.to_str().ok())
         .map(|v| v.to_string());
-
-    let raw_body = resp
-        .text()
+    check_content_type(content_type.as_deref(), policy)?;
+
+    let limit = policy
+        .max_response_bytes
+        .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
+    let mut bytes = Vec::new();
+    let mut body_truncated = false;
+    while let Some(chunk) = resp
+        .chunk()
         .await
-        .map_err(|e| anyhow!("读取响应失败: {e}"))?;
+        .map_err(|e| anyhow!("读取响应失败: {e}"))?
+    {
+        let remaining = limit - bytes.len();
+        if chunk.len() > remaining {
+            bytes.extend_from_slice(&chunk[..remaining]);
+            body_truncated = true;
+            break;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let raw_body = String::from_utf8_lossy(&bytes).into_owned();
 
-    let formatted_body = if query.expect_json(content_type.as_deref()) {
-        match serde_json::from_str::<serde_json::Value>(&raw_body) {
-            Ok(val) => serde_json::to_string_pretty(&val).unwrap_or(raw_body),
-            Err(_) => raw_body,
+    let expect_json = query.expect_json(content_type.as_deref()) && !body_truncated;
+    let mut output =
+        HttpResponseOutput::new(url, &method, status, content_type, raw_body, expect_json);
+    output.body_truncated = body_truncated;
+    Ok(output.render(query.format()))
+}
+
+/// 转换模型传入的请求头，拒绝认证类 header，避免模型绕过认证配置携带凭据
+fn request_headers(headers: &HashMap<String, String>) -> Result<header::HeaderMap> {
+    let mut header_map = header::HeaderMap::new();
+    for (k, v) in headers {
+        let name = header::HeaderName::from_bytes(k.as_bytes())
+            .map_err(|_| anyhow!("无效 header 名称: {}", k))?;
+        if SENSITIVE_HEADERS.contains(&name) {
+            return Err(anyhow!(
+                "header {} 不能直接设置，请通过 auth 引用认证配置",
+                k
+            ));
         }
+        let value =
+            header::HeaderValue::from_str(v).map_err(|_| anyhow!("无效 header 值: {}", k))?;
+        header_map.insert(name, value);
+    }
+    Ok(header_map)
+}
+
+/// 主机是否匹配模式："example.com" 仅精确匹配，"*.example.com" 仅匹配其子域名。
+///
+/// HTTP 工具的主机名单、认证配置与规则的 url_domains 共用此语义。
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim().trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .strip_suffix(suffix)
+            .is_some_and(|prefix| prefix.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+fn check_host_lists(host: &str, policy: &HttpPolicy) -> Result<()> {
+    if policy.deny_hosts.iter().any(|p| host_matches(p, host)) {
+        return Err(anyhow!("主机 {} 在禁止访问列表中", host));
+    }
+    if !policy.allow_hosts.is_empty() && !policy.allow_hosts.iter().any(|p| host_matches(p, host)) {
+        return Err(anyhow!("主机 {} 不在允许访问列表中", host));
+    }
+    Ok(())
+}
+
+/// 校验协议并取出小写主机名（IPv6 去掉方括号）
+pub(crate) fn target_host(url: &Url) -> Result<String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!("仅支持 http/https 协议"));
+    }
+    Ok(url
+        .host_str()
+        .ok_or_else(|| anyhow!("URL 缺少主机"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase())
+}
+
+/// 不跟随重定向，并固定为已校验的地址，防止跳转或 DNS 重绑定到内网
+pub(crate) fn pinned_client(host: &str, addrs: &[SocketAddr]) -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none());
+    if host.parse::<IpAddr>().is_err() {
+        builder.resolve_to_addrs(host, addrs)
     } else {
-        raw_body
+        builder
+    }
+}
+
+/// 对公网地址发起 GET：校验协议、拒绝内网地址并固定解析结果，不跟随重定向
+pub async fn guarded_get(url: &Url, timeout: Duration) -> Result<reqwest::RequestBuilder> {
+    let host = target_host(url)?;
+    let addrs = resolve_addrs(url, &host, false).await?;
+    let client = pinned_client(&host, &addrs)
+        .timeout(timeout)
+        .build()
+        .map_err(|e| anyhow!("创建 HTTP 客户端失败: {e}"))?;
+    Ok(client.get(url.clone()))
+}
+
+/// 解析目标地址并拒绝内网地址（除非 `allow_private`）
+pub(crate) async fn resolve_addrs(
+    url: &Url,
+    host: &str,
+    allow_private: bool,
+) -> Result<Vec<SocketAddr>> {
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("无法确定端口"))?;
+    let addrs: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| anyhow!("解析主机 {} 失败: {e}", host))?
+            .collect(),
     };
+    if addrs.is_empty() {
+        return Err(anyhow!("解析主机 {} 失败: 无可用地址", host));
+    }
+    if !allow_private {
+        if let Some(addr) = addrs.iter().find(|a| is_restricted_ip(a.ip())) {
+            return Err(anyhow!(
+                "禁止访问内网或元数据地址: {} ({})",
+                host,
+                addr.ip()
+            ));
+        }
+    }
+    Ok(addrs)
+}
 
-    let mut lines = Vec::new();
-    lines.push("HTTP 请求结果：".to_string());
-    lines.push(String::new());
-    lines.push(format!("URL: {}", url));
-    lines.push(format!("Method: {}", method));
-    lines.push(format!(
-        "状态: {} {}",
-        status.as_u16(),
-        status.canonical_reason().unwrap_or("Unknown")
-    ));
-    if let Some(ct) = content_type {
-        lines.push(format!("Content-Type: {}", ct));
-    }
-    lines.push(String::new());
-    lines.push("响应体:".to_string());
-    lines.push(formatted_body);
-
-    Ok(lines.join("\n"))
+/// 内网、回环、链路本地（含 169.254.169.254 元数据）、CGNAT（含 100.100.100.200 元数据）等非公网地址
+fn is_restricted_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_restricted_ipv4(v4),
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_restricted_ipv4(v4);
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // fc00::/7 唯一本地地址（含 fd00:ec2::254）
+                || (first & 0xffc0) == 0xfe80 // fe80::/10 链路本地
+        }
+    }
+}
+
+fn is_restricted_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        || (a == 100 && (b & 0xc0) == 64) // 100.64.0.0/10
+        || (a == 198 && (b & 0xfe) == 18) // 198.18.0.0/15
+}
+
+/// 按认证配置生成 header，值从环境变量读取
+fn auth_headers(
+    policy: &HttpPolicy,
+    id: &str,
+    host: &str,
+) -> Result<Vec<(header::HeaderName, header::HeaderValue)>> {
+    let profile = policy
+        .auth_profiles
+        .iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| anyhow!("未知的认证配置: {}", id))?;
+    if !profile.hosts.is_empty() && !profile.hosts.iter().any(|p| host_matches(p, host)) {
+        return Err(anyhow!("认证配置 {} 不允许用于主机 {}", id, host));
+    }
+    profile
+        .headers_env
+        .iter()
+        .map(|(name, env)| {
+            let value =
+                std::env::var(env).map_err(|_| anyhow!("认证配置 {} 缺少环境变量 {}", id, env))?;
+            let name = header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| anyhow!("无效 header 名称: {}", name))?;
+            let mut value = header::HeaderValue::from_str(&value)
+                .map_err(|_| anyhow!("环境变量 {} 不是有效的 header 值", env))?;
+            value.set_sensitive(true);
+            Ok((name, value))
+        })
+        .collect()
+}
+
+fn check_content_type(content_type: Option<&str>, policy: &HttpPolicy) -> Result<()> {
+    if policy.allowed_content_types.is_empty() {
+        return Ok(());
+    }
+    let ct = content_type.unwrap_or_default().to_ascii_lowercase();
+    if policy
+        .allowed_content_types
+        .iter()
+        .any(|allowed| ct.starts_with(&allowed.trim().to_ascii_lowercase()))
+    {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "不允许的响应类型: {}",
+            content_type.unwrap_or("未知")
+        ))
+    }
 }
 
 fn clamp_output(text: String, max: usize) -> (String, bool) {
@@ -203,6 +494,7 @@ fn clamp_output(text: String, max: usize) -> (String, bool) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::output::assert_golden;
 
     #[test]
     fn test_http_request_query_from_json() {
@@ -389,4 +681,143 @@ mod tests {
         assert!(query.expect_json(Some("Application/JSON")));
         assert!(query.expect_json(Some("APPLICATION/JSON")));
     }
+
+    #[test]
+    fn test_golden_output() {
+        let output = HttpResponseOutput::new(
+            "https://api.example.com/items?page=1",
+            &Method::GET,
+            reqwest::StatusCode::OK,
+            Some("application/json; charset=utf-8".to_string()),
+            r#"{"items":[{"id":1,"name":"苹果"}],"total":1}"#.to_string(),
+            true,
+        );
+        assert_golden("http_request.txt", &output.render(OutputFormat::Text));
+        assert_golden("http_request.json", &output.render(OutputFormat::Json));
+
+        let output = HttpResponseOutput::new(
+            "https://example.com/",
+            &Method::POST,
+            reqwest::StatusCode::NOT_FOUND,
+            Some("text/html".to_string()),
+            "<h1>Not Found</h1>".to_string(),
+            false,
+        );
+        assert_golden("http_request_text.txt", &output.render(OutputFormat::Text));
+    }
+
+    #[test]
+    fn test_is_restricted_ip() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(is_restricted_ip(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(!is_restricted_ip(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_host_lists() {
+        assert!(host_matches("*.example.com", "api.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+        assert!(host_matches("API.example.com", "api.example.com"));
+        assert!(host_matches("example.com.", "example.com"));
+        assert!(!host_matches("example.com", "www.example.com"));
+
+        let policy = HttpPolicy {
+            allow_hosts: vec!["*.example.com".to_string()],
+            deny_hosts: vec!["admin.example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(check_host_lists("api.example.com", &policy).is_ok());
+        assert!(check_host_lists("admin.example.com", &policy).is_err());
+        assert!(check_host_lists("other.com", &policy).is_err());
+        assert!(check_host_lists("other.com", &HttpPolicy::default()).is_ok());
+    }
+
+    #[test]
+    fn test_request_headers_rejects_credentials() {
+        let mut headers = HashMap::new();
+        headers.insert("X-Trace".to_string(), "1".to_string());
+        assert_eq!(request_headers(&headers).unwrap()["x-trace"], "1");
+
+        for name in ["Authorization", "proxy-authorization", "COOKIE"] {
+            let mut headers = headers.clone();
+            headers.insert(name.to_string(), "secret".to_string());
+            let err = request_headers(&headers).unwrap_err();
+            assert!(err.to_string().contains("auth"), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_auth_headers() {
+        std::env::set_var("GEWE_TEST_HTTP_AUTH_TOKEN", "Bearer secret");
+        let policy: HttpPolicy = toml::from_str(
+            r#"
+[[auth_profiles]]
+id = "api"
+hosts = ["api.example.com"]
+headers_env = { Authorization = "GEWE_TEST_HTTP_AUTH_TOKEN" }
+
+[[auth_profiles]]
+id = "missing"
+headers_env = { "X-Key" = "GEWE_TEST_HTTP_AUTH_MISSING" }
+"#,
+        )
+        .unwrap();
+        let headers = auth_headers(&policy, "api", "api.example.com").unwrap();
+        assert_eq!(headers[0].0, header::AUTHORIZATION);
+        assert_eq!(headers[0].1, "Bearer secret");
+        assert!(auth_headers(&policy, "api", "evil.com").is_err());
+        assert!(auth_headers(&policy, "unknown", "api.example.com").is_err());
+        assert!(auth_headers(&policy, "missing", "api.example.com").is_err());
+    }
+
+    #[test]
+    fn test_check_content_type() {
+        let policy = HttpPolicy {
+            allowed_content_types: vec!["application/json".to_string()],
+            ..Default::default()
+        };
+        assert!(check_content_type(Some("application/json; charset=utf-8"), &policy).is_ok());
+        assert!(check_content_type(Some("text/html"), &policy).is_err());
+        assert!(check_content_type(None, &policy).is_err());
+        assert!(check_content_type(None, &HttpPolicy::default()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_private_and_non_http() {
+        let policy = HttpPolicy::default();
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://127.0.0.1:8080/",
+            "http://[::1]/",
+            "file:///etc/passwd",
+        ] {
+            let query = HttpRequestQuery {
+                url: Some(url.to_string()),
+                ..Default::default()
+            };
+            let result = run_http_request(query, &policy, Some(1), 1000).await;
+            assert!(result.error.is_some(), "{url}");
+            let parsed = Url::parse(url).unwrap();
+            assert!(
+                guarded_get(&parsed, DEFAULT_TIMEOUT).await.is_err(),
+                "{url}"
+            );
+        }
+    }
 }
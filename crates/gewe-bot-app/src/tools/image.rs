@@ -0,0 +1,563 @@
+//! 图像生成工具
+//!
+//! 通过 [`ImageProvider`] 抽象不同的图像服务商（Gemini、OpenAI Images、Stability/SDXL），
+//! 由工具配置中的 `image.provider` 选择；生成的图片统一保存到本地并返回访问 URL。
+//! 配置中带有原图（收到或引用的图片）时，服务商改为图生图/图片编辑。
+
+use super::gemini_image::GeminiImageProvider;
+use super::openai_image::OpenAiImageProvider;
+use super::stability_image::StabilityImageProvider;
+use crate::config::{ImageProviderKind, ImageToolConfig};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tokio::time;
+use uuid::Uuid;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 图像生成查询参数（模型调用时传入，优先于工具配置中的默认值）
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImageQuery {
+    /// 图像描述 prompt
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// 模型名称，未指定时使用配置或服务商默认模型
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Gemini 图像宽高比: 1:1, 2:3, 3:2, 3:4, 4:3, 9:16, 16:9, 21:9
+    #[serde(default)]
+    pub aspect_ratio: Option<String>,
+    /// Gemini 图像尺寸: 1K, 2K, 4K
+    #[serde(default)]
+    pub image_size: Option<String>,
+    /// 是否启用 Google 搜索（仅 Gemini，让模型先搜索信息再生成图片）
+    #[serde(default)]
+    pub google_search: Option<bool>,
+    /// OpenAI / Stability 像素尺寸，如 1024x1024
+    #[serde(default)]
+    pub size: Option<String>,
+    /// OpenAI 图像质量
+    #[serde(default)]
+    pub quality: Option<String>,
+}
+
+impl ImageQuery {
+    pub fn from_json(json: &str) -> Self {
+        serde_json::from_str(json).unwrap_or_default()
+    }
+
+    pub fn prompt(&self) -> &str {
+        self.prompt.as_deref().unwrap_or("")
+    }
+
+    /// 查询参数 > 工具配置 > 服务商默认模型
+    pub fn model_or<'a>(&'a self, settings: &'a ImageToolConfig, default: &'a str) -> &'a str {
+        self.model
+            .as_deref()
+            .or(settings.model.as_deref())
+            .unwrap_or(default)
+    }
+}
+
+/// 图像生成配置
+#[derive(Debug, Clone, Default)]
+pub struct ImageConfig {
+    /// 服务商 API Key
+    pub api_key: String,
+    /// 基础 API URL（可选，用于代理或兼容端点）
+    pub base_url: Option<String>,
+    /// 服务商与默认参数
+    pub settings: ImageToolConfig,
+    /// 待编辑的原图（收到或引用的图片），存在时按 prompt 编辑该图
+    pub base_image: Option<ImageData>,
+    /// 图片存储目录
+    pub image_dir: String,
+    /// 图片 URL 前缀
+    pub image_url_prefix: String,
+    /// 外部访问基础 URL
+    pub external_base_url: Option<String>,
+}
+
+impl ImageConfig {
+    /// 基础 URL，未配置时使用服务商默认地址
+    pub(super) fn base_url_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.base_url
+            .as_deref()
+            .unwrap_or(default)
+            .trim_end_matches('/')
+    }
+}
+
+/// 一张图片：服务商返回的结果或待编辑的原图
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageData {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// 服务商返回的生成结果
+#[derive(Debug, Default)]
+pub struct GeneratedImages {
+    /// 附带的文字说明（如 Gemini 的文本回复、OpenAI 的 revised_prompt）
+    pub text: Vec<String>,
+    pub images: Vec<ImageData>,
+}
+
+/// 图像服务商
+#[async_trait]
+pub trait ImageProvider: Send + Sync {
+    /// 按查询参数与配置生成图片；`config.base_image` 存在时编辑原图
+    async fn generate(&self, query: &ImageQuery, config: &ImageConfig) -> Result<GeneratedImages>;
+}
+
+/// 按配置选择服务商实现
+pub fn image_provider(kind: ImageProviderKind) -> Box<dyn ImageProvider> {
+    match kind {
+        ImageProviderKind::Gemini => Box::new(GeminiImageProvider),
+        ImageProviderKind::Openai => Box::new(OpenAiImageProvider),
+        ImageProviderKind::Stability => Box::new(StabilityImageProvider),
+    }
+}
+
+/// 图像生成结果
+pub struct ImageResult {
+    /// 文本回复
+    pub text: Option<String>,
+    /// 生成的图片 URL 列表
+    pub image_urls: Vec<String>,
+    /// 是否截断
+    pub truncated: bool,
+    /// 执行时长
+    pub duration: Duration,
+    /// 错误信息
+    pub error: Option<String>,
+    /// 是否超时
+    pub timed_out: bool,
+}
+
+/// 执行图像生成
+pub async fn run_image_generation(
+    query: ImageQuery,
+    config: &ImageConfig,
+    timeout_secs: Option<u64>,
+    max_output: usize,
+) -> ImageResult {
+    let timeout = timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let start = Instant::now();
+
+    match time::timeout(timeout, execute_image_generation(&query, config)).await {
+        Ok(Ok((text, image_urls))) => {
+            let (final_text, truncated) = clamp_output(text, max_output);
+            ImageResult {
+                text: if final_text.is_empty() {
+                    None
+                } else {
+                    Some(final_text)
+                },
+                image_urls,
+                truncated,
+                duration: start.elapsed(),
+                error: None,
+                timed_out: false,
+            }
+        }
+        Ok(Err(err)) => ImageResult {
+            text: Some(format!("图像生成失败: {}", err)),
+            image_urls: vec![],
+            truncated: false,
+            duration: start.elapsed(),
+            error: Some(err.to_string()),
+            timed_out: false,
+        },
+        Err(_) => ImageResult {
+            text: Some("图像生成超时".to_string()),
+            image_urls: vec![],
+            truncated: false,
+            duration: timeout,
+            error: Some("timeout".to_string()),
+            timed_out: true,
+        },
+    }
+}
+
+/// 调用服务商并保存生成的图片
+async fn execute_image_generation(
+    query: &ImageQuery,
+    config: &ImageConfig,
+) -> Result<(String, Vec<String>)> {
+    if query.prompt().is_empty() {
+        return Err(anyhow!("prompt 不能为空"));
+    }
+    if config.api_key.is_empty() {
+        return Err(anyhow!(
+            "{} 图像服务未配置 API Key",
+            config.settings.provider.as_str()
+        ));
+    }
+
+    let provider = image_provider(config.settings.provider);
+    let generated = provider.generate(query, config).await?;
+    if generated.images.is_empty() && generated.text.is_empty() {
+        return Err(anyhow!("服务商未返回图片"));
+    }
+
+    let mut image_urls = Vec::new();
+    for image in &generated.images {
+        match save_image(image, config).await {
+            Ok(url) => image_urls.push(url),
+            Err(e) => tracing::warn!(?e, "保存图片失败"),
+        }
+    }
+
+    Ok((generated.text.join("\n"), image_urls))
+}
+
+/// 按文件头识别图片类型，服务商未返回 MIME 时使用
+pub fn detect_mime(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        "image/jpeg"
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else {
+        "image/png"
+    }
+}
+
+/// 根据 MIME 类型确定扩展名
+pub(super) fn image_extension(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/png" => "png",
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "png", // 默认 png
+    }
+}
+
+/// 保存图片到本地并返回访问 URL
+async fn save_image(image: &ImageData, config: &ImageConfig) -> Result<String> {
+    // 生成唯一文件名
+    let filename = format!("{}.{}", Uuid::new_v4(), image_extension(&image.mime_type));
+    let file_path = Path::new(&config.image_dir).join(&filename);
+
+    // 确保目录存在
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    // 写入文件
+    fs::write(&file_path, &image.data)
+        .await
+        .map_err(|e| anyhow!("写入文件失败: {}", e))?;
+
+    tracing::info!(
+        path = %file_path.display(),
+        size = image.data.len(),
+        mime = %image.mime_type,
+        provider = config.settings.provider.as_str(),
+        "图片已保存"
+    );
+
+    // 构建访问 URL
+    let url = if let Some(ref base_url) = config.external_base_url {
+        format!(
+            "{}{}/{}",
+            base_url.trim_end_matches('/'),
+            config.image_url_prefix,
+            filename
+        )
+    } else {
+        format!("{}/{}", config.image_url_prefix, filename)
+    };
+
+    Ok(url)
+}
+
+/// 截断输出
+fn clamp_output(text: String, max: usize) -> (String, bool) {
+    if text.len() <= max {
+        return (text, false);
+    }
+    if max == 0 {
+        return (String::new(), true);
+    }
+    let mut cut = max.min(text.len());
+    while cut > 0 && !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let mut truncated = text;
+    truncated.truncate(cut);
+    (truncated, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_query() {
+        let q = ImageQuery::from_json(
+            r#"{"prompt":"画一只猫","model":"dall-e-3","size":"1024x1792","quality":"hd"}"#,
+        );
+        assert_eq!(q.prompt(), "画一只猫");
+        assert_eq!(q.size.as_deref(), Some("1024x1792"));
+        assert_eq!(q.quality.as_deref(), Some("hd"));
+
+        // 测试无效 JSON 应返回默认值
+        let q = ImageQuery::from_json("invalid json");
+        assert_eq!(q.prompt(), "");
+        assert!(q.model.is_none());
+    }
+
+    #[test]
+    fn test_parse_query_with_aspect_ratio() {
+        let q = ImageQuery::from_json(
+            r#"{"prompt":"画一只猫","aspect_ratio":"16:9","image_size":"4K"}"#,
+        );
+        assert_eq!(q.prompt(), "画一只猫");
+        assert_eq!(q.aspect_ratio.as_deref(), Some("16:9"));
+        assert_eq!(q.image_size.as_deref(), Some("4K"));
+    }
+
+    #[test]
+    fn test_parse_query_with_google_search() {
+        let q = ImageQuery::from_json(r#"{"prompt":"画一只猫","google_search":true}"#);
+        assert_eq!(q.google_search, Some(true));
+
+        let q = ImageQuery::from_json(r#"{"prompt":"画一只猫"}"#);
+        assert_eq!(q.google_search, None);
+    }
+
+    #[test]
+    fn test_image_query_defaults() {
+        let q = ImageQuery::default();
+        assert_eq!(q.prompt(), "");
+        assert!(q.model.is_none());
+        assert!(q.aspect_ratio.is_none());
+        assert!(q.image_size.is_none());
+        assert!(q.google_search.is_none());
+        assert!(q.size.is_none());
+        assert!(q.quality.is_none());
+    }
+
+    #[test]
+    fn test_image_query_all_aspect_ratios() {
+        for ratio in ["1:1", "2:3", "3:2", "3:4", "4:3", "9:16", "16:9", "21:9"] {
+            let json = format!(r#"{{"prompt":"test","aspect_ratio":"{}"}}"#, ratio);
+            let q = ImageQuery::from_json(&json);
+            assert_eq!(q.aspect_ratio.as_deref(), Some(ratio));
+        }
+    }
+
+    #[test]
+    fn test_image_query_all_sizes() {
+        for size in ["1K", "2K", "4K"] {
+            let json = format!(r#"{{"prompt":"test","image_size":"{}"}}"#, size);
+            let q = ImageQuery::from_json(&json);
+            assert_eq!(q.image_size.as_deref(), Some(size));
+        }
+    }
+
+    #[test]
+    fn test_image_query_model_variants() {
+        for model in [
+            "gemini-3-pro-image-preview",
+            "gpt-image-1",
+            "stable-image-core",
+            "custom-model",
+        ] {
+            let json = format!(r#"{{"prompt":"test","model":"{}"}}"#, model);
+            let q = ImageQuery::from_json(&json);
+            assert_eq!(q.model_or(&ImageToolConfig::default(), "fallback"), model);
+        }
+    }
+
+    #[test]
+    fn test_model_precedence() {
+        let settings = ImageToolConfig {
+            model: Some("configured".to_string()),
+            ..Default::default()
+        };
+        let q = ImageQuery::default();
+        assert_eq!(q.model_or(&settings, "fallback"), "configured");
+        assert_eq!(
+            q.model_or(&ImageToolConfig::default(), "fallback"),
+            "fallback"
+        );
+        let q = ImageQuery::from_json(r#"{"model":"override"}"#);
+        assert_eq!(q.model_or(&settings, "fallback"), "override");
+    }
+
+    #[test]
+    fn test_image_extension() {
+        for (mime, ext) in [
+            ("image/png", "png"),
+            ("image/jpeg", "jpg"),
+            ("image/jpg", "jpg"),
+            ("image/webp", "webp"),
+            ("image/gif", "gif"),
+            ("application/octet-stream", "png"),
+        ] {
+            assert_eq!(image_extension(mime), ext);
+        }
+    }
+
+    #[test]
+    fn test_image_config_creation() {
+        let config = ImageConfig {
+            api_key: "test_key".to_string(),
+            base_url: Some("https://api.test.com/".to_string()),
+            image_dir: "/tmp/images".to_string(),
+            image_url_prefix: "/images".to_string(),
+            external_base_url: Some("https://example.com".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(config.api_key, "test_key");
+        assert_eq!(
+            config.base_url_or("https://default"),
+            "https://api.test.com"
+        );
+        assert_eq!(config.image_dir, "/tmp/images");
+        assert_eq!(config.image_url_prefix, "/images");
+        assert_eq!(
+            config.external_base_url,
+            Some("https://example.com".to_string())
+        );
+        assert!(config.base_image.is_none());
+        assert_eq!(
+            ImageConfig::default().base_url_or("https://default/"),
+            "https://default"
+        );
+    }
+
+    #[test]
+    fn test_clamp_output() {
+        let (result, truncated) = clamp_output("Hello, World!".to_string(), 100);
+        assert_eq!(result, "Hello, World!");
+        assert!(!truncated);
+
+        let (result, truncated) =
+            clamp_output("Hello, World! This is a long text.".to_string(), 10);
+        assert_eq!(result.len(), 10);
+        assert!(truncated);
+
+        let (result, truncated) = clamp_output("Hello".to_string(), 5);
+        assert_eq!(result, "Hello");
+        assert!(!truncated);
+
+        let (result, truncated) = clamp_output("Hello".to_string(), 0);
+        assert_eq!(result, "");
+        assert!(truncated);
+
+        // 测试 UTF-8 字符边界处理
+        let (result, truncated) = clamp_output("你好世界".to_string(), 20);
+        assert_eq!(result, "你好世界");
+        assert!(!truncated);
+
+        let (result, truncated) = clamp_output("你好世界".to_string(), 7);
+        assert_eq!(result.len(), 6);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_image_result_structure() {
+        let result = ImageResult {
+            text: Some("Generated successfully".to_string()),
+            image_urls: vec![
+                "https://example.com/image1.png".to_string(),
+                "https://example.com/image2.png".to_string(),
+            ],
+            truncated: false,
+            duration: Duration::from_secs(5),
+            error: None,
+            timed_out: false,
+        };
+
+        assert_eq!(result.text, Some("Generated successfully".to_string()));
+        assert_eq!(result.image_urls.len(), 2);
+        assert!(!result.truncated);
+        assert!(!result.timed_out);
+        assert!(result.error.is_none());
+    }
+
+    #[test]
+    fn test_image_result_with_error() {
+        let result = ImageResult {
+            text: Some("Failed to generate".to_string()),
+            image_urls: vec![],
+            truncated: false,
+            duration: Duration::from_secs(1),
+            error: Some("API error".to_string()),
+            timed_out: false,
+        };
+
+        assert!(result.error.is_some());
+        assert_eq!(result.error.unwrap(), "API error");
+        assert!(result.image_urls.is_empty());
+    }
+
+    #[test]
+    fn test_image_result_timeout() {
+        let result = ImageResult {
+            text: Some("Timeout".to_string()),
+            image_urls: vec![],
+            truncated: false,
+            duration: Duration::from_secs(120),
+            error: Some("timeout".to_string()),
+            timed_out: true,
+        };
+
+        assert!(result.timed_out);
+        assert_eq!(result.error.unwrap(), "timeout");
+    }
+
+    #[test]
+    fn test_detect_mime() {
+        assert_eq!(detect_mime(&[0xff, 0xd8, 0xff, 0xe0]), "image/jpeg");
+        assert_eq!(detect_mime(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(detect_mime(b"GIF89a"), "image/gif");
+        assert_eq!(detect_mime(b"\x89PNG\r\n"), "image/png");
+    }
+
+    #[tokio::test]
+    async fn test_save_image_url() {
+        let dir = TempDir::new().unwrap();
+        let config = ImageConfig {
+            image_dir: dir.path().display().to_string(),
+            image_url_prefix: "/images".to_string(),
+            external_base_url: Some("https://example.com/".to_string()),
+            ..Default::default()
+        };
+        let image = ImageData {
+            mime_type: "image/jpeg".to_string(),
+            data: vec![1, 2, 3],
+        };
+        let url = save_image(&image, &config).await.unwrap();
+        assert!(url.starts_with("https://example.com/images/"));
+        assert!(url.ends_with(".jpg"));
+        let filename = url.rsplit('/').next().unwrap();
+        assert_eq!(std::fs::read(dir.path().join(filename)).unwrap(), [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_run_requires_prompt_and_key() {
+        let config = ImageConfig::default();
+        let result = run_image_generation(ImageQuery::default(), &config, Some(5), 100).await;
+        assert_eq!(result.error.as_deref(), Some("prompt 不能为空"));
+
+        let query = ImageQuery::from_json(r#"{"prompt":"猫"}"#);
+        let result = run_image_generation(query, &config, Some(5), 100).await;
+        assert!(result.error.unwrap().contains("API Key"));
+        assert!(result.image_urls.is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+//! 轻量语言识别
+//!
+//! 基于 whatlang（按文字系统与三字母组频率判断），候选限定为 [`SUPPORTED_LANGUAGES`]，
+//! 这里只负责去掉链接与 @ 提及并换算为 ISO 639-1 代码。只用于规则路由，短句与混合语言的结果仅供参考。
+
+use std::sync::OnceLock;
+
+use whatlang::{Detector, Lang, Script};
+
+/// 支持识别的语言代码（ISO 639-1）
+pub const SUPPORTED_LANGUAGES: &[&str] = &[
+    "zh", "ja", "ko", "en", "fr", "de", "es", "pt", "it", "vi", "ru", "ar", "he", "el", "hi", "th",
+];
+
+/// whatlang 语言与 [`SUPPORTED_LANGUAGES`] 的对应关系
+const LANGUAGES: &[(Lang, &str)] = &[
+    (Lang::Cmn, "zh"),
+    (Lang::Jpn, "ja"),
+    (Lang::Kor, "ko"),
+    (Lang::Eng, "en"),
+    (Lang::Fra, "fr"),
+    (Lang::Deu, "de"),
+    (Lang::Spa, "es"),
+    (Lang::Por, "pt"),
+    (Lang::Ita, "it"),
+    (Lang::Vie, "vi"),
+    (Lang::Rus, "ru"),
+    (Lang::Ara, "ar"),
+    (Lang::Heb, "he"),
+    (Lang::Ell, "el"),
+    (Lang::Hin, "hi"),
+    (Lang::Tha, "th"),
+];
+
+/// 拉丁字母文本的最低置信度，低于该值时视为英语
+const MIN_LATIN_CONFIDENCE: f64 = 0.05;
+
+/// 规范化语言代码：取主标签并转小写，如 "zh-CN" → "zh"
+pub fn normalize_language_code(code: &str) -> String {
+    code.trim()
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// 识别文本的主要语言，返回 [`SUPPORTED_LANGUAGES`] 中的代码；没有可识别的文字时返回 None
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    static DETECTOR: OnceLock<Detector> = OnceLock::new();
+    let detector = DETECTOR.get_or_init(|| {
+        Detector::with_allowlist(LANGUAGES.iter().map(|(lang, _)| *lang).collect())
+    });
+    let info = detector.detect(&strip_noise(text))?;
+    // 拉丁字母的短句（如 "ok"）几乎无法区分语言，置信度过低时视为英语
+    let lang = if info.script() == Script::Latin && info.confidence() < MIN_LATIN_CONFIDENCE {
+        Lang::Eng
+    } else {
+        info.lang()
+    };
+    LANGUAGES
+        .iter()
+        .find(|(l, _)| *l == lang)
+        .map(|(_, code)| *code)
+}
+
+/// 去掉链接与 @ 提及，避免其中的字母影响判断
+fn strip_noise(text: &str) -> String {
+    text.split_whitespace()
+        .filter(|w| !w.starts_with('@') && !w.contains("://") && !w.starts_with("www."))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language_scripts() {
+        assert_eq!(detect_language("今天天气怎么样"), Some("zh"));
+        assert_eq!(detect_language("今日はいい天気ですね"), Some("ja"));
+        assert_eq!(detect_language("안녕하세요"), Some("ko"));
+        assert_eq!(detect_language("Привет, как дела?"), Some("ru"));
+        assert_eq!(detect_language("مرحبا كيف حالك"), Some("ar"));
+        assert_eq!(detect_language("สวัสดีครับ"), Some("th"));
+        assert_eq!(detect_language("123 😀 !!!"), None);
+        assert_eq!(detect_language(""), None);
+    }
+
+    #[test]
+    fn test_detect_language_latin() {
+        assert_eq!(detect_language("How are you doing today?"), Some("en"));
+        assert_eq!(detect_language("ok"), Some("en"));
+        assert_eq!(detect_language("Bonjour, je suis à la maison"), Some("fr"));
+        assert_eq!(detect_language("Ich bin nicht müde, danke"), Some("de"));
+        assert_eq!(detect_language("Hola, ¿cómo está usted?"), Some("es"));
+        assert_eq!(detect_language("Olá, você está bem? Não sei"), Some("pt"));
+        assert_eq!(
+            detect_language("Ciao, come stai? Sono molto felice"),
+            Some("it")
+        );
+        assert_eq!(detect_language("Xin chào, bạn khỏe không?"), Some("vi"));
+    }
+
+    #[test]
+    fn test_detect_language_mixed() {
+        // 以占比较多的语言为准，链接与 @ 提及不计入
+        assert_eq!(detect_language("@Bot 帮我看看这个 bug"), Some("zh"));
+        assert_eq!(
+            detect_language("please review https://example.com/中文 thanks"),
+            Some("en")
+        );
+        assert_eq!(detect_language("这个 API 怎么用"), Some("zh"));
+    }
+
+    #[test]
+    fn test_normalize_language_code() {
+        assert_eq!(normalize_language_code("zh-CN"), "zh");
+        assert_eq!(normalize_language_code(" EN_us "), "en");
+        assert_eq!(normalize_language_code("ja"), "ja");
+    }
+}
@@ -0,0 +1,298 @@
+//! 链接预览工具
+//!
+//! 抓取网页标题/描述/封面图，并清理追踪参数，用于把分享卡片整理成干净的链接卡片。
+//!
+//! 与 HTTP 工具共用内网地址防护：每一跳都解析并固定到已校验的公网地址，
+//! 重定向由本模块逐跳校验后手动跟随。
+
+use super::http_request::guarded_get;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use reqwest::{header, Url};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 只读取页面前 512 KB，足以覆盖 <head>
+const MAX_HTML_BYTES: usize = 512 * 1024;
+/// 卡片标题/描述长度上限（按字符计）
+const MAX_TITLE_CHARS: usize = 64;
+const MAX_DESC_CHARS: usize = 120;
+/// 短链常见多次跳转，超过该次数视为失败
+const MAX_REDIRECTS: usize = 5;
+
+/// 常见追踪参数（utm_* 以前缀方式匹配）
+const TRACKING_PARAMS: &[&str] = &[
+    "spm",
+    "from",
+    "share_token",
+    "share_source",
+    "share_medium",
+    "share_plat",
+    "share_session_id",
+    "share_tag",
+    "share_from",
+    "scene",
+    "srcid",
+    "sharer_sharetime",
+    "sharer_shareid",
+    "gclid",
+    "fbclid",
+    "mc_cid",
+    "mc_eid",
+    "vd_source",
+];
+
+/// 链接预览结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LinkPreview {
+    /// 清理后的最终 URL
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// 封面图（og:image），已转为绝对 URL
+    pub image: Option<String>,
+}
+
+/// 抓取网页并生成链接预览
+pub async fn fetch_link_preview(
+    url: &str,
+    timeout_secs: Option<u64>,
+    strip_tracking: bool,
+) -> Result<LinkPreview> {
+    let timeout = timeout_secs
+        .filter(|s| *s > 0)
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let (mut resp, final_url) = fetch_page(url, timeout).await?;
+    if !resp.status().is_success() {
+        return Err(anyhow!("页面返回状态 {}", resp.status().as_u16()));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|e| anyhow!("读取响应失败: {e}"))?
+    {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_HTML_BYTES {
+            break;
+        }
+    }
+    let html = String::from_utf8_lossy(&body);
+
+    let mut preview = parse_html_preview(&html, &final_url);
+    preview.url = if strip_tracking {
+        clean_url(final_url.as_str())
+    } else {
+        final_url.to_string()
+    };
+    Ok(preview)
+}
+
+/// 请求页面，逐跳校验并跟随重定向，返回最终响应及其地址
+async fn fetch_page(url: &str, timeout: Duration) -> Result<(reqwest::Response, Url)> {
+    let mut current = Url::parse(url).map_err(|e| anyhow!("无效 URL: {e}"))?;
+    for _ in 0..=MAX_REDIRECTS {
+        let resp = guarded_get(&current, timeout)
+            .await?
+            .header(header::ACCEPT, "text/html,application/xhtml+xml")
+            .send()
+            .await
+            .map_err(|e| anyhow!("请求失败: {e}"))?;
+        if !resp.status().is_redirection() {
+            return Ok((resp, current));
+        }
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("重定向缺少 Location"))?;
+        current = current
+            .join(location)
+            .map_err(|e| anyhow!("无效重定向地址: {e}"))?;
+    }
+    Err(anyhow!("重定向次数超过 {}", MAX_REDIRECTS))
+}
+
+/// 去除 utm_* 等追踪参数与锚点；无法解析的 URL 原样返回
+pub fn clean_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(k, _)| !is_tracking_param(k))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    parsed.set_fragment(None);
+    parsed.to_string()
+}
+
+fn is_tracking_param(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key.starts_with("utm_") || TRACKING_PARAMS.contains(&key.as_str())
+}
+
+/// 从 HTML 中解析 og:title/og:description/og:image，缺失时回退到 <title>/description
+fn parse_html_preview(html: &str, base: &Url) -> LinkPreview {
+    let title = meta_content(html, "og:title")
+        .or_else(|| meta_content(html, "twitter:title"))
+        .or_else(|| title_tag(html))
+        .map(|t| truncate_chars(&t, MAX_TITLE_CHARS));
+    let description = meta_content(html, "og:description")
+        .or_else(|| meta_content(html, "description"))
+        .or_else(|| meta_content(html, "twitter:description"))
+        .map(|d| truncate_chars(&d, MAX_DESC_CHARS));
+    let image = meta_content(html, "og:image")
+        .or_else(|| meta_content(html, "twitter:image"))
+        .and_then(|src| base.join(&src).ok())
+        .map(|u| u.to_string());
+    LinkPreview {
+        url: base.to_string(),
+        title,
+        description,
+        image,
+    }
+}
+
+fn meta_tag_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?is)<meta\s[^>]*>").expect("valid meta regex"))
+}
+
+fn attr_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"(?is)([a-z:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).expect("valid attr regex")
+    })
+}
+
+/// 读取 <meta property|name="key" content="..."> 的 content，属性顺序不限
+fn meta_content(html: &str, key: &str) -> Option<String> {
+    for tag in meta_tag_regex().find_iter(html) {
+        let mut name = None;
+        let mut content = None;
+        for cap in attr_regex().captures_iter(tag.as_str()) {
+            let attr = cap[1].to_ascii_lowercase();
+            let value = cap.get(2).or_else(|| cap.get(3)).map(|m| m.as_str());
+            match attr.as_str() {
+                "property" | "name" => name = value,
+                "content" => content = value,
+                _ => {}
+            }
+        }
+        if name.is_some_and(|n| n.eq_ignore_ascii_case(key)) {
+            if let Some(value) = content.map(decode_entities).filter(|v| !v.is_empty()) {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+fn title_tag(html: &str) -> Option<String> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("valid regex"));
+    re.captures(html)
+        .map(|c| decode_entities(&c[1]))
+        .filter(|t| !t.is_empty())
+}
+
+/// 解码常见 HTML 实体并压缩空白
+fn decode_entities(raw: &str) -> String {
+    let decoded = raw
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    decoded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate_chars(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_string();
+    }
+    let mut out: String = s.chars().take(max).collect();
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_url() {
+        assert_eq!(
+            clean_url("https://example.com/a?id=1&utm_source=wx&spm=abc#top"),
+            "https://example.com/a?id=1"
+        );
+        assert_eq!(
+            clean_url("https://example.com/a?utm_medium=x"),
+            "https://example.com/a"
+        );
+        // 无法解析时原样返回
+        assert_eq!(clean_url("not a url"), "not a url");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_link_preview_rejects_private_targets() {
+        for url in [
+            "http://127.0.0.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]:8080/",
+            "file:///etc/passwd",
+        ] {
+            assert!(
+                fetch_link_preview(url, Some(1), true).await.is_err(),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_html_preview_og() {
+        let html = r#"<html><head>
+            <title>Fallback</title>
+            <meta content="OG 标题 &amp; 更多" property="og:title">
+            <meta property='og:description' content='描述'>
+            <meta property="og:image" content="/cover.png" />
+            </head></html>"#;
+        let base = Url::parse("https://example.com/post/1").unwrap();
+        let preview = parse_html_preview(html, &base);
+        assert_eq!(preview.title.as_deref(), Some("OG 标题 & 更多"));
+        assert_eq!(preview.description.as_deref(), Some("描述"));
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://example.com/cover.png")
+        );
+    }
+
+    #[test]
+    fn test_parse_html_preview_fallback() {
+        let html = r#"<html><head><title>
+            Plain   Title
+        </title><meta name="description" content="desc"></head></html>"#;
+        let base = Url::parse("https://example.com").unwrap();
+        let preview = parse_html_preview(html, &base);
+        assert_eq!(preview.title.as_deref(), Some("Plain Title"));
+        assert_eq!(preview.description.as_deref(), Some("desc"));
+        assert!(preview.image.is_none());
+    }
+
+    #[test]
+    fn test_truncate_chars() {
+        assert_eq!(truncate_chars("短标题", 10), "短标题");
+        assert_eq!(truncate_chars("abcdef", 3), "abc…");
+    }
+}
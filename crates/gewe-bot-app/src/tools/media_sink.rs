@@ -0,0 +1,199 @@
+//! 媒体存储后端
+//!
+//! 把下载的媒体写入本地目录、S3 兼容对象存储（gewe-http 的 [`S3Bucket`] 预签名的 PUT）或
+//! WebDAV（逐级 MKCOL 后 PUT），返回保存位置：本地为文件路径，其余为对象地址
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use gewe_http::{S3Bucket, UPLOAD_PRESIGN_EXPIRES};
+use reqwest::{Client, Method, StatusCode, Url};
+use tokio::fs;
+
+use crate::config::MediaBackend;
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 写入一个存储，`key` 为渲染后的文件名（可包含子目录）
+pub async fn store_media(
+    backend: &MediaBackend,
+    key: &str,
+    bytes: &[u8],
+) -> Result<String, String> {
+    match backend {
+        MediaBackend::Local { dir } => store_local(dir, key, bytes).await,
+        MediaBackend::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key_id,
+            secret_access_key,
+            secret_access_key_env,
+            prefix,
+            path_style,
+        } => {
+            let secret = match (secret_access_key, secret_access_key_env) {
+                (Some(secret), _) => secret.clone(),
+                (None, Some(env)) => {
+                    std::env::var(env).map_err(|_| format!("未找到 S3 密钥环境变量 {}", env))?
+                }
+                (None, None) => return Err("未配置 S3 密钥".to_string()),
+            };
+            let key = format!("{}{}", prefix.as_deref().unwrap_or_default(), key);
+            let mut bucket = S3Bucket::new(endpoint, bucket, access_key_id, secret);
+            if let Some(region) = region {
+                bucket = bucket.region(region);
+            }
+            if !path_style.unwrap_or(true) {
+                bucket = bucket.virtual_hosted();
+            }
+            store_s3(&bucket, &key, bytes).await
+        }
+        MediaBackend::Webdav {
+            url,
+            username,
+            password,
+            password_env,
+        } => {
+            let password = match (password, password_env) {
+                (Some(password), _) => Some(password.clone()),
+                (None, Some(env)) => Some(
+                    std::env::var(env)
+                        .map_err(|_| format!("未找到 WebDAV 密码环境变量 {}", env))?,
+                ),
+                (None, None) => None,
+            };
+            let auth = username.as_deref().map(|u| (u, password.as_deref()));
+            store_webdav(url, auth, key, bytes).await
+        }
+    }
+}
+
+async fn store_local(dir: &str, key: &str, bytes: &[u8]) -> Result<String, String> {
+    let path = Path::new(dir).join(key);
+    // 文件名模板可以包含子目录
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("创建目录失败: {}", e))?;
+    }
+    fs::write(&path, bytes)
+        .await
+        .map_err(|e| format!("写入文件失败 {}: {}", path.display(), e))?;
+    Ok(path.display().to_string())
+}
+
+async fn store_s3(bucket: &S3Bucket, key: &str, bytes: &[u8]) -> Result<String, String> {
+    let url = bucket
+        .presigned_url("PUT", key, SystemTime::now(), UPLOAD_PRESIGN_EXPIRES)
+        .map_err(|e| format!("生成 S3 上传地址失败: {}", e))?;
+    let resp = http_client()?
+        .put(url)
+        .body(bytes.to_vec())
+        .send()
+        .await
+        .map_err(|e| format!("上传到 S3 失败: {}", e))?;
+    check_status(resp, "上传到 S3 失败").await?;
+    bucket
+        .object_url(key)
+        .map(String::from)
+        .map_err(|e| format!("生成 S3 对象地址失败: {}", e))
+}
+
+async fn store_webdav(
+    base: &str,
+    auth: Option<(&str, Option<&str>)>,
+    key: &str,
+    bytes: &[u8],
+) -> Result<String, String> {
+    let client = http_client()?;
+    let base_url = Url::parse(base.trim_end_matches('/'))
+        .map_err(|e| format!("WebDAV 地址无效 {}: {}", base, e))?;
+    if base_url.cannot_be_a_base() {
+        return Err(format!("WebDAV 地址无效: {}", base));
+    }
+    let request = |method: Method, url: &str| {
+        let req = client.request(method, url);
+        match auth {
+            Some((user, password)) => req.basic_auth(user, password),
+            None => req,
+        }
+    };
+    // 逐级创建子目录，已存在时服务器返回 405
+    let segments: Vec<&str> = key.split('/').filter(|s| !s.is_empty()).collect();
+    let mut dir = base_url.clone();
+    for segment in segments.iter().take(segments.len().saturating_sub(1)) {
+        push_segments(&mut dir, [*segment]);
+        let mkcol = Method::from_bytes(b"MKCOL").expect("MKCOL 是合法的方法名");
+        let resp = request(mkcol, dir.as_str())
+            .send()
+            .await
+            .map_err(|e| format!("创建 WebDAV 目录失败: {}", e))?;
+        if resp.status() != StatusCode::METHOD_NOT_ALLOWED {
+            check_status(resp, "创建 WebDAV 目录失败").await?;
+        }
+    }
+    let mut url = base_url;
+    push_segments(&mut url, segments);
+    let resp = request(Method::PUT, url.as_str())
+        .body(bytes.to_vec())
+        .send()
+        .await
+        .map_err(|e| format!("上传到 WebDAV 失败: {}", e))?;
+    check_status(resp, "上传到 WebDAV 失败").await?;
+    Ok(url.into())
+}
+
+/// 在地址末尾追加路径段，段内的 `/` 等字符会被编码
+fn push_segments<'a>(url: &mut Url, segments: impl IntoIterator<Item = &'a str>) {
+    url.path_segments_mut()
+        .expect("已检查地址可作为基址")
+        .pop_if_empty()
+        .extend(segments);
+}
+
+fn http_client() -> Result<Client, String> {
+    Client::builder()
+        .timeout(UPLOAD_TIMEOUT)
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))
+}
+
+async fn check_status(resp: reqwest::Response, context: &str) -> Result<(), String> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let body: String = body.chars().take(200).collect();
+    Err(format!("{}: HTTP {} {}", context, status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_segments_encodes_each_segment() {
+        let mut url = Url::parse("http://dav.local/remote.php/dav").unwrap();
+        push_segments(&mut url, ["a b", "中.png"]);
+        assert_eq!(
+            url.as_str(),
+            "http://dav.local/remote.php/dav/a%20b/%E4%B8%AD.png"
+        );
+        let mut root = Url::parse("http://dav.local").unwrap();
+        push_segments(&mut root, ["x"]);
+        assert_eq!(root.as_str(), "http://dav.local/x");
+    }
+
+    #[tokio::test]
+    async fn test_store_local_creates_subdirectories() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let backend = MediaBackend::Local {
+            dir: temp.path().to_string_lossy().to_string(),
+        };
+        let path = store_media(&backend, "app/1.png", b"png").await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"png");
+        assert!(path.ends_with("1.png"));
+    }
+}
@@ -0,0 +1,131 @@
+//! 会议纪要工具
+//!
+//! 构建由转写文本生成会议纪要的提示词，并把转写原文保存为可下载的 .txt 文件；
+//! 转写与模型调用由调度器完成。
+
+use super::image::ImageConfig;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use tokio::fs;
+use uuid::Uuid;
+
+/// 纪要请求默认的 system prompt
+pub const DEFAULT_MEETING_NOTES_SYSTEM_PROMPT: &str =
+    "你是会议记录助手。请根据语音转写文本整理会议纪要，使用与原文相同的语言，只记录原文中出现的信息，不要编造。转写可能有错别字，请按上下文理解。";
+
+/// 长转写的分段提炼提示词
+pub fn chunk_notes_prompt(index: usize, total: usize, chunk: &str) -> String {
+    format!(
+        "以下是一段录音转写的第 {}/{} 部分。请用不超过 300 字提炼这一部分的讨论要点、已作出的决定和分配的任务（含负责人与期限）：\n\n{}",
+        index, total, chunk
+    )
+}
+
+/// 会议纪要提示词：`content` 为转写全文（单段）或各部分提炼（`partial` 为 true）
+pub fn meeting_notes_prompt(content: &str, partial: bool, extra: Option<&str>) -> String {
+    let mut prompt = if partial {
+        "以下是一段录音转写各部分的提炼，请整合为一份会议纪要。".to_string()
+    } else {
+        "请根据以下录音转写整理一份会议纪要。".to_string()
+    };
+    prompt.push_str(
+        "按顺序输出四个部分，每部分一行标题：\
+         「摘要」用一段话（不超过 150 字）概括；\
+         「决定事项」逐条列出已达成的决定；\
+         「待办事项」逐条列出任务，注明负责人与期限（未提及写「待定」）；\
+         「待确认问题」列出尚未解决的问题。\
+         条目以「- 」开头，某部分没有内容时写「- 无」。不要使用 Markdown 标题。",
+    );
+    if let Some(extra) = extra.map(str::trim).filter(|s| !s.is_empty()) {
+        prompt.push_str("\n\n额外要求：");
+        prompt.push_str(extra);
+    }
+    prompt.push_str("\n\n");
+    prompt.push_str(content);
+    prompt
+}
+
+/// 转写原文附件的文件名，如 `会议转写-20261015-1030.txt`
+pub fn transcript_file_name(time: chrono::NaiveDateTime) -> String {
+    format!("会议转写-{}.txt", time.format("%Y%m%d-%H%M"))
+}
+
+/// 把转写原文保存到图片目录（由静态服务对外提供），返回访问 URL
+pub async fn save_transcript(text: &str, config: &ImageConfig) -> Result<String> {
+    let base_url = config
+        .external_base_url
+        .as_deref()
+        .ok_or_else(|| anyhow!("未配置 external_base_url，无法发送转写文件"))?;
+    let filename = format!("{}.txt", Uuid::new_v4());
+    let file_path = Path::new(&config.image_dir).join(&filename);
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&file_path, text)
+        .await
+        .map_err(|e| anyhow!("写入文件失败: {}", e))?;
+    tracing::info!(path = %file_path.display(), size = text.len(), "转写原文已保存");
+
+    Ok(format!(
+        "{}{}/{}",
+        base_url.trim_end_matches('/'),
+        config.image_url_prefix,
+        filename
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meeting_notes_prompts() {
+        let prompt = chunk_notes_prompt(1, 3, "转写");
+        assert!(prompt.contains("第 1/3 部分"));
+        assert!(prompt.ends_with("转写"));
+
+        let prompt = meeting_notes_prompt("全文", false, None);
+        assert!(prompt.starts_with("请根据以下录音转写"));
+        for section in ["「摘要」", "「决定事项」", "「待办事项」", "「待确认问题」"]
+        {
+            assert!(prompt.contains(section));
+        }
+        assert!(!prompt.contains("额外要求"));
+        assert!(prompt.ends_with("全文"));
+
+        let prompt = meeting_notes_prompt("提炼", true, Some("标注发言人"));
+        assert!(prompt.contains("各部分的提炼"));
+        assert!(prompt.contains("额外要求：标注发言人"));
+    }
+
+    #[test]
+    fn test_transcript_file_name() {
+        let time = chrono::NaiveDate::from_ymd_opt(2026, 10, 15)
+            .unwrap()
+            .and_hms_opt(10, 30, 5)
+            .unwrap();
+        assert_eq!(transcript_file_name(time), "会议转写-20261015-1030.txt");
+    }
+
+    #[tokio::test]
+    async fn test_save_transcript() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ImageConfig {
+            image_dir: dir.path().to_string_lossy().into_owned(),
+            image_url_prefix: "/images".to_string(),
+            external_base_url: Some("https://bot.example.com/".to_string()),
+            ..Default::default()
+        };
+        let url = save_transcript("第一行\n第二行", &config).await.unwrap();
+        let filename = url.strip_prefix("https://bot.example.com/images/").unwrap();
+        assert!(filename.ends_with(".txt"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(filename)).unwrap(),
+            "第一行\n第二行"
+        );
+
+        config.external_base_url = None;
+        let err = save_transcript("x", &config).await.unwrap_err();
+        assert!(err.to_string().contains("external_base_url"));
+    }
+}
@@ -1,11 +1,100 @@
 //! 内置工具模块
 
+mod budget;
 mod claude_changelog;
+mod countdown;
+mod document;
+#[cfg(feature = "tools")]
+mod docx_text;
 mod gemini_image;
 mod http_request;
+mod image;
+mod language;
+mod link_unfurl;
+mod media_sink;
+mod meeting_notes;
+mod ocr;
+mod openai_image;
+mod ops_digest;
+mod output;
+#[cfg(feature = "tools")]
+mod pdf_text;
+mod process_pool;
+mod prompt_guard;
+mod reminder;
+#[cfg(feature = "tools")]
+mod smtp;
+mod spawn;
+mod stability_image;
+mod todo_list;
 mod tool_versions;
+mod transcribe;
+mod tts;
 
+pub use budget::{
+    check_budget, estimate_tokens, usage_from_events, BudgetExceeded, TokenUsage,
+    DEFAULT_OUTPUT_TOKEN_RESERVE,
+};
 pub use claude_changelog::{run_claude_changelog, ChangelogQuery};
-pub use gemini_image::{run_gemini_image, ImageConfig, ImageQuery};
-pub use http_request::{run_http_request, HttpRequestQuery};
+pub use countdown::render_countdown;
+pub use document::{
+    chunk_summary_prompt, chunk_text, extract_document_text, final_summary_prompt,
+    is_supported_document, DEFAULT_SUMMARY_SYSTEM_PROMPT,
+};
+pub use http_request::{guarded_get, host_matches, run_http_request, HttpRequestQuery};
+pub use image::{detect_mime, run_image_generation, ImageConfig, ImageData, ImageQuery};
+pub use language::{detect_language, normalize_language_code, SUPPORTED_LANGUAGES};
+pub use link_unfurl::fetch_link_preview;
+pub use media_sink::store_media;
+pub use meeting_notes::{
+    chunk_notes_prompt, meeting_notes_prompt, save_transcript, transcript_file_name,
+    DEFAULT_MEETING_NOTES_SYSTEM_PROMPT,
+};
+pub use ocr::{run_ocr, OcrQuery};
+pub use ops_digest::{digest_title, render_digest_html, render_digest_text};
+pub use process_pool::ProcessPool;
+pub use prompt_guard::{
+    detect_injection, neutralize_injection, wrap_untrusted, PROMPT_GUARD_INSTRUCTION,
+};
+pub use reminder::{format_due, parse_remind_command, RemindCommand, DEFAULT_REMIND_PREFIX};
+#[cfg(feature = "tools")]
+pub use smtp::send_html_mail;
+pub use spawn::{external_command, sanitize_file_component};
+pub use todo_list::{
+    apply_todo_command, parse_todo_command, render_todo_list, DEFAULT_TODO_PREFIX,
+};
 pub use tool_versions::{run_tool_versions, VersionQuery};
+pub use transcribe::{is_audio_file, transcribe_audio};
+pub use tts::{synthesize_voice, DEFAULT_TTS_MAX_CHARS};
+
+/// 未启用 `tools` 特性时不支持发送邮件
+#[cfg(not(feature = "tools"))]
+pub async fn send_html_mail(
+    _cfg: &crate::config::DigestEmailConfig,
+    _subject: &str,
+    _html: &str,
+) -> Result<(), String> {
+    Err("邮件发送未启用，请使用 --features tools 重新编译".to_string())
+}
+
+// 内置工具的输出契约：二进制内只通过 run_* 使用，供库使用者与提示词编写方引用
+#[allow(unused_imports)]
+pub use claude_changelog::{ChangelogEntry, ChangelogOutput, CHANGELOG_SCHEMA_VERSION};
+#[allow(unused_imports)]
+pub use http_request::{HttpResponseOutput, HTTP_REQUEST_SCHEMA_VERSION};
+#[allow(unused_imports)]
+pub use output::{OutputFormat, ToolOutput};
+#[allow(unused_imports)]
+pub use tool_versions::{ToolVersion, ToolVersionsOutput, TOOL_VERSIONS_SCHEMA_VERSION};
+
+// 图像服务商抽象，供库使用者接入其他服务商
+#[allow(unused_imports)]
+pub use image::{image_provider, GeneratedImages, ImageProvider};
+
+// 进程池的拒绝原因与系统负载快照，供库使用者自行判断水位
+#[allow(unused_imports)]
+pub use process_pool::{PoolRejection, SystemLoad};
+
+// 合成结果与音频时长探测，供库使用者自行发送语音
+#[allow(unused_imports)]
+pub use tts::{audio_duration_ms, SynthesizedVoice};
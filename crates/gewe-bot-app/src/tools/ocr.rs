@@ -0,0 +1,306 @@
+//! OCR 文字识别工具
+//!
+//! 识别收到或引用的图片中的文字：默认调用 OpenAI 兼容的视觉模型，
+//! 以 `tesseract` feature 编译时可改用本地 tesseract 命令。
+//! 识别结果可直接回复，也可作为 AI Profile 的 `pre_tool` 附加到提示词中。
+
+use super::image::ImageData;
+use crate::config::{OcrConfig, OcrProviderKind};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+const DEFAULT_PROMPT: &str =
+    "识别图片中的全部文字，按原有段落与表格结构输出纯文本，不要解释或补充内容。";
+#[cfg(feature = "tesseract")]
+const DEFAULT_LANGUAGES: &str = "chi_sim+eng";
+
+/// OCR 查询参数（模型调用时传入）
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OcrQuery {
+    /// 识别指令，如「只提取表格」，覆盖配置中的 prompt（仅视觉模型）
+    #[serde(default)]
+    pub prompt: Option<String>,
+}
+
+impl OcrQuery {
+    pub fn from_json(json: &str) -> Self {
+        serde_json::from_str(json).unwrap_or_default()
+    }
+}
+
+/// OCR 结果
+pub struct OcrResult {
+    /// 识别出的文字或错误提示
+    pub text: Option<String>,
+    /// 是否截断
+    pub truncated: bool,
+    /// 执行时长
+    pub duration: Duration,
+    /// 错误信息
+    pub error: Option<String>,
+    /// 是否超时
+    pub timed_out: bool,
+}
+
+/// 识别图片中的文字
+pub async fn run_ocr(
+    query: OcrQuery,
+    image: Option<&ImageData>,
+    config: &OcrConfig,
+    timeout_secs: Option<u64>,
+    max_output: usize,
+) -> OcrResult {
+    let timeout = timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let start = Instant::now();
+
+    let result = match image {
+        Some(image) => time::timeout(timeout, recognize(&query, image, config)).await,
+        None => Ok(Err(anyhow!("未收到图片，请发送或引用一张图片"))),
+    };
+
+    match result {
+        Ok(Ok(text)) => {
+            let text = text.trim();
+            let text = if text.is_empty() {
+                "图片中未识别到文字".to_string()
+            } else {
+                text.to_string()
+            };
+            let (text, truncated) = clamp_output(text, max_output);
+            OcrResult {
+                text: Some(text),
+                truncated,
+                duration: start.elapsed(),
+                error: None,
+                timed_out: false,
+            }
+        }
+        Ok(Err(err)) => OcrResult {
+            text: Some(format!("文字识别失败: {}", err)),
+            truncated: false,
+            duration: start.elapsed(),
+            error: Some(err.to_string()),
+            timed_out: false,
+        },
+        Err(_) => OcrResult {
+            text: Some("文字识别超时".to_string()),
+            truncated: false,
+            duration: timeout,
+            error: Some("timeout".to_string()),
+            timed_out: true,
+        },
+    }
+}
+
+async fn recognize(query: &OcrQuery, image: &ImageData, config: &OcrConfig) -> Result<String> {
+    match config.provider {
+        OcrProviderKind::Openai => recognize_with_vision(query, image, config).await,
+        OcrProviderKind::Tesseract => recognize_with_tesseract(image, config).await,
+    }
+}
+
+/// 构建视觉模型请求
+fn vision_request(query: &OcrQuery, image: &ImageData, config: &OcrConfig) -> serde_json::Value {
+    let prompt = query
+        .prompt
+        .as_deref()
+        .or(config.prompt.as_deref())
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or(DEFAULT_PROMPT);
+    let data_url = format!(
+        "data:{};base64,{}",
+        image.mime_type,
+        BASE64.encode(&image.data)
+    );
+    serde_json::json!({
+        "model": config.model.as_deref().unwrap_or(DEFAULT_MODEL),
+        "temperature": 0,
+        "messages": [{
+            "role": "user",
+            "content": [
+                { "type": "text", "text": prompt },
+                { "type": "image_url", "image_url": { "url": data_url } }
+            ]
+        }]
+    })
+}
+
+async fn recognize_with_vision(
+    query: &OcrQuery,
+    image: &ImageData,
+    config: &OcrConfig,
+) -> Result<String> {
+    let env_name = config.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+    let api_key = std::env::var(env_name)
+        .ok()
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| anyhow!("缺少环境变量 {}", env_name))?;
+    let base_url = config
+        .base_url
+        .as_deref()
+        .unwrap_or(DEFAULT_BASE_URL)
+        .trim_end_matches('/');
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/chat/completions", base_url))
+        .bearer_auth(api_key)
+        .json(&vision_request(query, image, config))
+        .send()
+        .await
+        .map_err(|e| anyhow!("请求失败: {}", e))?;
+
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| anyhow!("读取响应失败: {}", e))?;
+    if !status.is_success() {
+        tracing::warn!(status = %status, body = %body, "OCR 视觉模型错误响应");
+        return Err(anyhow!("API 请求失败 ({}): {}", status, body));
+    }
+    parse_vision_response(&body)
+}
+
+fn parse_vision_response(body: &str) -> Result<String> {
+    let value: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| anyhow!("解析响应失败: {}", e))?;
+    if let Some(message) = value.pointer("/error/message").and_then(|v| v.as_str()) {
+        return Err(anyhow!("视觉模型错误: {}", message));
+    }
+    value
+        .pointer("/choices/0/message/content")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("响应中无识别结果"))
+}
+
+#[cfg(feature = "tesseract")]
+async fn recognize_with_tesseract(image: &ImageData, config: &OcrConfig) -> Result<String> {
+    use tokio::process::Command;
+
+    let path = std::env::temp_dir().join(format!(
+        "gewe-ocr-{}.{}",
+        uuid::Uuid::new_v4(),
+        super::image::image_extension(&image.mime_type)
+    ));
+    tokio::fs::write(&path, &image.data)
+        .await
+        .map_err(|e| anyhow!("写入临时文件失败: {}", e))?;
+
+    let no_args: [&str; 0] = [];
+    let output = super::spawn::external_command(
+        config.tesseract_path.as_deref().unwrap_or("tesseract"),
+        &no_args,
+    )
+    .arg(&path)
+    .arg("stdout")
+    .arg("-l")
+    .arg(config.languages.as_deref().unwrap_or(DEFAULT_LANGUAGES))
+    .kill_on_drop(true)
+    .output()
+    .await;
+    let _ = tokio::fs::remove_file(&path).await;
+
+    let output = output.map_err(|e| anyhow!("启动 tesseract 失败: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "tesseract 执行失败: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(not(feature = "tesseract"))]
+async fn recognize_with_tesseract(_image: &ImageData, _config: &OcrConfig) -> Result<String> {
+    Err(anyhow!(
+        "未启用 tesseract 支持，请以 `--features tesseract` 编译 gewe-bot-app"
+    ))
+}
+
+/// 截断输出
+fn clamp_output(text: String, max: usize) -> (String, bool) {
+    if text.len() <= max {
+        return (text, false);
+    }
+    let mut cut = max;
+    while cut > 0 && !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let mut truncated = text;
+    truncated.truncate(cut);
+    (truncated, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> ImageData {
+        ImageData {
+            mime_type: "image/png".to_string(),
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn test_vision_request() {
+        let config = OcrConfig {
+            model: Some("qwen-vl-max".to_string()),
+            prompt: Some("提取发票号码".to_string()),
+            ..Default::default()
+        };
+        let value = vision_request(&OcrQuery::default(), &image(), &config);
+        assert_eq!(value["model"], "qwen-vl-max");
+        let content = &value["messages"][0]["content"];
+        assert_eq!(content[0]["text"], "提取发票号码");
+        assert_eq!(
+            content[1]["image_url"]["url"],
+            format!("data:image/png;base64,{}", BASE64.encode([1u8, 2, 3]))
+        );
+
+        let query = OcrQuery::from_json(r#"{"prompt":"只提取表格"}"#);
+        let value = vision_request(&query, &image(), &OcrConfig::default());
+        assert_eq!(value["model"], DEFAULT_MODEL);
+        assert_eq!(value["messages"][0]["content"][0]["text"], "只提取表格");
+    }
+
+    #[test]
+    fn test_parse_vision_response() {
+        let body =
+            r#"{"choices":[{"message":{"role":"assistant","content":"会议纪要\n1. 预算"}}]}"#;
+        assert_eq!(parse_vision_response(body).unwrap(), "会议纪要\n1. 预算");
+
+        let err = parse_vision_response(r#"{"error":{"message":"invalid image"}}"#).unwrap_err();
+        assert!(err.to_string().contains("invalid image"));
+        assert!(parse_vision_response(r#"{"choices":[]}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_ocr_without_image() {
+        let result = run_ocr(OcrQuery::default(), None, &OcrConfig::default(), None, 100).await;
+        assert!(result.error.unwrap().contains("未收到图片"));
+        assert!(!result.timed_out);
+    }
+
+    #[cfg(not(feature = "tesseract"))]
+    #[tokio::test]
+    async fn test_tesseract_requires_feature() {
+        let config = OcrConfig {
+            provider: OcrProviderKind::Tesseract,
+            ..Default::default()
+        };
+        let result = run_ocr(OcrQuery::default(), Some(&image()), &config, None, 100).await;
+        assert!(result.error.unwrap().contains("--features tesseract"));
+    }
+}
@@ -0,0 +1,272 @@
+//! OpenAI 图像生成服务商
+//!
+//! 调用 Images API（`/images/generations`），支持 gpt-image-1、dall-e-3 及兼容端点；
+//! 提供原图时调用 `/images/edits` 编辑图片（gpt-image-1、dall-e-2）。
+
+use super::image::{
+    detect_mime, image_extension, GeneratedImages, ImageConfig, ImageData, ImageProvider,
+    ImageQuery,
+};
+use crate::config::ImageToolConfig;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+
+/// 默认图像生成模型
+const DEFAULT_MODEL: &str = "gpt-image-1";
+/// 默认基础 URL（与 OpenAI 兼容的 AI Profile 一样包含 /v1）
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Debug, Serialize)]
+struct OpenAiImageRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    n: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    style: Option<&'a str>,
+    /// dall-e 系列默认返回 URL，需显式要求 base64；gpt-image-1 不接受该参数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiImageResponse {
+    #[serde(default)]
+    data: Vec<OpenAiImageData>,
+    error: Option<OpenAiError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiImageData {
+    b64_json: Option<String>,
+    url: Option<String>,
+    revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiError {
+    message: String,
+}
+
+/// OpenAI 图像服务商
+pub struct OpenAiImageProvider;
+
+#[async_trait]
+impl ImageProvider for OpenAiImageProvider {
+    async fn generate(&self, query: &ImageQuery, config: &ImageConfig) -> Result<GeneratedImages> {
+        let model = query.model_or(&config.settings, DEFAULT_MODEL);
+        let base_url = config.base_url_or(DEFAULT_BASE_URL);
+        let client = reqwest::Client::new();
+
+        let request = match config.base_image.as_ref() {
+            Some(image) => {
+                tracing::debug!(model, prompt = query.prompt(), "发送 OpenAI 图片编辑请求");
+                let mut form = reqwest::multipart::Form::new();
+                for (name, value) in edit_fields(query, &config.settings, model) {
+                    form = form.text(name, value);
+                }
+                let part = reqwest::multipart::Part::bytes(image.data.clone())
+                    .file_name(format!("image.{}", image_extension(&image.mime_type)))
+                    .mime_str(&image.mime_type)
+                    .map_err(|e| anyhow!("无效的图片类型: {}", e))?;
+                client
+                    .post(format!("{}/images/edits", base_url))
+                    .multipart(form.part("image", part))
+            }
+            None => {
+                let request = build_request(query, &config.settings, model);
+                tracing::debug!(
+                    model,
+                    prompt = query.prompt(),
+                    size = ?request.size,
+                    quality = ?request.quality,
+                    "发送 OpenAI 图像生成请求"
+                );
+                client
+                    .post(format!("{}/images/generations", base_url))
+                    .json(&request)
+            }
+        };
+
+        let response = request
+            .bearer_auth(&config.api_key)
+            .send()
+            .await
+            .map_err(|e| anyhow!("请求失败: {}", e))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| anyhow!("读取响应失败: {}", e))?;
+
+        if !status.is_success() {
+            tracing::warn!(status = %status, body = %body, "OpenAI Images API 错误响应");
+            return Err(anyhow!("API 请求失败 ({}): {}", status, body));
+        }
+
+        let (mut generated, urls) = parse_response(&body)?;
+        // 兼容端点可能只返回 URL，下载后统一保存到本地
+        for url in urls {
+            let resp = client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| anyhow!("下载图片失败: {}", e))?;
+            let mime_type = resp
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .filter(|v| v.starts_with("image/"))
+                .map(|v| v.split(';').next().unwrap_or(v).trim().to_string());
+            let data = resp
+                .bytes()
+                .await
+                .map_err(|e| anyhow!("下载图片失败: {}", e))?
+                .to_vec();
+            generated.images.push(ImageData {
+                mime_type: mime_type.unwrap_or_else(|| detect_mime(&data).to_string()),
+                data,
+            });
+        }
+        Ok(generated)
+    }
+}
+
+/// 构建请求，查询参数优先于工具配置
+fn build_request<'a>(
+    query: &'a ImageQuery,
+    settings: &'a ImageToolConfig,
+    model: &'a str,
+) -> OpenAiImageRequest<'a> {
+    OpenAiImageRequest {
+        model,
+        prompt: query.prompt(),
+        n: 1,
+        size: query.size.as_deref().or(settings.size.as_deref()),
+        quality: query.quality.as_deref().or(settings.quality.as_deref()),
+        style: settings.style.as_deref(),
+        response_format: model.starts_with("dall-e").then_some("b64_json"),
+    }
+}
+
+/// 图片编辑的表单字段（原图单独作为文件字段）
+fn edit_fields(
+    query: &ImageQuery,
+    settings: &ImageToolConfig,
+    model: &str,
+) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("model", model.to_string()),
+        ("prompt", query.prompt().to_string()),
+        ("n", "1".to_string()),
+    ];
+    if let Some(size) = query.size.as_deref().or(settings.size.as_deref()) {
+        fields.push(("size", size.to_string()));
+    }
+    if let Some(quality) = query.quality.as_deref().or(settings.quality.as_deref()) {
+        fields.push(("quality", quality.to_string()));
+    }
+    if model.starts_with("dall-e") {
+        fields.push(("response_format", "b64_json".to_string()));
+    }
+    fields
+}
+
+/// 解析响应，返回已解码的图片与需要下载的 URL
+fn parse_response(body: &str) -> Result<(GeneratedImages, Vec<String>)> {
+    let response: OpenAiImageResponse =
+        serde_json::from_str(body).map_err(|e| anyhow!("解析响应失败: {}", e))?;
+    if let Some(err) = response.error {
+        return Err(anyhow!("OpenAI API 错误: {}", err.message));
+    }
+
+    let mut generated = GeneratedImages::default();
+    let mut urls = Vec::new();
+    for item in response.data {
+        if let Some(prompt) = item.revised_prompt.filter(|p| !p.trim().is_empty()) {
+            generated.text.push(prompt);
+        }
+        if let Some(b64) = item.b64_json {
+            let data = BASE64
+                .decode(&b64)
+                .map_err(|e| anyhow!("Base64 解码失败: {}", e))?;
+            generated.images.push(ImageData {
+                mime_type: detect_mime(&data).to_string(),
+                data,
+            });
+        } else if let Some(url) = item.url {
+            urls.push(url);
+        }
+    }
+    Ok((generated, urls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_request() {
+        let settings = ImageToolConfig {
+            size: Some("1024x1024".to_string()),
+            quality: Some("hd".to_string()),
+            style: Some("natural".to_string()),
+            ..Default::default()
+        };
+        let query = ImageQuery::from_json(r#"{"prompt":"画一只猫","size":"1792x1024"}"#);
+        let value = serde_json::to_value(build_request(&query, &settings, "dall-e-3")).unwrap();
+        assert_eq!(value["model"], "dall-e-3");
+        assert_eq!(value["size"], "1792x1024");
+        assert_eq!(value["quality"], "hd");
+        assert_eq!(value["style"], "natural");
+        assert_eq!(value["response_format"], "b64_json");
+
+        let value = serde_json::to_value(build_request(
+            &query,
+            &ImageToolConfig::default(),
+            DEFAULT_MODEL,
+        ))
+        .unwrap();
+        assert!(value.get("response_format").is_none());
+        assert!(value.get("quality").is_none());
+    }
+
+    #[test]
+    fn test_edit_fields() {
+        let settings = ImageToolConfig {
+            size: Some("1024x1024".to_string()),
+            ..Default::default()
+        };
+        let query = ImageQuery::from_json(r#"{"prompt":"把背景换成海边","quality":"high"}"#);
+        let fields = edit_fields(&query, &settings, DEFAULT_MODEL);
+        assert!(fields.contains(&("prompt", "把背景换成海边".to_string())));
+        assert!(fields.contains(&("size", "1024x1024".to_string())));
+        assert!(fields.contains(&("quality", "high".to_string())));
+        assert!(!fields.iter().any(|(k, _)| *k == "response_format"));
+
+        let fields = edit_fields(&query, &settings, "dall-e-2");
+        assert!(fields.contains(&("response_format", "b64_json".to_string())));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let body = format!(
+            r#"{{"data":[{{"b64_json":"{}","revised_prompt":"一只橘猫"}},{{"url":"https://cdn.example.com/a.png"}}]}}"#,
+            BASE64.encode([0xff, 0xd8, 0xff, 0xe0])
+        );
+        let (generated, urls) = parse_response(&body).unwrap();
+        assert_eq!(generated.text, vec!["一只橘猫"]);
+        assert_eq!(generated.images[0].mime_type, "image/jpeg");
+        assert_eq!(urls, vec!["https://cdn.example.com/a.png"]);
+
+        let err = parse_response(r#"{"error":{"message":"invalid size"}}"#).unwrap_err();
+        assert!(err.to_string().contains("invalid size"));
+    }
+}
@@ -0,0 +1,220 @@
+//! 运营摘要渲染
+//!
+//! 把 [`OpsDigest`] 渲染为邮件用的 HTML 与运营群用的纯文本
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Local, Utc};
+
+use crate::storage::OpsDigest;
+
+/// 文本摘要中列出的规则条数
+const TEXT_TOP_RULES: usize = 5;
+const TABLE_OPEN: &str =
+    "<table border=\"1\" cellpadding=\"4\" cellspacing=\"0\" style=\"border-collapse: collapse;\">";
+
+fn local(at: DateTime<Utc>) -> String {
+    at.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+fn incident_range(start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> String {
+    format!(
+        "{} ~ {}",
+        start.map(local).unwrap_or_else(|| "统计开始前".to_string()),
+        end.map(local).unwrap_or_else(|| "未恢复".to_string())
+    )
+}
+
+fn cost(value: Option<f64>) -> String {
+    value
+        .map(|c| format!("{:.4}", c))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn escape_html(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 摘要标题，如“机器人运营摘要 wx_app 2026-10-14 09:00 ~ 2026-10-15 09:00”
+pub fn digest_title(prefix: &str, digest: &OpsDigest) -> String {
+    format!(
+        "{} {} {} ~ {}",
+        prefix,
+        digest.app_id,
+        local(digest.start),
+        local(digest.end)
+    )
+}
+
+/// 发到运营群的纯文本摘要
+pub fn render_digest_text(title: &str, digest: &OpsDigest) -> String {
+    let mut out = format!("{}\n处理消息：{}\n", title, digest.messages);
+    if !digest.rule_hits.is_empty() {
+        out.push_str("规则命中：\n");
+        for hit in digest.rule_hits.iter().take(TEXT_TOP_RULES) {
+            let _ = writeln!(out, "  {}：{}", hit.rule, hit.hits);
+        }
+    }
+    let calls: u64 = digest.ai.iter().map(|s| s.calls).sum();
+    let _ = writeln!(out, "AI 调用：{} 次，花费约 {:.4}", calls, digest.ai_cost);
+    let _ = writeln!(out, "错误：{}", digest.errors);
+    let _ = write!(out, "离线：{} 次", digest.incidents.len());
+    for incident in &digest.incidents {
+        let _ = write!(out, "\n  {}", incident_range(incident.start, incident.end));
+    }
+    out
+}
+
+/// 邮件用的 HTML 摘要
+pub fn render_digest_html(title: &str, digest: &OpsDigest) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html><html lang=\"zh-CN\"><head><meta charset=\"UTF-8\"><title>{title}</title></head>\
+         <body style=\"font-family: system-ui, sans-serif; color: #333;\"><h2>{title}</h2>\
+         <p>处理消息：<b>{}</b>　AI 花费：<b>{:.4}</b>　错误：<b>{}</b>　离线：<b>{}</b></p>",
+        digest.messages,
+        digest.ai_cost,
+        digest.errors,
+        digest.incidents.len(),
+        title = escape_html(title),
+    );
+
+    out.push_str("<h3>规则命中</h3>");
+    if digest.rule_hits.is_empty() {
+        out.push_str("<p>无</p>");
+    } else {
+        out.push_str(TABLE_OPEN);
+        out.push_str("<tr><th>规则</th><th>命中次数</th></tr>");
+        for hit in &digest.rule_hits {
+            let _ = write!(
+                out,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape_html(&hit.rule),
+                hit.hits
+            );
+        }
+        out.push_str("</table>");
+    }
+
+    out.push_str("<h3>AI 用量</h3>");
+    if digest.ai.is_empty() {
+        out.push_str("<p>无</p>");
+    } else {
+        out.push_str(TABLE_OPEN);
+        out.push_str(
+            "<tr><th>模型</th><th>调用</th><th>输入 token</th><th>输出 token</th><th>花费</th></tr>",
+        );
+        for spend in &digest.ai {
+            let _ = write!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&spend.model),
+                spend.calls,
+                spend.input_tokens,
+                spend.output_tokens,
+                cost(spend.cost)
+            );
+        }
+        out.push_str("</table>");
+    }
+
+    out.push_str("<h3>最近错误</h3>");
+    if digest.recent_errors.is_empty() {
+        out.push_str("<p>无</p>");
+    } else {
+        out.push_str(TABLE_OPEN);
+        out.push_str("<tr><th>时间</th><th>来源</th><th>错误</th></tr>");
+        for error in &digest.recent_errors {
+            let _ = write!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                local(error.at),
+                escape_html(&error.source),
+                escape_html(&error.message)
+            );
+        }
+        out.push_str("</table>");
+    }
+
+    out.push_str("<h3>离线事件</h3>");
+    if digest.incidents.is_empty() {
+        out.push_str("<p>无</p>");
+    } else {
+        out.push_str("<ul>");
+        for incident in &digest.incidents {
+            let _ = write!(
+                out,
+                "<li>{}</li>",
+                incident_range(incident.start, incident.end)
+            );
+        }
+        out.push_str("</ul>");
+    }
+
+    out.push_str("</body></html>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{build_ops_digest, OpsEvent, OpsEventKind};
+    use std::collections::BTreeMap;
+
+    fn digest() -> OpsDigest {
+        let mut events = vec![OpsEvent::new(
+            "wx_app",
+            OpsEventKind::Error {
+                source: "ai".to_string(),
+                message: "<timeout>".to_string(),
+                model: None,
+            },
+        )];
+        for _ in 0..7 {
+            events.push(OpsEvent::new(
+                "wx_app",
+                OpsEventKind::RuleHit {
+                    rule: "faq".to_string(),
+                },
+            ));
+        }
+        for _ in 0..42 {
+            events.push(OpsEvent::new("wx_app", OpsEventKind::Message));
+        }
+        let end = Utc::now() + chrono::Duration::minutes(1);
+        let mut digest = build_ops_digest(
+            &events,
+            "wx_app",
+            end - chrono::Duration::days(1),
+            end,
+            &BTreeMap::new(),
+        );
+        digest.ai_cost = 1.25;
+        digest
+    }
+
+    #[test]
+    fn test_render_digest() {
+        let digest = digest();
+        let title = digest_title("机器人运营摘要", &digest);
+        assert!(title.starts_with("机器人运营摘要 wx_app "));
+
+        let text = render_digest_text(&title, &digest);
+        assert!(text.contains("处理消息：42"));
+        assert!(text.contains("  faq：7"));
+        assert!(text.contains("花费约 1.2500"));
+        assert!(text.ends_with("离线：0 次"));
+
+        let html = render_digest_html(&title, &digest);
+        assert!(html.contains("<td>faq</td><td>7</td>"));
+        assert!(html.contains("&lt;timeout&gt;"));
+        assert!(html.ends_with("</body></html>"));
+    }
+}
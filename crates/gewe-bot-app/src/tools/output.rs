@@ -0,0 +1,75 @@
+//! 内置工具输出契约
+//!
+//! 每个内置工具的结果都先构造为带 `schema_version` 的结构体，再按查询参数中的
+//! `format` 渲染为文本（默认）或 JSON。修改输出结构或文本格式时需同步更新
+//! `tests/golden/` 下的快照并在不兼容时提升对应的 schema 版本。
+
+use serde::Serialize;
+
+/// 工具输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 人类可读的文本，直接作为回复或提示词上下文
+    #[default]
+    Text,
+    /// 结构化 JSON，字段由各工具的输出结构体定义
+    Json,
+}
+
+impl OutputFormat {
+    /// 解析查询参数中的 `format`，无法识别时回退为文本
+    pub fn parse(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            Some("json") => Self::Json,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// 有固定输出结构的内置工具结果
+pub trait ToolOutput: Serialize {
+    /// 文本格式
+    fn to_text(&self) -> String;
+
+    /// 按格式渲染
+    fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.to_text(),
+            OutputFormat::Json => serde_json::to_string_pretty(self)
+                .unwrap_or_else(|e| format!("序列化工具输出失败: {}", e)),
+        }
+    }
+}
+
+/// 与 `tests/golden/{name}` 中的快照比对；设置 `UPDATE_GOLDEN=1` 时重写快照
+#[cfg(test)]
+pub(crate) fn assert_golden(name: &str, actual: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("读取快照 {} 失败: {}", path.display(), e));
+    assert_eq!(
+        actual, expected,
+        "工具输出与快照 {} 不一致，确认变更后使用 UPDATE_GOLDEN=1 重新生成",
+        name
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_format_parse() {
+        assert_eq!(OutputFormat::parse(None), OutputFormat::Text);
+        assert_eq!(OutputFormat::parse(Some("JSON")), OutputFormat::Json);
+        assert_eq!(OutputFormat::parse(Some(" json ")), OutputFormat::Json);
+        assert_eq!(OutputFormat::parse(Some("markdown")), OutputFormat::Text);
+    }
+}
@@ -0,0 +1,247 @@
+//! PDF 文字提取
+//!
+//! 基于 lopdf 解析文档结构（含对象流、FlateDecode 与字体的 ToUnicode CMap），
+//! 这里只负责按页拼接文字并整理空行。扫描件与无法用空密码解密的文档无法提取。
+
+use anyhow::{anyhow, Result};
+use lopdf::{Document, Error as PdfError, LoadOptions};
+
+/// 单个流解压后的最大字节数，防止压缩炸弹
+const MAX_STREAM_BYTES: usize = 64 * 1024 * 1024;
+
+/// 提取 PDF 中的文字，页面之间以空行分隔
+pub(super) fn extract_pdf_text(data: &[u8]) -> Result<String> {
+    if !data[..data.len().min(1024)]
+        .windows(4)
+        .any(|w| w == b"%PDF")
+    {
+        return Err(anyhow!("不是有效的 PDF 文件"));
+    }
+    let options = LoadOptions {
+        max_decompressed_size: Some(MAX_STREAM_BYTES),
+        ..Default::default()
+    };
+    let doc = Document::load_mem_with_options(data, options).map_err(|e| match e {
+        PdfError::InvalidPassword | PdfError::Decryption(_) => anyhow!("PDF 已加密，无法提取文字"),
+        e => anyhow!("无法解析 PDF 结构: {}", e),
+    })?;
+    if doc.is_encrypted() {
+        return Err(anyhow!("PDF 已加密，无法提取文字"));
+    }
+
+    let mut out = String::new();
+    for page in doc.get_pages().into_keys() {
+        // 单个字体或内容流解析失败时保留该页其余能识别的文字
+        let raw: String = doc
+            .extract_text_chunks_with_limit(&[page], MAX_STREAM_BYTES)
+            .into_iter()
+            .filter_map(|chunk| chunk.ok())
+            .collect();
+        let text = normalize_lines(&raw);
+        if !text.is_empty() {
+            if !out.is_empty() {
+                out.push_str("\n\n");
+            }
+            out.push_str(&text);
+        }
+    }
+    Ok(out)
+}
+
+/// 去掉行尾空白并合并多余的空行
+fn normalize_lines(text: &str) -> String {
+    let mut out = String::new();
+    let mut blank = 0;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        out.push_str(line);
+        blank = 0;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Object, ObjectId, Stream};
+
+    fn content(doc: &mut Document, ops: &[u8], compress: bool) -> ObjectId {
+        let mut stream = Stream::new(dictionary! {}, ops.to_vec());
+        if compress {
+            stream.compress().unwrap();
+        }
+        doc.add_object(stream)
+    }
+
+    /// 按给定顺序组装页面树并序列化
+    fn finish(mut doc: Document, pages_id: ObjectId, pages: Vec<ObjectId>) -> Vec<u8> {
+        let count = pages.len() as i64;
+        let kids: Vec<Object> = pages.into_iter().map(Object::Reference).collect();
+        let pages = doc
+            .get_object_mut(pages_id)
+            .and_then(Object::as_dict_mut)
+            .unwrap();
+        pages.set("Kids", kids);
+        pages.set("Count", count);
+        let catalog = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog);
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_extract_plain_text_in_page_order() {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let resources = doc.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Resources" => resources,
+            }),
+        );
+        let second = content(
+            &mut doc,
+            b"BT /F1 12 Tf 72 700 Td (Second page) Tj ET",
+            false,
+        );
+        let first = content(
+            &mut doc,
+            b"BT /F1 12 Tf 72 700 Td (Hello World \\(1\\)) Tj ET BT /F1 12 Tf 72 686 Td (Next line) Tj ET",
+            true,
+        );
+        let page_two = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => second,
+        });
+        let page_one = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => first,
+        });
+        // 页面顺序以页面树为准，而不是对象编号
+        let data = finish(doc, pages_id, vec![page_one, page_two]);
+        assert_eq!(
+            extract_pdf_text(&data).unwrap(),
+            "Hello World (1)\nNext line\n\nSecond page"
+        );
+    }
+
+    #[test]
+    fn test_extract_with_to_unicode() {
+        let cmap = b"/CIDInit /ProcSet findresource begin
+12 dict begin
+begincmap
+/CMapName /Adobe-Identity-UCS def
+/CMapType 2 def
+1 begincodespacerange
+<0000> <FFFF>
+endcodespacerange
+2 beginbfchar
+<0001> <4F60>
+<0002> <597D>
+endbfchar
+1 beginbfrange
+<0010> <0011> <4E16>
+endbfrange
+endcmap
+CMapName currentdict /CMap defineresource pop
+end
+end";
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let to_unicode = content(&mut doc, cmap, true);
+        let font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => "SimSun",
+            "Encoding" => "Identity-H",
+            "ToUnicode" => to_unicode,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages" }),
+        );
+        let first = content(
+            &mut doc,
+            b"BT /F2 10.5 Tf 1 0 0 1 72 700 Tm <0001> Tj 10.5 0 Td <0002> Tj ET",
+            true,
+        );
+        let second = content(
+            &mut doc,
+            b"BT /F2 10.5 Tf 1 0 0 1 72 680 Tm <00100011> Tj ET",
+            false,
+        );
+        let page = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F2" => font } },
+            "Contents" => vec![Object::Reference(first), Object::Reference(second)],
+        });
+        let data = finish(doc, pages_id, vec![page]);
+        assert_eq!(extract_pdf_text(&data).unwrap(), "你好\n世丗");
+    }
+
+    #[test]
+    fn test_extract_rejects_encrypted() {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages" }),
+        );
+        let encrypt = doc.add_object(dictionary! {
+            "Filter" => "Standard",
+            "V" => 2,
+            "R" => 3,
+            "Length" => 128,
+            "P" => -44,
+            "O" => Object::string_literal(vec![1u8; 32]),
+            "U" => Object::string_literal(vec![2u8; 32]),
+        });
+        doc.trailer.set("Encrypt", encrypt);
+        doc.trailer.set(
+            "ID",
+            vec![
+                Object::string_literal(vec![3u8; 16]),
+                Object::string_literal(vec![3u8; 16]),
+            ],
+        );
+        let data = finish(doc, pages_id, Vec::new());
+        let err = extract_pdf_text(&data).unwrap_err();
+        assert!(err.to_string().contains("已加密"), "{err}");
+    }
+
+    #[test]
+    fn test_extract_rejects_invalid() {
+        let err = extract_pdf_text(b"PK\x03\x04").unwrap_err();
+        assert!(err.to_string().contains("不是有效的 PDF"));
+        assert!(extract_pdf_text(b"%PDF-1.7\ngarbage").is_err());
+    }
+
+    #[test]
+    fn test_normalize_lines() {
+        assert_eq!(normalize_lines("a  \n\n\n b\nc \n"), "a\n\n b\nc");
+        assert_eq!(normalize_lines("\n \n"), "");
+    }
+}
@@ -0,0 +1,293 @@
+//! 外置命令进程池
+//!
+//! 限制同时运行的外置命令进程数，同一规则的命令按 `per_rule` 并发执行、其余排队；
+//! 排队过长或系统负载、可用内存越过水位线时直接拒绝，避免消息风暴时进程失控。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::CommandPoolConfig;
+
+pub const DEFAULT_MAX_PROCESSES: usize = 4;
+pub const DEFAULT_PER_RULE_PROCESSES: usize = 1;
+pub const DEFAULT_MAX_RULE_QUEUE: usize = 8;
+pub const DEFAULT_BUSY_REPLY: &str = "当前请求较多，请稍后再试";
+
+/// 拒绝执行的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolRejection {
+    /// 系统负载或内存越过水位线
+    Overloaded(String),
+    /// 该规则排队的命令已达上限
+    QueueFull,
+}
+
+impl std::fmt::Display for PoolRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Overloaded(reason) => write!(f, "系统负载过高：{}", reason),
+            Self::QueueFull => write!(f, "排队的命令过多"),
+        }
+    }
+}
+
+/// 系统负载快照，无法读取的项为 None
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SystemLoad {
+    /// 1 分钟平均负载除以 CPU 核数
+    pub load_per_cpu: Option<f64>,
+    /// 可用内存（MiB）
+    pub available_memory_mb: Option<u64>,
+}
+
+impl SystemLoad {
+    /// 读取当前负载；仅 Linux 提供 /proc，其他系统返回空快照
+    pub fn read() -> Self {
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let load_per_cpu = std::fs::read_to_string("/proc/loadavg")
+            .ok()
+            .and_then(|s| parse_loadavg(&s))
+            .map(|load| load / cpus as f64);
+        let available_memory_mb = std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|s| parse_mem_available_mb(&s));
+        Self {
+            load_per_cpu,
+            available_memory_mb,
+        }
+    }
+}
+
+fn parse_loadavg(content: &str) -> Option<f64> {
+    content.split_whitespace().next()?.parse().ok()
+}
+
+fn parse_mem_available_mb(content: &str) -> Option<u64> {
+    let line = content
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
+
+/// 按水位线判断是否过载，返回原因
+pub fn watermark_reason(cfg: &CommandPoolConfig, load: &SystemLoad) -> Option<String> {
+    if let (Some(max), Some(current)) = (cfg.max_load, load.load_per_cpu) {
+        if current > max {
+            return Some(format!("每核负载 {:.2} 超过 {:.2}", current, max));
+        }
+    }
+    if let (Some(min), Some(current)) = (cfg.min_free_memory_mb, load.available_memory_mb) {
+        if current < min {
+            return Some(format!("可用内存 {} MiB 低于 {} MiB", current, min));
+        }
+    }
+    None
+}
+
+struct RuleQueue {
+    running: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+/// 持有期间占用一个进程名额
+#[derive(Debug)]
+pub struct ProcessPermit {
+    _rule: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+pub struct ProcessPool {
+    cfg: CommandPoolConfig,
+    global: Arc<Semaphore>,
+    rules: Mutex<HashMap<String, Arc<RuleQueue>>>,
+}
+
+impl ProcessPool {
+    pub fn new(cfg: &CommandPoolConfig) -> Self {
+        let max = cfg
+            .max_processes
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_MAX_PROCESSES);
+        Self {
+            cfg: cfg.clone(),
+            global: Arc::new(Semaphore::new(max)),
+            rules: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn per_rule(&self) -> usize {
+        self.cfg
+            .per_rule
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_PER_RULE_PROCESSES)
+    }
+
+    fn max_queue(&self) -> usize {
+        self.cfg.max_queue.unwrap_or(DEFAULT_MAX_RULE_QUEUE)
+    }
+
+    /// 拒绝时回复给用户的文字
+    pub fn busy_reply(&self) -> &str {
+        self.cfg
+            .busy_reply
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or(DEFAULT_BUSY_REPLY)
+    }
+
+    /// 检查系统水位线，过载时返回拒绝原因
+    pub fn check_load(&self) -> Result<(), PoolRejection> {
+        if self.cfg.max_load.is_none() && self.cfg.min_free_memory_mb.is_none() {
+            return Ok(());
+        }
+        match watermark_reason(&self.cfg, &SystemLoad::read()) {
+            Some(reason) => Err(PoolRejection::Overloaded(reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// 申请一个进程名额：先在规则内排队，再占用全局名额
+    pub async fn acquire(&self, rule: &str) -> Result<ProcessPermit, PoolRejection> {
+        self.check_load()?;
+        let per_rule = self.per_rule();
+        let queue = {
+            let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+            // 没有运行或等待中命令的规则不再保留
+            rules.retain(|_, q| {
+                Arc::strong_count(q) > 1 || q.running.available_permits() < per_rule
+            });
+            rules
+                .entry(rule.to_string())
+                .or_insert_with(|| {
+                    Arc::new(RuleQueue {
+                        running: Arc::new(Semaphore::new(per_rule)),
+                        waiting: AtomicUsize::new(0),
+                    })
+                })
+                .clone()
+        };
+
+        let rule_permit = match queue.running.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                if queue.waiting.fetch_add(1, Ordering::SeqCst) >= self.max_queue() {
+                    queue.waiting.fetch_sub(1, Ordering::SeqCst);
+                    return Err(PoolRejection::QueueFull);
+                }
+                let permit = queue.running.clone().acquire_owned().await;
+                queue.waiting.fetch_sub(1, Ordering::SeqCst);
+                permit.map_err(|_| PoolRejection::QueueFull)?
+            }
+        };
+        let global_permit = self
+            .global
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| PoolRejection::QueueFull)?;
+        Ok(ProcessPermit {
+            _rule: rule_permit,
+            _global: global_permit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_proc_files() {
+        assert_eq!(parse_loadavg("0.98 0.91 0.90 2/73 10830\n"), Some(0.98));
+        assert_eq!(parse_loadavg(""), None);
+        let meminfo = "MemTotal:       16303428 kB\nMemFree:          412340 kB\nMemAvailable:    2097152 kB\n";
+        assert_eq!(parse_mem_available_mb(meminfo), Some(2048));
+        assert_eq!(parse_mem_available_mb("MemTotal: 1 kB"), None);
+    }
+
+    #[test]
+    fn test_watermark_reason() {
+        let cfg = CommandPoolConfig {
+            max_load: Some(1.5),
+            min_free_memory_mb: Some(512),
+            ..Default::default()
+        };
+        let calm = SystemLoad {
+            load_per_cpu: Some(0.4),
+            available_memory_mb: Some(4096),
+        };
+        assert_eq!(watermark_reason(&cfg, &calm), None);
+        let busy = SystemLoad {
+            load_per_cpu: Some(2.0),
+            ..calm
+        };
+        assert!(watermark_reason(&cfg, &busy)
+            .unwrap()
+            .contains("每核负载 2.00"));
+        let low_mem = SystemLoad {
+            available_memory_mb: Some(100),
+            ..calm
+        };
+        assert!(watermark_reason(&cfg, &low_mem)
+            .unwrap()
+            .contains("可用内存 100 MiB"));
+        // 读取不到的指标不参与判断
+        assert_eq!(watermark_reason(&cfg, &SystemLoad::default()), None);
+    }
+
+    #[tokio::test]
+    async fn test_process_pool_rule_queue() {
+        let pool = Arc::new(ProcessPool::new(&CommandPoolConfig {
+            max_processes: Some(2),
+            per_rule: Some(1),
+            max_queue: Some(1),
+            ..Default::default()
+        }));
+        let first = pool.acquire("deploy").await.unwrap();
+
+        // 同一规则第二个命令排队
+        let waiter = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.acquire("deploy").await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+        // 排队已满时直接拒绝
+        assert_eq!(
+            pool.acquire("deploy").await.unwrap_err(),
+            PoolRejection::QueueFull
+        );
+        // 其他规则不受影响
+        let other = pool.acquire("report").await.unwrap();
+
+        drop(first);
+        drop(other);
+        waiter.await.unwrap().unwrap();
+        assert_eq!(pool.busy_reply(), DEFAULT_BUSY_REPLY);
+    }
+
+    #[tokio::test]
+    async fn test_process_pool_global_limit() {
+        let pool = Arc::new(ProcessPool::new(&CommandPoolConfig {
+            max_processes: Some(1),
+            per_rule: Some(2),
+            ..Default::default()
+        }));
+        let first = pool.acquire("a").await.unwrap();
+        let waiter = {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.acquire("b").await.map(|_| ()) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+        drop(first);
+        waiter.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+//! 提醒命令
+//!
+//! 支持 `/remind me in 2h to ...`、`/remind 明天9点 开会`、`/remind list`、`/remind cancel 3`，
+//! 时间表达式同时支持中文与英文的相对/绝对时间
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// 默认命令前缀
+pub const DEFAULT_REMIND_PREFIX: &str = "/remind";
+
+/// 提醒命令
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemindCommand<Tz: TimeZone> {
+    Add { due_at: DateTime<Tz>, text: String },
+    List,
+    Cancel(u64),
+    Help,
+}
+
+/// 解析命令文本；不以前缀开头时返回 None
+pub fn parse_remind_command<Tz: TimeZone>(
+    prefix: &str,
+    text: &str,
+    now: DateTime<Tz>,
+) -> Option<RemindCommand<Tz>> {
+    let rest = text.trim().strip_prefix(prefix)?;
+    if rest.chars().next().is_some_and(|c| !c.is_whitespace()) {
+        return None;
+    }
+    let rest = rest.trim();
+    let lower = rest.to_ascii_lowercase();
+    if lower == "list" || lower == "ls" || rest == "列表" {
+        return Some(RemindCommand::List);
+    }
+    for verb in ["cancel ", "del ", "取消"] {
+        if let Some(arg) = lower.strip_prefix(verb) {
+            return arg.trim().parse().ok().map(RemindCommand::Cancel);
+        }
+    }
+
+    // 去掉 "me" / "我"，再解析时间
+    let body = strip_word(rest, "me").unwrap_or(rest);
+    let body = body.strip_prefix('我').unwrap_or(body).trim_start();
+    let Some((due_at, remaining)) = parse_remind_time(body, now) else {
+        return Some(RemindCommand::Help);
+    };
+    let remaining = remaining.trim_start();
+    let text = strip_word(remaining, "to").unwrap_or(remaining);
+    let text = text.strip_prefix("提醒我").unwrap_or(text);
+    let text = text.trim_start_matches([':', '：', ',', '，']).trim();
+    if text.is_empty() {
+        return Some(RemindCommand::Help);
+    }
+    Some(RemindCommand::Add {
+        due_at,
+        text: text.to_string(),
+    })
+}
+
+/// 去掉开头的英文单词（需后接空白），不区分大小写
+fn strip_word<'a>(s: &'a str, word: &str) -> Option<&'a str> {
+    let head = s.get(..word.len())?;
+    let rest = &s[word.len()..];
+    (head.eq_ignore_ascii_case(word) && rest.starts_with(char::is_whitespace))
+        .then(|| rest.trim_start())
+}
+
+fn en_relative_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)^in\s+((?:\d+\s*(?:days?|d|hours?|hrs?|h|minutes?|mins?|m|seconds?|secs?|s)\b\s*)+)",
+        )
+        .expect("valid regex")
+    })
+}
+
+fn en_absolute_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)^(?:(today|tomorrow)\s+)?(at\s+)?(\d{1,2})(?::(\d{2}))?\s*(am|pm)?\b")
+            .expect("valid regex")
+    })
+}
+
+fn zh_relative_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^((?:(?:\d+|半)\s*个?\s*(?:天|小时|钟头|分钟|分|秒钟|秒)\s*)+)[以之]?后")
+            .expect("valid regex")
+    })
+}
+
+fn zh_absolute_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^(今天|明天|后天)?\s*(凌晨|早上|上午|中午|下午|傍晚|晚上)?\s*(\d{1,2})\s*(?:[:：](\d{2})|[点时](半|\d{1,2}\s*分?)?)",
+        )
+        .expect("valid regex")
+    })
+}
+
+fn unit_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(\d+|半)\s*个?\s*([a-z]+|天|小时|钟头|分钟|分|秒钟|秒)").expect("valid regex")
+    })
+}
+
+/// 解析开头的时间表达式，返回到期时间与剩余文本
+pub fn parse_remind_time<Tz: TimeZone>(
+    input: &str,
+    now: DateTime<Tz>,
+) -> Option<(DateTime<Tz>, &str)> {
+    let input = input.trim_start();
+
+    for re in [en_relative_regex(), zh_relative_regex()] {
+        if let Some(caps) = re.captures(input) {
+            let offset = parse_duration(&caps[1])?;
+            let end = caps.get(0)?.end();
+            return Some((now + offset, &input[end..]));
+        }
+    }
+
+    if let Some(caps) = en_absolute_regex().captures(input) {
+        let day = caps.get(1).map(|m| m.as_str().to_ascii_lowercase());
+        let has_at = caps.get(2).is_some();
+        let minute = caps.get(4).map(|m| m.as_str());
+        let ampm = caps.get(5).map(|m| m.as_str().to_ascii_lowercase());
+        // 纯数字开头（如 "2 apples"）不视为时间
+        if day.is_some() || has_at || minute.is_some() || ampm.is_some() {
+            let mut hour: u32 = caps[3].parse().ok()?;
+            match ampm.as_deref() {
+                Some("pm") if hour < 12 => hour += 12,
+                Some("am") if hour == 12 => hour = 0,
+                _ => {}
+            }
+            let minute = minute.map(|m| m.parse().ok()).unwrap_or(Some(0))?;
+            let days = match day.as_deref() {
+                Some("today") => Some(0),
+                Some("tomorrow") => Some(1),
+                _ => None,
+            };
+            let due = at_time(&now, days, hour, minute)?;
+            return Some((due, &input[caps.get(0)?.end()..]));
+        }
+    }
+
+    if let Some(caps) = zh_absolute_regex().captures(input) {
+        let days = caps.get(1).map(|m| match m.as_str() {
+            "明天" => 1,
+            "后天" => 2,
+            _ => 0,
+        });
+        let mut hour: u32 = caps[3].parse().ok()?;
+        if let Some(period) = caps.get(2) {
+            match period.as_str() {
+                "下午" | "傍晚" | "晚上" if hour < 12 => hour += 12,
+                "中午" if hour < 11 => hour += 12,
+                "凌晨" if hour == 12 => hour = 0,
+                _ => {}
+            }
+        }
+        let minute = match (caps.get(4), caps.get(5)) {
+            (Some(m), _) => m.as_str().parse().ok()?,
+            (None, Some(m)) if m.as_str() == "半" => 30,
+            (None, Some(m)) => m.as_str().trim_end_matches('分').trim().parse().ok()?,
+            (None, None) => 0,
+        };
+        let due = at_time(&now, days, hour, minute)?;
+        return Some((due, &input[caps.get(0)?.end()..]));
+    }
+
+    None
+}
+
+fn parse_duration(expr: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    for caps in unit_regex().captures_iter(&expr.to_ascii_lowercase()) {
+        let half = &caps[1] == "半";
+        let n: i64 = if half { 0 } else { caps[1].parse().ok()? };
+        let unit = match &caps[2] {
+            "d" | "day" | "days" | "天" => Duration::days(1),
+            "h" | "hr" | "hrs" | "hour" | "hours" | "小时" | "钟头" => Duration::hours(1),
+            "m" | "min" | "mins" | "minute" | "minutes" | "分钟" | "分" => Duration::minutes(1),
+            "s" | "sec" | "secs" | "second" | "seconds" | "秒钟" | "秒" => Duration::seconds(1),
+            _ => return None,
+        };
+        total += if half { unit / 2 } else { unit * n as i32 };
+    }
+    (total > Duration::zero()).then_some(total)
+}
+
+/// 计算某天的指定时刻；未指定日期且时刻已过时顺延到明天
+fn at_time<Tz: TimeZone>(
+    now: &DateTime<Tz>,
+    days: Option<i64>,
+    hour: u32,
+    minute: u32,
+) -> Option<DateTime<Tz>> {
+    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+    let date = now.date_naive() + Duration::days(days.unwrap_or(0));
+    let mut due = now
+        .timezone()
+        .from_local_datetime(&date.and_time(time))
+        .earliest()?;
+    if days.is_none() && due <= *now {
+        due += Duration::days(1);
+    }
+    Some(due)
+}
+
+/// 渲染到期时间：`今天 15:00`、`明天 09:00`、`03-15 14:00`
+pub fn format_due<Tz: TimeZone>(due_at: &DateTime<Tz>, now: &DateTime<Tz>) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let days = (due_at.date_naive() - now.date_naive()).num_days();
+    let day = match days {
+        0 => "今天".to_string(),
+        1 => "明天".to_string(),
+        2 => "后天".to_string(),
+        _ => due_at.format("%m-%d").to_string(),
+    };
+    format!("{} {}", day, due_at.format("%H:%M"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2026, 3, 10, 14, 0, 0)
+            .unwrap()
+    }
+
+    fn add(cmd: Option<RemindCommand<FixedOffset>>) -> (String, String) {
+        match cmd {
+            Some(RemindCommand::Add { due_at, text }) => {
+                (due_at.format("%m-%d %H:%M").to_string(), text)
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_english() {
+        let p = DEFAULT_REMIND_PREFIX;
+        assert_eq!(
+            add(parse_remind_command(
+                p,
+                "/remind me in 2h to drink water",
+                now()
+            )),
+            ("03-10 16:00".to_string(), "drink water".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(
+                p,
+                "/remind in 1 hour 30 minutes stand up",
+                now()
+            )),
+            ("03-10 15:30".to_string(), "stand up".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(
+                p,
+                "/remind me tomorrow at 9am to call mom",
+                now()
+            )),
+            ("03-11 09:00".to_string(), "call mom".to_string())
+        );
+        // 已过的时刻顺延到明天
+        assert_eq!(
+            add(parse_remind_command(p, "/remind at 10:30 review", now())),
+            ("03-11 10:30".to_string(), "review".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(p, "/remind 5pm ship it", now())),
+            ("03-10 17:00".to_string(), "ship it".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_chinese() {
+        let p = DEFAULT_REMIND_PREFIX;
+        assert_eq!(
+            add(parse_remind_command(p, "/remind 30分钟后 喝水", now())),
+            ("03-10 14:30".to_string(), "喝水".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(
+                p,
+                "/remind 我半小时后提醒我开会",
+                now()
+            )),
+            ("03-10 14:30".to_string(), "开会".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(
+                p,
+                "/remind 明天上午9点半 交周报",
+                now()
+            )),
+            ("03-11 09:30".to_string(), "交周报".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(p, "/remind 晚上8点 健身", now())),
+            ("03-10 20:00".to_string(), "健身".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(p, "/remind 后天 10:15 体检", now())),
+            ("03-12 10:15".to_string(), "体检".to_string())
+        );
+        assert_eq!(
+            add(parse_remind_command(p, "/remind 2天后：续费", now())),
+            ("03-12 14:00".to_string(), "续费".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        let p = DEFAULT_REMIND_PREFIX;
+        assert_eq!(
+            parse_remind_command(p, "/remind list", now()),
+            Some(RemindCommand::List)
+        );
+        assert_eq!(
+            parse_remind_command(p, "/remind cancel 3", now()),
+            Some(RemindCommand::Cancel(3))
+        );
+        assert_eq!(
+            parse_remind_command(p, "/remind 取消 4", now()),
+            Some(RemindCommand::Cancel(4))
+        );
+        assert_eq!(
+            parse_remind_command(p, "/remind 2 apples", now()),
+            Some(RemindCommand::Help)
+        );
+        assert_eq!(
+            parse_remind_command(p, "/remind in 2h", now()),
+            Some(RemindCommand::Help)
+        );
+        assert_eq!(parse_remind_command(p, "/reminders", now()), None);
+    }
+
+    #[test]
+    fn test_format_due() {
+        let n = now();
+        assert_eq!(format_due(&(n + Duration::hours(1)), &n), "今天 15:00");
+        assert_eq!(format_due(&(n + Duration::days(1)), &n), "明天 14:00");
+        assert_eq!(format_due(&(n + Duration::days(5)), &n), "03-15 14:00");
+    }
+}
@@ -0,0 +1,119 @@
+//! SMTP 邮件发送
+//!
+//! 仅用于发送运营摘要等 HTML 邮件，基于 lettre：隐式 TLS（SMTPS）或强制 STARTTLS，
+//! 不支持明文连接，认证信息不会在未加密的连接上发送
+
+use std::time::Duration;
+
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::DigestEmailConfig;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 发送一封 HTML 邮件
+pub async fn send_html_mail(
+    cfg: &DigestEmailConfig,
+    subject: &str,
+    html: &str,
+) -> Result<(), String> {
+    let message = build_message(&cfg.from, &cfg.to, subject, html)?;
+    let transport = build_transport(cfg)?;
+    transport
+        .send(message)
+        .await
+        .map_err(|e| format!("SMTP 发送失败 {}: {}", cfg.smtp_host, e))?;
+    Ok(())
+}
+
+/// 按配置创建传输：tls 为 true 时使用隐式 TLS，否则要求服务器支持 STARTTLS
+fn build_transport(cfg: &DigestEmailConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+    let tls = cfg.tls.unwrap_or(true);
+    let builder = if tls {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.smtp_host)
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.smtp_host)
+    }
+    .map_err(|e| format!("无效的 SMTP 主机 {}: {}", cfg.smtp_host, e))?;
+    let mut builder = builder
+        .port(cfg.smtp_port.unwrap_or(if tls { 465 } else { 587 }))
+        .timeout(Some(SMTP_TIMEOUT));
+    if let Some(username) = cfg.username.as_deref() {
+        let password = match (&cfg.password, &cfg.password_env) {
+            (Some(password), _) => password.clone(),
+            (None, Some(env)) => {
+                std::env::var(env).map_err(|_| format!("未找到 SMTP 密码环境变量 {}", env))?
+            }
+            (None, None) => String::new(),
+        };
+        builder = builder.credentials(Credentials::new(username.to_string(), password));
+    }
+    Ok(builder.build())
+}
+
+/// 组装邮件；地址按 RFC 5322 解析，含换行等非法字符时拒绝
+fn build_message(from: &str, to: &[String], subject: &str, html: &str) -> Result<Message, String> {
+    let mailbox = |addr: &str| -> Result<Mailbox, String> {
+        addr.parse()
+            .map_err(|e| format!("无效的邮件地址 {:?}: {}", addr, e))
+    };
+    let mut builder = Message::builder()
+        .from(mailbox(from)?)
+        .subject(subject)
+        .header(ContentType::TEXT_HTML);
+    for addr in to {
+        builder = builder.to(mailbox(addr)?);
+    }
+    builder
+        .body(html.to_string())
+        .map_err(|e| format!("组装邮件失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_message() {
+        let to = vec!["ops@example.com".to_string(), "dev@example.com".to_string()];
+        let message = build_message("bot@example.com", &to, "运营摘要", "<p>ok</p>").unwrap();
+        let envelope = message.envelope();
+        assert_eq!(envelope.from().unwrap().to_string(), "bot@example.com");
+        assert_eq!(envelope.to().len(), 2);
+        let raw = String::from_utf8(message.formatted()).unwrap();
+        assert!(raw.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(raw.contains("Subject: =?utf-8?"));
+    }
+
+    #[test]
+    fn test_build_message_rejects_header_injection() {
+        let to = vec!["ops@example.com\r\nBcc: evil@example.com".to_string()];
+        let err = build_message("bot@example.com", &to, "s", "x").unwrap_err();
+        assert!(err.contains("无效的邮件地址"));
+        let to = vec!["ops@example.com".to_string()];
+        assert!(build_message("bot@example.com\nBcc: evil@example.com", &to, "s", "x").is_err());
+    }
+
+    #[test]
+    fn test_build_transport() {
+        let cfg = DigestEmailConfig {
+            smtp_host: "smtp.example.com".to_string(),
+            tls: Some(false),
+            username: Some("bot".to_string()),
+            password: Some("secret".to_string()),
+            from: "bot@example.com".to_string(),
+            to: vec!["ops@example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(build_transport(&cfg).is_ok());
+
+        let cfg = DigestEmailConfig {
+            password_env: Some("GEWE_TEST_SMTP_PASSWORD_MISSING".to_string()),
+            password: None,
+            ..cfg
+        };
+        assert!(build_transport(&cfg).unwrap_err().contains("环境变量"));
+    }
+}
@@ -0,0 +1,218 @@
+//! 跨平台的外置命令启动
+//!
+//! Unix 上直接执行 program；Windows 上按 PATHEXT 补全无扩展名的程序（如 npm 安装的 `claude.cmd`），
+//! `.ps1` 脚本经 powershell 执行。`.cmd` / `.bat` 由标准库交给 cmd.exe 执行并负责参数转义，
+//! 不再额外拼接 `cmd /C`，避免参数被二次解析。
+
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+
+const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+/// 实际启动的程序与参数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLine {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+/// 按当前平台构造命令，调用方继续设置环境变量与输入输出
+pub fn external_command<S: AsRef<str>>(program: &str, args: &[S]) -> Command {
+    let args: Vec<String> = args.iter().map(|a| a.as_ref().to_string()).collect();
+    let line = if cfg!(windows) {
+        let dirs: Vec<PathBuf> = std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect())
+            .unwrap_or_default();
+        let pathext = std::env::var("PATHEXT").ok();
+        windows_command_line(program, args, &dirs, pathext.as_deref(), &|p| p.is_file())
+    } else {
+        CommandLine {
+            program: program.to_string(),
+            args,
+        }
+    };
+    let mut cmd = Command::new(&line.program);
+    cmd.args(&line.args);
+    cmd
+}
+
+/// Windows 下的命令行：补全扩展名，`.ps1` 改由 powershell 执行
+fn windows_command_line(
+    program: &str,
+    args: Vec<String>,
+    dirs: &[PathBuf],
+    pathext: Option<&str>,
+    is_file: &dyn Fn(&Path) -> bool,
+) -> CommandLine {
+    let program = resolve_windows_program(program, dirs, pathext, is_file)
+        .unwrap_or_else(|| program.to_string());
+    let is_ps1 = Path::new(&program)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ps1"));
+    if is_ps1 {
+        let mut shim_args: Vec<String> = [
+            "-NoProfile",
+            "-NonInteractive",
+            "-ExecutionPolicy",
+            "Bypass",
+            "-File",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        shim_args.push(program);
+        shim_args.extend(args);
+        return CommandLine {
+            program: "powershell".to_string(),
+            args: shim_args,
+        };
+    }
+    CommandLine { program, args }
+}
+
+/// 为无扩展名的程序按 PATHEXT 顺序查找实际文件；带目录的程序只在该目录查找
+fn resolve_windows_program(
+    program: &str,
+    dirs: &[PathBuf],
+    pathext: Option<&str>,
+    is_file: &dyn Fn(&Path) -> bool,
+) -> Option<String> {
+    if program.is_empty() || Path::new(program).extension().is_some() {
+        return None;
+    }
+    let exts: Vec<&str> = pathext
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or(DEFAULT_PATHEXT)
+        .split(';')
+        .map(str::trim)
+        .filter(|ext| !ext.is_empty())
+        .collect();
+    let has_dir = program.contains(['/', '\\']);
+    let candidates: Vec<PathBuf> = if has_dir {
+        vec![PathBuf::new()]
+    } else {
+        dirs.to_vec()
+    };
+    for dir in candidates {
+        for ext in &exts {
+            let candidate = dir.join(format!("{}{}", program, ext.to_ascii_lowercase()));
+            if is_file(&candidate) {
+                return Some(candidate.to_string_lossy().into_owned());
+            }
+        }
+    }
+    None
+}
+
+/// 把模板渲染出的值转为可用于文件名的片段：替换路径分隔符与 Windows 保留字符，
+/// 并避开 CON、NUL 等设备名
+pub fn sanitize_file_component(value: &str) -> String {
+    let mut out: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows 不允许文件名以空格或句点结尾
+    while out.ends_with([' ', '.']) {
+        out.pop();
+    }
+    let stem = out
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.len() == 4
+            && stem.as_bytes()[3].is_ascii_digit());
+    if reserved {
+        out.insert(0, '_');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_windows_command_line_resolves_pathext() {
+        let dirs = vec![PathBuf::from("bin"), PathBuf::from("npm")];
+        let exists = |p: &Path| p == Path::new("npm").join("claude.cmd");
+        let line = windows_command_line(
+            "claude",
+            args(&["--version"]),
+            &dirs,
+            Some(".EXE;.CMD"),
+            &exists,
+        );
+        assert_eq!(
+            line,
+            CommandLine {
+                program: Path::new("npm")
+                    .join("claude.cmd")
+                    .to_string_lossy()
+                    .into_owned(),
+                args: args(&["--version"]),
+            }
+        );
+
+        // 找不到时原样执行，由系统报告错误
+        let line = windows_command_line("missing", vec![], &dirs, None, &|_| false);
+        assert_eq!(line.program, "missing");
+        // 已带扩展名的不再查找
+        let line = windows_command_line("tool.exe", vec![], &dirs, None, &|_| true);
+        assert_eq!(line.program, "tool.exe");
+    }
+
+    #[test]
+    fn test_windows_command_line_powershell_shim() {
+        let line = windows_command_line(
+            r"C:\scripts\deploy.PS1",
+            args(&["prod"]),
+            &[],
+            None,
+            &|_| false,
+        );
+        assert_eq!(line.program, "powershell");
+        assert_eq!(
+            line.args,
+            args(&[
+                "-NoProfile",
+                "-NonInteractive",
+                "-ExecutionPolicy",
+                "Bypass",
+                "-File",
+                r"C:\scripts\deploy.PS1",
+                "prod",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_resolve_windows_program_with_dir() {
+        let exists = |p: &Path| p == Path::new(r"C:\tools\build.bat");
+        assert_eq!(
+            resolve_windows_program(r"C:\tools\build", &[], None, &exists),
+            Some(r"C:\tools\build.bat".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sanitize_file_component() {
+        assert_eq!(sanitize_file_component("wxid_abc"), "wxid_abc");
+        assert_eq!(sanitize_file_component("a/b\\c:d*e?"), "a_b_c_d_e_");
+        assert_eq!(sanitize_file_component("report. "), "report");
+        assert_eq!(sanitize_file_component("CON"), "_CON");
+        assert_eq!(sanitize_file_component("com1.txt"), "_com1.txt");
+        assert_eq!(sanitize_file_component("console"), "console");
+    }
+}
@@ -0,0 +1,290 @@
+//! Stability 图像生成服务商
+//!
+//! 调用 Stability AI v1 text-to-image（SDXL 等引擎）接口，兼容同格式的自建端点；
+//! 提供原图时调用 image-to-image 接口。
+
+use super::image::{
+    detect_mime, image_extension, GeneratedImages, ImageConfig, ImageData, ImageProvider,
+    ImageQuery,
+};
+use crate::config::{parse_image_size, ImageToolConfig};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+
+/// 默认引擎
+const DEFAULT_MODEL: &str = "stable-diffusion-xl-1024-v1-0";
+const DEFAULT_BASE_URL: &str = "https://api.stability.ai";
+const DEFAULT_SIZE: (u32, u32) = (1024, 1024);
+const DEFAULT_IMAGE_STRENGTH: f32 = 0.35;
+
+#[derive(Debug, Serialize)]
+struct StabilityRequest<'a> {
+    text_prompts: Vec<TextPrompt<'a>>,
+    width: u32,
+    height: u32,
+    samples: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cfg_scale: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    style_preset: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct TextPrompt<'a> {
+    text: &'a str,
+    weight: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct StabilityResponse {
+    #[serde(default)]
+    artifacts: Vec<Artifact>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Artifact {
+    base64: String,
+    #[serde(rename = "finishReason")]
+    finish_reason: Option<String>,
+}
+
+/// Stability / SDXL 图像服务商
+pub struct StabilityImageProvider;
+
+#[async_trait]
+impl ImageProvider for StabilityImageProvider {
+    async fn generate(&self, query: &ImageQuery, config: &ImageConfig) -> Result<GeneratedImages> {
+        let model = query.model_or(&config.settings, DEFAULT_MODEL);
+        let base_url = config.base_url_or(DEFAULT_BASE_URL);
+        let client = reqwest::Client::new();
+
+        let request = match config.base_image.as_ref() {
+            Some(image) => {
+                tracing::debug!(model, prompt = query.prompt(), "发送 Stability 图生图请求");
+                let mut form = reqwest::multipart::Form::new();
+                for (name, value) in image_to_image_fields(query, &config.settings) {
+                    form = form.text(name, value);
+                }
+                let part = reqwest::multipart::Part::bytes(image.data.clone())
+                    .file_name(format!("init.{}", image_extension(&image.mime_type)))
+                    .mime_str(&image.mime_type)
+                    .map_err(|e| anyhow!("无效的图片类型: {}", e))?;
+                client
+                    .post(format!(
+                        "{}/v1/generation/{}/image-to-image",
+                        base_url, model
+                    ))
+                    .multipart(form.part("init_image", part))
+            }
+            None => {
+                let request = build_request(query, &config.settings)?;
+                tracing::debug!(
+                    model,
+                    prompt = query.prompt(),
+                    width = request.width,
+                    height = request.height,
+                    "发送 Stability 图像生成请求"
+                );
+                client
+                    .post(format!(
+                        "{}/v1/generation/{}/text-to-image",
+                        base_url, model
+                    ))
+                    .json(&request)
+            }
+        };
+
+        let response = request
+            .bearer_auth(&config.api_key)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await
+            .map_err(|e| anyhow!("请求失败: {}", e))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| anyhow!("读取响应失败: {}", e))?;
+
+        if !status.is_success() {
+            tracing::warn!(status = %status, body = %body, "Stability API 错误响应");
+            return Err(anyhow!("API 请求失败 ({}): {}", status, body));
+        }
+
+        parse_response(&body)
+    }
+}
+
+/// 构建请求，查询参数优先于工具配置
+fn build_request<'a>(
+    query: &'a ImageQuery,
+    settings: &'a ImageToolConfig,
+) -> Result<StabilityRequest<'a>> {
+    let (width, height) = match query.size.as_deref().or(settings.size.as_deref()) {
+        Some(size) => parse_image_size(size).ok_or_else(|| anyhow!("无效的图像尺寸: {}", size))?,
+        None => DEFAULT_SIZE,
+    };
+    let mut text_prompts = vec![TextPrompt {
+        text: query.prompt(),
+        weight: 1.0,
+    }];
+    if let Some(negative) = settings
+        .negative_prompt
+        .as_deref()
+        .filter(|s| !s.trim().is_empty())
+    {
+        text_prompts.push(TextPrompt {
+            text: negative,
+            weight: -1.0,
+        });
+    }
+    Ok(StabilityRequest {
+        text_prompts,
+        width,
+        height,
+        samples: 1,
+        steps: settings.steps,
+        cfg_scale: settings.cfg_scale,
+        style_preset: settings.style_preset.as_deref(),
+    })
+}
+
+/// 图生图的表单字段（尺寸由原图决定）
+fn image_to_image_fields(query: &ImageQuery, settings: &ImageToolConfig) -> Vec<(String, String)> {
+    let mut fields = vec![
+        (
+            "text_prompts[0][text]".to_string(),
+            query.prompt().to_string(),
+        ),
+        ("text_prompts[0][weight]".to_string(), "1".to_string()),
+        ("init_image_mode".to_string(), "IMAGE_STRENGTH".to_string()),
+        (
+            "image_strength".to_string(),
+            settings
+                .image_strength
+                .unwrap_or(DEFAULT_IMAGE_STRENGTH)
+                .to_string(),
+        ),
+        ("samples".to_string(), "1".to_string()),
+    ];
+    if let Some(negative) = settings
+        .negative_prompt
+        .as_deref()
+        .filter(|s| !s.trim().is_empty())
+    {
+        fields.push(("text_prompts[1][text]".to_string(), negative.to_string()));
+        fields.push(("text_prompts[1][weight]".to_string(), "-1".to_string()));
+    }
+    if let Some(steps) = settings.steps {
+        fields.push(("steps".to_string(), steps.to_string()));
+    }
+    if let Some(cfg_scale) = settings.cfg_scale {
+        fields.push(("cfg_scale".to_string(), cfg_scale.to_string()));
+    }
+    if let Some(preset) = settings.style_preset.as_deref() {
+        fields.push(("style_preset".to_string(), preset.to_string()));
+    }
+    fields
+}
+
+fn parse_response(body: &str) -> Result<GeneratedImages> {
+    let response: StabilityResponse =
+        serde_json::from_str(body).map_err(|e| anyhow!("解析响应失败: {}", e))?;
+    if response.artifacts.is_empty() {
+        return Err(anyhow!(
+            "Stability API 错误: {}",
+            response.message.as_deref().unwrap_or("响应中无图片")
+        ));
+    }
+
+    let mut generated = GeneratedImages::default();
+    for artifact in response.artifacts {
+        if artifact.finish_reason.as_deref() == Some("CONTENT_FILTERED") {
+            generated.text.push("图片被内容安全策略过滤".to_string());
+            continue;
+        }
+        let data = BASE64
+            .decode(&artifact.base64)
+            .map_err(|e| anyhow!("Base64 解码失败: {}", e))?;
+        generated.images.push(ImageData {
+            mime_type: detect_mime(&data).to_string(),
+            data,
+        });
+    }
+    Ok(generated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_request() {
+        let settings = ImageToolConfig {
+            size: Some("1152x896".to_string()),
+            steps: Some(30),
+            cfg_scale: Some(7.0),
+            style_preset: Some("photographic".to_string()),
+            negative_prompt: Some("模糊".to_string()),
+            ..Default::default()
+        };
+        let query = ImageQuery::from_json(r#"{"prompt":"画一只猫"}"#);
+        let value = serde_json::to_value(build_request(&query, &settings).unwrap()).unwrap();
+        assert_eq!(value["width"], 1152);
+        assert_eq!(value["height"], 896);
+        assert_eq!(value["steps"], 30);
+        assert_eq!(value["style_preset"], "photographic");
+        assert_eq!(value["text_prompts"][1]["weight"], -1.0);
+
+        let defaults = ImageToolConfig::default();
+        let request = build_request(&query, &defaults).unwrap();
+        assert_eq!((request.width, request.height), DEFAULT_SIZE);
+        assert_eq!(request.text_prompts.len(), 1);
+
+        let query = ImageQuery::from_json(r#"{"prompt":"猫","size":"big"}"#);
+        assert!(build_request(&query, &settings).is_err());
+    }
+
+    #[test]
+    fn test_image_to_image_fields() {
+        let settings = ImageToolConfig {
+            negative_prompt: Some("模糊".to_string()),
+            steps: Some(30),
+            ..Default::default()
+        };
+        let query = ImageQuery::from_json(r#"{"prompt":"把背景换成海边"}"#);
+        let fields = image_to_image_fields(&query, &settings);
+        let get = |k: &str| {
+            fields
+                .iter()
+                .find(|(name, _)| name == k)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("text_prompts[0][text]"), Some("把背景换成海边"));
+        assert_eq!(get("text_prompts[1][weight]"), Some("-1"));
+        assert_eq!(get("image_strength"), Some("0.35"));
+        assert_eq!(get("steps"), Some("30"));
+        assert_eq!(get("width"), None);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let body = format!(
+            r#"{{"artifacts":[{{"base64":"{}","finishReason":"SUCCESS"}},{{"base64":"","finishReason":"CONTENT_FILTERED"}}]}}"#,
+            BASE64.encode(b"\x89PNG")
+        );
+        let generated = parse_response(&body).unwrap();
+        assert_eq!(generated.images.len(), 1);
+        assert_eq!(generated.images[0].mime_type, "image/png");
+        assert_eq!(generated.text.len(), 1);
+
+        let err = parse_response(r#"{"name":"unauthorized","message":"bad key"}"#).unwrap_err();
+        assert!(err.to_string().contains("bad key"));
+    }
+}
@@ -0,0 +1,198 @@
+//! 群待办清单命令
+//!
+//! 解析 `/todo add xxx`、`/todo done 2`、`/todo list` 等聊天命令，并渲染为文本
+
+use chrono::Utc;
+
+use crate::storage::{TodoItem, TodoList};
+
+/// 默认命令前缀
+pub const DEFAULT_TODO_PREFIX: &str = "/todo";
+/// 单个会话默认最多保存的条目数
+const DEFAULT_MAX_ITEMS: usize = 50;
+
+/// 待办命令
+#[derive(Debug, Clone, PartialEq)]
+pub enum TodoCommand {
+    Add(String),
+    /// 标记完成（序号从 1 开始）
+    Done(usize),
+    /// 取消完成
+    Undo(usize),
+    Remove(usize),
+    List,
+    /// 清除已完成条目
+    Clear,
+    Help,
+}
+
+/// 解析命令文本；不以前缀开头时返回 None
+pub fn parse_todo_command(prefix: &str, text: &str) -> Option<TodoCommand> {
+    let rest = text.trim().strip_prefix(prefix)?;
+    // 前缀后必须是空白或结束，避免 /todos 之类误触发
+    if rest.chars().next().is_some_and(|c| !c.is_whitespace()) {
+        return None;
+    }
+    let rest = rest.trim();
+    let (verb, arg) = match rest.split_once(char::is_whitespace) {
+        Some((verb, arg)) => (verb, arg.trim()),
+        None => (rest, ""),
+    };
+    let index = || arg.parse::<usize>().ok().filter(|n| *n > 0);
+    let cmd = match verb.to_ascii_lowercase().as_str() {
+        "" | "list" | "ls" => TodoCommand::List,
+        "add" | "+" if !arg.is_empty() => TodoCommand::Add(arg.to_string()),
+        "done" | "ok" => index().map(TodoCommand::Done)?,
+        "undo" => index().map(TodoCommand::Undo)?,
+        "del" | "rm" | "remove" => index().map(TodoCommand::Remove)?,
+        "clear" => TodoCommand::Clear,
+        _ => TodoCommand::Help,
+    };
+    Some(cmd)
+}
+
+/// 执行命令并返回回复文本；清单是否被修改由返回值第二项表示
+pub fn apply_todo_command(
+    list: &mut TodoList,
+    cmd: &TodoCommand,
+    sender: Option<&str>,
+    max_items: Option<usize>,
+) -> (String, bool) {
+    let max_items = max_items.filter(|n| *n > 0).unwrap_or(DEFAULT_MAX_ITEMS);
+    match cmd {
+        TodoCommand::Add(text) => {
+            if list.items.len() >= max_items {
+                return (
+                    format!("待办已达上限 {} 条，请先 clear 已完成条目", max_items),
+                    false,
+                );
+            }
+            list.items.push(TodoItem {
+                text: text.clone(),
+                done: false,
+                created_by: sender.map(str::to_string),
+                created_at: Utc::now(),
+            });
+            (format!("已添加 #{}：{}", list.items.len(), text), true)
+        }
+        TodoCommand::Done(n) | TodoCommand::Undo(n) => {
+            let done = matches!(cmd, TodoCommand::Done(_));
+            match list.items.get_mut(n - 1) {
+                Some(item) => {
+                    item.done = done;
+                    let verb = if done { "已完成" } else { "已恢复" };
+                    (format!("{} #{}：{}", verb, n, item.text), true)
+                }
+                None => (format!("没有第 {} 条待办", n), false),
+            }
+        }
+        TodoCommand::Remove(n) => {
+            if *n > list.items.len() {
+                return (format!("没有第 {} 条待办", n), false);
+            }
+            let item = list.items.remove(n - 1);
+            (format!("已删除 #{}：{}", n, item.text), true)
+        }
+        TodoCommand::List => (render_todo_list(list), false),
+        TodoCommand::Clear => {
+            let before = list.items.len();
+            list.items.retain(|i| !i.done);
+            let removed = before - list.items.len();
+            (format!("已清除 {} 条已完成待办", removed), removed > 0)
+        }
+        TodoCommand::Help => (todo_help(), false),
+    }
+}
+
+/// 渲染清单：未完成 [ ]，已完成 [x]
+pub fn render_todo_list(list: &TodoList) -> String {
+    if list.items.is_empty() {
+        return "待办清单为空".to_string();
+    }
+    let mut out = format!(
+        "待办清单（{}/{} 未完成）",
+        list.open_count(),
+        list.items.len()
+    );
+    for (i, item) in list.items.iter().enumerate() {
+        let mark = if item.done { "[x]" } else { "[ ]" };
+        out.push_str(&format!("\n{}. {} {}", i + 1, mark, item.text));
+    }
+    out
+}
+
+fn todo_help() -> String {
+    [
+        "用法：",
+        "/todo add 内容  添加待办",
+        "/todo done 序号  标记完成",
+        "/todo undo 序号  取消完成",
+        "/todo del 序号  删除",
+        "/todo list  查看清单",
+        "/todo clear  清除已完成",
+    ]
+    .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_todo_command() {
+        let p = DEFAULT_TODO_PREFIX;
+        assert_eq!(
+            parse_todo_command(p, "/todo add 买咖啡 两杯"),
+            Some(TodoCommand::Add("买咖啡 两杯".to_string()))
+        );
+        assert_eq!(
+            parse_todo_command(p, " /todo done 2 "),
+            Some(TodoCommand::Done(2))
+        );
+        assert_eq!(parse_todo_command(p, "/todo"), Some(TodoCommand::List));
+        assert_eq!(parse_todo_command(p, "/todo list"), Some(TodoCommand::List));
+        assert_eq!(
+            parse_todo_command(p, "/todo clear"),
+            Some(TodoCommand::Clear)
+        );
+        assert_eq!(parse_todo_command(p, "/todo done x"), None);
+        assert_eq!(parse_todo_command(p, "/todo what"), Some(TodoCommand::Help));
+        assert_eq!(parse_todo_command(p, "/todos"), None);
+        assert_eq!(parse_todo_command(p, "hello"), None);
+    }
+
+    #[test]
+    fn test_apply_todo_commands() {
+        let mut list = TodoList::default();
+        let (reply, changed) = apply_todo_command(
+            &mut list,
+            &TodoCommand::Add("A".into()),
+            Some("wxid_a"),
+            None,
+        );
+        assert!(changed);
+        assert_eq!(reply, "已添加 #1：A");
+        apply_todo_command(&mut list, &TodoCommand::Add("B".into()), None, None);
+
+        let (_, changed) = apply_todo_command(&mut list, &TodoCommand::Done(1), None, None);
+        assert!(changed);
+        assert_eq!(
+            render_todo_list(&list),
+            "待办清单（1/2 未完成）\n1. [x] A\n2. [ ] B"
+        );
+
+        let (reply, changed) = apply_todo_command(&mut list, &TodoCommand::Done(5), None, None);
+        assert!(!changed);
+        assert_eq!(reply, "没有第 5 条待办");
+
+        let (reply, _) = apply_todo_command(&mut list, &TodoCommand::Clear, None, None);
+        assert_eq!(reply, "已清除 1 条已完成待办");
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.items[0].text, "B");
+
+        let (reply, changed) =
+            apply_todo_command(&mut list, &TodoCommand::Add("C".into()), None, Some(1));
+        assert!(!changed);
+        assert!(reply.contains("上限"));
+    }
+}
@@ -2,7 +2,10 @@
 //!
 //! 查询 Claude Code、CodeX、Gemini CLI 的最新版本信息
 //! 数据来源：https://mirror.duckcoding.com/api/v1/tools
+//!
+//! 输出结构见 [`ToolVersionsOutput`]，`format: "json"` 时返回结构化 JSON。
 
+use super::output::{OutputFormat, ToolOutput};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::OnceLock;
@@ -13,6 +16,8 @@ use tokio::time;
 const API_URL: &str = "https://mirror.duckcoding.com/api/v1/tools";
 const CACHE_TTL: Duration = Duration::from_secs(60); // 1 分钟缓存
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// [`ToolVersionsOutput`] 的结构版本，字段不兼容变更时递增
+pub const TOOL_VERSIONS_SCHEMA_VERSION: u32 = 1;
 
 /// 查询参数
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -23,6 +28,9 @@ pub struct VersionQuery {
     /// 是否返回详细信息
     #[serde(default)]
     pub detail: Option<bool>,
+    /// 输出格式: text（默认）, json
+    #[serde(default)]
+    pub format: Option<String>,
 }
 
 impl VersionQuery {
@@ -37,6 +45,10 @@ impl VersionQuery {
     pub fn detail(&self) -> bool {
         self.detail.unwrap_or(false)
     }
+
+    pub fn format(&self) -> OutputFormat {
+        OutputFormat::parse(self.format.as_deref())
+    }
 }
 
 /// 执行结果
@@ -48,6 +60,68 @@ pub struct VersionResult {
     pub timed_out: bool,
 }
 
+/// tool_versions 的输出结构
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolVersionsOutput {
+    pub schema_version: u32,
+    /// 数据源更新时间（ISO 8601）
+    pub updated_at: String,
+    pub tools: Vec<ToolVersion>,
+    /// 文本格式是否展示详细信息，JSON 始终包含全部字段
+    #[serde(skip)]
+    pub detail: bool,
+}
+
+/// 单个工具的版本信息
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolVersion {
+    pub id: String,
+    pub name: String,
+    pub latest_version: String,
+    pub release_date: Option<String>,
+    pub mirror_version: Option<String>,
+    /// 镜像版本落后于最新版本
+    pub is_stale: bool,
+    pub download_url: Option<String>,
+    pub release_notes_url: Option<String>,
+}
+
+impl ToolOutput for ToolVersionsOutput {
+    fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+
+        lines.push("AI 开发工具最新版本：".to_string());
+        lines.push(String::new());
+
+        for tool in &self.tools {
+            if self.detail {
+                lines.push(format!("【{}】", tool.name));
+                lines.push(format!("  版本: {}", tool.latest_version));
+                if let Some(ref date) = tool.release_date {
+                    lines.push(format!("  发布: {}", format_date(date)));
+                }
+                if let Some(ref mirror) = tool.mirror_version {
+                    let sync_status = if tool.is_stale { " (待同步)" } else { "" };
+                    lines.push(format!("  镜像: {}{}", mirror, sync_status));
+                }
+                if let Some(ref url) = tool.download_url {
+                    lines.push(format!("  下载: {}", url));
+                }
+                if let Some(ref url) = tool.release_notes_url {
+                    lines.push(format!("  说明: {}", url));
+                }
+                lines.push(String::new());
+            } else {
+                lines.push(format!("• {}: v{}", tool.name, tool.latest_version));
+            }
+        }
+
+        lines.push(format!("数据更新: {}", format_date(&self.updated_at)));
+
+        lines.join("\n")
+    }
+}
+
 /// API 响应结构
 #[derive(Debug, Deserialize, Serialize)]
 struct ApiResponse {
@@ -143,7 +217,7 @@ async fn execute_query(query: &VersionQuery) -> Result<String> {
         ));
     }
 
-    Ok(format_output(&selected, detail, &updated_at))
+    Ok(build_output(&selected, detail, &updated_at).render(query.format()))
 }
 
 /// 规范化工具 ID
@@ -204,43 +278,26 @@ async fn fetch_and_cache() -> Result<(Vec<ToolInfo>, String)> {
     Ok((tools, updated_at))
 }
 
-/// 格式化输出
-fn format_output(tools: &[&ToolInfo], detail: bool, updated_at: &str) -> String {
-    let mut lines = Vec::new();
-
-    lines.push("AI 开发工具最新版本：".to_string());
-    lines.push(String::new());
-
-    for tool in tools {
-        if detail {
-            lines.push(format!("【{}】", tool.name));
-            lines.push(format!("  版本: {}", tool.latest_version));
-            if let Some(ref date) = tool.release_date {
-                lines.push(format!("  发布: {}", format_date(date)));
-            }
-            if let Some(ref mirror) = tool.mirror_version {
-                let sync_status = if tool.is_stale.unwrap_or(false) {
-                    " (待同步)"
-                } else {
-                    ""
-                };
-                lines.push(format!("  镜像: {}{}", mirror, sync_status));
-            }
-            if let Some(ref url) = tool.download_url {
-                lines.push(format!("  下载: {}", url));
-            }
-            if let Some(ref url) = tool.release_notes_url {
-                lines.push(format!("  说明: {}", url));
-            }
-            lines.push(String::new());
-        } else {
-            lines.push(format!("• {}: v{}", tool.name, tool.latest_version));
-        }
+/// 构建输出结构
+fn build_output(tools: &[&ToolInfo], detail: bool, updated_at: &str) -> ToolVersionsOutput {
+    ToolVersionsOutput {
+        schema_version: TOOL_VERSIONS_SCHEMA_VERSION,
+        updated_at: updated_at.to_string(),
+        tools: tools
+            .iter()
+            .map(|t| ToolVersion {
+                id: t.id.clone(),
+                name: t.name.clone(),
+                latest_version: t.latest_version.clone(),
+                release_date: t.release_date.clone(),
+                mirror_version: t.mirror_version.clone(),
+                is_stale: t.is_stale.unwrap_or(false),
+                download_url: t.download_url.clone(),
+                release_notes_url: t.release_notes_url.clone(),
+            })
+            .collect(),
+        detail,
     }
-
-    lines.push(format!("数据更新: {}", format_date(updated_at)));
-
-    lines.join("\n")
 }
 
 /// 格式化日期时间
@@ -277,6 +334,7 @@ fn clamp_output(text: String, max: usize) -> (String, bool) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::output::assert_golden;
 
     #[test]
     fn test_parse_query() {
@@ -438,7 +496,7 @@ mod tests {
 
     #[test]
     fn test_format_output_simple() {
-        let tools = vec![ToolInfo {
+        let tools = [ToolInfo {
             id: "claude-code".to_string(),
             name: "Claude Code".to_string(),
             latest_version: "2.0.55".to_string(),
@@ -452,7 +510,7 @@ mod tests {
         }];
 
         let refs: Vec<&ToolInfo> = tools.iter().collect();
-        let result = format_output(&refs, false, "2024-01-15T10:30:45Z");
+        let result = build_output(&refs, false, "2024-01-15T10:30:45Z").to_text();
 
         assert!(result.contains("Claude Code: v2.0.55"));
         assert!(result.contains("数据更新"));
@@ -460,7 +518,7 @@ mod tests {
 
     #[test]
     fn test_format_output_detailed() {
-        let tools = vec![ToolInfo {
+        let tools = [ToolInfo {
             id: "claude-code".to_string(),
             name: "Claude Code".to_string(),
             latest_version: "2.0.55".to_string(),
@@ -474,7 +532,7 @@ mod tests {
         }];
 
         let refs: Vec<&ToolInfo> = tools.iter().collect();
-        let result = format_output(&refs, true, "2024-01-15T10:30:45Z");
+        let result = build_output(&refs, true, "2024-01-15T10:30:45Z").to_text();
 
         assert!(result.contains("【Claude Code】"));
         assert!(result.contains("版本: 2.0.55"));
@@ -486,7 +544,7 @@ mod tests {
 
     #[test]
     fn test_format_output_multiple_tools() {
-        let tools = vec![
+        let tools = [
             ToolInfo {
                 id: "claude-code".to_string(),
                 name: "Claude Code".to_string(),
@@ -514,7 +572,7 @@ mod tests {
         ];
 
         let refs: Vec<&ToolInfo> = tools.iter().collect();
-        let result = format_output(&refs, false, "2024-01-15T10:30:45Z");
+        let result = build_output(&refs, false, "2024-01-15T10:30:45Z").to_text();
 
         assert!(result.contains("Claude Code: v2.0.55"));
         assert!(result.contains("Gemini CLI: v1.5.0"));
@@ -522,7 +580,7 @@ mod tests {
 
     #[test]
     fn test_format_output_with_synced_mirror() {
-        let tools = vec![ToolInfo {
+        let tools = [ToolInfo {
             id: "claude-code".to_string(),
             name: "Claude Code".to_string(),
             latest_version: "2.0.55".to_string(),
@@ -536,7 +594,7 @@ mod tests {
         }];
 
         let refs: Vec<&ToolInfo> = tools.iter().collect();
-        let result = format_output(&refs, true, "2024-01-15T10:30:45Z");
+        let result = build_output(&refs, true, "2024-01-15T10:30:45Z").to_text();
 
         assert!(result.contains("镜像: 2.0.55"));
         assert!(!result.contains("(待同步)"));
@@ -571,4 +629,45 @@ mod tests {
         assert_eq!(info.mirror_version, Some("0.9.0".to_string()));
         assert_eq!(info.is_stale, Some(true));
     }
+
+    #[test]
+    fn test_golden_output() {
+        let tools = [
+            ToolInfo {
+                id: "claude-code".to_string(),
+                name: "Claude Code".to_string(),
+                latest_version: "2.0.55".to_string(),
+                mirror_version: Some("2.0.54".to_string()),
+                mirror_synced_at: Some("2024-01-14T10:30:45Z".to_string()),
+                is_stale: Some(true),
+                release_date: Some("2024-01-15T10:30:45Z".to_string()),
+                download_url: Some("https://example.com/download".to_string()),
+                release_notes_url: Some("https://example.com/notes".to_string()),
+                package_name: Some("@anthropic-ai/claude-code".to_string()),
+            },
+            ToolInfo {
+                id: "gemini-cli".to_string(),
+                name: "Gemini CLI".to_string(),
+                latest_version: "1.5.0".to_string(),
+                mirror_version: None,
+                mirror_synced_at: None,
+                is_stale: None,
+                release_date: None,
+                download_url: None,
+                release_notes_url: None,
+                package_name: None,
+            },
+        ];
+        let refs: Vec<&ToolInfo> = tools.iter().collect();
+
+        let output = build_output(&refs, false, "2024-01-15T10:30:45.123Z");
+        assert_golden("tool_versions.txt", &output.render(OutputFormat::Text));
+        assert_golden("tool_versions.json", &output.render(OutputFormat::Json));
+
+        let output = build_output(&refs, true, "2024-01-15T10:30:45.123Z");
+        assert_golden(
+            "tool_versions_detail.txt",
+            &output.render(OutputFormat::Text),
+        );
+    }
 }
@@ -0,0 +1,245 @@
+//! 语音转写工具
+//!
+//! 调用 OpenAI 兼容的 `/audio/transcriptions` 接口把语音转为文字。
+//! 微信语音消息为 SILK 编码，转写服务无法直接识别，需由管理员配置的外部命令先转码。
+
+use crate::config::SttConfig;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::time::Duration;
+use tokio::time;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+const DEFAULT_MODEL: &str = "whisper-1";
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+const DEFAULT_CONVERT_EXT: &str = "mp3";
+
+/// 支持转写的音频文件扩展名
+pub const AUDIO_FILE_EXTS: &[&str] = &[
+    "mp3", "m4a", "wav", "aac", "amr", "ogg", "flac", "webm", "mp4", "mpga", "opus",
+];
+
+/// 是否为支持转写的音频扩展名（不区分大小写）
+pub fn is_audio_file(ext: &str) -> bool {
+    AUDIO_FILE_EXTS
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(ext.trim().trim_start_matches('.')))
+}
+
+/// 是否为 SILK 编码（微信语音在文件头前多一个 0x02 字节）
+pub fn is_silk(data: &[u8]) -> bool {
+    data.starts_with(b"#!SILK") || data.get(1..).is_some_and(|d| d.starts_with(b"#!SILK"))
+}
+
+/// 转写音频；SILK 语音仅在 `allow_convert` 为 true 且配置了转码命令时处理
+pub async fn transcribe_audio(
+    data: Vec<u8>,
+    file_name: &str,
+    config: &SttConfig,
+    allow_convert: bool,
+) -> Result<String> {
+    let timeout = config
+        .timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    time::timeout(timeout, async {
+        let (data, file_name) = if is_silk(&data) {
+            convert_silk(&data, config, allow_convert).await?
+        } else {
+            (data, file_name.to_string())
+        };
+        request_transcription(data, &file_name, config).await
+    })
+    .await
+    .map_err(|_| anyhow!("语音转写超时"))?
+}
+
+/// 用配置的外部命令把 SILK 转为常见音频格式
+async fn convert_silk(
+    data: &[u8],
+    config: &SttConfig,
+    allow_convert: bool,
+) -> Result<(Vec<u8>, String)> {
+    let Some(program) = config.convert_command.first().filter(|p| !p.is_empty()) else {
+        return Err(anyhow!(
+            "语音为 SILK 格式，需配置 stt.convert_command 转码后才能转写"
+        ));
+    };
+    if !allow_convert {
+        return Err(anyhow!(
+            "未启用外部命令，请设置 GEWE_ALLOW_COMMAND=1 后再转写语音"
+        ));
+    }
+
+    let ext = config
+        .convert_ext
+        .as_deref()
+        .map(|e| e.trim().trim_start_matches('.'))
+        .filter(|e| !e.is_empty())
+        .unwrap_or(DEFAULT_CONVERT_EXT);
+    let id = uuid::Uuid::new_v4();
+    let dir = std::env::temp_dir();
+    let input = dir.join(format!("gewe-stt-{}.silk", id));
+    let output = dir.join(format!("gewe-stt-{}.{}", id, ext));
+    tokio::fs::write(&input, data)
+        .await
+        .map_err(|e| anyhow!("写入临时文件失败: {}", e))?;
+
+    let args = convert_args(&config.convert_command[1..], &input, &output);
+    let result = super::spawn::external_command(program, &args)
+        .kill_on_drop(true)
+        .output()
+        .await;
+    let converted = match result {
+        Ok(out) if out.status.success() => tokio::fs::read(&output)
+            .await
+            .map_err(|e| anyhow!("读取转码结果失败: {}", e)),
+        Ok(out) => Err(anyhow!(
+            "语音转码失败: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )),
+        Err(e) => Err(anyhow!("启动转码命令失败: {}", e)),
+    };
+    let _ = tokio::fs::remove_file(&input).await;
+    let _ = tokio::fs::remove_file(&output).await;
+    Ok((converted?, format!("voice.{}", ext)))
+}
+
+/// 替换参数中的 `{input}`/`{output}` 占位符
+pub(super) fn convert_args(args: &[String], input: &Path, output: &Path) -> Vec<String> {
+    let input = input.to_string_lossy();
+    let output = output.to_string_lossy();
+    args.iter()
+        .map(|arg| arg.replace("{input}", &input).replace("{output}", &output))
+        .collect()
+}
+
+async fn request_transcription(
+    data: Vec<u8>,
+    file_name: &str,
+    config: &SttConfig,
+) -> Result<String> {
+    use reqwest::multipart::{Form, Part};
+
+    let env_name = config.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+    let api_key = std::env::var(env_name)
+        .ok()
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| anyhow!("缺少环境变量 {}", env_name))?;
+    let base_url = config
+        .base_url
+        .as_deref()
+        .unwrap_or(DEFAULT_BASE_URL)
+        .trim_end_matches('/');
+
+    let mut form = Form::new()
+        .part("file", Part::bytes(data).file_name(file_name.to_string()))
+        .text(
+            "model",
+            config
+                .model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        )
+        .text("response_format", "json");
+    if let Some(language) = config.language.clone().filter(|l| !l.trim().is_empty()) {
+        form = form.text("language", language);
+    }
+    if let Some(prompt) = config.prompt.clone().filter(|p| !p.trim().is_empty()) {
+        form = form.text("prompt", prompt);
+    }
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/audio/transcriptions", base_url))
+        .bearer_auth(api_key)
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| anyhow!("请求失败: {}", e))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| anyhow!("读取响应失败: {}", e))?;
+    if !status.is_success() {
+        tracing::warn!(status = %status, body = %body, "语音转写错误响应");
+        return Err(anyhow!("API 请求失败 ({}): {}", status, body));
+    }
+    parse_transcription_response(&body)
+}
+
+fn parse_transcription_response(body: &str) -> Result<String> {
+    let value: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| anyhow!("解析响应失败: {}", e))?;
+    if let Some(message) = value.pointer("/error/message").and_then(|v| v.as_str()) {
+        return Err(anyhow!("转写服务错误: {}", message));
+    }
+    value
+        .get("text")
+        .and_then(|v| v.as_str())
+        .map(|text| text.trim().to_string())
+        .ok_or_else(|| anyhow!("响应中无转写结果"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_audio_file() {
+        assert!(is_audio_file("mp3"));
+        assert!(is_audio_file(".M4A"));
+        assert!(!is_audio_file("pdf"));
+    }
+
+    #[test]
+    fn test_is_silk() {
+        assert!(is_silk(b"\x02#!SILK_V3\x0c\x00"));
+        assert!(is_silk(b"#!SILK_V3"));
+        assert!(!is_silk(b"ID3\x04\x00"));
+        assert!(!is_silk(b""));
+    }
+
+    #[test]
+    fn test_convert_args() {
+        let args = vec![
+            "-i".to_string(),
+            "{input}".to_string(),
+            "--out={output}".to_string(),
+        ];
+        assert_eq!(
+            convert_args(&args, Path::new("/tmp/a.silk"), Path::new("/tmp/a.mp3")),
+            vec!["-i", "/tmp/a.silk", "--out=/tmp/a.mp3"]
+        );
+    }
+
+    #[test]
+    fn test_parse_transcription_response() {
+        assert_eq!(
+            parse_transcription_response(r#"{"text":" 今天讨论预算。 "}"#).unwrap(),
+            "今天讨论预算。"
+        );
+        let err = parse_transcription_response(r#"{"error":{"message":"bad audio"}}"#).unwrap_err();
+        assert!(err.to_string().contains("bad audio"));
+        assert!(parse_transcription_response("{}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_silk_requires_converter() {
+        let silk = b"\x02#!SILK_V3".to_vec();
+        let err = transcribe_audio(silk.clone(), "voice.silk", &SttConfig::default(), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("convert_command"));
+
+        let config = SttConfig {
+            convert_command: vec!["silk2mp3".to_string()],
+            ..Default::default()
+        };
+        let err = transcribe_audio(silk, "voice.silk", &config, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("GEWE_ALLOW_COMMAND"));
+    }
+}
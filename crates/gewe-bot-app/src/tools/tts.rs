@@ -0,0 +1,321 @@
+//! 语音合成工具
+//!
+//! 调用 OpenAI 兼容的 `/audio/speech` 接口把文字合成为语音，保存到图片目录后以语音消息发送。
+//! 微信语音只能播放 SILK 编码，服务商返回其他格式时需由管理员配置的外部命令转码。
+
+use super::image::ImageConfig;
+use super::transcribe::{convert_args, is_silk};
+use crate::config::TtsConfig;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::time::Duration;
+use tokio::{fs, time};
+use uuid::Uuid;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MODEL: &str = "tts-1";
+const DEFAULT_VOICE: &str = "alloy";
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+const DEFAULT_FORMAT: &str = "mp3";
+/// 未配置 max_chars 时可合成的最大字数
+pub const DEFAULT_TTS_MAX_CHARS: usize = 300;
+
+/// 合成并保存后的语音
+#[derive(Debug, Clone, PartialEq)]
+pub struct SynthesizedVoice {
+    /// 供 GeWe 下载的地址
+    pub url: String,
+    pub duration_ms: u64,
+}
+
+/// 合成语音并保存到图片目录；返回其他格式时仅在 `allow_convert` 为 true 且配置了转码命令时转为 SILK
+pub async fn synthesize_voice(
+    text: &str,
+    config: &TtsConfig,
+    image_config: &ImageConfig,
+    allow_convert: bool,
+) -> Result<SynthesizedVoice> {
+    let base_url = image_config
+        .external_base_url
+        .as_deref()
+        .ok_or_else(|| anyhow!("未配置 external_base_url，无法发送语音"))?;
+    let timeout = config
+        .timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let format = config
+        .response_format
+        .as_deref()
+        .map(|f| f.trim().trim_start_matches('.'))
+        .filter(|f| !f.is_empty())
+        .unwrap_or(DEFAULT_FORMAT);
+    let audio = time::timeout(timeout, async {
+        let audio = request_speech(text, format, config).await?;
+        if is_silk(&audio) || config.convert_command.is_empty() {
+            Ok(audio)
+        } else {
+            convert_to_silk(&audio, format, config, allow_convert).await
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("语音合成超时"))??;
+    let duration_ms = audio_duration_ms(&audio).ok_or_else(|| anyhow!("无法识别合成语音的时长"))?;
+
+    let ext = if is_silk(&audio) { "silk" } else { format };
+    let filename = format!("voice-{}.{}", Uuid::new_v4(), ext);
+    let file_path = Path::new(&image_config.image_dir).join(&filename);
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&file_path, &audio)
+        .await
+        .map_err(|e| anyhow!("写入文件失败: {}", e))?;
+    tracing::info!(path = %file_path.display(), size = audio.len(), duration_ms, "合成语音已保存");
+
+    Ok(SynthesizedVoice {
+        url: format!(
+            "{}{}/{}",
+            base_url.trim_end_matches('/'),
+            image_config.image_url_prefix,
+            filename
+        ),
+        duration_ms,
+    })
+}
+
+async fn request_speech(text: &str, format: &str, config: &TtsConfig) -> Result<Vec<u8>> {
+    let env_name = config.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+    let api_key = std::env::var(env_name)
+        .ok()
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| anyhow!("缺少环境变量 {}", env_name))?;
+    let base_url = config
+        .base_url
+        .as_deref()
+        .unwrap_or(DEFAULT_BASE_URL)
+        .trim_end_matches('/');
+    let body = serde_json::json!({
+        "model": config.model.as_deref().unwrap_or(DEFAULT_MODEL),
+        "voice": config.voice.as_deref().unwrap_or(DEFAULT_VOICE),
+        "input": text,
+        "response_format": format,
+    });
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/audio/speech", base_url))
+        .bearer_auth(api_key)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| anyhow!("请求失败: {}", e))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        tracing::warn!(status = %status, body = %body, "语音合成错误响应");
+        return Err(anyhow!("API 请求失败 ({}): {}", status, body));
+    }
+    let audio = response
+        .bytes()
+        .await
+        .map_err(|e| anyhow!("读取响应失败: {}", e))?;
+    Ok(audio.to_vec())
+}
+
+/// 用配置的外部命令把合成结果转为 SILK
+async fn convert_to_silk(
+    data: &[u8],
+    format: &str,
+    config: &TtsConfig,
+    allow_convert: bool,
+) -> Result<Vec<u8>> {
+    if !allow_convert {
+        return Err(anyhow!(
+            "未启用外部命令，请设置 GEWE_ALLOW_COMMAND=1 后再转码语音"
+        ));
+    }
+    let id = Uuid::new_v4();
+    let dir = std::env::temp_dir();
+    let input = dir.join(format!("gewe-tts-{}.{}", id, format));
+    let output = dir.join(format!("gewe-tts-{}.silk", id));
+    fs::write(&input, data)
+        .await
+        .map_err(|e| anyhow!("写入临时文件失败: {}", e))?;
+
+    let args = convert_args(&config.convert_command[1..], &input, &output);
+    let result = super::spawn::external_command(&config.convert_command[0], &args)
+        .kill_on_drop(true)
+        .output()
+        .await;
+    let converted = match result {
+        Ok(out) if out.status.success() => fs::read(&output)
+            .await
+            .map_err(|e| anyhow!("读取转码结果失败: {}", e)),
+        Ok(out) => Err(anyhow!(
+            "语音转码失败: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )),
+        Err(e) => Err(anyhow!("启动转码命令失败: {}", e)),
+    };
+    let _ = fs::remove_file(&input).await;
+    let _ = fs::remove_file(&output).await;
+    converted
+}
+
+/// 音频时长（毫秒），支持 SILK、WAV 与 MP3（Layer III），无法识别时返回 None
+pub fn audio_duration_ms(data: &[u8]) -> Option<u64> {
+    if is_silk(data) {
+        silk_duration_ms(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WAVE") {
+        wav_duration_ms(data)
+    } else {
+        mp3_duration_ms(data)
+    }
+}
+
+/// SILK：每帧前有 2 字节小端长度，每帧 20 毫秒，长度为 0xFFFF 时结束
+fn silk_duration_ms(data: &[u8]) -> Option<u64> {
+    let mut pos = if data.first() == Some(&0x02) { 1 } else { 0 } + b"#!SILK_V3".len();
+    let mut frames = 0u64;
+    while let Some(len) = data.get(pos..pos + 2) {
+        let len = u16::from_le_bytes([len[0], len[1]]);
+        if len == 0xFFFF {
+            break;
+        }
+        pos += 2 + len as usize;
+        if pos > data.len() {
+            break;
+        }
+        frames += 1;
+    }
+    (frames > 0).then_some(frames * 20)
+}
+
+/// WAV：data 块字节数除以 fmt 块中的每秒字节数
+fn wav_duration_ms(data: &[u8]) -> Option<u64> {
+    let u32_at = |pos: usize| {
+        data.get(pos..pos + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let mut pos = 12;
+    let mut byte_rate = None;
+    while let (Some(id), Some(size)) = (data.get(pos..pos + 4), u32_at(pos + 4)) {
+        match id {
+            b"fmt " => byte_rate = u32_at(pos + 16),
+            b"data" => {
+                let byte_rate = byte_rate.filter(|r| *r > 0)?;
+                // 流式输出的 WAV 常把长度写为 0xFFFFFFFF，以实际数据为准
+                let size = (size as usize).min(data.len().saturating_sub(pos + 8));
+                return Some(size as u64 * 1000 / byte_rate as u64);
+            }
+            _ => {}
+        }
+        // 块按偶数字节对齐
+        pos += 8 + size as usize + (size as usize & 1);
+    }
+    None
+}
+
+/// MP3：跳过 ID3v2 标签后逐帧累加采样数
+fn mp3_duration_ms(data: &[u8]) -> Option<u64> {
+    let mut pos = 0;
+    if data.starts_with(b"ID3") && data.len() >= 10 {
+        let size = data[6..10]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 7) | (*b & 0x7f) as usize);
+        let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+        pos = 10 + size + footer;
+    }
+    let mut micros = 0u64;
+    let mut frames = 0u32;
+    while let Some(header) = data.get(pos..pos + 4) {
+        match mp3_frame(header) {
+            Some((len, frame_micros)) => {
+                micros += frame_micros;
+                frames += 1;
+                pos += len;
+            }
+            // 第一帧之前可能有填充，逐字节寻找帧头；之后遇到无法识别的数据即结束
+            None if frames == 0 => pos += 1,
+            None => break,
+        }
+    }
+    (frames > 0).then_some(micros / 1000)
+}
+
+/// 解析 MPEG Layer III 帧头，返回帧字节数与时长（微秒）
+fn mp3_frame(header: &[u8]) -> Option<(usize, u64)> {
+    const MPEG1_KBPS: [u32; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const MPEG2_KBPS: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    const MPEG1_RATES: [u32; 3] = [44100, 48000, 32000];
+
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 || (header[1] >> 1) & 0x03 != 1 {
+        return None;
+    }
+    // 3 = MPEG1，2 = MPEG2（采样率减半），0 = MPEG2.5（采样率为四分之一）
+    let version = (header[1] >> 3) & 0x03;
+    let (kbps_table, rate_shift) = match version {
+        3 => (MPEG1_KBPS, 0),
+        2 => (MPEG2_KBPS, 1),
+        0 => (MPEG2_KBPS, 2),
+        _ => return None,
+    };
+    let kbps = kbps_table
+        .get((header[2] >> 4) as usize)
+        .copied()
+        .filter(|k| *k > 0)?;
+    let rate = MPEG1_RATES.get(((header[2] >> 2) & 0x03) as usize)? >> rate_shift;
+    let samples: u32 = if version == 3 { 1152 } else { 576 };
+    let padding = ((header[2] >> 1) & 0x01) as u32;
+    let len = samples / 8 * kbps * 1000 / rate + padding;
+    Some((len as usize, samples as u64 * 1_000_000 / rate as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_duration_ms() {
+        // SILK：3 帧 × 20 毫秒
+        let mut silk = b"\x02#!SILK_V3".to_vec();
+        for _ in 0..3 {
+            silk.extend_from_slice(&[2, 0, 0xAB, 0xCD]);
+        }
+        silk.extend_from_slice(&[0xFF, 0xFF]);
+        assert_eq!(audio_duration_ms(&silk), Some(60));
+
+        // WAV：16 kHz 单声道 16 位，32000 字节数据为 1 秒
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&[1, 0, 1, 0]);
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&32000u32.to_le_bytes());
+        wav.extend_from_slice(&[2, 0, 16, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&u32::MAX.to_le_bytes());
+        wav.resize(wav.len() + 32000, 0);
+        assert_eq!(audio_duration_ms(&wav), Some(1000));
+
+        // MP3：ID3 标签后 2 帧 MPEG1 Layer III 128 kbps 44.1 kHz，每帧 417 字节
+        let mut mp3 = b"ID3\x04\x00\x00\x00\x00\x00\x02\0\0".to_vec();
+        for _ in 0..2 {
+            let mut frame = vec![0u8; 417];
+            frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+            mp3.extend_from_slice(&frame);
+        }
+        assert_eq!(audio_duration_ms(&mp3), Some(52));
+
+        assert_eq!(audio_duration_ms(b"not audio"), None);
+    }
+
+    #[tokio::test]
+    async fn test_synthesize_voice_requires_external_base_url() {
+        let err = synthesize_voice("你好", &TtsConfig::default(), &ImageConfig::default(), true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("external_base_url"));
+    }
+}
@@ -0,0 +1,200 @@
+//! 机器人在线看护
+//!
+//! 定时检查各机器人的在线状态：离线时尝试断线重连，重连失败后按指数退避拉长下次检查的间隔，
+//! 持续离线超过阈值时通知管理员。这里只维护每个机器人的状态，实际的检查、重连与通知由
+//! `Dispatcher::run_online_watchdog` 执行
+
+use crate::config::WatchdogConfig;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// 一次检查后需要处理的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchdogEvent {
+    /// 首次判定离线（重连也未成功）
+    Offline,
+    /// 持续离线超过阈值，需要通知管理员
+    Alert { since: DateTime<Utc> },
+    /// 离线后恢复在线；`alerted` 表示离线期间已通知过管理员
+    Recovered { since: DateTime<Utc>, alerted: bool },
+}
+
+#[derive(Debug, Clone, Default)]
+struct BotWatch {
+    /// 下次检查的时间，None 表示尚未检查过
+    next_check: Option<DateTime<Utc>>,
+    /// 本次离线开始的时间
+    offline_since: Option<DateTime<Utc>>,
+    /// 连续重连失败次数
+    failures: u32,
+    /// 本次离线是否已通知管理员
+    alerted: bool,
+}
+
+/// 各机器人的看护状态
+#[derive(Debug)]
+pub struct OnlineWatchdog {
+    interval: Duration,
+    max_backoff: Duration,
+    alert_after: Duration,
+    bots: HashMap<String, BotWatch>,
+}
+
+impl OnlineWatchdog {
+    pub fn new(config: &WatchdogConfig) -> Self {
+        let interval = config.interval_secs().max(1);
+        Self {
+            interval: Duration::seconds(interval as i64),
+            max_backoff: Duration::seconds(config.max_backoff_secs().max(interval) as i64),
+            alert_after: Duration::seconds(config.alert_after_secs() as i64),
+            bots: HashMap::new(),
+        }
+    }
+
+    /// 在线时的检查间隔，也是后台任务的轮询间隔
+    pub fn interval(&self) -> std::time::Duration {
+        self.interval
+            .to_std()
+            .unwrap_or(std::time::Duration::from_secs(60))
+    }
+
+    /// 是否到了该机器人的检查时间
+    pub fn is_due(&self, app_id: &str, now: DateTime<Utc>) -> bool {
+        self.bots
+            .get(app_id)
+            .and_then(|w| w.next_check)
+            .is_none_or(|next| next <= now)
+    }
+
+    /// 最近一次检查是否在线，尚未检查过的机器人视为在线
+    pub fn is_online(&self, app_id: &str) -> bool {
+        self.bots
+            .get(app_id)
+            .is_none_or(|w| w.offline_since.is_none())
+    }
+
+    /// 记录一次检查（含重连）的结果，安排下次检查并返回需要处理的事件
+    pub fn record(&mut self, app_id: &str, online: bool, now: DateTime<Utc>) -> Vec<WatchdogEvent> {
+        let watch = self.bots.entry(app_id.to_string()).or_default();
+        let mut events = Vec::new();
+        if online {
+            watch.failures = 0;
+            watch.next_check = Some(now + self.interval);
+            if let Some(since) = watch.offline_since.take() {
+                events.push(WatchdogEvent::Recovered {
+                    since,
+                    alerted: std::mem::take(&mut watch.alerted),
+                });
+            }
+            return events;
+        }
+        watch.failures = watch.failures.saturating_add(1);
+        watch.next_check = Some(now + backoff(self.interval, self.max_backoff, watch.failures));
+        let since = *watch.offline_since.get_or_insert_with(|| {
+            events.push(WatchdogEvent::Offline);
+            now
+        });
+        if !watch.alerted && now - since >= self.alert_after {
+            watch.alerted = true;
+            events.push(WatchdogEvent::Alert { since });
+        }
+        events
+    }
+}
+
+/// 第 n 次重连失败后的检查间隔：interval × 2^(n-1)，不超过 max
+fn backoff(interval: Duration, max: Duration, failures: u32) -> Duration {
+    let factor = 1i32
+        .checked_shl(failures.saturating_sub(1))
+        .unwrap_or(i32::MAX);
+    interval.checked_mul(factor).map_or(max, |d| d.min(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_backoff() {
+        let interval = Duration::seconds(60);
+        let max = Duration::seconds(600);
+        assert_eq!(backoff(interval, max, 1), Duration::seconds(60));
+        assert_eq!(backoff(interval, max, 2), Duration::seconds(120));
+        assert_eq!(backoff(interval, max, 4), Duration::seconds(480));
+        assert_eq!(backoff(interval, max, 5), max);
+        assert_eq!(backoff(interval, max, 100), max);
+    }
+
+    #[test]
+    fn test_watchdog_offline_alert_and_recovery() {
+        let config = WatchdogConfig {
+            enabled: true,
+            interval_secs: Some(60),
+            alert_after_secs: Some(150),
+            ..Default::default()
+        };
+        let mut watchdog = OnlineWatchdog::new(&config);
+        assert!(watchdog.is_due("wx1", at(0)));
+        assert!(watchdog.record("wx1", true, at(0)).is_empty());
+        assert!(!watchdog.is_due("wx1", at(59)));
+        assert!(watchdog.is_due("wx1", at(60)));
+
+        assert_eq!(
+            watchdog.record("wx1", false, at(60)),
+            [WatchdogEvent::Offline]
+        );
+        assert!(!watchdog.is_online("wx1"));
+        assert!(watchdog.is_online("wx2"));
+        // 第二次失败后退避到 120 秒
+        assert!(watchdog.record("wx1", false, at(120)).is_empty());
+        assert!(!watchdog.is_due("wx1", at(239)));
+        assert_eq!(
+            watchdog.record("wx1", false, at(240)),
+            [WatchdogEvent::Alert { since: at(60) }]
+        );
+        // 同一次离线只通知一次
+        assert!(watchdog.record("wx1", false, at(480)).is_empty());
+
+        assert_eq!(
+            watchdog.record("wx1", true, at(720)),
+            [WatchdogEvent::Recovered {
+                since: at(60),
+                alerted: true
+            }]
+        );
+        assert!(watchdog.is_online("wx1"));
+        assert!(watchdog.is_due("wx1", at(780)));
+    }
+
+    #[test]
+    fn test_watchdog_immediate_alert() {
+        let config = WatchdogConfig {
+            enabled: true,
+            alert_after_secs: Some(0),
+            ..Default::default()
+        };
+        let mut watchdog = OnlineWatchdog::new(&config);
+        assert_eq!(
+            watchdog.record("wx1", false, at(0)),
+            [
+                WatchdogEvent::Offline,
+                WatchdogEvent::Alert { since: at(0) }
+            ]
+        );
+        // 短暂离线未通知时，恢复后不发恢复通知
+        let config = WatchdogConfig::default();
+        let mut watchdog = OnlineWatchdog::new(&config);
+        watchdog.record("wx1", false, at(0));
+        assert_eq!(
+            watchdog.record("wx1", true, at(60)),
+            [WatchdogEvent::Recovered {
+                since: at(0),
+                alerted: false
+            }]
+        );
+    }
+}
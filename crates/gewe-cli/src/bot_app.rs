@@ -0,0 +1,72 @@
+//! gewe-bot-app 管理 API 客户端
+//!
+//! 供 `rule-template`、`tools` 等命令调用运行中的 gewe-bot-app。
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+/// gewe-bot-app 管理 API 连接参数
+#[derive(Args, Clone)]
+pub struct BotAppArgs {
+    /// gewe-bot-app 服务地址
+    #[arg(long, default_value = "http://127.0.0.1:3000")]
+    pub server: String,
+    /// API Token，未指定时读取 GEWE_API_TOKEN
+    #[arg(long)]
+    pub api_token: Option<String>,
+}
+
+impl BotAppArgs {
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/api{}", self.server.trim_end_matches('/'), path)
+    }
+
+    pub fn authorize(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self
+            .api_token
+            .clone()
+            .or_else(|| std::env::var("GEWE_API_TOKEN").ok())
+        {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    /// 发送请求并解析 `{success, data, error, errors}` 响应，失败时返回带校验错误的信息
+    pub async fn send_json(
+        &self,
+        req: reqwest::RequestBuilder,
+        action: &str,
+    ) -> Result<serde_json::Value> {
+        let resp = self.authorize(req).send().await?;
+        let status = resp.status();
+        let mut json: serde_json::Value = resp.json().await?;
+        if !status.is_success() {
+            let mut msg = json["error"].as_str().unwrap_or("未知错误").to_string();
+            if let Some(errors) = json["errors"].as_array() {
+                for e in errors {
+                    msg.push_str(&format!("\n  - {}", e.as_str().unwrap_or_default()));
+                }
+            }
+            return Err(anyhow!("{} ({}): {}", action, status, msg));
+        }
+        Ok(json["data"].take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bot_app_url() {
+        let args = BotAppArgs {
+            server: "http://localhost:3000/".to_string(),
+            api_token: None,
+        };
+        assert_eq!(
+            args.url("/rule-templates/ask/export"),
+            "http://localhost:3000/api/rule-templates/ask/export"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use anyhow::{anyhow, Result};
 use clap::Args;
 use directories::{BaseDirs, ProjectDirs};
+use gewe_http::ChatroomNames;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -15,6 +17,9 @@ pub struct CliConfig {
     pub device_type: Option<String>,
     #[serde(default)]
     pub bots: Vec<BotRecord>,
+    /// 群名别名（群 ID → 显示名），`--chatroom-id` 可直接填写别名
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub chatroom_aliases: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -50,6 +55,11 @@ pub struct ConfigArgs {
     pub alias_target_app_id: Option<String>,
     #[arg(long)]
     pub alias_target_wxid: Option<String>,
+    /// 设置群名别名，格式为 `群ID=显示名`，显示名留空则删除
+    #[arg(long, value_name = "ID=NAME")]
+    pub chatroom_alias: Option<String>,
+    #[arg(long)]
+    pub list_chatrooms: bool,
 }
 
 pub fn resolve_config_path(custom: Option<&Path>) -> Result<PathBuf> {
@@ -125,6 +135,41 @@ pub fn set_alias(config: &mut CliConfig, target: &str, alias: String) -> bool {
     }
 }
 
+/// 由配置中的群名别名构造解析器
+pub fn chatroom_names(config: &CliConfig) -> ChatroomNames {
+    let names = ChatroomNames::default();
+    names.set_aliases(config.chatroom_aliases.clone());
+    names
+}
+
+/// 群 ID 原样返回；否则按别名反查群 ID，找不到时原样返回
+pub fn resolve_chatroom(config: &CliConfig, input: &str) -> String {
+    if input.ends_with("@chatroom") {
+        return input.to_string();
+    }
+    chatroom_names(config)
+        .find_id(input)
+        .unwrap_or_else(|| input.to_string())
+}
+
+pub fn set_chatroom_alias(config: &mut CliConfig, spec: &str) -> Result<()> {
+    let (id, name) = spec
+        .split_once('=')
+        .ok_or_else(|| anyhow!("--chatroom-alias expects ID=NAME"))?;
+    let (id, name) = (id.trim(), name.trim());
+    if !id.ends_with("@chatroom") {
+        return Err(anyhow!("chatroom id must end with @chatroom: {id}"));
+    }
+    if name.is_empty() {
+        config.chatroom_aliases.remove(id);
+    } else {
+        config
+            .chatroom_aliases
+            .insert(id.to_string(), name.to_string());
+    }
+    Ok(())
+}
+
 pub fn resolve_value(
     value: Option<String>,
     fallback: Option<String>,
@@ -150,6 +195,8 @@ pub fn handle_config(args: ConfigArgs, config_path: &Path, config: &mut CliConfi
         alias,
         alias_target_app_id,
         alias_target_wxid,
+        chatroom_alias,
+        list_chatrooms,
     } = args;
 
     let mut updated = false;
@@ -184,6 +231,10 @@ pub fn handle_config(args: ConfigArgs, config_path: &Path, config: &mut CliConfi
             eprintln!("--alias requires --alias-target-app-id or --alias-target-wxid");
         }
     }
+    if let Some(spec) = chatroom_alias {
+        set_chatroom_alias(config, &spec)?;
+        updated = true;
+    }
     if updated {
         save_config(config_path, config)?;
         println!("Config updated at {}", config_path.display());
@@ -200,6 +251,15 @@ pub fn handle_config(args: ConfigArgs, config_path: &Path, config: &mut CliConfi
                 );
             }
         }
+    } else if list_chatrooms {
+        if config.chatroom_aliases.is_empty() {
+            println!("No chatroom aliases stored");
+        } else {
+            let names = chatroom_names(config);
+            for id in config.chatroom_aliases.keys() {
+                println!("{}", names.label(id));
+            }
+        }
     } else {
         println!("{}", toml::to_string_pretty(config)?);
     }
@@ -381,6 +441,24 @@ mod tests {
             .contains("test_field required"));
     }
 
+    #[test]
+    fn test_chatroom_alias() {
+        let mut config = CliConfig::default();
+        set_chatroom_alias(&mut config, "123@chatroom=技术群").unwrap();
+        assert_eq!(resolve_chatroom(&config, "技术群"), "123@chatroom");
+        assert_eq!(resolve_chatroom(&config, "456@chatroom"), "456@chatroom");
+        assert_eq!(resolve_chatroom(&config, "运维群"), "运维群");
+        assert_eq!(
+            chatroom_names(&config).label("123@chatroom"),
+            "技术群 (123@chatroom)"
+        );
+
+        assert!(set_chatroom_alias(&mut config, "wxid_a=Alice").is_err());
+        assert!(set_chatroom_alias(&mut config, "123@chatroom").is_err());
+        set_chatroom_alias(&mut config, "123@chatroom=").unwrap();
+        assert!(config.chatroom_aliases.is_empty());
+    }
+
     #[test]
     fn test_save_and_load_config() {
         let temp_dir = TempDir::new().unwrap();
@@ -1,4 +1,5 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_http::GeweHttpClient;
@@ -277,6 +278,7 @@ pub async fn handle_fetch_contacts_list(
     args: FetchContactsListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let FetchContactsListArgs {
         token,
@@ -301,7 +303,7 @@ pub async fn handle_fetch_contacts_list(
         ghs = resp.ghs.len(),
         "contacts list fetched"
     );
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -309,6 +311,7 @@ pub async fn handle_fetch_contacts_list_cache(
     args: FetchContactsListCacheArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let FetchContactsListCacheArgs {
         token,
@@ -328,7 +331,7 @@ pub async fn handle_fetch_contacts_list_cache(
         .fetch_contacts_list_cache(gewe_core::FetchContactsListCacheRequest { app_id: &app_id })
         .await?;
     info!("contact list cache fetched");
-    println!("通讯录列表缓存获取成功");
+    output.print_text(&serde_json::json!({ "ok": true }), "通讯录列表缓存获取成功")?;
     Ok(())
 }
 
@@ -336,6 +339,7 @@ pub async fn handle_search_contacts(
     args: SearchContactsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SearchContactsArgs {
         token,
@@ -359,7 +363,7 @@ pub async fn handle_search_contacts(
         })
         .await?;
     info!(nick=%resp.nick_name, "contact search completed");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -367,6 +371,7 @@ pub async fn handle_add_contacts(
     args: AddContactsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let AddContactsArgs {
         token,
@@ -398,6 +403,7 @@ pub async fn handle_add_contacts(
         })
         .await?;
     info!(option, "contact add/accept triggered");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -405,6 +411,7 @@ pub async fn handle_set_friend_remark(
     args: SetFriendRemarkArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetFriendRemarkArgs {
         token,
@@ -430,6 +437,7 @@ pub async fn handle_set_friend_remark(
         })
         .await?;
     info!(%wxid, "friend remark set");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -437,6 +445,7 @@ pub async fn handle_set_friend_permissions(
     args: SetFriendPermissionsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetFriendPermissionsArgs {
         token,
@@ -462,6 +471,7 @@ pub async fn handle_set_friend_permissions(
         })
         .await?;
     info!(%wxid, only_chat, "friend permission updated");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -469,6 +479,7 @@ pub async fn handle_delete_friend(
     args: DeleteFriendArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DeleteFriendArgs {
         token,
@@ -492,6 +503,7 @@ pub async fn handle_delete_friend(
         })
         .await?;
     info!(%wxid, "friend deleted");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -499,6 +511,7 @@ pub async fn handle_check_contact_relation(
     args: CheckContactRelationArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let CheckContactRelationArgs {
         token,
@@ -523,7 +536,7 @@ pub async fn handle_check_contact_relation(
         })
         .await?;
     info!(count = resp.len(), "contact relations checked");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -531,6 +544,7 @@ pub async fn handle_get_contact_brief_info(
     args: GetContactBriefInfoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetContactBriefInfoArgs {
         token,
@@ -555,7 +569,7 @@ pub async fn handle_get_contact_brief_info(
         })
         .await?;
     info!(count = resp.len(), "contact brief info fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -563,6 +577,7 @@ pub async fn handle_get_contact_detail_info(
     args: GetContactDetailInfoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetContactDetailInfoArgs {
         token,
@@ -587,7 +602,7 @@ pub async fn handle_get_contact_detail_info(
         })
         .await?;
     info!(count = resp.len(), "contact detail info fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -595,6 +610,7 @@ pub async fn handle_get_phone_address_list(
     args: GetPhoneAddressListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetPhoneAddressListArgs {
         token,
@@ -623,7 +639,7 @@ pub async fn handle_get_phone_address_list(
         })
         .await?;
     info!(count = resp.len(), "phone address list fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -631,6 +647,7 @@ pub async fn handle_upload_phone_address_list(
     args: UploadPhoneAddressListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let UploadPhoneAddressListArgs {
         token,
@@ -657,6 +674,7 @@ pub async fn handle_upload_phone_address_list(
         })
         .await?;
     info!(count = phones.len(), op_type, "phone address list uploaded");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -664,6 +682,7 @@ pub async fn handle_search_wecom_contact(
     args: SearchWecomContactArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SearchWecomContactArgs {
         token,
@@ -689,7 +708,7 @@ pub async fn handle_search_wecom_contact(
         })
         .await?;
     info!(nick=%resp.nick_name, "wecom contact searched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -697,6 +716,7 @@ pub async fn handle_sync_wecom_contacts(
     args: SyncWecomContactsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SyncWecomContactsArgs {
         token,
@@ -716,7 +736,7 @@ pub async fn handle_sync_wecom_contacts(
         .sync_wecom_contacts(gewe_core::SyncWecomContactsRequest { app_id: &app_id })
         .await?;
     info!(count = resp.len(), "wecom contacts synced");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -724,6 +744,7 @@ pub async fn handle_add_wecom_contact(
     args: AddWecomContactArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let AddWecomContactArgs {
         token,
@@ -749,6 +770,7 @@ pub async fn handle_add_wecom_contact(
         })
         .await?;
     info!(%v3, "wecom contact add triggered");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -756,6 +778,7 @@ pub async fn handle_get_wecom_contact_detail(
     args: GetWecomContactDetailArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetWecomContactDetailArgs {
         token,
@@ -779,7 +802,7 @@ pub async fn handle_get_wecom_contact_detail(
         })
         .await?;
     info!(nick=%resp.nick_name, "wecom contact detail fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1,9 +1,9 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_core::{DeleteFavorRequest, GetFavorContentRequest, SyncFavorRequest};
 use gewe_http::GeweHttpClient;
-use serde_json::to_string_pretty;
 use std::path::Path;
 use tracing::info;
 
@@ -49,6 +49,7 @@ pub async fn handle_sync_favorites(
     args: SyncFavoriteArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -57,7 +58,7 @@ pub async fn handle_sync_favorites(
             sync_key: args.sync_key.as_deref(),
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -65,6 +66,7 @@ pub async fn handle_get_favorite_content(
     args: GetFavoriteContentArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -73,7 +75,7 @@ pub async fn handle_get_favorite_content(
             fav_id: args.fav_id,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -81,6 +83,7 @@ pub async fn handle_delete_favorite(
     args: DeleteFavoriteArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -90,6 +93,7 @@ pub async fn handle_delete_favorite(
         })
         .await?;
     info!(fav_id = args.fav_id, "favorite deleted");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1,4 +1,6 @@
-use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::config::{default_base_url, lookup_bot, resolve_chatroom, resolve_value, CliConfig};
+use crate::output::OutputFormat;
+use crate::qr::{print_qr, QrOutputArgs};
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_http::GeweHttpClient;
@@ -347,6 +349,8 @@ pub struct GetChatroomQrCodeArgs {
     pub chatroom_id: String,
     #[arg(long)]
     pub base_url: Option<String>,
+    #[command(flatten)]
+    pub qr: QrOutputArgs,
 }
 
 #[derive(Args)]
@@ -427,6 +431,7 @@ pub async fn handle_create_chatroom(
     args: CreateChatroomArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let CreateChatroomArgs {
         token,
@@ -450,6 +455,7 @@ pub async fn handle_create_chatroom(
         })
         .await?;
     info!(chatroom_id=%resp.chatroom_id, "chatroom created");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -457,6 +463,7 @@ pub async fn handle_disband_chatroom(
     args: DisbandChatroomArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DisbandChatroomArgs {
         token,
@@ -472,6 +479,7 @@ pub async fn handle_disband_chatroom(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .disband_chatroom(gewe_core::DisbandChatroomRequest {
@@ -480,6 +488,7 @@ pub async fn handle_disband_chatroom(
         })
         .await?;
     info!(%chatroom_id, "chatroom disbanded");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -487,6 +496,7 @@ pub async fn handle_quit_chatroom(
     args: QuitChatroomArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let QuitChatroomArgs {
         token,
@@ -502,6 +512,7 @@ pub async fn handle_quit_chatroom(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .quit_chatroom(gewe_core::QuitChatroomRequest {
@@ -510,6 +521,7 @@ pub async fn handle_quit_chatroom(
         })
         .await?;
     info!(%chatroom_id, "chatroom quitted");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -517,6 +529,7 @@ pub async fn handle_modify_chatroom_name(
     args: ModifyChatroomNameArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ModifyChatroomNameArgs {
         token,
@@ -533,6 +546,7 @@ pub async fn handle_modify_chatroom_name(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .modify_chatroom_name(gewe_core::ModifyChatroomNameRequest {
@@ -542,6 +556,7 @@ pub async fn handle_modify_chatroom_name(
         })
         .await?;
     info!(%chatroom_id, "chatroom name modified");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -549,6 +564,7 @@ pub async fn handle_modify_chatroom_remark(
     args: ModifyChatroomRemarkArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ModifyChatroomRemarkArgs {
         token,
@@ -565,6 +581,7 @@ pub async fn handle_modify_chatroom_remark(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .modify_chatroom_remark(gewe_core::ModifyChatroomRemarkRequest {
@@ -574,6 +591,7 @@ pub async fn handle_modify_chatroom_remark(
         })
         .await?;
     info!(%chatroom_id, "chatroom remark modified");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -581,6 +599,7 @@ pub async fn handle_modify_chatroom_nick_name_for_self(
     args: ModifyChatroomNickNameForSelfArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ModifyChatroomNickNameForSelfArgs {
         token,
@@ -597,6 +616,7 @@ pub async fn handle_modify_chatroom_nick_name_for_self(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .modify_chatroom_nick_name_for_self(gewe_core::ModifyChatroomNickNameForSelfRequest {
@@ -606,6 +626,7 @@ pub async fn handle_modify_chatroom_nick_name_for_self(
         })
         .await?;
     info!(%chatroom_id, "self nickname modified");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -613,6 +634,7 @@ pub async fn handle_invite_member(
     args: InviteMemberArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let InviteMemberArgs {
         token,
@@ -630,6 +652,7 @@ pub async fn handle_invite_member(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .invite_member(gewe_core::InviteMemberRequest {
@@ -640,6 +663,7 @@ pub async fn handle_invite_member(
         })
         .await?;
     info!(%chatroom_id, invited=?wxids, %reason, "members invited");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -647,6 +671,7 @@ pub async fn handle_remove_member(
     args: RemoveMemberArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let RemoveMemberArgs {
         token,
@@ -663,6 +688,7 @@ pub async fn handle_remove_member(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .remove_member(gewe_core::RemoveMemberRequest {
@@ -672,6 +698,7 @@ pub async fn handle_remove_member(
         })
         .await?;
     info!(%chatroom_id, removed=?wxids, "members removed");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -679,6 +706,7 @@ pub async fn handle_join_room_using_qr_code(
     args: JoinRoomUsingQrCodeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let JoinRoomUsingQrCodeArgs {
         token,
@@ -704,6 +732,7 @@ pub async fn handle_join_room_using_qr_code(
         })
         .await?;
     info!(%chatroom_name, %qr_uuid, "join room using QR requested");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -711,6 +740,7 @@ pub async fn handle_agree_join_room(
     args: AgreeJoinRoomArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let AgreeJoinRoomArgs {
         token,
@@ -742,6 +772,7 @@ pub async fn handle_agree_join_room(
         })
         .await?;
     info!(%msg_id, "join room agreed");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -749,6 +780,7 @@ pub async fn handle_room_access_apply_check_approve(
     args: RoomAccessApplyCheckApproveArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let RoomAccessApplyCheckApproveArgs {
         token,
@@ -766,6 +798,7 @@ pub async fn handle_room_access_apply_check_approve(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .room_access_apply_check_approve(gewe_core::RoomAccessApplyCheckApproveRequest {
@@ -776,6 +809,7 @@ pub async fn handle_room_access_apply_check_approve(
         })
         .await?;
     info!(%chatroom_id, %wxid, "room access approved");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -783,6 +817,7 @@ pub async fn handle_invite_add_enter_room(
     args: InviteAddEnterRoomArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let InviteAddEnterRoomArgs {
         token,
@@ -800,6 +835,7 @@ pub async fn handle_invite_add_enter_room(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .invite_add_enter_room(gewe_core::InviteAddEnterRoomRequest {
@@ -810,6 +846,7 @@ pub async fn handle_invite_add_enter_room(
         })
         .await?;
     info!(%chatroom_id, invited=?wxids, "members invited (exp)");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -817,6 +854,7 @@ pub async fn handle_add_group_member_as_friend(
     args: AddGroupMemberAsFriendArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let AddGroupMemberAsFriendArgs {
         token,
@@ -834,6 +872,7 @@ pub async fn handle_add_group_member_as_friend(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .add_group_member_as_friend(gewe_core::AddGroupMemberAsFriendRequest {
@@ -844,6 +883,7 @@ pub async fn handle_add_group_member_as_friend(
         })
         .await?;
     info!(%chatroom_id, %wxid, "friend request sent");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -851,6 +891,7 @@ pub async fn handle_get_chatroom_member_list(
     args: GetChatroomMemberListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetChatroomMemberListArgs {
         token,
@@ -866,6 +907,7 @@ pub async fn handle_get_chatroom_member_list(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .get_chatroom_member_list(gewe_core::GetChatroomMemberListRequest {
@@ -874,7 +916,7 @@ pub async fn handle_get_chatroom_member_list(
         })
         .await?;
     info!(owner=%resp.chat_room_owner, members=%resp.chatroom_members.len(), "member list fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -882,6 +924,7 @@ pub async fn handle_get_chatroom_member_detail(
     args: GetChatroomMemberDetailArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetChatroomMemberDetailArgs {
         token,
@@ -898,6 +941,7 @@ pub async fn handle_get_chatroom_member_detail(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .get_chatroom_member_detail(gewe_core::GetChatroomMemberDetailRequest {
@@ -907,7 +951,7 @@ pub async fn handle_get_chatroom_member_detail(
         })
         .await?;
     info!(member=%resp.wxid, nick=%resp.nick_name, "member detail fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -915,6 +959,7 @@ pub async fn handle_get_chatroom_info(
     args: GetChatroomInfoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetChatroomInfoArgs {
         token,
@@ -930,6 +975,7 @@ pub async fn handle_get_chatroom_info(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .get_chatroom_info(gewe_core::GetChatroomInfoRequest {
@@ -937,8 +983,8 @@ pub async fn handle_get_chatroom_info(
             chatroom_id: &chatroom_id,
         })
         .await?;
-    info!(chatroom=%resp.chatroom_id, members=%resp.member_list.len(), "chatroom info fetched");
-    println!("{resp:#?}");
+    info!(chatroom=%resp.chatroom_id, name=%resp.nick_name, members=%resp.member_list.len(), "chatroom info fetched");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -946,6 +992,7 @@ pub async fn handle_get_chatroom_announcement(
     args: GetChatroomAnnouncementArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetChatroomAnnouncementArgs {
         token,
@@ -962,6 +1009,7 @@ pub async fn handle_get_chatroom_announcement(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .get_chatroom_announcement(gewe_core::GetChatroomAnnouncementRequest {
@@ -971,7 +1019,7 @@ pub async fn handle_get_chatroom_announcement(
         })
         .await?;
     info!(%chatroom_id, sender=%resp.sender, "announcement fetched");
-    println!("{}", resp.chat_room_announcement);
+    output.print_text(&resp, &resp.chat_room_announcement)?;
     Ok(())
 }
 
@@ -979,6 +1027,7 @@ pub async fn handle_set_chatroom_announcement(
     args: SetChatroomAnnouncementArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetChatroomAnnouncementArgs {
         token,
@@ -995,6 +1044,7 @@ pub async fn handle_set_chatroom_announcement(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .set_chatroom_announcement(gewe_core::SetChatroomAnnouncementRequest {
@@ -1004,6 +1054,7 @@ pub async fn handle_set_chatroom_announcement(
         })
         .await?;
     info!(%chatroom_id, "announcement set");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1011,6 +1062,7 @@ pub async fn handle_get_chatroom_qr_code(
     args: GetChatroomQrCodeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetChatroomQrCodeArgs {
         token,
@@ -1019,6 +1071,7 @@ pub async fn handle_get_chatroom_qr_code(
         bot_alias,
         chatroom_id,
         base_url,
+        qr,
     } = args;
     let token = resolve_value(token, config.token.clone(), "token")?;
     let base_url = base_url
@@ -1026,6 +1079,7 @@ pub async fn handle_get_chatroom_qr_code(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .get_chatroom_qr_code(gewe_core::GetChatroomQrCodeRequest {
@@ -1034,7 +1088,7 @@ pub async fn handle_get_chatroom_qr_code(
         })
         .await?;
     info!(%chatroom_id, "qr code fetched");
-    println!("{}", resp.qr_img_base64);
+    print_qr(output, &qr, &resp, &resp.qr_url, &resp.qr_img_base64)?;
     Ok(())
 }
 
@@ -1042,6 +1096,7 @@ pub async fn handle_save_contract_list(
     args: SaveContractListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SaveContractListArgs {
         token,
@@ -1058,6 +1113,7 @@ pub async fn handle_save_contract_list(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .save_contract_list(gewe_core::SaveContractListRequest {
@@ -1067,6 +1123,7 @@ pub async fn handle_save_contract_list(
         })
         .await?;
     info!(%chatroom_id, %save, "save to contacts set");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1074,6 +1131,7 @@ pub async fn handle_pin_chat(
     args: PinChatArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let PinChatArgs {
         token,
@@ -1090,6 +1148,7 @@ pub async fn handle_pin_chat(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .pin_chat(gewe_core::PinChatRequest {
@@ -1099,6 +1158,7 @@ pub async fn handle_pin_chat(
         })
         .await?;
     info!(%chatroom_id, %add, "pin chat set");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1106,6 +1166,7 @@ pub async fn handle_set_msg_silence(
     args: SetMsgSilenceArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetMsgSilenceArgs {
         token,
@@ -1122,6 +1183,7 @@ pub async fn handle_set_msg_silence(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .set_msg_silence(gewe_core::SetMsgSilenceRequest {
@@ -1131,6 +1193,7 @@ pub async fn handle_set_msg_silence(
         })
         .await?;
     info!(%chatroom_id, %switch_, "msg silence set");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1138,6 +1201,7 @@ pub async fn handle_admin_operate(
     args: AdminOperateArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let AdminOperateArgs {
         token,
@@ -1155,6 +1219,7 @@ pub async fn handle_admin_operate(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let chatroom_id = resolve_chatroom(config, &chatroom_id);
     let client = GeweHttpClient::new(token, base_url)?;
     client
         .admin_operate(gewe_core::AdminOperateRequest {
@@ -1165,6 +1230,7 @@ pub async fn handle_admin_operate(
         })
         .await?;
     info!(%chatroom_id, %wxid, %is_admin, "admin operate done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1482,6 +1548,7 @@ mod tests {
             bot_alias: None,
             chatroom_id: "chatroom_qr@chatroom".to_string(),
             base_url: None,
+            qr: QrOutputArgs::default(),
         };
 
         assert_eq!(args.chatroom_id, "chatroom_qr@chatroom");
@@ -1,14 +1,18 @@
 use crate::config::{default_base_url, resolve_value, save_config, upsert_bot, CliConfig};
+use crate::output::OutputFormat;
+use crate::qr::{eprint_qr, print_qr, QrOutputArgs};
 use anyhow::Result;
+use async_trait::async_trait;
 use clap::Args;
 use gewe_core::{
-    ChangeMacToIpadRequest, CheckLoginRequest, CheckOnlineRequest, DialogLoginRequest,
-    GetLoginQrCodeRequest, LoginByAccountRequest, LogoutRequest, ReconnectionRequest,
-    SetCallbackRequest,
+    ChangeMacToIpadRequest, CheckLoginRequest, CheckLoginResponse, CheckOnlineRequest,
+    DialogLoginRequest, GetLoginQrCodeRequest, GetLoginQrCodeResponse, LoginByAccountRequest,
+    LogoutRequest, ReconnectionRequest, SetCallbackRequest,
 };
-use gewe_http::GeweHttpClient;
-use serde_json::to_string_pretty;
+use gewe_http::{GeweHttpClient, LoginFlow, LoginHooks};
 use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tracing::info;
 
 #[derive(Args)]
@@ -23,6 +27,44 @@ pub struct GetLoginQrArgs {
     pub region_id: Option<String>,
     #[arg(long)]
     pub base_url: Option<String>,
+    #[command(flatten)]
+    pub qr: QrOutputArgs,
+}
+
+#[derive(Args)]
+pub struct LoginArgs {
+    #[arg(long)]
+    pub token: Option<String>,
+    /// 重新登录已有设备时填写，首次登录留空
+    #[arg(long)]
+    pub app_id: Option<String>,
+    #[arg(long)]
+    pub device_type: Option<String>,
+    #[arg(long)]
+    pub region_id: Option<String>,
+    #[arg(long)]
+    pub proxy_ip: Option<String>,
+    #[arg(long)]
+    pub base_url: Option<String>,
+    /// 登录成功后设置的回调地址
+    #[arg(long)]
+    pub callback_url: Option<String>,
+    /// 回调请求携带的 token，不设置时默认与 --token 一致
+    #[arg(long)]
+    pub callback_token: Option<String>,
+    /// 轮询间隔（秒）
+    #[arg(long, default_value_t = 5)]
+    pub poll_interval: u64,
+    /// 等待扫码的超时（秒），期间二维码过期会自动刷新
+    #[arg(long, default_value_t = 300)]
+    pub timeout: u64,
+    /// 扫码后提示输入手机上显示的验证码（异地登录时需要）
+    #[arg(long)]
+    pub captcha: bool,
+    #[arg(long, default_value_t = true)]
+    pub auto_sliding: bool,
+    #[command(flatten)]
+    pub qr: QrOutputArgs,
 }
 
 #[derive(Args)]
@@ -132,6 +174,7 @@ pub async fn handle_get_login_qr(
     args: GetLoginQrArgs,
     config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetLoginQrArgs {
         token,
@@ -139,6 +182,7 @@ pub async fn handle_get_login_qr(
         device_type,
         region_id,
         base_url,
+        qr,
     } = args;
     let token = resolve_value(token, config.token.clone(), "token")?;
     let base_url = base_url
@@ -166,7 +210,106 @@ pub async fn handle_get_login_qr(
     config.app_id = Some(resp.app_id.clone());
     upsert_bot(config, &resp.app_id, None);
     save_config(config_path, config)?;
-    println!("{}", resp.qr_img_base64);
+    print_qr(output, &qr, &resp, &resp.qr_data, &resp.qr_img_base64)?;
+    Ok(())
+}
+
+/// `login` 命令的交互：二维码与状态输出到标准错误，标准输出只保留最终结果
+struct CliLoginHooks {
+    qr: QrOutputArgs,
+    captcha: bool,
+}
+
+#[async_trait]
+impl LoginHooks for CliLoginHooks {
+    async fn on_qr_code(&self, qr: &GetLoginQrCodeResponse) {
+        if let Err(e) = eprint_qr(&self.qr, &qr.qr_data, &qr.qr_img_base64) {
+            tracing::warn!(error = %e, "failed to show login QR");
+        }
+        eprintln!("请使用微信扫描二维码登录");
+    }
+
+    async fn on_status(&self, status: &CheckLoginResponse) {
+        info!(status = status.status, expired_time = ?status.expired_time, "check login");
+    }
+
+    async fn captcha_code(&self, status: &CheckLoginResponse) -> Option<String> {
+        match &status.nick_name {
+            Some(nick_name) => eprintln!("{} 已扫码，请在手机上确认登录", nick_name),
+            None => eprintln!("已扫码，请在手机上确认登录"),
+        }
+        if !self.captcha {
+            return None;
+        }
+        eprintln!("如手机提示验证码请输入后回车，无需验证码直接回车：");
+        let mut line = String::new();
+        BufReader::new(tokio::io::stdin())
+            .read_line(&mut line)
+            .await
+            .ok()?;
+        let code = line.trim();
+        (!code.is_empty()).then(|| code.to_string())
+    }
+}
+
+pub async fn handle_login(
+    args: LoginArgs,
+    config_path: &Path,
+    config: &mut CliConfig,
+    output: OutputFormat,
+) -> Result<()> {
+    let LoginArgs {
+        token,
+        app_id,
+        device_type,
+        region_id,
+        proxy_ip,
+        base_url,
+        callback_url,
+        callback_token,
+        poll_interval,
+        timeout,
+        captcha,
+        auto_sliding,
+        qr,
+    } = args;
+    let token = resolve_value(token, config.token.clone(), "token")?;
+    let base_url = base_url
+        .or_else(|| config.base_url.clone())
+        .unwrap_or_else(default_base_url);
+    let app_id = app_id.or_else(|| config.app_id.clone()).unwrap_or_default();
+    let device_type = device_type
+        .or_else(|| config.device_type.clone())
+        .unwrap_or_else(|| "ipad".to_string());
+    let region_id = region_id
+        .or_else(|| config.region_id.clone())
+        .unwrap_or_else(|| "320000".to_string());
+    let client = GeweHttpClient::new(token.clone(), base_url)?;
+    let mut flow = LoginFlow::new(client, token.clone())
+        .app_id(app_id)
+        .device_type(device_type)
+        .region_id(region_id)
+        .auto_sliding(auto_sliding)
+        .poll_interval(Duration::from_secs(poll_interval.max(1)))
+        .timeout(Duration::from_secs(timeout));
+    if let Some(proxy_ip) = proxy_ip {
+        flow = flow.proxy_ip(proxy_ip);
+    }
+    if let Some(callback_url) = callback_url {
+        flow = flow.callback(callback_url, callback_token.unwrap_or(token));
+    }
+    let outcome = flow.run(&CliLoginHooks { qr, captcha }).await?;
+    config.app_id = Some(outcome.app_id.clone());
+    upsert_bot(config, &outcome.app_id, outcome.wxid.clone());
+    save_config(config_path, config)?;
+    match output {
+        OutputFormat::Table => println!(
+            "登录成功: {} ({})",
+            outcome.nick_name.as_deref().unwrap_or_default(),
+            outcome.wxid.as_deref().unwrap_or(&outcome.app_id)
+        ),
+        _ => output.print(&outcome)?,
+    }
     Ok(())
 }
 
@@ -174,6 +317,7 @@ pub async fn handle_check_login(
     args: CheckLoginArgs,
     config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let CheckLoginArgs {
         token,
@@ -199,6 +343,7 @@ pub async fn handle_check_login(
         .await?;
     let wxid = resp.login_info.as_ref().and_then(|info| info.wxid.clone());
     info!(status=%resp.status, uuid=%resp.uuid, ?wxid, "check login result");
+    output.print(&resp)?;
     if resp.status == 2 {
         config.app_id = Some(app_id.clone());
         upsert_bot(config, &app_id, wxid);
@@ -211,6 +356,7 @@ pub async fn handle_dialog_login(
     args: DialogLoginArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DialogLoginArgs {
         token,
@@ -238,7 +384,7 @@ pub async fn handle_dialog_login(
         })
         .await?;
     info!(uuid = %resp.uuid, "dialog login triggered");
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -246,6 +392,7 @@ pub async fn handle_login_by_account(
     args: LoginByAccountArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let LoginByAccountArgs {
         token,
@@ -273,7 +420,7 @@ pub async fn handle_login_by_account(
             step,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -281,6 +428,7 @@ pub async fn handle_set_callback(
     args: SetCallbackArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetCallbackArgs {
         token,
@@ -301,6 +449,7 @@ pub async fn handle_set_callback(
         })
         .await?;
     info!("callback url updated");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -308,6 +457,7 @@ pub async fn handle_change_mac_to_ipad(
     args: ChangeMacToIpadArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ChangeMacToIpadArgs {
         token,
@@ -323,7 +473,7 @@ pub async fn handle_change_mac_to_ipad(
     let resp = client
         .change_mac_to_ipad(ChangeMacToIpadRequest { app_id: &app_id })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -331,6 +481,7 @@ pub async fn handle_check_online(
     args: CheckOnlineArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let CheckOnlineArgs {
         token,
@@ -346,7 +497,7 @@ pub async fn handle_check_online(
     let online = client
         .check_online(CheckOnlineRequest { app_id: &app_id })
         .await?;
-    println!("{}", online);
+    output.print(&online)?;
     Ok(())
 }
 
@@ -354,6 +505,7 @@ pub async fn handle_reconnection(
     args: ReconnectionArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ReconnectionArgs {
         token,
@@ -370,9 +522,10 @@ pub async fn handle_reconnection(
         .reconnection(ReconnectionRequest { app_id: &app_id })
         .await?;
     if let Some(data) = resp {
-        println!("{}", to_string_pretty(&data)?);
+        output.print(&data)?;
     } else {
         info!("reconnection request accepted, waiting for confirmation");
+        output.print_ok()?;
     }
     Ok(())
 }
@@ -381,6 +534,7 @@ pub async fn handle_logout(
     args: LogoutArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let LogoutArgs {
         token,
@@ -395,6 +549,7 @@ pub async fn handle_logout(
     let client = GeweHttpClient::new(token, base_url)?;
     client.logout(LogoutRequest { app_id: &app_id }).await?;
     info!("logout command sent");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -410,6 +565,7 @@ mod tests {
             device_type: Some("ipad".to_string()),
             region_id: Some("320000".to_string()),
             base_url: None,
+            qr: QrOutputArgs::default(),
         };
 
         assert_eq!(args.token, Some("test_token".to_string()));
@@ -550,6 +706,7 @@ mod tests {
             device_type: Some("mac".to_string()),
             region_id: Some("110000".to_string()),
             base_url: Some("http://custom.com".to_string()),
+            qr: QrOutputArgs::default(),
         };
 
         assert_eq!(args.token, Some("token123".to_string()));
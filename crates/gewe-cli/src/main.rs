@@ -1,3 +1,5 @@
+#[cfg(feature = "bot-app")]
+mod bot_app;
 mod config;
 mod contact;
 mod favorite;
@@ -5,10 +7,26 @@ mod group;
 mod login;
 mod message;
 mod moments;
+mod output;
 mod personal;
+mod qr;
+#[cfg(feature = "bot-app")]
+mod rule_template;
+#[cfg(feature = "send-queue")]
+mod schedule_send;
+#[cfg(feature = "bot-app")]
+mod search;
+#[cfg(feature = "bot-app")]
+mod service;
 mod tag;
+#[cfg(feature = "bot-app")]
+mod tail;
+#[cfg(feature = "bot-app")]
+mod tools;
 mod video_account;
+#[cfg(feature = "webhook")]
 mod wait_reply;
+#[cfg(feature = "webhook")]
 mod webhook;
 
 use anyhow::Result;
@@ -43,7 +61,7 @@ use group::{
 };
 use login::{
     handle_change_mac_to_ipad, handle_check_login, handle_check_online, handle_dialog_login,
-    handle_get_login_qr, handle_login_by_account, handle_logout, handle_reconnection,
+    handle_get_login_qr, handle_login, handle_login_by_account, handle_logout, handle_reconnection,
     handle_set_callback,
 };
 use message::{
@@ -54,6 +72,7 @@ use message::{
     handle_send_mini_app, handle_send_name_card, handle_send_text, handle_send_video,
     handle_send_voice,
 };
+use output::OutputFormat;
 use personal::{
     handle_get_profile, handle_get_qr_code, handle_get_safety_info, handle_privacy_settings,
     handle_update_head_img, handle_update_profile, GetProfileArgs, GetQrCodeArgs,
@@ -73,6 +92,9 @@ struct Cli {
     verbose: u8,
     #[arg(long, global = true)]
     config: Option<std::path::PathBuf>,
+    /// 结果输出格式：table / json / yaml，需写在子命令之前，如 `gewe --output json get-profile`
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
     #[command(subcommand)]
     command: Commands,
 }
@@ -274,6 +296,8 @@ enum Commands {
     DialogLogin(login::DialogLoginArgs),
     /// 账号密码登录
     LoginByAccount(login::LoginByAccountArgs),
+    /// 扫码登录：获取二维码、等待扫码确认、设置回调并保存 appId
+    Login(login::LoginArgs),
     /// 设置回调地址
     SetCallback(login::SetCallbackArgs),
     /// Mac 设备转 iPad 登录
@@ -287,254 +311,378 @@ enum Commands {
     /// 查看或更新配置
     Config(config::ConfigArgs),
     /// 启动 webhook 服务器，接收并处理消息事件
+    #[cfg(feature = "webhook")]
     ServeWebhook(webhook::ServeWebhookArgs),
+    /// 导出/导入 gewe-bot-app 规则模板
+    #[cfg(feature = "bot-app")]
+    RuleTemplate {
+        #[command(subcommand)]
+        command: rule_template::RuleTemplateCommands,
+    },
+    /// 管理 gewe-bot-app 外部工具
+    #[cfg(feature = "bot-app")]
+    Tools {
+        #[command(subcommand)]
+        command: tools::ToolsCommands,
+    },
+    /// 安装 gewe-bot-app 系统服务（systemd / launchd / Windows 计划任务）
+    #[cfg(feature = "bot-app")]
+    Service {
+        #[command(subcommand)]
+        command: service::ServiceCommands,
+    },
+    /// 实时查看某个会话的新消息，`--reply` 可在终端直接回复
+    #[cfg(feature = "bot-app")]
+    Tail(tail::TailArgs),
+    /// 全文搜索会话消息记录，需 gewe-bot-app 开启 `[server.history.search]`
+    #[cfg(feature = "bot-app")]
+    Search(search::SearchArgs),
     /// 发送消息后等待特定用户回复
+    #[cfg(feature = "webhook")]
     WaitReply(wait_reply::WaitReplyArgs),
+    /// 定时发送：管理本地持久化发送队列
+    #[cfg(feature = "send-queue")]
+    ScheduleSend {
+        #[command(subcommand)]
+        command: schedule_send::ScheduleSendCommands,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
     let filter = get_log_filter_for_command(&cli);
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+    let output = cli.output;
+    // json/yaml 模式下日志写到 stderr，保持 stdout 可解析
+    if output == OutputFormat::Table {
+        tracing_subscriber::fmt().with_env_filter(filter).init();
+    } else {
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .init();
+    }
     let config_path = resolve_config_path(cli.config.as_deref())?;
     let mut cfg = load_config(&config_path)?;
 
     match cli.command {
-        Commands::SendText(args) => handle_send_text(args, &config_path, &mut cfg).await?,
-        Commands::SendImage(args) => handle_send_image(args, &config_path, &mut cfg).await?,
-        Commands::SendVoice(args) => handle_send_voice(args, &config_path, &mut cfg).await?,
-        Commands::SendVideo(args) => handle_send_video(args, &config_path, &mut cfg).await?,
-        Commands::SendFile(args) => handle_send_file(args, &config_path, &mut cfg).await?,
-        Commands::SendLink(args) => handle_send_link(args, &config_path, &mut cfg).await?,
-        Commands::SendEmoji(args) => handle_send_emoji(args, &config_path, &mut cfg).await?,
-        Commands::SendAppmsg(args) => handle_send_appmsg(args, &config_path, &mut cfg).await?,
-        Commands::SendMiniApp(args) => handle_send_mini_app(args, &config_path, &mut cfg).await?,
-        Commands::SendNameCard(args) => handle_send_name_card(args, &config_path, &mut cfg).await?,
-        Commands::ForwardImage(args) => handle_forward_image(args, &config_path, &mut cfg).await?,
-        Commands::ForwardVideo(args) => handle_forward_video(args, &config_path, &mut cfg).await?,
-        Commands::ForwardFile(args) => handle_forward_file(args, &config_path, &mut cfg).await?,
+        Commands::SendText(args) => handle_send_text(args, &config_path, &mut cfg, output).await?,
+        Commands::SendImage(args) => {
+            handle_send_image(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::SendVoice(args) => {
+            handle_send_voice(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::SendVideo(args) => {
+            handle_send_video(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::SendFile(args) => handle_send_file(args, &config_path, &mut cfg, output).await?,
+        Commands::SendLink(args) => handle_send_link(args, &config_path, &mut cfg, output).await?,
+        Commands::SendEmoji(args) => {
+            handle_send_emoji(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::SendAppmsg(args) => {
+            handle_send_appmsg(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::SendMiniApp(args) => {
+            handle_send_mini_app(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::SendNameCard(args) => {
+            handle_send_name_card(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::ForwardImage(args) => {
+            handle_forward_image(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::ForwardVideo(args) => {
+            handle_forward_video(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::ForwardFile(args) => {
+            handle_forward_file(args, &config_path, &mut cfg, output).await?
+        }
         Commands::ForwardMiniApp(args) => {
-            handle_forward_mini_app(args, &config_path, &mut cfg).await?
+            handle_forward_mini_app(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::ForwardUrl(args) => {
+            handle_forward_url(args, &config_path, &mut cfg, output).await?
         }
-        Commands::ForwardUrl(args) => handle_forward_url(args, &config_path, &mut cfg).await?,
         Commands::DownloadImage(args) => {
-            handle_download_image(args, &config_path, &mut cfg).await?
+            handle_download_image(args, &config_path, &mut cfg, output).await?
         }
         Commands::DownloadVideo(args) => {
-            handle_download_video(args, &config_path, &mut cfg).await?
+            handle_download_video(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::DownloadFile(args) => {
+            handle_download_file(args, &config_path, &mut cfg, output).await?
         }
-        Commands::DownloadFile(args) => handle_download_file(args, &config_path, &mut cfg).await?,
         Commands::DownloadVoice(args) => {
-            handle_download_voice(args, &config_path, &mut cfg).await?
+            handle_download_voice(args, &config_path, &mut cfg, output).await?
         }
         Commands::DownloadEmoji(args) => {
-            handle_download_emoji(args, &config_path, &mut cfg).await?
+            handle_download_emoji(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::DownloadCdn(args) => {
+            handle_download_cdn(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::RevokeMsg(args) => {
+            handle_revoke_msg(args, &config_path, &mut cfg, output).await?
         }
-        Commands::DownloadCdn(args) => handle_download_cdn(args, &config_path, &mut cfg).await?,
-        Commands::RevokeMsg(args) => handle_revoke_msg(args, &config_path, &mut cfg).await?,
         Commands::FetchContactsList(args) => {
-            handle_fetch_contacts_list(args, &config_path, &mut cfg).await?
+            handle_fetch_contacts_list(args, &config_path, &mut cfg, output).await?
         }
         Commands::FetchContactsListCache(args) => {
-            handle_fetch_contacts_list_cache(args, &config_path, &mut cfg).await?
+            handle_fetch_contacts_list_cache(args, &config_path, &mut cfg, output).await?
         }
         Commands::SearchContacts(args) => {
-            handle_search_contacts(args, &config_path, &mut cfg).await?
+            handle_search_contacts(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::AddContacts(args) => {
+            handle_add_contacts(args, &config_path, &mut cfg, output).await?
         }
-        Commands::AddContacts(args) => handle_add_contacts(args, &config_path, &mut cfg).await?,
         Commands::SetFriendRemark(args) => {
-            handle_set_friend_remark(args, &config_path, &mut cfg).await?
+            handle_set_friend_remark(args, &config_path, &mut cfg, output).await?
         }
         Commands::SetFriendPermissions(args) => {
-            handle_set_friend_permissions(args, &config_path, &mut cfg).await?
+            handle_set_friend_permissions(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::DeleteFriend(args) => {
+            handle_delete_friend(args, &config_path, &mut cfg, output).await?
         }
-        Commands::DeleteFriend(args) => handle_delete_friend(args, &config_path, &mut cfg).await?,
         Commands::CheckContactRelation(args) => {
-            handle_check_contact_relation(args, &config_path, &mut cfg).await?
+            handle_check_contact_relation(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetContactBriefInfo(args) => {
-            handle_get_contact_brief_info(args, &config_path, &mut cfg).await?
+            handle_get_contact_brief_info(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetContactDetailInfo(args) => {
-            handle_get_contact_detail_info(args, &config_path, &mut cfg).await?
+            handle_get_contact_detail_info(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetPhoneAddressList(args) => {
-            handle_get_phone_address_list(args, &config_path, &mut cfg).await?
+            handle_get_phone_address_list(args, &config_path, &mut cfg, output).await?
         }
         Commands::UploadPhoneAddressList(args) => {
-            handle_upload_phone_address_list(args, &config_path, &mut cfg).await?
+            handle_upload_phone_address_list(args, &config_path, &mut cfg, output).await?
         }
         Commands::SearchWecomContact(args) => {
-            handle_search_wecom_contact(args, &config_path, &mut cfg).await?
+            handle_search_wecom_contact(args, &config_path, &mut cfg, output).await?
         }
         Commands::SyncWecomContacts(args) => {
-            handle_sync_wecom_contacts(args, &config_path, &mut cfg).await?
+            handle_sync_wecom_contacts(args, &config_path, &mut cfg, output).await?
         }
         Commands::AddWecomContact(args) => {
-            handle_add_wecom_contact(args, &config_path, &mut cfg).await?
+            handle_add_wecom_contact(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetWecomContactDetail(args) => {
-            handle_get_wecom_contact_detail(args, &config_path, &mut cfg).await?
+            handle_get_wecom_contact_detail(args, &config_path, &mut cfg, output).await?
         }
         Commands::SendMomentText(args) => {
-            handle_send_moment_text(args, &config_path, &mut cfg).await?
+            handle_send_moment_text(args, &config_path, &mut cfg, output).await?
         }
         Commands::SendMomentImage(args) => {
-            handle_send_moment_image(args, &config_path, &mut cfg).await?
+            handle_send_moment_image(args, &config_path, &mut cfg, output).await?
         }
         Commands::SendMomentVideo(args) => {
-            handle_send_moment_video(args, &config_path, &mut cfg).await?
+            handle_send_moment_video(args, &config_path, &mut cfg, output).await?
         }
         Commands::SendMomentLink(args) => {
-            handle_send_moment_link(args, &config_path, &mut cfg).await?
+            handle_send_moment_link(args, &config_path, &mut cfg, output).await?
         }
         Commands::ForwardMoment(args) => {
-            handle_forward_moment(args, &config_path, &mut cfg).await?
+            handle_forward_moment(args, &config_path, &mut cfg, output).await?
         }
         Commands::UploadMomentImage(args) => {
-            handle_upload_moment_image(args, &config_path, &mut cfg).await?
+            handle_upload_moment_image(args, &config_path, &mut cfg, output).await?
         }
         Commands::UploadMomentVideo(args) => {
-            handle_upload_moment_video(args, &config_path, &mut cfg).await?
+            handle_upload_moment_video(args, &config_path, &mut cfg, output).await?
         }
         Commands::DownloadMomentVideo(args) => {
-            handle_download_moment_video(args, &config_path, &mut cfg).await?
+            handle_download_moment_video(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::DeleteMoment(args) => {
+            handle_delete_moment(args, &config_path, &mut cfg, output).await?
         }
-        Commands::DeleteMoment(args) => handle_delete_moment(args, &config_path, &mut cfg).await?,
         Commands::SetStrangerVisibility(args) => {
-            handle_set_stranger_visibility(args, &config_path, &mut cfg).await?
+            handle_set_stranger_visibility(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetMomentDetail(args) => {
-            handle_get_moment_detail(args, &config_path, &mut cfg).await?
+            handle_get_moment_detail(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::LikeMoment(args) => {
+            handle_like_moment(args, &config_path, &mut cfg, output).await?
         }
-        Commands::LikeMoment(args) => handle_like_moment(args, &config_path, &mut cfg).await?,
         Commands::CommentMoment(args) => {
-            handle_comment_moment(args, &config_path, &mut cfg).await?
+            handle_comment_moment(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetContactMoments(args) => {
-            handle_get_contact_moments(args, &config_path, &mut cfg).await?
+            handle_get_contact_moments(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetSelfMoments(args) => {
-            handle_get_self_moments(args, &config_path, &mut cfg).await?
+            handle_get_self_moments(args, &config_path, &mut cfg, output).await?
         }
         Commands::SetMomentVisibleScope(args) => {
-            handle_set_moment_visible_scope(args, &config_path, &mut cfg).await?
+            handle_set_moment_visible_scope(args, &config_path, &mut cfg, output).await?
         }
         Commands::SetMomentPrivacy(args) => {
-            handle_set_moment_privacy(args, &config_path, &mut cfg).await?
+            handle_set_moment_privacy(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::GetProfile(args) => {
+            handle_get_profile(args, &config_path, &mut cfg, output).await?
         }
-        Commands::GetProfile(args) => handle_get_profile(args, &config_path, &mut cfg).await?,
         Commands::UpdateProfile(args) => {
-            handle_update_profile(args, &config_path, &mut cfg).await?
+            handle_update_profile(args, &config_path, &mut cfg, output).await?
         }
         Commands::UpdateHeadImg(args) => {
-            handle_update_head_img(args, &config_path, &mut cfg).await?
+            handle_update_head_img(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::GetQrCode(args) => {
+            handle_get_qr_code(args, &config_path, &mut cfg, output).await?
         }
-        Commands::GetQrCode(args) => handle_get_qr_code(args, &config_path, &mut cfg).await?,
         Commands::PrivacySettings(args) => {
-            handle_privacy_settings(args, &config_path, &mut cfg).await?
+            handle_privacy_settings(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetSafetyInfo(args) => {
-            handle_get_safety_info(args, &config_path, &mut cfg).await?
+            handle_get_safety_info(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::GetLoginQr(args) => {
+            handle_get_login_qr(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::CheckLogin(args) => {
+            handle_check_login(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::DialogLogin(args) => {
+            handle_dialog_login(args, &config_path, &mut cfg, output).await?
         }
-        Commands::GetLoginQr(args) => handle_get_login_qr(args, &config_path, &mut cfg).await?,
-        Commands::CheckLogin(args) => handle_check_login(args, &config_path, &mut cfg).await?,
-        Commands::DialogLogin(args) => handle_dialog_login(args, &config_path, &mut cfg).await?,
         Commands::LoginByAccount(args) => {
-            handle_login_by_account(args, &config_path, &mut cfg).await?
+            handle_login_by_account(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::Login(args) => handle_login(args, &config_path, &mut cfg, output).await?,
+        Commands::SetCallback(args) => {
+            handle_set_callback(args, &config_path, &mut cfg, output).await?
         }
-        Commands::SetCallback(args) => handle_set_callback(args, &config_path, &mut cfg).await?,
         Commands::ChangeMacToIpad(args) => {
-            handle_change_mac_to_ipad(args, &config_path, &mut cfg).await?
-        }
-        Commands::CheckOnline(args) => handle_check_online(args, &config_path, &mut cfg).await?,
-        Commands::Reconnection(args) => handle_reconnection(args, &config_path, &mut cfg).await?,
-        Commands::Logout(args) => handle_logout(args, &config_path, &mut cfg).await?,
-        Commands::AddLabel(args) => handle_add_label(args, &config_path, &mut cfg).await?,
-        Commands::DeleteLabel(args) => handle_delete_label(args, &config_path, &mut cfg).await?,
-        Commands::ListLabels(args) => handle_list_labels(args, &config_path, &mut cfg).await?,
+            handle_change_mac_to_ipad(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::CheckOnline(args) => {
+            handle_check_online(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::Reconnection(args) => {
+            handle_reconnection(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::Logout(args) => handle_logout(args, &config_path, &mut cfg, output).await?,
+        Commands::AddLabel(args) => handle_add_label(args, &config_path, &mut cfg, output).await?,
+        Commands::DeleteLabel(args) => {
+            handle_delete_label(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::ListLabels(args) => {
+            handle_list_labels(args, &config_path, &mut cfg, output).await?
+        }
         Commands::ModifyLabelMembers(args) => {
-            handle_modify_label_members(args, &config_path, &mut cfg).await?
+            handle_modify_label_members(args, &config_path, &mut cfg, output).await?
         }
         Commands::SyncFavorites(args) => {
-            handle_sync_favorites(args, &config_path, &mut cfg).await?
+            handle_sync_favorites(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetFavoriteContent(args) => {
-            handle_get_favorite_content(args, &config_path, &mut cfg).await?
+            handle_get_favorite_content(args, &config_path, &mut cfg, output).await?
         }
         Commands::DeleteFavorite(args) => {
-            handle_delete_favorite(args, &config_path, &mut cfg).await?
+            handle_delete_favorite(args, &config_path, &mut cfg, output).await?
         }
         Commands::VideoAccount { command } => {
-            handle_video_account_command(command, &config_path, &mut cfg).await?
+            handle_video_account_command(command, &config_path, &mut cfg, output).await?
         }
         Commands::CreateChatroom(args) => {
-            handle_create_chatroom(args, &config_path, &mut cfg).await?
+            handle_create_chatroom(args, &config_path, &mut cfg, output).await?
         }
         Commands::DisbandChatroom(args) => {
-            handle_disband_chatroom(args, &config_path, &mut cfg).await?
+            handle_disband_chatroom(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::QuitChatroom(args) => {
+            handle_quit_chatroom(args, &config_path, &mut cfg, output).await?
         }
-        Commands::QuitChatroom(args) => handle_quit_chatroom(args, &config_path, &mut cfg).await?,
         Commands::ModifyChatroomName(args) => {
-            handle_modify_chatroom_name(args, &config_path, &mut cfg).await?
+            handle_modify_chatroom_name(args, &config_path, &mut cfg, output).await?
         }
         Commands::ModifyChatroomRemark(args) => {
-            handle_modify_chatroom_remark(args, &config_path, &mut cfg).await?
+            handle_modify_chatroom_remark(args, &config_path, &mut cfg, output).await?
         }
         Commands::ModifyChatroomNickNameForSelf(args) => {
-            handle_modify_chatroom_nick_name_for_self(args, &config_path, &mut cfg).await?
+            handle_modify_chatroom_nick_name_for_self(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::InviteMember(args) => {
+            handle_invite_member(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::RemoveMember(args) => {
+            handle_remove_member(args, &config_path, &mut cfg, output).await?
         }
-        Commands::InviteMember(args) => handle_invite_member(args, &config_path, &mut cfg).await?,
-        Commands::RemoveMember(args) => handle_remove_member(args, &config_path, &mut cfg).await?,
         Commands::JoinRoomUsingQrCode(args) => {
-            handle_join_room_using_qr_code(args, &config_path, &mut cfg).await?
+            handle_join_room_using_qr_code(args, &config_path, &mut cfg, output).await?
         }
         Commands::AgreeJoinRoom(args) => {
-            handle_agree_join_room(args, &config_path, &mut cfg).await?
+            handle_agree_join_room(args, &config_path, &mut cfg, output).await?
         }
         Commands::RoomAccessApplyCheckApprove(args) => {
-            handle_room_access_apply_check_approve(args, &config_path, &mut cfg).await?
+            handle_room_access_apply_check_approve(args, &config_path, &mut cfg, output).await?
         }
         Commands::InviteAddEnterRoom(args) => {
-            handle_invite_add_enter_room(args, &config_path, &mut cfg).await?
+            handle_invite_add_enter_room(args, &config_path, &mut cfg, output).await?
         }
         Commands::AddGroupMemberAsFriend(args) => {
-            handle_add_group_member_as_friend(args, &config_path, &mut cfg).await?
+            handle_add_group_member_as_friend(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetChatroomMemberList(args) => {
-            handle_get_chatroom_member_list(args, &config_path, &mut cfg).await?
+            handle_get_chatroom_member_list(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetChatroomMemberDetail(args) => {
-            handle_get_chatroom_member_detail(args, &config_path, &mut cfg).await?
+            handle_get_chatroom_member_detail(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetChatroomInfo(args) => {
-            handle_get_chatroom_info(args, &config_path, &mut cfg).await?
+            handle_get_chatroom_info(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetChatroomAnnouncement(args) => {
-            handle_get_chatroom_announcement(args, &config_path, &mut cfg).await?
+            handle_get_chatroom_announcement(args, &config_path, &mut cfg, output).await?
         }
         Commands::SetChatroomAnnouncement(args) => {
-            handle_set_chatroom_announcement(args, &config_path, &mut cfg).await?
+            handle_set_chatroom_announcement(args, &config_path, &mut cfg, output).await?
         }
         Commands::GetChatroomQrCode(args) => {
-            handle_get_chatroom_qr_code(args, &config_path, &mut cfg).await?
+            handle_get_chatroom_qr_code(args, &config_path, &mut cfg, output).await?
         }
         Commands::SaveContractList(args) => {
-            handle_save_contract_list(args, &config_path, &mut cfg).await?
+            handle_save_contract_list(args, &config_path, &mut cfg, output).await?
         }
-        Commands::PinChat(args) => handle_pin_chat(args, &config_path, &mut cfg).await?,
+        Commands::PinChat(args) => handle_pin_chat(args, &config_path, &mut cfg, output).await?,
         Commands::SetMsgSilence(args) => {
-            handle_set_msg_silence(args, &config_path, &mut cfg).await?
+            handle_set_msg_silence(args, &config_path, &mut cfg, output).await?
+        }
+        Commands::AdminOperate(args) => {
+            handle_admin_operate(args, &config_path, &mut cfg, output).await?
         }
-        Commands::AdminOperate(args) => handle_admin_operate(args, &config_path, &mut cfg).await?,
         Commands::Config(args) => config::handle_config(args, &config_path, &mut cfg)?,
+        #[cfg(feature = "webhook")]
         Commands::ServeWebhook(args) => {
             webhook::handle_serve_webhook(args, &config_path, &cfg).await?
         }
+        #[cfg(feature = "webhook")]
         Commands::WaitReply(args) => {
             wait_reply::handle_wait_reply(args, &config_path, &cfg).await?;
         }
+        #[cfg(feature = "bot-app")]
+        Commands::RuleTemplate { command } => {
+            rule_template::handle_rule_template_command(command).await?
+        }
+        #[cfg(feature = "bot-app")]
+        Commands::Tools { command } => tools::handle_tools_command(command).await?,
+        #[cfg(feature = "bot-app")]
+        Commands::Service { command } => service::handle_service_command(command)?,
+        #[cfg(feature = "bot-app")]
+        Commands::Tail(args) => tail::handle_tail(args, &cfg).await?,
+        #[cfg(feature = "bot-app")]
+        Commands::Search(args) => search::handle_search(args, &cfg, output).await?,
+        #[cfg(feature = "send-queue")]
+        Commands::ScheduleSend { command } => {
+            schedule_send::handle_schedule_send_command(command, &config_path, &cfg, output).await?
+        }
     }
     Ok(())
 }
@@ -550,6 +698,7 @@ fn get_log_filter(verbose: u8) -> &'static str {
 /// 根据命令类型决定日志级别
 /// wait-reply 命令在 text 输出模式下自动静默（只显示 warn/error）
 fn get_log_filter_for_command(cli: &Cli) -> &'static str {
+    #[cfg(feature = "webhook")]
     if let Commands::WaitReply(ref args) = cli.command {
         if args.output_format == wait_reply::OutputFormat::Text {
             return "warn";
@@ -1,4 +1,5 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_http::GeweHttpClient;
@@ -117,6 +118,7 @@ pub async fn handle_download_image(
     args: DownloadImageArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DownloadImageArgs {
         token,
@@ -136,7 +138,7 @@ pub async fn handle_download_image(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.download_image(&app_id, &xml, image_type).await?;
     info!(?resp, "image downloaded");
-    println!("{}", resp.file_url);
+    output.print_text(&resp, &resp.file_url)?;
     Ok(())
 }
 
@@ -144,6 +146,7 @@ pub async fn handle_download_video(
     args: DownloadVideoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DownloadVideoArgs {
         token,
@@ -162,7 +165,7 @@ pub async fn handle_download_video(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.download_video(&app_id, &xml).await?;
     info!(?resp, "video downloaded");
-    println!("{}", resp.file_url);
+    output.print_text(&resp, &resp.file_url)?;
     Ok(())
 }
 
@@ -170,6 +173,7 @@ pub async fn handle_download_file(
     args: DownloadFileArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DownloadFileArgs {
         token,
@@ -188,7 +192,7 @@ pub async fn handle_download_file(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.download_file(&app_id, &xml).await?;
     info!(?resp, "file downloaded");
-    println!("{}", resp.file_url);
+    output.print_text(&resp, &resp.file_url)?;
     Ok(())
 }
 
@@ -196,6 +200,7 @@ pub async fn handle_download_voice(
     args: DownloadVoiceArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DownloadVoiceArgs {
         token,
@@ -215,7 +220,7 @@ pub async fn handle_download_voice(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.download_voice(&app_id, &xml, msg_id).await?;
     info!(?resp, "voice downloaded");
-    println!("{}", resp.file_url);
+    output.print_text(&resp, &resp.file_url)?;
     Ok(())
 }
 
@@ -223,6 +228,7 @@ pub async fn handle_download_emoji(
     args: DownloadEmojiArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DownloadEmojiArgs {
         token,
@@ -241,7 +247,7 @@ pub async fn handle_download_emoji(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.download_emoji(&app_id, &emoji_md5).await?;
     info!(?resp, "emoji downloaded");
-    println!("{}", resp.url);
+    output.print_text(&resp, &resp.url)?;
     Ok(())
 }
 
@@ -249,6 +255,7 @@ pub async fn handle_download_cdn(
     args: DownloadCdnArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DownloadCdnArgs {
         token,
@@ -280,7 +287,7 @@ pub async fn handle_download_cdn(
         )
         .await?;
     info!(?resp, "cdn downloaded");
-    println!("{}", resp.file_url);
+    output.print_text(&resp, &resp.file_url)?;
     Ok(())
 }
 
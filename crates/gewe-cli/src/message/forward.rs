@@ -1,4 +1,5 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_http::GeweHttpClient;
@@ -101,6 +102,7 @@ pub async fn handle_forward_image(
     args: ForwardImageArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ForwardImageArgs {
         token,
@@ -120,6 +122,7 @@ pub async fn handle_forward_image(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.forward_image(&app_id, &to_wxid, &xml).await?;
     info!(?resp, "image forwarded");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -127,6 +130,7 @@ pub async fn handle_forward_video(
     args: ForwardVideoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ForwardVideoArgs {
         token,
@@ -146,6 +150,7 @@ pub async fn handle_forward_video(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.forward_video(&app_id, &to_wxid, &xml).await?;
     info!(?resp, "video forwarded");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -153,6 +158,7 @@ pub async fn handle_forward_file(
     args: ForwardFileArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ForwardFileArgs {
         token,
@@ -172,6 +178,7 @@ pub async fn handle_forward_file(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.forward_file(&app_id, &to_wxid, &xml).await?;
     info!(?resp, "file forwarded");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -179,6 +186,7 @@ pub async fn handle_forward_mini_app(
     args: ForwardMiniAppArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ForwardMiniAppArgs {
         token,
@@ -201,6 +209,7 @@ pub async fn handle_forward_mini_app(
         .forward_mini_app(&app_id, &to_wxid, &xml, &cover_img_url)
         .await?;
     info!(?resp, "mini app forwarded");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -208,6 +217,7 @@ pub async fn handle_forward_url(
     args: ForwardUrlArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ForwardUrlArgs {
         token,
@@ -227,6 +237,7 @@ pub async fn handle_forward_url(
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.forward_url(&app_id, &to_wxid, &xml).await?;
     info!(?resp, "url forwarded");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1,4 +1,5 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_http::GeweHttpClient;
@@ -31,6 +32,7 @@ pub async fn handle_revoke_msg(
     args: RevokeMsgArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let RevokeMsgArgs {
         token,
@@ -54,6 +56,7 @@ pub async fn handle_revoke_msg(
         .revoke_message(&app_id, &to_wxid, &msg_id, &new_msg_id, &create_time)
         .await?;
     info!(%msg_id, %new_msg_id, "message revoked");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1,8 +1,10 @@
-use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::config::{default_base_url, lookup_bot, resolve_chatroom, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
-use gewe_http::GeweHttpClient;
-use std::path::Path;
+use gewe_http::{BatchReport, GeweHttpClient};
+use serde_json::json;
+use std::path::{Path, PathBuf};
 use tracing::info;
 
 #[derive(Args)]
@@ -15,8 +17,11 @@ pub struct SendTextArgs {
     pub bot_app_id: Option<String>,
     #[arg(long)]
     pub bot_alias: Option<String>,
-    #[arg(long)]
-    pub to_wxid: String,
+    #[arg(long, required_unless_present = "to_file")]
+    pub to_wxid: Option<String>,
+    /// 群发：从文件读取接收者，每行一个 wxid，忽略空行与 # 开头的注释
+    #[arg(long, conflicts_with_all = ["to_wxid", "ats"])]
+    pub to_file: Option<PathBuf>,
     #[arg(long)]
     pub content: String,
     #[arg(long)]
@@ -219,6 +224,7 @@ pub async fn handle_send_text(
     args: SendTextArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendTextArgs {
         token,
@@ -226,6 +232,7 @@ pub async fn handle_send_text(
         bot_app_id,
         bot_alias,
         to_wxid,
+        to_file,
         content,
         ats,
         base_url,
@@ -237,10 +244,24 @@ pub async fn handle_send_text(
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
     let client = GeweHttpClient::new(token, base_url)?;
+    if let Some(path) = to_file {
+        let recipients = read_recipients(&path)?;
+        let recipients: Vec<&str> = recipients.iter().map(String::as_str).collect();
+        let report = client.send_text_batch(&app_id, &recipients, &content).await;
+        info!(
+            succeeded = report.success_count(),
+            failed = report.failure_count(),
+            "batch text sent"
+        );
+        return print_batch_report(&report, output);
+    }
+    let to_wxid = to_wxid.ok_or_else(|| anyhow!("缺少 --to-wxid"))?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let resp = client
         .send_text(&app_id, &to_wxid, &content, ats.as_deref())
         .await?;
     info!(?resp, "text sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -248,6 +269,7 @@ pub async fn handle_send_image(
     args: SendImageArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendImageArgs {
         token,
@@ -264,9 +286,11 @@ pub async fn handle_send_image(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.send_image(&app_id, &to_wxid, &img_url).await?;
     info!(?resp, "image sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -274,6 +298,7 @@ pub async fn handle_send_voice(
     args: SendVoiceArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendVoiceArgs {
         token,
@@ -291,11 +316,13 @@ pub async fn handle_send_voice(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .send_voice(&app_id, &to_wxid, &voice_url, voice_duration)
         .await?;
     info!(?resp, "voice sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -303,6 +330,7 @@ pub async fn handle_send_video(
     args: SendVideoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendVideoArgs {
         token,
@@ -321,11 +349,13 @@ pub async fn handle_send_video(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .send_video(&app_id, &to_wxid, &video_url, &thumb_url, video_duration)
         .await?;
     info!(?resp, "video sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -333,6 +363,7 @@ pub async fn handle_send_file(
     args: SendFileArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendFileArgs {
         token,
@@ -350,11 +381,13 @@ pub async fn handle_send_file(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .send_file(&app_id, &to_wxid, &file_url, &file_name)
         .await?;
     info!(?resp, "file sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -362,6 +395,7 @@ pub async fn handle_send_link(
     args: SendLinkArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendLinkArgs {
         token,
@@ -381,11 +415,13 @@ pub async fn handle_send_link(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .send_link(&app_id, &to_wxid, &title, &desc, &link_url, &thumb_url)
         .await?;
     info!(?resp, "link sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -393,6 +429,7 @@ pub async fn handle_send_emoji(
     args: SendEmojiArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendEmojiArgs {
         token,
@@ -410,11 +447,13 @@ pub async fn handle_send_emoji(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .send_emoji(&app_id, &to_wxid, &emoji_md5, emoji_size)
         .await?;
     info!(?resp, "emoji sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -422,6 +461,7 @@ pub async fn handle_send_appmsg(
     args: SendAppmsgArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendAppmsgArgs {
         token,
@@ -438,9 +478,11 @@ pub async fn handle_send_appmsg(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client.send_app_msg(&app_id, &to_wxid, &appmsg).await?;
     info!(?resp, "appmsg sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -448,6 +490,7 @@ pub async fn handle_send_mini_app(
     args: SendMiniAppArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendMiniAppArgs {
         token,
@@ -469,6 +512,7 @@ pub async fn handle_send_mini_app(
         .unwrap_or_else(default_base_url);
     let effective_app_id = resolve_bot(bot_alias, bot_app_id.or(app_id), config)?;
     let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &to_wxid);
     let client = GeweHttpClient::new(token, base_url)?;
     let resp = client
         .send_mini_app(
@@ -483,6 +527,7 @@ pub async fn handle_send_mini_app(
         )
         .await?;
     info!(?resp, "mini app sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -490,6 +535,7 @@ pub async fn handle_send_name_card(
     args: SendNameCardArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendNameCardArgs {
         token,
@@ -512,6 +558,7 @@ pub async fn handle_send_name_card(
         .send_name_card(&app_id, &to_wxid, &nick_name, &name_card_wxid)
         .await?;
     info!(?resp, "name card sent");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -529,6 +576,54 @@ fn resolve_bot(
     }
 }
 
+/// 读取接收者列表：每行一个 wxid，忽略空行与 # 注释，去重并保持顺序
+fn read_recipients(path: &Path) -> Result<Vec<String>> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("读取接收者文件 {} 失败: {e}", path.display()))?;
+    let mut recipients: Vec<String> = Vec::new();
+    for line in raw.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || recipients.iter().any(|r| r == line) {
+            continue;
+        }
+        recipients.push(line.to_string());
+    }
+    if recipients.is_empty() {
+        return Err(anyhow!("接收者文件 {} 为空", path.display()));
+    }
+    Ok(recipients)
+}
+
+/// 逐个输出群发结果，有失败时返回错误
+fn print_batch_report<T: serde::Serialize>(
+    report: &BatchReport<T>,
+    output: OutputFormat,
+) -> Result<()> {
+    let rows: Vec<_> = report
+        .results
+        .iter()
+        .map(|r| {
+            let mut row = json!({"toWxid": r.to_wxid, "ok": r.result.is_ok()});
+            match &r.result {
+                // 表格只列出接收者与结果，完整响应见 json/yaml
+                Ok(resp) if output != OutputFormat::Table => row["response"] = json!(resp),
+                Err(err) => row["error"] = json!(err.to_string()),
+                Ok(_) => {}
+            }
+            row
+        })
+        .collect();
+    output.print(&rows)?;
+    if report.is_all_ok() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} 个接收者发送失败（共 {} 个）",
+            report.failure_count(),
+            report.results.len()
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -541,13 +636,14 @@ mod tests {
             app_id: Some("test_app_id".to_string()),
             bot_app_id: None,
             bot_alias: None,
-            to_wxid: "wxid123".to_string(),
+            to_wxid: Some("wxid123".to_string()),
+            to_file: None,
             content: "Hello".to_string(),
             ats: None,
             base_url: None,
         };
 
-        assert_eq!(args.to_wxid, "wxid123");
+        assert_eq!(args.to_wxid.as_deref(), Some("wxid123"));
         assert_eq!(args.content, "Hello");
         assert_eq!(args.token, Some("test_token".to_string()));
         assert_eq!(args.app_id, Some("test_app_id".to_string()));
@@ -560,7 +656,8 @@ mod tests {
             app_id: None,
             bot_app_id: None,
             bot_alias: None,
-            to_wxid: "chatroom@123".to_string(),
+            to_wxid: Some("chatroom@123".to_string()),
+            to_file: None,
             content: "@user test".to_string(),
             ats: Some("wxid_user1,wxid_user2".to_string()),
             base_url: None,
@@ -569,6 +666,23 @@ mod tests {
         assert_eq!(args.ats, Some("wxid_user1,wxid_user2".to_string()));
     }
 
+    #[test]
+    fn test_read_recipients() {
+        let path = std::env::temp_dir().join(format!("gewe-recipients-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# 通知名单\nwxid_a\n\n  wxid_b  \nwxid_a\n123@chatroom\n",
+        )
+        .unwrap();
+        let recipients = read_recipients(&path).unwrap();
+        assert_eq!(recipients, ["wxid_a", "wxid_b", "123@chatroom"]);
+
+        std::fs::write(&path, "# 空\n\n").unwrap();
+        assert!(read_recipients(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_recipients(&path).is_err());
+    }
+
     #[test]
     fn test_send_image_args_structure() {
         let args = SendImageArgs {
@@ -800,7 +914,8 @@ mod tests {
             app_id: None,
             bot_app_id: None,
             bot_alias: Some("my_bot".to_string()),
-            to_wxid: "wxid123".to_string(),
+            to_wxid: Some("wxid123".to_string()),
+            to_file: None,
             content: "Hello".to_string(),
             ats: None,
             base_url: None,
@@ -816,7 +931,8 @@ mod tests {
             app_id: None,
             bot_app_id: Some("app789".to_string()),
             bot_alias: None,
-            to_wxid: "wxid123".to_string(),
+            to_wxid: Some("wxid123".to_string()),
+            to_file: None,
             content: "Hello".to_string(),
             ats: None,
             base_url: None,
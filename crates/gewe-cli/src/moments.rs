@@ -1,4 +1,5 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_core::{
@@ -359,6 +360,7 @@ pub async fn handle_send_moment_text(
     args: SendMomentTextArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendMomentTextArgs {
         token,
@@ -377,7 +379,7 @@ pub async fn handle_send_moment_text(
     };
     let resp = client.send_text_sns(req).await?;
     info!(id = resp.id, "moment text sent");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -385,6 +387,7 @@ pub async fn handle_send_moment_image(
     args: SendMomentImageArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendMomentImageArgs {
         token,
@@ -409,7 +412,7 @@ pub async fn handle_send_moment_image(
     };
     let resp = client.send_img_sns(req).await?;
     info!(id = resp.id, "moment image sent");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -417,6 +420,7 @@ pub async fn handle_send_moment_video(
     args: SendMomentVideoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendMomentVideoArgs {
         token,
@@ -446,7 +450,7 @@ pub async fn handle_send_moment_video(
     };
     let resp = client.send_video_sns(req).await?;
     info!(id = resp.id, "moment video sent");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -454,6 +458,7 @@ pub async fn handle_send_moment_link(
     args: SendMomentLinkArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SendMomentLinkArgs {
         token,
@@ -480,7 +485,7 @@ pub async fn handle_send_moment_link(
     };
     let resp = client.send_url_sns(req).await?;
     info!(id = resp.id, "moment link sent");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -488,6 +493,7 @@ pub async fn handle_forward_moment(
     args: ForwardMomentArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let ForwardMomentArgs {
         token,
@@ -506,7 +512,7 @@ pub async fn handle_forward_moment(
     };
     let resp = client.forward_sns(req).await?;
     info!(id = resp.id, "moment forwarded");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -514,6 +520,7 @@ pub async fn handle_upload_moment_image(
     args: UploadMomentImageArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let UploadMomentImageArgs {
         token,
@@ -534,7 +541,7 @@ pub async fn handle_upload_moment_image(
             img_urls: img_refs,
         })
         .await?;
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -542,6 +549,7 @@ pub async fn handle_upload_moment_video(
     args: UploadMomentVideoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let UploadMomentVideoArgs {
         token,
@@ -560,7 +568,7 @@ pub async fn handle_upload_moment_video(
             video_url: &video_url,
         })
         .await?;
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -568,6 +576,7 @@ pub async fn handle_download_moment_video(
     args: DownloadMomentVideoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DownloadMomentVideoArgs {
         token,
@@ -584,7 +593,7 @@ pub async fn handle_download_moment_video(
             sns_xml: &sns_xml,
         })
         .await?;
-    println!("{}", resp.file_url);
+    output.print_text(&resp, &resp.file_url)?;
     Ok(())
 }
 
@@ -592,6 +601,7 @@ pub async fn handle_delete_moment(
     args: DeleteMomentArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let DeleteMomentArgs {
         token,
@@ -609,6 +619,7 @@ pub async fn handle_delete_moment(
         })
         .await?;
     info!(sns_id, "moment deleted");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -616,6 +627,7 @@ pub async fn handle_set_stranger_visibility(
     args: SetStrangerVisibilityArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetStrangerVisibilityArgs {
         token,
@@ -633,6 +645,7 @@ pub async fn handle_set_stranger_visibility(
         })
         .await?;
     info!(enabled, "stranger visibility updated");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -640,6 +653,7 @@ pub async fn handle_get_moment_detail(
     args: GetMomentDetailArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetMomentDetailArgs {
         token,
@@ -656,7 +670,7 @@ pub async fn handle_get_moment_detail(
             sns_id,
         })
         .await?;
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -664,6 +678,7 @@ pub async fn handle_like_moment(
     args: LikeMomentArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let LikeMomentArgs {
         token,
@@ -685,6 +700,7 @@ pub async fn handle_like_moment(
         })
         .await?;
     info!(sns_id, oper_type, "like action sent");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -692,6 +708,7 @@ pub async fn handle_comment_moment(
     args: CommentMomentArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let CommentMomentArgs {
         token,
@@ -717,6 +734,7 @@ pub async fn handle_comment_moment(
         })
         .await?;
     info!(sns_id, oper_type, "comment action sent");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -724,6 +742,7 @@ pub async fn handle_get_contact_moments(
     args: GetContactMomentsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetContactMomentsArgs {
         token,
@@ -746,7 +765,7 @@ pub async fn handle_get_contact_moments(
             first_page_md5: first_page_md5.as_deref(),
         })
         .await?;
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -754,6 +773,7 @@ pub async fn handle_get_self_moments(
     args: GetSelfMomentsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetSelfMomentsArgs {
         token,
@@ -774,7 +794,7 @@ pub async fn handle_get_self_moments(
             first_page_md5: first_page_md5.as_deref(),
         })
         .await?;
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -782,6 +802,7 @@ pub async fn handle_set_moment_visible_scope(
     args: SetMomentVisibleScopeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetMomentVisibleScopeArgs {
         token,
@@ -799,6 +820,7 @@ pub async fn handle_set_moment_visible_scope(
         })
         .await?;
     info!(option, "moment visible scope updated");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -806,6 +828,7 @@ pub async fn handle_set_moment_privacy(
     args: SetMomentPrivacyArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let SetMomentPrivacyArgs {
         token,
@@ -825,6 +848,7 @@ pub async fn handle_set_moment_privacy(
         })
         .await?;
     info!(sns_id, open, "moment privacy updated");
+    output.print_ok()?;
     Ok(())
 }
 
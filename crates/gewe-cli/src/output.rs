@@ -0,0 +1,239 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+/// 命令结果的输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// 人类可读的表格（默认）
+    #[default]
+    Table,
+    /// 格式化 JSON
+    Json,
+    /// YAML
+    Yaml,
+}
+
+impl OutputFormat {
+    /// 输出响应
+    pub fn print<T: Serialize>(self, value: &T) -> Result<()> {
+        println!("{}", self.render(value)?);
+        Ok(())
+    }
+
+    /// 表格模式只输出 `text`（如二维码、下载地址），JSON/YAML 输出完整响应
+    pub fn print_text<T: Serialize>(self, value: &T, text: &str) -> Result<()> {
+        match self {
+            OutputFormat::Table => {
+                println!("{}", text);
+                Ok(())
+            }
+            _ => self.print(value),
+        }
+    }
+
+    /// 没有响应体的命令：JSON/YAML 输出 `{"ok": true}`，表格模式不输出
+    pub fn print_ok(self) -> Result<()> {
+        match self {
+            OutputFormat::Table => Ok(()),
+            _ => self.print(&serde_json::json!({ "ok": true })),
+        }
+    }
+
+    pub fn render<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            OutputFormat::Json => serde_json::to_string_pretty(value)?,
+            OutputFormat::Yaml => serde_yaml::to_string(value)?.trim_end().to_string(),
+            OutputFormat::Table => render_table(&serde_json::to_value(value)?),
+        })
+    }
+}
+
+/// 对象输出为「字段 值」两列，对象数组输出为表格，嵌套结构按字段分段
+fn render_table(value: &Value) -> String {
+    let mut out = Vec::new();
+    push_value(&mut out, value);
+    out.join("\n")
+}
+
+fn push_value(out: &mut Vec<String>, value: &Value) {
+    match value {
+        Value::Object(map) => {
+            let (simple, nested): (Vec<_>, Vec<_>) = map.iter().partition(|(_, v)| is_simple(v));
+            let rows: Vec<Vec<String>> = simple
+                .iter()
+                .map(|(k, v)| vec![k.to_string(), cell(v)])
+                .collect();
+            push_grid(out, None, &rows);
+            for (key, v) in nested {
+                if !out.is_empty() {
+                    out.push(String::new());
+                }
+                out.push(format!("{}:", key));
+                push_value(out, v);
+            }
+        }
+        Value::Array(items) if items.iter().all(Value::is_object) && !items.is_empty() => {
+            let mut columns: Vec<&str> = Vec::new();
+            for item in items {
+                for key in item.as_object().into_iter().flat_map(|m| m.keys()) {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+            let rows: Vec<Vec<String>> = items
+                .iter()
+                .map(|item| {
+                    columns
+                        .iter()
+                        .map(|c| item.get(*c).map(cell).unwrap_or_default())
+                        .collect()
+                })
+                .collect();
+            push_grid(out, Some(&columns), &rows);
+        }
+        Value::Array(items) if items.is_empty() => out.push("(空)".to_string()),
+        Value::Array(items) => out.extend(items.iter().map(cell)),
+        other => out.push(cell(other)),
+    }
+}
+
+/// 标量与标量数组可以放进单元格
+fn is_simple(value: &Value) -> bool {
+    match value {
+        Value::Object(_) => false,
+        Value::Array(items) => items.iter().all(|v| !v.is_object() && !v.is_array()),
+        _ => true,
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.replace('\n', "\\n"),
+        Value::Array(items) if items.iter().all(|v| !v.is_object() && !v.is_array()) => {
+            items.iter().map(cell).collect::<Vec<_>>().join(", ")
+        }
+        other => other.to_string(),
+    }
+}
+
+fn push_grid(out: &mut Vec<String>, header: Option<&[&str]>, rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header
+        .map(|h| h.iter().map(|c| display_width(c)).collect())
+        .unwrap_or_else(|| vec![0; rows.first().map_or(0, Vec::len)]);
+    for row in rows {
+        for (w, c) in widths.iter_mut().zip(row) {
+            *w = (*w).max(display_width(c));
+        }
+    }
+    if let Some(header) = header {
+        out.push(format_row(header, &widths));
+        let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        out.push(format_row(&rule, &widths));
+    }
+    for row in rows {
+        out.push(format_row(row, &widths));
+    }
+}
+
+fn format_row<S: AsRef<str>>(cells: &[S], widths: &[usize]) -> String {
+    let padded: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(c, w)| {
+            let c = c.as_ref();
+            format!("{}{}", c, " ".repeat(w - display_width(c)))
+        })
+        .collect();
+    padded.join("  ").trim_end().to_string()
+}
+
+/// 终端显示宽度：中日韩字符与全角符号按 2 计
+fn display_width(s: &str) -> usize {
+    s.chars()
+        .map(|c| match c as u32 {
+            0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA000..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6
+            | 0x1F300..=0x1F64F
+            | 0x1F900..=0x1F9FF
+            | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_json_and_yaml() {
+        let value = json!({"msgId": 1, "toWxid": "wxid_a"});
+        assert_eq!(
+            OutputFormat::Json.render(&value).unwrap(),
+            "{\n  \"msgId\": 1,\n  \"toWxid\": \"wxid_a\"\n}"
+        );
+        assert_eq!(
+            OutputFormat::Yaml.render(&value).unwrap(),
+            "msgId: 1\ntoWxid: wxid_a"
+        );
+    }
+
+    #[test]
+    fn test_render_table_object() {
+        // Keys are listed alphabetically so the expectation holds with or without
+        // serde_json's preserve_order feature.
+        let value = json!({
+            "alias": null,
+            "friends": [
+                {"remark": "同事", "userName": "wxid_a"},
+                {"sex": 1, "userName": "wxid_longer"}
+            ],
+            "labels": ["1", "2"],
+            "nickName": "小明"
+        });
+        let table = OutputFormat::Table.render(&value).unwrap();
+        let expected = [
+            "alias",
+            "labels    1, 2",
+            "nickName  小明",
+            "",
+            "friends:",
+            "remark  userName     sex",
+            "------  -----------  ---",
+            "同事    wxid_a",
+            "        wxid_longer  1",
+        ];
+        assert_eq!(table, expected.join("\n"));
+    }
+
+    #[test]
+    fn test_render_table_scalars() {
+        assert_eq!(OutputFormat::Table.render(&json!([])).unwrap(), "(空)");
+        assert_eq!(
+            OutputFormat::Table.render(&json!(["a", 1])).unwrap(),
+            "a\n1"
+        );
+        assert_eq!(OutputFormat::Table.render(&true).unwrap(), "true");
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("小明"), 4);
+        assert_eq!(display_width("ａ"), 2);
+    }
+}
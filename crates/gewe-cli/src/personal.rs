@@ -1,4 +1,5 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_http::GeweHttpClient;
@@ -111,6 +112,7 @@ pub async fn handle_get_profile(
     args: GetProfileArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetProfileArgs {
         token,
@@ -130,7 +132,7 @@ pub async fn handle_get_profile(
         .get_profile(gewe_core::GetProfileRequest { app_id: &app_id })
         .await?;
     info!(wxid=%resp.wxid, nick=%resp.nick_name, "profile fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -138,6 +140,7 @@ pub async fn handle_update_profile(
     args: UpdateProfileArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let UpdateProfileArgs {
         token,
@@ -180,6 +183,7 @@ pub async fn handle_update_profile(
         })
         .await?;
     info!("profile updated");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -187,6 +191,7 @@ pub async fn handle_update_head_img(
     args: UpdateHeadImgArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let UpdateHeadImgArgs {
         token,
@@ -210,6 +215,7 @@ pub async fn handle_update_head_img(
         })
         .await?;
     info!("head image update triggered");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -217,6 +223,7 @@ pub async fn handle_get_qr_code(
     args: GetQrCodeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetQrCodeArgs {
         token,
@@ -236,7 +243,7 @@ pub async fn handle_get_qr_code(
         .get_qr_code(gewe_core::GetQrCodeRequest { app_id: &app_id })
         .await?;
     info!("qr code fetched");
-    println!("{}", resp.qr_code);
+    output.print_text(&resp, &resp.qr_code)?;
     Ok(())
 }
 
@@ -244,6 +251,7 @@ pub async fn handle_privacy_settings(
     args: PrivacySettingsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let PrivacySettingsArgs {
         token,
@@ -269,6 +277,7 @@ pub async fn handle_privacy_settings(
         })
         .await?;
     info!(option, open, "privacy settings updated");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -276,6 +285,7 @@ pub async fn handle_get_safety_info(
     args: GetSafetyInfoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let GetSafetyInfoArgs {
         token,
@@ -295,7 +305,7 @@ pub async fn handle_get_safety_info(
         .get_safety_info(gewe_core::GetSafetyInfoRequest { app_id: &app_id })
         .await?;
     info!(count = resp.list.len(), "safety info fetched");
-    println!("{resp:#?}");
+    output.print(&resp)?;
     Ok(())
 }
 
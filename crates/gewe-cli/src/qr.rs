@@ -0,0 +1,88 @@
+use crate::output::OutputFormat;
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+/// 二维码的输出方式，表格模式下默认在终端直接显示二维码
+#[derive(Args, Debug, Clone, Default)]
+pub struct QrOutputArgs {
+    /// 同时把二维码图片保存为 PNG
+    #[arg(long, value_name = "PATH")]
+    pub png: Option<PathBuf>,
+    /// 表格模式输出原始 base64 图片而不在终端渲染
+    #[arg(long)]
+    pub base64: bool,
+}
+
+/// 输出二维码：`content` 为二维码内容，`image_base64` 为接口返回的图片。
+/// 表格模式在终端渲染二维码并附上内容，内容为空时退回输出 base64；JSON/YAML 输出完整响应
+pub fn print_qr<T: Serialize>(
+    output: OutputFormat,
+    args: &QrOutputArgs,
+    value: &T,
+    content: &str,
+    image_base64: &str,
+) -> Result<()> {
+    save_png(args, image_base64)?;
+    if output != OutputFormat::Table {
+        return output.print(value);
+    }
+    if args.base64 || content.is_empty() {
+        println!("{}", image_base64);
+        return Ok(());
+    }
+    let ansi = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    println!("{}", gewe_core::qr::render_terminal(content, ansi)?);
+    println!("{}", content);
+    Ok(())
+}
+
+/// 把二维码输出到标准错误，用于交互流程中标准输出留给最终结果的场景
+pub fn eprint_qr(args: &QrOutputArgs, content: &str, image_base64: &str) -> Result<()> {
+    save_png(args, image_base64)?;
+    if args.base64 || content.is_empty() {
+        eprintln!("{}", image_base64);
+        return Ok(());
+    }
+    let ansi = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    eprintln!("{}", gewe_core::qr::render_terminal(content, ansi)?);
+    eprintln!("{}", content);
+    Ok(())
+}
+
+fn save_png(args: &QrOutputArgs, image_base64: &str) -> Result<()> {
+    if let Some(path) = &args.png {
+        let bytes = gewe_core::qr::decode_image_base64(image_base64)?;
+        std::fs::write(path, bytes)
+            .with_context(|| format!("写入二维码图片失败: {}", path.display()))?;
+        eprintln!("二维码图片已保存到 {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_print_qr_writes_png() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("qr.png");
+        let args = QrOutputArgs {
+            png: Some(path.clone()),
+            base64: false,
+        };
+        let value = serde_json::json!({ "qrUrl": "" });
+        print_qr(OutputFormat::Json, &args, &value, "", "iVBORw==").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"\x89PNG");
+
+        let args = QrOutputArgs {
+            png: Some(path),
+            base64: false,
+        };
+        assert!(print_qr(OutputFormat::Table, &args, &value, "", "!!").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! 规则模板分享命令模块
+//!
+//! 通过 gewe-bot-app 的管理 API 导出/导入自包含的 YAML 规则包。
+
+use crate::bot_app::BotAppArgs;
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Subcommand)]
+pub enum RuleTemplateCommands {
+    /// 导出规则模板为 YAML 规则包
+    Export(ExportRuleTemplateArgs),
+    /// 导入 YAML 规则包（保存为草稿，需在管理端发布）
+    Import(ImportRuleTemplateArgs),
+}
+
+#[derive(Args)]
+pub struct ExportRuleTemplateArgs {
+    #[command(flatten)]
+    pub server: BotAppArgs,
+    /// 规则模板 ID
+    pub id: String,
+    /// 输出文件，未指定时打印到标准输出
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ImportRuleTemplateArgs {
+    #[command(flatten)]
+    pub server: BotAppArgs,
+    /// 规则包文件路径
+    pub file: PathBuf,
+    /// 覆盖同名模板、AI Profile 与工具
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+pub async fn handle_rule_template_command(command: RuleTemplateCommands) -> Result<()> {
+    match command {
+        RuleTemplateCommands::Export(args) => handle_export(args).await,
+        RuleTemplateCommands::Import(args) => handle_import(args).await,
+    }
+}
+
+async fn handle_export(args: ExportRuleTemplateArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let url = args
+        .server
+        .url(&format!("/rule-templates/{}/export", args.id));
+    let resp = args.server.authorize(client.get(&url)).send().await?;
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        return Err(anyhow!("导出失败 ({}): {}", status, body));
+    }
+    match args.output {
+        Some(path) => {
+            std::fs::write(&path, body)?;
+            info!(id = %args.id, path = %path.display(), "规则包已导出");
+        }
+        None => print!("{}", body),
+    }
+    Ok(())
+}
+
+async fn handle_import(args: ImportRuleTemplateArgs) -> Result<()> {
+    let body = std::fs::read_to_string(&args.file)?;
+    let client = reqwest::Client::new();
+    let url = args.server.url("/rule-templates/import");
+    let req = client
+        .post(&url)
+        .query(&[("overwrite", args.overwrite)])
+        .header("Content-Type", "application/yaml")
+        .body(body);
+    let data = args.server.send_json(req, "导入失败").await?;
+    println!("{}", serde_json::to_string_pretty(&data)?);
+    Ok(())
+}
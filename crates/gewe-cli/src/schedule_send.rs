@@ -0,0 +1,386 @@
+//! schedule-send 命令模块
+//!
+//! 把消息加入本地持久化发送队列（可定时），并提供查看、取消与发送队列的子命令。
+//! 队列文件默认位于配置文件同目录的 `send_queue.db`。
+
+use crate::config::{default_base_url, lookup_bot, resolve_chatroom, resolve_value, CliConfig};
+use crate::output::OutputFormat;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use clap::{ArgGroup, Args, Subcommand, ValueEnum};
+use gewe_http::GeweHttpClient;
+use gewe_queue::{QueuedMessage, SendQueue, SendStatus};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+#[derive(Subcommand)]
+pub enum ScheduleSendCommands {
+    /// 加入发送队列，可用 --at / --in 指定发送时间
+    Add(Box<AddArgs>),
+    /// 列出队列中的消息
+    List(ListArgs),
+    /// 取消尚未发送的消息
+    Cancel(CancelArgs),
+    /// 发送到期的消息；不加 --once 时持续轮询
+    Run(RunArgs),
+}
+
+#[derive(Args)]
+pub struct QueueArgs {
+    /// 队列文件路径（默认为配置目录下的 send_queue.db）
+    #[arg(long)]
+    pub queue: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("content").required(true).args(["text", "image_url", "file_url", "link_url"])))]
+#[command(group(ArgGroup::new("when").args(["at", "in"])))]
+pub struct AddArgs {
+    #[command(flatten)]
+    pub queue: QueueArgs,
+    #[arg(long)]
+    pub app_id: Option<String>,
+    #[arg(long)]
+    pub bot_app_id: Option<String>,
+    #[arg(long)]
+    pub bot_alias: Option<String>,
+    /// 接收者 wxid 或群 ID（群可填写别名）
+    #[arg(long)]
+    pub to_wxid: String,
+    /// 文本消息内容
+    #[arg(long)]
+    pub text: Option<String>,
+    /// 文本消息中 @ 的成员，逗号分隔
+    #[arg(long, requires = "text")]
+    pub ats: Option<String>,
+    /// 图片地址
+    #[arg(long)]
+    pub image_url: Option<String>,
+    /// 文件地址
+    #[arg(long, requires = "file_name")]
+    pub file_url: Option<String>,
+    #[arg(long)]
+    pub file_name: Option<String>,
+    /// 链接地址
+    #[arg(long, requires = "title")]
+    pub link_url: Option<String>,
+    /// 链接标题
+    #[arg(long)]
+    pub title: Option<String>,
+    /// 链接描述
+    #[arg(long, default_value = "")]
+    pub desc: String,
+    /// 链接缩略图地址
+    #[arg(long, default_value = "")]
+    pub thumb_url: String,
+    /// 发送时间：RFC 3339（如 2026-01-01T09:00:00+08:00）或本地时间 `YYYY-MM-DD HH:MM`
+    #[arg(long)]
+    pub at: Option<String>,
+    /// 相对当前的延迟，如 90s、30m、2h、1d
+    #[arg(long = "in", value_name = "DELAY")]
+    pub r#in: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StatusFilter {
+    Pending,
+    Sent,
+    Failed,
+    Cancelled,
+    All,
+}
+
+#[derive(Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub queue: QueueArgs,
+    #[arg(long, default_value = "pending")]
+    pub status: StatusFilter,
+    #[arg(long, default_value_t = 50)]
+    pub limit: u32,
+}
+
+#[derive(Args)]
+pub struct CancelArgs {
+    #[command(flatten)]
+    pub queue: QueueArgs,
+    /// 消息编号（见 list 输出）
+    pub id: i64,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub queue: QueueArgs,
+    #[arg(long)]
+    pub token: Option<String>,
+    #[arg(long)]
+    pub base_url: Option<String>,
+    /// 发送一轮到期消息后退出
+    #[arg(long)]
+    pub once: bool,
+    /// 轮询间隔秒数
+    #[arg(long, default_value_t = 10)]
+    pub interval: u64,
+}
+
+pub async fn handle_schedule_send_command(
+    command: ScheduleSendCommands,
+    config_path: &Path,
+    config: &CliConfig,
+    output: OutputFormat,
+) -> Result<()> {
+    match command {
+        ScheduleSendCommands::Add(args) => handle_add(*args, config_path, config, output).await,
+        ScheduleSendCommands::List(args) => handle_list(args, config_path, output).await,
+        ScheduleSendCommands::Cancel(args) => handle_cancel(args, config_path, output).await,
+        ScheduleSendCommands::Run(args) => handle_run(args, config_path, config).await,
+    }
+}
+
+async fn open_queue(args: &QueueArgs, config_path: &Path) -> Result<SendQueue> {
+    let path = match &args.queue {
+        Some(path) => path.clone(),
+        None => config_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("send_queue.db"),
+    };
+    SendQueue::open(&path)
+        .await
+        .map_err(|e| anyhow!("打开发送队列 {} 失败: {e}", path.display()))
+}
+
+async fn handle_add(
+    args: AddArgs,
+    config_path: &Path,
+    config: &CliConfig,
+    output: OutputFormat,
+) -> Result<()> {
+    let queue = open_queue(&args.queue, config_path).await?;
+    let effective_app_id = resolve_bot(
+        args.bot_alias.clone(),
+        args.bot_app_id.clone().or(args.app_id.clone()),
+        config,
+    )?;
+    let app_id = resolve_value(effective_app_id, config.app_id.clone(), "app_id")?;
+    let to_wxid = resolve_chatroom(config, &args.to_wxid);
+    let send_at = match (&args.at, &args.r#in) {
+        (Some(at), _) => Some(parse_at(at)?),
+        (None, Some(delay)) => Some(Utc::now() + chrono::Duration::from_std(parse_delay(delay)?)?),
+        (None, None) => None,
+    };
+    let message = build_message(args)?;
+    let id = queue.enqueue(&app_id, &to_wxid, &message, send_at).await?;
+    info!(id, %to_wxid, "message queued");
+    let item = queue
+        .get(id)
+        .await?
+        .ok_or_else(|| anyhow!("消息 {id} 不存在"))?;
+    output.print(&row(&item))
+}
+
+fn build_message(args: AddArgs) -> Result<QueuedMessage> {
+    Ok(if let Some(content) = args.text {
+        QueuedMessage::Text {
+            content,
+            ats: args.ats,
+        }
+    } else if let Some(url) = args.image_url {
+        QueuedMessage::Image { url }
+    } else if let Some(url) = args.file_url {
+        QueuedMessage::File {
+            url,
+            name: args.file_name.unwrap_or_default(),
+        }
+    } else if let Some(url) = args.link_url {
+        QueuedMessage::Link {
+            title: args.title.unwrap_or_default(),
+            desc: args.desc,
+            url,
+            thumb_url: args.thumb_url,
+        }
+    } else {
+        return Err(anyhow!(
+            "需要指定 --text、--image-url、--file-url 或 --link-url"
+        ));
+    })
+}
+
+async fn handle_list(args: ListArgs, config_path: &Path, output: OutputFormat) -> Result<()> {
+    let queue = open_queue(&args.queue, config_path).await?;
+    let status = match args.status {
+        StatusFilter::Pending => Some(SendStatus::Pending),
+        StatusFilter::Sent => Some(SendStatus::Sent),
+        StatusFilter::Failed => Some(SendStatus::Failed),
+        StatusFilter::Cancelled => Some(SendStatus::Cancelled),
+        StatusFilter::All => None,
+    };
+    let items = queue.list(status, args.limit).await?;
+    if items.is_empty() && output == OutputFormat::Table {
+        println!("队列为空");
+        return Ok(());
+    }
+    let rows: Vec<_> = items.iter().map(row).collect();
+    output.print(&rows)
+}
+
+async fn handle_cancel(args: CancelArgs, config_path: &Path, output: OutputFormat) -> Result<()> {
+    let queue = open_queue(&args.queue, config_path).await?;
+    if !queue.cancel(args.id).await? {
+        return Err(anyhow!("消息 {} 不存在或已不在等待发送", args.id));
+    }
+    info!(id = args.id, "queued message cancelled");
+    output.print_ok()
+}
+
+async fn handle_run(args: RunArgs, config_path: &Path, config: &CliConfig) -> Result<()> {
+    let queue = open_queue(&args.queue, config_path).await?;
+    let base_url = args
+        .base_url
+        .clone()
+        .or_else(|| config.base_url.clone())
+        .unwrap_or_else(default_base_url);
+    let clients = build_clients(args.token.clone(), &base_url, config)?;
+    if clients.is_empty() {
+        return Err(anyhow!("配置中没有可用的机器人，请先设置 app_id 与 token"));
+    }
+    loop {
+        let report = queue
+            .drain(Utc::now(), |app_id| clients.get(app_id))
+            .await?;
+        if report.sent + report.retried + report.failed > 0 {
+            info!(
+                sent = report.sent,
+                retried = report.retried,
+                failed = report.failed,
+                "send queue drained"
+            );
+        }
+        if args.once {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(args.interval.max(1))).await;
+    }
+}
+
+/// 为配置中的每个机器人创建客户端，机器人未单独配置 token 时使用全局 token
+fn build_clients(
+    token: Option<String>,
+    base_url: &str,
+    config: &CliConfig,
+) -> Result<HashMap<String, GeweHttpClient>> {
+    let default_token = token.or_else(|| config.token.clone());
+    let mut apps: Vec<(String, Option<String>)> = config
+        .bots
+        .iter()
+        .map(|b| (b.app_id.clone(), b.token.clone()))
+        .collect();
+    if let Some(app_id) = &config.app_id {
+        apps.push((app_id.clone(), None));
+    }
+    let mut clients = HashMap::new();
+    for (app_id, token) in apps {
+        if clients.contains_key(&app_id) {
+            continue;
+        }
+        match token.or_else(|| default_token.clone()) {
+            Some(token) => {
+                clients.insert(app_id, GeweHttpClient::new(token, base_url)?);
+            }
+            None => warn!(%app_id, "no token configured, skipping bot"),
+        }
+    }
+    Ok(clients)
+}
+
+fn row(item: &gewe_queue::QueuedSend) -> serde_json::Value {
+    json!({
+        "id": item.id,
+        "appId": item.app_id,
+        "toWxid": item.to_wxid,
+        "type": item.message.kind(),
+        "content": item.message.summary(),
+        "sendAt": item.send_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+        "status": item.status.as_str(),
+        "attempts": item.attempts,
+        "lastError": item.last_error,
+    })
+}
+
+/// 解析 RFC 3339 时间，或按本地时区解析 `YYYY-MM-DD HH:MM[:SS]`
+fn parse_at(input: &str) -> Result<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(input) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M"))
+        .map_err(|_| anyhow!("无法解析发送时间: {input}"))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|at| at.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("本地时间不存在: {input}"))
+}
+
+/// 解析 `90s`、`30m`、`2h`、`1d` 形式的延迟
+fn parse_delay(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (num, unit) = input.split_at(split);
+    let num: u64 = num.parse().map_err(|_| anyhow!("无法解析延迟: {input}"))?;
+    let secs = match unit {
+        "" | "s" => num,
+        "m" => num * 60,
+        "h" => num * 3600,
+        "d" => num * 86400,
+        _ => return Err(anyhow!("未知的时间单位: {unit}（可用 s/m/h/d）")),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+fn resolve_bot(
+    alias: Option<String>,
+    explicit: Option<String>,
+    config: &CliConfig,
+) -> Result<Option<String>> {
+    if let Some(alias) = alias {
+        Ok(Some(lookup_bot(config, &alias).ok_or_else(|| {
+            anyhow!("bot alias not found: {}", alias)
+        })?))
+    } else {
+        Ok(explicit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_delay() {
+        assert_eq!(parse_delay("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_delay("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_delay("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_delay("1d").unwrap(), Duration::from_secs(86400));
+        assert!(parse_delay("5w").is_err());
+        assert!(parse_delay("m").is_err());
+    }
+
+    #[test]
+    fn test_parse_at() {
+        let at = parse_at("2026-01-01T09:00:00+08:00").unwrap();
+        assert_eq!(at, Utc.with_ymd_and_hms(2026, 1, 1, 1, 0, 0).unwrap());
+        let local = parse_at("2026-01-01 09:00").unwrap();
+        assert_eq!(
+            local.with_timezone(&Local).format("%H:%M").to_string(),
+            "09:00"
+        );
+        assert!(parse_at("tomorrow").is_err());
+    }
+}
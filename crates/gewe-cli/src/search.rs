@@ -0,0 +1,152 @@
+//! search 命令模块
+//!
+//! 调用 gewe-bot-app 的 `/api/search` 全文搜索会话消息记录，终端中高亮显示命中词。
+
+use crate::bot_app::BotAppArgs;
+use crate::config::{lookup_bot, resolve_chatroom, CliConfig};
+use crate::output::OutputFormat;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use clap::Args;
+use serde_json::Value;
+use std::io::IsTerminal;
+
+/// 与 gewe-bot-app 摘要中的高亮标记一致
+const HIGHLIGHT_START: &str = "<mark>";
+const HIGHLIGHT_END: &str = "</mark>";
+
+#[derive(Args)]
+pub struct SearchArgs {
+    #[command(flatten)]
+    pub server: BotAppArgs,
+    /// 搜索词，多个词需同时命中
+    #[arg(required = true)]
+    pub query: Vec<String>,
+    /// 只搜索指定会话：群 ID、群名别名或私聊 wxid
+    #[arg(long, visible_alias = "chat")]
+    pub chatroom: Option<String>,
+    /// 只搜索指定机器人（app_id 或别名）的消息
+    #[arg(long)]
+    pub app_id: Option<String>,
+    /// 只搜索指定发送者的消息
+    #[arg(long)]
+    pub sender: Option<String>,
+    /// 消息类型，如 text、image、link
+    #[arg(long)]
+    pub kind: Option<String>,
+    /// 起始时间：RFC 3339 或 `YYYY-MM-DD`
+    #[arg(long)]
+    pub since: Option<String>,
+    /// 结束时间：RFC 3339 或 `YYYY-MM-DD`（含当天）
+    #[arg(long)]
+    pub until: Option<String>,
+    /// 最多返回条数
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+pub async fn handle_search(
+    args: SearchArgs,
+    config: &CliConfig,
+    output: OutputFormat,
+) -> Result<()> {
+    let mut query = vec![
+        ("q", args.query.join(" ")),
+        ("limit", args.limit.to_string()),
+    ];
+    if let Some(chat) = &args.chatroom {
+        query.push(("chat", resolve_chatroom(config, chat)));
+    }
+    if let Some(app_id) = &args.app_id {
+        query.push((
+            "app_id",
+            lookup_bot(config, app_id).unwrap_or_else(|| app_id.clone()),
+        ));
+    }
+    for (key, value) in [
+        ("sender", &args.sender),
+        ("kind", &args.kind),
+        ("since", &args.since),
+        ("until", &args.until),
+    ] {
+        if let Some(value) = value {
+            query.push((key, value.clone()));
+        }
+    }
+    let req = reqwest::Client::new()
+        .get(args.server.url("/search"))
+        .query(&query);
+    let hits = args.server.send_json(req, "搜索会话消息").await?;
+    if output != OutputFormat::Table {
+        return output.print(&hits);
+    }
+    let hits = hits
+        .as_array()
+        .ok_or_else(|| anyhow!("搜索结果格式不正确"))?;
+    if hits.is_empty() {
+        println!("没有找到匹配的消息");
+        return Ok(());
+    }
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    for hit in hits {
+        println!("{}", format_hit(hit, color));
+    }
+    Ok(())
+}
+
+/// 一行一条：`[时间] [会话] 发送者: 摘要`
+fn format_hit(hit: &Value, color: bool) -> String {
+    let time = hit["at"]
+        .as_str()
+        .and_then(|at| at.parse::<DateTime<Utc>>().ok())
+        .map(|at| {
+            at.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default();
+    let who = match hit["direction"].as_str() {
+        Some("out") => "[机器人]",
+        _ => hit["sender"].as_str().unwrap_or("?"),
+    };
+    let snippet = hit["snippet"]
+        .as_str()
+        .or_else(|| hit["text"].as_str())
+        .unwrap_or_default();
+    format!(
+        "[{time}] [{}] {who}: {}",
+        hit["chat"].as_str().unwrap_or("?"),
+        highlight(snippet, color)
+    )
+}
+
+/// 把 `<mark>` 标记换成终端高亮（黄色加粗）；非终端输出时去掉标记
+fn highlight(snippet: &str, color: bool) -> String {
+    let (start, end) = if color {
+        ("\x1b[1;33m", "\x1b[0m")
+    } else {
+        ("", "")
+    };
+    snippet
+        .replace(HIGHLIGHT_START, start)
+        .replace(HIGHLIGHT_END, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_hit() {
+        let hit = json!({
+            "at": "2026-01-01T00:00:00Z", "chat": "1@chatroom", "sender": "wxid_a",
+            "direction": "in", "kind": "text", "text": "明天开会",
+            "snippet": "明天<mark>开会</mark>"
+        });
+        assert!(format_hit(&hit, false).ends_with("] [1@chatroom] wxid_a: 明天开会"));
+        assert!(format_hit(&hit, true).ends_with("wxid_a: 明天\x1b[1;33m开会\x1b[0m"));
+        let out = json!({ "chat": "1@chatroom", "direction": "out", "text": "好的" });
+        assert_eq!(format_hit(&out, false), "[] [1@chatroom] [机器人]: 好的");
+    }
+}
@@ -0,0 +1,736 @@
+//! 系统服务打包命令模块
+//!
+//! 提供 `service install`，为 gewe-bot-app 生成 systemd unit、launchd plist 或 Windows 计划任务启动脚本，
+//! 并准备环境变量文件与日志目录，便于非容器环境部署。
+
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use directories::BaseDirs;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_SERVICE_NAME: &str = "gewe-bot-app";
+const DEFAULT_RUN_AS: &str = "gewe";
+const LOG_FILE_NAME: &str = "gewe-bot-app.log";
+
+#[derive(Subcommand)]
+pub enum ServiceCommands {
+    /// 安装 gewe-bot-app 系统服务
+    Install(InstallServiceArgs),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ServicePlatform {
+    Systemd,
+    Launchd,
+    Windows,
+}
+
+impl ServicePlatform {
+    fn current() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else if cfg!(target_os = "macos") {
+            Self::Launchd
+        } else {
+            Self::Systemd
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct InstallServiceArgs {
+    /// 服务名称
+    #[arg(long, default_value = DEFAULT_SERVICE_NAME)]
+    pub name: String,
+    /// 以当前用户身份安装（systemd --user / LaunchAgents / 登录时启动）
+    #[arg(long)]
+    pub user: bool,
+    /// 目标平台，未指定时按当前系统选择
+    #[arg(long, value_enum)]
+    pub platform: Option<ServicePlatform>,
+    /// gewe-bot-app 可执行文件，未指定时在当前程序目录与 PATH 中查找
+    #[arg(long)]
+    pub binary: Option<PathBuf>,
+    /// gewe-bot-app 配置文件，未指定时由 gewe-bot-app 读取 GEWE_BOT_CONFIG 或工作目录下的默认配置
+    #[arg(long)]
+    pub bot_config: Option<PathBuf>,
+    /// 工作目录（相对路径的 data_dir、图片目录以此为基准）
+    #[arg(long)]
+    pub working_dir: Option<PathBuf>,
+    /// 环境变量文件，不存在时生成模板
+    #[arg(long)]
+    pub env_file: Option<PathBuf>,
+    /// 日志目录，服务通过 GEWE_LOG_FILE 写入其中
+    #[arg(long)]
+    pub log_dir: Option<PathBuf>,
+    /// 系统服务的运行账户（仅 systemd / launchd 系统模式）
+    #[arg(long, default_value = DEFAULT_RUN_AS)]
+    pub run_as: String,
+    /// 覆盖已存在的服务文件
+    #[arg(long)]
+    pub force: bool,
+    /// 只打印将生成的内容，不写入文件
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// 生成服务文件所需的全部参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceSpec {
+    pub name: String,
+    pub platform: ServicePlatform,
+    pub user_mode: bool,
+    pub binary: PathBuf,
+    pub bot_config: Option<PathBuf>,
+    pub working_dir: PathBuf,
+    pub env_file: PathBuf,
+    pub log_dir: PathBuf,
+    /// 系统模式下的运行账户
+    pub run_as: Option<String>,
+}
+
+/// 各平台的默认目录
+#[derive(Debug, Clone, PartialEq)]
+struct ServicePaths {
+    service_file: PathBuf,
+    working_dir: PathBuf,
+    env_file: PathBuf,
+    log_dir: PathBuf,
+}
+
+/// 用户模式所需的目录，系统模式不使用
+#[derive(Debug, Clone)]
+struct HomeDirs {
+    home: PathBuf,
+    config: PathBuf,
+    data: PathBuf,
+    state: Option<PathBuf>,
+}
+
+impl HomeDirs {
+    fn detect() -> Result<Self> {
+        let base = BaseDirs::new().ok_or_else(|| anyhow!("failed to resolve home directory"))?;
+        Ok(Self {
+            home: base.home_dir().to_path_buf(),
+            config: base.config_dir().to_path_buf(),
+            data: base.data_local_dir().to_path_buf(),
+            state: base.state_dir().map(Path::to_path_buf),
+        })
+    }
+}
+
+pub fn handle_service_command(command: ServiceCommands) -> Result<()> {
+    match command {
+        ServiceCommands::Install(args) => handle_install(args),
+    }
+}
+
+fn handle_install(args: InstallServiceArgs) -> Result<()> {
+    validate_name(&args.name)?;
+    let platform = args.platform.unwrap_or_else(ServicePlatform::current);
+    let home = if args.user {
+        Some(HomeDirs::detect()?)
+    } else {
+        None
+    };
+    let defaults = default_paths(platform, &args.name, home.as_ref());
+    let binary = match &args.binary {
+        Some(path) => absolute(path)?,
+        None => find_bot_binary()?,
+    };
+    let spec = ServiceSpec {
+        name: args.name.clone(),
+        platform,
+        user_mode: args.user,
+        binary,
+        bot_config: args.bot_config.as_deref().map(absolute).transpose()?,
+        working_dir: args
+            .working_dir
+            .as_deref()
+            .map(absolute)
+            .transpose()?
+            .unwrap_or(defaults.working_dir),
+        env_file: args
+            .env_file
+            .as_deref()
+            .map(absolute)
+            .transpose()?
+            .unwrap_or(defaults.env_file),
+        log_dir: args
+            .log_dir
+            .as_deref()
+            .map(absolute)
+            .transpose()?
+            .unwrap_or(defaults.log_dir),
+        run_as: (!args.user && platform != ServicePlatform::Windows)
+            .then(|| args.run_as.clone())
+            .filter(|u| !u.trim().is_empty()),
+    };
+    let service_file = defaults.service_file;
+    let contents = render_service(&spec);
+
+    if args.dry_run {
+        println!("# {}", service_file.display());
+        println!("{}", contents);
+        return Ok(());
+    }
+
+    if service_file.exists() && !args.force {
+        return Err(anyhow!(
+            "服务文件已存在: {}（使用 --force 覆盖）",
+            service_file.display()
+        ));
+    }
+    for dir in [&spec.working_dir, &spec.log_dir] {
+        fs::create_dir_all(dir)?;
+    }
+    if !spec.env_file.exists() {
+        write_env_template(&spec.env_file)?;
+        println!("已生成环境变量文件: {}", spec.env_file.display());
+    }
+    if let Some(parent) = service_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&service_file, contents)?;
+    println!("已写入服务文件: {}", service_file.display());
+    println!("日志目录: {}", spec.log_dir.display());
+    println!();
+    for line in next_steps(&spec, &service_file) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// 服务名会拼入文件名与 launchd Label，只允许字母、数字、`-`、`_`、`.`
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "服务名称只能包含字母、数字、-、_、.，且不能以 . 开头: {}",
+            name
+        ))
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+/// 依次在当前程序所在目录与 PATH 中查找 gewe-bot-app
+fn find_bot_binary() -> Result<PathBuf> {
+    let file_name = format!("gewe-bot-app{}", std::env::consts::EXE_SUFFIX);
+    let mut dirs: Vec<PathBuf> = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .into_iter()
+        .collect();
+    if let Some(path) = std::env::var_os("PATH") {
+        dirs.extend(std::env::split_paths(&path));
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| candidate.is_file())
+        .map(|found| fs::canonicalize(&found).unwrap_or(found))
+        .ok_or_else(|| anyhow!("未找到 {}，请通过 --binary 指定", file_name))
+}
+
+fn default_paths(platform: ServicePlatform, name: &str, home: Option<&HomeDirs>) -> ServicePaths {
+    match (platform, home) {
+        (ServicePlatform::Systemd, None) => ServicePaths {
+            service_file: PathBuf::from(format!("/etc/systemd/system/{}.service", name)),
+            working_dir: Path::new("/var/lib").join(name),
+            env_file: PathBuf::from(format!("/etc/gewe/{}.env", name)),
+            log_dir: Path::new("/var/log").join(name),
+        },
+        (ServicePlatform::Systemd, Some(home)) => ServicePaths {
+            service_file: home
+                .config
+                .join("systemd/user")
+                .join(format!("{}.service", name)),
+            working_dir: home.data.join(name),
+            env_file: home.config.join("gewe").join(format!("{}.env", name)),
+            log_dir: home
+                .state
+                .clone()
+                .unwrap_or_else(|| home.home.join(".local/state"))
+                .join(name)
+                .join("logs"),
+        },
+        (ServicePlatform::Launchd, None) => ServicePaths {
+            service_file: PathBuf::from(format!(
+                "/Library/LaunchDaemons/{}.plist",
+                launchd_label(name)
+            )),
+            working_dir: Path::new("/usr/local/var").join(name),
+            env_file: PathBuf::from(format!("/usr/local/etc/gewe/{}.env", name)),
+            log_dir: Path::new("/usr/local/var/log").join(name),
+        },
+        (ServicePlatform::Launchd, Some(home)) => ServicePaths {
+            service_file: home
+                .home
+                .join("Library/LaunchAgents")
+                .join(format!("{}.plist", launchd_label(name))),
+            working_dir: home.data.join(name),
+            env_file: home.config.join("gewe").join(format!("{}.env", name)),
+            log_dir: home.home.join("Library/Logs").join(name),
+        },
+        (ServicePlatform::Windows, home) => {
+            let root = match home {
+                Some(home) => home.data.join(name),
+                None => std::env::var_os("ProgramData")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+                    .join(name),
+            };
+            ServicePaths {
+                service_file: root.join(format!("{}.cmd", name)),
+                working_dir: root.clone(),
+                env_file: root.join(format!("{}.env", name)),
+                log_dir: root.join("logs"),
+            }
+        }
+    }
+}
+
+fn launchd_label(name: &str) -> String {
+    format!("com.gewe.{}", name)
+}
+
+fn log_file(spec: &ServiceSpec) -> PathBuf {
+    spec.log_dir.join(LOG_FILE_NAME)
+}
+
+/// gewe-bot-app 的启动参数
+fn exec_args(spec: &ServiceSpec) -> Vec<String> {
+    let mut args = vec![spec.binary.display().to_string()];
+    if let Some(config) = &spec.bot_config {
+        args.push(config.display().to_string());
+    }
+    args
+}
+
+pub fn render_service(spec: &ServiceSpec) -> String {
+    match spec.platform {
+        ServicePlatform::Systemd => render_systemd_unit(spec),
+        ServicePlatform::Launchd => render_launchd_plist(spec),
+        ServicePlatform::Windows => render_windows_launcher(spec),
+    }
+}
+
+fn systemd_quote(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// systemd unit：崩溃自动重启，系统模式下以专用账户运行并启用沙箱限制
+pub fn render_systemd_unit(spec: &ServiceSpec) -> String {
+    let exec: Vec<String> = exec_args(spec).iter().map(|a| systemd_quote(a)).collect();
+    let mut unit = format!(
+        "[Unit]\n\
+         Description=GeWe bot app ({name})\n\
+         After=network-online.target\n\
+         Wants=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         WorkingDirectory={workdir}\n\
+         EnvironmentFile={env}\n\
+         Environment={log}\n\
+         ExecStart={exec}\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         UMask=0077\n\
+         NoNewPrivileges=yes\n",
+        name = spec.name,
+        workdir = systemd_quote(&spec.working_dir.display().to_string()),
+        env = systemd_quote(&spec.env_file.display().to_string()),
+        // 引号需包住整个赋值，systemd 才会把含空格的值作为一个变量
+        log = systemd_quote(&format!("GEWE_LOG_FILE={}", log_file(spec).display())),
+        exec = exec.join(" "),
+    );
+    if let Some(user) = &spec.run_as {
+        // 系统模式下以只读方式挂载系统目录，仅工作目录与日志目录可写
+        unit.push_str(&format!(
+            "User={user}\n\
+             Group={user}\n\
+             ProtectSystem=strict\n\
+             ReadWritePaths={workdir} {logs}\n\
+             ProtectHome=read-only\n\
+             PrivateTmp=yes\n\
+             PrivateDevices=yes\n\
+             ProtectKernelTunables=yes\n\
+             ProtectKernelModules=yes\n\
+             ProtectKernelLogs=yes\n\
+             ProtectControlGroups=yes\n\
+             ProtectClock=yes\n\
+             ProtectHostname=yes\n\
+             RestrictSUIDSGID=yes\n\
+             RestrictRealtime=yes\n\
+             RestrictNamespaces=yes\n\
+             RestrictAddressFamilies=AF_UNIX AF_INET AF_INET6\n\
+             LockPersonality=yes\n\
+             CapabilityBoundingSet=\n\
+             SystemCallArchitectures=native\n",
+            user = user,
+            workdir = systemd_quote(&spec.working_dir.display().to_string()),
+            logs = systemd_quote(&spec.log_dir.display().to_string()),
+        ));
+    }
+    let wanted_by = if spec.user_mode {
+        "default.target"
+    } else {
+        "multi-user.target"
+    };
+    unit.push_str(&format!("\n[Install]\nWantedBy={}\n", wanted_by));
+    unit
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// launchd plist：launchd 不支持环境变量文件，经 /bin/sh 加载后再 exec gewe-bot-app
+pub fn render_launchd_plist(spec: &ServiceSpec) -> String {
+    let exec: Vec<String> = exec_args(spec).iter().map(|a| shell_quote(a)).collect();
+    let script = format!(
+        "set -a; . {}; set +a; exec {}",
+        shell_quote(&spec.env_file.display().to_string()),
+        exec.join(" ")
+    );
+    let stdout = spec.log_dir.join("stdout.log");
+    let stderr = spec.log_dir.join("stderr.log");
+    let mut plist = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/bin/sh</string>
+        <string>-c</string>
+        <string>{script}</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{workdir}</string>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>GEWE_LOG_FILE</key>
+        <string>{log}</string>
+    </dict>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>5</integer>
+    <key>Umask</key>
+    <integer>63</integer>
+    <key>StandardOutPath</key>
+    <string>{stdout}</string>
+    <key>StandardErrorPath</key>
+    <string>{stderr}</string>
+"#,
+        label = xml_escape(&launchd_label(&spec.name)),
+        script = xml_escape(&script),
+        workdir = xml_escape(&spec.working_dir.display().to_string()),
+        log = xml_escape(&log_file(spec).display().to_string()),
+        stdout = xml_escape(&stdout.display().to_string()),
+        stderr = xml_escape(&stderr.display().to_string()),
+    );
+    if let Some(user) = &spec.run_as {
+        plist.push_str(&format!(
+            "    <key>UserName</key>\n    <string>{}</string>\n",
+            xml_escape(user)
+        ));
+    }
+    plist.push_str("</dict>\n</plist>\n");
+    plist
+}
+
+/// Windows 启动脚本：gewe-bot-app 未实现服务控制协议，由计划任务在开机或登录时运行该脚本
+pub fn render_windows_launcher(spec: &ServiceSpec) -> String {
+    let exec: Vec<String> = exec_args(spec)
+        .iter()
+        .map(|a| format!("\"{}\"", a))
+        .collect();
+    format!(
+        "@echo off\r\n\
+         rem {name}: 由 gewe service install 生成\r\n\
+         setlocal\r\n\
+         cd /d \"{workdir}\"\r\n\
+         if exist \"{env}\" (\r\n\
+         \x20   for /f \"usebackq eol=# tokens=1,* delims==\" %%a in (\"{env}\") do set \"%%a=%%b\"\r\n\
+         )\r\n\
+         set \"GEWE_LOG_FILE={log}\"\r\n\
+         {exec} >> \"{stdout}\" 2>&1\r\n",
+        name = spec.name,
+        workdir = spec.working_dir.display(),
+        env = spec.env_file.display(),
+        log = log_file(spec).display(),
+        exec = exec.join(" "),
+        stdout = spec.log_dir.join("stdout.log").display(),
+    )
+}
+
+fn write_env_template(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let template = "\
+# gewe-bot-app 环境变量，每行 KEY=VALUE
+# Bot Token（与配置中的 token_env 对应）
+# GEWE_BOT_TOKEN_MAIN=
+# 管理 API 鉴权
+# GEWE_API_TOKEN=
+# AI 接口密钥
+# GEWE_AI_API_KEY=
+# 日志级别与格式
+# RUST_LOG=info,gewe_bot_app=debug
+# GEWE_LOG_JSON=1
+# GEWE_LOG_ROLLING=daily
+";
+    fs::write(path, template)?;
+    // 环境变量文件包含密钥，仅允许属主读写
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// 写入后需要手动执行的命令
+fn next_steps(spec: &ServiceSpec, service_file: &Path) -> Vec<String> {
+    let mut steps = vec!["后续步骤：".to_string()];
+    match spec.platform {
+        ServicePlatform::Systemd => {
+            if let Some(user) = &spec.run_as {
+                steps.push(format!(
+                    "  sudo useradd --system --home {} --shell /usr/sbin/nologin {}  # 账户不存在时",
+                    spec.working_dir.display(),
+                    user
+                ));
+                steps.push(format!(
+                    "  sudo chown -R {user}:{user} {} {}",
+                    spec.working_dir.display(),
+                    spec.log_dir.display()
+                ));
+                steps.push(format!(
+                    "  sudo chown root:{} {} && sudo chmod 640 {}",
+                    user,
+                    spec.env_file.display(),
+                    spec.env_file.display()
+                ));
+            }
+            let (prefix, flag) = if spec.user_mode {
+                ("", " --user")
+            } else {
+                ("sudo ", "")
+            };
+            steps.push(format!("  编辑 {}", spec.env_file.display()));
+            steps.push(format!("  {}systemctl{} daemon-reload", prefix, flag));
+            steps.push(format!(
+                "  {}systemctl{} enable --now {}",
+                prefix, flag, spec.name
+            ));
+            if spec.user_mode {
+                steps.push(format!(
+                    "  loginctl enable-linger {}  # 注销后继续运行",
+                    std::env::var("USER").unwrap_or_else(|_| "$USER".to_string())
+                ));
+            }
+        }
+        ServicePlatform::Launchd => {
+            steps.push(format!("  编辑 {}", spec.env_file.display()));
+            if spec.user_mode {
+                steps.push(format!(
+                    "  launchctl bootstrap gui/$(id -u) {}",
+                    service_file.display()
+                ));
+            } else {
+                steps.push(format!(
+                    "  sudo chown -R {} {}",
+                    spec.run_as.as_deref().unwrap_or(DEFAULT_RUN_AS),
+                    spec.log_dir.display()
+                ));
+                steps.push(format!(
+                    "  sudo launchctl bootstrap system {}",
+                    service_file.display()
+                ));
+            }
+        }
+        ServicePlatform::Windows => {
+            steps.push(format!("  编辑 {}", spec.env_file.display()));
+            let trigger = if spec.user_mode {
+                "/SC ONLOGON"
+            } else {
+                "/SC ONSTART /RU SYSTEM"
+            };
+            steps.push(format!(
+                "  schtasks /Create /TN {} /TR \"\\\"{}\\\"\" {} /RL LIMITED /F",
+                spec.name,
+                service_file.display(),
+                trigger
+            ));
+            steps.push(format!("  schtasks /Run /TN {}", spec.name));
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(platform: ServicePlatform, user_mode: bool) -> ServiceSpec {
+        ServiceSpec {
+            name: "gewe-bot-app".to_string(),
+            platform,
+            user_mode,
+            binary: PathBuf::from("/usr/local/bin/gewe-bot-app"),
+            bot_config: Some(PathBuf::from("/etc/gewe/bot app.toml")),
+            working_dir: PathBuf::from("/var/lib/gewe-bot-app"),
+            env_file: PathBuf::from("/etc/gewe/gewe-bot-app.env"),
+            log_dir: PathBuf::from("/var/log/gewe-bot-app"),
+            run_as: (!user_mode).then(|| "gewe".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_render_systemd_unit() {
+        let system = spec(ServicePlatform::Systemd, false);
+        let unit = render_systemd_unit(&system);
+        assert!(unit.contains("ExecStart=/usr/local/bin/gewe-bot-app \"/etc/gewe/bot app.toml\"\n"));
+        assert!(unit.contains("EnvironmentFile=/etc/gewe/gewe-bot-app.env\n"));
+        assert!(unit.contains(&format!(
+            "Environment=GEWE_LOG_FILE={}\n",
+            log_file(&system).display()
+        )));
+        assert!(unit.contains("User=gewe\n"));
+        assert!(unit.contains("ProtectSystem=strict\n"));
+        assert!(unit.contains("ReadWritePaths=/var/lib/gewe-bot-app /var/log/gewe-bot-app\n"));
+        assert!(unit.ends_with("WantedBy=multi-user.target\n"));
+
+        // 用户模式不设置运行账户与沙箱
+        let unit = render_systemd_unit(&spec(ServicePlatform::Systemd, true));
+        assert!(!unit.contains("User="));
+        assert!(!unit.contains("ProtectSystem"));
+        assert!(unit.contains("NoNewPrivileges=yes\n"));
+        assert!(unit.ends_with("WantedBy=default.target\n"));
+
+        // 含空格的路径加引号
+        let mut spaced = spec(ServicePlatform::Systemd, true);
+        spaced.env_file = PathBuf::from("/home/me/gewe config/gewe-bot-app.env");
+        spaced.log_dir = PathBuf::from("/home/me/gewe logs");
+        let unit = render_systemd_unit(&spaced);
+        assert!(unit.contains("EnvironmentFile=\"/home/me/gewe config/gewe-bot-app.env\"\n"));
+        assert!(unit.contains(&format!(
+            "Environment=\"GEWE_LOG_FILE={}\"\n",
+            log_file(&spaced).display()
+        )));
+    }
+
+    #[test]
+    fn test_render_launchd_plist() {
+        let mut spec = spec(ServicePlatform::Launchd, false);
+        spec.bot_config = Some(PathBuf::from("/etc/gewe/o'neil&co.toml"));
+        let plist = render_launchd_plist(&spec);
+        assert!(plist.contains("<string>com.gewe.gewe-bot-app</string>"));
+        assert!(plist.contains(
+            "<string>set -a; . '/etc/gewe/gewe-bot-app.env'; set +a; exec '/usr/local/bin/gewe-bot-app' '/etc/gewe/o'\\''neil&amp;co.toml'</string>"
+        ));
+        assert!(plist.contains(&format!(
+            "<string>{}</string>",
+            spec.log_dir.join("stderr.log").display()
+        )));
+        assert!(plist.contains("<key>UserName</key>\n    <string>gewe</string>"));
+        assert!(plist.ends_with("</dict>\n</plist>\n"));
+    }
+
+    #[test]
+    fn test_render_windows_launcher() {
+        let launcher = render_windows_launcher(&spec(ServicePlatform::Windows, true));
+        assert!(launcher.starts_with("@echo off\r\n"));
+        assert!(launcher.contains("cd /d \"/var/lib/gewe-bot-app\"\r\n"));
+        assert!(launcher.contains("set \"GEWE_LOG_FILE=/var/log/gewe-bot-app"));
+        assert!(launcher.contains("\"/usr/local/bin/gewe-bot-app\" \"/etc/gewe/bot app.toml\" >> "));
+    }
+
+    #[test]
+    fn test_default_paths() {
+        let home = HomeDirs {
+            home: PathBuf::from("/home/u"),
+            config: PathBuf::from("/home/u/.config"),
+            data: PathBuf::from("/home/u/.local/share"),
+            state: None,
+        };
+        let paths = default_paths(ServicePlatform::Systemd, "bot", Some(&home));
+        assert_eq!(
+            paths.service_file,
+            PathBuf::from("/home/u/.config/systemd/user/bot.service")
+        );
+        assert_eq!(
+            paths.log_dir,
+            PathBuf::from("/home/u/.local/state/bot/logs")
+        );
+        assert_eq!(
+            paths.env_file,
+            PathBuf::from("/home/u/.config/gewe/bot.env")
+        );
+
+        let paths = default_paths(ServicePlatform::Systemd, "bot", None);
+        assert_eq!(
+            paths.service_file,
+            PathBuf::from("/etc/systemd/system/bot.service")
+        );
+        assert_eq!(paths.working_dir, PathBuf::from("/var/lib/bot"));
+
+        let paths = default_paths(ServicePlatform::Launchd, "bot", Some(&home));
+        assert_eq!(
+            paths.service_file,
+            PathBuf::from("/home/u/Library/LaunchAgents/com.gewe.bot.plist")
+        );
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("gewe-bot-app").is_ok());
+        assert!(validate_name("bot_2.prod").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../evil").is_err());
+        assert!(validate_name("a b").is_err());
+    }
+
+    #[test]
+    fn test_systemd_quote() {
+        assert_eq!(systemd_quote("/usr/bin/app"), "/usr/bin/app");
+        assert_eq!(systemd_quote("/opt/my app"), "\"/opt/my app\"");
+        assert_eq!(systemd_quote(r#"a"b"#), r#""a\"b""#);
+    }
+}
@@ -1,9 +1,9 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::Args;
 use gewe_core::{AddLabelRequest, DeleteLabelRequest, ListLabelRequest, ModifyLabelMemberRequest};
 use gewe_http::GeweHttpClient;
-use serde_json::to_string_pretty;
 use std::path::Path;
 use tracing::info;
 
@@ -60,6 +60,7 @@ pub async fn handle_add_label(
     args: AddLabelArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -68,7 +69,7 @@ pub async fn handle_add_label(
             label_name: &args.label_name,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -76,6 +77,7 @@ pub async fn handle_delete_label(
     args: DeleteLabelArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -85,6 +87,7 @@ pub async fn handle_delete_label(
         })
         .await?;
     info!(ids = args.label_ids, "labels deleted");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -92,12 +95,13 @@ pub async fn handle_list_labels(
     args: ListLabelArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
         .list_labels(ListLabelRequest { app_id: &app_id })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -105,6 +109,7 @@ pub async fn handle_modify_label_members(
     args: ModifyLabelMembersArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     if args.wx_ids.is_empty() {
         return Err(anyhow!("至少传入一个 wx-id"));
@@ -123,6 +128,7 @@ pub async fn handle_modify_label_members(
         count = args.wx_ids.len(),
         "label members updated"
     );
+    output.print_ok()?;
     Ok(())
 }
 
@@ -0,0 +1,561 @@
+//! tail 命令模块
+//!
+//! 实时打印某个会话的新消息：连接 gewe-bot-app 的 `/api/ws` 推送通道，或用 `--data-dir`
+//! 跟随本地的会话消息记录；`--reply` 时从终端读取输入并发送到该会话。
+
+use crate::bot_app::BotAppArgs;
+use crate::config::{default_base_url, lookup_bot, resolve_chatroom, resolve_value, CliConfig};
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use chrono::{DateTime, Local, Utc};
+use clap::Args;
+use gewe_http::GeweHttpClient;
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+/// 跟随本地记录时检查文件变化的间隔
+const LOCAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+#[derive(Args)]
+pub struct TailArgs {
+    #[command(flatten)]
+    pub server: BotAppArgs,
+    /// 会话：群 ID、群名别名或私聊 wxid
+    #[arg(long, visible_alias = "chat")]
+    pub chatroom: String,
+    /// 只看指定机器人（app_id 或别名）的消息，`--reply` 时也用于发送
+    #[arg(long)]
+    pub app_id: Option<String>,
+    /// 启动时先打印的最近消息条数，需 gewe-bot-app 开启会话消息记录
+    #[arg(long, default_value_t = 10)]
+    pub history: usize,
+    /// 跟随本地会话消息记录（gewe-bot-app 的 data_dir），不连接服务
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    /// 交互模式：输入一行文字即发送到该会话
+    #[arg(long)]
+    pub reply: bool,
+    /// 按行输出原始 JSON
+    #[arg(long)]
+    pub json: bool,
+    /// `--reply` 发送使用的 GeWe token，默认取配置
+    #[arg(long)]
+    pub token: Option<String>,
+    /// `--reply` 发送使用的 GeWe API 地址，默认取配置
+    #[arg(long)]
+    pub base_url: Option<String>,
+}
+
+/// `--reply` 模式的发送端
+struct ReplySender {
+    client: GeweHttpClient,
+    app_id: String,
+}
+
+pub async fn handle_tail(args: TailArgs, config: &CliConfig) -> Result<()> {
+    let chat = resolve_chatroom(config, &args.chatroom);
+    let app_id = args
+        .app_id
+        .as_deref()
+        .map(|id| lookup_bot(config, id).unwrap_or_else(|| id.to_string()));
+    let sender = if args.reply {
+        let token = resolve_value(args.token.clone(), config.token.clone(), "token")?;
+        let base_url = args
+            .base_url
+            .clone()
+            .or_else(|| config.base_url.clone())
+            .unwrap_or_else(default_base_url);
+        Some(ReplySender {
+            client: GeweHttpClient::new(token, base_url)?,
+            app_id: resolve_value(app_id.clone(), config.app_id.clone(), "app_id")?,
+        })
+    } else {
+        None
+    };
+
+    let (tx, mut rx) = mpsc::channel::<Value>(64);
+    let mut source = match args.data_dir.clone() {
+        Some(dir) => tokio::spawn(follow_local(dir, app_id, chat.clone(), args.history, tx)),
+        None => tokio::spawn(follow_ws(
+            args.server.clone(),
+            app_id,
+            chat.clone(),
+            args.history,
+            tx,
+        )),
+    };
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut reading = sender.is_some();
+    loop {
+        tokio::select! {
+            message = rx.recv() => match message {
+                Some(message) => print_message(&message, args.json),
+                None => break,
+            },
+            line = lines.next_line(), if reading => match line? {
+                Some(line) if !line.trim().is_empty() => {
+                    let Some(sender) = &sender else { continue };
+                    match sender.client.send_text(&sender.app_id, &chat, line.trim(), None).await {
+                        Ok(_) => print_message(&local_reply(&sender.app_id, &chat, line.trim()), args.json),
+                        Err(err) => eprintln!("发送失败: {err}"),
+                    }
+                }
+                Some(_) => {}
+                None => reading = false,
+            },
+        }
+    }
+    (&mut source).await?
+}
+
+/// 自己从终端发出的消息，格式同会话消息记录
+fn local_reply(app_id: &str, chat: &str, text: &str) -> Value {
+    json!({
+        "type": "event",
+        "at": Utc::now(),
+        "app_id": app_id,
+        "chat": chat,
+        "direction": "out",
+        "kind": "text",
+        "text": text,
+    })
+}
+
+fn print_message(message: &Value, raw: bool) {
+    if raw {
+        println!("{message}");
+    } else if let Some(line) = format_message(message) {
+        println!("{line}");
+    }
+}
+
+/// 把推送消息或会话消息记录格式化为一行；确认等控制消息返回 None
+fn format_message(message: &Value) -> Option<String> {
+    let time = message["at"]
+        .as_str()
+        .and_then(|at| at.parse::<DateTime<Utc>>().ok())
+        .map(|at| {
+            at.with_timezone(&Local)
+                .format("%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default();
+    let kind = message["kind"].as_str().unwrap_or("other");
+    match message["type"].as_str() {
+        // 会话消息记录没有 type 字段
+        Some("event") | None => {
+            let who = match message["direction"].as_str() {
+                Some("out") => "[机器人]",
+                _ => message["sender"].as_str().unwrap_or("?"),
+            };
+            let text = match message["text"].as_str() {
+                Some(text) if kind == "text" => text.to_string(),
+                Some(text) => format!("[{kind}] {text}"),
+                None => format!("[{kind}]"),
+            };
+            Some(format!("[{time}] {who}: {text}"))
+        }
+        Some("send_status") => {
+            let status = match message["status"].as_str() {
+                Some("sent") => "已发送".to_string(),
+                Some("shadowed") => "影子模式未发送".to_string(),
+                _ => format!(
+                    "发送失败: {}",
+                    message["error"].as_str().unwrap_or("未知错误")
+                ),
+            };
+            Some(format!("[{time}] [机器人] {kind} {status}"))
+        }
+        Some("lagged") => Some(format!(
+            "（处理过慢，跳过了 {} 条消息）",
+            message["skipped"].as_u64().unwrap_or_default()
+        )),
+        Some("error") => Some(format!(
+            "服务端错误: {}",
+            message["error"].as_str().unwrap_or_default()
+        )),
+        Some(_) => None,
+    }
+}
+
+/// 连接 `/api/ws` 订阅该会话；订阅确认后先补上最近的消息记录
+async fn follow_ws(
+    server: BotAppArgs,
+    app_id: Option<String>,
+    chat: String,
+    history: usize,
+    tx: mpsc::Sender<Value>,
+) -> Result<()> {
+    let client = reqwest::Client::builder().http1_only().build()?;
+    let key = base64::engine::general_purpose::STANDARD.encode(random_bytes::<16>());
+    let req = client
+        .get(server.url("/ws"))
+        .header(reqwest::header::CONNECTION, "Upgrade")
+        .header(reqwest::header::UPGRADE, "websocket")
+        .header(reqwest::header::SEC_WEBSOCKET_VERSION, "13")
+        .header(reqwest::header::SEC_WEBSOCKET_KEY, key);
+    let resp = server.authorize(req).send().await?;
+    if resp.status() != reqwest::StatusCode::SWITCHING_PROTOCOLS {
+        return Err(anyhow!(
+            "连接 {} 失败: HTTP {}",
+            server.url("/ws"),
+            resp.status()
+        ));
+    }
+    let mut socket = resp.upgrade().await?;
+    let subscribe = json!({ "type": "subscribe", "app_id": app_id, "chat": chat });
+    socket
+        .write_all(&encode_client_frame(
+            OP_TEXT,
+            subscribe.to_string().as_bytes(),
+        ))
+        .await?;
+
+    loop {
+        let Some((opcode, payload)) = read_frame(&mut socket).await? else {
+            return Err(anyhow!("服务端关闭了连接"));
+        };
+        match opcode {
+            OP_TEXT => {
+                let message: Value = serde_json::from_slice(&payload)?;
+                if message["type"] == "subscribed" && history > 0 {
+                    for entry in recent_history(&server, app_id.as_deref(), &chat, history).await {
+                        if tx.send(entry).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                if tx.send(message).await.is_err() {
+                    return Ok(());
+                }
+            }
+            OP_PING => {
+                socket
+                    .write_all(&encode_client_frame(OP_PONG, &payload))
+                    .await?;
+            }
+            OP_CLOSE => return Err(anyhow!("服务端关闭了连接")),
+            _ => {}
+        }
+    }
+}
+
+/// 读取会话最近的消息记录；未开启会话消息记录时返回空
+async fn recent_history(
+    server: &BotAppArgs,
+    app_id: Option<&str>,
+    chat: &str,
+    limit: usize,
+) -> Vec<Value> {
+    let mut query = vec![("limit", limit.to_string())];
+    if let Some(app_id) = app_id {
+        query.push(("app_id", app_id.to_string()));
+    }
+    let req = reqwest::Client::new()
+        .get(server.url(&format!("/history/{}", chat)))
+        .query(&query);
+    match server.send_json(req, "读取会话消息记录").await {
+        Ok(Value::Array(entries)) => entries,
+        Ok(_) => Vec::new(),
+        Err(err) => {
+            tracing::debug!(%err, "跳过最近消息");
+            Vec::new()
+        }
+    }
+}
+
+/// 跟随 `{data_dir}/history/{app_id}/{chat}.jsonl`：先打印最近的消息，之后定期读取新增的行。
+/// 文件被截断（超过保留上限）后从新的末尾继续
+async fn follow_local(
+    data_dir: PathBuf,
+    app_id: Option<String>,
+    chat: String,
+    history: usize,
+    tx: mpsc::Sender<Value>,
+) -> Result<()> {
+    let root = data_dir.join("history");
+    let file_name = format!("{}.jsonl", sanitize_segment(&chat));
+    let mut offsets: HashMap<PathBuf, u64> = HashMap::new();
+    let mut recent = Vec::new();
+    for path in chat_files(&root, app_id.as_deref(), &file_name).await? {
+        let (messages, offset) = read_new_lines(&path, 0).await?;
+        recent.extend(messages);
+        offsets.insert(path, offset);
+    }
+    recent.sort_by_key(|m| {
+        m["at"]
+            .as_str()
+            .and_then(|at| at.parse::<DateTime<Utc>>().ok())
+    });
+    let start = recent.len().saturating_sub(history);
+    for message in recent.split_off(start) {
+        if tx.send(message).await.is_err() {
+            return Ok(());
+        }
+    }
+
+    loop {
+        tokio::time::sleep(LOCAL_POLL_INTERVAL).await;
+        for path in chat_files(&root, app_id.as_deref(), &file_name).await? {
+            let offset = offsets.get(&path).copied().unwrap_or_default();
+            let len = tokio::fs::metadata(&path).await?.len();
+            if len < offset {
+                offsets.insert(path, len);
+                continue;
+            }
+            let (messages, offset) = read_new_lines(&path, offset).await?;
+            offsets.insert(path, offset);
+            for message in messages {
+                if tx.send(message).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// 该会话在各机器人目录下已存在的记录文件
+async fn chat_files(root: &Path, app_id: Option<&str>, file_name: &str) -> Result<Vec<PathBuf>> {
+    let dirs = match app_id {
+        Some(app_id) => vec![root.join(sanitize_segment(app_id))],
+        None => {
+            let mut dirs = Vec::new();
+            let mut entries = match tokio::fs::read_dir(root).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(dirs),
+                Err(err) => return Err(err.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                dirs.push(entry.path());
+            }
+            dirs
+        }
+    };
+    Ok(dirs
+        .into_iter()
+        .map(|dir| dir.join(file_name))
+        .filter(|path| path.is_file())
+        .collect())
+}
+
+/// 从 `offset` 起读取完整的行，返回解析出的消息与新的偏移；末尾未写完的行留到下次读取
+async fn read_new_lines(path: &Path, offset: u64) -> Result<(Vec<Value>, u64)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(io::SeekFrom::Start(offset)).await?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).await?;
+    let complete = buf.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let messages = buf[..complete]
+        .split(|&b| b == b'\n')
+        .filter_map(|line| serde_json::from_slice(line).ok())
+        .collect();
+    Ok((messages, offset + complete as u64))
+}
+
+/// 与 gewe-bot-app 存储的文件名规则一致
+fn sanitize_segment(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '@' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    for chunk in bytes.chunks_mut(8) {
+        let value = RandomState::new().build_hasher().finish().to_le_bytes();
+        chunk.copy_from_slice(&value[..chunk.len()]);
+    }
+    bytes
+}
+
+/// 编码客户端帧（FIN，按协议要求加掩码）
+fn encode_client_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = random_bytes::<4>();
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+/// 读取一个服务端帧，返回 (opcode, 负载)；连接正常结束时返回 None
+async fn read_frame<R>(reader: &mut R) -> io::Result<Option<(u8, Vec<u8>)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut head = [0u8; 2];
+    match reader.read_exact(&mut head).await {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    let mut mask = [0u8; 4];
+    if head[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if head[1] & 0x80 != 0 {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    Ok(Some((head[0] & 0x0F, payload)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_message() {
+        let event = json!({
+            "type": "event", "at": "2026-01-01T00:00:00Z", "app_id": "wx1",
+            "chat": "1@chatroom", "sender": "wxid_a", "direction": "in",
+            "kind": "text", "text": "你好"
+        });
+        assert!(format_message(&event).unwrap().ends_with("] wxid_a: 你好"));
+        let image = json!({ "chat": "1@chatroom", "direction": "out", "kind": "image" });
+        assert_eq!(format_message(&image).unwrap(), "[] [机器人]: [image]");
+        let failed = json!({
+            "type": "send_status", "kind": "text", "status": "failed", "error": "timeout"
+        });
+        assert!(format_message(&failed)
+            .unwrap()
+            .ends_with("text 发送失败: timeout"));
+        assert_eq!(format_message(&json!({ "type": "subscribed" })), None);
+    }
+
+    #[tokio::test]
+    async fn test_client_frame_round_trip() {
+        let payload = vec![b'x'; 300];
+        let frame = encode_client_frame(OP_TEXT, &payload);
+        assert_eq!(&frame[..4], &[0x81, 0x80 | 126, 1, 44]);
+        let mut reader = &frame[..];
+        assert_eq!(
+            read_frame(&mut reader).await.unwrap(),
+            Some((OP_TEXT, payload))
+        );
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+    }
+
+    #[cfg(feature = "webhook")]
+    #[tokio::test]
+    async fn test_follow_ws_subscribes_and_forwards_events() {
+        use axum::{
+            extract::ws::{Message, WebSocketUpgrade},
+            routing::get,
+            Router,
+        };
+
+        let router = Router::new().route(
+            "/api/ws",
+            get(|ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(|mut socket| async move {
+                    let Some(Ok(Message::Text(subscribe))) = socket.recv().await else {
+                        panic!("expected a subscribe message");
+                    };
+                    let subscribe: Value = serde_json::from_str(&subscribe).unwrap();
+                    let ack = json!({ "type": "subscribed", "chat": subscribe["chat"] });
+                    let event = json!({ "type": "event", "chat": subscribe["chat"], "text": "hi" });
+                    for message in [ack, event] {
+                        socket
+                            .send(Message::Text(message.to_string().into()))
+                            .await
+                            .unwrap();
+                    }
+                    socket.recv().await;
+                })
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = BotAppArgs {
+            server: format!("http://{}", listener.local_addr().unwrap()),
+            api_token: None,
+        };
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let task = tokio::spawn(follow_ws(server, None, "1@chatroom".to_string(), 0, tx));
+        let ack = rx.recv().await.unwrap();
+        assert_eq!(ack["type"], "subscribed");
+        assert_eq!(ack["chat"], "1@chatroom");
+        assert_eq!(rx.recv().await.unwrap()["text"], "hi");
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_follow_local_prints_recent_then_new_lines() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path().join("history/wx1");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("1@chatroom.jsonl");
+        let line = |at: &str, text: &str| {
+            format!(
+                "{}\n",
+                json!({ "at": at, "app_id": "wx1", "chat": "1@chatroom", "direction": "in", "kind": "text", "text": text })
+            )
+        };
+        let initial = [
+            line("2026-01-01T00:00:01Z", "a"),
+            line("2026-01-01T00:00:02Z", "b"),
+            line("2026-01-01T00:00:03Z", "c"),
+        ];
+        std::fs::write(&path, initial.concat()).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let task = tokio::spawn(follow_local(
+            temp.path().to_path_buf(),
+            None,
+            "1@chatroom".to_string(),
+            2,
+            tx,
+        ));
+        assert_eq!(rx.recv().await.unwrap()["text"], "b");
+        assert_eq!(rx.recv().await.unwrap()["text"], "c");
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        std::io::Write::write_all(&mut file, line("2026-01-01T00:00:04Z", "d").as_bytes()).unwrap();
+        assert_eq!(rx.recv().await.unwrap()["text"], "d");
+        task.abort();
+    }
+}
@@ -0,0 +1,102 @@
+//! 工具注册表命令模块
+//!
+//! 提供 `tools add`，从 URL 或本地文件读取工具注册表并安装到 gewe-bot-app。
+
+use crate::bot_app::BotAppArgs;
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use std::path::Path;
+
+#[derive(Subcommand)]
+pub enum ToolsCommands {
+    /// 从注册表安装工具（保存为草稿，需在管理端发布）
+    Add(AddToolsArgs),
+}
+
+#[derive(Args)]
+pub struct AddToolsArgs {
+    #[command(flatten)]
+    pub server: BotAppArgs,
+    /// 注册表 URL 或本地路径
+    pub source: String,
+    /// 覆盖同名工具
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+pub async fn handle_tools_command(command: ToolsCommands) -> Result<()> {
+    match command {
+        ToolsCommands::Add(args) => handle_add(args).await,
+    }
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+async fn read_registry(client: &reqwest::Client, source: &str) -> Result<String> {
+    if is_url(source) {
+        let resp = client.get(source).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("下载注册表失败: HTTP {}", resp.status()));
+        }
+        return Ok(resp.text().await?);
+    }
+    Ok(std::fs::read_to_string(Path::new(source))?)
+}
+
+async fn handle_add(args: AddToolsArgs) -> Result<()> {
+    let client = reqwest::Client::new();
+    let body = read_registry(&client, &args.source).await?;
+    // 本地路径记录为绝对路径，便于在 Tools 页面追溯来源
+    let source = if is_url(&args.source) {
+        args.source.clone()
+    } else {
+        std::fs::canonicalize(&args.source)?.display().to_string()
+    };
+    let req = client
+        .post(args.server.url("/tools/install"))
+        .query(&[
+            ("source", source.as_str()),
+            ("overwrite", if args.overwrite { "true" } else { "false" }),
+        ])
+        .header("Content-Type", "application/toml")
+        .body(body);
+    let data = args.server.send_json(req, "安装失败").await?;
+
+    let list = |key: &str| -> Vec<String> {
+        data[key]
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    println!("已安装: {}", list("installed").join(", "));
+    let skipped = list("skipped");
+    if !skipped.is_empty() {
+        println!(
+            "已存在未覆盖（使用 --overwrite 覆盖）: {}",
+            skipped.join(", ")
+        );
+    }
+    let missing = list("missing_env");
+    if !missing.is_empty() {
+        println!("缺少环境变量: {}", missing.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/tools.toml"));
+        assert!(is_url("http://localhost/tools.toml"));
+        assert!(!is_url("./tools.toml"));
+    }
+}
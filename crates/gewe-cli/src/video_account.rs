@@ -1,4 +1,5 @@
 use crate::config::{default_base_url, lookup_bot, resolve_value, CliConfig};
+use crate::output::OutputFormat;
 use anyhow::{anyhow, Result};
 use clap::{Args, Subcommand};
 use gewe_core::{
@@ -14,7 +15,6 @@ use gewe_core::{
     UserPageRequest, UserPageResponse,
 };
 use gewe_http::GeweHttpClient;
-use serde_json::to_string_pretty;
 use std::path::Path;
 use tracing::info;
 
@@ -73,95 +73,106 @@ pub async fn handle_video_account_command(
     command: VideoAccountCommands,
     config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     match command {
         VideoAccountCommands::UploadFinderVideo(args) => {
-            handle_upload_finder_video(args, config_path, config).await
+            handle_upload_finder_video(args, config_path, config, output).await
         }
         VideoAccountCommands::PublishFinderCdn(args) => {
-            handle_publish_finder_cdn(args, config_path, config).await
+            handle_publish_finder_cdn(args, config_path, config, output).await
         }
         VideoAccountCommands::PublishFinderWeb(args) => {
-            handle_publish_finder_web(args, config_path, config).await
+            handle_publish_finder_web(args, config_path, config, output).await
         }
         VideoAccountCommands::SendFinderSns(args) => {
-            handle_send_finder_sns(args, config_path, config).await
+            handle_send_finder_sns(args, config_path, config, output).await
         }
         VideoAccountCommands::SendFinderMsg(args) => {
-            handle_send_finder_msg(args, config_path, config).await
+            handle_send_finder_msg(args, config_path, config, output).await
         }
         VideoAccountCommands::FollowFinder(args) => {
-            handle_follow_finder(args, config_path, config).await
+            handle_follow_finder(args, config_path, config, output).await
         }
         VideoAccountCommands::FollowList(args) => {
-            handle_follow_list(args, config_path, config).await
+            handle_follow_list(args, config_path, config, output).await
         }
         VideoAccountCommands::SearchFollow(args) => {
-            handle_search_follow(args, config_path, config).await
+            handle_search_follow(args, config_path, config, output).await
         }
         VideoAccountCommands::SearchFinder(args) => {
-            handle_search_finder(args, config_path, config).await
+            handle_search_finder(args, config_path, config, output).await
         }
         VideoAccountCommands::ScanFollow(args) => {
-            handle_scan_follow(args, config_path, config).await
+            handle_scan_follow(args, config_path, config, output).await
         }
         VideoAccountCommands::ScanBrowse(args) => {
-            handle_scan_browse(args, config_path, config).await
+            handle_scan_browse(args, config_path, config, output).await
+        }
+        VideoAccountCommands::ScanLike(args) => {
+            handle_scan_like(args, config_path, config, output).await
+        }
+        VideoAccountCommands::ScanFav(args) => {
+            handle_scan_fav(args, config_path, config, output).await
         }
-        VideoAccountCommands::ScanLike(args) => handle_scan_like(args, config_path, config).await,
-        VideoAccountCommands::ScanFav(args) => handle_scan_fav(args, config_path, config).await,
         VideoAccountCommands::ScanComment(args) => {
-            handle_scan_comment(args, config_path, config).await
+            handle_scan_comment(args, config_path, config, output).await
         }
         VideoAccountCommands::ScanQrCode(args) => {
-            handle_scan_qr_code(args, config_path, config).await
+            handle_scan_qr_code(args, config_path, config, output).await
         }
         VideoAccountCommands::ScanLoginChannels(args) => {
-            handle_scan_login_channels(args, config_path, config).await
+            handle_scan_login_channels(args, config_path, config, output).await
+        }
+        VideoAccountCommands::IdFav(args) => handle_id_fav(args, config_path, config, output).await,
+        VideoAccountCommands::IdLike(args) => {
+            handle_id_like(args, config_path, config, output).await
+        }
+        VideoAccountCommands::FinderOpt(args) => {
+            handle_finder_opt(args, config_path, config, output).await
         }
-        VideoAccountCommands::IdFav(args) => handle_id_fav(args, config_path, config).await,
-        VideoAccountCommands::IdLike(args) => handle_id_like(args, config_path, config).await,
-        VideoAccountCommands::FinderOpt(args) => handle_finder_opt(args, config_path, config).await,
         VideoAccountCommands::BrowseFinder(args) => {
-            handle_browse_finder(args, config_path, config).await
+            handle_browse_finder(args, config_path, config, output).await
         }
         VideoAccountCommands::LikeFavList(args) => {
-            handle_like_fav_list(args, config_path, config).await
+            handle_like_fav_list(args, config_path, config, output).await
         }
         VideoAccountCommands::CommentFinder(args) => {
-            handle_comment_finder(args, config_path, config).await
+            handle_comment_finder(args, config_path, config, output).await
         }
         VideoAccountCommands::CommentList(args) => {
-            handle_comment_list(args, config_path, config).await
+            handle_comment_list(args, config_path, config, output).await
         }
         VideoAccountCommands::MentionList(args) => {
-            handle_mention_list(args, config_path, config).await
+            handle_mention_list(args, config_path, config, output).await
         }
         VideoAccountCommands::ContactList(args) => {
-            handle_contact_list(args, config_path, config).await
+            handle_contact_list(args, config_path, config, output).await
         }
         VideoAccountCommands::PostPrivateLetter(args) => {
-            handle_post_private_letter(args, config_path, config).await
+            handle_post_private_letter(args, config_path, config, output).await
         }
         VideoAccountCommands::PostPrivateLetterImg(args) => {
-            handle_post_private_letter_img(args, config_path, config).await
+            handle_post_private_letter_img(args, config_path, config, output).await
         }
         VideoAccountCommands::SyncPrivateLetterMsg(args) => {
-            handle_sync_private_letter_msg(args, config_path, config).await
+            handle_sync_private_letter_msg(args, config_path, config, output).await
         }
         VideoAccountCommands::CreateFinder(args) => {
-            handle_create_finder(args, config_path, config).await
+            handle_create_finder(args, config_path, config, output).await
         }
         VideoAccountCommands::UpdateFinderProfile(args) => {
-            handle_update_finder_profile(args, config_path, config).await
+            handle_update_finder_profile(args, config_path, config, output).await
         }
         VideoAccountCommands::GetFinderProfile(args) => {
-            handle_get_finder_profile(args, config_path, config).await
+            handle_get_finder_profile(args, config_path, config, output).await
         }
         VideoAccountCommands::GetFinderQrCode(args) => {
-            handle_get_finder_qr_code(args, config_path, config).await
+            handle_get_finder_qr_code(args, config_path, config, output).await
+        }
+        VideoAccountCommands::UserPage(args) => {
+            handle_user_page(args, config_path, config, output).await
         }
-        VideoAccountCommands::UserPage(args) => handle_user_page(args, config_path, config).await,
     }
 }
 
@@ -731,6 +742,7 @@ async fn handle_upload_finder_video(
     args: UploadFinderVideoArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -740,7 +752,7 @@ async fn handle_upload_finder_video(
             cover_img_url: &args.cover_img_url,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -748,6 +760,7 @@ async fn handle_publish_finder_cdn(
     args: PublishFinderCdnArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let video_cdn = FinderVideoCdn {
@@ -768,7 +781,7 @@ async fn handle_publish_finder_cdn(
             video_cdn,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -776,6 +789,7 @@ async fn handle_publish_finder_web(
     args: PublishFinderWebArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -787,7 +801,7 @@ async fn handle_publish_finder_web(
             description: &args.description,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -795,6 +809,7 @@ async fn handle_send_finder_sns(
     args: SendFinderSnsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -819,6 +834,7 @@ async fn handle_send_finder_sns(
         })
         .await?;
     info!("send finder sns triggered");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -826,6 +842,7 @@ async fn handle_send_finder_msg(
     args: SendFinderMsgArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -848,6 +865,7 @@ async fn handle_send_finder_msg(
         })
         .await?;
     info!("finder message sent");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -855,6 +873,7 @@ async fn handle_follow_finder(
     args: FollowFinderArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let search_info = if args.search_cookies.is_some()
@@ -879,7 +898,7 @@ async fn handle_follow_finder(
             search_info,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -887,6 +906,7 @@ async fn handle_follow_list(
     args: FollowListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -897,7 +917,7 @@ async fn handle_follow_list(
             last_buffer: args.last_buffer.as_deref(),
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -905,6 +925,7 @@ async fn handle_search_follow(
     args: SearchFollowArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -917,6 +938,7 @@ async fn handle_search_follow(
         })
         .await?;
     info!("search follow finished");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -924,6 +946,7 @@ async fn handle_search_finder(
     args: SearchFinderArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -938,7 +961,7 @@ async fn handle_search_finder(
             offset: args.offset,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -946,6 +969,7 @@ async fn handle_scan_follow(
     args: ScanFollowArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -959,7 +983,7 @@ async fn handle_scan_follow(
             object_nonce_id: &args.object_nonce_id,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -967,6 +991,7 @@ async fn handle_scan_browse(
     args: ScanBrowseArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -979,6 +1004,7 @@ async fn handle_scan_browse(
         })
         .await?;
     info!("scan browse done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -986,6 +1012,7 @@ async fn handle_scan_like(
     args: ScanLikeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -998,6 +1025,7 @@ async fn handle_scan_like(
         })
         .await?;
     info!("scan like done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1005,6 +1033,7 @@ async fn handle_scan_fav(
     args: ScanFavArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -1017,6 +1046,7 @@ async fn handle_scan_fav(
         })
         .await?;
     info!("scan fav done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1024,6 +1054,7 @@ async fn handle_scan_comment(
     args: ScanCommentArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1039,7 +1070,7 @@ async fn handle_scan_comment(
             root_comment_id: args.root_comment_id,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1047,6 +1078,7 @@ async fn handle_scan_qr_code(
     args: ScanQrCodeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1057,7 +1089,7 @@ async fn handle_scan_qr_code(
             qr_content: &args.qr_content,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1065,6 +1097,7 @@ async fn handle_scan_login_channels(
     args: ScanLoginChannelsArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1073,11 +1106,16 @@ async fn handle_scan_login_channels(
             qr_content: &args.qr_content,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
-async fn handle_id_fav(args: IdFavArgs, _config_path: &Path, config: &mut CliConfig) -> Result<()> {
+async fn handle_id_fav(
+    args: IdFavArgs,
+    _config_path: &Path,
+    config: &mut CliConfig,
+    output: OutputFormat,
+) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
         .id_fav(IdFavRequest {
@@ -1092,6 +1130,7 @@ async fn handle_id_fav(args: IdFavArgs, _config_path: &Path, config: &mut CliCon
         })
         .await?;
     info!("id fav done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1099,6 +1138,7 @@ async fn handle_id_like(
     args: IdLikeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -1114,6 +1154,7 @@ async fn handle_id_like(
         })
         .await?;
     info!("id like done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1121,6 +1162,7 @@ async fn handle_finder_opt(
     args: FinderOptArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -1135,6 +1177,7 @@ async fn handle_finder_opt(
         })
         .await?;
     info!("finder opt done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1142,6 +1185,7 @@ async fn handle_browse_finder(
     args: BrowseFinderArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -1155,6 +1199,7 @@ async fn handle_browse_finder(
         })
         .await?;
     info!("browse finder done");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1162,6 +1207,7 @@ async fn handle_like_fav_list(
     args: LikeFavListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1173,7 +1219,7 @@ async fn handle_like_fav_list(
             flag: args.flag,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1181,6 +1227,7 @@ async fn handle_comment_finder(
     args: CommentFinderArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1200,7 +1247,7 @@ async fn handle_comment_finder(
             root_comment_id: args.root_comment_id,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1208,6 +1255,7 @@ async fn handle_comment_list(
     args: CommentListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1221,7 +1269,7 @@ async fn handle_comment_list(
             root_comment_id: args.root_comment_id,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1229,6 +1277,7 @@ async fn handle_mention_list(
     args: MentionListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1240,7 +1289,7 @@ async fn handle_mention_list(
             last_buff: &args.last_buff,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1248,6 +1297,7 @@ async fn handle_contact_list(
     args: ContactListArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1258,7 +1308,7 @@ async fn handle_contact_list(
             query_info: &args.query_info,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1266,6 +1316,7 @@ async fn handle_post_private_letter(
     args: PostPrivateLetterArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1277,7 +1328,7 @@ async fn handle_post_private_letter(
             msg_session_id: &args.msg_session_id,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1285,6 +1336,7 @@ async fn handle_post_private_letter_img(
     args: PostPrivateLetterImgArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1296,7 +1348,7 @@ async fn handle_post_private_letter_img(
             img_url: &args.img_url,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1304,6 +1356,7 @@ async fn handle_sync_private_letter_msg(
     args: SyncPrivateLetterMsgArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1312,7 +1365,7 @@ async fn handle_sync_private_letter_msg(
             key_buff: args.key_buff.as_deref(),
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1320,6 +1373,7 @@ async fn handle_create_finder(
     args: CreateFinderArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp = client
@@ -1331,7 +1385,7 @@ async fn handle_create_finder(
             sex: args.sex,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1339,6 +1393,7 @@ async fn handle_update_finder_profile(
     args: UpdateFinderProfileArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     client
@@ -1356,6 +1411,7 @@ async fn handle_update_finder_profile(
         })
         .await?;
     info!("finder profile updated");
+    output.print_ok()?;
     Ok(())
 }
 
@@ -1363,12 +1419,13 @@ async fn handle_get_finder_profile(
     args: GetFinderProfileArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp: FinderProfileInfo = client
         .get_finder_profile(GetFinderProfileRequest { app_id: &app_id })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
 
@@ -1376,6 +1433,7 @@ async fn handle_get_finder_qr_code(
     args: GetFinderQrCodeArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let resp: GetFinderQrCodeResponse = client
@@ -1385,7 +1443,7 @@ async fn handle_get_finder_qr_code(
             my_role_type: args.my_role_type,
         })
         .await?;
-    println!("{}", resp.qrcode_url);
+    output.print_text(&resp, &resp.qrcode_url)?;
     Ok(())
 }
 
@@ -1393,6 +1451,7 @@ async fn handle_user_page(
     args: UserPageArgs,
     _config_path: &Path,
     config: &mut CliConfig,
+    output: OutputFormat,
 ) -> Result<()> {
     let (client, app_id) = resolve_client(&args.base, config).await?;
     let search_info = if args.search_cookies.is_some() || args.search_id.is_some() {
@@ -1413,6 +1472,6 @@ async fn handle_user_page(
             search_info,
         })
         .await?;
-    println!("{}", to_string_pretty(&resp)?);
+    output.print(&resp)?;
     Ok(())
 }
@@ -488,7 +488,10 @@ async fn run_as_primary(
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
                 .as_secs();
-            if broadcast_tx_heartbeat.send(BroadcastMessage::Heartbeat { timestamp }).is_err() {
+            if broadcast_tx_heartbeat
+                .send(BroadcastMessage::Heartbeat { timestamp })
+                .is_err()
+            {
                 debug!("心跳发送失败，可能没有订阅者");
             }
         }
@@ -2,13 +2,16 @@
 //!
 //! 提供 `serve-webhook` 命令，启动 HTTP 服务器接收 Gewe 平台推送的消息事件。
 
-use crate::config::CliConfig;
+use crate::config::{chatroom_names, CliConfig};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use clap::Args;
+use clap::{Args, ValueEnum};
 use gewe_core::{AppId, BotContext};
+use gewe_http::ChatroomNames;
 use gewe_session::{InMemorySessionStore, SessionStore};
+use gewe_webhook::normalize::{ChatKind, MessageKind, NormalizedEvent};
 use gewe_webhook::{router_with_channel_and_state, WebhookBuilderOptions, WebhookEvent};
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::OpenOptions;
@@ -31,6 +34,10 @@ pub struct ServeWebhookArgs {
     #[arg(long, default_value = "true")]
     pub print: bool,
 
+    /// 控制台输出格式：pretty 为可读的单行摘要，json 每行输出一个原始事件
+    #[arg(long, value_enum, default_value_t = EventFormat::Pretty)]
+    pub format: EventFormat,
+
     /// 保存事件到文件（JSONL 格式）
     #[arg(long, short = 'o')]
     pub output_file: Option<PathBuf>,
@@ -48,6 +55,16 @@ pub struct ServeWebhookArgs {
     pub require_signature: bool,
 }
 
+/// 控制台事件输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum EventFormat {
+    /// 时间、类型图标、会话、发送者与内容摘要，终端中带颜色
+    #[default]
+    Pretty,
+    /// 原始回调（Appid、TypeName、Data），每行一个 JSON
+    Json,
+}
+
 /// 输出处理器 trait
 #[async_trait]
 pub trait OutputHandler: Send + Sync {
@@ -55,27 +72,161 @@ pub trait OutputHandler: Send + Sync {
 }
 
 /// 控制台输出处理器
-pub struct ConsoleOutput;
+pub struct ConsoleOutput {
+    format: EventFormat,
+    /// 群名别名，来自配置的 chatroom_aliases
+    names: ChatroomNames,
+    /// 是否输出 ANSI 颜色：标准输出为终端且未设置 NO_COLOR
+    color: bool,
+}
 
 impl ConsoleOutput {
-    pub fn new() -> Self {
-        Self
+    pub fn new(format: EventFormat, names: ChatroomNames) -> Self {
+        Self {
+            format,
+            names,
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
     }
 }
 
 #[async_trait]
 impl OutputHandler for ConsoleOutput {
     async fn handle(&self, event: &WebhookEvent) -> Result<()> {
-        let json = serde_json::to_string_pretty(&serde_json::json!({
-            "Appid": event.app_id.0,
-            "TypeName": event.type_name,
-            "Data": event.data,
-        }))?;
-        println!("{}", json);
+        let raw = || {
+            serde_json::to_string(&serde_json::json!({
+                "Appid": event.app_id.0,
+                "TypeName": event.type_name,
+                "Data": event.data,
+            }))
+        };
+        let line = match self.format {
+            EventFormat::Json => raw()?,
+            // 与 gewe-bot-app 的规则匹配使用同一份规范化结果；无法解析的事件原样输出
+            EventFormat::Pretty => match event.normalize() {
+                Ok(norm) => render_event(
+                    &norm,
+                    &self.names,
+                    self.color,
+                    &chrono::Local::now().format("%H:%M:%S").to_string(),
+                ),
+                Err(_) => raw()?,
+            },
+        };
+        println!("{}", line);
         Ok(())
     }
 }
 
+/// 单行事件摘要：`时间 图标 [会话] 发送者: 内容`，非消息事件显示 TypeName
+fn render_event(norm: &NormalizedEvent, names: &ChatroomNames, color: bool, time: &str) -> String {
+    let paint = |text: &str, code: &str| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    };
+    let type_name = norm.type_name.as_deref().unwrap_or("-");
+    let mut line = format!(
+        "{} {} {}",
+        paint(time, "2"),
+        kind_icon(norm.kind),
+        paint(&norm.app_id.0, "34")
+    );
+    let Some(from) = norm.from_wxid.as_deref() else {
+        line.push_str(&format!(" {}", paint(type_name, "35")));
+        return line;
+    };
+    let chat = match norm.chat {
+        Some(ChatKind::Group) => names.label(from),
+        _ => "私聊".to_string(),
+    };
+    line.push_str(&format!(" [{}]", paint(&chat, "32")));
+    if norm.kind == MessageKind::ContactEvent {
+        line.push_str(&format!(" {} {}", paint(type_name, "35"), from));
+        return line;
+    }
+    let sender = norm.sender_wxid().unwrap_or("-");
+    let sender = match norm.nickname.as_deref() {
+        Some(nick) if !nick.is_empty() => format!("{}({})", nick, sender),
+        _ => sender.to_string(),
+    };
+    line.push_str(&format!(
+        " {}: {}",
+        paint(&sender, "33"),
+        paint(&describe_content(norm), "36")
+    ));
+    line
+}
+
+fn kind_icon(kind: MessageKind) -> &'static str {
+    match kind {
+        MessageKind::Text => "💬",
+        MessageKind::Image => "🖼️",
+        MessageKind::Voice => "🎤",
+        MessageKind::Video => "🎬",
+        MessageKind::Emoji => "😀",
+        MessageKind::Link => "🔗",
+        MessageKind::FileNotice => "📎",
+        MessageKind::Location => "📍",
+        MessageKind::RedPacket => "🧧",
+        MessageKind::Transfer => "💰",
+        MessageKind::NameCard => "📇",
+        MessageKind::ContactEvent => "👥",
+        MessageKind::MemberJoined => "🙋",
+        MessageKind::MemberLeft => "👋",
+        MessageKind::Other => "📨",
+    }
+}
+
+/// 内容摘要：规范化内容，媒体附带格式、大小、链接等信息
+fn describe_content(norm: &NormalizedEvent) -> String {
+    let mut text = norm
+        .normalized_content
+        .clone()
+        .unwrap_or_else(|| "[unknown]".to_string());
+    let mut details = Vec::new();
+    match norm.kind {
+        MessageKind::FileNotice | MessageKind::Image | MessageKind::Video | MessageKind::Voice => {
+            if let Some(ext) = norm.file_ext.as_deref() {
+                details.push(ext.to_string());
+            }
+            if let Some(size) = norm.file_size {
+                details.push(format_size(size));
+            }
+        }
+        MessageKind::Emoji if norm.emoji_animated == Some(true) => {
+            details.push("动图".to_string());
+        }
+        MessageKind::Link => {
+            if let Some(url) = norm.urls.first() {
+                details.push(url.clone());
+            }
+        }
+        _ => {}
+    }
+    if !details.is_empty() {
+        text.push_str(&format!(" ({})", details.join(", ")));
+    }
+    text
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 /// 文件输出处理器（JSONL 格式）
 pub struct FileOutput {
     path: PathBuf,
@@ -206,7 +357,10 @@ pub async fn handle_serve_webhook(
     let mut outputs: Vec<Box<dyn OutputHandler>> = Vec::new();
 
     if args.print {
-        outputs.push(Box::new(ConsoleOutput::new()));
+        outputs.push(Box::new(ConsoleOutput::new(
+            args.format,
+            chatroom_names(config),
+        )));
     }
 
     if let Some(ref path) = args.output_file {
@@ -221,7 +375,10 @@ pub async fn handle_serve_webhook(
     }
 
     if outputs.is_empty() {
-        outputs.push(Box::new(ConsoleOutput::new()));
+        outputs.push(Box::new(ConsoleOutput::new(
+            args.format,
+            chatroom_names(config),
+        )));
     }
 
     let processor = Arc::new(EventProcessor::new(outputs));
@@ -287,3 +444,73 @@ pub async fn handle_serve_webhook(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn add_msg(from: &str, msg_type: i64, content: &str, push: Option<&str>) -> WebhookEvent {
+        let mut data = json!({
+            "MsgType": msg_type,
+            "FromUserName": {"string": from},
+            "ToUserName": {"string": "wxid_bot"},
+            "Content": {"string": content},
+            "NewMsgId": 1,
+        });
+        if let Some(push) = push {
+            data["PushContent"] = json!(push);
+        }
+        WebhookEvent {
+            app_id: AppId("wx_app".to_string()),
+            type_name: Some("AddMsg".to_string()),
+            data,
+        }
+    }
+
+    #[test]
+    fn test_render_group_text_with_alias() {
+        let names = ChatroomNames::default();
+        names.set_aliases([("123@chatroom", "技术群")]);
+        let event = add_msg(
+            "123@chatroom",
+            1,
+            "wxid_alice:\n大家好",
+            Some("Alice : 大家好"),
+        );
+        let norm = event.normalize().unwrap();
+        assert_eq!(
+            render_event(&norm, &names, false, "12:00:00"),
+            "12:00:00 💬 wx_app [技术群 (123@chatroom)] Alice(wxid_alice): 大家好"
+        );
+        let colored = render_event(&norm, &names, true, "12:00:00");
+        assert!(colored.contains("\x1b[33mAlice(wxid_alice)\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_private_and_non_message_events() {
+        let names = ChatroomNames::default();
+        let norm = add_msg("wxid_bob", 3, "<msg><img length=\"2048\"/></msg>", None)
+            .normalize()
+            .unwrap();
+        assert_eq!(
+            render_event(&norm, &names, false, "12:00:00"),
+            "12:00:00 🖼️ wx_app [私聊] wxid_bob: [图片] (2.0 KB)"
+        );
+
+        let offline = WebhookEvent {
+            app_id: AppId("wx_app".to_string()),
+            type_name: Some("Offline".to_string()),
+            data: json!({}),
+        };
+        let norm = offline.normalize().unwrap();
+        assert!(render_event(&norm, &names, false, "12:00:00").ends_with("wx_app Offline"));
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    }
+}
@@ -93,3 +93,53 @@ fn test_cli_config_flag() {
 
     assert!(output.status.success());
 }
+
+#[test]
+fn test_cli_output_flag() {
+    let output = Command::new("cargo")
+        .args(["run", "-p", "gewe-cli", "--", "--help"])
+        .output()
+        .expect("Failed to execute command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--output"));
+
+    let output = Command::new("cargo")
+        .args(["run", "-p", "gewe-cli", "--", "--output", "xml", "config"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid value"));
+}
+
+#[test]
+fn test_cli_send_text_to_file_flag() {
+    let output = Command::new("cargo")
+        .args(["run", "-p", "gewe-cli", "--", "send-text", "--help"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--to-file"));
+
+    // --to-file and --to-wxid are mutually exclusive
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "-p",
+            "gewe-cli",
+            "--",
+            "send-text",
+            "--to-wxid",
+            "wxid_a",
+            "--to-file",
+            "recipients.txt",
+            "--content",
+            "hi",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("cannot be used with"));
+}
@@ -58,6 +58,8 @@ pub enum GeweError {
     Decode(String),
     #[error("missing data")]
     MissingData,
+    #[error("unknown app: {0}")]
+    UnknownApp(String),
 }
 
 #[cfg(test)]
@@ -214,4 +216,10 @@ mod tests {
         let err = GeweError::MissingData;
         assert_eq!(err.to_string(), "missing data");
     }
+
+    #[test]
+    fn test_gewe_error_unknown_app() {
+        let err = GeweError::UnknownApp("wx_app".to_string());
+        assert_eq!(err.to_string(), "unknown app: wx_app");
+    }
 }
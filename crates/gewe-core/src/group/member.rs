@@ -100,6 +100,9 @@ pub struct GetChatroomMemberListResponse {
     pub chat_room_owner: String,
     #[serde(rename = "chatroomMembers")]
     pub chatroom_members: Vec<ChatroomMember>,
+    /// 群管理员 wxid，没有管理员时接口返回 null
+    #[serde(rename = "adminWxid", default)]
+    pub admin_wxid: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -6,6 +6,8 @@ pub mod login;
 pub mod message;
 pub mod moments;
 pub mod personal;
+#[cfg(feature = "qr")]
+pub mod qr;
 pub mod tag;
 pub mod video_account;
 
@@ -669,6 +671,288 @@ mod tests {
         assert!(json.contains("\"nameCardWxid\":\"wxid_card\""));
     }
 
+    // ===== message/appmsg.rs tests =====
+    #[test]
+    fn test_appmsg_link_builder() {
+        let xml = AppMsg::link("标题 & <副标题>", "https://example.com/a?x=1&y=2")
+            .des("描述")
+            .thumb("https://example.com/thumb.jpg")
+            .to_xml();
+        assert!(xml.starts_with(r#"<appmsg appid="" sdkver="0">"#));
+        assert!(xml.contains("<title>标题 &amp; &lt;副标题&gt;</title>"));
+        assert!(xml.contains("<type>5</type>"));
+        assert!(xml.contains("<url>https://example.com/a?x=1&amp;y=2</url>"));
+        assert!(xml.contains("<dataurl />"));
+
+        let parsed = AppMsg::from_xml(&xml).unwrap();
+        assert_eq!(parsed.title, "标题 & <副标题>");
+        assert_eq!(parsed.url, "https://example.com/a?x=1&y=2");
+        assert_eq!(parsed.thumb_url, "https://example.com/thumb.jpg");
+    }
+
+    #[test]
+    fn test_appmsg_round_trip_builders() {
+        for msg in [
+            AppMsg::music(
+                "晴天",
+                "周杰伦",
+                "https://y.qq.com/n/ryqq/songDetail/0039MnYb0qxYhV",
+                "https://example.com/qingtian.m4a",
+            )
+            .thumb("https://example.com/album.jpg"),
+            AppMsg::file("Report.PDF", 20480, "@cdn_3057020100_1"),
+            AppMsg::link("t", "https://example.com").app_id("wx6618f1cfc6c132f8"),
+        ] {
+            assert_eq!(AppMsg::from_xml(&msg.to_xml()), Some(msg));
+        }
+        assert_eq!(
+            AppMsg::file("Report.PDF", 1, "id").file_ext,
+            "pdf".to_string()
+        );
+    }
+
+    #[test]
+    fn test_appmsg_parse_captured_payloads() {
+        let music = r#"<?xml version="1.0"?>
+<msg>
+	<appmsg appid="wx5aa333606550dfd5" sdkver="0">
+		<title>晴天</title>
+		<des>周杰伦</des>
+		<action>view</action>
+		<type>3</type>
+		<showtype>0</showtype>
+		<content />
+		<url>https://i.y.qq.com/v8/playsong.html?songmid=0039MnYb0qxYhV&amp;type=0</url>
+		<dataurl>http://isure.stream.qqmusic.qq.com/C400.m4a?fromtag=46</dataurl>
+		<lowurl />
+		<lowdataurl />
+		<appattach>
+			<totallen>0</totallen>
+			<attachid />
+			<emoticonmd5 />
+			<fileext />
+		</appattach>
+		<thumburl><![CDATA[https://y.gtimg.cn/music/photo_new/T002R150x150M000.jpg]]></thumburl>
+	</appmsg>
+	<fromusername>wxid_sender</fromusername>
+</msg>"#;
+        let parsed = AppMsg::from_xml(music).unwrap();
+        assert_eq!(parsed.msg_type, AppMsgType::Music);
+        assert_eq!(parsed.app_id, "wx5aa333606550dfd5");
+        assert_eq!(parsed.des, "周杰伦");
+        assert_eq!(
+            parsed.url,
+            "https://i.y.qq.com/v8/playsong.html?songmid=0039MnYb0qxYhV&type=0"
+        );
+        assert_eq!(
+            parsed.thumb_url,
+            "https://y.gtimg.cn/music/photo_new/T002R150x150M000.jpg"
+        );
+
+        let file = r#"<msg><appmsg appid="" sdkver="0"><title>季度报告.xlsx</title><des></des><action></action><type>6</type><showtype>0</showtype><content></content><url></url><appattach><totallen>18349</totallen><attachid>@cdn_3057020100044b_1</attachid><fileext>xlsx</fileext><cdnattachurl>3057020100044b</cdnattachurl><aeskey>0a1b2c</aeskey></appattach><md5>f1e2d3</md5></appmsg><fromusername>wxid_sender</fromusername></msg>"#;
+        let parsed = AppMsg::from_xml(file).unwrap();
+        assert_eq!(parsed.msg_type, AppMsgType::File);
+        assert_eq!(parsed.total_len, 18349);
+        assert_eq!(parsed.attach_id, "@cdn_3057020100044b_1");
+        assert_eq!(parsed.file_ext, "xlsx");
+        // 重新序列化后语义不变
+        assert_eq!(AppMsg::from_xml(&parsed.to_xml()), Some(parsed));
+
+        // 不支持的类型
+        assert!(AppMsg::from_xml("<appmsg><type>57</type></appmsg>").is_none());
+    }
+
+    // ===== message/mini_app.rs tests =====
+    #[test]
+    fn test_mini_app_card_from_preset() {
+        let card = MiniAppCard::from_preset(&MiniAppPreset::TENCENT_DOCS)
+            .page_path("/pages/detail/detail?id=1")
+            .cover_img_url("https://example.com/cover.jpg")
+            .title("周报")
+            .build()
+            .unwrap();
+        assert_eq!(card.display_name, "腾讯文档");
+        assert_eq!(card.page_path, "pages/detail/detail.html?id=1");
+
+        let req = card.to_request("app123", "wxid456");
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains("\"miniAppId\":\"wxd45c635d754dbf59\""));
+        assert!(json.contains("\"userName\":\"gh_252c5f06840b@app\""));
+        assert_eq!(
+            MiniAppPreset::find("京东购物").map(|p| p.mini_app_id),
+            Some("wx91d27dbf599dff74")
+        );
+    }
+
+    #[test]
+    fn test_mini_app_card_validation() {
+        let base = || {
+            MiniAppCard::builder()
+                .mini_app_id("wx1234567890")
+                .user_name("gh_abcdef")
+                .page_path("pages/index/index.html")
+                .cover_img_url("https://example.com/cover.jpg")
+                .title("标题")
+        };
+        let card = base().build().unwrap();
+        assert_eq!(card.user_name, "gh_abcdef@app");
+        assert_eq!(card.display_name, "标题");
+
+        assert_eq!(
+            base().cover_img_url("").build(),
+            Err(MiniAppCardError::MissingField("coverImgUrl"))
+        );
+        assert!(matches!(
+            base().cover_img_url("/tmp/cover.jpg").build(),
+            Err(MiniAppCardError::InvalidCoverImgUrl(_))
+        ));
+        assert!(matches!(
+            base().page_path("pages/ index").build(),
+            Err(MiniAppCardError::InvalidPagePath(_))
+        ));
+        assert!(matches!(
+            base().mini_app_id("miniapp123").build(),
+            Err(MiniAppCardError::InvalidMiniAppId(_))
+        ));
+    }
+
+    #[test]
+    fn test_rewrite_mini_app_cover() {
+        let xml = "<appmsg><type>33</type><thumburl>https://old/cover.jpg</thumburl><appattach><cdnthumburl>3057abc</cdnthumburl><cdnthumbaeskey>key</cdnthumbaeskey><cdnthumblength>100</cdnthumblength></appattach></appmsg>";
+        let out = rewrite_mini_app_cover(xml, "https://new/cover.jpg?a=1&b=2");
+        assert!(out.contains("<thumburl>https://new/cover.jpg?a=1&amp;b=2</thumburl>"));
+        assert!(!out.contains("cdnthumb"));
+        assert!(out.contains("<appattach></appattach>"));
+    }
+
+    // ===== message/xml.rs tests =====
+    #[test]
+    fn test_xml_prefix_entities_and_syntax_errors() {
+        let parsed = AppMsgContent::parse(
+            "wxid_a:\n<?xml version=\"1.0\"?><!-- c --><msg><appmsg appid='wx &amp; 1'>\
+             <title> x &lt; y&#33;&#x4e2d; </title><des><![CDATA[<raw>]]></des><type>5</type>\
+             <unknown><nested/></unknown></appmsg></msg>",
+        )
+        .unwrap();
+        assert_eq!(parsed.app_id.as_deref(), Some("wx & 1"));
+        assert_eq!(parsed.title.as_deref(), Some("x < y!中"));
+        assert_eq!(parsed.des.as_deref(), Some("<raw>"));
+
+        // 根元素本身即为 <appmsg> 时同样可以解析
+        let bare = AppMsgContent::parse("<appmsg><type>6</type></appmsg>").unwrap();
+        assert_eq!(bare.msg_type, 6);
+
+        for xml in [
+            "<msg><appmsg><type>5</b></appmsg></msg>",
+            "<msg><appmsg><type>5</type>",
+            "<msg><appmsg appid=1><type>5</type></appmsg></msg>",
+            "plain text",
+        ] {
+            assert!(
+                matches!(AppMsgContent::parse(xml), Err(XmlError::Syntax(_))),
+                "{xml}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_appmsg_content_link_file_and_mini_app() {
+        let link = "<msg><appmsg appid=\"wx6618f1cfc6c132f8\" sdkver=\"0\"><title>Rust 1.80 发布</title>\
+                    <des>新特性一览</des><type>5</type><url>https://example.com/a?x=1&amp;y=2</url>\
+                    <thumburl>https://example.com/t.jpg</thumburl></appmsg>\
+                    <sourceusername>gh_abc</sourceusername><sourcedisplayname>Rust 中文</sourcedisplayname></msg>";
+        let parsed = AppMsgContent::parse(link).unwrap();
+        assert_eq!(parsed.msg_type, 5);
+        assert_eq!(parsed.title.as_deref(), Some("Rust 1.80 发布"));
+        assert_eq!(parsed.url.as_deref(), Some("https://example.com/a?x=1&y=2"));
+        assert_eq!(parsed.app_id.as_deref(), Some("wx6618f1cfc6c132f8"));
+        assert_eq!(parsed.source_username.as_deref(), Some("gh_abc"));
+        assert_eq!(parsed.source_display_name.as_deref(), Some("Rust 中文"));
+        assert_eq!(parsed.attach, None);
+
+        let file = r#"<msg><appmsg appid="" sdkver="0"><title>季度报告.xlsx</title><des></des><type>6</type><appattach><totallen>18349</totallen><attachid>@cdn_1</attachid><fileext>xlsx</fileext></appattach></appmsg></msg>"#;
+        let parsed = AppMsgContent::parse(file).unwrap();
+        assert_eq!(parsed.des, None);
+        assert_eq!(parsed.app_id, None);
+        let attach = parsed.attach.unwrap();
+        assert_eq!(attach.total_len, 18349);
+        assert_eq!(attach.file_ext.as_deref(), Some("xlsx"));
+
+        let mini_app = r#"<msg><appmsg appid="" sdkver="0"><title>腾讯文档</title><type>33</type><sourcedisplayname>腾讯文档</sourcedisplayname><weappinfo><username><![CDATA[gh_252c5f06840b@app]]></username><appid><![CDATA[wxd45c635d754dbf59]]></appid><pagepath><![CDATA[pages/detail/detail.html?id=1]]></pagepath></weappinfo></appmsg></msg>"#;
+        let parsed = AppMsgContent::parse(mini_app).unwrap();
+        assert_eq!(parsed.mini_app_id(), Some("wxd45c635d754dbf59"));
+        let weapp = parsed.weapp.unwrap();
+        assert_eq!(weapp.username.as_deref(), Some("gh_252c5f06840b@app"));
+        assert_eq!(
+            weapp.page_path.as_deref(),
+            Some("pages/detail/detail.html?id=1")
+        );
+
+        assert_eq!(
+            AppMsgContent::parse("<msg><img/></msg>"),
+            Err(XmlError::MissingElement("appmsg"))
+        );
+    }
+
+    #[test]
+    fn test_quoted_message() {
+        let text = "wxid_bob:\n<?xml version=\"1.0\"?><msg><appmsg appid=\"\" sdkver=\"0\">\
+                    <title>好的，几点？</title><type>57</type><refermsg><type>1</type>\
+                    <svrid>7204358102739517000</svrid><fromusr>wxid_alice</fromusr>\
+                    <chatusr>123@chatroom</chatusr><displayname>Alice</displayname>\
+                    <content>明天开会</content><createtime>1700000000</createtime></refermsg>\
+                    </appmsg></msg>";
+        let quoted = QuotedMessage::parse(text).unwrap();
+        assert_eq!(quoted.reply, "好的，几点？");
+        assert_eq!(quoted.quoted_text(), Some("明天开会"));
+        assert_eq!(quoted.refer.new_msg_id, Some(7204358102739517000));
+        assert_eq!(quoted.refer.from_wxid.as_deref(), Some("wxid_alice"));
+        assert_eq!(quoted.refer.chat_wxid.as_deref(), Some("123@chatroom"));
+        assert_eq!(quoted.refer.display_name.as_deref(), Some("Alice"));
+        assert_eq!(quoted.refer.create_time, Some(1700000000));
+        assert!(quoted.quoted_appmsg().is_none());
+
+        // A quoted link card carries its XML escaped inside <content>.
+        let link = "<msg><appmsg><title>看这个</title><type>57</type><refermsg><type>49</type>\
+                    <content>&lt;msg&gt;&lt;appmsg&gt;&lt;title&gt;文章&lt;/title&gt;&lt;type&gt;5&lt;/type&gt;\
+                    &lt;url&gt;https://example.com&lt;/url&gt;&lt;/appmsg&gt;&lt;/msg&gt;</content>\
+                    </refermsg></appmsg></msg>";
+        let quoted = QuotedMessage::parse(link).unwrap();
+        assert_eq!(quoted.quoted_text(), None);
+        let inner = quoted.quoted_appmsg().unwrap();
+        assert_eq!(inner.title.as_deref(), Some("文章"));
+        assert_eq!(inner.url.as_deref(), Some("https://example.com"));
+
+        assert_eq!(
+            QuotedMessage::parse("<msg><appmsg><type>5</type></appmsg></msg>"),
+            Err(XmlError::UnexpectedType(5))
+        );
+        assert_eq!(
+            QuotedMessage::parse("<msg><appmsg><type>57</type></appmsg></msg>"),
+            Err(XmlError::MissingElement("refermsg"))
+        );
+    }
+
+    #[test]
+    fn test_emoji_info() {
+        let xml = r#"<msg><emoji fromusername="wxid_a" tousername="wxid_b" type="2" md5="0B3C5E2E7A1D4F5A9C8B7A6D5E4F3A2B" len="53213" productid="" cdnurl="http://wxapp.tc.qq.com/262/20304/stodownload?m=0b3c&amp;filekey=30340201" width="240" height="240" /></msg>"#;
+        let emoji = EmojiInfo::parse(xml).unwrap();
+        assert_eq!(emoji.md5, "0b3c5e2e7a1d4f5a9c8b7a6d5e4f3a2b");
+        assert_eq!(emoji.len, 53213);
+        assert!(emoji.animated);
+        assert_eq!(
+            emoji.cdn_url.as_deref(),
+            Some("http://wxapp.tc.qq.com/262/20304/stodownload?m=0b3c&filekey=30340201")
+        );
+        assert_eq!((emoji.width, emoji.height), (Some(240), Some(240)));
+        assert_eq!(emoji.product_id, None);
+
+        assert_eq!(
+            EmojiInfo::parse(r#"<msg><emoji type="1"/></msg>"#),
+            Err(XmlError::MissingElement("emoji md5"))
+        );
+    }
+
     // ===== message/download.rs tests =====
     #[test]
     fn test_download_image_request_serialize() {
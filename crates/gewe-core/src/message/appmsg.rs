@@ -0,0 +1,232 @@
+/// appmsg 类型（`<type>`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppMsgType {
+    /// 音乐卡片
+    Music,
+    /// 链接卡片
+    Link,
+    /// 文件卡片
+    File,
+}
+
+impl AppMsgType {
+    pub fn code(self) -> i32 {
+        match self {
+            AppMsgType::Music => 3,
+            AppMsgType::Link => 5,
+            AppMsgType::File => 6,
+        }
+    }
+
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            3 => Some(AppMsgType::Music),
+            5 => Some(AppMsgType::Link),
+            6 => Some(AppMsgType::File),
+            _ => None,
+        }
+    }
+}
+
+/// 类型化的 `<appmsg>` 构造器，序列化结果可直接传给 `send_app_msg`
+///
+/// ```
+/// use gewe_core::AppMsg;
+///
+/// let xml = AppMsg::link("标题", "https://example.com")
+///     .des("描述")
+///     .thumb("https://example.com/thumb.jpg")
+///     .to_xml();
+/// assert!(xml.contains("<type>5</type>"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppMsg {
+    pub msg_type: AppMsgType,
+    pub title: String,
+    pub des: String,
+    /// 点击跳转地址（音乐卡片为歌曲页面）
+    pub url: String,
+    /// 音乐播放地址
+    pub data_url: String,
+    pub thumb_url: String,
+    /// 文件大小（字节）
+    pub total_len: u64,
+    pub attach_id: String,
+    pub file_ext: String,
+    /// 来源小程序/应用 appid，一般留空
+    pub app_id: String,
+}
+
+impl AppMsg {
+    fn new(msg_type: AppMsgType, title: impl Into<String>) -> Self {
+        Self {
+            msg_type,
+            title: title.into(),
+            des: String::new(),
+            url: String::new(),
+            data_url: String::new(),
+            thumb_url: String::new(),
+            total_len: 0,
+            attach_id: String::new(),
+            file_ext: String::new(),
+            app_id: String::new(),
+        }
+    }
+
+    /// 链接卡片（type=5）
+    pub fn link(title: impl Into<String>, url: impl Into<String>) -> Self {
+        let mut msg = Self::new(AppMsgType::Link, title);
+        msg.url = url.into();
+        msg
+    }
+
+    /// 音乐卡片（type=3）：`page_url` 为点击跳转页，`data_url` 为音频地址
+    pub fn music(
+        title: impl Into<String>,
+        singer: impl Into<String>,
+        page_url: impl Into<String>,
+        data_url: impl Into<String>,
+    ) -> Self {
+        let mut msg = Self::new(AppMsgType::Music, title);
+        msg.des = singer.into();
+        msg.url = page_url.into();
+        msg.data_url = data_url.into();
+        msg
+    }
+
+    /// 文件卡片（type=6）：引用已上传到 CDN 的附件
+    pub fn file(
+        file_name: impl Into<String>,
+        total_len: u64,
+        attach_id: impl Into<String>,
+    ) -> Self {
+        let file_name = file_name.into();
+        let file_ext = file_name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+        let mut msg = Self::new(AppMsgType::File, file_name);
+        msg.total_len = total_len;
+        msg.attach_id = attach_id.into();
+        msg.file_ext = file_ext;
+        msg
+    }
+
+    pub fn des(mut self, des: impl Into<String>) -> Self {
+        self.des = des.into();
+        self
+    }
+
+    pub fn thumb(mut self, thumb_url: impl Into<String>) -> Self {
+        self.thumb_url = thumb_url.into();
+        self
+    }
+
+    pub fn file_ext(mut self, ext: impl Into<String>) -> Self {
+        self.file_ext = ext.into();
+        self
+    }
+
+    pub fn app_id(mut self, app_id: impl Into<String>) -> Self {
+        self.app_id = app_id.into();
+        self
+    }
+
+    /// 序列化为 `<appmsg>` XML
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str(&format!(
+            r#"<appmsg appid="{}" sdkver="0">"#,
+            escape_xml(&self.app_id)
+        ));
+        push_element(&mut xml, "title", &self.title);
+        push_element(&mut xml, "des", &self.des);
+        push_element(&mut xml, "action", "view");
+        push_element(&mut xml, "type", &self.msg_type.code().to_string());
+        push_element(&mut xml, "showtype", "0");
+        push_element(&mut xml, "url", &self.url);
+        push_element(&mut xml, "dataurl", &self.data_url);
+        push_element(&mut xml, "lowurl", &self.url);
+        push_element(&mut xml, "lowdataurl", &self.data_url);
+        push_element(&mut xml, "thumburl", &self.thumb_url);
+        xml.push_str("<appattach>");
+        push_element(&mut xml, "totallen", &self.total_len.to_string());
+        push_element(&mut xml, "attachid", &self.attach_id);
+        push_element(&mut xml, "fileext", &self.file_ext);
+        xml.push_str("</appattach>");
+        xml.push_str("</appmsg>");
+        xml
+    }
+
+    /// 从收到的 `<msg><appmsg>...</appmsg></msg>` 或裸 `<appmsg>` 中解析；不支持的类型返回 None
+    pub fn from_xml(xml: &str) -> Option<Self> {
+        let start = xml.find("<appmsg")?;
+        let end = xml.rfind("</appmsg>")?;
+        let body = &xml[start..end];
+        let msg_type = AppMsgType::from_code(element(body, "type")?.parse().ok()?)?;
+        let attach = element_raw(body, "appattach").unwrap_or_default();
+        let app_id = body
+            .find(r#"appid=""#)
+            .map(|i| &body[i + 7..])
+            .and_then(|rest| rest.split_once('"'))
+            .map(|(v, _)| unescape_xml(v))
+            .unwrap_or_default();
+        Some(Self {
+            msg_type,
+            title: element(body, "title").unwrap_or_default(),
+            des: element(body, "des").unwrap_or_default(),
+            url: element(body, "url").unwrap_or_default(),
+            data_url: element(body, "dataurl").unwrap_or_default(),
+            thumb_url: element(body, "thumburl").unwrap_or_default(),
+            total_len: element(attach, "totallen")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default(),
+            attach_id: element(attach, "attachid").unwrap_or_default(),
+            file_ext: element(attach, "fileext").unwrap_or_default(),
+            app_id,
+        })
+    }
+}
+
+fn push_element(xml: &mut String, tag: &str, value: &str) {
+    if value.is_empty() {
+        xml.push_str(&format!("<{tag} />"));
+    } else {
+        xml.push_str(&format!("<{tag}>{}</{tag}>", escape_xml(value)));
+    }
+}
+
+/// 取第一个同名元素的原始内容（不含嵌套同名元素的情形）
+fn element_raw<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let start = xml.find(&open)? + open.len();
+    let len = xml[start..].find(&close)?;
+    Some(&xml[start..start + len])
+}
+
+fn element(xml: &str, tag: &str) -> Option<String> {
+    let raw = element_raw(xml, tag)?.trim();
+    let value = raw
+        .strip_prefix("<![CDATA[")
+        .and_then(|v| v.strip_suffix("]]>"))
+        .map(str::to_string)
+        .unwrap_or_else(|| unescape_xml(raw));
+    Some(value)
+}
+
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,250 @@
+use super::appmsg::escape_xml;
+use thiserror::Error;
+
+/// 小程序卡片校验错误
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MiniAppCardError {
+    #[error("missing field: {0}")]
+    MissingField(&'static str),
+    #[error("invalid miniAppId: {0}")]
+    InvalidMiniAppId(String),
+    #[error("invalid userName: {0}")]
+    InvalidUserName(String),
+    #[error("invalid pagePath: {0}")]
+    InvalidPagePath(String),
+    #[error("invalid coverImgUrl: {0}")]
+    InvalidCoverImgUrl(String),
+}
+
+/// 常用小程序的固定参数（appid / 原始 ID / 展示名 / 默认页面）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiniAppPreset {
+    pub mini_app_id: &'static str,
+    pub user_name: &'static str,
+    pub display_name: &'static str,
+    pub default_page_path: &'static str,
+}
+
+impl MiniAppPreset {
+    pub const TENCENT_DOCS: MiniAppPreset = MiniAppPreset {
+        mini_app_id: "wxd45c635d754dbf59",
+        user_name: "gh_252c5f06840b@app",
+        display_name: "腾讯文档",
+        default_page_path: "pages/index/index.html",
+    };
+    pub const JD: MiniAppPreset = MiniAppPreset {
+        mini_app_id: "wx91d27dbf599dff74",
+        user_name: "gh_45b306365c3d@app",
+        display_name: "京东购物",
+        default_page_path: "pages/index/index.html",
+    };
+    pub const PINDUODUO: MiniAppPreset = MiniAppPreset {
+        mini_app_id: "wx32540bd863b27570",
+        user_name: "gh_0e7477744313@app",
+        display_name: "拼多多",
+        default_page_path: "pages/index/index.html",
+    };
+
+    /// 内置预设列表
+    pub const ALL: &'static [MiniAppPreset] = &[Self::TENCENT_DOCS, Self::JD, Self::PINDUODUO];
+
+    /// 按 appid 或展示名查找内置预设
+    pub fn find(key: &str) -> Option<&'static MiniAppPreset> {
+        Self::ALL
+            .iter()
+            .find(|p| p.mini_app_id == key || p.display_name == key)
+    }
+}
+
+/// 经过校验的小程序卡片，可直接转换为 [`super::PostMiniAppRequest`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MiniAppCard {
+    pub mini_app_id: String,
+    pub user_name: String,
+    pub display_name: String,
+    pub page_path: String,
+    pub cover_img_url: String,
+    pub title: String,
+}
+
+impl MiniAppCard {
+    pub fn builder() -> MiniAppCardBuilder {
+        MiniAppCardBuilder::default()
+    }
+
+    /// 以预设填充 appid / userName / displayName / pagePath
+    pub fn from_preset(preset: &MiniAppPreset) -> MiniAppCardBuilder {
+        MiniAppCardBuilder::default()
+            .mini_app_id(preset.mini_app_id)
+            .user_name(preset.user_name)
+            .display_name(preset.display_name)
+            .page_path(preset.default_page_path)
+    }
+
+    pub fn to_request<'a>(
+        &'a self,
+        app_id: &'a str,
+        to_wxid: &'a str,
+    ) -> super::PostMiniAppRequest<'a> {
+        super::PostMiniAppRequest {
+            app_id,
+            to_wxid,
+            mini_app_id: &self.mini_app_id,
+            display_name: &self.display_name,
+            page_path: &self.page_path,
+            cover_img_url: &self.cover_img_url,
+            title: &self.title,
+            user_name: &self.user_name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MiniAppCardBuilder {
+    mini_app_id: Option<String>,
+    user_name: Option<String>,
+    display_name: Option<String>,
+    page_path: Option<String>,
+    cover_img_url: Option<String>,
+    title: Option<String>,
+}
+
+impl MiniAppCardBuilder {
+    pub fn mini_app_id(mut self, v: impl Into<String>) -> Self {
+        self.mini_app_id = Some(v.into());
+        self
+    }
+
+    pub fn user_name(mut self, v: impl Into<String>) -> Self {
+        self.user_name = Some(v.into());
+        self
+    }
+
+    pub fn display_name(mut self, v: impl Into<String>) -> Self {
+        self.display_name = Some(v.into());
+        self
+    }
+
+    pub fn page_path(mut self, v: impl Into<String>) -> Self {
+        self.page_path = Some(v.into());
+        self
+    }
+
+    pub fn cover_img_url(mut self, v: impl Into<String>) -> Self {
+        self.cover_img_url = Some(v.into());
+        self
+    }
+
+    pub fn title(mut self, v: impl Into<String>) -> Self {
+        self.title = Some(v.into());
+        self
+    }
+
+    /// 校验并生成卡片：
+    /// - miniAppId 以 `wx` 开头，userName 为 `gh_` 开头的原始 ID（缺省补 `@app`）
+    /// - pagePath 为相对路径，开头的 `/` 会被去掉，页面部分缺少 `.html` 后缀时自动补齐
+    /// - coverImgUrl 必须是 http(s) 地址，微信不会为小程序卡片自动生成封面
+    pub fn build(self) -> Result<MiniAppCard, MiniAppCardError> {
+        let mini_app_id = required(self.mini_app_id, "miniAppId")?;
+        if !mini_app_id.starts_with("wx") {
+            return Err(MiniAppCardError::InvalidMiniAppId(mini_app_id));
+        }
+
+        let mut user_name = required(self.user_name, "userName")?;
+        if !user_name.starts_with("gh_") {
+            return Err(MiniAppCardError::InvalidUserName(user_name));
+        }
+        if !user_name.ends_with("@app") {
+            user_name.push_str("@app");
+        }
+
+        let raw_path = required(self.page_path, "pagePath")?;
+        let page_path =
+            normalize_page_path(&raw_path).ok_or(MiniAppCardError::InvalidPagePath(raw_path))?;
+
+        let cover_img_url = required(self.cover_img_url, "coverImgUrl")?;
+        if !(cover_img_url.starts_with("http://") || cover_img_url.starts_with("https://")) {
+            return Err(MiniAppCardError::InvalidCoverImgUrl(cover_img_url));
+        }
+
+        let title = required(self.title, "title")?;
+        let display_name = self
+            .display_name
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| title.clone());
+
+        Ok(MiniAppCard {
+            mini_app_id,
+            user_name,
+            display_name,
+            page_path,
+            cover_img_url,
+            title,
+        })
+    }
+}
+
+fn required(v: Option<String>, field: &'static str) -> Result<String, MiniAppCardError> {
+    v.map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or(MiniAppCardError::MissingField(field))
+}
+
+fn normalize_page_path(raw: &str) -> Option<String> {
+    let path = raw.trim_start_matches('/');
+    let (page, query) = match path.split_once('?') {
+        Some((page, query)) => (page, Some(query)),
+        None => (path, None),
+    };
+    if page.is_empty() || page.contains(char::is_whitespace) || page.ends_with('/') {
+        return None;
+    }
+    let mut out = page.to_string();
+    if !out.ends_with(".html") {
+        out.push_str(".html");
+    }
+    if let Some(query) = query {
+        out.push('?');
+        out.push_str(query);
+    }
+    Some(out)
+}
+
+/// 转发小程序卡片前替换封面：改写 `<thumburl>`，并移除原聊天里的 CDN 缩略图字段，
+/// 避免目标会话仍引用旧封面
+pub fn rewrite_mini_app_cover(xml: &str, cover_img_url: &str) -> String {
+    let mut out = xml.to_string();
+    for tag in [
+        "cdnthumburl",
+        "cdnthumbmd5",
+        "cdnthumbaeskey",
+        "cdnthumblength",
+        "cdnthumbheight",
+        "cdnthumbwidth",
+    ] {
+        out = remove_element(&out, tag);
+    }
+    let open = "<thumburl>";
+    let close = "</thumburl>";
+    if let Some(start) = out.find(open) {
+        if let Some(len) = out[start + open.len()..].find(close) {
+            let end = start + open.len() + len;
+            out.replace_range(start + open.len()..end, &escape_xml(cover_img_url));
+        }
+    }
+    out
+}
+
+fn remove_element(xml: &str, tag: &str) -> String {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let mut out = xml.to_string();
+    while let Some(start) = out.find(&open) {
+        match out[start..].find(&close) {
+            Some(len) => out.replace_range(start..start + len + close.len(), ""),
+            None => break,
+        }
+    }
+    out
+}
@@ -1,9 +1,15 @@
+pub mod appmsg;
 pub mod download;
 pub mod forward;
+pub mod mini_app;
 pub mod revoke;
 pub mod send;
+pub mod xml;
 
+pub use appmsg::*;
 pub use download::*;
 pub use forward::*;
+pub use mini_app::*;
 pub use revoke::*;
 pub use send::*;
+pub use xml::*;
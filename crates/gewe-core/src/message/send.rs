@@ -23,6 +23,64 @@ pub struct SendTextResponse {
     pub msg_type: i32,
 }
 
+/// 发送成功后的回执，撤回消息需要其中的 msgId、newMsgId 与 createTime
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendReceipt {
+    pub to_wxid: String,
+    pub msg_id: i64,
+    pub new_msg_id: i64,
+    pub create_time: i64,
+}
+
+/// 发送与转发类接口的响应，均可取出 [`SendReceipt`]
+pub trait SendResponse {
+    fn receipt(&self) -> SendReceipt;
+}
+
+macro_rules! impl_send_response {
+    ($($ty:ty),* $(,)?) => {
+        $(impl SendResponse for $ty {
+            fn receipt(&self) -> SendReceipt {
+                SendReceipt {
+                    to_wxid: self.to_wxid.clone(),
+                    msg_id: self.msg_id,
+                    new_msg_id: self.new_msg_id,
+                    create_time: self.create_time,
+                }
+            }
+        })*
+    };
+}
+
+/// `createTime` 可能缺失的响应，缺失时回执中为 0
+macro_rules! impl_send_response_optional_time {
+    ($($ty:ty),* $(,)?) => {
+        $(impl SendResponse for $ty {
+            fn receipt(&self) -> SendReceipt {
+                SendReceipt {
+                    to_wxid: self.to_wxid.clone(),
+                    msg_id: self.msg_id,
+                    new_msg_id: self.new_msg_id,
+                    create_time: self.create_time.unwrap_or_default(),
+                }
+            }
+        })*
+    };
+}
+
+impl_send_response!(
+    SendTextResponse,
+    PostImageResponse,
+    PostVoiceResponse,
+    PostEmojiResponse,
+);
+impl_send_response_optional_time!(
+    PostVideoResponse,
+    super::ForwardImageResponse,
+    super::ForwardVideoResponse,
+);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PostImageRequest<'a> {
@@ -209,3 +267,34 @@ pub struct PostNameCardRequest<'a> {
 }
 
 pub type PostNameCardResponse = SendTextResponse;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_receipt_from_responses() {
+        let text: SendTextResponse = serde_json::from_str(
+            r#"{"toWxid":"wxid_a","createTime":1700000000,"msgId":1,"newMsgId":2,"type":1}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            text.receipt(),
+            SendReceipt {
+                to_wxid: "wxid_a".to_string(),
+                msg_id: 1,
+                new_msg_id: 2,
+                create_time: 1700000000,
+            }
+        );
+
+        let video = PostVideoResponse {
+            to_wxid: "wxid_b".to_string(),
+            msg_id: 3,
+            new_msg_id: 4,
+            ..Default::default()
+        };
+        assert_eq!(video.receipt().create_time, 0);
+        assert_eq!(video.receipt().new_msg_id, 4);
+    }
+}
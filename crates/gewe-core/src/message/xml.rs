@@ -0,0 +1,345 @@
+//! 收到的 XML 消息内容解析：appmsg（链接、文件、小程序）、引用回复、表情
+//!
+//! 基于 quick-xml 的 serde 反序列化，只声明用到的元素与属性，其余内容忽略。
+//! 群聊消息内容带有 `wxid:\n` 前缀，解析时从第一个 `<` 开始读取。
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// XML 解析错误
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum XmlError {
+    #[error("invalid xml: {0}")]
+    Syntax(String),
+    #[error("missing element: {0}")]
+    MissingElement(&'static str),
+    #[error("unexpected appmsg type: {0}")]
+    UnexpectedType(i32),
+}
+
+impl From<quick_xml::DeError> for XmlError {
+    fn from(e: quick_xml::DeError) -> Self {
+        Self::Syntax(e.to_string())
+    }
+}
+
+/// 去掉根元素前的非 XML 前缀（如群聊的 `wxid:\n`）
+fn strip_prefix(xml: &str) -> Result<&str, XmlError> {
+    xml.find('<')
+        .map(|start| &xml[start..])
+        .ok_or_else(|| XmlError::Syntax("no element".to_string()))
+}
+
+/// 根元素名，跳过声明与注释
+fn root_name(xml: &str) -> Result<String, XmlError> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e)) => {
+                return Ok(String::from_utf8_lossy(e.name().as_ref()).into_owned())
+            }
+            Ok(Event::Eof) => return Err(XmlError::Syntax("no element".to_string())),
+            Ok(_) => {}
+            Err(e) => return Err(XmlError::Syntax(e.to_string())),
+        }
+    }
+}
+
+/// `<msg>` 外层结构；根元素本身就是 `<appmsg>` 或 `<emoji>` 时放入对应字段
+#[derive(Debug, Default, Deserialize)]
+struct RawMsg {
+    appmsg: Option<RawAppMsg>,
+    emoji: Option<RawEmoji>,
+    sourceusername: Option<String>,
+    sourcedisplayname: Option<String>,
+}
+
+impl RawMsg {
+    fn parse(xml: &str) -> Result<Self, XmlError> {
+        let xml = strip_prefix(xml)?;
+        Ok(match root_name(xml)?.as_str() {
+            "appmsg" => Self {
+                appmsg: Some(quick_xml::de::from_str(xml)?),
+                ..Default::default()
+            },
+            "emoji" => Self {
+                emoji: Some(quick_xml::de::from_str(xml)?),
+                ..Default::default()
+            },
+            _ => quick_xml::de::from_str(xml)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawAppMsg {
+    #[serde(rename = "@appid")]
+    appid: Option<String>,
+    #[serde(rename = "type")]
+    msg_type: Option<String>,
+    title: Option<String>,
+    des: Option<String>,
+    url: Option<String>,
+    thumburl: Option<String>,
+    sourceusername: Option<String>,
+    sourcedisplayname: Option<String>,
+    appattach: Option<RawAppAttach>,
+    weappinfo: Option<RawWeAppInfo>,
+    refermsg: Option<RawReferMsg>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawAppAttach {
+    totallen: Option<String>,
+    attachid: Option<String>,
+    fileext: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawWeAppInfo {
+    username: Option<String>,
+    appid: Option<String>,
+    pagepath: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawReferMsg {
+    #[serde(rename = "type")]
+    msg_type: Option<String>,
+    svrid: Option<String>,
+    fromusr: Option<String>,
+    chatusr: Option<String>,
+    displayname: Option<String>,
+    content: Option<String>,
+    createtime: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawEmoji {
+    #[serde(rename = "@md5")]
+    md5: Option<String>,
+    #[serde(rename = "@len")]
+    len: Option<String>,
+    #[serde(rename = "@type")]
+    emoji_type: Option<String>,
+    #[serde(rename = "@cdnurl")]
+    cdnurl: Option<String>,
+    #[serde(rename = "@width")]
+    width: Option<String>,
+    #[serde(rename = "@height")]
+    height: Option<String>,
+    #[serde(rename = "@productid")]
+    productid: Option<String>,
+}
+
+/// 元素文本去掉首尾空白，空文本视为不存在
+fn text(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn number<T: std::str::FromStr>(value: Option<String>) -> Option<T> {
+    text(value).and_then(|v| v.parse().ok())
+}
+
+/// appmsg 中的文件附件（`<appattach>`）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppAttach {
+    /// 文件大小（字节）
+    pub total_len: u64,
+    pub attach_id: Option<String>,
+    pub file_ext: Option<String>,
+}
+
+/// appmsg 中的小程序信息（`<weappinfo>`，type=33/36）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WeAppInfo {
+    /// 小程序原始 ID，如 `gh_xxx@app`
+    pub username: Option<String>,
+    /// 小程序 appid
+    pub app_id: Option<String>,
+    pub page_path: Option<String>,
+}
+
+/// 收到的 `<appmsg>` 内容（MsgType=49）
+///
+/// ```
+/// use gewe_core::AppMsgContent;
+///
+/// let xml = "<msg><appmsg appid=\"\"><title>标题</title><type>5</type><url>https://example.com</url></appmsg></msg>";
+/// let msg = AppMsgContent::parse(xml).unwrap();
+/// assert_eq!(msg.msg_type, 5);
+/// assert_eq!(msg.title.as_deref(), Some("标题"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppMsgContent {
+    /// `<type>`：5 链接、6 文件、33/36 小程序、57 引用回复、2000 转账、2001 红包等
+    pub msg_type: i32,
+    pub title: Option<String>,
+    pub des: Option<String>,
+    pub url: Option<String>,
+    pub thumb_url: Option<String>,
+    /// `<appmsg appid>`：来源应用
+    pub app_id: Option<String>,
+    /// 来源公众号/小程序的原始 ID
+    pub source_username: Option<String>,
+    pub source_display_name: Option<String>,
+    pub attach: Option<AppAttach>,
+    pub weapp: Option<WeAppInfo>,
+    /// 引用回复（type=57）中被引用的消息
+    pub refer: Option<ReferMsg>,
+}
+
+impl AppMsgContent {
+    pub fn parse(xml: &str) -> Result<Self, XmlError> {
+        let msg = RawMsg::parse(xml)?;
+        let appmsg = msg.appmsg.ok_or(XmlError::MissingElement("appmsg"))?;
+        let msg_type = number(appmsg.msg_type).ok_or(XmlError::MissingElement("type"))?;
+        Ok(Self {
+            msg_type,
+            title: text(appmsg.title),
+            des: text(appmsg.des),
+            url: text(appmsg.url),
+            thumb_url: text(appmsg.thumburl),
+            app_id: text(appmsg.appid),
+            source_username: text(appmsg.sourceusername).or_else(|| text(msg.sourceusername)),
+            source_display_name: text(appmsg.sourcedisplayname)
+                .or_else(|| text(msg.sourcedisplayname)),
+            attach: appmsg.appattach.map(|a| AppAttach {
+                total_len: number(a.totallen).unwrap_or_default(),
+                attach_id: text(a.attachid),
+                file_ext: text(a.fileext),
+            }),
+            weapp: appmsg.weappinfo.map(|w| WeAppInfo {
+                username: text(w.username),
+                app_id: text(w.appid),
+                page_path: text(w.pagepath),
+            }),
+            refer: appmsg.refermsg.map(ReferMsg::from_raw),
+        })
+    }
+
+    /// 小程序卡片（type=33/36）的 appid
+    pub fn mini_app_id(&self) -> Option<&str> {
+        self.weapp.as_ref().and_then(|w| w.app_id.as_deref())
+    }
+}
+
+/// 被引用的消息（`<refermsg>`）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReferMsg {
+    /// 被引用消息的 MsgType
+    pub msg_type: i64,
+    /// 被引用消息的 NewMsgId（`<svrid>`）
+    pub new_msg_id: Option<i64>,
+    /// 被引用消息的发送者
+    pub from_wxid: Option<String>,
+    /// 被引用消息所在会话
+    pub chat_wxid: Option<String>,
+    pub display_name: Option<String>,
+    /// 被引用消息的内容：文本消息为正文，其余为转义还原后的 XML
+    pub content: Option<String>,
+    pub create_time: Option<i64>,
+}
+
+impl ReferMsg {
+    fn from_raw(raw: RawReferMsg) -> Self {
+        Self {
+            msg_type: number(raw.msg_type).unwrap_or_default(),
+            new_msg_id: number(raw.svrid),
+            from_wxid: text(raw.fromusr),
+            chat_wxid: text(raw.chatusr),
+            display_name: text(raw.displayname),
+            content: text(raw.content),
+            create_time: number(raw.createtime),
+        }
+    }
+}
+
+/// 引用回复（appmsg type=57）：回复内容与被引用的消息
+///
+/// ```
+/// use gewe_core::QuotedMessage;
+///
+/// let xml = "<msg><appmsg><title>收到</title><type>57</type><refermsg><type>1</type>\
+///            <svrid>123</svrid><content>明天开会</content></refermsg></appmsg></msg>";
+/// let quoted = QuotedMessage::parse(xml).unwrap();
+/// assert_eq!(quoted.reply, "收到");
+/// assert_eq!(quoted.quoted_text(), Some("明天开会"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuotedMessage {
+    /// 回复的文字（appmsg 的 `<title>`）
+    pub reply: String,
+    pub refer: ReferMsg,
+}
+
+impl QuotedMessage {
+    pub const APPMSG_TYPE: i32 = 57;
+
+    pub fn parse(xml: &str) -> Result<Self, XmlError> {
+        let content = AppMsgContent::parse(xml)?;
+        if content.msg_type != Self::APPMSG_TYPE {
+            return Err(XmlError::UnexpectedType(content.msg_type));
+        }
+        Ok(Self {
+            reply: content.title.unwrap_or_default(),
+            refer: content.refer.ok_or(XmlError::MissingElement("refermsg"))?,
+        })
+    }
+
+    /// 被引用的是文本消息时返回其正文
+    pub fn quoted_text(&self) -> Option<&str> {
+        if self.refer.msg_type == 1 {
+            self.refer.content.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// 被引用的是 appmsg（链接、文件等）时解析其内容
+    pub fn quoted_appmsg(&self) -> Option<AppMsgContent> {
+        if self.refer.msg_type != 49 {
+            return None;
+        }
+        AppMsgContent::parse(self.refer.content.as_deref()?).ok()
+    }
+}
+
+/// 表情消息（MsgType=47）中的 `<emoji>`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmojiInfo {
+    /// 表情 md5（小写），可用于 `send_emoji`
+    pub md5: String,
+    /// 文件大小（字节）
+    pub len: u64,
+    /// 是否为动图（`type="2"`）
+    pub animated: bool,
+    pub cdn_url: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// 表情商店的表情包 ID，自定义表情为空
+    pub product_id: Option<String>,
+}
+
+impl EmojiInfo {
+    pub fn parse(xml: &str) -> Result<Self, XmlError> {
+        let emoji = RawMsg::parse(xml)?
+            .emoji
+            .ok_or(XmlError::MissingElement("emoji"))?;
+        Ok(Self {
+            md5: text(emoji.md5)
+                .ok_or(XmlError::MissingElement("emoji md5"))?
+                .to_ascii_lowercase(),
+            len: number(emoji.len).unwrap_or_default(),
+            animated: text(emoji.emoji_type).as_deref() == Some("2"),
+            cdn_url: text(emoji.cdnurl),
+            width: number(emoji.width),
+            height: number(emoji.height),
+            product_id: text(emoji.productid),
+        })
+    }
+}
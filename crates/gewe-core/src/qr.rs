@@ -0,0 +1,83 @@
+//! 二维码辅助函数
+//!
+//! 登录、群二维码等接口同时返回二维码内容（`qr_data` / `qr_url`）与 base64 图片：
+//! [`render_terminal`] 把内容重新编码为可在终端直接扫描的字符画，
+//! [`decode_image_base64`] 把 base64 图片解码为可写入磁盘的 PNG 字节。
+
+use base64::Engine;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+
+#[derive(Debug, thiserror::Error)]
+pub enum QrError {
+    #[error("qr encode error: {0}")]
+    Encode(String),
+    #[error("qr image decode error: {0}")]
+    Decode(String),
+}
+
+/// 把二维码内容渲染为终端字符画，每个字符表示上下两个模块。
+///
+/// `ansi` 为 true 时每行以 ANSI 转义固定为白底黑字，深色背景的终端也能正常扫描；
+/// 为 false 时输出纯文本，深色模块为字符块，适合写入文件或浅色背景。
+pub fn render_terminal(content: &str, ansi: bool) -> Result<String, QrError> {
+    let code = QrCode::new(content.as_bytes()).map_err(|e| QrError::Encode(e.to_string()))?;
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Dark)
+        .light_color(Dense1x2::Light)
+        .quiet_zone(true)
+        .build();
+    if !ansi {
+        return Ok(image);
+    }
+    Ok(image
+        .lines()
+        .map(|line| format!("\x1b[30;47m{}\x1b[0m", line))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// 解码接口返回的 base64 图片，兼容 `data:image/png;base64,` 前缀
+pub fn decode_image_base64(data: &str) -> Result<Vec<u8>, QrError> {
+    let data = data.trim();
+    let data = match data.split_once(";base64,") {
+        Some((prefix, rest)) if prefix.starts_with("data:") => rest,
+        _ => data,
+    };
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| QrError::Decode(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_terminal() {
+        let plain = render_terminal("http://weixin.qq.com/x/abc", false).unwrap();
+        let lines: Vec<&str> = plain.lines().collect();
+        // 版本 2 的二维码 25 个模块，加上两侧各 4 个模块的静区
+        assert_eq!(lines[0].chars().count(), 33);
+        assert_eq!(lines.len(), 17);
+        assert!(plain.contains('█'));
+
+        let ansi = render_terminal("http://weixin.qq.com/x/abc", true).unwrap();
+        assert!(ansi.lines().all(|line| line.starts_with("\x1b[30;47m")));
+        assert!(ansi.ends_with("\x1b[0m"));
+    }
+
+    #[test]
+    fn test_decode_image_base64() {
+        assert_eq!(decode_image_base64("iVBORw==").unwrap(), b"\x89PNG");
+        assert_eq!(
+            decode_image_base64("data:image/png;base64,iVBORw==").unwrap(),
+            b"\x89PNG"
+        );
+        assert!(matches!(
+            decode_image_base64("not base64!"),
+            Err(QrError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,13 @@
+//! 由 `proto/gewe/v1/bot.proto` 生成消息类型与服务代码，使用随 crate 提供的 protoc，
+//! 构建环境无需安装 protoc
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+    tonic_prost_build::configure().compile_with_config(
+        config,
+        &["proto/gewe/v1/bot.proto"],
+        &["proto"],
+    )?;
+    Ok(())
+}
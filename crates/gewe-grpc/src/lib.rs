@@ -1,41 +1,13 @@
-//! gRPC 封装占位：后续接入 tonic/生成 proto。
-
-pub fn placeholder() -> &'static str {
-    "gewe-grpc placeholder"
+//! gewe 的 gRPC 服务
+//!
+//! 接口定义见 `proto/gewe/v1/bot.proto`：一元方法 `SendText`、`SendImage`、`SendFile`
+//! 发送消息，服务端流 `SubscribeEvents` 推送 webhook 事件。消息类型与服务代码由 tonic 与
+//! prost 在构建时生成，[`GeweGrpcService::router`] 转为 axum Router，可与 webhook 共用端口。
+
+/// 由 proto 生成的 `gewe.v1` 消息类型、服务端与客户端
+pub mod proto {
+    tonic::include_proto!("gewe.v1");
 }
+mod service;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_placeholder_returns_expected_string() {
-        assert_eq!(placeholder(), "gewe-grpc placeholder");
-    }
-
-    #[test]
-    fn test_placeholder_is_static_str() {
-        let result = placeholder();
-        // 验证返回的是 'static 字符串，可以安全地存储
-        let _stored: &'static str = result;
-        assert!(!result.is_empty());
-    }
-
-    #[test]
-    fn test_placeholder_consistency() {
-        // 验证多次调用返回相同的结果
-        let first_call = placeholder();
-        let second_call = placeholder();
-        assert_eq!(first_call, second_call);
-    }
-
-    #[test]
-    fn test_placeholder_string_properties() {
-        let result = placeholder();
-        // 验证字符串属性
-        assert!(result.len() > 0);
-        assert!(result.starts_with("gewe-grpc"));
-        assert!(result.ends_with("placeholder"));
-        assert!(result.contains("placeholder"));
-    }
-}
+pub use service::{GeweGrpcService, DEFAULT_EVENT_CAPACITY};
@@ -0,0 +1,540 @@
+//! `gewe.v1.GeweBot` 服务实现
+//!
+//! 一元方法通过 [`BotManager`] 取得对应 appId 的客户端发送消息；`SubscribeEvents` 把 webhook
+//! 事件广播给所有订阅者，以服务端流返回。每个请求都需在 metadata 中携带
+//! `authorization: Bearer <token>`，校验规则与 `/ws/events` 相同。
+
+use crate::proto::gewe_bot_server::{GeweBot, GeweBotServer};
+use crate::proto::{
+    Event, SendFileRequest, SendImageRequest, SendResult, SendTextRequest, SubscribeEventsRequest,
+};
+use axum::Router;
+use futures_util::{stream, Stream};
+use gewe_core::{AppId, GeweError};
+use gewe_http::BotManager;
+use gewe_session::SessionStore;
+use gewe_webhook::WebhookEvent;
+use std::pin::Pin;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tonic::{Request, Response, Status};
+use tracing::{instrument, warn};
+
+/// 每个订阅者最多缓存的未读事件数，超出后丢弃最旧的事件
+pub const DEFAULT_EVENT_CAPACITY: usize = 256;
+
+fn to_status(err: GeweError) -> Status {
+    let message = err.to_string();
+    match err {
+        GeweError::UnknownApp(_) => Status::not_found(message),
+        GeweError::Http(_) => Status::unavailable(message),
+        GeweError::Api { .. } => Status::unknown(message),
+        GeweError::Decode(_) | GeweError::MissingData => Status::internal(message),
+    }
+}
+
+fn require(field: &str, value: &str) -> Result<(), Status> {
+    if value.trim().is_empty() {
+        Err(Status::invalid_argument(format!("{field} is required")))
+    } else {
+        Ok(())
+    }
+}
+
+/// metadata 中 `authorization: Bearer <token>` 的 token
+fn bearer_token<T>(request: &Request<T>) -> Result<&str, Status> {
+    request
+        .metadata()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| Status::unauthenticated("missing bearer token"))
+}
+
+fn token_eq(expected: &str, token: &str) -> bool {
+    expected.as_bytes().ct_eq(token.as_bytes()).into()
+}
+
+/// 发送消息的 gRPC 服务，包装 [`BotManager`] 与 webhook 事件通道。
+///
+/// 鉴权：token 与请求所涉及的每个机器人的 token 一致时才允许调用；配置了
+/// [`GeweGrpcService::with_access_token`] 时，持有该 token 的客户端可操作任意机器人，
+/// 订阅事件时不指定 `app_ids` 即接收全部事件。
+pub struct GeweGrpcService<S: SessionStore + ?Sized> {
+    manager: Arc<BotManager<S>>,
+    events: broadcast::Sender<WebhookEvent>,
+    access_token: Option<Arc<str>>,
+}
+
+impl<S: SessionStore + ?Sized> Clone for GeweGrpcService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            manager: self.manager.clone(),
+            events: self.events.clone(),
+            access_token: self.access_token.clone(),
+        }
+    }
+}
+
+impl<S: SessionStore + ?Sized + 'static> GeweGrpcService<S> {
+    pub fn new(manager: Arc<BotManager<S>>) -> Self {
+        Self::with_event_capacity(manager, DEFAULT_EVENT_CAPACITY)
+    }
+
+    /// 指定每个订阅者的事件缓冲大小，处理较慢的订阅者超出后会丢失最旧的事件
+    pub fn with_event_capacity(manager: Arc<BotManager<S>>, capacity: usize) -> Self {
+        let (events, _) = broadcast::channel(capacity.max(1));
+        Self {
+            manager,
+            events,
+            access_token: None,
+        }
+    }
+
+    /// 设置可操作全部机器人的访问 token（如供内部服务使用）
+    pub fn with_access_token(mut self, token: impl Into<String>) -> Self {
+        self.access_token = Some(Arc::from(token.into()));
+        self
+    }
+
+    /// 把 webhook 事件通道接入服务，通道关闭后任务结束，进行中的订阅随之正常结束
+    pub fn forward_events(&self, mut rx: mpsc::Receiver<WebhookEvent>) -> JoinHandle<()> {
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                // 没有订阅者时发送失败，直接丢弃
+                let _ = events.send(event);
+            }
+        })
+    }
+
+    /// 直接发布一条事件，返回收到事件的订阅者数量
+    pub fn publish(&self, event: WebhookEvent) -> usize {
+        self.events.send(event).unwrap_or(0)
+    }
+
+    /// 构建处理 `/gewe.v1.GeweBot/*` 的 Router，可与 webhook Router 合并到同一端口
+    pub fn router(self) -> Router {
+        tonic::service::Routes::new(GeweBotServer::new(self)).into_axum_router()
+    }
+
+    /// 校验 token 可以操作 `app_ids` 中的每个机器人，访问 token 不受限制
+    async fn authorize<T>(&self, request: &Request<T>, app_ids: &[String]) -> Result<(), Status> {
+        let token = bearer_token(request)?;
+        if self
+            .access_token
+            .as_deref()
+            .is_some_and(|expected| token_eq(expected, token))
+        {
+            return Ok(());
+        }
+        if app_ids.is_empty() {
+            return Err(Status::unauthenticated("invalid token"));
+        }
+        for app_id in app_ids {
+            match self
+                .manager
+                .store()
+                .get_session(&AppId(app_id.clone()))
+                .await
+            {
+                Some(ctx) if token_eq(&ctx.token, token) => {}
+                _ => return Err(Status::unauthenticated("invalid token")),
+            }
+        }
+        Ok(())
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl<S: SessionStore + ?Sized + 'static> GeweBot for GeweGrpcService<S> {
+    #[instrument(skip_all)]
+    async fn send_text(
+        &self,
+        request: Request<SendTextRequest>,
+    ) -> Result<Response<SendResult>, Status> {
+        self.authorize(&request, std::slice::from_ref(&request.get_ref().app_id))
+            .await?;
+        let req = request.into_inner();
+        require("app_id", &req.app_id)?;
+        require("to_wxid", &req.to_wxid)?;
+        require("content", &req.content)?;
+        let app_id = AppId(req.app_id);
+        let ats = Some(req.ats.as_str()).filter(|s| !s.is_empty());
+        let resp = self
+            .manager
+            .for_app(&app_id)
+            .await
+            .map_err(to_status)?
+            .send_text(&app_id.0, &req.to_wxid, &req.content, ats)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(SendResult {
+            to_wxid: resp.to_wxid,
+            msg_id: resp.msg_id,
+            new_msg_id: resp.new_msg_id,
+            create_time: resp.create_time,
+        }))
+    }
+
+    #[instrument(skip_all)]
+    async fn send_image(
+        &self,
+        request: Request<SendImageRequest>,
+    ) -> Result<Response<SendResult>, Status> {
+        self.authorize(&request, std::slice::from_ref(&request.get_ref().app_id))
+            .await?;
+        let req = request.into_inner();
+        require("app_id", &req.app_id)?;
+        require("to_wxid", &req.to_wxid)?;
+        require("image_url", &req.image_url)?;
+        let app_id = AppId(req.app_id);
+        let resp = self
+            .manager
+            .for_app(&app_id)
+            .await
+            .map_err(to_status)?
+            .send_image(&app_id.0, &req.to_wxid, &req.image_url)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(SendResult {
+            to_wxid: resp.to_wxid,
+            msg_id: resp.msg_id,
+            new_msg_id: resp.new_msg_id,
+            create_time: resp.create_time,
+        }))
+    }
+
+    #[instrument(skip_all)]
+    async fn send_file(
+        &self,
+        request: Request<SendFileRequest>,
+    ) -> Result<Response<SendResult>, Status> {
+        self.authorize(&request, std::slice::from_ref(&request.get_ref().app_id))
+            .await?;
+        let req = request.into_inner();
+        require("app_id", &req.app_id)?;
+        require("to_wxid", &req.to_wxid)?;
+        require("file_url", &req.file_url)?;
+        require("file_name", &req.file_name)?;
+        let app_id = AppId(req.app_id);
+        let resp = self
+            .manager
+            .for_app(&app_id)
+            .await
+            .map_err(to_status)?
+            .send_file(&app_id.0, &req.to_wxid, &req.file_url, &req.file_name)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(SendResult {
+            to_wxid: resp.to_wxid,
+            msg_id: resp.msg_id,
+            new_msg_id: resp.new_msg_id,
+            create_time: resp.create_time,
+        }))
+    }
+
+    type SubscribeEventsStream = EventStream;
+
+    async fn subscribe_events(
+        &self,
+        request: Request<SubscribeEventsRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        self.authorize(&request, &request.get_ref().app_ids).await?;
+        let filter = request.into_inner();
+        let rx = self.events.subscribe();
+        let events = stream::unfold((rx, filter), |(mut rx, filter)| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) if matches(&filter, &event) => {
+                        return Some((Ok(to_proto(event)), (rx, filter)));
+                    }
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "grpc event subscriber lagged, events dropped");
+                    }
+                    // 事件源关闭后以 OK 状态结束流
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+fn matches(filter: &SubscribeEventsRequest, event: &WebhookEvent) -> bool {
+    let app_ok = filter.app_ids.is_empty() || filter.app_ids.contains(&event.app_id.0);
+    let type_ok = filter.type_names.is_empty()
+        || event
+            .type_name
+            .as_ref()
+            .is_some_and(|t| filter.type_names.contains(t));
+    app_ok && type_ok
+}
+
+fn to_proto(event: WebhookEvent) -> Event {
+    Event {
+        app_id: event.app_id.0,
+        type_name: event.type_name.unwrap_or_default(),
+        data_json: event.data.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::gewe_bot_client::GeweBotClient;
+    use axum::routing::post;
+    use futures_util::StreamExt;
+    use gewe_core::BotContext;
+    use gewe_session::InMemorySessionStore;
+    use serde_json::json;
+    use std::sync::Mutex;
+    use tonic::Code;
+
+    type Seen = Arc<Mutex<Vec<(String, serde_json::Value)>>>;
+
+    /// 启动模拟的 Gewe HTTP 接口，记录请求路径与请求体
+    async fn mock_gewe() -> (String, Seen) {
+        let seen: Seen = Arc::default();
+        let recorder = seen.clone();
+        let app = Router::new().fallback(post(
+            move |uri: axum::http::Uri, axum::Json(body): axum::Json<serde_json::Value>| {
+                let recorder = recorder.clone();
+                async move {
+                    recorder
+                        .lock()
+                        .unwrap()
+                        .push((uri.path().to_string(), body.clone()));
+                    axum::Json(json!({
+                        "ret": 200,
+                        "msg": "操作成功",
+                        "data": {
+                            "toWxid": body["toWxid"],
+                            "createTime": 1700000000,
+                            "msgId": 1,
+                            "newMsgId": 2,
+                            "type": 1,
+                            "aesKey": "",
+                            "fileId": "",
+                            "length": 0,
+                            "width": 0,
+                            "height": 0,
+                            "md5": ""
+                        }
+                    }))
+                }
+            },
+        ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{addr}"), seen)
+    }
+
+    async fn service(base_url: &str) -> GeweGrpcService<InMemorySessionStore> {
+        let manager = BotManager::new(Arc::new(InMemorySessionStore::default()), base_url);
+        manager
+            .register(BotContext {
+                app_id: AppId("wx_app".to_string()),
+                token: "token".to_string(),
+                webhook_secret: None,
+                description: None,
+            })
+            .await
+            .unwrap();
+        GeweGrpcService::new(Arc::new(manager)).with_access_token("admin")
+    }
+
+    fn authed<T>(message: T, token: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {token}").parse().unwrap());
+        request
+    }
+
+    fn text(app_id: &str, content: &str) -> SendTextRequest {
+        SendTextRequest {
+            app_id: app_id.to_string(),
+            to_wxid: "wxid_friend".to_string(),
+            content: content.to_string(),
+            ats: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_text() {
+        let (base_url, seen) = mock_gewe().await;
+        let service = service(&base_url).await;
+        let result = service
+            .send_text(authed(text("wx_app", "你好"), "token"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(result.to_wxid, "wxid_friend");
+        assert_eq!(result.new_msg_id, 2);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].0, "/gewe/v2/api/message/postText");
+        assert_eq!(seen[0].1["content"], "你好");
+        assert!(seen[0].1.get("ats").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_image_and_file() {
+        let (base_url, seen) = mock_gewe().await;
+        let service = service(&base_url).await;
+        let image = SendImageRequest {
+            app_id: "wx_app".to_string(),
+            to_wxid: "wxid_friend".to_string(),
+            image_url: "https://example.com/a.png".to_string(),
+        };
+        service.send_image(authed(image, "token")).await.unwrap();
+
+        let file = SendFileRequest {
+            app_id: "wx_app".to_string(),
+            to_wxid: "wxid_friend".to_string(),
+            file_url: "https://example.com/a.pdf".to_string(),
+            file_name: "a.pdf".to_string(),
+        };
+        service.send_file(authed(file, "admin")).await.unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].0, "/gewe/v2/api/message/postImage");
+        assert_eq!(seen[0].1["imgUrl"], "https://example.com/a.png");
+        assert_eq!(seen[1].0, "/gewe/v2/api/message/postFile");
+        assert_eq!(seen[1].1["fileName"], "a.pdf");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_missing_or_wrong_token() {
+        let service = service("http://127.0.0.1:9").await;
+        let code = |result: Result<Response<SendResult>, Status>| result.unwrap_err().code();
+
+        let anonymous = service.send_text(Request::new(text("wx_app", "hi"))).await;
+        assert_eq!(code(anonymous), Code::Unauthenticated);
+        let mut basic = Request::new(text("wx_app", "hi"));
+        basic
+            .metadata_mut()
+            .insert("authorization", "Basic token".parse().unwrap());
+        assert_eq!(code(service.send_text(basic).await), Code::Unauthenticated);
+        for (app_id, token) in [("wx_app", "wrong"), ("other_app", "token"), ("", "token")] {
+            let result = service.send_text(authed(text(app_id, "hi"), token)).await;
+            assert_eq!(code(result), Code::Unauthenticated, "{app_id} {token}");
+        }
+
+        let filter = SubscribeEventsRequest::default();
+        let result = service.subscribe_events(authed(filter, "token")).await;
+        assert_eq!(result.err().unwrap().code(), Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_error_statuses() {
+        let service = service("http://127.0.0.1:9").await;
+        let status = service
+            .send_text(authed(text("wx_app", ""), "token"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "content is required");
+
+        // 访问 token 可以通过鉴权，但机器人未注册
+        let status = service
+            .send_text(authed(text("other_app", "hi"), "admin"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_events_filters_and_streams() {
+        let service = service("http://127.0.0.1:9").await;
+        let (tx, rx) = mpsc::channel(8);
+        let forwarder = service.forward_events(rx);
+
+        let filter = SubscribeEventsRequest {
+            app_ids: vec!["wx_app".to_string()],
+            type_names: vec!["AddMsg".to_string()],
+        };
+        let mut events = service
+            .subscribe_events(authed(filter, "token"))
+            .await
+            .unwrap()
+            .into_inner();
+
+        for (app_id, type_name) in [
+            ("other_app", "AddMsg"),
+            ("wx_app", "ModContacts"),
+            ("wx_app", "AddMsg"),
+        ] {
+            tx.send(WebhookEvent {
+                app_id: AppId(app_id.to_string()),
+                type_name: Some(type_name.to_string()),
+                data: json!({ "MsgId": 1 }),
+            })
+            .await
+            .unwrap();
+        }
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(event.app_id, "wx_app");
+        assert_eq!(event.type_name, "AddMsg");
+        assert_eq!(event.data_json, r#"{"MsgId":1}"#);
+
+        // 关闭事件源后流正常结束
+        drop(tx);
+        forwarder.await.unwrap();
+        drop(service);
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serves_h2c_with_generated_client() {
+        let (base_url, _) = mock_gewe().await;
+        let router = service(&base_url).await.router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        let mut client = GeweBotClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap();
+        let result = client
+            .send_text(authed(text("wx_app", "hi"), "token"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(result.to_wxid, "wxid_friend");
+
+        let status = client
+            .send_text(Request::new(text("wx_app", "hi")))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_status_from_gewe_error() {
+        let status = to_status(GeweError::Api {
+            code: 500,
+            message: "boom".to_string(),
+        });
+        assert_eq!(status.code(), Code::Unknown);
+        assert_eq!(
+            to_status(GeweError::Http("timeout".to_string())).code(),
+            Code::Unavailable
+        );
+        assert_eq!(to_status(GeweError::MissingData).code(), Code::Internal);
+    }
+}
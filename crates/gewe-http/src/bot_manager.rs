@@ -0,0 +1,272 @@
+//! 多机器人客户端管理
+//!
+//! 每个 appId 对应的 token 保存在 [`SessionStore`] 中，[`BotManager`] 在首次使用时
+//! 按 token 创建 [`GeweHttpClient`] 并缓存；token 变化时通过 [`BotManager::register`]
+//! 或 [`BotManager::refresh`] 重建客户端，在线检查也统一从这里发起。
+
+use crate::client::GeweHttpClient;
+use crate::rate_limit::RateLimitPolicy;
+use gewe_core::{AppId, BotContext, CheckOnlineRequest, GeweError, ReconnectionRequest};
+use gewe_session::SessionStore;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{instrument, warn};
+
+struct CachedClient {
+    token: String,
+    client: GeweHttpClient,
+}
+
+/// 按 appId 管理 [`GeweHttpClient`]，所有机器人共用同一个 base_url 与客户端配置
+pub struct BotManager<S: SessionStore + ?Sized> {
+    store: Arc<S>,
+    base_url: String,
+    timeout: Option<Duration>,
+    rate_limit: Option<RateLimitPolicy>,
+    clients: RwLock<HashMap<AppId, CachedClient>>,
+}
+
+impl<S: SessionStore + ?Sized> BotManager<S> {
+    pub fn new(store: Arc<S>, base_url: impl Into<String>) -> Self {
+        Self {
+            store,
+            base_url: base_url.into(),
+            timeout: None,
+            rate_limit: None,
+            clients: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 新建客户端的请求超时，未设置时沿用 [`GeweHttpClient`] 默认值
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// 新建客户端的发送限流策略，每个 appId 各自计数
+    pub fn rate_limit(mut self, policy: RateLimitPolicy) -> Self {
+        self.rate_limit = Some(policy);
+        self
+    }
+
+    pub fn store(&self) -> &Arc<S> {
+        &self.store
+    }
+
+    /// 取得 appId 对应的客户端，首次调用时从 SessionStore 读取 token 创建
+    ///
+    /// 返回的客户端为廉价克隆，与缓存共享连接池与限流状态。
+    pub async fn for_app(&self, app_id: &AppId) -> Result<GeweHttpClient, GeweError> {
+        if let Some(cached) = self.clients.read().await.get(app_id) {
+            return Ok(cached.client.clone());
+        }
+        let context = self
+            .store
+            .get_session(app_id)
+            .await
+            .ok_or_else(|| GeweError::UnknownApp(app_id.0.clone()))?;
+        self.cache_client(&context).await
+    }
+
+    /// 写入（或更新）机器人上下文，token 变化时立即重建客户端
+    pub async fn register(&self, context: BotContext) -> Result<GeweHttpClient, GeweError> {
+        let client = self.cache_client(&context).await?;
+        self.store.put_session(context).await;
+        Ok(client)
+    }
+
+    /// 丢弃缓存的客户端并按 SessionStore 中的最新 token 重建，用于外部更新了 token 的场景
+    pub async fn refresh(&self, app_id: &AppId) -> Result<GeweHttpClient, GeweError> {
+        self.clients.write().await.remove(app_id);
+        self.for_app(app_id).await
+    }
+
+    /// 从 SessionStore 与缓存中移除机器人，返回 SessionStore 中是否存在
+    pub async fn remove(&self, app_id: &AppId) -> bool {
+        self.clients.write().await.remove(app_id);
+        self.store.remove_session(app_id).await
+    }
+
+    /// SessionStore 中登记的全部 appId，按字典序排列
+    pub async fn app_ids(&self) -> Vec<AppId> {
+        self.store
+            .list_sessions()
+            .await
+            .into_iter()
+            .map(|c| c.app_id)
+            .collect()
+    }
+
+    #[instrument(skip(self), fields(app_id = %app_id.0))]
+    pub async fn check_online(&self, app_id: &AppId) -> Result<bool, GeweError> {
+        self.for_app(app_id)
+            .await?
+            .check_online(CheckOnlineRequest { app_id: &app_id.0 })
+            .await
+    }
+
+    /// 依次检查全部机器人的在线状态
+    pub async fn check_all_online(&self) -> Vec<(AppId, Result<bool, GeweError>)> {
+        let mut results = Vec::new();
+        for app_id in self.app_ids().await {
+            let online = self.check_online(&app_id).await;
+            results.push((app_id, online));
+        }
+        results
+    }
+
+    /// 离线时尝试断线重连，返回重连后的在线状态
+    #[instrument(skip(self), fields(app_id = %app_id.0))]
+    pub async fn ensure_online(&self, app_id: &AppId) -> Result<bool, GeweError> {
+        if self.check_online(app_id).await? {
+            return Ok(true);
+        }
+        warn!("bot offline, trying reconnection");
+        self.for_app(app_id)
+            .await?
+            .reconnection(ReconnectionRequest { app_id: &app_id.0 })
+            .await?;
+        self.check_online(app_id).await
+    }
+
+    async fn cache_client(&self, context: &BotContext) -> Result<GeweHttpClient, GeweError> {
+        let mut clients = self.clients.write().await;
+        if let Some(cached) = clients.get(&context.app_id) {
+            if cached.token == context.token {
+                return Ok(cached.client.clone());
+            }
+        }
+        let client = self.build_client(&context.token)?;
+        clients.insert(
+            context.app_id.clone(),
+            CachedClient {
+                token: context.token.clone(),
+                client: client.clone(),
+            },
+        );
+        Ok(client)
+    }
+
+    fn build_client(&self, token: &str) -> Result<GeweHttpClient, GeweError> {
+        let mut builder = GeweHttpClient::builder(token, self.base_url.clone());
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(policy) = self.rate_limit {
+            builder = builder.rate_limit(policy);
+        }
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gewe_session::InMemorySessionStore;
+
+    fn context(app_id: &str, token: &str) -> BotContext {
+        BotContext {
+            app_id: AppId(app_id.to_string()),
+            token: token.to_string(),
+            webhook_secret: None,
+            description: None,
+        }
+    }
+
+    async fn cached_token(manager: &BotManager<InMemorySessionStore>, app_id: &AppId) -> String {
+        manager.clients.read().await[app_id].token.clone()
+    }
+
+    #[tokio::test]
+    async fn test_for_app_lazily_creates_client() {
+        let store = Arc::new(InMemorySessionStore::default());
+        store.put_session(context("app1", "token1")).await;
+        let manager = BotManager::new(store, "https://api.example.com")
+            .rate_limit(RateLimitPolicy::default());
+
+        let app_id = AppId("app1".to_string());
+        assert!(manager.clients.read().await.is_empty());
+        let client = manager.for_app(&app_id).await.unwrap();
+        assert_eq!(client.base_url, "https://api.example.com");
+        assert_eq!(client.rate_limit_policy(), Some(RateLimitPolicy::default()));
+        assert_eq!(cached_token(&manager, &app_id).await, "token1");
+    }
+
+    #[tokio::test]
+    async fn test_for_app_unknown_app() {
+        let manager = BotManager::new(
+            Arc::new(InMemorySessionStore::default()),
+            "https://api.example.com",
+        );
+        let err = manager
+            .for_app(&AppId("missing".to_string()))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, GeweError::UnknownApp(ref id) if id == "missing"));
+    }
+
+    #[tokio::test]
+    async fn test_register_and_refresh_token() {
+        let store = Arc::new(InMemorySessionStore::default());
+        let manager = BotManager::new(store.clone(), "https://api.example.com");
+        let app_id = AppId("app1".to_string());
+
+        manager.register(context("app1", "old")).await.unwrap();
+        assert_eq!(cached_token(&manager, &app_id).await, "old");
+
+        // 通过 register 更新 token 时立即重建
+        manager.register(context("app1", "new")).await.unwrap();
+        assert_eq!(cached_token(&manager, &app_id).await, "new");
+        assert_eq!(store.get_session(&app_id).await.unwrap().token, "new");
+
+        // 直接写入 SessionStore 的 token 需要 refresh 才生效
+        store.put_session(context("app1", "rotated")).await;
+        manager.for_app(&app_id).await.unwrap();
+        assert_eq!(cached_token(&manager, &app_id).await, "new");
+        manager.refresh(&app_id).await.unwrap();
+        assert_eq!(cached_token(&manager, &app_id).await, "rotated");
+    }
+
+    #[tokio::test]
+    async fn test_remove_and_app_ids() {
+        let store = Arc::new(InMemorySessionStore::default());
+        let manager = BotManager::new(store, "https://api.example.com");
+        manager.register(context("b", "t2")).await.unwrap();
+        manager.register(context("a", "t1")).await.unwrap();
+        assert_eq!(
+            manager.app_ids().await,
+            vec![AppId("a".to_string()), AppId("b".to_string())]
+        );
+
+        assert!(manager.remove(&AppId("a".to_string())).await);
+        assert!(!manager.remove(&AppId("a".to_string())).await);
+        assert!(manager.for_app(&AppId("a".to_string())).await.is_err());
+        assert_eq!(manager.app_ids().await, vec![AppId("b".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token_is_not_cached() {
+        let manager = BotManager::new(
+            Arc::new(InMemorySessionStore::default()),
+            "https://api.example.com",
+        );
+        assert!(manager
+            .register(context("app1", "bad\ntoken"))
+            .await
+            .is_err());
+        assert!(manager.clients.read().await.is_empty());
+        assert!(manager.app_ids().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dyn_session_store() {
+        let store: Arc<dyn SessionStore> = Arc::new(InMemorySessionStore::default());
+        store.put_session(context("app1", "token1")).await;
+        let manager =
+            BotManager::new(store, "https://api.example.com").timeout(Duration::from_secs(3));
+        assert!(manager.for_app(&AppId("app1".to_string())).await.is_ok());
+    }
+}
@@ -0,0 +1,329 @@
+//! 群成员缓存
+//!
+//! [`ChatroomMembers`] 通过 `getChatroomMemberList` 拉取群主、管理员与成员列表并缓存，
+//! 查询（[`is_admin`](ChatroomMembers::is_admin)、[`member`](ChatroomMembers::member) 等）
+//! 是同步的、不发起请求。回调中的入群/退群系统消息可用 [`add`](ChatroomMembers::add)、
+//! [`remove`](ChatroomMembers::remove) 增量更新，不必每次变动都重新拉取整个群。
+
+use crate::client::GeweHttpClient;
+use gewe_core::GetChatroomMemberListRequest;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument};
+
+/// 群成员缓存的默认有效期
+pub const DEFAULT_CHATROOM_MEMBER_TTL: Duration = Duration::from_secs(3600);
+/// 拉取失败后的重试间隔，避免每条消息都请求一次
+const FAILURE_RETRY: Duration = Duration::from_secs(60);
+
+/// 缓存的群成员信息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatroomMemberInfo {
+    pub wxid: String,
+    pub nick_name: String,
+    /// 成员在群里设置的群昵称
+    pub display_name: Option<String>,
+    /// 邀请人 wxid
+    pub inviter: Option<String>,
+}
+
+impl ChatroomMemberInfo {
+    /// 展示用名称：群昵称优先，其次昵称
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.nick_name)
+    }
+}
+
+/// 一个群的群主、管理员与成员
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatroomRoster {
+    pub owner: Option<String>,
+    pub admins: BTreeSet<String>,
+    pub members: BTreeMap<String, ChatroomMemberInfo>,
+}
+
+impl ChatroomRoster {
+    /// 群主也视为管理员
+    pub fn is_admin(&self, wxid: &str) -> bool {
+        self.owner.as_deref() == Some(wxid) || self.admins.contains(wxid)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedRoster {
+    /// 从未拉取成功时为 None
+    roster: Option<ChatroomRoster>,
+    expires_at: Instant,
+}
+
+/// 群聊 ID → 成员列表，带过期时间的缓存
+#[derive(Debug)]
+pub struct ChatroomMembers {
+    ttl: Duration,
+    cache: RwLock<HashMap<String, CachedRoster>>,
+}
+
+impl Default for ChatroomMembers {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHATROOM_MEMBER_TTL)
+    }
+}
+
+impl ChatroomMembers {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 写入已知的成员列表，覆盖原有缓存
+    pub fn insert(&self, chatroom_id: &str, roster: ChatroomRoster) {
+        self.cache
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                chatroom_id.to_string(),
+                CachedRoster {
+                    roster: Some(roster),
+                    expires_at: Instant::now() + self.ttl,
+                },
+            );
+    }
+
+    /// 缓存缺失或过期时调用 `getChatroomMemberList` 拉取，返回缓存中是否有该群的成员列表
+    ///
+    /// 非群聊 ID 不发起请求；拉取失败时保留过期的成员列表（如有），一分钟后再重试。
+    #[instrument(skip(self, client))]
+    pub async fn load(&self, client: &GeweHttpClient, app_id: &str, chatroom_id: &str) -> bool {
+        if !chatroom_id.ends_with("@chatroom") || self.is_fresh(chatroom_id) {
+            return self.contains(chatroom_id);
+        }
+        let fetched = client
+            .get_chatroom_member_list(GetChatroomMemberListRequest {
+                app_id,
+                chatroom_id,
+            })
+            .await;
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        match fetched {
+            Ok(list) => {
+                let roster = ChatroomRoster {
+                    owner: Some(list.chat_room_owner).filter(|o| !o.is_empty()),
+                    admins: list.admin_wxid.unwrap_or_default().into_iter().collect(),
+                    members: list
+                        .chatroom_members
+                        .into_iter()
+                        .map(|m| {
+                            let info = ChatroomMemberInfo {
+                                wxid: m.wxid.clone(),
+                                nick_name: m.nick_name,
+                                display_name: Some(m.display_name).filter(|d| !d.trim().is_empty()),
+                                inviter: Some(m.inviter_user_name).filter(|i| !i.is_empty()),
+                            };
+                            (m.wxid, info)
+                        })
+                        .collect(),
+                };
+                cache.insert(
+                    chatroom_id.to_string(),
+                    CachedRoster {
+                        roster: Some(roster),
+                        expires_at: Instant::now() + self.ttl,
+                    },
+                );
+                true
+            }
+            Err(err) => {
+                debug!(?err, "拉取群成员失败");
+                let entry = cache
+                    .entry(chatroom_id.to_string())
+                    .or_insert(CachedRoster {
+                        roster: None,
+                        expires_at: Instant::now(),
+                    });
+                entry.expires_at = Instant::now() + FAILURE_RETRY.min(self.ttl);
+                entry.roster.is_some()
+            }
+        }
+    }
+
+    /// 缓存中的成员列表（过期的也返回）
+    pub fn roster(&self, chatroom_id: &str) -> Option<ChatroomRoster> {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .and_then(|c| c.roster.clone())
+    }
+
+    /// 缓存中的某个成员
+    pub fn member(&self, chatroom_id: &str, wxid: &str) -> Option<ChatroomMemberInfo> {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .and_then(|c| c.roster.as_ref()?.members.get(wxid).cloned())
+    }
+
+    /// 是否为群主或管理员，未缓存该群时为 false
+    pub fn is_admin(&self, chatroom_id: &str, wxid: &str) -> bool {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .and_then(|c| c.roster.as_ref())
+            .is_some_and(|r| r.is_admin(wxid))
+    }
+
+    /// 缓存中的成员数
+    pub fn member_count(&self, chatroom_id: &str) -> Option<usize> {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .and_then(|c| c.roster.as_ref())
+            .map(|r| r.members.len())
+    }
+
+    /// 按昵称或群昵称查找成员 wxid，有重名时返回 None
+    pub fn find_by_name(&self, chatroom_id: &str, name: &str) -> Option<String> {
+        let cache = self.cache.read().unwrap_or_else(|e| e.into_inner());
+        let roster = cache.get(chatroom_id)?.roster.as_ref()?;
+        let mut found = roster
+            .members
+            .values()
+            .filter(|m| m.nick_name == name || m.display_name.as_deref() == Some(name));
+        let first = found.next()?;
+        found.next().is_none().then(|| first.wxid.clone())
+    }
+
+    /// 记录新入群的成员；未缓存该群时忽略，下次拉取会包含该成员
+    pub fn add(&self, chatroom_id: &str, member: ChatroomMemberInfo) {
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(roster) = cache.get_mut(chatroom_id).and_then(|c| c.roster.as_mut()) {
+            roster.members.insert(member.wxid.clone(), member);
+        }
+    }
+
+    /// 移除退群的成员，同时取消其管理员身份，返回被移除的成员
+    pub fn remove(&self, chatroom_id: &str, wxid: &str) -> Option<ChatroomMemberInfo> {
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        let roster = cache.get_mut(chatroom_id)?.roster.as_mut()?;
+        roster.admins.remove(wxid);
+        roster.members.remove(wxid)
+    }
+
+    /// 标记为过期，下次 [`load`](Self::load) 重新拉取；用于只知道昵称、无法增量更新的变动
+    pub fn invalidate(&self, chatroom_id: &str) {
+        if let Some(entry) = self
+            .cache
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(chatroom_id)
+        {
+            entry.expires_at = Instant::now();
+        }
+    }
+
+    fn contains(&self, chatroom_id: &str) -> bool {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .is_some_and(|c| c.roster.is_some())
+    }
+
+    fn is_fresh(&self, chatroom_id: &str) -> bool {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .is_some_and(|c| c.expires_at > Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(wxid: &str, nick_name: &str) -> ChatroomMemberInfo {
+        ChatroomMemberInfo {
+            wxid: wxid.to_string(),
+            nick_name: nick_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_add_remove_and_admins() {
+        let members = ChatroomMembers::default();
+        // Unknown chatrooms ignore incremental updates
+        members.add("1@chatroom", member("wxid_alice", "Alice"));
+        assert_eq!(members.roster("1@chatroom"), None);
+        assert!(!members.is_admin("1@chatroom", "wxid_owner"));
+
+        members.insert(
+            "1@chatroom",
+            ChatroomRoster {
+                owner: Some("wxid_owner".to_string()),
+                admins: BTreeSet::from(["wxid_admin".to_string()]),
+                members: BTreeMap::from([
+                    ("wxid_owner".to_string(), member("wxid_owner", "群主")),
+                    ("wxid_admin".to_string(), member("wxid_admin", "管理员")),
+                ]),
+            },
+        );
+        assert!(members.is_admin("1@chatroom", "wxid_owner"));
+        assert!(members.is_admin("1@chatroom", "wxid_admin"));
+        assert!(!members.is_admin("1@chatroom", "wxid_alice"));
+
+        members.add("1@chatroom", member("wxid_alice", "Alice"));
+        assert_eq!(members.member_count("1@chatroom"), Some(3));
+        assert_eq!(
+            members.find_by_name("1@chatroom", "Alice").as_deref(),
+            Some("wxid_alice")
+        );
+
+        assert!(members.remove("1@chatroom", "wxid_admin").is_some());
+        assert!(!members.is_admin("1@chatroom", "wxid_admin"));
+        assert_eq!(members.member_count("1@chatroom"), Some(2));
+        assert_eq!(members.remove("1@chatroom", "wxid_admin"), None);
+    }
+
+    #[test]
+    fn test_find_by_name_ambiguous() {
+        let members = ChatroomMembers::default();
+        let mut bob = member("wxid_bob", "Bob");
+        bob.display_name = Some("Alice".to_string());
+        members.insert(
+            "1@chatroom",
+            ChatroomRoster {
+                members: BTreeMap::from([
+                    ("wxid_alice".to_string(), member("wxid_alice", "Alice")),
+                    ("wxid_bob".to_string(), bob),
+                ]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(members.find_by_name("1@chatroom", "Alice"), None);
+        assert_eq!(
+            members.find_by_name("1@chatroom", "Bob").as_deref(),
+            Some("wxid_bob")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_failure_keeps_roster() {
+        let client = GeweHttpClient::new("token", "http://127.0.0.1:9").unwrap();
+        let members = ChatroomMembers::new(Duration::ZERO);
+        assert!(!members.load(&client, "wx_app", "1@chatroom").await);
+        assert_eq!(members.roster("1@chatroom"), None);
+
+        members.insert("1@chatroom", ChatroomRoster::default());
+        members.invalidate("1@chatroom");
+        assert!(members.load(&client, "wx_app", "1@chatroom").await);
+        assert!(!members.load(&client, "wx_app", "wxid_alice").await);
+    }
+}
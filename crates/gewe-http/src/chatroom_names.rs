@@ -0,0 +1,395 @@
+//! 群聊 ID 与群名互查
+//!
+//! 日志、管理页面与 CLI 中的 `12345@chatroom` 对人并不友好。[`ChatroomNames`] 通过
+//! `getChatroomInfo` 查询群名并缓存，手动设置的别名优先于查询结果；缓存读取是同步的，
+//! 可以直接用在日志字段与页面渲染里。查询时一并记录群成员数与成员昵称，供 AI 提示词等
+//! 需要群上下文的地方读取。
+
+use crate::client::GeweHttpClient;
+use gewe_core::GetChatroomInfoRequest;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::{debug, instrument};
+
+/// 群名缓存的默认有效期
+pub const DEFAULT_CHATROOM_NAME_TTL: Duration = Duration::from_secs(3600);
+/// 查询失败后的重试间隔，避免每条消息都请求一次
+const FAILURE_RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+struct CachedName {
+    /// 查询失败时保留上一次的群名
+    name: Option<String>,
+    /// 最近一次查询到的成员数，未查询过时为 None
+    member_count: Option<usize>,
+    members: HashMap<String, ChatroomMemberName>,
+    expires_at: Instant,
+}
+
+/// 群成员的昵称与群昵称
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatroomMemberName {
+    pub nick_name: String,
+    /// 成员在群里设置的群昵称
+    pub display_name: Option<String>,
+}
+
+/// 群聊 ID → 群名解析，带缓存与手动别名
+#[derive(Debug)]
+pub struct ChatroomNames {
+    ttl: Duration,
+    aliases: RwLock<HashMap<String, String>>,
+    cache: RwLock<HashMap<String, CachedName>>,
+}
+
+impl Default for ChatroomNames {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHATROOM_NAME_TTL)
+    }
+}
+
+impl ChatroomNames {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            aliases: RwLock::new(HashMap::new()),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 替换全部手动别名（群 ID → 显示名），用于配置加载与热更新
+    pub fn set_aliases<I, K, V>(&self, aliases: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let aliases = aliases
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .filter(|(_, v): &(String, String)| !v.trim().is_empty())
+            .collect();
+        *self.aliases.write().unwrap_or_else(|e| e.into_inner()) = aliases;
+    }
+
+    /// 写入已知的群名，如回调中的 ModContacts 或其他接口返回的群信息；已缓存的成员信息保留
+    pub fn insert(&self, chatroom_id: &str, name: &str) {
+        if name.trim().is_empty() {
+            return;
+        }
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        let expires_at = Instant::now() + self.ttl;
+        match cache.get_mut(chatroom_id) {
+            Some(entry) => {
+                entry.name = Some(name.to_string());
+                entry.expires_at = expires_at;
+            }
+            None => {
+                cache.insert(
+                    chatroom_id.to_string(),
+                    CachedName {
+                        name: Some(name.to_string()),
+                        member_count: None,
+                        members: HashMap::new(),
+                        expires_at,
+                    },
+                );
+            }
+        }
+    }
+
+    /// 最近一次查询到的群成员数，不发起请求
+    pub fn member_count(&self, chatroom_id: &str) -> Option<usize> {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .and_then(|c| c.member_count)
+    }
+
+    /// 最近一次查询到的群成员昵称与群昵称，不发起请求
+    pub fn member(&self, chatroom_id: &str, wxid: &str) -> Option<ChatroomMemberName> {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .and_then(|c| c.members.get(wxid).cloned())
+    }
+
+    /// 已知的显示名：别名优先，其次为缓存（过期的群名也返回），不发起请求
+    pub fn cached(&self, chatroom_id: &str) -> Option<String> {
+        if let Some(alias) = self
+            .aliases
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+        {
+            return Some(alias.clone());
+        }
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .and_then(|c| c.name.clone())
+    }
+
+    /// 查询显示名：别名与未过期的缓存直接返回，否则调用 `getChatroomInfo` 并缓存
+    ///
+    /// 非群聊 ID 只查别名；查询失败时返回过期缓存中的群名（如有）。
+    #[instrument(skip(self, client))]
+    pub async fn resolve(
+        &self,
+        client: &GeweHttpClient,
+        app_id: &str,
+        chatroom_id: &str,
+    ) -> Option<String> {
+        if !chatroom_id.ends_with("@chatroom") || self.is_fresh(chatroom_id) {
+            return self.cached(chatroom_id);
+        }
+        let fetched = client
+            .get_chatroom_info(GetChatroomInfoRequest {
+                app_id,
+                chatroom_id,
+            })
+            .await;
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        let stale = cache.get(chatroom_id).cloned();
+        let stale_name = stale.as_ref().and_then(|c| c.name.clone());
+        let entry = match fetched {
+            Ok(info) => CachedName {
+                name: Some(info.nick_name)
+                    .filter(|n| !n.trim().is_empty())
+                    .or(stale_name),
+                member_count: Some(info.member_list.len()),
+                members: info
+                    .member_list
+                    .into_iter()
+                    .map(|m| {
+                        let name = ChatroomMemberName {
+                            nick_name: m.nick_name,
+                            display_name: m.display_name.filter(|d| !d.trim().is_empty()),
+                        };
+                        (m.wxid, name)
+                    })
+                    .collect(),
+                expires_at: Instant::now() + self.ttl,
+            },
+            Err(err) => {
+                debug!(?err, "查询群名失败");
+                CachedName {
+                    name: stale_name,
+                    member_count: stale.as_ref().and_then(|c| c.member_count),
+                    members: stale.map(|c| c.members).unwrap_or_default(),
+                    expires_at: Instant::now() + FAILURE_RETRY.min(self.ttl),
+                }
+            }
+        };
+        cache.insert(chatroom_id.to_string(), entry);
+        drop(cache);
+        self.cached(chatroom_id)
+    }
+
+    /// 缓存中已知的群名（不含别名），用于持久化后在重启或其他进程中 [`insert`](Self::insert) 恢复
+    pub fn known(&self) -> BTreeMap<String, String> {
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter_map(|(id, c)| c.name.clone().map(|name| (id.clone(), name)))
+            .collect()
+    }
+
+    /// 按显示名反查群 ID（精确匹配别名或缓存的群名）
+    pub fn find_id(&self, name: &str) -> Option<String> {
+        let aliases = self.aliases.read().unwrap_or_else(|e| e.into_inner());
+        if let Some((id, _)) = aliases.iter().find(|(_, v)| v.as_str() == name) {
+            return Some(id.clone());
+        }
+        drop(aliases);
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|(_, c)| c.name.as_deref() == Some(name))
+            .map(|(id, _)| id.clone())
+    }
+
+    /// 展示用文本：已知群名时为「群名 (ID)」，否则原样返回 ID
+    pub fn label(&self, chatroom_id: &str) -> String {
+        match self.cached(chatroom_id) {
+            Some(name) => format!("{} ({})", name, chatroom_id),
+            None => chatroom_id.to_string(),
+        }
+    }
+
+    fn is_fresh(&self, chatroom_id: &str) -> bool {
+        if self
+            .aliases
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(chatroom_id)
+        {
+            return true;
+        }
+        self.cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(chatroom_id)
+            .is_some_and(|c| c.expires_at > Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias_overrides_cache() {
+        let names = ChatroomNames::default();
+        names.insert("1@chatroom", "技术交流群");
+        assert_eq!(names.cached("1@chatroom").as_deref(), Some("技术交流群"));
+
+        names.set_aliases([("1@chatroom", "技术群"), ("2@chatroom", "")]);
+        assert_eq!(names.cached("1@chatroom").as_deref(), Some("技术群"));
+        assert_eq!(names.cached("2@chatroom"), None);
+        assert_eq!(names.label("1@chatroom"), "技术群 (1@chatroom)");
+        assert_eq!(names.label("3@chatroom"), "3@chatroom");
+
+        // Replacing the aliases falls back to the cached name
+        names.set_aliases(Vec::<(String, String)>::new());
+        assert_eq!(names.cached("1@chatroom").as_deref(), Some("技术交流群"));
+    }
+
+    #[test]
+    fn test_find_id() {
+        let names = ChatroomNames::default();
+        names.insert("1@chatroom", "技术交流群");
+        names.set_aliases([("2@chatroom", "运维群")]);
+        assert_eq!(names.find_id("运维群").as_deref(), Some("2@chatroom"));
+        assert_eq!(names.find_id("技术交流群").as_deref(), Some("1@chatroom"));
+        assert_eq!(names.find_id("不存在"), None);
+        // Aliases are not part of the persisted names
+        assert_eq!(
+            names.known(),
+            BTreeMap::from([("1@chatroom".to_string(), "技术交流群".to_string())])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_without_request() {
+        // Unreachable API: aliases, fresh cache and non-chatroom ids never hit it
+        let client = GeweHttpClient::new("token", "http://127.0.0.1:9").unwrap();
+        let names = ChatroomNames::default();
+        names.set_aliases([("1@chatroom", "技术群")]);
+        names.insert("2@chatroom", "运维群");
+        assert_eq!(
+            names
+                .resolve(&client, "wx_app", "1@chatroom")
+                .await
+                .as_deref(),
+            Some("技术群")
+        );
+        assert_eq!(
+            names
+                .resolve(&client, "wx_app", "2@chatroom")
+                .await
+                .as_deref(),
+            Some("运维群")
+        );
+        assert_eq!(names.resolve(&client, "wx_app", "wxid_alice").await, None);
+    }
+
+    /// Minimal HTTP server answering every request with the same chatroom info
+    fn mock_chatroom_info(data: serde_json::Value) -> String {
+        use std::io::{BufRead, BufReader, Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        len = v.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                let resp = serde_json::json!({"ret": 200, "msg": "ok", "data": data}).to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    resp.len(),
+                    resp
+                );
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_resolve_records_members() {
+        let base_url = mock_chatroom_info(serde_json::json!({
+            "chatroomId": "1@chatroom",
+            "nickName": "技术交流群",
+            "pyInitial": "", "quanPin": "", "sex": 0,
+            "chatRoomNotify": 1, "chatRoomOwner": "wxid_owner", "smallHeadImgUrl": "",
+            "memberList": [
+                {"wxid": "wxid_owner", "nickName": "群主", "memberFlag": 0, "displayName": "老王"},
+                {"wxid": "wxid_alice", "nickName": "Alice", "memberFlag": 0, "displayName": ""}
+            ]
+        }));
+        let client = GeweHttpClient::new("token", base_url).unwrap();
+        let names = ChatroomNames::default();
+        names.insert("1@chatroom", "旧群名");
+        assert_eq!(names.member_count("1@chatroom"), None);
+
+        let names = ChatroomNames::new(Duration::ZERO);
+        assert_eq!(
+            names
+                .resolve(&client, "wx_app", "1@chatroom")
+                .await
+                .as_deref(),
+            Some("技术交流群")
+        );
+        assert_eq!(names.member_count("1@chatroom"), Some(2));
+        assert_eq!(
+            names.member("1@chatroom", "wxid_owner"),
+            Some(ChatroomMemberName {
+                nick_name: "群主".to_string(),
+                display_name: Some("老王".to_string()),
+            })
+        );
+        assert_eq!(
+            names
+                .member("1@chatroom", "wxid_alice")
+                .and_then(|m| m.display_name),
+            None
+        );
+        // Renaming keeps the member info
+        names.insert("1@chatroom", "新群名");
+        assert_eq!(names.member_count("1@chatroom"), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_failure_keeps_stale_name() {
+        let client = GeweHttpClient::new("token", "http://127.0.0.1:9").unwrap();
+        let names = ChatroomNames::new(Duration::ZERO);
+        names.insert("1@chatroom", "旧群名");
+        assert_eq!(
+            names
+                .resolve(&client, "wx_app", "1@chatroom")
+                .await
+                .as_deref(),
+            Some("旧群名")
+        );
+        assert_eq!(names.resolve(&client, "wx_app", "2@chatroom").await, None);
+    }
+}
@@ -1,37 +1,133 @@
-use gewe_core::{ApiEnvelope, GeweError};
+use crate::message::batch::DEFAULT_BATCH_CONCURRENCY;
+use crate::rate_limit::{RateLimitPolicy, RateLimiter};
+use crate::receipts::SendReceipts;
+use crate::upload::MediaUploader;
+use gewe_core::{ApiEnvelope, GeweError, SendResponse};
 use reqwest::{Client, ClientBuilder};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
+use std::sync::Arc;
 use std::time::Duration;
 
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
 #[derive(Clone)]
 pub struct GeweHttpClient {
     client: Client,
     #[cfg_attr(test, allow(dead_code))]
     pub(crate) base_url: String,
+    /// 克隆的客户端共享同一组令牌桶
+    limiter: Option<Arc<RateLimiter>>,
+    pub(crate) batch_concurrency: usize,
+    /// 克隆的客户端共享发送回执
+    receipts: Arc<SendReceipts>,
+    /// `send_*_from_path` 上传本地文件的方式
+    pub(crate) uploader: Option<Arc<dyn MediaUploader>>,
 }
 
-impl GeweHttpClient {
-    pub fn new(token: impl Into<String>, base_url: impl Into<String>) -> Result<Self, GeweError> {
+/// 构造 [`GeweHttpClient`]，可设置请求超时与发送限流
+pub struct GeweHttpClientBuilder {
+    token: String,
+    base_url: String,
+    timeout: Duration,
+    rate_limit: Option<RateLimitPolicy>,
+    batch_concurrency: usize,
+    receipts: Option<(usize, usize)>,
+    uploader: Option<Arc<dyn MediaUploader>>,
+}
+
+impl GeweHttpClientBuilder {
+    /// 单次请求超时，默认 15 秒
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 按 appId 限制发送类接口（发送、转发消息与添加好友）的频率，默认不限流
+    pub fn rate_limit(mut self, policy: RateLimitPolicy) -> Self {
+        self.rate_limit = Some(policy);
+        self
+    }
+
+    /// 群发（`send_*_batch`）时同时进行的请求数，默认 5
+    pub fn batch_concurrency(mut self, concurrency: usize) -> Self {
+        self.batch_concurrency = concurrency;
+        self
+    }
+
+    /// 每个接收者保留的发送回执条数与最多记录的接收者数，默认 20 与 4096；传 0 不记录
+    pub fn receipt_capacity(mut self, per_recipient: usize, max_recipients: usize) -> Self {
+        self.receipts = Some((per_recipient, max_recipients));
+        self
+    }
+
+    /// 上传本地文件的方式，启用 `send_image_from_path` 等方法，默认不上传
+    pub fn uploader(mut self, uploader: impl MediaUploader + 'static) -> Self {
+        self.uploader = Some(Arc::new(uploader));
+        self
+    }
+
+    pub fn build(self) -> Result<GeweHttpClient, GeweError> {
         let mut headers = reqwest::header::HeaderMap::new();
-        let token = token.into();
         headers.insert(
             "X-GEWE-TOKEN",
-            reqwest::header::HeaderValue::from_str(&token)
+            reqwest::header::HeaderValue::from_str(&self.token)
                 .map_err(|e| GeweError::Http(e.to_string()))?,
         );
         let client = ClientBuilder::new()
             .default_headers(headers)
             .pool_idle_timeout(Duration::from_secs(90))
-            .timeout(Duration::from_secs(15))
+            .timeout(self.timeout)
             .build()
             .map_err(|e| GeweError::Http(e.to_string()))?;
-        Ok(Self {
+        Ok(GeweHttpClient {
             client,
-            base_url: base_url.into(),
+            base_url: self.base_url,
+            limiter: self.rate_limit.map(|p| Arc::new(RateLimiter::new(p))),
+            batch_concurrency: self.batch_concurrency,
+            receipts: Arc::new(
+                self.receipts
+                    .map(|(per, max)| SendReceipts::new(per, max))
+                    .unwrap_or_default(),
+            ),
+            uploader: self.uploader,
         })
     }
+}
+
+impl GeweHttpClient {
+    pub fn new(token: impl Into<String>, base_url: impl Into<String>) -> Result<Self, GeweError> {
+        Self::builder(token, base_url).build()
+    }
+
+    pub fn builder(token: impl Into<String>, base_url: impl Into<String>) -> GeweHttpClientBuilder {
+        GeweHttpClientBuilder {
+            token: token.into(),
+            base_url: base_url.into(),
+            timeout: DEFAULT_TIMEOUT,
+            rate_limit: None,
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            receipts: None,
+            uploader: None,
+        }
+    }
+
+    /// 最近的发送回执，见 [`SendReceipts`]
+    pub fn receipts(&self) -> &SendReceipts {
+        &self.receipts
+    }
+
+    /// 记录发送成功的回执
+    pub(crate) fn track<R: SendResponse>(&self, app_id: &str, resp: R) -> R {
+        self.receipts.record(app_id, resp.receipt());
+        resp
+    }
+
+    /// 当前的发送限流策略，未启用时为 None
+    pub fn rate_limit_policy(&self) -> Option<RateLimitPolicy> {
+        self.limiter.as_ref().map(|l| l.policy())
+    }
 
     pub(crate) fn endpoint(&self, path: &str) -> String {
         format!(
@@ -41,6 +137,23 @@ impl GeweHttpClient {
         )
     }
 
+    /// 发送类接口：先按 appId 取得限流令牌再请求
+    pub(crate) async fn post_send_api<B, R>(
+        &self,
+        app_id: &str,
+        path: &str,
+        body: &B,
+    ) -> Result<ApiEnvelope<R>, GeweError>
+    where
+        B: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(app_id).await;
+        }
+        self.post_api(path, body).await
+    }
+
     pub(crate) async fn post_api<B, R>(
         &self,
         path: &str,
@@ -205,6 +318,32 @@ mod tests {
         assert!(envelope.data.is_none());
     }
 
+    #[test]
+    fn test_client_builder_rate_limit() {
+        let client = GeweHttpClient::new("token", "https://api.example.com").unwrap();
+        assert_eq!(client.rate_limit_policy(), None);
+
+        let policy = RateLimitPolicy::new(Duration::from_secs(10), 5);
+        let client = GeweHttpClient::builder("token", "https://api.example.com")
+            .timeout(Duration::from_secs(30))
+            .rate_limit(policy)
+            .build()
+            .unwrap();
+        assert_eq!(client.rate_limit_policy(), Some(policy));
+        // 克隆共享令牌桶
+        let cloned = client.clone();
+        assert!(Arc::ptr_eq(
+            client.limiter.as_ref().unwrap(),
+            cloned.limiter.as_ref().unwrap()
+        ));
+
+        assert!(
+            GeweHttpClient::builder("bad\ntoken", "https://api.example.com")
+                .build()
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_empty_base_url() {
         let client = GeweHttpClient::new("token", "").expect("Failed to create client");
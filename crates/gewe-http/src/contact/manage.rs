@@ -9,7 +9,7 @@ impl GeweHttpClient {
     #[instrument(skip(self))]
     pub async fn add_contacts(&self, req: AddContactsRequest<'_>) -> Result<(), GeweError> {
         let _ = self
-            .post_api::<_, ()>("gewe/v2/api/contacts/addContacts", &req)
+            .post_send_api::<_, ()>(req.app_id, "gewe/v2/api/contacts/addContacts", &req)
             .await?;
         Ok(())
     }
@@ -1,15 +1,38 @@
+pub mod bot_manager;
+pub mod chatroom_members;
+pub mod chatroom_names;
 pub mod client;
 pub mod contact;
 pub mod favorite;
 pub mod group;
 pub mod login;
+pub mod login_flow;
 pub mod message;
 pub mod moments;
 pub mod personal;
+pub mod rate_limit;
+pub mod receipts;
 pub mod tag;
+pub mod upload;
 pub mod video_account;
 
-pub use client::GeweHttpClient;
+pub use bot_manager::BotManager;
+pub use chatroom_members::{
+    ChatroomMemberInfo, ChatroomMembers, ChatroomRoster, DEFAULT_CHATROOM_MEMBER_TTL,
+};
+pub use chatroom_names::{ChatroomMemberName, ChatroomNames, DEFAULT_CHATROOM_NAME_TTL};
+pub use client::{GeweHttpClient, GeweHttpClientBuilder};
+pub use login_flow::{
+    LoginFlow, LoginFlowError, LoginHooks, LoginOutcome, DEFAULT_LOGIN_POLL_INTERVAL,
+    DEFAULT_LOGIN_TIMEOUT,
+};
+pub use message::batch::{BatchReport, BatchResult, DEFAULT_BATCH_CONCURRENCY};
+pub use rate_limit::RateLimitPolicy;
+pub use receipts::{SendReceipts, DEFAULT_RECEIPTS_PER_RECIPIENT, DEFAULT_RECEIPT_RECIPIENTS};
+pub use upload::{
+    BotAppUploader, MediaUploader, S3Bucket, S3PresignedUploader, DEFAULT_PRESIGN_EXPIRES,
+    UPLOAD_PRESIGN_EXPIRES,
+};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,417 @@
+//! 扫码登录流程编排
+//!
+//! [`LoginFlow`] 依次完成获取登录二维码、轮询 `checkLogin` 直到登录成功（二维码过期时自动刷新）、
+//! 设置回调地址，并把 appId 与 token 写入 [`SessionStore`]。二维码展示、状态变化与验证码输入
+//! 通过 [`LoginHooks`] 交给调用方处理。
+
+use crate::client::GeweHttpClient;
+use async_trait::async_trait;
+use gewe_core::{
+    AppId, BotContext, CheckLoginRequest, CheckLoginResponse, GetLoginQrCodeRequest,
+    GetLoginQrCodeResponse, GeweError, SetCallbackRequest,
+};
+use gewe_session::SessionStore;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, instrument};
+
+/// 默认轮询间隔
+pub const DEFAULT_LOGIN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// 默认的整体超时，期间二维码过期会自动刷新
+pub const DEFAULT_LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `checkLogin` 返回的状态：已扫码、等待手机确认
+pub const LOGIN_STATUS_SCANNED: i32 = 1;
+/// `checkLogin` 返回的状态：登录成功
+pub const LOGIN_STATUS_LOGGED_IN: i32 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoginFlowError {
+    #[error(transparent)]
+    Gewe(#[from] GeweError),
+    #[error("login timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+/// 登录过程中的回调，方法均有空实现，按需覆盖
+#[async_trait]
+pub trait LoginHooks: Send + Sync {
+    /// 取得二维码后调用，二维码过期刷新时会再次调用
+    async fn on_qr_code(&self, _qr: &GetLoginQrCodeResponse) {}
+
+    /// 每次轮询 `checkLogin` 的结果
+    async fn on_status(&self, _status: &CheckLoginResponse) {}
+
+    /// 扫码后调用一次，返回手机上提示的验证码（异地登录时需要）；返回 `None` 表示无需验证码
+    async fn captcha_code(&self, _status: &CheckLoginResponse) -> Option<String> {
+        None
+    }
+}
+
+impl LoginHooks for () {}
+
+/// 登录成功的结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginOutcome {
+    pub app_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wxid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nick_name: Option<String>,
+    /// 是否已设置回调地址
+    pub callback_set: bool,
+    /// 最后一次 `checkLogin` 的完整响应
+    pub check: CheckLoginResponse,
+}
+
+/// 扫码登录流程
+pub struct LoginFlow {
+    client: GeweHttpClient,
+    token: String,
+    app_id: String,
+    device_type: String,
+    region_id: String,
+    proxy_ip: Option<String>,
+    auto_sliding: bool,
+    poll_interval: Duration,
+    timeout: Duration,
+    callback: Option<(String, String)>,
+    store: Option<Arc<dyn SessionStore>>,
+}
+
+impl LoginFlow {
+    /// `token` 为创建 `client` 所用的 GeWe token，写入 SessionStore 与设置回调时使用
+    pub fn new(client: GeweHttpClient, token: impl Into<String>) -> Self {
+        Self {
+            client,
+            token: token.into(),
+            app_id: String::new(),
+            device_type: "ipad".to_string(),
+            region_id: "320000".to_string(),
+            proxy_ip: None,
+            auto_sliding: true,
+            poll_interval: DEFAULT_LOGIN_POLL_INTERVAL,
+            timeout: DEFAULT_LOGIN_TIMEOUT,
+            callback: None,
+            store: None,
+        }
+    }
+
+    /// 重新登录已有设备时填写其 appId，首次登录留空
+    pub fn app_id(mut self, app_id: impl Into<String>) -> Self {
+        self.app_id = app_id.into();
+        self
+    }
+
+    /// 设备类型，默认 ipad
+    pub fn device_type(mut self, device_type: impl Into<String>) -> Self {
+        self.device_type = device_type.into();
+        self
+    }
+
+    /// 地区 ID，默认 320000
+    pub fn region_id(mut self, region_id: impl Into<String>) -> Self {
+        self.region_id = region_id.into();
+        self
+    }
+
+    pub fn proxy_ip(mut self, proxy_ip: impl Into<String>) -> Self {
+        self.proxy_ip = Some(proxy_ip.into());
+        self
+    }
+
+    /// 是否自动处理滑块验证，默认开启
+    pub fn auto_sliding(mut self, auto_sliding: bool) -> Self {
+        self.auto_sliding = auto_sliding;
+        self
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 登录成功后设置回调地址，`callback_token` 为回调请求携带的 token
+    pub fn callback(mut self, url: impl Into<String>, callback_token: impl Into<String>) -> Self {
+        self.callback = Some((url.into(), callback_token.into()));
+        self
+    }
+
+    /// 登录成功后把 appId 与 token 写入 SessionStore，已有记录的 webhook_secret 保持不变
+    pub fn session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    #[instrument(skip_all, fields(app_id = %self.app_id))]
+    pub async fn run(&self, hooks: &dyn LoginHooks) -> Result<LoginOutcome, LoginFlowError> {
+        let deadline = Instant::now() + self.timeout;
+        let mut qr = self.fetch_qr(&self.app_id).await?;
+        hooks.on_qr_code(&qr).await;
+        let mut captcha: Option<String> = None;
+        let mut captcha_asked = false;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(LoginFlowError::Timeout(self.timeout));
+            }
+            tokio::time::sleep(self.poll_interval).await;
+            let status = self
+                .client
+                .check_login(CheckLoginRequest {
+                    app_id: &qr.app_id,
+                    uuid: &qr.uuid,
+                    proxy_ip: self.proxy_ip.as_deref(),
+                    captch_code: captcha.as_deref(),
+                    auto_sliding: Some(self.auto_sliding),
+                })
+                .await?;
+            hooks.on_status(&status).await;
+            match status.status {
+                LOGIN_STATUS_LOGGED_IN => return self.finish(&qr.app_id, status).await,
+                LOGIN_STATUS_SCANNED if !captcha_asked => {
+                    captcha_asked = true;
+                    captcha = hooks.captcha_code(&status).await;
+                }
+                _ if status.expired_time.is_some_and(|secs| secs <= 0) => {
+                    info!(uuid = %qr.uuid, "login qr code expired, refreshing");
+                    qr = self.fetch_qr(&qr.app_id).await?;
+                    hooks.on_qr_code(&qr).await;
+                    captcha = None;
+                    captcha_asked = false;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn fetch_qr(&self, app_id: &str) -> Result<GetLoginQrCodeResponse, GeweError> {
+        self.client
+            .get_login_qr_code(GetLoginQrCodeRequest {
+                app_id,
+                r#type: &self.device_type,
+                region_id: &self.region_id,
+                proxy_ip: self.proxy_ip.as_deref(),
+                ttuid: None,
+                aid: None,
+            })
+            .await
+    }
+
+    async fn finish(
+        &self,
+        app_id: &str,
+        check: CheckLoginResponse,
+    ) -> Result<LoginOutcome, LoginFlowError> {
+        let info = check.login_info.as_ref();
+        let wxid = info.and_then(|info| info.wxid.clone());
+        let nick_name = info
+            .and_then(|info| info.nick_name.clone())
+            .or_else(|| check.nick_name.clone());
+        info!(app_id, ?wxid, "login succeeded");
+        if let Some(store) = &self.store {
+            let app = AppId(app_id.to_string());
+            let webhook_secret = store
+                .get_session(&app)
+                .await
+                .and_then(|context| context.webhook_secret);
+            store
+                .put_session(BotContext {
+                    app_id: app,
+                    token: self.token.clone(),
+                    webhook_secret,
+                    description: nick_name.clone(),
+                })
+                .await;
+        }
+        if let Some((url, callback_token)) = &self.callback {
+            self.client
+                .set_callback(SetCallbackRequest {
+                    token: callback_token,
+                    callback_url: url,
+                })
+                .await?;
+        }
+        Ok(LoginOutcome {
+            app_id: app_id.to_string(),
+            wxid,
+            nick_name,
+            callback_set: self.callback.is_some(),
+            check,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gewe_session::InMemorySessionStore;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    type Requests = Arc<Mutex<Vec<(String, Value)>>>;
+
+    /// Minimal HTTP server answering by request path; records `(path, body)` of every request
+    fn mock_gewe(respond: impl Fn(&str, usize) -> Value + Send + 'static) -> (String, Requests) {
+        use std::io::{BufRead, BufReader, Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        len = v.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                let calls = {
+                    let mut requests = recorded.lock().unwrap();
+                    let calls = requests.iter().filter(|(p, _)| *p == path).count();
+                    requests.push((path.clone(), serde_json::from_slice(&body).unwrap()));
+                    calls
+                };
+                let data = respond(&path, calls);
+                let resp = json!({"ret": 200, "msg": "ok", "data": data}).to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    resp.len(),
+                    resp
+                );
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    fn qr(uuid: &str) -> Value {
+        json!({"qrData": "http://weixin.qq.com/x/1", "qrImgBase64": "", "uuid": uuid, "appId": "wx_app"})
+    }
+
+    #[derive(Default)]
+    struct RecordingHooks {
+        qr_codes: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LoginHooks for RecordingHooks {
+        async fn on_qr_code(&self, qr: &GetLoginQrCodeResponse) {
+            self.qr_codes.lock().unwrap().push(qr.uuid.clone());
+        }
+
+        async fn captcha_code(&self, _status: &CheckLoginResponse) -> Option<String> {
+            Some("123456".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_flow_refreshes_qr_and_persists() {
+        let (base_url, requests) = mock_gewe(|path, calls| {
+            if path.ends_with("getLoginQrCode") {
+                qr(&format!("uuid-{}", calls))
+            } else if path.ends_with("checkLogin") {
+                match calls {
+                    0 => json!({"uuid": "uuid-0", "status": 0, "expiredTime": 0}),
+                    1 => json!({"uuid": "uuid-1", "status": 1, "expiredTime": 100}),
+                    _ => json!({
+                        "uuid": "uuid-1", "status": 2,
+                        "loginInfo": {"wxid": "wxid_bot", "nickName": "小助手"}
+                    }),
+                }
+            } else {
+                Value::Null
+            }
+        });
+        let store = Arc::new(InMemorySessionStore::default());
+        let client = GeweHttpClient::new("gewe-token", base_url).unwrap();
+        let hooks = RecordingHooks::default();
+        let outcome = LoginFlow::new(client, "gewe-token")
+            .poll_interval(Duration::from_millis(1))
+            .callback("https://bot.example.com/webhook", "gewe-token")
+            .session_store(store.clone())
+            .run(&hooks)
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.app_id, "wx_app");
+        assert_eq!(outcome.wxid.as_deref(), Some("wxid_bot"));
+        assert_eq!(outcome.nick_name.as_deref(), Some("小助手"));
+        assert!(outcome.callback_set);
+        assert_eq!(*hooks.qr_codes.lock().unwrap(), ["uuid-0", "uuid-1"]);
+
+        let requests = requests.lock().unwrap().clone();
+        let paths: Vec<_> = requests
+            .iter()
+            .map(|(p, _)| p.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "getLoginQrCode",
+                "checkLogin",
+                "getLoginQrCode",
+                "checkLogin",
+                "checkLogin",
+                "setCallback"
+            ]
+        );
+        // 刷新二维码沿用首次返回的 appId，扫码后带上验证码
+        assert_eq!(requests[2].1["appId"], "wx_app");
+        assert!(requests[3].1.get("captchCode").is_none());
+        assert_eq!(requests[4].1["captchCode"], "123456");
+        assert_eq!(
+            requests[5].1["callbackUrl"],
+            "https://bot.example.com/webhook"
+        );
+
+        let context = store
+            .get_session(&AppId("wx_app".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(context.token, "gewe-token");
+        assert_eq!(context.description.as_deref(), Some("小助手"));
+    }
+
+    #[tokio::test]
+    async fn test_login_flow_times_out() {
+        let (base_url, _) = mock_gewe(|path, _| {
+            if path.ends_with("getLoginQrCode") {
+                qr("uuid-0")
+            } else {
+                json!({"uuid": "uuid-0", "status": 0, "expiredTime": 100})
+            }
+        });
+        let client = GeweHttpClient::new("token", base_url).unwrap();
+        let err = LoginFlow::new(client, "token")
+            .poll_interval(Duration::from_millis(1))
+            .timeout(Duration::from_millis(30))
+            .run(&())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LoginFlowError::Timeout(_)));
+    }
+}
@@ -0,0 +1,283 @@
+//! 群发：同一条消息并发发给多个接收者，并逐个返回发送结果
+//!
+//! 同时进行的请求数由 [`GeweHttpClientBuilder::batch_concurrency`] 控制，
+//! 启用限流时每条消息仍会按 appId 取得令牌。
+//!
+//! [`GeweHttpClientBuilder::batch_concurrency`]: crate::GeweHttpClientBuilder::batch_concurrency
+
+use crate::client::GeweHttpClient;
+use gewe_core::{GeweError, PostFileResponse, PostImageResponse, SendTextResponse};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::instrument;
+
+/// 默认同时进行的发送请求数
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 5;
+
+/// 单个接收者的发送结果
+#[derive(Debug)]
+pub struct BatchResult<T> {
+    pub to_wxid: String,
+    pub result: Result<T, GeweError>,
+}
+
+/// 群发结果，顺序与传入的接收者一致
+#[derive(Debug)]
+pub struct BatchReport<T> {
+    pub results: Vec<BatchResult<T>>,
+}
+
+impl<T> BatchReport<T> {
+    pub fn success_count(&self) -> usize {
+        self.results.iter().filter(|r| r.result.is_ok()).count()
+    }
+
+    pub fn failure_count(&self) -> usize {
+        self.results.len() - self.success_count()
+    }
+
+    pub fn is_all_ok(&self) -> bool {
+        self.results.iter().all(|r| r.result.is_ok())
+    }
+
+    /// 发送失败的接收者及错误
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &GeweError)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.result.as_ref().err().map(|e| (r.to_wxid.as_str(), e)))
+    }
+}
+
+impl GeweHttpClient {
+    /// 向多个接收者发送同一条文本
+    #[instrument(skip(self, recipients), fields(recipients = recipients.len()))]
+    pub async fn send_text_batch(
+        &self,
+        app_id: &str,
+        recipients: &[&str],
+        content: &str,
+    ) -> BatchReport<SendTextResponse> {
+        let app_id = app_id.to_string();
+        let content = content.to_string();
+        self.send_batch(recipients, move |client, to_wxid| {
+            let app_id = app_id.clone();
+            let content = content.clone();
+            async move { client.send_text(&app_id, &to_wxid, &content, None).await }
+        })
+        .await
+    }
+
+    /// 向多个接收者发送同一张图片
+    #[instrument(skip(self, recipients), fields(recipients = recipients.len()))]
+    pub async fn send_image_batch(
+        &self,
+        app_id: &str,
+        recipients: &[&str],
+        img_url: &str,
+    ) -> BatchReport<PostImageResponse> {
+        let app_id = app_id.to_string();
+        let img_url = img_url.to_string();
+        self.send_batch(recipients, move |client, to_wxid| {
+            let app_id = app_id.clone();
+            let img_url = img_url.clone();
+            async move { client.send_image(&app_id, &to_wxid, &img_url).await }
+        })
+        .await
+    }
+
+    /// 向多个接收者发送同一个文件
+    #[instrument(skip(self, recipients), fields(recipients = recipients.len()))]
+    pub async fn send_file_batch(
+        &self,
+        app_id: &str,
+        recipients: &[&str],
+        file_url: &str,
+        file_name: &str,
+    ) -> BatchReport<PostFileResponse> {
+        let app_id = app_id.to_string();
+        let file_url = file_url.to_string();
+        let file_name = file_name.to_string();
+        self.send_batch(recipients, move |client, to_wxid| {
+            let app_id = app_id.clone();
+            let file_url = file_url.clone();
+            let file_name = file_name.clone();
+            async move {
+                client
+                    .send_file(&app_id, &to_wxid, &file_url, &file_name)
+                    .await
+            }
+        })
+        .await
+    }
+
+    async fn send_batch<T, F, Fut>(&self, recipients: &[&str], send: F) -> BatchReport<T>
+    where
+        T: Send + 'static,
+        F: Fn(GeweHttpClient, String) -> Fut,
+        Fut: Future<Output = Result<T, GeweError>> + Send + 'static,
+    {
+        let semaphore = Arc::new(Semaphore::new(self.batch_concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+        for (idx, to_wxid) in recipients.iter().enumerate() {
+            let fut = send(self.clone(), to_wxid.to_string());
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                (idx, fut.await)
+            });
+        }
+
+        let mut slots: Vec<Option<Result<T, GeweError>>> =
+            recipients.iter().map(|_| None).collect();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((idx, result)) => slots[idx] = Some(result),
+                Err(err) => tracing::warn!(?err, "群发任务异常退出"),
+            }
+        }
+        let results = recipients
+            .iter()
+            .zip(slots)
+            .map(|(to_wxid, slot)| BatchResult {
+                to_wxid: to_wxid.to_string(),
+                result: slot
+                    .unwrap_or_else(|| Err(GeweError::Http("send task aborted".to_string()))),
+            })
+            .collect();
+        BatchReport { results }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Minimal HTTP server: replies with ret=200 for every recipient except
+    /// `wxid_bad`, and records the peak number of in-flight requests.
+    /// `revokeMsg` always succeeds without data.
+    fn mock_server(delay_ms: u64) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let peak_out = peak.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+                std::thread::spawn(move || {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(current, Ordering::SeqCst);
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut len = 0;
+                    let mut revoke = false;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line.starts_with("POST ") {
+                            revoke = line.contains("revokeMsg");
+                        }
+                        if line == "\r\n" || line.is_empty() {
+                            break;
+                        }
+                        if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                            len = v.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; len];
+                    reader.read_exact(&mut body).unwrap();
+                    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    let to = body["toWxid"].as_str().unwrap_or_default().to_string();
+                    std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+                    let resp = if revoke {
+                        serde_json::json!({"ret": 200, "msg": "ok"})
+                    } else if to == "wxid_bad" {
+                        serde_json::json!({"ret": 500, "msg": "not friend"})
+                    } else {
+                        serde_json::json!({"ret": 200, "msg": "ok", "data": {
+                            "toWxid": to, "createTime": 1, "msgId": 1, "newMsgId": 2, "type": 1
+                        }})
+                    };
+                    let resp = resp.to_string();
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        resp.len(),
+                        resp
+                    );
+                });
+            }
+        });
+        (format!("http://{}", addr), peak_out)
+    }
+
+    #[tokio::test]
+    async fn test_send_text_batch_reports_each_recipient() {
+        let (base_url, _) = mock_server(0);
+        let client = GeweHttpClient::new("token", base_url).unwrap();
+        let report = client
+            .send_text_batch("wx_app", &["wxid_a", "wxid_bad", "wxid_c"], "通知")
+            .await;
+
+        let order: Vec<_> = report.results.iter().map(|r| r.to_wxid.as_str()).collect();
+        assert_eq!(order, ["wxid_a", "wxid_bad", "wxid_c"]);
+        assert_eq!(report.success_count(), 2);
+        assert_eq!(report.failure_count(), 1);
+        assert!(!report.is_all_ok());
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "wxid_bad");
+        assert!(matches!(failures[0].1, GeweError::Api { code: 500, .. }));
+        assert_eq!(report.results[2].result.as_ref().unwrap().to_wxid, "wxid_c");
+    }
+
+    #[tokio::test]
+    async fn test_send_batch_respects_concurrency() {
+        let (base_url, peak) = mock_server(50);
+        let client = GeweHttpClient::builder("token", base_url)
+            .batch_concurrency(2)
+            .build()
+            .unwrap();
+        let recipients = ["wxid_1", "wxid_2", "wxid_3", "wxid_4", "wxid_5"];
+        let report = client.send_text_batch("wx_app", &recipients, "hi").await;
+        assert!(report.is_all_ok());
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+
+        let empty = client.send_text_batch("wx_app", &[], "hi").await;
+        assert!(empty.results.is_empty());
+        assert!(empty.is_all_ok());
+    }
+
+    #[tokio::test]
+    async fn test_batch_records_receipts_and_revoke_last() {
+        let (base_url, _) = mock_server(0);
+        let client = GeweHttpClient::new("token", base_url).unwrap();
+        client
+            .send_text_batch("wx_app", &["wxid_a", "wxid_bad"], "通知")
+            .await;
+
+        let receipt = client.receipts().last("wx_app", "wxid_a").unwrap();
+        assert_eq!((receipt.msg_id, receipt.new_msg_id), (1, 2));
+        assert!(client.receipts().last("wx_app", "wxid_bad").is_none());
+        // 克隆的客户端共享回执
+        let cloned = client.clone();
+        assert_eq!(
+            cloned.receipts().last("wx_app", "wxid_a"),
+            Some(receipt.clone())
+        );
+
+        assert_eq!(
+            client.revoke_last("wx_app", "wxid_a").await.unwrap(),
+            Some(receipt)
+        );
+        assert!(client.receipts().last("wx_app", "wxid_a").is_none());
+        assert_eq!(client.revoke_last("wx_app", "wxid_a").await.unwrap(), None);
+    }
+}
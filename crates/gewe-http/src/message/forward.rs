@@ -1,8 +1,8 @@
 use crate::client::GeweHttpClient;
 use gewe_core::{
-    ForwardFileRequest, ForwardFileResponse, ForwardImageRequest, ForwardImageResponse,
-    ForwardMiniAppRequest, ForwardMiniAppResponse, ForwardUrlRequest, ForwardUrlResponse,
-    ForwardVideoRequest, ForwardVideoResponse, GeweError,
+    rewrite_mini_app_cover, ForwardFileRequest, ForwardFileResponse, ForwardImageRequest,
+    ForwardImageResponse, ForwardMiniAppRequest, ForwardMiniAppResponse, ForwardUrlRequest,
+    ForwardUrlResponse, ForwardVideoRequest, ForwardVideoResponse, GeweError,
 };
 use tracing::instrument;
 
@@ -20,9 +20,15 @@ impl GeweHttpClient {
             xml,
         };
         let env = self
-            .post_api::<_, ForwardImageResponse>("gewe/v2/api/message/forwardImage", &body)
+            .post_send_api::<_, ForwardImageResponse>(
+                app_id,
+                "gewe/v2/api/message/forwardImage",
+                &body,
+            )
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -38,9 +44,15 @@ impl GeweHttpClient {
             xml,
         };
         let env = self
-            .post_api::<_, ForwardVideoResponse>("gewe/v2/api/message/forwardVideo", &body)
+            .post_send_api::<_, ForwardVideoResponse>(
+                app_id,
+                "gewe/v2/api/message/forwardVideo",
+                &body,
+            )
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -56,9 +68,15 @@ impl GeweHttpClient {
             xml,
         };
         let env = self
-            .post_api::<_, ForwardFileResponse>("gewe/v2/api/message/forwardFile", &body)
+            .post_send_api::<_, ForwardFileResponse>(
+                app_id,
+                "gewe/v2/api/message/forwardFile",
+                &body,
+            )
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -76,9 +94,29 @@ impl GeweHttpClient {
             cover_img_url,
         };
         let env = self
-            .post_api::<_, ForwardMiniAppResponse>("gewe/v2/api/message/forwardMiniApp", &body)
+            .post_send_api::<_, ForwardMiniAppResponse>(
+                app_id,
+                "gewe/v2/api/message/forwardMiniApp",
+                &body,
+            )
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
+    }
+
+    /// 跨会话转发小程序卡片并替换封面：先改写 XML 中的封面字段，再调用 forwardMiniApp
+    #[instrument(skip(self, xml))]
+    pub async fn forward_mini_app_with_cover(
+        &self,
+        app_id: &str,
+        to_wxid: &str,
+        xml: &str,
+        cover_img_url: &str,
+    ) -> Result<ForwardMiniAppResponse, GeweError> {
+        let xml = rewrite_mini_app_cover(xml, cover_img_url);
+        self.forward_mini_app(app_id, to_wxid, &xml, cover_img_url)
+            .await
     }
 
     #[instrument(skip(self))]
@@ -94,9 +132,11 @@ impl GeweHttpClient {
             xml,
         };
         let env = self
-            .post_api::<_, ForwardUrlResponse>("gewe/v2/api/message/forwardUrl", &body)
+            .post_send_api::<_, ForwardUrlResponse>(app_id, "gewe/v2/api/message/forwardUrl", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 }
 
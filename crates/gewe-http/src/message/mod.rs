@@ -1,3 +1,4 @@
+pub mod batch;
 pub mod download;
 pub mod forward;
 pub mod revoke;
@@ -1,5 +1,5 @@
 use crate::client::GeweHttpClient;
-use gewe_core::{GeweError, RevokeMessageRequest};
+use gewe_core::{GeweError, RevokeMessageRequest, SendReceipt};
 use tracing::instrument;
 
 impl GeweHttpClient {
@@ -22,8 +22,42 @@ impl GeweHttpClient {
         let _ = self
             .post_api::<_, ()>("gewe/v2/api/message/revokeMsg", &body)
             .await?;
+        if let Ok(new_msg_id) = new_msg_id.parse() {
+            self.receipts().remove(app_id, to_wxid, new_msg_id);
+        }
         Ok(())
     }
+
+    /// 按发送回执撤回消息
+    #[instrument(skip(self))]
+    pub async fn revoke_receipt(
+        &self,
+        app_id: &str,
+        receipt: &SendReceipt,
+    ) -> Result<(), GeweError> {
+        self.revoke_message(
+            app_id,
+            &receipt.to_wxid,
+            &receipt.msg_id.to_string(),
+            &receipt.new_msg_id.to_string(),
+            &receipt.create_time.to_string(),
+        )
+        .await
+    }
+
+    /// 撤回最后一条发给该接收者的消息，没有发送记录时返回 `Ok(None)`
+    #[instrument(skip(self))]
+    pub async fn revoke_last(
+        &self,
+        app_id: &str,
+        to_wxid: &str,
+    ) -> Result<Option<SendReceipt>, GeweError> {
+        let Some(receipt) = self.receipts().last(app_id, to_wxid) else {
+            return Ok(None);
+        };
+        self.revoke_receipt(app_id, &receipt).await?;
+        Ok(Some(receipt))
+    }
 }
 
 #[cfg(test)]
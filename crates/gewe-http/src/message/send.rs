@@ -1,10 +1,10 @@
 use crate::client::GeweHttpClient;
 use gewe_core::{
-    GeweError, PostAppMsgRequest, PostAppMsgResponse, PostEmojiRequest, PostEmojiResponse,
-    PostFileRequest, PostFileResponse, PostImageRequest, PostImageResponse, PostLinkRequest,
-    PostLinkResponse, PostMiniAppRequest, PostMiniAppResponse, PostNameCardRequest,
-    PostNameCardResponse, PostVideoRequest, PostVideoResponse, PostVoiceRequest, PostVoiceResponse,
-    SendTextRequest, SendTextResponse,
+    GeweError, MiniAppCard, PostAppMsgRequest, PostAppMsgResponse, PostEmojiRequest,
+    PostEmojiResponse, PostFileRequest, PostFileResponse, PostImageRequest, PostImageResponse,
+    PostLinkRequest, PostLinkResponse, PostMiniAppRequest, PostMiniAppResponse,
+    PostNameCardRequest, PostNameCardResponse, PostVideoRequest, PostVideoResponse,
+    PostVoiceRequest, PostVoiceResponse, SendTextRequest, SendTextResponse,
 };
 use tracing::instrument;
 
@@ -24,9 +24,11 @@ impl GeweHttpClient {
             ats,
         };
         let env = self
-            .post_api::<_, SendTextResponse>("gewe/v2/api/message/postText", &body)
+            .post_send_api::<_, SendTextResponse>(app_id, "gewe/v2/api/message/postText", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -42,9 +44,11 @@ impl GeweHttpClient {
             img_url,
         };
         let env = self
-            .post_api::<_, PostImageResponse>("gewe/v2/api/message/postImage", &body)
+            .post_send_api::<_, PostImageResponse>(app_id, "gewe/v2/api/message/postImage", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -62,9 +66,11 @@ impl GeweHttpClient {
             voice_duration,
         };
         let env = self
-            .post_api::<_, PostVoiceResponse>("gewe/v2/api/message/postVoice", &body)
+            .post_send_api::<_, PostVoiceResponse>(app_id, "gewe/v2/api/message/postVoice", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -84,9 +90,11 @@ impl GeweHttpClient {
             video_duration,
         };
         let env = self
-            .post_api::<_, PostVideoResponse>("gewe/v2/api/message/postVideo", &body)
+            .post_send_api::<_, PostVideoResponse>(app_id, "gewe/v2/api/message/postVideo", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -104,9 +112,11 @@ impl GeweHttpClient {
             file_name,
         };
         let env = self
-            .post_api::<_, PostFileResponse>("gewe/v2/api/message/postFile", &body)
+            .post_send_api::<_, PostFileResponse>(app_id, "gewe/v2/api/message/postFile", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -128,9 +138,11 @@ impl GeweHttpClient {
             thumb_url,
         };
         let env = self
-            .post_api::<_, PostLinkResponse>("gewe/v2/api/message/postLink", &body)
+            .post_send_api::<_, PostLinkResponse>(app_id, "gewe/v2/api/message/postLink", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -148,9 +160,11 @@ impl GeweHttpClient {
             emoji_size,
         };
         let env = self
-            .post_api::<_, PostEmojiResponse>("gewe/v2/api/message/postEmoji", &body)
+            .post_send_api::<_, PostEmojiResponse>(app_id, "gewe/v2/api/message/postEmoji", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -166,9 +180,11 @@ impl GeweHttpClient {
             appmsg,
         };
         let env = self
-            .post_api::<_, PostAppMsgResponse>("gewe/v2/api/message/postAppMsg", &body)
+            .post_send_api::<_, PostAppMsgResponse>(app_id, "gewe/v2/api/message/postAppMsg", &body)
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -195,9 +211,36 @@ impl GeweHttpClient {
             user_name,
         };
         let env = self
-            .post_api::<_, PostMiniAppResponse>("gewe/v2/api/message/postMiniApp", &body)
+            .post_send_api::<_, PostMiniAppResponse>(
+                app_id,
+                "gewe/v2/api/message/postMiniApp",
+                &body,
+            )
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
+    }
+
+    /// 发送经过校验的小程序卡片（见 [`gewe_core::MiniAppCard`]）
+    #[instrument(skip(self))]
+    pub async fn send_mini_app_card(
+        &self,
+        app_id: &str,
+        to_wxid: &str,
+        card: &MiniAppCard,
+    ) -> Result<PostMiniAppResponse, GeweError> {
+        let body = card.to_request(app_id, to_wxid);
+        let env = self
+            .post_send_api::<_, PostMiniAppResponse>(
+                app_id,
+                "gewe/v2/api/message/postMiniApp",
+                &body,
+            )
+            .await?;
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 
     #[instrument(skip(self))]
@@ -215,9 +258,15 @@ impl GeweHttpClient {
             name_card_wxid,
         };
         let env = self
-            .post_api::<_, PostNameCardResponse>("gewe/v2/api/message/postNameCard", &body)
+            .post_send_api::<_, PostNameCardResponse>(
+                app_id,
+                "gewe/v2/api/message/postNameCard",
+                &body,
+            )
             .await?;
-        env.data.ok_or(GeweError::MissingData)
+        env.data
+            .ok_or(GeweError::MissingData)
+            .map(|resp| self.track(app_id, resp))
     }
 }
 
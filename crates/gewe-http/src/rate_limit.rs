@@ -0,0 +1,153 @@
+//! 按 appId 限制发送频率的令牌桶
+//!
+//! 每个 appId 一个桶，容量为 `max_per_window`，在 `window` 内匀速补满；
+//! 桶空时发送方法等待下一个令牌，而不是直接报错。
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// 发送限流策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    /// 补满一次令牌桶所需的时间
+    pub window: Duration,
+    /// 窗口内允许的发送次数，同时也是可突发的上限
+    pub max_per_window: u32,
+    /// 每次发送前附加的随机延迟上限，避免固定节奏被识别为机器行为
+    pub max_jitter: Duration,
+}
+
+impl RateLimitPolicy {
+    pub fn new(window: Duration, max_per_window: u32) -> Self {
+        Self {
+            window,
+            max_per_window,
+            max_jitter: Duration::ZERO,
+        }
+    }
+
+    pub fn with_jitter(mut self, max_jitter: Duration) -> Self {
+        self.max_jitter = max_jitter;
+        self
+    }
+
+    /// 每秒补充的令牌数
+    fn refill_per_sec(&self) -> f64 {
+        self.max_per_window as f64 / self.window.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl Default for RateLimitPolicy {
+    /// 每分钟 40 条，附加最多 300ms 随机延迟
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60), 40).with_jitter(Duration::from_millis(300))
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    policy: RateLimitPolicy,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(policy: RateLimitPolicy) -> Self {
+        Self {
+            policy,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> RateLimitPolicy {
+        self.policy
+    }
+
+    /// 取走一个令牌，桶空时等待补充
+    pub(crate) async fn acquire(&self, app_id: &str) {
+        if self.policy.max_per_window == 0 {
+            return;
+        }
+        let capacity = self.policy.max_per_window as f64;
+        let rate = self.policy.refill_per_sec();
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().await;
+                let now = Instant::now();
+                let bucket = buckets.entry(app_id.to_string()).or_insert(Bucket {
+                    tokens: capacity,
+                    updated: now,
+                });
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+                bucket.updated = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    None
+                } else {
+                    Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+                }
+            };
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => break,
+            }
+        }
+
+        let max_jitter = self.policy.max_jitter.as_millis() as u64;
+        if max_jitter > 0 {
+            let jitter = rand::rng().random_range(0..=max_jitter);
+            if jitter > 0 {
+                tokio::time::sleep(Duration::from_millis(jitter)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_default() {
+        let policy = RateLimitPolicy::default();
+        assert_eq!(policy.window, Duration::from_secs(60));
+        assert_eq!(policy.max_per_window, 40);
+        assert_eq!(policy.max_jitter, Duration::from_millis(300));
+        assert_eq!(
+            RateLimitPolicy::new(Duration::from_secs(1), 5).max_jitter,
+            Duration::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_per_app_id() {
+        let limiter = RateLimiter::new(RateLimitPolicy::new(Duration::from_millis(400), 2));
+        let start = Instant::now();
+        limiter.acquire("wx_a").await;
+        limiter.acquire("wx_a").await;
+        // 其他 appId 有独立的令牌桶
+        limiter.acquire("wx_b").await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // 桶空后等待补充一个令牌（400ms / 2 = 200ms）
+        limiter.acquire("wx_a").await;
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(190), "{:?}", waited);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_disabled_when_zero() {
+        let limiter = RateLimiter::new(RateLimitPolicy::new(Duration::from_secs(60), 0));
+        for _ in 0..100 {
+            limiter.acquire("wx_a").await;
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! 发送回执
+//!
+//! 发送与转发成功后按 (appId, 接收者) 记录回执，撤回“最后一条发给某人的消息”时无需调用方自己保存
+//! msgId、newMsgId 与 createTime。每个接收者保留最近的若干条，接收者过多时淘汰最久未发送的。
+
+use gewe_core::SendReceipt;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// 每个接收者默认保留的回执条数
+pub const DEFAULT_RECEIPTS_PER_RECIPIENT: usize = 20;
+/// 默认最多记录的接收者数
+pub const DEFAULT_RECEIPT_RECIPIENTS: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// (appId, 接收者) → 回执，新的在后
+    receipts: HashMap<(String, String), VecDeque<SendReceipt>>,
+    /// 记录顺序，用于淘汰最久未发送的接收者
+    seq: u64,
+    touched: HashMap<(String, String), u64>,
+}
+
+/// 最近的发送回执，克隆的客户端共享同一份记录
+#[derive(Debug)]
+pub struct SendReceipts {
+    per_recipient: usize,
+    max_recipients: usize,
+    inner: Mutex<Inner>,
+}
+
+impl Default for SendReceipts {
+    fn default() -> Self {
+        Self::new(DEFAULT_RECEIPTS_PER_RECIPIENT, DEFAULT_RECEIPT_RECIPIENTS)
+    }
+}
+
+impl SendReceipts {
+    pub fn new(per_recipient: usize, max_recipients: usize) -> Self {
+        Self {
+            per_recipient,
+            max_recipients,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// 记录一条回执；容量为 0 时不记录
+    pub fn record(&self, app_id: &str, receipt: SendReceipt) {
+        if self.per_recipient == 0 || self.max_recipients == 0 {
+            return;
+        }
+        let key = (app_id.to_string(), receipt.to_wxid.clone());
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if !inner.receipts.contains_key(&key) && inner.receipts.len() >= self.max_recipients {
+            if let Some(oldest) = inner
+                .touched
+                .iter()
+                .min_by_key(|(_, seq)| **seq)
+                .map(|(k, _)| k.clone())
+            {
+                inner.receipts.remove(&oldest);
+                inner.touched.remove(&oldest);
+            }
+        }
+        inner.seq += 1;
+        let seq = inner.seq;
+        inner.touched.insert(key.clone(), seq);
+        let list = inner.receipts.entry(key).or_default();
+        if list.len() >= self.per_recipient {
+            list.pop_front();
+        }
+        list.push_back(receipt);
+    }
+
+    /// 最后一条发给该接收者的消息
+    pub fn last(&self, app_id: &str, to_wxid: &str) -> Option<SendReceipt> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner
+            .receipts
+            .get(&(app_id.to_string(), to_wxid.to_string()))
+            .and_then(|list| list.back().cloned())
+    }
+
+    /// 发给该接收者的消息，新的在前
+    pub fn list(&self, app_id: &str, to_wxid: &str) -> Vec<SendReceipt> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner
+            .receipts
+            .get(&(app_id.to_string(), to_wxid.to_string()))
+            .map(|list| list.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// 按 newMsgId 移除一条回执（如已撤回），返回是否存在
+    pub fn remove(&self, app_id: &str, to_wxid: &str, new_msg_id: i64) -> bool {
+        let key = (app_id.to_string(), to_wxid.to_string());
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(list) = inner.receipts.get_mut(&key) else {
+            return false;
+        };
+        let before = list.len();
+        list.retain(|r| r.new_msg_id != new_msg_id);
+        let removed = list.len() != before;
+        if list.is_empty() {
+            inner.receipts.remove(&key);
+            inner.touched.remove(&key);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(to: &str, new_msg_id: i64) -> SendReceipt {
+        SendReceipt {
+            to_wxid: to.to_string(),
+            msg_id: new_msg_id,
+            new_msg_id,
+            create_time: 1_700_000_000 + new_msg_id,
+        }
+    }
+
+    #[test]
+    fn test_receipts_last_and_remove() {
+        let receipts = SendReceipts::new(2, 10);
+        receipts.record("wx_a", receipt("wxid_1", 1));
+        receipts.record("wx_a", receipt("wxid_1", 2));
+        receipts.record("wx_a", receipt("wxid_1", 3));
+        receipts.record("wx_b", receipt("wxid_1", 9));
+
+        assert_eq!(receipts.last("wx_a", "wxid_1"), Some(receipt("wxid_1", 3)));
+        let ids: Vec<_> = receipts
+            .list("wx_a", "wxid_1")
+            .iter()
+            .map(|r| r.new_msg_id)
+            .collect();
+        assert_eq!(ids, [3, 2]);
+
+        assert!(receipts.remove("wx_a", "wxid_1", 3));
+        assert!(!receipts.remove("wx_a", "wxid_1", 3));
+        assert_eq!(receipts.last("wx_a", "wxid_1"), Some(receipt("wxid_1", 2)));
+        assert!(receipts.remove("wx_a", "wxid_1", 2));
+        assert_eq!(receipts.last("wx_a", "wxid_1"), None);
+        assert_eq!(receipts.last("wx_b", "wxid_1"), Some(receipt("wxid_1", 9)));
+    }
+
+    #[test]
+    fn test_receipts_evict_least_recent_recipient() {
+        let receipts = SendReceipts::new(5, 2);
+        receipts.record("wx_a", receipt("wxid_1", 1));
+        receipts.record("wx_a", receipt("wxid_2", 2));
+        receipts.record("wx_a", receipt("wxid_1", 3));
+        receipts.record("wx_a", receipt("wxid_3", 4));
+
+        assert!(receipts.last("wx_a", "wxid_2").is_none());
+        assert!(receipts.last("wx_a", "wxid_1").is_some());
+        assert!(receipts.last("wx_a", "wxid_3").is_some());
+
+        let disabled = SendReceipts::new(0, 10);
+        disabled.record("wx_a", receipt("wxid_1", 1));
+        assert!(disabled.last("wx_a", "wxid_1").is_none());
+    }
+}
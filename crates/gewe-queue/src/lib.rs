@@ -0,0 +1,29 @@
+//! gewe 的持久化发送队列
+//!
+//! 待发送的消息写入 SQLite，可指定 `send_at` 定时发送。[`SendQueue::drain`] 按发送时间
+//! 顺序取出到期的消息逐条发送，经由 [`GeweHttpClient`] 的限流策略取令牌；发送失败按
+//! [`RetryPolicy`] 退避重试，超过次数后标记为失败。CLI 的 `schedule-send` 与
+//! gewe-bot-app 可以共用同一个队列文件：
+//!
+//! ```no_run
+//! # async fn demo(client: gewe_http::GeweHttpClient) -> Result<(), gewe_queue::QueueError> {
+//! use gewe_queue::{QueuedMessage, SendQueue};
+//!
+//! let queue = SendQueue::open("data/send_queue.db").await?;
+//! let at = chrono::Utc::now() + chrono::Duration::hours(1);
+//! queue
+//!     .enqueue("wx_app", "123@chatroom", &QueuedMessage::text("一小时后开会"), Some(at))
+//!     .await?;
+//! let report = queue.drain(chrono::Utc::now(), |_| Some(&client)).await?;
+//! println!("已发送 {} 条", report.sent);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`GeweHttpClient`]: gewe_http::GeweHttpClient
+
+mod message;
+mod queue;
+
+pub use message::QueuedMessage;
+pub use queue::{DrainReport, QueueError, QueuedSend, RetryPolicy, SendQueue, SendStatus};
@@ -0,0 +1,86 @@
+//! 队列中的消息内容
+
+use gewe_core::GeweError;
+use gewe_http::GeweHttpClient;
+use serde::{Deserialize, Serialize};
+
+/// 待发送的消息，以 JSON 存入队列
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueuedMessage {
+    Text {
+        content: String,
+        /// 群聊中 @ 的成员，逗号分隔；`notify@all` 为 @所有人
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ats: Option<String>,
+    },
+    Image {
+        url: String,
+    },
+    File {
+        url: String,
+        name: String,
+    },
+    Link {
+        title: String,
+        desc: String,
+        url: String,
+        thumb_url: String,
+    },
+}
+
+impl QueuedMessage {
+    pub fn text(content: impl Into<String>) -> Self {
+        QueuedMessage::Text {
+            content: content.into(),
+            ats: None,
+        }
+    }
+
+    /// 消息类型，用于列表展示
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QueuedMessage::Text { .. } => "text",
+            QueuedMessage::Image { .. } => "image",
+            QueuedMessage::File { .. } => "file",
+            QueuedMessage::Link { .. } => "link",
+        }
+    }
+
+    /// 内容摘要：文本为正文，其余为地址或标题
+    pub fn summary(&self) -> &str {
+        match self {
+            QueuedMessage::Text { content, .. } => content,
+            QueuedMessage::Image { url } => url,
+            QueuedMessage::File { name, .. } => name,
+            QueuedMessage::Link { title, .. } => title,
+        }
+    }
+
+    pub(crate) async fn send(
+        &self,
+        client: &GeweHttpClient,
+        app_id: &str,
+        to_wxid: &str,
+    ) -> Result<(), GeweError> {
+        match self {
+            QueuedMessage::Text { content, ats } => client
+                .send_text(app_id, to_wxid, content, ats.as_deref())
+                .await
+                .map(drop),
+            QueuedMessage::Image { url } => client.send_image(app_id, to_wxid, url).await.map(drop),
+            QueuedMessage::File { url, name } => {
+                client.send_file(app_id, to_wxid, url, name).await.map(drop)
+            }
+            QueuedMessage::Link {
+                title,
+                desc,
+                url,
+                thumb_url,
+            } => client
+                .send_link(app_id, to_wxid, title, desc, url, thumb_url)
+                .await
+                .map(drop),
+        }
+    }
+}
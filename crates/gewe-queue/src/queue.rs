@@ -0,0 +1,537 @@
+//! SQLite 队列表与到期消息的发送
+
+use crate::message::QueuedMessage;
+use chrono::{DateTime, TimeZone, Utc};
+use gewe_http::GeweHttpClient;
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, instrument, warn};
+
+/// 单次 [`SendQueue::drain`] 最多处理的消息数
+const DRAIN_BATCH: i64 = 100;
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS send_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    app_id TEXT NOT NULL,
+    to_wxid TEXT NOT NULL,
+    message TEXT NOT NULL,
+    send_at INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    sent_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS send_queue_due ON send_queue (status, send_at);
+"#;
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueueError {
+    #[error("queue storage error: {0}")]
+    Storage(#[from] sqlx::Error),
+    #[error("queue encode error: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+/// 队列中消息的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SendStatus {
+    /// 等待发送（含等待重试）
+    Pending,
+    Sent,
+    /// 重试次数用尽或机器人不存在
+    Failed,
+    /// 发送前被取消
+    Cancelled,
+}
+
+impl SendStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SendStatus::Pending => "pending",
+            SendStatus::Sent => "sent",
+            SendStatus::Failed => "failed",
+            SendStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        match s {
+            "sent" => SendStatus::Sent,
+            "failed" => SendStatus::Failed,
+            "cancelled" => SendStatus::Cancelled,
+            _ => SendStatus::Pending,
+        }
+    }
+}
+
+/// 队列中的一条消息
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueuedSend {
+    pub id: i64,
+    pub app_id: String,
+    pub to_wxid: String,
+    pub message: QueuedMessage,
+    /// 计划发送时间；等待重试时为下次重试的时间
+    pub send_at: DateTime<Utc>,
+    pub status: SendStatus,
+    /// 已开始发送的次数
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// 失败重试策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 最多尝试发送的次数（含第一次）
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub base_delay: Duration,
+    /// 取出消息后的租约：进程在发送途中退出时，消息在租约到期后重新发送；
+    /// 多个进程同时消费同一个队列文件时也不会重复发送
+    pub lease: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(30),
+            lease: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第 `attempts` 次失败后的等待时间
+    fn delay(&self, attempts: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+    }
+}
+
+/// 一次 [`SendQueue::drain`] 的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrainReport {
+    pub sent: usize,
+    /// 发送失败、已安排重试
+    pub retried: usize,
+    /// 发送失败且不再重试
+    pub failed: usize,
+}
+
+/// 基于 SQLite 的发送队列
+#[derive(Debug, Clone)]
+pub struct SendQueue {
+    pool: SqlitePool,
+    retry: RetryPolicy,
+}
+
+impl SendQueue {
+    /// 打开（不存在时创建）队列文件
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, QueueError> {
+        if let Some(parent) = path.as_ref().parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent).map_err(sqlx::Error::Io)?;
+            }
+        }
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await?;
+        sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+        Ok(Self {
+            pool,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// 加入队列，`send_at` 为空时立即可发送，返回消息编号
+    #[instrument(skip(self, message))]
+    pub async fn enqueue(
+        &self,
+        app_id: &str,
+        to_wxid: &str,
+        message: &QueuedMessage,
+        send_at: Option<DateTime<Utc>>,
+    ) -> Result<i64, QueueError> {
+        let now = Utc::now();
+        let id = sqlx::query(
+            "INSERT INTO send_queue (app_id, to_wxid, message, send_at, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(app_id)
+        .bind(to_wxid)
+        .bind(serde_json::to_string(message)?)
+        .bind(send_at.unwrap_or(now).timestamp())
+        .bind(now.timestamp())
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        debug!(id, "消息已加入发送队列");
+        Ok(id)
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<QueuedSend>, QueueError> {
+        let row = sqlx::query("SELECT * FROM send_queue WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.map(|r| from_row(&r)).transpose()
+    }
+
+    /// 按计划发送时间列出消息，`status` 为空时列出全部
+    pub async fn list(
+        &self,
+        status: Option<SendStatus>,
+        limit: u32,
+    ) -> Result<Vec<QueuedSend>, QueueError> {
+        let rows = match status {
+            Some(status) => {
+                sqlx::query(
+                    "SELECT * FROM send_queue WHERE status = ? ORDER BY send_at, id LIMIT ?",
+                )
+                .bind(status.as_str())
+                .bind(limit as i64)
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query("SELECT * FROM send_queue ORDER BY send_at, id LIMIT ?")
+                    .bind(limit as i64)
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+        rows.iter().map(from_row).collect()
+    }
+
+    /// 取消尚未发送的消息，返回是否取消成功
+    pub async fn cancel(&self, id: i64) -> Result<bool, QueueError> {
+        let result = sqlx::query(
+            "UPDATE send_queue SET status = 'cancelled' WHERE id = ? AND status = 'pending'",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// 按发送时间顺序发送 `now` 之前到期的消息
+    ///
+    /// `client_for` 按 appId 返回发送用的客户端，返回 None 的消息直接标记为失败。
+    /// 发送逐条进行，限流由客户端的 [`RateLimitPolicy`](gewe_http::RateLimitPolicy) 负责。
+    #[instrument(skip(self, client_for))]
+    pub async fn drain<'a, F>(
+        &self,
+        now: DateTime<Utc>,
+        client_for: F,
+    ) -> Result<DrainReport, QueueError>
+    where
+        F: Fn(&str) -> Option<&'a GeweHttpClient>,
+    {
+        let due = sqlx::query(
+            "SELECT * FROM send_queue WHERE status = 'pending' AND send_at <= ? ORDER BY send_at, id LIMIT ?",
+        )
+        .bind(now.timestamp())
+        .bind(DRAIN_BATCH)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut report = DrainReport::default();
+        for row in &due {
+            let item = from_row(row)?;
+            if !self.claim(&item, now).await? {
+                continue;
+            }
+            let attempts = item.attempts + 1;
+            let Some(client) = client_for(&item.app_id) else {
+                self.finish(item.id, SendStatus::Failed, Some("unknown app"), now)
+                    .await?;
+                report.failed += 1;
+                continue;
+            };
+            match item.message.send(client, &item.app_id, &item.to_wxid).await {
+                Ok(()) => {
+                    self.finish(item.id, SendStatus::Sent, None, Utc::now())
+                        .await?;
+                    report.sent += 1;
+                }
+                Err(err) if attempts >= self.retry.max_attempts => {
+                    warn!(id = item.id, ?err, attempts, "队列消息发送失败，不再重试");
+                    self.finish(item.id, SendStatus::Failed, Some(&err.to_string()), now)
+                        .await?;
+                    report.failed += 1;
+                }
+                Err(err) => {
+                    let retry_at = Utc::now() + to_chrono(self.retry.delay(attempts));
+                    debug!(id = item.id, ?err, attempts, %retry_at, "队列消息发送失败，稍后重试");
+                    sqlx::query("UPDATE send_queue SET send_at = ?, last_error = ? WHERE id = ?")
+                        .bind(retry_at.timestamp())
+                        .bind(err.to_string())
+                        .bind(item.id)
+                        .execute(&self.pool)
+                        .await?;
+                    report.retried += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// 取得消息的发送权：计数加一并把发送时间推迟到租约结束
+    async fn claim(&self, item: &QueuedSend, now: DateTime<Utc>) -> Result<bool, QueueError> {
+        let lease_until = now + to_chrono(self.retry.lease);
+        let result = sqlx::query(
+            "UPDATE send_queue SET attempts = attempts + 1, send_at = ? WHERE id = ? AND status = 'pending' AND send_at = ?",
+        )
+        .bind(lease_until.timestamp())
+        .bind(item.id)
+        .bind(item.send_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn finish(
+        &self,
+        id: i64,
+        status: SendStatus,
+        error: Option<&str>,
+        at: DateTime<Utc>,
+    ) -> Result<(), QueueError> {
+        let sent_at = (status == SendStatus::Sent).then(|| at.timestamp());
+        sqlx::query(
+            "UPDATE send_queue SET status = ?, last_error = COALESCE(?, last_error), sent_at = ? WHERE id = ?",
+        )
+        .bind(status.as_str())
+        .bind(error)
+        .bind(sent_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+fn to_chrono(d: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(d).unwrap_or(chrono::Duration::MAX)
+}
+
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(secs, 0).single().unwrap_or_default()
+}
+
+fn from_row(row: &SqliteRow) -> Result<QueuedSend, QueueError> {
+    let message: String = row.try_get("message")?;
+    let status: String = row.try_get("status")?;
+    let attempts: i64 = row.try_get("attempts")?;
+    let sent_at: Option<i64> = row.try_get("sent_at")?;
+    Ok(QueuedSend {
+        id: row.try_get("id")?,
+        app_id: row.try_get("app_id")?,
+        to_wxid: row.try_get("to_wxid")?,
+        message: serde_json::from_str(&message)?,
+        send_at: timestamp(row.try_get("send_at")?),
+        status: SendStatus::parse(&status),
+        attempts: attempts.max(0) as u32,
+        last_error: row.try_get("last_error")?,
+        created_at: timestamp(row.try_get("created_at")?),
+        sent_at: sent_at.map(timestamp),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Minimal HTTP server: ret=200 for every recipient except `wxid_bad`
+    fn mock_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        len = v.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let to = body["toWxid"].as_str().unwrap_or_default().to_string();
+                let resp = if to == "wxid_bad" {
+                    serde_json::json!({"ret": 500, "msg": "not friend"})
+                } else {
+                    serde_json::json!({"ret": 200, "msg": "ok", "data": {
+                        "toWxid": to, "createTime": 1, "msgId": 1, "newMsgId": 2, "type": 1
+                    }})
+                }
+                .to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    resp.len(),
+                    resp
+                );
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_list_cancel_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue/send.db");
+        let queue = SendQueue::open(&path).await.unwrap();
+        let at = Utc.timestamp_opt(1_800_000_000, 0).unwrap();
+        let first = queue
+            .enqueue("wx_app", "wxid_a", &QueuedMessage::text("早上好"), Some(at))
+            .await
+            .unwrap();
+        let link = QueuedMessage::Link {
+            title: "周报".to_string(),
+            desc: "本周进展".to_string(),
+            url: "https://example.com".to_string(),
+            thumb_url: String::new(),
+        };
+        let second = queue
+            .enqueue("wx_app", "wxid_b", &link, None)
+            .await
+            .unwrap();
+
+        let all = queue.list(None, 10).await.unwrap();
+        assert_eq!(
+            all.iter().map(|s| s.id).collect::<Vec<_>>(),
+            [second, first]
+        );
+        assert_eq!(all[1].send_at, at);
+        assert_eq!(all[0].message, link);
+        assert_eq!(all[0].status, SendStatus::Pending);
+
+        assert!(queue.cancel(first).await.unwrap());
+        assert!(!queue.cancel(first).await.unwrap());
+        drop(queue);
+
+        let queue = SendQueue::open(&path).await.unwrap();
+        let cancelled = queue.get(first).await.unwrap().unwrap();
+        assert_eq!(cancelled.status, SendStatus::Cancelled);
+        assert_eq!(
+            queue
+                .list(Some(SendStatus::Pending), 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(queue.get(999).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_drain_sends_due_and_retries_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = SendQueue::open(dir.path().join("send.db"))
+            .await
+            .unwrap()
+            .with_retry(RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_secs(60),
+                lease: Duration::from_secs(300),
+            });
+        let client = GeweHttpClient::new("token", mock_server()).unwrap();
+        let now = Utc::now();
+        let ok = queue
+            .enqueue("wx_app", "wxid_a", &QueuedMessage::text("hi"), None)
+            .await
+            .unwrap();
+        let bad = queue
+            .enqueue("wx_app", "wxid_bad", &QueuedMessage::text("hi"), None)
+            .await
+            .unwrap();
+        let later = queue
+            .enqueue(
+                "wx_app",
+                "wxid_a",
+                &QueuedMessage::text("later"),
+                Some(now + chrono::Duration::hours(1)),
+            )
+            .await
+            .unwrap();
+        let orphan = queue
+            .enqueue("wx_other", "wxid_a", &QueuedMessage::text("hi"), None)
+            .await
+            .unwrap();
+
+        let clients = |app: &str| (app == "wx_app").then_some(&client);
+        let report = queue.drain(Utc::now(), clients).await.unwrap();
+        assert_eq!(
+            report,
+            DrainReport {
+                sent: 1,
+                retried: 1,
+                failed: 1
+            }
+        );
+        let sent = queue.get(ok).await.unwrap().unwrap();
+        assert_eq!(sent.status, SendStatus::Sent);
+        assert!(sent.sent_at.is_some());
+        assert_eq!(
+            queue
+                .get(orphan)
+                .await
+                .unwrap()
+                .unwrap()
+                .last_error
+                .as_deref(),
+            Some("unknown app")
+        );
+        let retrying = queue.get(bad).await.unwrap().unwrap();
+        assert_eq!(retrying.status, SendStatus::Pending);
+        assert_eq!(retrying.attempts, 1);
+        assert!(retrying.send_at > now + chrono::Duration::seconds(50));
+        assert_eq!(
+            queue.get(later).await.unwrap().unwrap().status,
+            SendStatus::Pending
+        );
+
+        // Nothing else is due until the retry time; the second failure is final
+        let report = queue.drain(Utc::now(), clients).await.unwrap();
+        assert_eq!(report, DrainReport::default());
+        let report = queue
+            .drain(now + chrono::Duration::minutes(2), clients)
+            .await
+            .unwrap();
+        assert_eq!(report.failed, 1);
+        let failed = queue.get(bad).await.unwrap().unwrap();
+        assert_eq!(failed.status, SendStatus::Failed);
+        assert_eq!(failed.attempts, 2);
+        assert!(failed.last_error.unwrap().contains("not friend"));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_secs(30));
+        assert_eq!(policy.delay(3), Duration::from_secs(120));
+    }
+}
@@ -0,0 +1,731 @@
+//! 过滤表达式的解析与求值
+//!
+//! 语法（优先级从高到低：`!`、比较、`&&`、`||`）：
+//!
+//! ```text
+//! expr    := and ("||" and)*
+//! and     := unary ("&&" unary)*
+//! unary   := "!" unary | "(" expr ")" | field [op value]
+//! op      := "==" | "!=" | "~=" | "!~" | "<" | "<=" | ">" | ">=" | "contains" | "in"
+//! value   := 标识符 | "字符串" | '字符串' | 整数 | /正则/标志 | "[" value ("," value)* "]"
+//! ```
+
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+use std::str::FromStr;
+
+use gewe_webhook::WebhookEvent;
+
+use crate::message::Message;
+use gewe_webhook::normalize::{ChatKind, MessageKind};
+
+/// 表达式解析错误，`offset` 为出错位置的字节偏移
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message} (at offset {offset})")]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(offset: usize, message: impl Into<String>) -> Self {
+        Self {
+            offset,
+            message: message.into(),
+        }
+    }
+}
+
+/// 编译后的过滤表达式
+///
+/// ```
+/// use gewe_rules::Filter;
+///
+/// let filter: Filter = "kind == text && chat == group && content ~= /^\\/cmd/".parse().unwrap();
+/// # let _ = filter;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let tokens = lex(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: source.len(),
+        };
+        let expr = parser.expr()?;
+        if let Some(tok) = parser.peek() {
+            return Err(ParseError::new(tok.offset, "unexpected token"));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn matches(&self, msg: &Message) -> bool {
+        self.expr.eval(msg)
+    }
+
+    /// 解析事件后求值
+    pub fn matches_event(&self, event: &WebhookEvent) -> bool {
+        self.matches(&Message::from_event(event))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl FromStr for Filter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    AppId,
+    TypeName,
+    Kind,
+    Chat,
+    From,
+    Sender,
+    To,
+    Content,
+    MsgType,
+    AppmsgType,
+    NewMsgId,
+    Mentioned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    Str,
+    Int,
+    Bool,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "app_id" => Field::AppId,
+            "type_name" => Field::TypeName,
+            "kind" => Field::Kind,
+            "chat" => Field::Chat,
+            "from" => Field::From,
+            "sender" => Field::Sender,
+            "to" => Field::To,
+            "content" => Field::Content,
+            "msg_type" => Field::MsgType,
+            "appmsg_type" => Field::AppmsgType,
+            "new_msg_id" => Field::NewMsgId,
+            "mentioned" => Field::Mentioned,
+            _ => return None,
+        })
+    }
+
+    fn ty(self) -> FieldType {
+        match self {
+            Field::MsgType | Field::AppmsgType | Field::NewMsgId => FieldType::Int,
+            Field::Mentioned => FieldType::Bool,
+            _ => FieldType::Str,
+        }
+    }
+
+    fn str_value(self, msg: &Message) -> Option<&str> {
+        match self {
+            Field::AppId => Some(&msg.app_id),
+            Field::TypeName => msg.type_name.as_deref(),
+            Field::Kind => Some(msg.kind.as_str()),
+            Field::Chat => msg.chat.map(ChatKind::as_str),
+            Field::From => msg.from.as_deref(),
+            Field::Sender => msg.sender.as_deref(),
+            Field::To => msg.to.as_deref(),
+            // 与规则的 equals/contains/regex 一致，比较前去掉首尾空白
+            Field::Content => msg.content.as_deref().map(str::trim),
+            _ => None,
+        }
+    }
+
+    fn int_value(self, msg: &Message) -> Option<i64> {
+        match self {
+            Field::MsgType => msg.msg_type,
+            Field::AppmsgType => msg.appmsg_type.map(i64::from),
+            Field::NewMsgId => msg.new_msg_id,
+            _ => None,
+        }
+    }
+
+    /// kind、chat 只接受已知的取值，避免拼写错误的表达式永远不命中
+    fn check_str(self, value: &str, offset: usize) -> Result<(), ParseError> {
+        let known = match self {
+            Field::Kind => MessageKind::from_name(value).is_some(),
+            Field::Chat => ChatKind::from_name(value).is_some(),
+            _ => true,
+        };
+        if known {
+            Ok(())
+        } else {
+            Err(ParseError::new(offset, format!("unknown value `{value}`")))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Str(String),
+    Int(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Flag(Field),
+    Eq(Field, Literal),
+    Matches(Field, Regex),
+    Contains(Field, String),
+    In(Field, Vec<Literal>),
+    Compare(Field, CmpOp, i64),
+}
+
+impl Expr {
+    fn eval(&self, msg: &Message) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(msg) && b.eval(msg),
+            Expr::Or(a, b) => a.eval(msg) || b.eval(msg),
+            Expr::Not(e) => !e.eval(msg),
+            Expr::Flag(Field::Mentioned) => msg.mentioned,
+            Expr::Flag(_) => false,
+            Expr::Eq(field, value) => literal_eq(*field, value, msg),
+            Expr::Matches(field, re) => field.str_value(msg).is_some_and(|s| re.is_match(s)),
+            Expr::Contains(field, needle) => {
+                field.str_value(msg).is_some_and(|s| s.contains(needle))
+            }
+            Expr::In(field, values) => values.iter().any(|v| literal_eq(*field, v, msg)),
+            Expr::Compare(field, ord, value) => field.int_value(msg).is_some_and(|v| match ord {
+                CmpOp::Lt => v < *value,
+                CmpOp::Le => v <= *value,
+                CmpOp::Gt => v > *value,
+                CmpOp::Ge => v >= *value,
+            }),
+        }
+    }
+}
+
+fn literal_eq(field: Field, value: &Literal, msg: &Message) -> bool {
+    match value {
+        Literal::Str(s) => field.str_value(msg) == Some(s.as_str()),
+        Literal::Int(n) => field.int_value(msg) == Some(*n),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Regex(String, String),
+    Op(&'static str),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    offset: usize,
+}
+
+fn lex(src: &str) -> Result<Vec<Token>, ParseError> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        let two = src.get(i..i + 2).unwrap_or("");
+        let kind = match c {
+            b' ' | b'\t' | b'\r' | b'\n' => {
+                i += 1;
+                continue;
+            }
+            b'(' => TokenKind::LParen,
+            b')' => TokenKind::RParen,
+            b'[' => TokenKind::LBracket,
+            b']' => TokenKind::RBracket,
+            b',' => TokenKind::Comma,
+            _ if two == "&&" => TokenKind::And,
+            _ if two == "||" => TokenKind::Or,
+            _ if ["==", "!=", "~=", "!~", "<=", ">="].contains(&two) => {
+                i += 2;
+                tokens.push(Token {
+                    kind: TokenKind::Op(match two {
+                        "==" => "==",
+                        "!=" => "!=",
+                        "~=" => "~=",
+                        "!~" => "!~",
+                        "<=" => "<=",
+                        _ => ">=",
+                    }),
+                    offset: start,
+                });
+                continue;
+            }
+            b'<' => TokenKind::Op("<"),
+            b'>' => TokenKind::Op(">"),
+            b'!' => TokenKind::Not,
+            b'"' | b'\'' => {
+                let (value, next) = lex_string(src, i)?;
+                i = next;
+                tokens.push(Token {
+                    kind: TokenKind::Str(value),
+                    offset: start,
+                });
+                continue;
+            }
+            b'/' => {
+                let (pattern, flags, next) = lex_regex(src, i)?;
+                i = next;
+                tokens.push(Token {
+                    kind: TokenKind::Regex(pattern, flags),
+                    offset: start,
+                });
+                continue;
+            }
+            b'-' | b'0'..=b'9' => {
+                let mut end = i + 1;
+                while end < bytes.len() && bytes[end].is_ascii_digit() {
+                    end += 1;
+                }
+                let value = src[i..end]
+                    .parse()
+                    .map_err(|_| ParseError::new(start, "invalid integer"))?;
+                i = end;
+                tokens.push(Token {
+                    kind: TokenKind::Int(value),
+                    offset: start,
+                });
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                let mut end = i + 1;
+                while end < bytes.len()
+                    && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_')
+                {
+                    end += 1;
+                }
+                i = end;
+                tokens.push(Token {
+                    kind: TokenKind::Ident(src[start..end].to_string()),
+                    offset: start,
+                });
+                continue;
+            }
+            _ => return Err(ParseError::new(start, "unexpected character")),
+        };
+        i += match kind {
+            TokenKind::And | TokenKind::Or => 2,
+            _ => 1,
+        };
+        tokens.push(Token {
+            kind,
+            offset: start,
+        });
+    }
+    Ok(tokens)
+}
+
+/// 引号字符串，支持 `\"` `\'` `\\` `\n` `\t` 转义
+fn lex_string(src: &str, start: usize) -> Result<(String, usize), ParseError> {
+    let quote = src.as_bytes()[start] as char;
+    let mut value = String::new();
+    let mut chars = src[start + 1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((value, start + 1 + i + 1)),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c @ ('\\' | '"' | '\''))) => value.push(c),
+                Some((j, _)) => {
+                    return Err(ParseError::new(start + 1 + j, "unknown escape"));
+                }
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err(ParseError::new(start, "unterminated string"))
+}
+
+/// 正则字面量 `/.../flags`，`\/` 表示斜杠，其余转义原样交给正则
+fn lex_regex(src: &str, start: usize) -> Result<(String, String, usize), ParseError> {
+    let mut pattern = String::new();
+    let mut chars = src[start + 1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '/' => {
+                let rest = &src[start + 1 + i + 1..];
+                let flags_len = rest.bytes().take_while(|b| b.is_ascii_alphabetic()).count();
+                let flags = rest[..flags_len].to_string();
+                return Ok((pattern, flags, start + 1 + i + 1 + flags_len));
+            }
+            '\\' => match chars.next() {
+                Some((_, '/')) => pattern.push('/'),
+                Some((_, c)) => {
+                    pattern.push('\\');
+                    pattern.push(c);
+                }
+                None => break,
+            },
+            c => pattern.push(c),
+        }
+    }
+    Err(ParseError::new(start, "unterminated regex"))
+}
+
+fn build_regex(pattern: &str, flags: &str, offset: usize) -> Result<Regex, ParseError> {
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            _ => {
+                return Err(ParseError::new(
+                    offset,
+                    format!("unknown regex flag `{flag}`"),
+                ))
+            }
+        };
+    }
+    builder
+        .build()
+        .map_err(|e| ParseError::new(offset, format!("invalid regex: {e}")))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let tok = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseError::new(self.end, "unexpected end of expression"))?;
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().map(|t| &t.kind) == Some(kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.and()?;
+        while self.eat(&TokenKind::Or) {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        while self.eat(&TokenKind::And) {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let tok = self.next()?;
+        match tok.kind {
+            TokenKind::Not => Ok(Expr::Not(Box::new(self.unary()?))),
+            TokenKind::LParen => {
+                let expr = self.expr()?;
+                let close = self.next()?;
+                if close.kind != TokenKind::RParen {
+                    return Err(ParseError::new(close.offset, "expected `)`"));
+                }
+                Ok(expr)
+            }
+            TokenKind::Ident(name) => {
+                let field = Field::from_name(&name).ok_or_else(|| {
+                    ParseError::new(tok.offset, format!("unknown field `{name}`"))
+                })?;
+                self.condition(field, tok.offset)
+            }
+            _ => Err(ParseError::new(tok.offset, "expected field")),
+        }
+    }
+
+    fn condition(&mut self, field: Field, offset: usize) -> Result<Expr, ParseError> {
+        let op = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Op(op)) => *op,
+            Some(TokenKind::Ident(word)) if word == "contains" || word == "in" => {
+                if word == "contains" {
+                    "contains"
+                } else {
+                    "in"
+                }
+            }
+            _ if field.ty() == FieldType::Bool => return Ok(Expr::Flag(field)),
+            _ => return Err(ParseError::new(self.end_of(offset), "expected operator")),
+        };
+        let op_offset = self.next()?.offset;
+        if field.ty() == FieldType::Bool {
+            return Err(ParseError::new(
+                op_offset,
+                "boolean field takes no operator",
+            ));
+        }
+        let expr = match op {
+            "==" | "!=" => {
+                let value = self.literal(field)?;
+                let eq = Expr::Eq(field, value);
+                if op == "!=" {
+                    Expr::Not(Box::new(eq))
+                } else {
+                    eq
+                }
+            }
+            "~=" | "!~" => {
+                let tok = self.next()?;
+                if field.ty() != FieldType::Str {
+                    return Err(ParseError::new(op_offset, "`~=` needs a text field"));
+                }
+                let re = match tok.kind {
+                    TokenKind::Regex(pattern, flags) => build_regex(&pattern, &flags, tok.offset)?,
+                    TokenKind::Str(pattern) => build_regex(&pattern, "", tok.offset)?,
+                    _ => return Err(ParseError::new(tok.offset, "expected regex")),
+                };
+                let matches = Expr::Matches(field, re);
+                if op == "!~" {
+                    Expr::Not(Box::new(matches))
+                } else {
+                    matches
+                }
+            }
+            "contains" => {
+                let tok = self.next()?;
+                match (field.ty(), tok.kind) {
+                    (FieldType::Str, TokenKind::Str(s) | TokenKind::Ident(s)) => {
+                        Expr::Contains(field, s)
+                    }
+                    _ => return Err(ParseError::new(tok.offset, "expected text")),
+                }
+            }
+            "in" => {
+                let open = self.next()?;
+                if open.kind != TokenKind::LBracket {
+                    return Err(ParseError::new(open.offset, "expected `[`"));
+                }
+                let mut values = vec![self.literal(field)?];
+                loop {
+                    let tok = self.next()?;
+                    match tok.kind {
+                        TokenKind::Comma => values.push(self.literal(field)?),
+                        TokenKind::RBracket => break,
+                        _ => return Err(ParseError::new(tok.offset, "expected `,` or `]`")),
+                    }
+                }
+                Expr::In(field, values)
+            }
+            ord => {
+                let tok = self.next()?;
+                let (FieldType::Int, TokenKind::Int(value)) = (field.ty(), tok.kind) else {
+                    return Err(ParseError::new(tok.offset, "expected integer"));
+                };
+                let ord = match ord {
+                    "<" => CmpOp::Lt,
+                    "<=" => CmpOp::Le,
+                    ">" => CmpOp::Gt,
+                    _ => CmpOp::Ge,
+                };
+                Expr::Compare(field, ord, value)
+            }
+        };
+        Ok(expr)
+    }
+
+    fn literal(&mut self, field: Field) -> Result<Literal, ParseError> {
+        let tok = self.next()?;
+        match (field.ty(), tok.kind) {
+            (FieldType::Str, TokenKind::Str(s) | TokenKind::Ident(s)) => {
+                field.check_str(&s, tok.offset)?;
+                Ok(Literal::Str(s))
+            }
+            (FieldType::Int, TokenKind::Int(n)) => Ok(Literal::Int(n)),
+            (FieldType::Int, _) => Err(ParseError::new(tok.offset, "expected integer")),
+            _ => Err(ParseError::new(tok.offset, "expected text")),
+        }
+    }
+
+    /// 下一个 token 的位置，已到末尾时为表达式长度
+    fn end_of(&self, fallback: usize) -> usize {
+        self.peek()
+            .map(|t| t.offset)
+            .unwrap_or(self.end.max(fallback))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_cmd() -> Message {
+        Message {
+            app_id: "wx_app".to_string(),
+            type_name: Some("AddMsg".to_string()),
+            kind: MessageKind::Text,
+            chat: Some(ChatKind::Group),
+            msg_type: Some(1),
+            appmsg_type: None,
+            from: Some("123@chatroom".to_string()),
+            sender: Some("wxid_alice".to_string()),
+            to: Some("wxid_bot".to_string()),
+            content: Some(" /cmd deploy ".to_string()),
+            new_msg_id: Some(42),
+            mentioned: false,
+        }
+    }
+
+    fn eval(src: &str, msg: &Message) -> bool {
+        Filter::parse(src)
+            .unwrap_or_else(|e| panic!("{src}: {e}"))
+            .matches(msg)
+    }
+
+    #[test]
+    fn test_example_expression() {
+        let msg = group_cmd();
+        assert!(eval(
+            r"kind == text && chat == group && content ~= /^\/cmd/",
+            &msg
+        ));
+        let mut private = msg.clone();
+        private.chat = Some(ChatKind::Private);
+        assert!(!eval(
+            r"kind == text && chat == group && content ~= /^\/cmd/",
+            &private
+        ));
+    }
+
+    #[test]
+    fn test_operators() {
+        let msg = group_cmd();
+        for (src, expected) in [
+            ("content == \"/cmd deploy\"", true),
+            ("content != '/cmd deploy'", false),
+            ("content contains deploy", true),
+            ("content !~ /^\\/CMD/i", false),
+            ("content ~= \"deploy$\"", true),
+            ("sender in [wxid_bob, wxid_alice]", true),
+            ("kind in [image, video]", false),
+            ("msg_type == 1 && new_msg_id >= 42 && new_msg_id < 43", true),
+            ("appmsg_type == 5", false),
+            ("appmsg_type != 5", true),
+            ("mentioned", false),
+            ("!mentioned", true),
+            (
+                "kind == image || chat == group && sender == wxid_alice",
+                true,
+            ),
+            (
+                "(kind == image || chat == group) && sender == wxid_bob",
+                false,
+            ),
+            ("!(kind == image) && type_name == AddMsg", true),
+            (
+                "app_id == wx_app && to == wxid_bot && from ~= /@chatroom$/",
+                true,
+            ),
+        ] {
+            assert_eq!(eval(src, &msg), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let msg = Message {
+            content: None,
+            chat: None,
+            ..group_cmd()
+        };
+        assert!(!eval("content contains x", &msg));
+        assert!(!eval("content ~= /.*/", &msg));
+        assert!(eval("content !~ /x/", &msg));
+        assert!(!eval("chat == private", &msg));
+        assert!(eval("chat != private", &msg));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (src, offset) in [
+            ("knd == text", 0),
+            ("kind == txt", 8),
+            ("kind ==", 7),
+            ("content ~= /[/", 11),
+            ("content ~= /a/x", 11),
+            ("msg_type > text", 11),
+            ("mentioned == true", 10),
+            ("content == \"open", 11),
+            ("(kind == text", 13),
+            ("kind == text text", 13),
+            ("content # x", 8),
+        ] {
+            let err = Filter::parse(src).unwrap_err();
+            assert_eq!(err.offset, offset, "{src}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_display_and_from_str() {
+        let filter: Filter = "kind == text".parse().unwrap();
+        assert_eq!(filter.to_string(), "kind == text");
+        assert_eq!(filter.as_str(), "kind == text");
+    }
+}
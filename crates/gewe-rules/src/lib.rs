@@ -0,0 +1,42 @@
+//! gewe 消息匹配与过滤表达式
+//!
+//! 把 gewe-bot-app 规则匹配所用的消息规范化（消息类型、群聊/私聊、群成员发送者、
+//! 群聊正文前缀剥离、@ 机器人识别）独立出来，并提供一个小型过滤表达式，方便只使用
+//! gewe-webhook 的程序复用同一套匹配逻辑：
+//!
+//! ```
+//! use gewe_core::AppId;
+//! use gewe_rules::Filter;
+//! use gewe_webhook::WebhookEvent;
+//!
+//! let filter: Filter = r"kind == text && chat == group && content ~= /^\/cmd/"
+//!     .parse()
+//!     .unwrap();
+//! let event = WebhookEvent {
+//!     app_id: AppId("wx_app".into()),
+//!     type_name: Some("AddMsg".into()),
+//!     data: serde_json::json!({
+//!         "MsgType": 1,
+//!         "FromUserName": {"string": "123@chatroom"},
+//!         "ToUserName": {"string": "wxid_bot"},
+//!         "Content": {"string": "wxid_alice:\n/cmd ping"}
+//!     }),
+//! };
+//! assert!(filter.matches_event(&event));
+//! ```
+//!
+//! 字段：`app_id`、`type_name`、`kind`、`chat`、`from`、`sender`、`to`、`content`（文本），
+//! `msg_type`、`appmsg_type`、`new_msg_id`（整数），`mentioned`（布尔，直接作为条件）。
+//! 运算符：`==`、`!=`、`~=`/`!~`（正则，`/.../i` 不区分大小写）、`contains`、
+//! `in [a, b]`、`<`、`<=`、`>`、`>=`（整数），以及 `!`、`&&`、`||` 与括号。
+//! 缺失的字段不满足任何比较（`!=`、`!~` 除外）。
+
+mod filter;
+mod message;
+
+pub use filter::{Filter, ParseError};
+pub use gewe_webhook::normalize::{
+    extract_appmsg_type, extract_atuserlist, extract_group_sender, mentions, strip_sender_prefix,
+    ChatKind, MessageKind,
+};
+pub use message::Message;
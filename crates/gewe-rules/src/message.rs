@@ -0,0 +1,198 @@
+//! 过滤表达式使用的消息视图
+
+use gewe_webhook::normalize::{
+    extract_appmsg_type, extract_group_sender, extract_member_change, extract_new_msg_id, mentions,
+    strip_sender_prefix, ChatKind, MemberChangeKind, MessageKind,
+};
+use gewe_webhook::WebhookEvent;
+
+/// 过滤表达式求值使用的消息字段
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub app_id: String,
+    /// 回调的 TypeName，如 `AddMsg`
+    pub type_name: Option<String>,
+    pub kind: MessageKind,
+    pub chat: Option<ChatKind>,
+    pub msg_type: Option<i64>,
+    /// appmsg（MsgType=49）XML 中的 `<type>`
+    pub appmsg_type: Option<i32>,
+    /// 会话 ID：私聊为对方 wxid，群聊为群 ID
+    pub from: Option<String>,
+    /// 实际发送者：群聊为群成员 wxid，私聊同 `from`
+    pub sender: Option<String>,
+    /// 接收方，一般为机器人自身的 wxid
+    pub to: Option<String>,
+    /// 消息内容，群聊文本已去掉「发送者:」前缀
+    pub content: Option<String>,
+    pub new_msg_id: Option<i64>,
+    /// 群聊中是否 @ 了机器人（`to`）
+    pub mentioned: bool,
+}
+
+impl Message {
+    /// 从 webhook 事件解析，规则与 gewe-bot-app 的消息规范化一致
+    pub fn from_event(event: &WebhookEvent) -> Self {
+        let data = &event.data;
+        let mut msg = Message {
+            app_id: event.app_id.0.clone(),
+            type_name: event.type_name.clone(),
+            kind: MessageKind::Other,
+            chat: None,
+            msg_type: None,
+            appmsg_type: None,
+            from: None,
+            sender: None,
+            to: None,
+            content: None,
+            new_msg_id: extract_new_msg_id(data),
+            mentioned: false,
+        };
+        match event.type_name.as_deref() {
+            Some("AddMsg") => {
+                let string_field = |key: &str| {
+                    data.get(key)
+                        .and_then(|v| v.get("string"))
+                        .and_then(|v| v.as_str())
+                        .map(str::to_string)
+                };
+                msg.msg_type = data.get("MsgType").and_then(|v| v.as_i64());
+                msg.from = string_field("FromUserName");
+                msg.to = string_field("ToUserName");
+                msg.content = string_field("Content");
+                msg.chat = msg.from.as_deref().map(|w| {
+                    if w.ends_with("@chatroom") {
+                        ChatKind::Group
+                    } else {
+                        ChatKind::Private
+                    }
+                });
+                msg.appmsg_type = extract_appmsg_type(msg.msg_type, msg.content.as_deref());
+                if let Some(msg_type) = msg.msg_type {
+                    msg.kind = MessageKind::from_msg_type(msg_type, msg.appmsg_type);
+                }
+                if msg.chat == Some(ChatKind::Group) {
+                    let change = msg
+                        .msg_type
+                        .zip(msg.content.as_deref())
+                        .and_then(|(t, c)| extract_member_change(t, c));
+                    if let Some(change) = change {
+                        msg.kind = match change.kind {
+                            MemberChangeKind::Joined => MessageKind::MemberJoined,
+                            MemberChangeKind::Left => MessageKind::MemberLeft,
+                        };
+                        msg.sender = change
+                            .operator
+                            .and_then(|o| o.wxid)
+                            .or_else(|| msg.from.clone());
+                        return msg;
+                    }
+                    msg.sender = msg.content.as_deref().and_then(extract_group_sender);
+                    // 群聊文本形如 "sender:\n内容"，在确定类型后切分正文
+                    if msg.msg_type == Some(1) {
+                        msg.content = msg.content.as_deref().map(strip_sender_prefix);
+                    }
+                    if let Some(bot) = msg.to.as_deref() {
+                        let msg_source = data.get("MsgSource").and_then(|v| v.as_str());
+                        msg.mentioned = mentions(msg_source, msg.content.as_deref(), bot);
+                    }
+                } else {
+                    msg.sender = msg.from.clone();
+                }
+                if msg.sender.is_none() {
+                    msg.sender = msg.from.clone();
+                }
+            }
+            Some("ModContacts") | Some("DelContacts") | Some("Offline") => {
+                msg.kind = MessageKind::ContactEvent;
+            }
+            _ => {}
+        }
+        msg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gewe_core::AppId;
+    use serde_json::json;
+
+    fn add_msg(data: serde_json::Value) -> WebhookEvent {
+        WebhookEvent {
+            app_id: AppId("wx_app".to_string()),
+            type_name: Some("AddMsg".to_string()),
+            data,
+        }
+    }
+
+    #[test]
+    fn test_from_event_group_text() {
+        let msg = Message::from_event(&add_msg(json!({
+            "MsgType": 1,
+            "FromUserName": {"string": "123@chatroom"},
+            "ToUserName": {"string": "wxid_bot"},
+            "Content": {"string": "wxid_alice:\n@机器人 /cmd ping"},
+            "MsgSource": "<msgsource><atuserlist>wxid_bot</atuserlist></msgsource>",
+            "NewMsgId": 42
+        })));
+        assert_eq!(msg.kind, MessageKind::Text);
+        assert_eq!(msg.chat, Some(ChatKind::Group));
+        assert_eq!(msg.from.as_deref(), Some("123@chatroom"));
+        assert_eq!(msg.sender.as_deref(), Some("wxid_alice"));
+        assert_eq!(msg.content.as_deref(), Some("@机器人 /cmd ping"));
+        assert_eq!(msg.new_msg_id, Some(42));
+        assert!(msg.mentioned);
+    }
+
+    #[test]
+    fn test_from_event_private_link_and_contact_event() {
+        let msg = Message::from_event(&add_msg(json!({
+            "MsgType": 49,
+            "FromUserName": {"string": "wxid_alice"},
+            "ToUserName": {"string": "wxid_bot"},
+            "Content": {"string": "<msg><appmsg><type>5</type></appmsg></msg>"}
+        })));
+        assert_eq!(msg.kind, MessageKind::Link);
+        assert_eq!(msg.appmsg_type, Some(5));
+        assert_eq!(msg.chat, Some(ChatKind::Private));
+        assert_eq!(msg.sender.as_deref(), Some("wxid_alice"));
+        assert!(!msg.mentioned);
+
+        let msg = Message::from_event(&WebhookEvent {
+            app_id: AppId("wx_app".to_string()),
+            type_name: Some("DelContacts".to_string()),
+            data: json!({}),
+        });
+        assert_eq!(msg.kind, MessageKind::ContactEvent);
+        assert_eq!(msg.chat, None);
+    }
+
+    #[test]
+    fn test_from_event_member_joined() {
+        let msg = Message::from_event(&add_msg(json!({
+            "MsgType": 10000,
+            "FromUserName": {"string": "123@chatroom"},
+            "ToUserName": {"string": "wxid_bot"},
+            "Content": {"string": "你邀请\"李四\"加入了群聊"}
+        })));
+        assert_eq!(msg.kind, MessageKind::MemberJoined);
+        assert_eq!(msg.sender.as_deref(), Some("123@chatroom"));
+    }
+
+    #[test]
+    fn test_kind_names_roundtrip() {
+        for kind in MessageKind::ALL {
+            assert_eq!(MessageKind::from_name(kind.as_str()), Some(kind));
+        }
+        assert_eq!(MessageKind::from_name("any"), None);
+    }
+
+    #[test]
+    fn test_mentions() {
+        let src = "<msgsource><atuserlist>wxid_a,wxid_bot</atuserlist></msgsource>";
+        assert!(mentions(Some(src), None, "wxid_bot"));
+        assert!(mentions(None, Some("hi wxid_bot"), "wxid_bot"));
+        assert!(!mentions(Some("<msgsource/>"), Some("hi"), "wxid_bot"));
+    }
+}
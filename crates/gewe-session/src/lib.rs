@@ -3,7 +3,8 @@ use gewe_core::{AppId, BotContext};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
 
 pub type BotRegistry = Arc<RwLock<HashMap<AppId, BotContext>>>;
 
@@ -13,6 +14,10 @@ pub trait SessionStore: Send + Sync {
     async fn put_session(&self, context: BotContext);
     /// returns true if this message id is first seen
     async fn mark_message_seen(&self, app_id: &AppId, new_msg_id: i64) -> bool;
+    /// returns true if a session existed and was removed
+    async fn remove_session(&self, app_id: &AppId) -> bool;
+    /// all registered sessions, ordered by app_id
+    async fn list_sessions(&self) -> Vec<BotContext>;
 }
 
 #[derive(Clone, Default)]
@@ -66,6 +71,75 @@ impl SessionStore for InMemorySessionStore {
         }
         true
     }
+
+    async fn remove_session(&self, app_id: &AppId) -> bool {
+        self.inner.write().await.remove(app_id).is_some()
+    }
+
+    async fn list_sessions(&self) -> Vec<BotContext> {
+        let map = self.inner.read().await;
+        let mut sessions: Vec<BotContext> =
+            map.values().map(|entry| entry.context.clone()).collect();
+        sessions.sort_by(|a, b| a.app_id.0.cmp(&b.app_id.0));
+        sessions
+    }
+}
+
+/// How long a message claim is kept after its first bid
+pub const CLAIM_TTL: Duration = Duration::from_secs(60);
+
+/// Coordinates bots that receive the same group message so that only one of them responds.
+///
+/// Bots that want to respond `bid` for the message key; once a bot `settle`s as the winner the
+/// claim is decided and later bids can no longer take it over. Share one store (e.g. Redis)
+/// between processes to coordinate bots running separately.
+#[async_trait]
+pub trait MessageClaimStore: Send + Sync {
+    /// registers a bot for the message; a lower priority wins, ties go to the smaller app_id
+    async fn bid(&self, key: &str, priority: i32, app_id: &str);
+    /// returns true if the bot won the message (or no bid was recorded), locking in the result
+    async fn settle(&self, key: &str, app_id: &str) -> bool;
+}
+
+#[derive(Default)]
+pub struct InMemoryClaimStore {
+    claims: Mutex<HashMap<String, Claim>>,
+}
+
+struct Claim {
+    /// current winner (priority, app_id)
+    best: (i32, String),
+    decided: bool,
+    at: Instant,
+}
+
+#[async_trait]
+impl MessageClaimStore for InMemoryClaimStore {
+    async fn bid(&self, key: &str, priority: i32, app_id: &str) {
+        let now = Instant::now();
+        let mut claims = self.claims.lock().await;
+        claims.retain(|_, c| now.saturating_duration_since(c.at) < CLAIM_TTL);
+        let candidate = (priority, app_id.to_string());
+        let claim = claims.entry(key.to_string()).or_insert_with(|| Claim {
+            best: candidate.clone(),
+            decided: false,
+            at: now,
+        });
+        if !claim.decided && candidate < claim.best {
+            claim.best = candidate;
+        }
+    }
+
+    async fn settle(&self, key: &str, app_id: &str) -> bool {
+        match self.claims.lock().await.get_mut(key) {
+            Some(claim) if claim.best.1 == app_id => {
+                claim.decided = true;
+                true
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -356,6 +430,53 @@ mod tests {
         assert!(!store.mark_message_seen(&ctx.app_id, 50).await);
         assert!(!store.mark_message_seen(&ctx.app_id, 150).await);
     }
+
+    #[tokio::test]
+    async fn test_in_memory_store_remove_session() {
+        let store = InMemorySessionStore::default();
+        let ctx = create_test_context("app123");
+        store.put_session(ctx.clone()).await;
+
+        assert!(store.remove_session(&ctx.app_id).await);
+        assert!(store.get_session(&ctx.app_id).await.is_none());
+        // Removing again reports nothing removed
+        assert!(!store.remove_session(&ctx.app_id).await);
+        // Seen ids are dropped with the session
+        assert!(store.mark_message_seen(&ctx.app_id, 1).await);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_list_sessions() {
+        let store = InMemorySessionStore::default();
+        assert!(store.list_sessions().await.is_empty());
+
+        for app_id in ["app_c", "app_a", "app_b"] {
+            store.put_session(create_test_context(app_id)).await;
+        }
+        store.remove_session(&AppId("app_b".to_string())).await;
+
+        let ids: Vec<String> = store
+            .list_sessions()
+            .await
+            .into_iter()
+            .map(|ctx| ctx.app_id.0)
+            .collect();
+        assert_eq!(ids, vec!["app_a", "app_c"]);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_claims_prefer_priority_until_decided() {
+        let claims = InMemoryClaimStore::default();
+        claims.bid("room:1", 2, "wx_b").await;
+        claims.bid("room:1", 1, "wx_a").await;
+        claims.bid("room:1", 1, "wx_c").await;
+        assert!(!claims.settle("room:1", "wx_b").await);
+        assert!(claims.settle("room:1", "wx_a").await);
+        // a better bid after the decision cannot take the message over
+        claims.bid("room:1", 0, "wx_d").await;
+        assert!(!claims.settle("room:1", "wx_d").await);
+        assert!(claims.settle("room:2", "wx_b").await);
+    }
 }
 
 #[cfg(feature = "sqlite")]
@@ -452,14 +573,84 @@ CREATE TABLE IF NOT EXISTS sessions (
                 .await;
             true
         }
+
+        async fn remove_session(&self, app_id: &AppId) -> bool {
+            sqlx::query("DELETE FROM sessions WHERE app_id = ?")
+                .bind(&app_id.0)
+                .execute(&self.pool)
+                .await
+                .map(|result| result.rows_affected() > 0)
+                .unwrap_or(false)
+        }
+
+        async fn list_sessions(&self) -> Vec<BotContext> {
+            let rows: Vec<(String,)> =
+                match sqlx::query_as("SELECT payload FROM sessions ORDER BY app_id")
+                    .fetch_all(&self.pool)
+                    .await
+                {
+                    Ok(rows) => rows,
+                    Err(err) => {
+                        tracing::warn!(?err, "failed to list sessions");
+                        return Vec::new();
+                    }
+                };
+            rows.into_iter()
+                .filter_map(|(payload,)| serde_json::from_str::<StoredEntry>(&payload).ok())
+                .map(|entry| entry.context)
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_sqlite_store_remove_and_list_sessions() {
+            let path = std::env::temp_dir().join(format!(
+                "gewe-session-{}-{}.db",
+                std::process::id(),
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos()
+            ));
+            let url = format!("sqlite://{}?mode=rwc", path.display());
+            let store = SqliteSessionStore::connect(&url).await.unwrap();
+
+            for app_id in ["app_b", "app_a"] {
+                store
+                    .put_session(BotContext {
+                        app_id: AppId(app_id.to_string()),
+                        token: format!("token_{}", app_id),
+                        webhook_secret: None,
+                        description: None,
+                    })
+                    .await;
+            }
+            let ids: Vec<String> = store
+                .list_sessions()
+                .await
+                .into_iter()
+                .map(|ctx| ctx.app_id.0)
+                .collect();
+            assert_eq!(ids, vec!["app_a", "app_b"]);
+
+            assert!(store.remove_session(&AppId("app_a".to_string())).await);
+            assert!(!store.remove_session(&AppId("app_a".to_string())).await);
+            assert_eq!(store.list_sessions().await.len(), 1);
+
+            let _ = std::fs::remove_file(path);
+        }
     }
 }
 
 #[cfg(feature = "redis-store")]
 pub mod redis_store {
-    use super::{AppId, BotContext, SessionStore, StoredEntry};
+    use super::{AppId, BotContext, MessageClaimStore, SessionStore, StoredEntry, CLAIM_TTL};
     use async_trait::async_trait;
-    use redis::{AsyncCommands, Client};
+    use redis::{AsyncCommands, Client, Script};
     use serde_json;
     use std::collections::VecDeque;
 
@@ -529,5 +720,138 @@ pub mod redis_store {
             }
             true
         }
+
+        async fn remove_session(&self, app_id: &AppId) -> bool {
+            let Ok(mut conn) = self.client.get_multiplexed_async_connection().await else {
+                return false;
+            };
+            let removed: redis::RedisResult<u64> = conn.del(self.key(app_id)).await;
+            removed.map(|n| n > 0).unwrap_or(false)
+        }
+
+        async fn list_sessions(&self) -> Vec<BotContext> {
+            let Ok(mut conn) = self.client.get_multiplexed_async_connection().await else {
+                return Vec::new();
+            };
+            // SCAN 避免 KEYS 阻塞服务端
+            let pattern = format!("{}:*", self.prefix);
+            let mut keys = Vec::new();
+            let mut cursor: u64 = 0;
+            loop {
+                let page: redis::RedisResult<(u64, Vec<String>)> = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(100)
+                    .query_async(&mut conn)
+                    .await;
+                let Ok((next, batch)) = page else {
+                    tracing::warn!("failed to scan sessions");
+                    break;
+                };
+                keys.extend(batch);
+                cursor = next;
+                if cursor == 0 {
+                    break;
+                }
+            }
+
+            let mut sessions = Vec::new();
+            for key in keys {
+                let payload: Option<String> = conn.get(&key).await.ok().flatten();
+                if let Some(entry) =
+                    payload.and_then(|p| serde_json::from_str::<StoredEntry>(&p).ok())
+                {
+                    sessions.push(entry.context);
+                }
+            }
+            sessions.sort_by(|a, b| a.app_id.0.cmp(&b.app_id.0));
+            sessions
+        }
+    }
+
+    /// Message claims shared by every process connected to the same Redis
+    #[derive(Clone)]
+    pub struct RedisClaimStore {
+        client: Client,
+        prefix: String,
+    }
+
+    /// KEYS[1] claim, ARGV: priority, app_id, ttl secs
+    const BID_SCRIPT: &str = r#"
+if redis.call('HGET', KEYS[1], 'decided') == '1' then return 0 end
+local p = tonumber(ARGV[1])
+local best = redis.call('HGET', KEYS[1], 'priority')
+local app = redis.call('HGET', KEYS[1], 'app_id')
+if (not best) or p < tonumber(best) or (p == tonumber(best) and ARGV[2] < app) then
+  redis.call('HSET', KEYS[1], 'priority', ARGV[1], 'app_id', ARGV[2])
+end
+if redis.call('TTL', KEYS[1]) < 0 then redis.call('EXPIRE', KEYS[1], ARGV[3]) end
+return 1
+"#;
+
+    /// KEYS[1] claim, ARGV: app_id
+    const SETTLE_SCRIPT: &str = r#"
+local app = redis.call('HGET', KEYS[1], 'app_id')
+if not app then return 1 end
+if app == ARGV[1] then
+  redis.call('HSET', KEYS[1], 'decided', '1')
+  return 1
+end
+return 0
+"#;
+
+    impl RedisClaimStore {
+        pub fn new(url: &str, prefix: impl Into<String>) -> redis::RedisResult<Self> {
+            Ok(Self {
+                client: Client::open(url)?,
+                prefix: prefix.into(),
+            })
+        }
+
+        fn key(&self, key: &str) -> String {
+            format!("{}:{}", self.prefix, key)
+        }
+    }
+
+    #[async_trait]
+    impl MessageClaimStore for RedisClaimStore {
+        async fn bid(&self, key: &str, priority: i32, app_id: &str) {
+            let result: redis::RedisResult<i64> = async {
+                let mut conn = self.client.get_multiplexed_async_connection().await?;
+                Script::new(BID_SCRIPT)
+                    .key(self.key(key))
+                    .arg(priority)
+                    .arg(app_id)
+                    .arg(CLAIM_TTL.as_secs())
+                    .invoke_async(&mut conn)
+                    .await
+            }
+            .await;
+            if let Err(err) = result {
+                tracing::warn!(?err, key, "failed to record message claim");
+            }
+        }
+
+        async fn settle(&self, key: &str, app_id: &str) -> bool {
+            let result: redis::RedisResult<i64> = async {
+                let mut conn = self.client.get_multiplexed_async_connection().await?;
+                Script::new(SETTLE_SCRIPT)
+                    .key(self.key(key))
+                    .arg(app_id)
+                    .invoke_async(&mut conn)
+                    .await
+            }
+            .await;
+            match result {
+                Ok(won) => won == 1,
+                Err(err) => {
+                    // respond anyway: a duplicate reply is better than none
+                    tracing::warn!(?err, key, "failed to settle message claim");
+                    true
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,291 @@
+//! 回调载荷夹具
+//!
+//! [`normalize_fixtures_dir`] 下保存脱敏后的真实回调请求体（与 `GEWE_WEBHOOK_DUMP_DIR`
+//! 落盘的格式相同），normalize 模块的测试对每个夹具做消息规范化，并用 insta 快照比对结果。
+//!
+//! 新夹具通过 [`import_dump_dir`] 从 dump 目录导入（也可运行
+//! `gewe-bot-app import-fixtures <dump 目录>`）：Appid、wxid、群 ID 会替换为稳定的占位符，
+//! 昵称与消息正文保持原样，提交前需人工检查。
+
+use crate::normalize::{extract_appmsg_type, extract_group_sender};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// normalize 测试读取的夹具目录，即本 crate 源码中的 `src/fixtures/normalize`
+pub fn normalize_fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/fixtures/normalize")
+}
+
+/// 脱敏回调请求体：Appid 替换为 `wx_app`，机器人自身 wxid 替换为 `wxid_bot`，
+/// 其他 wxid 依出现顺序替换为 `wxid_user1`、`wxid_user2`…，群 ID 替换为 `room1@chatroom`…
+///
+/// 替换作用于所有字符串（包括 Content 中的 XML），同一标识始终映射到同一占位符。
+pub fn redact(body: &Value) -> Value {
+    let mut ids = Identifiers::default();
+    if let Some(app_id) = body.get("Appid").and_then(|v| v.as_str()) {
+        ids.insert(app_id, "wx_app".to_string());
+    }
+    if let Some(bot) = body.get("Wxid").and_then(|v| v.as_str()) {
+        ids.insert(bot, "wxid_bot".to_string());
+    }
+    if let Some(data) = body.get("Data") {
+        let field = |key: &str| {
+            data.get(key)
+                .and_then(|v| v.get("string"))
+                .and_then(|v| v.as_str())
+        };
+        // 自定义微信号不以 wxid_ 开头，只能从收发方字段与群聊前缀中识别
+        for wxid in [field("FromUserName"), field("ToUserName")]
+            .into_iter()
+            .flatten()
+        {
+            ids.add_wxid(wxid);
+        }
+        if field("FromUserName").is_some_and(|w| w.ends_with("@chatroom")) {
+            if let Some(sender) = field("Content").and_then(extract_group_sender) {
+                ids.add_wxid(&sender);
+            }
+        }
+    }
+    collect_strings(body, &mut |s| {
+        for m in identifier_re().find_iter(s) {
+            ids.add_wxid(m.as_str());
+        }
+    });
+    ids.apply(body)
+}
+
+/// 由回调内容生成夹具名，如 `addmsg_49_5`、`modcontacts`
+pub fn fixture_name(body: &Value) -> String {
+    let mut name = body
+        .get("TypeName")
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown")
+        .to_ascii_lowercase();
+    let data = body.get("Data");
+    if let Some(msg_type) = data.and_then(|d| d.get("MsgType")).and_then(|v| v.as_i64()) {
+        name.push_str(&format!("_{msg_type}"));
+        let content = data
+            .and_then(|d| d.get("Content"))
+            .and_then(|v| v.get("string"))
+            .and_then(|v| v.as_str());
+        if let Some(appmsg_type) = extract_appmsg_type(Some(msg_type), content) {
+            name.push_str(&format!("_{appmsg_type}"));
+        }
+    }
+    name
+}
+
+/// 导入单个 dump 文件：脱敏后以格式化 JSON 写入 `{fixtures_dir}/{name}.json`
+pub fn import_dump(dump: &Path, fixtures_dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let raw = std::fs::read(dump).map_err(|e| context(e, "读取 dump", dump))?;
+    let body: Value = serde_json::from_slice(&raw).map_err(|e| context(e, "解析 dump", dump))?;
+    std::fs::create_dir_all(fixtures_dir)?;
+    let path = fixtures_dir.join(format!("{name}.json"));
+    let mut text = serde_json::to_string_pretty(&redact(&body))?;
+    text.push('\n');
+    std::fs::write(&path, text).map_err(|e| context(e, "写入夹具", &path))?;
+    Ok(path)
+}
+
+/// 导入 dump 目录下全部回调，夹具名由 [`fixture_name`] 生成，重名时追加序号；
+/// 无法解析的文件跳过，返回新写入的夹具路径
+pub fn import_dump_dir(dump_dir: &Path, fixtures_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for dump in json_files(dump_dir)? {
+        let body: Value = match std::fs::read(&dump)
+            .ok()
+            .and_then(|raw| serde_json::from_slice(&raw).ok())
+        {
+            Some(body) => body,
+            None => {
+                tracing::warn!(path = %dump.display(), "跳过无法解析的 dump");
+                continue;
+            }
+        };
+        let base = fixture_name(&body);
+        let mut name = base.clone();
+        let mut n = 1;
+        while fixtures_dir.join(format!("{name}.json")).exists() {
+            n += 1;
+            name = format!("{base}_{n}");
+        }
+        written.push(import_dump(&dump, fixtures_dir, &name)?);
+    }
+    Ok(written)
+}
+
+fn json_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| context(e, "读取目录", dir))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn context(err: impl std::fmt::Display, action: &str, path: &Path) -> io::Error {
+    io::Error::other(format!("{action} {} 失败: {err}", path.display()))
+}
+
+fn identifier_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\bwxid_[A-Za-z0-9_-]+|\b\d+@chatroom").unwrap())
+}
+
+fn collect_strings(value: &Value, f: &mut impl FnMut(&str)) {
+    match value {
+        Value::String(s) => f(s),
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, f)),
+        Value::Object(map) => map.values().for_each(|v| collect_strings(v, f)),
+        _ => {}
+    }
+}
+
+/// 原始标识到占位符的映射
+#[derive(Default)]
+struct Identifiers {
+    map: HashMap<String, String>,
+    users: usize,
+    rooms: usize,
+}
+
+impl Identifiers {
+    fn insert(&mut self, id: &str, placeholder: String) {
+        if !id.is_empty() {
+            self.map.entry(id.to_string()).or_insert(placeholder);
+        }
+    }
+
+    fn add_wxid(&mut self, id: &str) {
+        if id.is_empty() || self.map.contains_key(id) {
+            return;
+        }
+        let placeholder = if id.ends_with("@chatroom") {
+            self.rooms += 1;
+            format!("room{}@chatroom", self.rooms)
+        } else {
+            self.users += 1;
+            format!("wxid_user{}", self.users)
+        };
+        self.map.insert(id.to_string(), placeholder);
+    }
+
+    fn apply(&self, value: &Value) -> Value {
+        if self.map.is_empty() {
+            return value.clone();
+        }
+        // 长标识优先，避免 wxid_a 先于 wxid_ab 匹配
+        let mut keys: Vec<&str> = self.map.keys().map(String::as_str).collect();
+        keys.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        let pattern = keys
+            .iter()
+            .map(|k| regex::escape(k))
+            .collect::<Vec<_>>()
+            .join("|");
+        let re = Regex::new(&pattern).expect("escaped identifiers");
+        self.replace(value, &re)
+    }
+
+    fn replace(&self, value: &Value, re: &Regex) -> Value {
+        match value {
+            Value::String(s) => Value::String(
+                re.replace_all(s, |caps: &regex::Captures| self.map[&caps[0]].clone())
+                    .into_owned(),
+            ),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| self.replace(v, re)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.replace(v, re)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn group_text() -> Value {
+        json!({
+            "TypeName": "AddMsg",
+            "Appid": "wx_8f2a7c",
+            "Wxid": "wxid_real_bot",
+            "Data": {
+                "MsgType": 1,
+                "FromUserName": {"string": "34757816141@chatroom"},
+                "ToUserName": {"string": "wxid_real_bot"},
+                "Content": {"string": "alice_custom:\n@bot 你好 wxid_someone_else"},
+                "MsgSource": "<msgsource><atuserlist>wxid_real_bot</atuserlist></msgsource>",
+                "NewMsgId": 1
+            }
+        })
+    }
+
+    #[test]
+    fn test_redact_replaces_identifiers_consistently() {
+        let redacted = redact(&group_text());
+        assert_eq!(redacted["Appid"], "wx_app");
+        assert_eq!(redacted["Wxid"], "wxid_bot");
+        let data = &redacted["Data"];
+        assert_eq!(data["FromUserName"]["string"], "room1@chatroom");
+        assert_eq!(data["ToUserName"]["string"], "wxid_bot");
+        assert_eq!(
+            data["Content"]["string"],
+            "wxid_user1:\n@bot 你好 wxid_user2"
+        );
+        assert_eq!(
+            data["MsgSource"],
+            "<msgsource><atuserlist>wxid_bot</atuserlist></msgsource>"
+        );
+        assert_eq!(data["MsgType"], 1);
+        // 再次脱敏结果不变
+        assert_eq!(redact(&redacted), redacted);
+    }
+
+    #[test]
+    fn test_fixture_name() {
+        assert_eq!(fixture_name(&group_text()), "addmsg_1");
+        let link = json!({
+            "TypeName": "AddMsg",
+            "Data": {"MsgType": 49, "Content": {"string": "<msg><appmsg><type>5</type></appmsg></msg>"}}
+        });
+        assert_eq!(fixture_name(&link), "addmsg_49_5");
+        assert_eq!(
+            fixture_name(&json!({"TypeName": "ModContacts"})),
+            "modcontacts"
+        );
+    }
+
+    #[test]
+    fn test_import_dump_dir() {
+        let dumps = tempfile::tempdir().unwrap();
+        let fixtures = tempfile::tempdir().unwrap();
+        let body = serde_json::to_vec(&group_text()).unwrap();
+        std::fs::write(dumps.path().join("1700000000000_wx_8f2a7c.json"), &body).unwrap();
+        std::fs::write(dumps.path().join("1700000000001_wx_8f2a7c.json"), &body).unwrap();
+        std::fs::write(dumps.path().join("broken.json"), b"not json").unwrap();
+
+        let written = import_dump_dir(dumps.path(), fixtures.path()).unwrap();
+        let names: Vec<_> = written
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["addmsg_1.json", "addmsg_1_2.json"]);
+
+        let loaded: Value = serde_json::from_slice(&std::fs::read(&written[0]).unwrap()).unwrap();
+        assert_eq!(loaded["Appid"], "wx_app");
+        assert_eq!(loaded["TypeName"], "AddMsg");
+        assert_eq!(loaded["Data"]["NewMsgId"], 1);
+    }
+}
@@ -9,14 +9,48 @@ use axum::{
 use gewe_core::{AppId, BotContext};
 use gewe_session::SessionStore;
 use hmac::{Hmac, Mac};
+use normalize::extract_new_msg_id;
 use serde::Deserialize;
 use sha2::Sha256;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::fs;
 use tokio::sync::mpsc;
 use tracing::instrument;
 
+pub mod fixtures;
+pub mod normalize;
+mod ws;
+
+pub use ws::{EventStream, DEFAULT_EVENT_STREAM_CAPACITY};
+
+/// 收到的回调（不含 ping 与仅抓包模式）
+pub const METRIC_EVENTS_RECEIVED: &str = "gewe_webhook_events_received_total";
+/// 按 NewMsgId 去重丢弃的回调
+pub const METRIC_EVENTS_DEDUPED: &str = "gewe_webhook_events_deduped_total";
+/// 通过校验但未能投递的事件，`reason` 为 `queue_full` 或 `no_handler`
+pub const METRIC_EVENTS_DROPPED: &str = "gewe_webhook_events_dropped_total";
+/// 请求体无效或 app_id 未登记而拒绝的回调，`reason` 为 `invalid_body` 或 `unknown_app`
+pub const METRIC_EVENTS_REJECTED: &str = "gewe_webhook_events_rejected_total";
+/// 签名校验失败的回调
+pub const METRIC_SIGNATURE_FAILURES: &str = "gewe_webhook_signature_failures_total";
+/// 事件队列中等待处理的事件数
+pub const METRIC_QUEUE_DEPTH: &str = "gewe_webhook_queue_depth";
+
+/// 登记回调指标的说明。指标通过 `metrics` 门面记录，应用安装记录器（如 Prometheus 导出器）后生效，
+/// 未安装时记录操作为空操作
+pub fn describe_metrics() {
+    metrics::describe_counter!(METRIC_EVENTS_RECEIVED, "收到的 webhook 回调数");
+    metrics::describe_counter!(METRIC_EVENTS_DEDUPED, "按 NewMsgId 去重丢弃的回调数");
+    metrics::describe_counter!(METRIC_EVENTS_DROPPED, "通过校验但未能投递的事件数");
+    metrics::describe_counter!(METRIC_EVENTS_REJECTED, "请求体无效或 app_id 未登记的回调数");
+    metrics::describe_counter!(METRIC_SIGNATURE_FAILURES, "签名校验失败的回调数");
+    metrics::describe_gauge!(METRIC_QUEUE_DEPTH, "事件队列中等待处理的事件数");
+}
+
 #[derive(Clone)]
 pub struct WebhookState<S> {
     pub store: Arc<S>,
@@ -68,21 +102,64 @@ pub fn router_with_channel_and_store<S>(
     opts: WebhookBuilderOptions,
     store: Arc<S>,
 ) -> (Router, mpsc::Receiver<WebhookEvent>)
+where
+    S: SessionStore + Send + Sync + Clone + 'static,
+{
+    webhook_router(opts, store, None)
+}
+
+/// 在 `/webhook` 之外挂载 `/ws/events`，通过校验的事件同时推送给 WebSocket 客户端，
+/// 看板等外部消费者无需共享事件接收端即可实时订阅。
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use gewe_session::InMemorySessionStore;
+/// use gewe_webhook::{router_with_events, EventStream};
+///
+/// let store = Arc::new(InMemorySessionStore::default());
+/// let events = EventStream::default().with_access_token("dashboard-token");
+/// let (router, rx) = router_with_events(Default::default(), store, events);
+/// # let _ = (router, rx);
+/// ```
+pub fn router_with_events<S>(
+    opts: WebhookBuilderOptions,
+    store: Arc<S>,
+    events: EventStream,
+) -> (Router, mpsc::Receiver<WebhookEvent>)
+where
+    S: SessionStore + Send + Sync + Clone + 'static,
+{
+    webhook_router(opts, store, Some(events))
+}
+
+fn webhook_router<S>(
+    opts: WebhookBuilderOptions,
+    store: Arc<S>,
+    events: Option<EventStream>,
+) -> (Router, mpsc::Receiver<WebhookEvent>)
 where
     S: SessionStore + Send + Sync + Clone + 'static,
 {
     let (tx, rx) = mpsc::channel(opts.queue_size);
-    let state = WebhookState { store, tx };
+    let state = WebhookState {
+        store: Arc::clone(&store),
+        tx,
+    };
+    let publisher = events.clone();
     let router: Router<()> = Router::new()
         .route(
             "/webhook",
             post(
-                |State(state): State<WebhookState<S>>, headers: HeaderMap, body: Bytes| async move {
-                    handle_webhook::<S>(state, headers, body).await
+                move |State(state): State<WebhookState<S>>, headers: HeaderMap, body: Bytes| async move {
+                    handle_webhook::<S>(state, publisher.as_ref(), headers, body).await
                 },
             ),
         )
         .with_state(state);
+    let router = match events {
+        Some(events) => router.merge(events.router(store)),
+        None => router,
+    };
     (router, rx)
 }
 
@@ -95,6 +172,155 @@ where
     router
 }
 
+/// 事件处理器返回的 future
+pub type HandlerFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+type Handler = Arc<dyn Fn(WebhookEvent) -> HandlerFuture + Send + Sync>;
+
+#[derive(Default)]
+struct Handlers {
+    by_type: HashMap<String, Handler>,
+    fallback: Option<Handler>,
+}
+
+impl Handlers {
+    fn get(&self, type_name: Option<&str>) -> Option<&Handler> {
+        type_name
+            .and_then(|name| self.by_type.get(name))
+            .or(self.fallback.as_ref())
+    }
+}
+
+struct HandlerState<S> {
+    store: Arc<S>,
+    handlers: Arc<Handlers>,
+    events: Option<EventStream>,
+}
+
+impl<S> Clone for HandlerState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            handlers: Arc::clone(&self.handlers),
+            events: self.events.clone(),
+        }
+    }
+}
+
+/// 按 TypeName 注册异步处理器的 webhook 构建器，无需自行消费事件队列。
+///
+/// 事件通过签名校验与 NewMsgId 去重后，按 TypeName 选择处理器（未注册时使用 fallback），
+/// 在独立任务中执行，不阻塞 webhook 响应；两者都没有时丢弃事件。
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use gewe_session::InMemorySessionStore;
+/// use gewe_webhook::WebhookBuilder;
+///
+/// let store = Arc::new(InMemorySessionStore::default());
+/// let router = WebhookBuilder::new(Arc::clone(&store))
+///     .on("AddMsg", |event| async move {
+///         println!("收到消息: {}", event.data);
+///     })
+///     .fallback(|event| async move {
+///         println!("其他事件: {:?}", event.type_name);
+///     })
+///     .build();
+/// # let _ = router;
+/// ```
+pub struct WebhookBuilder<S> {
+    store: Arc<S>,
+    handlers: Handlers,
+    events: Option<EventStream>,
+}
+
+impl<S> WebhookBuilder<S>
+where
+    S: SessionStore + Send + Sync + 'static,
+{
+    /// 使用外部持有的 `SessionStore`，便于启动时写入 BotContext。
+    pub fn new(store: Arc<S>) -> Self {
+        Self {
+            store,
+            handlers: Handlers::default(),
+            events: None,
+        }
+    }
+
+    /// 注册指定 TypeName（如 `AddMsg`、`ModContacts`）的处理器，重复注册时后者覆盖前者。
+    pub fn on<F, Fut>(mut self, type_name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(WebhookEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.handlers
+            .by_type
+            .insert(type_name.into(), boxed_handler(handler));
+        self
+    }
+
+    /// 注册兜底处理器，处理未单独注册 TypeName 的事件。
+    pub fn fallback<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(WebhookEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.handlers.fallback = Some(boxed_handler(handler));
+        self
+    }
+
+    /// 同时挂载 `/ws/events`，把通过校验的事件推送给 WebSocket 客户端，见 [`router_with_events`]。
+    pub fn events(mut self, events: EventStream) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn build(self) -> Router {
+        let ws = self
+            .events
+            .as_ref()
+            .map(|events| events.router(Arc::clone(&self.store)));
+        let state = HandlerState {
+            store: self.store,
+            handlers: Arc::new(self.handlers),
+            events: self.events,
+        };
+        let router = Router::new()
+            .route(
+                "/webhook",
+                post(
+                    |State(state): State<HandlerState<S>>,
+                     headers: HeaderMap,
+                     body: Bytes| async move {
+                        handle_webhook_with_handlers::<S>(state, headers, body).await
+                    },
+                ),
+            )
+            .with_state(state);
+        match ws {
+            Some(ws) => router.merge(ws),
+            None => router,
+        }
+    }
+}
+
+impl<S> Default for WebhookBuilder<S>
+where
+    S: SessionStore + Default + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new(Arc::new(S::default()))
+    }
+}
+
+fn boxed_handler<F, Fut>(handler: F) -> Handler
+where
+    F: Fn(WebhookEvent) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    Arc::new(move |event| Box::pin(handler(event)) as HandlerFuture)
+}
+
 #[derive(Debug, Deserialize)]
 struct WebhookBody {
     #[serde(rename = "Appid")]
@@ -105,66 +331,130 @@ struct WebhookBody {
     type_name: Option<String>,
 }
 
-#[instrument(skip(state, headers, raw_body))]
+#[instrument(skip(state, events, headers, raw_body))]
 async fn handle_webhook<S>(
     state: WebhookState<S>,
+    events: Option<&EventStream>,
+    headers: HeaderMap,
+    raw_body: Bytes,
+) -> impl IntoResponse
+where
+    S: SessionStore + Send + Sync + 'static,
+{
+    let event = match accept_event(state.store.as_ref(), &headers, &raw_body).await {
+        Ok(Some(event)) => event,
+        Ok(None) => return StatusCode::OK,
+        Err(status) => return status,
+    };
+
+    if let Some(events) = events {
+        events.publish(&event);
+    }
+
+    // 投递到异步队列，避免阻塞 3s SLA
+    if let Err(err) = state.tx.try_send(event) {
+        metrics::counter!(METRIC_EVENTS_DROPPED, "reason" => "queue_full").increment(1);
+        tracing::warn!(?err, "webhook queue full; dropping event");
+    }
+    metrics::gauge!(METRIC_QUEUE_DEPTH).set((state.tx.max_capacity() - state.tx.capacity()) as f64);
+
+    StatusCode::OK
+}
+
+#[instrument(skip(state, headers, raw_body))]
+async fn handle_webhook_with_handlers<S>(
+    state: HandlerState<S>,
     headers: HeaderMap,
     raw_body: Bytes,
 ) -> impl IntoResponse
 where
     S: SessionStore + Send + Sync + 'static,
 {
-    log_request_pre_parse(&headers, &raw_body);
+    let event = match accept_event(state.store.as_ref(), &headers, &raw_body).await {
+        Ok(Some(event)) => event,
+        Ok(None) => return StatusCode::OK,
+        Err(status) => return status,
+    };
+
+    if let Some(events) = &state.events {
+        events.publish(&event);
+    }
+
+    match state.handlers.get(event.type_name.as_deref()) {
+        // 在独立任务中执行，避免阻塞 3s SLA
+        Some(handler) => {
+            tokio::spawn(handler(event));
+        }
+        None => {
+            metrics::counter!(METRIC_EVENTS_DROPPED, "reason" => "no_handler").increment(1);
+            tracing::debug!(type_name = ?event.type_name, "no webhook handler; dropping event")
+        }
+    }
+
+    StatusCode::OK
+}
+
+/// 校验并解析 webhook 请求：返回待处理的事件；ping、仅抓包模式与重复消息返回 None，
+/// 校验失败时返回应答状态码
+async fn accept_event<S>(
+    store: &S,
+    headers: &HeaderMap,
+    raw_body: &[u8],
+) -> Result<Option<WebhookEvent>, StatusCode>
+where
+    S: SessionStore + Send + Sync + 'static,
+{
+    log_request_pre_parse(headers, raw_body);
     if capture_only() {
-        return StatusCode::OK;
+        return Ok(None);
     }
 
-    if is_ping(&raw_body) {
-        tracing::info!("webhook ping: {}", String::from_utf8_lossy(&raw_body));
-        return StatusCode::OK;
+    if is_ping(raw_body) {
+        tracing::info!("webhook ping: {}", String::from_utf8_lossy(raw_body));
+        return Ok(None);
     }
+    metrics::counter!(METRIC_EVENTS_RECEIVED).increment(1);
 
-    let body: WebhookBody = match serde_json::from_slice(&raw_body) {
+    let body: WebhookBody = match serde_json::from_slice(raw_body) {
         Ok(v) => v,
         Err(err) => {
-            log_raw_invalid_body(&raw_body);
+            metrics::counter!(METRIC_EVENTS_REJECTED, "reason" => "invalid_body").increment(1);
+            log_raw_invalid_body(raw_body);
             tracing::warn!(?err, "invalid webhook body");
-            return StatusCode::BAD_REQUEST;
+            return Err(StatusCode::BAD_REQUEST);
         }
     };
-    maybe_dump_raw(&body.appid, &raw_body).await;
+    maybe_dump_raw(&body.appid, raw_body).await;
 
     let app_id = AppId(body.appid.clone());
-    let Some(ctx) = state.store.get_session(&app_id).await else {
+    let Some(ctx) = store.get_session(&app_id).await else {
+        metrics::counter!(METRIC_EVENTS_REJECTED, "reason" => "unknown_app").increment(1);
         tracing::warn!("unknown app_id for webhook");
-        return StatusCode::UNAUTHORIZED;
+        return Err(StatusCode::UNAUTHORIZED);
     };
 
     if require_signature() {
-        if let Err(err) = verify_signature(&headers, &ctx, &raw_body) {
-            log_headers_on_verify_fail(&headers);
-            log_raw_on_verify_fail(&raw_body);
+        if let Err(err) = verify_signature(headers, &ctx, raw_body) {
+            metrics::counter!(METRIC_SIGNATURE_FAILURES).increment(1);
+            log_headers_on_verify_fail(headers);
+            log_raw_on_verify_fail(raw_body);
             tracing::warn!(?err, "webhook signature verify failed");
-            return StatusCode::UNAUTHORIZED;
+            return Err(StatusCode::UNAUTHORIZED);
         }
     }
 
     if let Some(mid) = extract_new_msg_id(&body.data) {
-        if !state.store.mark_message_seen(&app_id, mid).await {
-            return StatusCode::OK;
+        if !store.mark_message_seen(&app_id, mid).await {
+            metrics::counter!(METRIC_EVENTS_DEDUPED).increment(1);
+            return Ok(None);
         }
     }
 
-    // 投递到异步队列，避免阻塞 3s SLA
-    if let Err(err) = state.tx.try_send(WebhookEvent {
+    Ok(Some(WebhookEvent {
         app_id,
         type_name: body.type_name,
         data: body.data,
-    }) {
-        tracing::warn!(?err, "webhook queue full; dropping event");
-    }
-
-    StatusCode::OK
+    }))
 }
 
 fn dump_dir() -> Option<String> {
@@ -341,14 +631,6 @@ fn verify_signature(
     }
 }
 
-fn extract_new_msg_id(data: &serde_json::Value) -> Option<i64> {
-    data.get("NewMsgId").and_then(|v| v.as_i64()).or_else(|| {
-        data.get("Data")
-            .and_then(|inner| inner.get("NewMsgId"))
-            .and_then(|v| v.as_i64())
-    })
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -927,6 +1209,49 @@ mod tests {
         assert!(rx.try_recv().is_err());
     }
 
+    #[test]
+    fn test_handle_webhook_metrics() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let (router, _rx, store) =
+                    router_with_channel_and_state::<InMemorySessionStore>(WebhookBuilderOptions {
+                        queue_size: 10,
+                    });
+                store
+                    .put_session(create_test_context("app123", "token123"))
+                    .await;
+                for body in [
+                    r#"{"Appid":"app123","Data":{"NewMsgId":1}}"#,
+                    r#"{"Appid":"app123","Data":{"NewMsgId":1}}"#,
+                    r#"{"Appid":"unknown","Data":{}}"#,
+                    "not json",
+                ] {
+                    let request = Request::builder()
+                        .uri("/webhook")
+                        .method("POST")
+                        .body(Body::from(body))
+                        .unwrap();
+                    router.clone().oneshot(request).await.unwrap();
+                }
+            })
+        });
+        let rendered = handle.render();
+        for line in [
+            "gewe_webhook_events_received_total 4",
+            "gewe_webhook_events_deduped_total 1",
+            "gewe_webhook_events_rejected_total{reason=\"unknown_app\"} 1",
+            "gewe_webhook_events_rejected_total{reason=\"invalid_body\"} 1",
+            "gewe_webhook_queue_depth 1",
+        ] {
+            assert!(rendered.contains(line), "{line}\n{rendered}");
+        }
+    }
+
     #[tokio::test]
     async fn test_handle_webhook_nested_new_msg_id() {
         let (router, mut rx, store) =
@@ -1283,4 +1608,173 @@ mod tests {
         let result = verify_signature(&headers, &ctx, body);
         assert!(matches!(result, Err(SignatureError::Stale)));
     }
+
+    // ===== WebhookBuilder handler tests =====
+    fn webhook_request(body: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/webhook")
+            .method("POST")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_router_with_events_publishes_accepted_events() {
+        let store = Arc::new(InMemorySessionStore::default());
+        store
+            .put_session(create_test_context("app123", "token123"))
+            .await;
+        let events = EventStream::default();
+        let mut subscriber = events.subscribe();
+        let (router, mut rx) = router_with_events(
+            WebhookBuilderOptions { queue_size: 10 },
+            Arc::clone(&store),
+            events.clone(),
+        );
+
+        let body = r#"{"Appid":"app123","Data":{"NewMsgId":7},"TypeName":"AddMsg"}"#;
+        let response = router.clone().oneshot(webhook_request(body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(rx.try_recv().unwrap().app_id.0, "app123");
+        let published = subscriber.try_recv().unwrap();
+        assert_eq!(published.type_name.as_deref(), Some("AddMsg"));
+
+        // Duplicates are not published
+        let response = router.clone().oneshot(webhook_request(body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(subscriber.try_recv().is_err());
+
+        // The /ws/events route is mounted alongside /webhook
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/ws/events")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_builder_publishes_events() {
+        let store = Arc::new(InMemorySessionStore::default());
+        store
+            .put_session(create_test_context("app123", "token123"))
+            .await;
+        let events = EventStream::default();
+        let mut subscriber = events.subscribe();
+        let router = WebhookBuilder::new(store)
+            .events(events)
+            .on("AddMsg", |_| async {})
+            .build();
+
+        let response = router
+            .oneshot(webhook_request(
+                r#"{"Appid":"app123","Data":{"NewMsgId":1},"TypeName":"AddMsg"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(subscriber.try_recv().unwrap().app_id.0, "app123");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_builder_dispatches_by_type_name() {
+        let store = Arc::new(InMemorySessionStore::default());
+        store
+            .put_session(create_test_context("app123", "token123"))
+            .await;
+        let (tx, mut rx) = mpsc::unbounded_channel::<(&'static str, WebhookEvent)>();
+        let msg_tx = tx.clone();
+        let router = WebhookBuilder::new(Arc::clone(&store))
+            .on("AddMsg", move |event| {
+                let tx = msg_tx.clone();
+                async move {
+                    let _ = tx.send(("AddMsg", event));
+                }
+            })
+            .fallback(move |event| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(("fallback", event));
+                }
+            })
+            .build();
+
+        let response = router
+            .clone()
+            .oneshot(webhook_request(
+                r#"{"Appid":"app123","Data":{"NewMsgId":1},"TypeName":"AddMsg"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let (handler, event) = rx.recv().await.unwrap();
+        assert_eq!(handler, "AddMsg");
+        assert_eq!(event.app_id.0, "app123");
+
+        let response = router
+            .clone()
+            .oneshot(webhook_request(
+                r#"{"Appid":"app123","Data":{},"TypeName":"ModContacts"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let (handler, event) = rx.recv().await.unwrap();
+        assert_eq!(handler, "fallback");
+        assert_eq!(event.type_name.as_deref(), Some("ModContacts"));
+
+        // Duplicate NewMsgId is filtered before handlers run
+        let response = router
+            .clone()
+            .oneshot(webhook_request(
+                r#"{"Appid":"app123","Data":{"NewMsgId":1},"TypeName":"AddMsg"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Unknown app_id is rejected before handlers run
+        let response = router
+            .oneshot(webhook_request(
+                r#"{"Appid":"unknown","Data":{},"TypeName":"AddMsg"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        tokio::task::yield_now().await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_builder_without_fallback_drops_event() {
+        let store = Arc::new(InMemorySessionStore::default());
+        store
+            .put_session(create_test_context("app123", "token123"))
+            .await;
+        let (tx, mut rx) = mpsc::unbounded_channel::<WebhookEvent>();
+        let router = WebhookBuilder::new(store)
+            .on("AddMsg", move |event| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(event);
+                }
+            })
+            .build();
+
+        let response = router
+            .oneshot(webhook_request(
+                r#"{"Appid":"app123","Data":{},"TypeName":"Offline"}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        tokio::task::yield_now().await;
+        assert!(rx.try_recv().is_err());
+    }
 }
@@ -0,0 +1,1347 @@
+//! 回调事件规范化
+//!
+//! 把 [`WebhookEvent`] 解析为 [`NormalizedEvent`]：消息类型、群聊/私聊、群成员发送者、
+//! 去掉群聊前缀的正文，以及文件、表情、链接、位置、红包/转账、名片等附带信息。
+//! gewe-bot-app 的规则匹配、gewe-rules 的过滤表达式都基于这里的结果，自定义程序可以直接复用：
+//!
+//! ```
+//! use gewe_core::AppId;
+//! use gewe_webhook::normalize::{ChatKind, MessageKind};
+//! use gewe_webhook::WebhookEvent;
+//!
+//! let event = WebhookEvent {
+//!     app_id: AppId("wx_app".into()),
+//!     type_name: Some("AddMsg".into()),
+//!     data: serde_json::json!({
+//!         "MsgType": 1,
+//!         "FromUserName": {"string": "123@chatroom"},
+//!         "ToUserName": {"string": "wxid_bot"},
+//!         "Content": {"string": "wxid_alice:\n看看 https://example.com"},
+//!         "NewMsgId": 42
+//!     }),
+//! };
+//! let norm = event.normalize().unwrap();
+//! assert_eq!(norm.kind, MessageKind::Text);
+//! assert_eq!(norm.chat, Some(ChatKind::Group));
+//! assert_eq!(norm.sender_wxid(), Some("wxid_alice"));
+//! assert_eq!(norm.content.as_deref(), Some("看看 https://example.com"));
+//! assert_eq!(norm.urls, vec!["https://example.com"]);
+//! ```
+
+use crate::WebhookEvent;
+use gewe_core::AppId;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// 消息类型，名称与 gewe-bot-app 规则的 `kind` 一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    Text,
+    Image,
+    Voice,
+    Video,
+    Emoji,
+    Link,
+    FileNotice,
+    Location,
+    RedPacket,
+    Transfer,
+    NameCard,
+    /// 联系人变更（ModContacts、DelContacts）与掉线通知（Offline）
+    ContactEvent,
+    /// 新成员入群（群聊系统消息），成员见 [`NormalizedEvent::member_change`]
+    MemberJoined,
+    /// 成员被移出或退出群聊（群聊系统消息）
+    MemberLeft,
+    /// 其他消息或事件
+    Other,
+}
+
+impl MessageKind {
+    pub const ALL: [MessageKind; 15] = [
+        MessageKind::Text,
+        MessageKind::Image,
+        MessageKind::Voice,
+        MessageKind::Video,
+        MessageKind::Emoji,
+        MessageKind::Link,
+        MessageKind::FileNotice,
+        MessageKind::Location,
+        MessageKind::RedPacket,
+        MessageKind::Transfer,
+        MessageKind::NameCard,
+        MessageKind::ContactEvent,
+        MessageKind::MemberJoined,
+        MessageKind::MemberLeft,
+        MessageKind::Other,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageKind::Text => "text",
+            MessageKind::Image => "image",
+            MessageKind::Voice => "voice",
+            MessageKind::Video => "video",
+            MessageKind::Emoji => "emoji",
+            MessageKind::Link => "link",
+            MessageKind::FileNotice => "file_notice",
+            MessageKind::Location => "location",
+            MessageKind::RedPacket => "red_packet",
+            MessageKind::Transfer => "transfer",
+            MessageKind::NameCard => "name_card",
+            MessageKind::ContactEvent => "contact_event",
+            MessageKind::MemberJoined => "member_joined",
+            MessageKind::MemberLeft => "member_left",
+            MessageKind::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.as_str() == name)
+    }
+
+    /// 由 MsgType 与 appmsg 子类型确定消息类型
+    pub fn from_msg_type(msg_type: i64, appmsg_type: Option<i32>) -> Self {
+        match (msg_type, appmsg_type) {
+            (1, _) => MessageKind::Text,
+            (3, _) => MessageKind::Image,
+            (34, _) => MessageKind::Voice,
+            (43, _) => MessageKind::Video,
+            (47, _) => MessageKind::Emoji,
+            (42, _) => MessageKind::NameCard,
+            (48, _) => MessageKind::Location,
+            (49, Some(5)) => MessageKind::Link,
+            (49, Some(74)) => MessageKind::FileNotice,
+            (49, Some(2000)) => MessageKind::Transfer,
+            (49, Some(2001)) => MessageKind::RedPacket,
+            _ => MessageKind::Other,
+        }
+    }
+}
+
+/// 会话类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatKind {
+    Private,
+    Group,
+}
+
+impl ChatKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatKind::Private => "private",
+            ChatKind::Group => "group",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "private" => Some(ChatKind::Private),
+            "group" => Some(ChatKind::Group),
+            _ => None,
+        }
+    }
+}
+
+/// 回调无法规范化
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum NormalizeError {
+    /// AddMsg 回调缺少 MsgType
+    MissingMsgType,
+}
+
+impl fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormalizeError::MissingMsgType => f.write_str("AddMsg 缺少 MsgType"),
+        }
+    }
+}
+
+impl std::error::Error for NormalizeError {}
+
+impl WebhookEvent {
+    /// 规范化回调，见 [`normalize_event`]
+    pub fn normalize(&self) -> Result<NormalizedEvent, NormalizeError> {
+        normalize_event(self)
+    }
+}
+
+/// 规范化后的回调事件
+///
+/// 非 AddMsg 回调只填充 `kind`、`app_id`、`type_name`、`new_msg_id`。
+#[derive(Debug, Clone, Serialize)]
+pub struct NormalizedEvent {
+    pub kind: MessageKind,
+    pub app_id: AppId,
+    /// AddMsg 的 MsgType
+    pub msg_type: Option<i64>,
+    /// 会话 ID：私聊为对方 wxid，群聊为群 ID
+    pub from_wxid: Option<String>,
+    /// 群聊中实际发言的群成员 wxid
+    pub group_sender_wxid: Option<String>,
+    /// 接收方，一般为机器人自身的 wxid
+    pub to_wxid: Option<String>,
+    /// 消息内容，群聊文本已去掉「发送者:」前缀
+    pub content: Option<String>,
+    /// 通知栏文本，形如「昵称 : 内容」
+    pub push_content: Option<String>,
+    /// MsgSource XML，含 `<atuserlist>` 等
+    pub msg_source: Option<String>,
+    /// appmsg（MsgType=49）XML 中的 `<type>`
+    pub appmsg_type: Option<i32>,
+    pub new_msg_id: Option<i64>,
+    pub chat: Option<ChatKind>,
+    /// 发送者昵称，来自 PushContent
+    pub nickname: Option<String>,
+    /// 回调的 TypeName，如 `AddMsg`
+    pub type_name: Option<String>,
+    /// 日志与摘要用的内容，见 [`normalize_content`]
+    pub normalized_content: Option<String>,
+    /// 文件扩展名（小写，不含点），来自 appmsg 附件信息或标题
+    pub file_ext: Option<String>,
+    /// 媒体/文件字节数，来自 XML 中的 length/totallen
+    pub file_size: Option<u64>,
+    /// 表情 md5（小写），仅表情消息
+    pub emoji_md5: Option<String>,
+    /// 是否为动图表情（<emoji type="2">），仅表情消息
+    pub emoji_animated: Option<bool>,
+    /// 文本或 appmsg 中出现的链接
+    pub urls: Vec<String>,
+    /// 位置消息解析结果
+    pub location: Option<LocationInfo>,
+    /// 红包/转账附带信息（金额不可见）
+    pub payment: Option<PaymentInfo>,
+    /// 名片消息解析结果
+    pub name_card: Option<NameCardInfo>,
+    /// 入群/退群系统消息解析结果
+    pub member_change: Option<MemberChange>,
+}
+
+/// 名片消息（MsgType=42）
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct NameCardInfo {
+    /// 名片中的 username：好友为 wxid，陌生人为 v3 加密串
+    pub wxid: String,
+    pub nickname: Option<String>,
+    pub avatar: Option<String>,
+    /// 陌生人的 v3（username 以 v3_ 开头时）
+    pub v3: Option<String>,
+    /// 添加好友所需的 v4（antispamticket）
+    pub v4: Option<String>,
+}
+
+/// 群成员变动方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberChangeKind {
+    Joined,
+    Left,
+}
+
+/// 入群/退群通知中出现的成员
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ChangedMember {
+    /// 纯文本通知（MsgType=10000）只有昵称，没有 wxid
+    pub wxid: Option<String>,
+    pub nickname: String,
+}
+
+/// 群成员变动：MsgType=10002 的 sysmsgtemplate 或 MsgType=10000 的文本通知
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemberChange {
+    pub kind: MemberChangeKind,
+    /// 入群或被移出的成员
+    pub members: Vec<ChangedMember>,
+    /// 邀请人、分享二维码的人或执行移出的人，「你」表示机器人自身
+    pub operator: Option<ChangedMember>,
+}
+
+impl MemberChange {
+    /// 成员昵称，以「、」连接
+    pub fn nicknames(&self) -> String {
+        self.members
+            .iter()
+            .map(|m| m.nickname.as_str())
+            .collect::<Vec<_>>()
+            .join("、")
+    }
+
+    /// 带 wxid 的成员，纯文本通知中的成员没有 wxid
+    pub fn wxids(&self) -> Vec<&str> {
+        self.members
+            .iter()
+            .filter_map(|m| m.wxid.as_deref())
+            .collect()
+    }
+}
+
+/// 红包（appmsg type=2001）与转账（type=2000）通知中可读取的信息
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PaymentInfo {
+    /// 红包祝福语或转账备注
+    pub memo: Option<String>,
+    /// 转账子类型：1 发起转账，3 已收款，4 已退还
+    pub pay_subtype: Option<i32>,
+}
+
+/// 位置分享消息（MsgType=48）中的坐标与地点名
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LocationInfo {
+    pub lat: f64,
+    pub lng: f64,
+    /// 详细地址（label）
+    pub label: Option<String>,
+    /// 地点名（poiname）
+    pub poi_name: Option<String>,
+}
+
+impl LocationInfo {
+    /// 展示用名称：优先地点名，其次地址
+    pub fn name(&self) -> Option<&str> {
+        self.poi_name.as_deref().or(self.label.as_deref())
+    }
+}
+
+impl NormalizedEvent {
+    pub fn nickname(&self) -> Option<String> {
+        self.nickname.clone()
+    }
+
+    /// 实际发送者：群聊为群成员 wxid（解析失败时退回群 ID），私聊为对方 wxid
+    pub fn sender_wxid(&self) -> Option<&str> {
+        if self.chat == Some(ChatKind::Group) {
+            self.group_sender_wxid
+                .as_deref()
+                .or(self.from_wxid.as_deref())
+        } else {
+            self.from_wxid.as_deref()
+        }
+    }
+}
+
+/// 把回调解析为 [`NormalizedEvent`]，AddMsg 缺少 MsgType 时返回错误
+pub fn normalize_event(event: &WebhookEvent) -> Result<NormalizedEvent, NormalizeError> {
+    let type_name = event.type_name.clone();
+    let mut norm = NormalizedEvent {
+        kind: MessageKind::Other,
+        app_id: event.app_id.clone(),
+        msg_type: None,
+        from_wxid: None,
+        group_sender_wxid: None,
+        to_wxid: None,
+        content: None,
+        push_content: None,
+        msg_source: None,
+        appmsg_type: None,
+        new_msg_id: extract_new_msg_id(&event.data),
+        chat: None,
+        nickname: None,
+        type_name,
+        normalized_content: None,
+        file_ext: None,
+        file_size: None,
+        emoji_md5: None,
+        emoji_animated: None,
+        urls: Vec::new(),
+        location: None,
+        payment: None,
+        name_card: None,
+        member_change: None,
+    };
+
+    match norm.type_name.as_deref() {
+        Some("AddMsg") => {
+            let msg_type = event
+                .data
+                .get("MsgType")
+                .and_then(|v| v.as_i64())
+                .ok_or(NormalizeError::MissingMsgType)?;
+            norm.msg_type = Some(msg_type);
+            norm.from_wxid = event
+                .data
+                .get("FromUserName")
+                .and_then(|v| v.get("string"))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            norm.to_wxid = event
+                .data
+                .get("ToUserName")
+                .and_then(|v| v.get("string"))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            norm.chat = norm.from_wxid.as_deref().map(|w| {
+                if w.ends_with("@chatroom") {
+                    ChatKind::Group
+                } else {
+                    ChatKind::Private
+                }
+            });
+            norm.content = event
+                .data
+                .get("Content")
+                .and_then(|v| v.get("string"))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            if matches!(norm.chat, Some(ChatKind::Group)) {
+                if let Some(ref content) = norm.content {
+                    norm.group_sender_wxid = extract_group_sender(content);
+                }
+            }
+            norm.push_content = event
+                .data
+                .get("PushContent")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            norm.msg_source = event
+                .data
+                .get("MsgSource")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            norm.appmsg_type = extract_appmsg_type(norm.msg_type, norm.content.as_deref());
+            norm.kind = MessageKind::from_msg_type(msg_type, norm.appmsg_type);
+            // 群聊文本形如 "sender:\n内容"，在确定类型后切分正文
+            if norm.msg_type == Some(1) && norm.chat == Some(ChatKind::Group) {
+                if let Some(ref content) = norm.content {
+                    norm.content = Some(strip_sender_prefix(content));
+                }
+            }
+            if let Some(ref content) = norm.content {
+                norm.file_size = extract_media_size(msg_type, content);
+                norm.file_ext = extract_file_ext(content);
+                norm.urls = extract_urls(msg_type, content);
+                if msg_type == 48 {
+                    norm.location = extract_location(content);
+                }
+                if msg_type == 42 {
+                    norm.name_card = extract_name_card(content);
+                }
+                if matches!(norm.kind, MessageKind::RedPacket | MessageKind::Transfer) {
+                    norm.payment = Some(extract_payment(content));
+                }
+                if msg_type == 47 {
+                    norm.emoji_md5 = extract_emoji_md5(content).map(|m| m.to_ascii_lowercase());
+                    norm.emoji_animated =
+                        extract_attr(content, "<emoji", "type").map(|t| t.trim() == "2");
+                }
+            }
+            if norm.chat == Some(ChatKind::Group) {
+                if let Some(change) = norm
+                    .content
+                    .as_deref()
+                    .and_then(|c| extract_member_change(msg_type, c))
+                {
+                    norm.kind = match change.kind {
+                        MemberChangeKind::Joined => MessageKind::MemberJoined,
+                        MemberChangeKind::Left => MessageKind::MemberLeft,
+                    };
+                    // 系统消息没有发言人，10002 的「群 ID:」前缀不是发送者
+                    norm.group_sender_wxid = change.operator.as_ref().and_then(|o| o.wxid.clone());
+                    norm.member_change = Some(change);
+                }
+            }
+            norm.normalized_content = Some(normalize_content(&norm));
+        }
+        Some("ModContacts") | Some("DelContacts") | Some("Offline") => {
+            norm.kind = MessageKind::ContactEvent;
+        }
+        _ => {
+            norm.kind = MessageKind::Other;
+        }
+    }
+    norm.nickname = extract_nickname(norm.push_content.as_deref());
+
+    Ok(norm)
+}
+
+/// 回调中的 NewMsgId，兼容外层与 `Data` 内层两种位置
+pub fn extract_new_msg_id(data: &serde_json::Value) -> Option<i64> {
+    data.get("NewMsgId").and_then(|v| v.as_i64()).or_else(|| {
+        data.get("Data")
+            .and_then(|inner| inner.get("NewMsgId"))
+            .and_then(|v| v.as_i64())
+    })
+}
+
+/// 提取媒体/文件字节数：
+/// - appmsg 文件：<appattach><totallen>
+/// - 图片/视频/语音：<img length>/<videomsg length>/<voicemsg length>
+/// - 表情：<emoji len>
+fn extract_media_size(msg_type: i64, xml: &str) -> Option<u64> {
+    let raw = match msg_type {
+        49 => extract_between(xml, "<totallen>", "</totallen>"),
+        3 => extract_attr(xml, "<img", "length"),
+        43 => extract_attr(xml, "<videomsg", "length"),
+        34 => extract_attr(xml, "<voicemsg", "length"),
+        47 => extract_attr(xml, "<emoji", "len"),
+        _ => None,
+    };
+    raw.and_then(|v| v.trim().parse::<u64>().ok())
+}
+
+/// 解析位置消息：<location x="纬度" y="经度" label="地址" poiname="地点名" />
+fn extract_location(xml: &str) -> Option<LocationInfo> {
+    let lat = extract_attr(xml, "<location", "x")?
+        .trim()
+        .parse::<f64>()
+        .ok()?;
+    let lng = extract_attr(xml, "<location", "y")?
+        .trim()
+        .parse::<f64>()
+        .ok()?;
+    let non_empty = |v: String| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+    Some(LocationInfo {
+        lat,
+        lng,
+        label: extract_attr(xml, "<location", "label").and_then(non_empty),
+        poi_name: extract_attr(xml, "<location", "poiname").and_then(non_empty),
+    })
+}
+
+/// 解析名片消息：<msg username="..." nickname="..." bigheadimgurl="..." antispamticket="..." />
+fn extract_name_card(xml: &str) -> Option<NameCardInfo> {
+    let attr = |name: &str| {
+        extract_attr(xml, "<msg", name)
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let wxid = attr("username")?;
+    Some(NameCardInfo {
+        v3: wxid.starts_with("v3_").then(|| wxid.clone()),
+        v4: attr("antispamticket"),
+        nickname: attr("nickname"),
+        avatar: attr("bigheadimgurl").or_else(|| attr("smallheadimgurl")),
+        wxid,
+    })
+}
+
+/// 解析红包/转账通知中的 <wcpayinfo>：红包取 <sendertitle>，转账取 <pay_memo>
+fn extract_payment(xml: &str) -> PaymentInfo {
+    let field = |tag: &str| {
+        extract_between(xml, &format!("<{tag}>"), &format!("</{tag}>"))
+            .map(|v| strip_cdata(&v).trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    PaymentInfo {
+        memo: field("sendertitle").or_else(|| field("pay_memo")),
+        pay_subtype: field("paysubtype").and_then(|v| v.parse().ok()),
+    }
+}
+
+/// 去掉 `<![CDATA[...]]>` 包裹
+pub fn strip_cdata(s: &str) -> &str {
+    s.trim()
+        .strip_prefix("<![CDATA[")
+        .and_then(|v| v.strip_suffix("]]>"))
+        .unwrap_or(s)
+}
+
+/// 提取文件扩展名，优先 <fileext>，否则取 appmsg 标题中的后缀
+fn extract_file_ext(xml: &str) -> Option<String> {
+    extract_between(xml, "<fileext>", "</fileext>")
+        .map(|e| normalize_file_ext(&e))
+        .filter(|e| !e.is_empty())
+        .or_else(|| {
+            if !xml.contains("<appattach>") {
+                return None;
+            }
+            let title = extract_between(xml, "<title>", "</title>")?;
+            let (_, ext) = title.trim().rsplit_once('.')?;
+            Some(normalize_file_ext(ext)).filter(|e| !e.is_empty() && e.len() <= 10)
+        })
+}
+
+/// 扩展名统一为小写、不含点
+pub fn normalize_file_ext(ext: &str) -> String {
+    ext.trim().trim_start_matches('.').to_ascii_lowercase()
+}
+
+/// 提取某个标签上的属性值，如 extract_attr(xml, "<img", "length")
+pub fn extract_attr(xml: &str, tag: &str, attr: &str) -> Option<String> {
+    let start = xml.find(tag)?;
+    let rest = &xml[start + tag.len()..];
+    let tag_body = &rest[..rest.find('>').unwrap_or(rest.len())];
+    let needle = format!(" {}=\"", attr);
+    let value_start = tag_body.find(&needle)? + needle.len();
+    let value = &tag_body[value_start..];
+    value.find('"').map(|end| value[..end].to_string())
+}
+
+/// 用于日志的内容归一化：
+/// - 文本：展示实际内容
+/// - 引用：展示被引内容类型/文本
+/// - 图片/表情/链接/文件：展示占位符
+pub fn normalize_content(norm: &NormalizedEvent) -> String {
+    match norm.kind {
+        MessageKind::Text => normalize_text_content(norm),
+        MessageKind::Image => "[图片]".to_string(),
+        MessageKind::Voice => "[语音]".to_string(),
+        MessageKind::Video => "[视频]".to_string(),
+        MessageKind::Emoji => "[表情]".to_string(),
+        MessageKind::Link => "[链接]".to_string(),
+        MessageKind::FileNotice => "[文件]".to_string(),
+        MessageKind::Location => match norm.location.as_ref().and_then(|l| l.name()) {
+            Some(name) => format!("[位置] {}", name),
+            None => "[位置]".to_string(),
+        },
+        MessageKind::RedPacket | MessageKind::Transfer => {
+            let label = if norm.kind == MessageKind::RedPacket {
+                "[红包]"
+            } else {
+                "[转账]"
+            };
+            match norm.payment.as_ref().and_then(|p| p.memo.as_deref()) {
+                Some(memo) => format!("{} {}", label, memo),
+                None => label.to_string(),
+            }
+        }
+        MessageKind::NameCard => {
+            match norm.name_card.as_ref().and_then(|c| c.nickname.as_deref()) {
+                Some(name) => format!("[名片] {}", name),
+                None => "[名片]".to_string(),
+            }
+        }
+        MessageKind::ContactEvent => "[联系人事件]".to_string(),
+        MessageKind::MemberJoined | MessageKind::MemberLeft => {
+            let label = if norm.kind == MessageKind::MemberJoined {
+                "[入群]"
+            } else {
+                "[退群]"
+            };
+            match norm.member_change.as_ref().map(MemberChange::nicknames) {
+                Some(names) if !names.is_empty() => format!("{} {}", label, names),
+                _ => label.to_string(),
+            }
+        }
+        // 对于未识别类型，若是 appmsg（如引用 57），走文本归一化，否则占位符
+        MessageKind::Other => {
+            if norm.msg_type == Some(49) {
+                normalize_text_content(norm)
+            } else {
+                norm.content
+                    .as_deref()
+                    .map(|s| shorten(s, 200))
+                    .unwrap_or("[unknown]".to_string())
+            }
+        }
+    }
+}
+
+/// 文本类内容的归一化，兼顾引用消息（appmsg type 57）
+fn normalize_text_content(norm: &NormalizedEvent) -> String {
+    let raw = norm.content.as_deref().unwrap_or("[text]").to_string();
+    // 如果是微信卡片/引用消息（MsgType=49 且含 appmsg），尝试提取引用内容/标题
+    if norm.msg_type == Some(49) && raw.contains("<appmsg") {
+        let title = extract_between(&raw, "<title>", "</title>");
+        if raw.contains("<refermsg>") {
+            let refer_block = extract_between(&raw, "<refermsg>", "</refermsg>");
+            let refer_type = refer_block
+                .as_deref()
+                .and_then(|r| extract_between(r, "<type>", "</type>"))
+                .and_then(|t| t.parse::<i32>().ok());
+            let refer_label = map_type_label(refer_type);
+            // 尝试取引用内容；如果为空，再尝试 title
+            let refer_content = refer_block
+                .as_deref()
+                .and_then(|r| extract_between(r, "<content>", "</content>"))
+                .or_else(|| {
+                    refer_block
+                        .as_deref()
+                        .and_then(|r| extract_between(r, "<title>", "</title>"))
+                })
+                .unwrap_or_default();
+            let mut parts = Vec::new();
+            parts.push(format!("[引用:{}]", refer_label));
+            // 非文本或含 XML/IMG 的引用，不输出原文
+            if refer_label == "文本"
+                && !refer_content.trim_start().starts_with('<')
+                && !refer_content.contains("<img")
+                && !refer_content.trim().is_empty()
+            {
+                parts.push(refer_content.trim().to_string());
+            }
+            if let Some(t) = title {
+                if !t.trim().is_empty() {
+                    parts.push(t);
+                }
+            }
+            return shorten(&parts.join(" "), 200);
+        }
+        // 非引用的卡片/链接
+        return title.unwrap_or("[卡片]".to_string());
+    }
+    shorten(&raw, 300)
+}
+
+/// 解析群聊中的入群/退群系统消息，其他消息返回 None
+///
+/// - MsgType=10002：`<sysmsg type="sysmsgtemplate">`，模板如 `"$username$"邀请"$names$"加入了群聊`，
+///   成员带 wxid
+/// - MsgType=10000：文本通知，如 `"张三"邀请"李四、王五"加入了群聊`、`你将"李四"移出了群聊`，只有昵称
+pub fn extract_member_change(msg_type: i64, content: &str) -> Option<MemberChange> {
+    match msg_type {
+        10002 => extract_member_change_template(content),
+        10000 => extract_member_change_text(content),
+        _ => None,
+    }
+}
+
+fn member_change_kind(text: &str) -> Option<MemberChangeKind> {
+    if text.contains("加入了群聊") || text.contains("加入群聊") {
+        Some(MemberChangeKind::Joined)
+    } else if text.contains("移出了群聊") || text.contains("退出了群聊") {
+        Some(MemberChangeKind::Left)
+    } else {
+        None
+    }
+}
+
+fn extract_member_change_template(xml: &str) -> Option<MemberChange> {
+    if !xml.contains("sysmsgtemplate") {
+        return None;
+    }
+    let template = extract_between(xml, "<template>", "</template>")?;
+    let kind = member_change_kind(strip_cdata(template.trim()))?;
+    let mut links: Vec<(String, Vec<ChangedMember>)> = Vec::new();
+    for block in xml.split("<link ").skip(1) {
+        let Some(name) = block
+            .split("name=\"")
+            .nth(1)
+            .and_then(|s| s.split('"').next())
+        else {
+            continue;
+        };
+        let block = block.split("</link>").next().unwrap_or(block);
+        let members = block
+            .split("<member>")
+            .skip(1)
+            .filter_map(|m| {
+                let field = |tag: &str| {
+                    extract_between(m, &format!("<{}>", tag), &format!("</{}>", tag))
+                        .map(|v| strip_cdata(v.trim()).trim().to_string())
+                        .filter(|v| !v.is_empty())
+                };
+                let wxid = field("username");
+                let nickname = field("nickname").or_else(|| wxid.clone())?;
+                Some(ChangedMember { wxid, nickname })
+            })
+            .collect();
+        links.push((name.to_string(), members));
+    }
+    let take = |names: &[&str]| {
+        links
+            .iter()
+            .filter(|(name, _)| names.contains(&name.as_str()))
+            .flat_map(|(_, members)| members.iter().cloned())
+            .collect::<Vec<_>>()
+    };
+    let (targets, operators) = match kind {
+        // 扫码入群："$adder$"通过扫描"$from$"分享的二维码加入群聊
+        MemberChangeKind::Joined => (take(&["names", "adder"]), take(&["username", "from"])),
+        MemberChangeKind::Left => (take(&["kickoutname", "names"]), take(&["username"])),
+    };
+    let members = if targets.is_empty() && links.len() == 1 {
+        links.remove(0).1
+    } else {
+        targets
+    };
+    if members.is_empty() {
+        return None;
+    }
+    Some(MemberChange {
+        kind,
+        members,
+        operator: operators.into_iter().next(),
+    })
+}
+
+fn extract_member_change_text(text: &str) -> Option<MemberChange> {
+    let kind = member_change_kind(text)?;
+    // 昵称写在英文或中文引号里
+    let quoted: Vec<&str> = text
+        .split(['"', '“', '”'])
+        .skip(1)
+        .step_by(2)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    let by_nickname = |name: &str| ChangedMember {
+        wxid: None,
+        nickname: name.to_string(),
+    };
+    let (members, operator) = if text.contains("二维码") {
+        // "李四"通过扫描"张三"分享的二维码加入群聊
+        (quoted.first().copied(), quoted.get(1).copied())
+    } else {
+        // "张三"邀请"李四、王五"加入了群聊 / 你将"李四"移出了群聊
+        let operator = (quoted.len() >= 2).then(|| quoted[0]);
+        (quoted.last().copied(), operator)
+    };
+    let members: Vec<ChangedMember> = members?
+        .split('、')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(by_nickname)
+        .collect();
+    if members.is_empty() {
+        return None;
+    }
+    Some(MemberChange {
+        kind,
+        members,
+        operator: operator.map(by_nickname),
+    })
+}
+
+/// 取 `start` 与 `end` 之间的第一段文本
+pub fn extract_between(s: &str, start: &str, end: &str) -> Option<String> {
+    let start_pos = s.find(start)?;
+    let rest = &s[start_pos + start.len()..];
+    let end_pos = rest.find(end)?;
+    Some(rest[..end_pos].to_string())
+}
+
+fn map_type_label(t: Option<i32>) -> &'static str {
+    match t {
+        Some(1) => "文本",
+        Some(3) => "图片",
+        Some(34) => "语音",
+        Some(43) => "视频",
+        Some(47) => "表情",
+        Some(5) => "链接",
+        Some(_) => "其他",
+        None => "引用",
+    }
+}
+
+fn url_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"https?://[^\s<>"'，。！？、；：）】》]+"#).expect("valid url regex")
+    })
+}
+
+/// 提取消息中的链接：文本直接扫描，appmsg 取 <url> 与标题中的链接
+fn extract_urls(msg_type: i64, content: &str) -> Vec<String> {
+    let mut sources = Vec::new();
+    if msg_type == 49 {
+        if let Some(url) = extract_between(content, "<url>", "</url>") {
+            sources.push(url.replace("&amp;", "&"));
+        }
+        if let Some(title) = extract_between(content, "<title>", "</title>") {
+            sources.push(title);
+        }
+    } else if msg_type == 1 {
+        sources.push(content.to_string());
+    }
+
+    let mut urls: Vec<String> = Vec::new();
+    for src in &sources {
+        for m in url_regex().find_iter(src) {
+            let url = m
+                .as_str()
+                .trim_end_matches(['.', ',', ')', ']'])
+                .to_string();
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+/// 表情消息 XML 中的 md5 属性
+pub fn extract_emoji_md5(xml: &str) -> Option<String> {
+    xml.split("md5=\"")
+        .nth(1)
+        .and_then(|s| s.split('"').next())
+        .map(|s| s.to_string())
+}
+
+fn extract_nickname(push_content: Option<&str>) -> Option<String> {
+    let raw = push_content?;
+    raw.split_once(':')
+        .or_else(|| raw.split_once('：'))
+        .map(|(name, _)| name.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// appmsg（MsgType=49）XML 中的 `<type>` 子类型
+pub fn extract_appmsg_type(msg_type: Option<i64>, content: Option<&str>) -> Option<i32> {
+    if msg_type != Some(49) {
+        return None;
+    }
+    let xml = content?;
+    // 简单提取 <type>5</type>
+    xml.find("<type>").and_then(|start| {
+        let rest = &xml[start + 6..];
+        rest.find("</type>")
+            .and_then(|end| rest[..end].trim().parse::<i32>().ok())
+    })
+}
+
+/// 群聊消息的发送者 wxid
+pub fn extract_group_sender(content: &str) -> Option<String> {
+    let trimmed = content.trim_start();
+    // 群聊消息格式常见为「发送者: 内容」，wxid 不包含冒号，取首个冒号前的部分。
+    if let Some((head, _)) = trimmed.split_once(':') {
+        let sender = head.trim();
+        if !sender.is_empty() {
+            return Some(sender.to_string());
+        }
+    }
+    None
+}
+
+/// 群聊文本前缀剥离，形如 "sender:\n正文" 或 "sender:\r\n正文"
+pub fn strip_sender_prefix(raw: &str) -> String {
+    if let Some(pos) = raw.find(":\n") {
+        return raw[pos + 2..].to_string();
+    }
+    if let Some(pos) = raw.find(":\r\n") {
+        return raw[pos + 3..].to_string();
+    }
+    raw.to_string()
+}
+
+/// MsgSource 中 `<atuserlist>` 的内容
+pub fn extract_atuserlist(src: &str) -> Option<String> {
+    let start = src.find("<atuserlist>")?;
+    let tail = &src[start + "<atuserlist>".len()..];
+    let end = tail.find("</atuserlist>")?;
+    Some(tail[..end].to_string())
+}
+
+/// 消息是否 @ 了 `wxid`：优先看 MsgSource 的 atuserlist，其次看正文
+pub fn mentions(msg_source: Option<&str>, content: Option<&str>, wxid: &str) -> bool {
+    if let Some(inner) = msg_source.and_then(extract_atuserlist) {
+        if inner.contains(wxid) {
+            return true;
+        }
+    }
+    content.is_some_and(|c| c.contains(wxid))
+}
+
+fn shorten(s: &str, max: usize) -> String {
+    if s.len() <= max {
+        return s.to_string();
+    }
+    let mut cut = max;
+    while cut > 0 && !s.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    format!("{}…(+{} chars)", &s[..cut], s.len() - cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_strip_sender_prefix() {
+        // 测试群聊前缀剥离
+        let input = "sender123:\ncontent here";
+        let result = strip_sender_prefix(input);
+        assert_eq!(result, "content here");
+
+        let input = "sender456:\r\ncontent here";
+        let result = strip_sender_prefix(input);
+        assert_eq!(result, "content here");
+
+        // 测试不含前缀的情况
+        let input = "no prefix content";
+        let result = strip_sender_prefix(input);
+        assert_eq!(result, "no prefix content");
+    }
+
+    #[test]
+    fn test_normalize_content_text() {
+        let norm = NormalizedEvent {
+            kind: MessageKind::Text,
+            app_id: AppId("test".to_string()),
+            msg_type: Some(1),
+            from_wxid: None,
+            group_sender_wxid: None,
+            to_wxid: None,
+            content: Some("test content".to_string()),
+            push_content: None,
+            msg_source: None,
+            appmsg_type: None,
+            new_msg_id: None,
+            chat: None,
+            nickname: None,
+            type_name: None,
+            normalized_content: None,
+            file_ext: None,
+            file_size: None,
+            emoji_md5: None,
+            emoji_animated: None,
+            urls: Vec::new(),
+            location: None,
+            payment: None,
+            name_card: None,
+            member_change: None,
+        };
+        let result = normalize_content(&norm);
+        assert_eq!(result, "test content");
+    }
+
+    #[test]
+    fn test_normalize_content_with_quote() {
+        let xml = r#"<msg><appmsg><type>57</type><title>回复内容</title>
+            <refermsg><type>1</type><content>原始文本</content></refermsg>
+            </appmsg></msg>"#;
+        let norm = NormalizedEvent {
+            kind: MessageKind::Text,
+            app_id: AppId("test".to_string()),
+            msg_type: Some(49),
+            from_wxid: None,
+            group_sender_wxid: None,
+            to_wxid: None,
+            content: Some(xml.to_string()),
+            push_content: None,
+            msg_source: None,
+            appmsg_type: None,
+            new_msg_id: None,
+            chat: None,
+            nickname: None,
+            type_name: None,
+            normalized_content: None,
+            file_ext: None,
+            file_size: None,
+            emoji_md5: None,
+            emoji_animated: None,
+            urls: Vec::new(),
+            location: None,
+            payment: None,
+            name_card: None,
+            member_change: None,
+        };
+        let result = normalize_content(&norm);
+        assert!(result.contains("[引用"));
+        assert!(result.contains("原始文本"));
+    }
+
+    #[test]
+    fn test_normalize_content_quote_with_xml() {
+        // 引用内容包含 XML 时应该被过滤
+        let xml = r#"<msg><appmsg><type>57</type><title>回复</title>
+            <refermsg><type>1</type><content><img src="test" /></content></refermsg>
+            </appmsg></msg>"#;
+        let norm = NormalizedEvent {
+            kind: MessageKind::Text,
+            app_id: AppId("test".to_string()),
+            msg_type: Some(49),
+            from_wxid: None,
+            group_sender_wxid: None,
+            to_wxid: None,
+            content: Some(xml.to_string()),
+            push_content: None,
+            msg_source: None,
+            appmsg_type: None,
+            new_msg_id: None,
+            chat: None,
+            nickname: None,
+            type_name: None,
+            normalized_content: None,
+            file_ext: None,
+            file_size: None,
+            emoji_md5: None,
+            emoji_animated: None,
+            urls: Vec::new(),
+            location: None,
+            payment: None,
+            name_card: None,
+            member_change: None,
+        };
+        let result = normalize_content(&norm);
+        assert!(result.contains("[引用"));
+        // XML 内容应该被过滤掉
+        assert!(!result.contains("<img"));
+    }
+
+    #[test]
+    fn test_extract_between() {
+        // 测试字符串提取
+        let text = "<tag>content</tag>";
+        let result = extract_between(text, "<tag>", "</tag>");
+        assert_eq!(result, Some("content".to_string()));
+
+        let text = "no tags here";
+        let result = extract_between(text, "<tag>", "</tag>");
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_map_type_label() {
+        // 测试类型标签映射
+        assert_eq!(map_type_label(Some(1)), "文本");
+        assert_eq!(map_type_label(Some(3)), "图片");
+        assert_eq!(map_type_label(Some(34)), "语音");
+        assert_eq!(map_type_label(None), "引用");
+    }
+
+    #[test]
+    fn test_extract_emoji_md5() {
+        // 测试 emoji MD5 提取
+        let xml = r#"<emoji md5="abc123def456" />"#;
+        let result = extract_emoji_md5(xml);
+        assert_eq!(result, Some("abc123def456".to_string()));
+
+        let xml = r#"<emoji />"#;
+        let result = extract_emoji_md5(xml);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_extract_nickname() {
+        // 测试昵称提取
+        let push = "Alice: hello world";
+        assert_eq!(extract_nickname(Some(push)), Some("Alice".to_string()));
+
+        let push = "Bob：你好";
+        assert_eq!(extract_nickname(Some(push)), Some("Bob".to_string()));
+
+        let push = "no colon";
+        assert_eq!(extract_nickname(Some(push)), None);
+
+        assert_eq!(extract_nickname(None), None);
+    }
+
+    #[test]
+    fn test_extract_group_sender() {
+        // 测试群聊发送者提取
+        let content = "wxid_abc123: hello world";
+        assert_eq!(
+            extract_group_sender(content),
+            Some("wxid_abc123".to_string())
+        );
+
+        let content = "no colon content";
+        assert_eq!(extract_group_sender(content), None);
+    }
+
+    #[test]
+    fn test_extract_atuserlist() {
+        // 测试 @ 用户列表提取
+        let src = "<msgsource><atuserlist>wxid_1,wxid_2</atuserlist></msgsource>";
+        assert_eq!(extract_atuserlist(src), Some("wxid_1,wxid_2".to_string()));
+
+        let src = "<msgsource></msgsource>";
+        assert_eq!(extract_atuserlist(src), None);
+    }
+
+    #[test]
+    fn test_extract_appmsg_type() {
+        // 测试 appmsg 类型提取
+        let xml = "<msg><appmsg><type>5</type></appmsg></msg>";
+        assert_eq!(extract_appmsg_type(Some(49), Some(xml)), Some(5));
+
+        // 非 appmsg
+        assert_eq!(extract_appmsg_type(Some(1), Some("text")), None);
+
+        // 无内容
+        assert_eq!(extract_appmsg_type(Some(49), None), None);
+    }
+
+    #[test]
+    fn test_extract_media_size() {
+        // 测试图片/视频大小提取
+        let img =
+            r#"<msg><img aeskey="k" cdnthumblength="100" length="2048" hdlength="4096" /></msg>"#;
+        assert_eq!(extract_media_size(3, img), Some(2048));
+
+        let video = r#"<msg><videomsg aeskey="k" length="123456" playlength="10" /></msg>"#;
+        assert_eq!(extract_media_size(43, video), Some(123456));
+
+        assert_eq!(extract_media_size(1, "hello"), None);
+        assert_eq!(extract_media_size(3, "<msg><img /></msg>"), None);
+    }
+
+    #[test]
+    fn test_extract_file_ext_from_title() {
+        // 无 <fileext> 时回退到标题后缀
+        let xml = "<appmsg><title>archive.Tar.GZ</title><appattach><totallen>1</totallen></appattach></appmsg>";
+        assert_eq!(extract_file_ext(xml), Some("gz".to_string()));
+
+        // 非附件卡片不从标题推断
+        let xml = "<appmsg><title>v1.2 发布</title><type>5</type></appmsg>";
+        assert_eq!(extract_file_ext(xml), None);
+    }
+
+    #[test]
+    fn test_extract_urls() {
+        // 文本消息：去掉中文标点与结尾符号，去重
+        let urls = extract_urls(
+            1,
+            "看看 https://example.com/a?x=1，还有 https://sub.test.org/b. 以及 https://example.com/a?x=1",
+        );
+        assert_eq!(
+            urls,
+            vec!["https://example.com/a?x=1", "https://sub.test.org/b"]
+        );
+
+        // appmsg：取 <url> 并解码 &amp;
+        let xml = "<appmsg><title>分享</title><type>5</type><url>https://mp.weixin.qq.com/s?a=1&amp;b=2</url></appmsg>";
+        assert_eq!(
+            extract_urls(49, xml),
+            vec!["https://mp.weixin.qq.com/s?a=1&b=2"]
+        );
+
+        // 其他类型不提取
+        assert!(extract_urls(3, "https://example.com").is_empty());
+    }
+
+    #[test]
+    fn test_extract_name_card() {
+        // 好友名片的 username 为 wxid，不带 v3
+        let card = extract_name_card(r#"<msg username="wxid_friend" nickname="李四" />"#).unwrap();
+        assert_eq!(card.wxid, "wxid_friend");
+        assert_eq!(card.nickname.as_deref(), Some("李四"));
+        assert!(card.v3.is_none());
+        assert!(extract_name_card("<msg />").is_none());
+    }
+
+    #[test]
+    fn test_extract_member_change_template() {
+        let xml = r#"123@chatroom:
+<sysmsg type="sysmsgtemplate"><sysmsgtemplate><content_template type="tmpl_type_profile">
+<plain><![CDATA[]]></plain>
+<template><![CDATA["$username$"邀请"$names$"加入了群聊]]></template>
+<link_list>
+<link name="username" type="link_profile"><memberlist><member><username><![CDATA[wxid_zhang]]></username><nickname><![CDATA[张三]]></nickname></member></memberlist></link>
+<link name="names" type="link_profile"><memberlist><member><username><![CDATA[wxid_li]]></username><nickname><![CDATA[李四]]></nickname></member><member><username><![CDATA[wxid_wang]]></username><nickname><![CDATA[王五]]></nickname></member></memberlist><separator><![CDATA[、]]></separator></link>
+</link_list></content_template></sysmsgtemplate></sysmsg>"#;
+        let change = extract_member_change(10002, xml).unwrap();
+        assert_eq!(change.kind, MemberChangeKind::Joined);
+        assert_eq!(change.nicknames(), "李四、王五");
+        assert_eq!(change.members[0].wxid.as_deref(), Some("wxid_li"));
+        assert_eq!(
+            change.operator.and_then(|o| o.wxid).as_deref(),
+            Some("wxid_zhang")
+        );
+
+        let kicked = r#"<sysmsg type="sysmsgtemplate"><sysmsgtemplate><content_template type="tmpl_type_profile">
+<template><![CDATA[你将"$kickoutname$"移出了群聊]]></template>
+<link_list><link name="kickoutname" type="link_profile"><memberlist><member><username><![CDATA[wxid_li]]></username><nickname><![CDATA[李四]]></nickname></member></memberlist></link></link_list>
+</content_template></sysmsgtemplate></sysmsg>"#;
+        let change = extract_member_change(10002, kicked).unwrap();
+        assert_eq!(change.kind, MemberChangeKind::Left);
+        assert_eq!(change.members[0].wxid.as_deref(), Some("wxid_li"));
+        assert_eq!(change.operator, None);
+
+        // Other templates (e.g. pat) and other message types are ignored
+        let pat = r#"<sysmsg type="sysmsgtemplate"><sysmsgtemplate><content_template><template><![CDATA["$username$"拍了拍我]]></template></content_template></sysmsgtemplate></sysmsg>"#;
+        assert_eq!(extract_member_change(10002, pat), None);
+        assert_eq!(extract_member_change(1, "\"张三\"加入了群聊"), None);
+    }
+
+    #[test]
+    fn test_extract_member_change_text() {
+        let change =
+            extract_member_change(10000, "\"李四\"通过扫描\"张三\"分享的二维码加入群聊").unwrap();
+        assert_eq!(change.kind, MemberChangeKind::Joined);
+        assert_eq!(change.nicknames(), "李四");
+        assert_eq!(change.operator.map(|o| o.nickname).as_deref(), Some("张三"));
+
+        let change = extract_member_change(10000, "你将\"李四\"移出了群聊").unwrap();
+        assert_eq!(change.kind, MemberChangeKind::Left);
+        assert_eq!(change.members[0].wxid, None);
+        assert_eq!(change.operator, None);
+
+        assert_eq!(
+            extract_member_change(10000, "你已添加了张三，现在可以开始聊天了。"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_normalize_event_kinds() {
+        let event = |type_name: &str, data| WebhookEvent {
+            app_id: AppId("wx_app".to_string()),
+            type_name: Some(type_name.to_string()),
+            data,
+        };
+
+        let norm = event(
+            "AddMsg",
+            json!({
+                "MsgType": 47,
+                "FromUserName": {"string": "wxid_alice"},
+                "ToUserName": {"string": "wxid_bot"},
+                "Content": {"string": r#"<msg><emoji md5="ABC123" type="2" len="1024" /></msg>"#},
+                "PushContent": "Alice : [动画表情]",
+                "NewMsgId": 7
+            }),
+        )
+        .normalize()
+        .unwrap();
+        assert_eq!(norm.kind, MessageKind::Emoji);
+        assert_eq!(norm.chat, Some(ChatKind::Private));
+        assert_eq!(norm.sender_wxid(), Some("wxid_alice"));
+        assert_eq!(norm.emoji_md5.as_deref(), Some("abc123"));
+        assert_eq!(norm.emoji_animated, Some(true));
+        assert_eq!(norm.file_size, Some(1024));
+        assert_eq!(norm.nickname(), Some("Alice".to_string()));
+        assert_eq!(norm.normalized_content.as_deref(), Some("[表情]"));
+
+        let norm = normalize_event(&event("ModContacts", json!({"NewMsgId": 8}))).unwrap();
+        assert_eq!(norm.kind, MessageKind::ContactEvent);
+        assert_eq!(norm.new_msg_id, Some(8));
+        assert_eq!(norm.chat, None);
+
+        let norm = event(
+            "AddMsg",
+            json!({
+                "MsgType": 10000,
+                "FromUserName": {"string": "123@chatroom"},
+                "ToUserName": {"string": "wxid_bot"},
+                "Content": {"string": "\"张三\"邀请\"李四、王五\"加入了群聊"},
+                "NewMsgId": 9
+            }),
+        )
+        .normalize()
+        .unwrap();
+        assert_eq!(norm.kind, MessageKind::MemberJoined);
+        assert_eq!(norm.sender_wxid(), Some("123@chatroom"));
+        assert_eq!(
+            norm.normalized_content.as_deref(),
+            Some("[入群] 李四、王五")
+        );
+
+        let err = normalize_event(&event("AddMsg", json!({}))).unwrap_err();
+        assert_eq!(err, NormalizeError::MissingMsgType);
+        assert_eq!(err.to_string(), "AddMsg 缺少 MsgType");
+    }
+
+    #[test]
+    fn test_normalize_event_fixtures() {
+        // 每个夹具是脱敏后的回调请求体，规范化结果以 insta 快照保存在 src/snapshots/，
+        // 新增夹具可用 crate::fixtures::import_dump_dir（或 gewe-bot-app 的 import-fixtures）从 dump 目录导入
+        let dir = crate::fixtures::normalize_fixtures_dir();
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+            let body: crate::WebhookBody =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let event = WebhookEvent {
+                app_id: AppId(body.appid),
+                type_name: body.type_name,
+                data: body.data,
+            };
+            insta::assert_json_snapshot!(name, normalize_event(&event));
+        }
+    }
+}
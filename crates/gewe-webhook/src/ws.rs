@@ -0,0 +1,401 @@
+//! `/ws/events`：把收到的 webhook 事件推送给 WebSocket 客户端
+//!
+//! 握手与帧处理使用 axum 的 [`WebSocketUpgrade`]。每个事件以一条文本消息推送，内容与
+//! webhook 回调同格式：`{"Appid": ..., "TypeName": ..., "Data": ...}`。
+
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use gewe_core::AppId;
+use gewe_session::SessionStore;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::sync::broadcast;
+
+use crate::WebhookEvent;
+
+/// 事件广播缓冲的默认容量，客户端落后超过该数量时丢弃其最早的事件
+pub const DEFAULT_EVENT_STREAM_CAPACITY: usize = 256;
+
+/// 客户端消息的最大长度，超过时断开连接
+const MAX_CLIENT_MESSAGE: usize = 64 * 1024;
+/// 服务端主动 ping 的间隔，避免代理断开空闲连接
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// webhook 事件的广播端，挂载 `/ws/events` 后把通过校验的事件推送给已连接的客户端。
+///
+/// 鉴权：客户端通过 `X-GEWE-TOKEN` 请求头提供 token（不接受查询参数，避免 token 出现在
+/// 访问日志中），`app_id` 查询参数指定要订阅的机器人（逗号分隔）。token 与所列每个机器人的
+/// token 一致时才允许连接；配置了 [`EventStream::with_access_token`] 时，持有该 token 的
+/// 客户端可订阅任意机器人，不指定 `app_id` 时接收全部事件。
+#[derive(Clone)]
+pub struct EventStream {
+    tx: broadcast::Sender<WebhookEvent>,
+    access_token: Option<Arc<str>>,
+}
+
+impl EventStream {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self {
+            tx,
+            access_token: None,
+        }
+    }
+
+    /// 设置可订阅全部机器人的访问 token（如供看板使用）
+    pub fn with_access_token(mut self, token: impl Into<String>) -> Self {
+        self.access_token = Some(Arc::from(token.into()));
+        self
+    }
+
+    /// 推送事件；没有客户端连接时直接丢弃
+    pub fn publish(&self, event: &WebhookEvent) {
+        if self.tx.receiver_count() > 0 {
+            let _ = self.tx.send(event.clone());
+        }
+    }
+
+    /// 在进程内订阅事件，与 WebSocket 客户端收到的事件相同
+    pub fn subscribe(&self) -> broadcast::Receiver<WebhookEvent> {
+        self.tx.subscribe()
+    }
+
+    /// 当前订阅者数量（含进程内订阅）
+    pub fn subscriber_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// 仅包含 `/ws/events` 的路由，用 `store` 中的 BotContext 校验 token
+    pub fn router<S>(&self, store: Arc<S>) -> Router
+    where
+        S: SessionStore + Send + Sync + 'static,
+    {
+        let state = WsState {
+            store,
+            events: self.clone(),
+        };
+        Router::new()
+            .route("/ws/events", get(handle_ws_events::<S>))
+            .with_state(state)
+    }
+
+    /// 校验 token 并返回订阅的机器人，`None` 表示全部
+    async fn authorize<S>(
+        &self,
+        store: &S,
+        token: &str,
+        app_ids: &[String],
+    ) -> Result<Option<HashSet<String>>, StatusCode>
+    where
+        S: SessionStore + Send + Sync + 'static,
+    {
+        if token.is_empty() {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        if self
+            .access_token
+            .as_deref()
+            .is_some_and(|expected| token_eq(expected, token))
+        {
+            return Ok((!app_ids.is_empty()).then(|| app_ids.iter().cloned().collect()));
+        }
+        if app_ids.is_empty() {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        for app_id in app_ids {
+            match store.get_session(&AppId(app_id.clone())).await {
+                Some(ctx) if token_eq(&ctx.token, token) => {}
+                _ => return Err(StatusCode::UNAUTHORIZED),
+            }
+        }
+        Ok(Some(app_ids.iter().cloned().collect()))
+    }
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_STREAM_CAPACITY)
+    }
+}
+
+struct WsState<S> {
+    store: Arc<S>,
+    events: EventStream,
+}
+
+impl<S> Clone for WsState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            events: self.events.clone(),
+        }
+    }
+}
+
+/// 按常量时间比较 token，避免通过响应耗时逐字节猜测
+fn token_eq(expected: &str, token: &str) -> bool {
+    expected.as_bytes().ct_eq(token.as_bytes()).into()
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WsQuery {
+    #[serde(default)]
+    app_id: Option<String>,
+}
+
+async fn handle_ws_events<S>(
+    State(state): State<WsState<S>>,
+    Query(query): Query<WsQuery>,
+    headers: HeaderMap,
+    ws: Result<WebSocketUpgrade, axum::extract::ws::rejection::WebSocketUpgradeRejection>,
+) -> Response
+where
+    S: SessionStore + Send + Sync + 'static,
+{
+    let token = headers
+        .get("X-GEWE-TOKEN")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let app_ids: Vec<String> = query
+        .app_id
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect();
+    let filter = match state
+        .events
+        .authorize(state.store.as_ref(), token, &app_ids)
+        .await
+    {
+        Ok(filter) => filter,
+        Err(status) => {
+            tracing::warn!(?app_ids, "ws events unauthorized");
+            return status.into_response();
+        }
+    };
+    let ws = match ws {
+        Ok(ws) => ws,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    // 在应答之前订阅，握手完成后的事件不会遗漏
+    let rx = state.events.subscribe();
+    ws.max_message_size(MAX_CLIENT_MESSAGE)
+        .on_upgrade(move |socket| forward_events(rx, filter, socket))
+}
+
+/// 把广播的事件转发给一个客户端，客户端断开或广播关闭时结束。
+/// 客户端的 ping 与 close 由 axum 自动应答，其余客户端消息忽略
+async fn forward_events(
+    mut rx: broadcast::Receiver<WebhookEvent>,
+    filter: Option<HashSet<String>>,
+    mut socket: WebSocket,
+) {
+    tracing::debug!(?filter, "ws events client connected");
+    let mut ping =
+        tokio::time::interval_at(tokio::time::Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    if filter
+                        .as_ref()
+                        .is_some_and(|ids| !ids.contains(&event.app_id.0))
+                    {
+                        continue;
+                    }
+                    if socket.send(Message::Text(event_json(&event).into())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "ws events client lagging; events dropped");
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    let close = CloseFrame {
+                        code: close_code::AWAY,
+                        reason: Default::default(),
+                    };
+                    let _ = socket.send(Message::Close(Some(close))).await;
+                    break;
+                }
+            },
+            incoming = socket.recv() => {
+                if !matches!(incoming, Some(Ok(_))) {
+                    break;
+                }
+            }
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Bytes::new())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// 事件序列化为与 webhook 回调相同的格式
+fn event_json(event: &WebhookEvent) -> String {
+    serde_json::json!({
+        "Appid": event.app_id.0,
+        "TypeName": event.type_name,
+        "Data": event.data,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use futures_util::{SinkExt, StreamExt};
+    use gewe_core::BotContext;
+    use gewe_session::InMemorySessionStore;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+    use tower::ServiceExt;
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn test_store() -> Arc<InMemorySessionStore> {
+        let store = Arc::new(InMemorySessionStore::default());
+        for (app_id, token) in [("wx1", "t1"), ("wx2", "t2")] {
+            store
+                .put_session(BotContext {
+                    app_id: AppId(app_id.to_string()),
+                    token: token.to_string(),
+                    webhook_secret: None,
+                    description: None,
+                })
+                .await;
+        }
+        store
+    }
+
+    fn event(app_id: &str, msg_id: i64) -> WebhookEvent {
+        WebhookEvent {
+            app_id: AppId(app_id.to_string()),
+            type_name: Some("AddMsg".to_string()),
+            data: serde_json::json!({ "NewMsgId": msg_id }),
+        }
+    }
+
+    async fn connect(addr: std::net::SocketAddr, query: &str, token: &str) -> Client {
+        let mut request = format!("ws://{addr}/ws/events?{query}")
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("X-GEWE-TOKEN", token.parse().unwrap());
+        tokio_tungstenite::connect_async(request).await.unwrap().0
+    }
+
+    async fn next_json(client: &mut Client) -> serde_json::Value {
+        match client.next().await.unwrap().unwrap() {
+            tungstenite::Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_token_eq() {
+        assert!(token_eq("t1", "t1"));
+        assert!(!token_eq("t1", "t2"));
+        assert!(!token_eq("t1", "t10"));
+        assert!(!token_eq("t1", ""));
+    }
+
+    #[tokio::test]
+    async fn test_ws_events_rejects_bad_token_and_plain_get() {
+        let store = test_store().await;
+        let router = EventStream::default().router(store);
+        let status = |uri: &'static str, token: Option<&'static str>| {
+            let router = router.clone();
+            async move {
+                let mut request = Request::get(uri);
+                if let Some(token) = token {
+                    request = request.header("X-GEWE-TOKEN", token);
+                }
+                router
+                    .oneshot(request.body(axum::body::Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+        assert_eq!(status("/ws/events", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status("/ws/events", Some("t1")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status("/ws/events?app_id=wx1,wx2", Some("t1")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        // The token is only accepted from the header.
+        assert_eq!(
+            status("/ws/events?app_id=wx1&token=t1", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        // Authorized, but not a websocket handshake.
+        assert_eq!(
+            status("/ws/events?app_id=wx1", Some("t1")).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ws_events_streams_filtered_events() {
+        let store = test_store().await;
+        let events = EventStream::default().with_access_token("admin");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = events.router(store);
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let mut wx1 = connect(addr, "app_id=wx1", "t1").await;
+        let mut admin = connect(addr, "", "admin").await;
+
+        events.publish(&event("wx2", 1));
+        events.publish(&event("wx1", 2));
+
+        // The wx1 client only sees its own bot's event.
+        let value = next_json(&mut wx1).await;
+        assert_eq!(value["Appid"], "wx1");
+        assert_eq!(value["TypeName"], "AddMsg");
+        assert_eq!(value["Data"]["NewMsgId"], 2);
+
+        // The access token subscribes to every bot.
+        for expected in ["wx2", "wx1"] {
+            assert_eq!(next_json(&mut admin).await["Appid"], expected);
+        }
+
+        wx1.send(tungstenite::Message::Ping("p".into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            wx1.next().await.unwrap().unwrap(),
+            tungstenite::Message::Pong("p".into())
+        );
+        wx1.close(None).await.unwrap();
+        while let Some(message) = wx1.next().await {
+            assert!(matches!(message, Ok(tungstenite::Message::Close(_))));
+        }
+    }
+}